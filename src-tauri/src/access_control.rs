@@ -0,0 +1,340 @@
+// Access Control - viewers ask to join and the host approves each one
+// With approval required the server stops multicasting: it unicasts every
+// packet to the viewers the host let in. A viewer first proves it knows the
+// session PIN, then waits while the host sees a `viewer-join-request` event
+// and approves or denies it.
+//
+// Join request (viewer -> server's control port, every second, also keeps it alive):
+//   "JOIN" | JSON JoinRequest
+// Reply (server -> viewer):
+//   "JACK" | JSON JoinStatus
+//
+// Viewers that go quiet are dropped and have to be approved again.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::net_interfaces::IpVersion;
+use crate::quic_transport::QuicServer;
+use crate::tcp_transport::TcpFanout;
+
+pub const JOIN_MAGIC: &[u8; 4] = b"JOIN";
+pub const JOIN_REPLY_MAGIC: &[u8; 4] = b"JACK";
+const PIN_DIGITS: u32 = 6;
+const JOIN_INTERVAL_MS: u64 = 1000;
+const VIEWER_TIMEOUT_SECS: u64 = 10;
+const MAX_PIN_FAILURES: u32 = 5;
+const LOCKOUT_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JoinRequest {
+    pub pin: String,
+    /// Shown to the host when it decides
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinStatus {
+    Pending,
+    Approved,
+    Denied,
+    WrongPin,
+}
+
+impl JoinStatus {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = JOIN_REPLY_MAGIC.to_vec();
+        out.extend_from_slice(&serde_json::to_vec(self).unwrap_or_default());
+        out
+    }
+
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        serde_json::from_slice(buf.strip_prefix(JOIN_REPLY_MAGIC)?).ok()
+    }
+}
+
+impl JoinRequest {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = JOIN_MAGIC.to_vec();
+        out.extend_from_slice(&serde_json::to_vec(self).unwrap_or_default());
+        out
+    }
+
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        serde_json::from_slice(buf.strip_prefix(JOIN_MAGIC)?).ok()
+    }
+}
+
+/// `viewer-join-request` payload
+#[derive(Debug, Clone, Serialize)]
+pub struct ViewerRequest {
+    pub id: u64,
+    pub address: String,
+    pub name: String,
+}
+
+struct Viewer {
+    id: u64,
+    status: JoinStatus,
+    last_seen: Instant,
+}
+
+type RequestNotifier = Box<dyn Fn(ViewerRequest) + Send + Sync>;
+
+pub struct AccessControl {
+    pin: String,
+    viewers: Mutex<HashMap<SocketAddr, Viewer>>,
+    failures: Mutex<HashMap<IpAddr, (u32, Instant)>>,
+    next_id: AtomicU64,
+    notifier: Mutex<Option<RequestNotifier>>,
+}
+
+impl AccessControl {
+    /// Use `pin`, or make up a fresh 6-digit one
+    pub fn new(pin: Option<String>) -> Self {
+        let pin = pin.unwrap_or_else(|| {
+            format!("{:0width$}", rand::random::<u32>() % 10u32.pow(PIN_DIGITS), width = PIN_DIGITS as usize)
+        });
+        Self {
+            pin,
+            viewers: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            notifier: Mutex::new(None),
+        }
+    }
+
+    pub fn pin(&self) -> &str {
+        &self.pin
+    }
+
+    /// Called with every new viewer waiting for a decision
+    pub fn on_request<F>(&self, notify: F)
+    where
+        F: Fn(ViewerRequest) + Send + Sync + 'static,
+    {
+        *self.notifier.lock().unwrap() = Some(Box::new(notify));
+    }
+
+    pub fn handle_join(&self, from: SocketAddr, request: &JoinRequest) -> JoinStatus {
+        if !self.check_pin(from.ip(), &request.pin) {
+            return JoinStatus::WrongPin;
+        }
+
+        let mut viewers = self.viewers.lock().unwrap();
+        if let Some(viewer) = viewers.get_mut(&from) {
+            viewer.last_seen = Instant::now();
+            return viewer.status;
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        viewers.insert(from, Viewer { id, status: JoinStatus::Pending, last_seen: Instant::now() });
+        drop(viewers);
+
+        eprintln!("🙋 Viewer {} ({}) asks to join", request.name, from);
+        if let Some(notify) = self.notifier.lock().unwrap().as_ref() {
+            notify(ViewerRequest { id, address: from.to_string(), name: request.name.clone() });
+        }
+        JoinStatus::Pending
+    }
+
+    // Repeated wrong PINs lock the address out for a while
+    fn check_pin(&self, ip: IpAddr, pin: &str) -> bool {
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, (_, since)| since.elapsed() < Duration::from_secs(LOCKOUT_SECS));
+        if failures.get(&ip).is_some_and(|(count, _)| *count >= MAX_PIN_FAILURES) {
+            return false;
+        }
+        if pin == self.pin {
+            return true;
+        }
+        let entry = failures.entry(ip).or_insert((0, Instant::now()));
+        entry.0 += 1;
+        entry.1 = Instant::now();
+        false
+    }
+
+    pub fn decide(&self, id: u64, approve: bool) -> Result<(), String> {
+        let mut viewers = self.viewers.lock().unwrap();
+        let (addr, viewer) = viewers.iter_mut()
+            .find(|(_, v)| v.id == id)
+            .ok_or_else(|| format!("No viewer request {}", id))?;
+        viewer.status = if approve { JoinStatus::Approved } else { JoinStatus::Denied };
+        eprintln!("{} Viewer {} {}", if approve { "✅" } else { "⛔" }, addr, if approve { "approved" } else { "denied" });
+        Ok(())
+    }
+
+    pub fn is_approved(&self, addr: &SocketAddr) -> bool {
+        self.viewers.lock().unwrap().get(addr).is_some_and(|v| v.status == JoinStatus::Approved)
+    }
+
+    /// Where stream packets go, dropping viewers that went quiet
+    pub fn approved(&self) -> Vec<SocketAddr> {
+        let mut viewers = self.viewers.lock().unwrap();
+        viewers.retain(|_, v| v.last_seen.elapsed() < Duration::from_secs(VIEWER_TIMEOUT_SECS));
+        viewers.iter()
+            .filter(|(_, v)| v.status == JoinStatus::Approved)
+            .map(|(addr, _)| *addr)
+            .collect()
+    }
+}
+
+/// Where stream packets go
+#[derive(Clone)]
+pub enum Delivery {
+    Multicast {
+        group: SocketAddr,
+        /// Viewers on the TCP fallback
+        tcp: Option<Arc<TcpFanout>>,
+        /// Viewers connected over QUIC
+        quic: Option<Arc<QuicServer>>,
+    },
+    /// Unicast to every approved viewer
+    Approved(Arc<AccessControl>),
+}
+
+impl Delivery {
+    pub fn targets(&self) -> Vec<SocketAddr> {
+        match self {
+            Delivery::Multicast { group, .. } => vec![*group],
+            Delivery::Approved(access) => access.approved(),
+        }
+    }
+
+    pub fn send(&self, socket: &UdpSocket, packet: &[u8]) -> std::io::Result<()> {
+        let direct_viewers = match self {
+            Delivery::Multicast { tcp, quic, .. } => {
+                tcp.as_ref().map_or(0, |tcp| tcp.send(packet)) + quic.as_ref().map_or(0, |quic| quic.send(packet))
+            }
+            Delivery::Approved(_) => 0,
+        };
+        for target in self.targets() {
+            match socket.send_to(packet, target) {
+                // A host that can't multicast at all may still have TCP or QUIC viewers
+                Err(_) if direct_viewers > 0 => {}
+                Err(e) => return Err(e),
+                Ok(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Viewer side: keeps asking the server to be let in, which also keeps it alive
+pub struct JoinRequester {
+    server: SocketAddr,
+    request: Vec<u8>,
+    last_sent: Option<Instant>,
+    status: JoinStatus,
+}
+
+impl JoinRequester {
+    pub fn new(server: SocketAddr, pin: &str) -> Self {
+        let request = JoinRequest { pin: pin.to_string(), name: crate::discovery::host_name() };
+        Self {
+            server,
+            request: request.serialize(),
+            last_sent: None,
+            status: JoinStatus::Pending,
+        }
+    }
+
+    pub fn send_if_due(&mut self, socket: &UdpSocket) {
+        // A refusal is final until the viewer starts over
+        if matches!(self.status, JoinStatus::Denied | JoinStatus::WrongPin) {
+            return;
+        }
+        if self.last_sent.is_some_and(|t| t.elapsed() < Duration::from_millis(JOIN_INTERVAL_MS)) {
+            return;
+        }
+        self.last_sent = Some(Instant::now());
+        if let Err(e) = socket.send_to(&self.request, self.server) {
+            eprintln!("❌ Failed to send join request to {}: {}", self.server, e);
+        }
+    }
+
+    /// Record a reply, returns true if the status changed
+    pub fn update(&mut self, from: SocketAddr, status: JoinStatus) -> bool {
+        if from.ip() != self.server.ip() || status == self.status {
+            return false;
+        }
+        self.status = status;
+        true
+    }
+}
+
+/// "host", "host:port" or "ip:port", the port defaults to `default_port`.
+/// Only addresses of `ip_version` are usable from the stream socket.
+pub fn resolve_server(address: &str, ip_version: IpVersion, default_port: u16) -> Result<SocketAddr, String> {
+    let address = address.trim();
+    let resolved = match address.parse::<SocketAddr>() {
+        Ok(addr) => Ok(vec![addr]),
+        Err(_) if address.contains(':') => address.to_socket_addrs().map(|a| a.collect::<Vec<_>>()),
+        Err(_) => (address, default_port).to_socket_addrs().map(|a| a.collect::<Vec<_>>()),
+    };
+    resolved
+        .map_err(|e| format!("Cannot resolve server address {:?}: {}", address, e))?
+        .into_iter()
+        .find(|addr| ip_version.matches(&addr.ip()))
+        .ok_or_else(|| format!("No {:?} address for {:?}", ip_version, address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_flow() {
+        let access = AccessControl::new(None);
+        assert_eq!(access.pin().len(), 6);
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        access.on_request(move |request| seen.lock().unwrap().push(request));
+
+        let viewer: SocketAddr = "192.168.1.20:9999".parse().unwrap();
+        let join = JoinRequest { pin: access.pin().to_string(), name: "PC-01".to_string() };
+        assert_eq!(access.handle_join(viewer, &join), JoinStatus::Pending);
+        assert_eq!(access.handle_join(viewer, &join), JoinStatus::Pending);
+        assert!(access.approved().is_empty());
+
+        // Asked once, however often the viewer repeats itself
+        let id = {
+            let requests = requests.lock().unwrap();
+            assert_eq!(requests.len(), 1);
+            requests[0].id
+        };
+        access.decide(id, true).unwrap();
+        assert_eq!(access.handle_join(viewer, &join), JoinStatus::Approved);
+        assert_eq!(access.approved(), vec![viewer]);
+        assert!(access.decide(id + 1, true).is_err());
+    }
+
+    #[test]
+    fn test_wrong_pin_locks_out() {
+        let access = AccessControl::new(Some("123456".to_string()));
+        let viewer: SocketAddr = "192.168.1.30:9999".parse().unwrap();
+        let wrong = JoinRequest { pin: "000000".to_string(), name: "PC-02".to_string() };
+        for _ in 0..MAX_PIN_FAILURES {
+            assert_eq!(access.handle_join(viewer, &wrong), JoinStatus::WrongPin);
+        }
+        let right = JoinRequest { pin: "123456".to_string(), ..wrong };
+        assert_eq!(access.handle_join(viewer, &right), JoinStatus::WrongPin);
+    }
+
+    #[test]
+    fn test_messages_round_trip() {
+        let request = JoinRequest { pin: "042042".to_string(), name: "PC-03".to_string() };
+        assert_eq!(JoinRequest::parse(&request.serialize()).unwrap().pin, "042042");
+        assert_eq!(JoinStatus::parse(&JoinStatus::Denied.serialize()), Some(JoinStatus::Denied));
+        assert_eq!(JoinStatus::parse(b"KEYFRAME"), None);
+        assert_eq!(resolve_server("127.0.0.1", IpVersion::V4, 7000).unwrap().port(), 7000);
+        assert_eq!(resolve_server("127.0.0.1:9996", IpVersion::V4, 7000).unwrap().port(), 9996);
+        assert!(resolve_server("[::1]:9996", IpVersion::V6, 7000).unwrap().is_ipv6());
+        assert!(resolve_server("127.0.0.1", IpVersion::V6, 7000).is_err());
+    }
+}
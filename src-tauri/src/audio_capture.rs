@@ -0,0 +1,338 @@
+// Audio Capture - system audio as Opus packets, delivered like the video
+//   Windows: WASAPI loopback of the default output device
+//   macOS:   CoreAudio has no loopback, needs a virtual device (BlackHole, Soundflower)
+//   Linux:   PulseAudio/PipeWire monitor of the default sink
+//
+// The audio device's callback hands samples to an encoder thread, which
+// downmixes to stereo, resamples to 48kHz and sends one packet per 20ms.
+
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::access_control::Delivery;
+use crate::clock::StreamClock;
+use crate::stream_crypto::StreamCipher;
+
+pub const SAMPLE_RATE: u32 = 48_000;
+pub const CHANNELS: usize = 2;
+pub const FRAME_SAMPLES: usize = 960; // 20ms per channel at 48kHz
+pub const DEFAULT_BITRATE: i32 = 96_000;
+
+/// Running capture, stopped on `stop()` or drop
+pub struct AudioCapture {
+    running: Arc<AtomicBool>,
+}
+
+impl AudioCapture {
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for AudioCapture {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(feature = "audio")]
+impl AudioCapture {
+    /// Open the platform's loopback device and start sending to `destination`.
+    /// Fails if no capture device is available.
+    pub fn start(
+        socket: Arc<UdpSocket>,
+        destination: Delivery,
+        clock: StreamClock,
+        cipher: Option<Arc<StreamCipher>>,
+    ) -> Result<Self, String> {
+        let running = Arc::new(AtomicBool::new(true));
+        let (ready_tx, ready_rx) = std::sync::mpsc::sync_channel::<Result<(), String>>(1);
+
+        let thread_running = running.clone();
+        std::thread::spawn(move || {
+            // cpal streams can't move between threads, so this thread owns it
+            let (samples_tx, samples_rx) = std::sync::mpsc::sync_channel::<Vec<f32>>(64);
+            let opened = open_loopback_stream(samples_tx)
+                .and_then(|(stream, rate, channels)| {
+                    let encoder = OpusSender::new(socket, destination, clock, cipher)?;
+                    Ok((stream, rate, channels, encoder))
+                });
+            let (_stream, rate, channels, mut encoder) = match opened {
+                Ok(opened) => {
+                    let _ = ready_tx.send(Ok(()));
+                    opened
+                }
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+
+            eprintln!("🔊 Audio capture started ({} Hz, {} ch → Opus {} Hz stereo)", rate, channels, SAMPLE_RATE);
+
+            while thread_running.load(Ordering::Relaxed) {
+                match samples_rx.recv_timeout(std::time::Duration::from_millis(200)) {
+                    Ok(samples) => encoder.push(&to_stereo_48k(&samples, channels, rate)),
+                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                        eprintln!("❌ Audio device stream ended");
+                        break;
+                    }
+                }
+            }
+
+            eprintln!("🔇 Audio capture stopped");
+        });
+
+        ready_rx.recv()
+            .map_err(|_| "Audio capture thread exited during startup".to_string())??;
+
+        Ok(Self { running })
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+impl AudioCapture {
+    pub fn start(
+        _socket: Arc<UdpSocket>,
+        _destination: Delivery,
+        _clock: StreamClock,
+        _cipher: Option<Arc<StreamCipher>>,
+    ) -> Result<Self, String> {
+        Err("Audio streaming not compiled in (enable the `audio` feature)".to_string())
+    }
+}
+
+#[cfg(feature = "audio")]
+fn open_loopback_stream(
+    samples_tx: std::sync::mpsc::SyncSender<Vec<f32>>,
+) -> Result<(cpal::Stream, u32, usize), String> {
+    use cpal::traits::{DeviceTrait, StreamTrait};
+
+    let device = loopback_device()?;
+    let name = device.name().unwrap_or_else(|_| "unknown".to_string());
+
+    // WASAPI loopback is an input stream on the output device
+    let config = if cfg!(target_os = "windows") {
+        device.default_output_config()
+    } else {
+        device.default_input_config()
+    }.map_err(|e| format!("No usable config for audio device {}: {}", name, e))?;
+
+    let rate = config.sample_rate().0;
+    let channels = config.channels() as usize;
+
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config.into(), samples_tx),
+        cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config.into(), samples_tx),
+        cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config.into(), samples_tx),
+        format => return Err(format!("Unsupported audio sample format: {:?}", format)),
+    }?;
+
+    stream.play().map_err(|e| format!("Failed to start audio stream: {}", e))?;
+    eprintln!("🎙️  Capturing audio from: {}", name);
+
+    Ok((stream, rate, channels))
+}
+
+#[cfg(feature = "audio")]
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    samples_tx: std::sync::mpsc::SyncSender<Vec<f32>>,
+) -> Result<cpal::Stream, String>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    use cpal::traits::DeviceTrait;
+    use cpal::Sample;
+
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            // Never block the audio callback, drop audio if the encoder falls behind
+            let _ = samples_tx.try_send(data.iter().map(|s| s.to_sample::<f32>()).collect());
+        },
+        |e| eprintln!("❌ Audio stream error: {}", e),
+        None,
+    ).map_err(|e| format!("Failed to open audio stream: {}", e))
+}
+
+#[cfg(all(feature = "audio", target_os = "windows"))]
+fn loopback_device() -> Result<cpal::Device, String> {
+    use cpal::traits::HostTrait;
+    cpal::default_host()
+        .default_output_device()
+        .ok_or_else(|| "No audio output device to capture".to_string())
+}
+
+#[cfg(all(feature = "audio", target_os = "macos"))]
+fn loopback_device() -> Result<cpal::Device, String> {
+    use cpal::traits::{DeviceTrait, HostTrait};
+    const VIRTUAL_DEVICES: [&str; 2] = ["BlackHole", "Soundflower"];
+
+    let devices = cpal::default_host()
+        .input_devices()
+        .map_err(|e| format!("Failed to list audio devices: {}", e))?;
+    for device in devices {
+        if let Ok(name) = device.name() {
+            if VIRTUAL_DEVICES.iter().any(|v| name.contains(v)) {
+                return Ok(device);
+            }
+        }
+    }
+    Err("No loopback audio device found, install BlackHole and route system output to it".to_string())
+}
+
+#[cfg(all(feature = "audio", not(any(target_os = "windows", target_os = "macos"))))]
+fn loopback_device() -> Result<cpal::Device, String> {
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    // Through the ALSA "pulse" plugin, which PipeWire also provides,
+    // @DEFAULT_MONITOR@ is whatever the default sink is playing
+    if std::env::var_os("PULSE_SOURCE").is_none() {
+        std::env::set_var("PULSE_SOURCE", "@DEFAULT_MONITOR@");
+    }
+
+    let host = cpal::default_host();
+    let pulse = host.input_devices()
+        .map_err(|e| format!("Failed to list audio devices: {}", e))?
+        .find(|d| matches!(d.name().as_deref(), Ok("pulse") | Ok("pipewire")));
+    pulse
+        .or_else(|| host.default_input_device())
+        .ok_or_else(|| "No PulseAudio/PipeWire capture device found".to_string())
+}
+
+/// Downmix/upmix to interleaved stereo and linearly resample to 48kHz
+pub fn to_stereo_48k(samples: &[f32], channels: usize, rate: u32) -> Vec<f32> {
+    if channels == 0 {
+        return Vec::new();
+    }
+    let stereo: Vec<[f32; 2]> = samples
+        .chunks_exact(channels)
+        .map(|frame| match frame {
+            [mono] => [*mono, *mono],
+            [left, right, ..] => [*left, *right],
+            [] => [0.0, 0.0],
+        })
+        .collect();
+
+    if rate == SAMPLE_RATE || stereo.is_empty() {
+        return stereo.into_iter().flatten().collect();
+    }
+
+    let out_len = (stereo.len() as u64 * SAMPLE_RATE as u64 / rate as u64) as usize;
+    let step = rate as f64 / SAMPLE_RATE as f64;
+    let mut out = Vec::with_capacity(out_len * CHANNELS);
+    for i in 0..out_len {
+        let pos = i as f64 * step;
+        let index = pos as usize;
+        let frac = (pos - index as f64) as f32;
+        let a = stereo[index.min(stereo.len() - 1)];
+        let b = stereo[(index + 1).min(stereo.len() - 1)];
+        out.push(a[0] + (b[0] - a[0]) * frac);
+        out.push(a[1] + (b[1] - a[1]) * frac);
+    }
+    out
+}
+
+/// Buffers 48kHz stereo samples and sends every full 20ms as one packet
+#[cfg(feature = "audio")]
+struct OpusSender {
+    encoder: opus::Encoder,
+    socket: Arc<UdpSocket>,
+    destination: Delivery,
+    clock: StreamClock,
+    cipher: Option<Arc<StreamCipher>>,
+    pending: Vec<f32>,
+    sequence: u32,
+}
+
+#[cfg(feature = "audio")]
+impl OpusSender {
+    fn new(
+        socket: Arc<UdpSocket>,
+        destination: Delivery,
+        clock: StreamClock,
+        cipher: Option<Arc<StreamCipher>>,
+    ) -> Result<Self, String> {
+        let mut encoder = opus::Encoder::new(SAMPLE_RATE, opus::Channels::Stereo, opus::Application::Audio)
+            .map_err(|e| format!("Failed to create Opus encoder: {}", e))?;
+        encoder.set_bitrate(opus::Bitrate::Bits(DEFAULT_BITRATE))
+            .map_err(|e| format!("Failed to set Opus bitrate: {}", e))?;
+        Ok(Self {
+            encoder,
+            socket,
+            destination,
+            clock,
+            cipher,
+            pending: Vec::new(),
+            sequence: 0,
+        })
+    }
+
+    fn push(&mut self, samples: &[f32]) {
+        use crate::packet::{AudioHeader, AUDIO_CHANNEL};
+        use std::time::{Duration, Instant};
+
+        self.pending.extend_from_slice(samples);
+        let frame_len = FRAME_SAMPLES * CHANNELS;
+        let mut output = [0u8; 4000];
+
+        while self.pending.len() >= frame_len {
+            // The oldest buffered sample was captured this long ago
+            let buffered = Duration::from_secs_f64((self.pending.len() / CHANNELS) as f64 / SAMPLE_RATE as f64);
+            let now = Instant::now();
+            let captured_at = now.checked_sub(buffered).unwrap_or(now);
+
+            let frame: Vec<f32> = self.pending.drain(..frame_len).collect();
+            let size = match self.encoder.encode_float(&frame, &mut output) {
+                Ok(size) => size,
+                Err(e) => {
+                    eprintln!("❌ Opus encode error: {}", e);
+                    continue;
+                }
+            };
+
+            let header = AudioHeader {
+                channel: AUDIO_CHANNEL,
+                encrypted: self.cipher.is_some(),
+                sequence: self.sequence,
+                capture_ts: self.clock.timestamp(captured_at),
+            };
+            self.sequence = self.sequence.wrapping_add(1);
+
+            let mut packet = Vec::with_capacity(crate::packet::AUDIO_HEADER_SIZE + size);
+            header.write(&mut packet);
+            match &self.cipher {
+                Some(cipher) => match cipher.seal(&packet, &output[..size]) {
+                    Ok(sealed) => packet.extend_from_slice(&sealed),
+                    Err(e) => {
+                        eprintln!("❌ Audio {}", e);
+                        continue;
+                    }
+                },
+                None => packet.extend_from_slice(&output[..size]),
+            }
+            let _ = self.destination.send(&self.socket, &packet);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_stereo_48k() {
+        // Mono is duplicated to both channels
+        assert_eq!(to_stereo_48k(&[0.5, -0.5], 1, SAMPLE_RATE), vec![0.5, 0.5, -0.5, -0.5]);
+        // Extra channels are dropped
+        assert_eq!(to_stereo_48k(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 3, SAMPLE_RATE), vec![0.1, 0.2, 0.4, 0.5]);
+        // 44.1kHz → 48kHz keeps the duration
+        let resampled = to_stereo_48k(&vec![0.0; 441 * 2], 2, 44_100);
+        assert_eq!(resampled.len(), 480 * 2);
+    }
+}
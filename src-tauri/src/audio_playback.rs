@@ -0,0 +1,100 @@
+// Audio Playback - plays the stream's Opus packets through a rodio sink
+// Lost packets are concealed by the decoder; if the sink falls behind,
+// packets are dropped so audio never lags far behind the picture.
+
+use crate::packet::AudioHeader;
+
+#[cfg(feature = "audio")]
+const MAX_QUEUED_PACKETS: usize = 10; // 200ms of audio
+#[cfg(feature = "audio")]
+const MAX_CONCEALED_PACKETS: u32 = 5; // Longer gaps are silence, not worth faking
+
+pub struct AudioPlayer {
+    #[cfg(feature = "audio")]
+    packets: std::sync::mpsc::SyncSender<(AudioHeader, Vec<u8>)>,
+}
+
+#[cfg(feature = "audio")]
+impl AudioPlayer {
+    pub fn new() -> Result<Self, String> {
+        use crate::audio_capture::{CHANNELS, FRAME_SAMPLES, SAMPLE_RATE};
+
+        let (packets, packets_rx) = std::sync::mpsc::sync_channel::<(AudioHeader, Vec<u8>)>(32);
+        let (ready_tx, ready_rx) = std::sync::mpsc::sync_channel::<Result<(), String>>(1);
+
+        // rodio's output stream can't move between threads, so this thread owns it
+        std::thread::spawn(move || {
+            let opened = rodio::OutputStream::try_default()
+                .map_err(|e| format!("No audio output device: {}", e))
+                .and_then(|(stream, handle)| {
+                    let sink = rodio::Sink::try_new(&handle)
+                        .map_err(|e| format!("Failed to create audio sink: {}", e))?;
+                    let decoder = opus::Decoder::new(SAMPLE_RATE, opus::Channels::Stereo)
+                        .map_err(|e| format!("Failed to create Opus decoder: {}", e))?;
+                    Ok((stream, sink, decoder))
+                });
+            let (_stream, sink, mut decoder) = match opened {
+                Ok(opened) => {
+                    let _ = ready_tx.send(Ok(()));
+                    opened
+                }
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+
+            let mut expected_sequence: Option<u32> = None;
+            let mut pcm = vec![0f32; FRAME_SAMPLES * CHANNELS * 6]; // Opus allows up to 120ms
+
+            // Ends when the client drops the player
+            while let Ok((header, payload)) = packets_rx.recv() {
+                if sink.len() > MAX_QUEUED_PACKETS {
+                    expected_sequence = None;
+                    continue;
+                }
+
+                if let Some(expected) = expected_sequence {
+                    let missing = header.sequence.wrapping_sub(expected);
+                    if missing > 0 && missing <= MAX_CONCEALED_PACKETS {
+                        for _ in 0..missing {
+                            if let Ok(samples) = decoder.decode_float(&[], &mut pcm, false) {
+                                let concealed = pcm[..samples * CHANNELS].to_vec();
+                                sink.append(rodio::buffer::SamplesBuffer::new(CHANNELS as u16, SAMPLE_RATE, concealed));
+                            }
+                        }
+                    }
+                }
+                expected_sequence = Some(header.sequence.wrapping_add(1));
+
+                match decoder.decode_float(&payload, &mut pcm, false) {
+                    Ok(samples) => {
+                        let decoded = pcm[..samples * CHANNELS].to_vec();
+                        sink.append(rodio::buffer::SamplesBuffer::new(CHANNELS as u16, SAMPLE_RATE, decoded));
+                    }
+                    Err(e) => eprintln!("❌ Opus decode error: {}", e),
+                }
+            }
+        });
+
+        ready_rx.recv()
+            .map_err(|_| "Audio playback thread exited during startup".to_string())??;
+
+        eprintln!("🔊 Audio playback started");
+        Ok(Self { packets })
+    }
+
+    /// Queue one received packet, dropped if playback is backed up
+    pub fn play(&self, header: AudioHeader, payload: &[u8]) {
+        let _ = self.packets.try_send((header, payload.to_vec()));
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+impl AudioPlayer {
+    pub fn new() -> Result<Self, String> {
+        Err("Audio playback not compiled in (enable the `audio` feature)".to_string())
+    }
+
+    pub fn play(&self, _header: AudioHeader, _payload: &[u8]) {}
+}
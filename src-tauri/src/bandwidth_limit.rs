@@ -0,0 +1,112 @@
+// Bandwidth Limit - a token bucket under everything the video sends
+// An office uplink shared with everyone else's calls is no place for a
+// stream that takes all it can get. Tokens (bytes) drip in at the limit and
+// every datagram takes its size out; a frame's chunks wait for what they
+// lack, spread out instead of going in one burst. A frame that would have to
+// wait too long is dropped whole before any of it goes out, and the pacer,
+// seeing frames take longer, lowers quality and frame rate on its own.
+//
+// One bucket covers the main stream, its simulcast layers and the other
+// displays. Audio, pointer and control packets are small and go around it.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Longest a frame may wait for tokens before it's dropped instead
+const MAX_WAIT: Duration = Duration::from_millis(250);
+/// Tokens saved up while idle, as time at the limit
+const BURST: Duration = Duration::from_millis(100);
+
+struct Bucket {
+    /// Bytes per second, 0 is unlimited
+    rate: f64,
+    /// Bytes that may go out right now, negative while chunks wait
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn capacity(&self) -> f64 {
+        self.rate * BURST.as_secs_f64()
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity());
+        self.updated = now;
+    }
+}
+
+pub struct BandwidthLimit {
+    bucket: Mutex<Bucket>,
+}
+
+impl BandwidthLimit {
+    pub fn new(mbps: f32) -> Self {
+        let limit = Self {
+            bucket: Mutex::new(Bucket { rate: 0.0, tokens: 0.0, updated: Instant::now() }),
+        };
+        limit.set_limit(mbps);
+        limit
+    }
+
+    /// Cap at `mbps` megabits per second from now on, 0 lifts it
+    pub fn set_limit(&self, mbps: f32) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.rate = mbps.max(0.0) as f64 * 1_000_000.0 / 8.0;
+        bucket.tokens = bucket.capacity();
+        bucket.updated = Instant::now();
+    }
+
+    /// Whether a frame of `bytes` goes out soon enough to be worth sending.
+    /// A full bucket lets anything through, or a frame bigger than the
+    /// budget would never go at all.
+    pub fn admits(&self, bytes: usize, now: Instant) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        if bucket.rate == 0.0 {
+            return true;
+        }
+        bucket.refill(now);
+        bucket.tokens >= bucket.capacity() || bytes as f64 <= bucket.tokens + bucket.rate * MAX_WAIT.as_secs_f64()
+    }
+
+    /// Take a datagram of `bytes` out of the bucket; how long to wait before sending it
+    pub fn take(&self, bytes: usize, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        if bucket.rate == 0.0 {
+            return Duration::ZERO;
+        }
+        bucket.refill(now);
+        bucket.tokens -= bytes as f64;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / bucket.rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_delays_then_drops() {
+        // 8 Mbit/s: 1MB a second, 100KB saved up, 250KB of waiting allowed
+        let limit = BandwidthLimit::new(8.0);
+        let start = Instant::now();
+        assert!(limit.admits(1_000_000, start));
+        assert_eq!(limit.take(60_000, start), Duration::ZERO);
+        assert_eq!(limit.take(60_000, start).as_millis(), 20);
+
+        // 20KB in debt: 230KB more may wait, 300KB may not
+        assert!(limit.admits(230_000, start));
+        assert!(!limit.admits(300_000, start));
+        // A full bucket again lets even that through
+        assert!(limit.admits(300_000, start + Duration::from_millis(120)));
+
+        limit.set_limit(0.0);
+        assert!(limit.admits(usize::MAX, start));
+        assert_eq!(limit.take(1_000_000, start), Duration::ZERO);
+    }
+}
@@ -0,0 +1,117 @@
+// Black Frame Detection - drops all-black frames produced by failed captures
+// A healthy stream that suddenly turns fully black is almost always a broken
+// capture (lost DXGI access, locked desktop), not real content
+
+const SAMPLE_STEP: u32 = 8;            // Check every 8th pixel in both directions
+const DARK_LUMA: u8 = 24;              // Pixels at or below this count as black
+const MAX_BRIGHT_RATIO: f32 = 0.002;   // More than 0.2% bright samples means real content
+const MAX_SUPPRESSED_FRAMES: u32 = 90; // ~3s at 30 FPS, after that the screen really is black
+
+/// Tracks whether the stream has shown content, so only sudden black frames are dropped
+#[derive(Default)]
+pub struct BlackFrameDetector {
+    had_content: bool,
+    suppressed_in_a_row: u32,
+}
+
+impl BlackFrameDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true when the JPEG should be dropped and the last good frame kept
+    pub fn should_suppress(&mut self, jpeg: &[u8]) -> bool {
+        // Undecodable frames are left to the existing validation
+        let Ok(img) = image::load_from_memory_with_format(jpeg, image::ImageFormat::Jpeg) else {
+            return false;
+        };
+        self.should_suppress_image(&img)
+    }
+
+    /// `should_suppress` for a frame that is already decoded
+    pub fn should_suppress_image(&mut self, img: &image::DynamicImage) -> bool {
+        if !is_black(&img.to_luma8()) {
+            self.had_content = true;
+            self.suppressed_in_a_row = 0;
+            return false;
+        }
+
+        if !self.had_content {
+            return false;
+        }
+
+        self.suppressed_in_a_row += 1;
+        if self.suppressed_in_a_row > MAX_SUPPRESSED_FRAMES {
+            // Black for too long to be a glitch, show it
+            eprintln!("⚫ Black frames persisted, accepting them as real content");
+            self.had_content = false;
+            self.suppressed_in_a_row = 0;
+            return false;
+        }
+
+        true
+    }
+}
+
+/// Cheap luminance check over a sparse grid of pixels
+pub fn is_black(luma: &image::GrayImage) -> bool {
+    let mut samples = 0u32;
+    let mut bright = 0u32;
+
+    for y in (0..luma.height()).step_by(SAMPLE_STEP as usize) {
+        for x in (0..luma.width()).step_by(SAMPLE_STEP as usize) {
+            samples += 1;
+            if luma.get_pixel(x, y)[0] > DARK_LUMA {
+                bright += 1;
+            }
+        }
+    }
+
+    samples > 0 && (bright as f32 / samples as f32) <= MAX_BRIGHT_RATIO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jpeg(value: u8) -> Vec<u8> {
+        let img = image::RgbImage::from_pixel(160, 120, image::Rgb([value, value, value]));
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut buffer, image::ImageFormat::Jpeg).unwrap();
+        buffer.into_inner()
+    }
+
+    #[test]
+    fn test_black_at_start_is_kept() {
+        let mut detector = BlackFrameDetector::new();
+        assert!(!detector.should_suppress(&jpeg(0)));
+    }
+
+    #[test]
+    fn test_sudden_black_is_suppressed() {
+        let mut detector = BlackFrameDetector::new();
+        assert!(!detector.should_suppress(&jpeg(200)));
+        assert!(detector.should_suppress(&jpeg(0)));
+        assert!(!detector.should_suppress(&jpeg(200)));
+    }
+
+    #[test]
+    fn test_persistent_black_is_eventually_shown() {
+        let mut detector = BlackFrameDetector::new();
+        detector.should_suppress(&jpeg(200));
+        for _ in 0..MAX_SUPPRESSED_FRAMES {
+            assert!(detector.should_suppress(&jpeg(0)));
+        }
+        assert!(!detector.should_suppress(&jpeg(0)));
+        assert!(!detector.should_suppress(&jpeg(0)));
+    }
+
+    #[test]
+    fn test_dark_frame_with_content_is_not_black() {
+        let mut img = image::GrayImage::new(160, 120);
+        for x in 0..160 {
+            img.put_pixel(x, 64, image::Luma([255]));
+        }
+        assert!(!is_black(&img));
+    }
+}
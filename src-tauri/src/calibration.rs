@@ -0,0 +1,272 @@
+// Calibration - per-machine defaults instead of one-size-fits-all constants
+// On first run a short benchmark times JPEG encoding of a 1080p test frame
+// and how fast this machine can push stream-sized datagrams onto the network.
+// The result is stored next to the app data and turned into starting values
+// for FPS, JPEG quality, chunk size and encode workers. The adaptive pacer
+// still takes over from there once a stream runs.
+
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::frame_source::{FrameSource, SyntheticSource};
+use crate::hw_encoder::{self, EncoderConfig, EncoderType, VideoEncoder};
+use crate::screen_capture::RawFrame;
+use crate::settings::{self, StreamSettings};
+
+const FILE_NAME: &str = "calibration.json";
+/// Bump when the benchmark changes so older results are measured again
+const VERSION: u32 = 1;
+const BENCH_WIDTH: usize = 1920;
+const BENCH_HEIGHT: usize = 1080;
+const BENCH_FRAMES: u32 = 5;
+// Nobody listens here; TTL 1 keeps the probe on the local segment
+const PROBE_ADDR: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(239, 0, 0, 1), 9994);
+const PROBE_DURATION_MS: u64 = 300;
+const PROBE_PACKET_SIZE: usize = 8192;
+/// Below this the link is likely Wi-Fi, where a lost fragment costs the whole datagram
+const SLOW_LINK_MBPS: f32 = 50.0;
+const MTU_SAFE_CHUNK_SIZE: usize = 1400;
+
+/// What the benchmark measured on this machine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Calibration {
+    pub version: u32,
+    /// Average JPEG encode time of a 1080p frame at the default quality
+    pub encode_ms: f32,
+    /// Size of that frame, to estimate what a stream costs on the wire
+    pub frame_bytes: usize,
+    /// Rate the probe got datagrams out of the socket, 0 when it couldn't send
+    pub bandwidth_mbps: f32,
+    pub cpu_count: usize,
+    /// Seconds since the Unix epoch
+    pub measured_at: u64,
+}
+
+/// Starting values derived from a calibration
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MachineDefaults {
+    pub target_fps: u32,
+    pub max_fps: u32,
+    pub jpeg_quality: u8,
+    pub chunk_size: usize,
+    pub encode_workers: usize,
+}
+
+impl Calibration {
+    /// Run the benchmark, takes well under a second on most machines
+    pub fn run() -> Result<Self, String> {
+        let (encode_ms, frame_bytes) = benchmark_encode()?;
+        let bandwidth_mbps = match probe_bandwidth() {
+            Ok(mbps) => mbps,
+            Err(e) => {
+                // Offline machines still get CPU-based defaults
+                eprintln!("⚠️  Bandwidth probe failed: {}", e);
+                0.0
+            }
+        };
+        let calibration = Self {
+            version: VERSION,
+            encode_ms,
+            frame_bytes,
+            bandwidth_mbps,
+            cpu_count: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            measured_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        };
+        eprintln!("📏 Calibrated: encode {:.1} ms/frame ({} KB), {:.0} Mbps, {} CPUs",
+            calibration.encode_ms, calibration.frame_bytes / 1024, calibration.bandwidth_mbps, calibration.cpu_count);
+        Ok(calibration)
+    }
+
+    /// Stored result, `None` when there is none or it came from another benchmark version
+    pub fn load(dir: &Path) -> Option<Self> {
+        let data = std::fs::read(path(dir)).ok()?;
+        serde_json::from_slice::<Self>(&data).ok().filter(|c| c.version == VERSION)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let data = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path(dir), data)
+            .map_err(|e| format!("Failed to save calibration: {}", e))
+    }
+
+    /// The stored result, or a fresh one (saved for next time) on first run
+    pub fn load_or_run(dir: &Path) -> Result<Self, String> {
+        if let Some(calibration) = Self::load(dir) {
+            return Ok(calibration);
+        }
+        let calibration = Self::run()?;
+        if let Err(e) = calibration.save(dir) {
+            // Measured again next start, not worth failing over
+            eprintln!("⚠️  {}", e);
+        }
+        Ok(calibration)
+    }
+
+    pub fn defaults(&self) -> MachineDefaults {
+        // Leave headroom for capture and sending
+        let cpu_fps = if self.encode_ms > 0.0 {
+            (1000.0 / (self.encode_ms * 1.5)) as u32
+        } else {
+            settings::DEFAULT_MAX_FPS
+        };
+        // Half the link for the stream, the rest is the classroom's
+        let network_fps = if self.bandwidth_mbps > 0.0 && self.frame_bytes > 0 {
+            (self.bandwidth_mbps * 1_000_000.0 * 0.5 / (self.frame_bytes as f32 * 8.0)) as u32
+        } else {
+            settings::DEFAULT_MAX_FPS
+        };
+
+        let max_fps = cpu_fps.clamp(settings::DEFAULT_MIN_FPS, settings::DEFAULT_MAX_FPS);
+        let target_fps = settings::DEFAULT_TARGET_FPS.min(max_fps);
+        // Too little bandwidth for the target at the default quality: smaller frames instead of fewer
+        let jpeg_quality = if network_fps < settings::DEFAULT_MIN_FPS {
+            30
+        } else if network_fps < target_fps {
+            40
+        } else if network_fps >= 2 * target_fps && cpu_fps >= 2 * target_fps {
+            65
+        } else {
+            hw_encoder::DEFAULT_JPEG_QUALITY
+        };
+        let chunk_size = if self.bandwidth_mbps > 0.0 && self.bandwidth_mbps < SLOW_LINK_MBPS {
+            MTU_SAFE_CHUNK_SIZE
+        } else {
+            settings::DEFAULT_CHUNK_SIZE
+        };
+
+        MachineDefaults {
+            target_fps,
+            max_fps,
+            jpeg_quality,
+            chunk_size,
+            // One per core, JPEG frames encode side by side (see encode_pool.rs)
+            encode_workers: self.cpu_count.clamp(1, settings::MAX_ENCODE_WORKERS),
+        }
+    }
+}
+
+impl MachineDefaults {
+    /// Replace the built-in starting values in `settings`
+    pub fn apply(&self, settings: &mut StreamSettings) {
+        settings.target_fps = self.target_fps;
+        settings.max_fps = self.max_fps;
+        settings.min_fps = settings.min_fps.min(self.max_fps);
+        settings.jpeg_quality = self.jpeg_quality;
+        settings.chunk_size = self.chunk_size;
+        settings.encode_workers = self.encode_workers;
+    }
+}
+
+fn path(dir: &Path) -> PathBuf {
+    dir.join(FILE_NAME)
+}
+
+// Average over a few frames after one warm-up encode
+fn benchmark_encode() -> Result<(f32, usize), String> {
+    let mut source = SyntheticSource::new(BENCH_WIDTH, BENCH_HEIGHT);
+    let mut encoder = hw_encoder::JpegEncoder::new(&EncoderConfig {
+        width: BENCH_WIDTH,
+        height: BENCH_HEIGHT,
+        fps: settings::DEFAULT_TARGET_FPS,
+        bitrate: 0,
+        encoder_type: EncoderType::Software,
+        quality: hw_encoder::DEFAULT_JPEG_QUALITY,
+        workers: 1,
+        speed: hw_encoder::DEFAULT_ENCODER_SPEED,
+    })?;
+
+    let mut next_frame = || -> Result<RawFrame, String> {
+        source.next_frame()?.ok_or_else(|| "No benchmark frame".to_string())
+    };
+    encoder.encode(&next_frame()?.rgba)?;
+
+    let mut elapsed = Duration::ZERO;
+    let mut frame_bytes = 0;
+    for _ in 0..BENCH_FRAMES {
+        let frame = next_frame()?;
+        let start = Instant::now();
+        frame_bytes = encoder.encode(&frame.rgba)?.len();
+        elapsed += start.elapsed();
+    }
+    Ok((elapsed.as_secs_f32() * 1000.0 / BENCH_FRAMES as f32, frame_bytes))
+}
+
+// Blocking sends are held back once the socket buffer is full, so the
+// sustained rate is roughly what the interface accepts
+fn probe_bandwidth() -> Result<f32, String> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0))
+        .map_err(|e| format!("Failed to bind probe socket: {}", e))?;
+    socket.set_multicast_ttl_v4(1)
+        .map_err(|e| format!("Failed to set TTL: {}", e))?;
+
+    let packet = vec![0u8; PROBE_PACKET_SIZE];
+    let start = Instant::now();
+    let mut sent = 0usize;
+    while start.elapsed() < Duration::from_millis(PROBE_DURATION_MS) {
+        sent += socket.send_to(&packet, PROBE_ADDR)
+            .map_err(|e| format!("Probe send failed: {}", e))?;
+    }
+    Ok(sent as f32 * 8.0 / start.elapsed().as_secs_f32() / 1_000_000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calibration(encode_ms: f32, bandwidth_mbps: f32, cpu_count: usize) -> Calibration {
+        Calibration {
+            version: VERSION,
+            encode_ms,
+            frame_bytes: 200_000,
+            bandwidth_mbps,
+            cpu_count,
+            measured_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_defaults_follow_the_machine() {
+        // Fast desktop on gigabit: full rate, better quality, big chunks
+        let fast = calibration(8.0, 900.0, 16).defaults();
+        assert_eq!((fast.target_fps, fast.max_fps), (30, 60));
+        assert!(fast.jpeg_quality > hw_encoder::DEFAULT_JPEG_QUALITY);
+        assert_eq!(fast.chunk_size, settings::DEFAULT_CHUNK_SIZE);
+        assert_eq!(fast.encode_workers, 16);
+        assert_eq!(calibration(8.0, 900.0, 64).defaults().encode_workers, settings::MAX_ENCODE_WORKERS);
+
+        // Slow laptop on Wi-Fi: fewer frames, lower quality, MTU-sized chunks
+        let slow = calibration(50.0, 20.0, 2).defaults();
+        assert_eq!((slow.target_fps, slow.max_fps), (13, 13));
+        assert!(slow.jpeg_quality < hw_encoder::DEFAULT_JPEG_QUALITY);
+        assert_eq!(slow.chunk_size, MTU_SAFE_CHUNK_SIZE);
+        assert_eq!(slow.encode_workers, 2);
+    }
+
+    #[test]
+    fn test_applied_defaults_are_valid() {
+        for c in [calibration(8.0, 900.0, 16), calibration(50.0, 20.0, 2), calibration(500.0, 0.0, 1)] {
+            let mut settings = StreamSettings::default();
+            c.defaults().apply(&mut settings);
+            assert!(settings.validate().is_ok(), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("calibration-test-{}", std::process::id()));
+        assert!(Calibration::load(&dir).is_none());
+
+        let saved = calibration(12.5, 300.0, 8);
+        saved.save(&dir).unwrap();
+        let loaded = Calibration::load(&dir).unwrap();
+        assert_eq!(loaded.defaults(), saved.defaults());
+
+        // Results from another benchmark version are measured again
+        Calibration { version: VERSION + 1, ..saved }.save(&dir).unwrap();
+        assert!(Calibration::load(&dir).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
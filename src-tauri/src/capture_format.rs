@@ -0,0 +1,267 @@
+// Capture Format - pixel layouts the DXGI capturer can be handed
+// Desktop Duplication usually delivers BGRA8, but HDR desktops can come back as
+// RGBA16F (linear scRGB), 10-bit monitors as RGB10A2 and some hybrid GPU setups
+// as NV12. Reading those as BGRA gives garbage, so the texture's format is
+// checked on every frame and converted to the packed RGBA the encoders take.
+//
+// HDR formats are brought down to SDR by clipping at reference white (scRGB
+// 1.0), the same picture Windows shows SDR apps on an HDR desktop.
+
+use std::fmt;
+use std::sync::OnceLock;
+use serde::{Deserialize, Serialize};
+use crate::pixel_convert;
+use crate::screen_capture::DirtyRect;
+
+// DXGI_FORMAT values, see dxgiformat.h
+const DXGI_FORMAT_R16G16B16A16_FLOAT: u32 = 10;
+const DXGI_FORMAT_R10G10B10A2_UNORM: u32 = 24;
+const DXGI_FORMAT_R8G8B8A8_UNORM: u32 = 28;
+const DXGI_FORMAT_R8G8B8A8_UNORM_SRGB: u32 = 29;
+const DXGI_FORMAT_B8G8R8A8_UNORM: u32 = 87;
+const DXGI_FORMAT_B8G8R8X8_UNORM: u32 = 88;
+const DXGI_FORMAT_B8G8R8A8_UNORM_SRGB: u32 = 91;
+const DXGI_FORMAT_NV12: u32 = 103;
+
+const SRGB_LUT_SIZE: usize = 4096;
+
+/// A pixel layout the capturer understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureFormat {
+    Bgra8,
+    Rgba8,
+    /// 10 bits per color channel
+    Rgb10A2,
+    /// Half floats, linear scRGB (HDR desktops)
+    Rgba16Float,
+    /// 8-bit 4:2:0 YUV, BT.709 limited range
+    Nv12,
+}
+
+/// Why a captured texture couldn't be turned into RGBA
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FormatError {
+    /// The driver handed back a format there is no conversion for
+    Unsupported { dxgi_format: u32 },
+    /// The mapped texture is smaller than its size and pitch say
+    ShortBuffer { format: CaptureFormat, expected: usize, actual: usize },
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Unsupported { dxgi_format } => write!(
+                f,
+                "Unsupported capture format DXGI_FORMAT {} (supported: BGRA8, RGBA8, RGB10A2, RGBA16F, NV12)",
+                dxgi_format
+            ),
+            FormatError::ShortBuffer { format, expected, actual } => write!(
+                f,
+                "{:?} capture buffer holds {} bytes, expected at least {}",
+                format, actual, expected
+            ),
+        }
+    }
+}
+
+impl CaptureFormat {
+    pub fn from_dxgi(dxgi_format: u32) -> Result<Self, FormatError> {
+        match dxgi_format {
+            DXGI_FORMAT_B8G8R8A8_UNORM | DXGI_FORMAT_B8G8R8X8_UNORM | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => Ok(CaptureFormat::Bgra8),
+            DXGI_FORMAT_R8G8B8A8_UNORM | DXGI_FORMAT_R8G8B8A8_UNORM_SRGB => Ok(CaptureFormat::Rgba8),
+            DXGI_FORMAT_R10G10B10A2_UNORM => Ok(CaptureFormat::Rgb10A2),
+            DXGI_FORMAT_R16G16B16A16_FLOAT => Ok(CaptureFormat::Rgba16Float),
+            DXGI_FORMAT_NV12 => Ok(CaptureFormat::Nv12),
+            _ => Err(FormatError::Unsupported { dxgi_format }),
+        }
+    }
+
+    /// The DXGI_FORMAT to offer the duplication API
+    pub fn dxgi(self) -> u32 {
+        match self {
+            CaptureFormat::Bgra8 => DXGI_FORMAT_B8G8R8A8_UNORM,
+            CaptureFormat::Rgba8 => DXGI_FORMAT_R8G8B8A8_UNORM,
+            CaptureFormat::Rgb10A2 => DXGI_FORMAT_R10G10B10A2_UNORM,
+            CaptureFormat::Rgba16Float => DXGI_FORMAT_R16G16B16A16_FLOAT,
+            CaptureFormat::Nv12 => DXGI_FORMAT_NV12,
+        }
+    }
+
+    /// Formats Desktop Duplication can be asked for; the rest only ever show up unasked
+    pub fn is_requestable(self) -> bool {
+        matches!(self, CaptureFormat::Bgra8 | CaptureFormat::Rgb10A2 | CaptureFormat::Rgba16Float)
+    }
+
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            CaptureFormat::Rgba16Float => 8,
+            CaptureFormat::Nv12 => 1,
+            _ => 4,
+        }
+    }
+
+    /// Rows a mapped texture of `height` spans; NV12 has a half-height UV plane after the Y plane
+    pub fn mapped_rows(self, height: usize) -> usize {
+        if self == CaptureFormat::Nv12 { height + height.div_ceil(2) } else { height }
+    }
+
+    /// Whether `update_rgba` works, NV12's chroma plane is found through the full height
+    pub fn converts_regions(self) -> bool {
+        self != CaptureFormat::Nv12
+    }
+
+    /// Convert just `rects` of a mapped texture into `frame`, the packed RGBA a
+    /// `to_rgba` of the same `width` wide texture produced earlier. The rects
+    /// have to lie inside the texture.
+    pub fn update_rgba(self, data: &[u8], row_pitch: usize, frame: &mut [u8], width: usize, rects: &[DirtyRect]) -> Result<(), FormatError> {
+        for rect in rects {
+            let offset = rect.y * row_pitch + rect.x * self.bytes_per_pixel();
+            let rgba = self.to_rgba(data.get(offset..).unwrap_or_default(), row_pitch, rect.width, rect.height)?;
+            for (row, pixels) in rgba.chunks_exact((rect.width * 4).max(1)).enumerate() {
+                let start = ((rect.y + row) * width + rect.x) * 4;
+                frame[start..start + pixels.len()].copy_from_slice(pixels);
+            }
+        }
+        Ok(())
+    }
+
+    /// Packed RGBA from a mapped texture whose rows are `row_pitch` bytes apart
+    pub fn to_rgba(self, data: &[u8], row_pitch: usize, width: usize, height: usize) -> Result<Vec<u8>, FormatError> {
+        let expected = row_pitch * (self.mapped_rows(height).max(1) - 1) + width * self.bytes_per_pixel();
+        if data.len() < expected || row_pitch < width * self.bytes_per_pixel() {
+            return Err(FormatError::ShortBuffer { format: self, expected, actual: data.len() });
+        }
+
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let row = &data[y * row_pitch..];
+            match self {
+                CaptureFormat::Bgra8 => {
+                    let start = rgba.len();
+                    rgba.resize(start + width * 4, 0);
+                    pixel_convert::bgra_to_rgba(&row[..width * 4], &mut rgba[start..], true);
+                }
+                CaptureFormat::Rgba8 => {
+                    for px in row[..width * 4].chunks_exact(4) {
+                        rgba.extend_from_slice(&[px[0], px[1], px[2], 255]);
+                    }
+                }
+                CaptureFormat::Rgb10A2 => {
+                    for px in row[..width * 4].chunks_exact(4) {
+                        let v = u32::from_le_bytes([px[0], px[1], px[2], px[3]]);
+                        let channel = |shift: u32| (((v >> shift) & 0x3FF) >> 2) as u8;
+                        rgba.extend_from_slice(&[channel(0), channel(10), channel(20), 255]);
+                    }
+                }
+                CaptureFormat::Rgba16Float => {
+                    for px in row[..width * 8].chunks_exact(8) {
+                        let channel = |i: usize| linear_to_srgb(f16_to_f32(u16::from_le_bytes([px[i], px[i + 1]])));
+                        rgba.extend_from_slice(&[channel(0), channel(2), channel(4), 255]);
+                    }
+                }
+                CaptureFormat::Nv12 => {
+                    let uv_row = &data[(height + y / 2) * row_pitch..];
+                    for (x, &luma) in row[..width].iter().enumerate() {
+                        let uv = x & !1;
+                        rgba.extend_from_slice(&yuv_to_rgb(luma, uv_row[uv], uv_row[uv + 1]));
+                    }
+                }
+            }
+        }
+        Ok(rgba)
+    }
+}
+
+// BT.709 limited range, also used for decoded AV1 frames
+pub fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 4] {
+    let y = (y as f32 - 16.0) * 1.164;
+    let u = u as f32 - 128.0;
+    let v = v as f32 - 128.0;
+    let clamp = |c: f32| c.round().clamp(0.0, 255.0) as u8;
+    [
+        clamp(y + 1.793 * v),
+        clamp(y - 0.213 * u - 0.533 * v),
+        clamp(y + 2.112 * u),
+        255,
+    ]
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1F) as i32;
+    let mantissa = (half & 0x3FF) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1F => if mantissa == 0.0 { sign * f32::INFINITY } else { f32::NAN },
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+// Clip at reference white and apply the sRGB curve, through a table since
+// powf per channel is too slow for full frames
+fn linear_to_srgb(linear: f32) -> u8 {
+    static LUT: OnceLock<Vec<u8>> = OnceLock::new();
+    let lut = LUT.get_or_init(|| {
+        (0..SRGB_LUT_SIZE)
+            .map(|i| {
+                let c = i as f32 / (SRGB_LUT_SIZE - 1) as f32;
+                let encoded = if c <= 0.003_130_8 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+                (encoded * 255.0).round() as u8
+            })
+            .collect()
+    });
+    // NaN lands on 0 through the cast
+    let index = (linear.clamp(0.0, 1.0) * (SRGB_LUT_SIZE - 1) as f32).round() as usize;
+    lut[index]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dxgi_formats() {
+        assert_eq!(CaptureFormat::from_dxgi(87), Ok(CaptureFormat::Bgra8));
+        assert_eq!(CaptureFormat::from_dxgi(10), Ok(CaptureFormat::Rgba16Float));
+        assert_eq!(CaptureFormat::from_dxgi(2), Err(FormatError::Unsupported { dxgi_format: 2 }));
+        for format in [CaptureFormat::Bgra8, CaptureFormat::Rgb10A2, CaptureFormat::Nv12] {
+            assert_eq!(CaptureFormat::from_dxgi(format.dxgi()), Ok(format));
+        }
+        let json = serde_json::to_value(FormatError::Unsupported { dxgi_format: 2 }).unwrap();
+        assert_eq!(json["kind"], "unsupported");
+    }
+
+    #[test]
+    fn test_conversions_with_row_padding() {
+        // 2x1 pixels, rows padded to 16 bytes
+        let bgra = [10, 20, 30, 0, 40, 50, 60, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(CaptureFormat::Bgra8.to_rgba(&bgra, 16, 2, 1).unwrap(), [30, 20, 10, 255, 60, 50, 40, 255]);
+
+        let white_red = (0x3FFu32 | (0x3FF << 10) | (0x3FF << 20)).to_le_bytes();
+        assert_eq!(CaptureFormat::Rgb10A2.to_rgba(&white_red, 4, 1, 1).unwrap(), [255, 255, 255, 255]);
+
+        // scRGB: 1.0 is white, 0.5 linear is sRGB 188, overbright clips
+        let half = |v: u16| v.to_le_bytes();
+        let hdr = [half(0x3C00), half(0x3800), half(0x4400), half(0x3C00)].concat();
+        assert_eq!(CaptureFormat::Rgba16Float.to_rgba(&hdr, 8, 1, 1).unwrap(), [255, 188, 255, 255]);
+
+        // 2x2 NV12: Y plane, then one UV pair; mid grey
+        let nv12 = [126, 126, 126, 126, 128, 128];
+        assert_eq!(CaptureFormat::Nv12.to_rgba(&nv12, 2, 2, 2).unwrap()[..4], [128, 128, 128, 255]);
+
+        // Only the second pixel changed
+        let mut frame = CaptureFormat::Bgra8.to_rgba(&bgra, 16, 2, 1).unwrap();
+        let mut changed = bgra;
+        changed[4..7].copy_from_slice(&[1, 2, 3]);
+        let rect = DirtyRect { x: 1, y: 0, width: 1, height: 1 };
+        CaptureFormat::Bgra8.update_rgba(&changed, 16, &mut frame, 2, &[rect]).unwrap();
+        assert_eq!(frame, [30, 20, 10, 255, 3, 2, 1, 255]);
+
+        assert_eq!(
+            CaptureFormat::Bgra8.to_rgba(&bgra[..7], 16, 2, 1),
+            Err(FormatError::ShortBuffer { format: CaptureFormat::Bgra8, expected: 8, actual: 7 })
+        );
+    }
+}
@@ -0,0 +1,301 @@
+// Capture Manager - picks the screen capture backend and remembers why
+// Backends are tried best first: Windows.Graphics.Capture, then DXGI Desktop
+// Duplication, then scrap, which works everywhere but on Wayland, where the
+// desktop portal (PipeWire) comes first. Each streaming session gets
+// its own `CaptureSession` holding whatever it opened, and every fallback is
+// written to a report the manager keeps, so the UI can show why a machine
+// ended up on a slower path.
+
+use std::sync::{Arc, Mutex};
+use serde::Serialize;
+use crate::capture_format::CaptureFormat;
+use crate::frame_source::FrameSource;
+use crate::screen_capture::{self, RawFrame, ScreenCapture};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureBackend {
+    /// Windows.Graphics.Capture (Windows 10 1803+)
+    GraphicsCapture,
+    /// DXGI Desktop Duplication
+    Dxgi,
+    /// xdg-desktop-portal ScreenCast over PipeWire (Wayland)
+    Portal,
+    Scrap,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum BackendState {
+    /// Not tried this session, or a better backend took over
+    Unused,
+    /// Delivering the frames
+    Active,
+    /// Unavailable, failed to open or failed while capturing
+    Failed { reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BackendStatus {
+    pub backend: CaptureBackend,
+    #[serde(flatten)]
+    pub state: BackendState,
+}
+
+/// The backends this build can use, best first, with how the last session fared
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CaptureCapabilities {
+    pub active: Option<CaptureBackend>,
+    pub backends: Vec<BackendStatus>,
+}
+
+impl Default for CaptureCapabilities {
+    fn default() -> Self {
+        Self {
+            active: None,
+            backends: backends()
+                .into_iter()
+                .map(|(backend, state)| BackendStatus { backend, state })
+                .collect(),
+        }
+    }
+}
+
+// Backends of this platform and build, those compiled out already failed
+fn backends() -> Vec<(CaptureBackend, BackendState)> {
+    let mut backends = Vec::new();
+    if cfg!(target_os = "windows") {
+        let state = if cfg!(feature = "dxgi") {
+            BackendState::Unused
+        } else {
+            BackendState::Failed { reason: "not compiled in (enable the `dxgi` feature)".to_string() }
+        };
+        backends.push((CaptureBackend::GraphicsCapture, state.clone()));
+        backends.push((CaptureBackend::Dxgi, state));
+    }
+    if cfg!(target_os = "linux") {
+        let state = if cfg!(feature = "wayland") {
+            BackendState::Unused
+        } else {
+            BackendState::Failed { reason: "not compiled in (enable the `wayland` feature)".to_string() }
+        };
+        backends.push((CaptureBackend::Portal, state));
+    }
+    backends.push((CaptureBackend::Scrap, BackendState::Unused));
+    backends
+}
+
+/// Shared record of one session's backend decisions
+#[derive(Clone, Default)]
+pub struct CaptureReport(Arc<Mutex<CaptureCapabilities>>);
+
+impl CaptureReport {
+    /// `backend` delivers the frames from now on
+    pub fn activate(&self, backend: CaptureBackend) {
+        let mut capabilities = self.0.lock().unwrap();
+        if capabilities.active == Some(backend) {
+            return;
+        }
+        capabilities.active = Some(backend);
+        for status in &mut capabilities.backends {
+            if status.backend == backend {
+                status.state = BackendState::Active;
+            } else if status.state == BackendState::Active {
+                status.state = BackendState::Unused;
+            }
+        }
+    }
+
+    /// `backend` can't be used, the next one down takes over
+    pub fn fail(&self, backend: CaptureBackend, reason: impl Into<String>) {
+        let mut capabilities = self.0.lock().unwrap();
+        if capabilities.active == Some(backend) {
+            capabilities.active = None;
+        }
+        if let Some(status) = capabilities.backends.iter_mut().find(|s| s.backend == backend) {
+            status.state = BackendState::Failed { reason: reason.into() };
+        }
+    }
+
+    fn snapshot(&self) -> CaptureCapabilities {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Owned by the app state, hands out one capture session per stream
+#[derive(Default)]
+pub struct CaptureManager {
+    report: Mutex<CaptureReport>,
+}
+
+impl CaptureManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Capture of the selected display for a new stream, replacing the last session's report
+    pub fn session(&self, formats: &[CaptureFormat]) -> CaptureSession {
+        let report = CaptureReport::default();
+        *self.report.lock().unwrap() = report.clone();
+        CaptureSession::new(screen_capture::selected_display(), formats, report)
+    }
+
+    /// Capture of another display streamed alongside, which the report leaves out
+    pub fn display_session(&self, display: usize, formats: &[CaptureFormat]) -> CaptureSession {
+        CaptureSession::new(Some(display), formats, CaptureReport::default())
+    }
+
+    pub fn capabilities(&self) -> CaptureCapabilities {
+        self.report.lock().unwrap().snapshot()
+    }
+}
+
+/// Everything one stream captures with, released when the pipeline drops it
+pub struct CaptureSession {
+    #[cfg(all(target_os = "windows", feature = "dxgi"))]
+    graphics: Option<crate::windows_capture::WindowsScreenCapture>,
+    #[cfg(all(target_os = "linux", feature = "wayland"))]
+    portal: Option<crate::wayland_capture::PortalCapture>,
+    screen: ScreenCapture,
+    #[cfg_attr(not(any(all(target_os = "windows", feature = "dxgi"), all(target_os = "linux", feature = "wayland"))), allow(dead_code))]
+    report: CaptureReport,
+}
+
+impl CaptureSession {
+    fn new(display: Option<usize>, formats: &[CaptureFormat], report: CaptureReport) -> Self {
+        let screen = ScreenCapture::new(display, formats, report.clone());
+
+        #[cfg(all(target_os = "windows", feature = "dxgi"))]
+        let graphics = match screen.display() {
+            Some(_) => {
+                report.fail(CaptureBackend::GraphicsCapture, "only captures the primary display");
+                None
+            }
+            None => match crate::windows_capture::open_graphics_capture() {
+                Ok(capture) => {
+                    eprintln!("✅ Using Windows.Graphics.Capture (high performance)");
+                    Some(capture)
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Windows.Graphics.Capture unavailable: {}", e);
+                    report.fail(CaptureBackend::GraphicsCapture, e);
+                    None
+                }
+            },
+        };
+
+        #[cfg(all(target_os = "linux", feature = "wayland"))]
+        let portal = match (crate::wayland_capture::is_wayland_session(), screen.display()) {
+            // X11, scrap sees the screen
+            (false, _) => None,
+            (true, Some(_)) => {
+                report.fail(CaptureBackend::Portal, "only captures the monitor picked in the portal's dialog");
+                None
+            }
+            (true, None) => match crate::wayland_capture::PortalCapture::open() {
+                Ok(capture) => {
+                    eprintln!("✅ Using the desktop portal (PipeWire) for Wayland");
+                    Some(capture)
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Desktop portal capture unavailable: {}", e);
+                    report.fail(CaptureBackend::Portal, e);
+                    None
+                }
+            },
+        };
+        #[cfg(all(target_os = "linux", not(feature = "wayland")))]
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            eprintln!("⚠️  Wayland session, but the `wayland` feature isn't compiled in; scrap may capture nothing");
+        }
+
+        Self {
+            #[cfg(all(target_os = "windows", feature = "dxgi"))]
+            graphics,
+            #[cfg(all(target_os = "linux", feature = "wayland"))]
+            portal,
+            screen,
+            report,
+        }
+    }
+
+    /// Frames scaled to at most `max_width`, 0 for native resolution
+    pub fn with_max_width(mut self, max_width: u32) -> Self {
+        self.screen.set_max_width(max_width);
+        self
+    }
+
+    /// Frames without the pointer, for streams that send it as metadata
+    pub fn without_cursor(mut self) -> Self {
+        self.screen.hide_cursor();
+        #[cfg(all(target_os = "windows", feature = "dxgi"))]
+        if let Some(graphics) = &self.graphics {
+            graphics.hide_cursor();
+        }
+        self
+    }
+}
+
+impl FrameSource for CaptureSession {
+    fn next_frame(&mut self) -> Result<Option<RawFrame>, String> {
+        #[cfg(all(target_os = "windows", feature = "dxgi"))]
+        if let Some(graphics) = self.graphics.as_mut() {
+            match graphics.capture_frame() {
+                Ok(Some(img)) => {
+                    let captured_at = std::time::Instant::now();
+                    self.report.activate(CaptureBackend::GraphicsCapture);
+                    return Ok(Some(screen_capture::scale_frame(img, self.screen.max_width(), captured_at, None, Vec::new())));
+                }
+                Ok(None) => return Ok(None),
+                Err(e) => {
+                    // DXGI or scrap from now on
+                    eprintln!("❌ Windows.Graphics.Capture error: {}, falling back", e);
+                    self.report.fail(CaptureBackend::GraphicsCapture, e);
+                    self.graphics = None;
+                }
+            }
+        }
+
+        #[cfg(all(target_os = "linux", feature = "wayland"))]
+        if let Some(portal) = &self.portal {
+            match portal.capture_frame() {
+                Ok(Some(img)) => {
+                    let captured_at = std::time::Instant::now();
+                    self.report.activate(CaptureBackend::Portal);
+                    return Ok(Some(screen_capture::scale_frame(img, self.screen.max_width(), captured_at, None, Vec::new())));
+                }
+                Ok(None) => return Ok(None),
+                Err(e) => {
+                    eprintln!("❌ Desktop portal capture ended: {}, falling back to scrap", e);
+                    self.report.fail(CaptureBackend::Portal, e);
+                    self.portal = None;
+                }
+            }
+        }
+
+        self.screen.capture()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_tracks_fallbacks() {
+        let report = CaptureReport::default();
+        report.activate(CaptureBackend::Scrap);
+        assert_eq!(report.snapshot().active, Some(CaptureBackend::Scrap));
+
+        report.fail(CaptureBackend::Scrap, "display gone");
+        let capabilities = report.snapshot();
+        assert_eq!(capabilities.active, None);
+        let scrap = capabilities.backends.iter().find(|s| s.backend == CaptureBackend::Scrap).unwrap();
+        assert_eq!(scrap.state, BackendState::Failed { reason: "display gone".to_string() });
+
+        let json = serde_json::to_value(scrap).unwrap();
+        assert_eq!(json["backend"], "scrap");
+        assert_eq!(json["state"], "failed");
+        assert_eq!(json["reason"], "display gone");
+    }
+}
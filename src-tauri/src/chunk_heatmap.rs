@@ -0,0 +1,145 @@
+// Chunk Heatmap - where in a frame the viewer loses chunks
+// A diagnostic the viewer turns on to find out *why* chunks go missing.
+// Every frame that was dropped or salvaged with gaps adds its missing chunk
+// indices to three histograms, and the overall shape points at the fix:
+//
+//   tail       the end of each burst: the receive buffer overflows
+//              (bigger socket buffers, or fewer/smaller chunks per frame)
+//   clustered  runs of consecutive chunks mid-frame: a switch or queue drops
+//              bursts (pace the sends, lower the frame size)
+//   scattered  single chunks all over: random loss on the link (FEC)
+
+use serde::Serialize;
+
+/// Chunk indices tracked one by one, later ones share the last bucket
+const INDEX_BUCKETS: usize = 32;
+const POSITION_BUCKETS: usize = 10;
+/// Chunks counted back from the last one
+const FROM_END_BUCKETS: usize = 8;
+const MIN_SAMPLE: u64 = 20; // Missing chunks before a pattern is called
+const DOMINANT_SHARE: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LossPattern {
+    /// Too little loss to tell
+    #[default]
+    Unknown,
+    Tail,
+    Clustered,
+    Scattered,
+}
+
+/// Histograms of missing chunk indices, reported in the viewer's stats
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChunkHeatmap {
+    pub frames_with_loss: u64,
+    pub missing_chunks: u64,
+    /// Missing count per chunk index, the last bucket holds every index beyond
+    pub by_index: Vec<u64>,
+    /// Missing count per tenth of the frame, first tenth first
+    pub by_position: Vec<u64>,
+    /// Missing count counted back from the frame's last chunk, the last chunk first
+    pub from_end: Vec<u64>,
+    /// Missing chunks whose predecessor was missing too
+    pub in_runs: u64,
+    pub pattern: LossPattern,
+}
+
+impl Default for ChunkHeatmap {
+    fn default() -> Self {
+        Self {
+            frames_with_loss: 0,
+            missing_chunks: 0,
+            by_index: vec![0; INDEX_BUCKETS],
+            by_position: vec![0; POSITION_BUCKETS],
+            from_end: vec![0; FROM_END_BUCKETS],
+            in_runs: 0,
+            pattern: LossPattern::Unknown,
+        }
+    }
+}
+
+impl ChunkHeatmap {
+    /// Record a frame given as its chunk slots, empty slots never arrived
+    pub fn record(&mut self, chunks: &[Vec<u8>]) {
+        let total = chunks.len();
+        let mut previous_missing = false;
+        let mut any_missing = false;
+        for (index, chunk) in chunks.iter().enumerate() {
+            let missing = chunk.is_empty();
+            if missing {
+                any_missing = true;
+                self.missing_chunks += 1;
+                self.by_index[index.min(INDEX_BUCKETS - 1)] += 1;
+                self.by_position[index * POSITION_BUCKETS / total] += 1;
+                if let Some(bucket) = self.from_end.get_mut(total - 1 - index) {
+                    *bucket += 1;
+                }
+                if previous_missing {
+                    self.in_runs += 1;
+                }
+            }
+            previous_missing = missing;
+        }
+        if any_missing {
+            self.frames_with_loss += 1;
+            self.pattern = self.classify();
+        }
+    }
+
+    fn classify(&self) -> LossPattern {
+        if self.missing_chunks < MIN_SAMPLE {
+            return LossPattern::Unknown;
+        }
+        let share = |count: u64| count as f32 / self.missing_chunks as f32;
+        if share(self.by_position[POSITION_BUCKETS - 1]) >= DOMINANT_SHARE {
+            LossPattern::Tail
+        } else if share(self.in_runs) >= DOMINANT_SHARE {
+            LossPattern::Clustered
+        } else {
+            LossPattern::Scattered
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(total: usize, missing: &[usize]) -> Vec<Vec<u8>> {
+        (0..total).map(|i| if missing.contains(&i) { Vec::new() } else { vec![1] }).collect()
+    }
+
+    #[test]
+    fn test_tail_loss() {
+        let mut heatmap = ChunkHeatmap::default();
+        heatmap.record(&frame(10, &[]));
+        assert_eq!(heatmap.frames_with_loss, 0);
+
+        for _ in 0..10 {
+            heatmap.record(&frame(40, &[38, 39]));
+        }
+        assert_eq!((heatmap.frames_with_loss, heatmap.missing_chunks), (10, 20));
+        assert_eq!(heatmap.by_index[INDEX_BUCKETS - 1], 20);
+        assert_eq!(heatmap.from_end[..2], [10, 10]);
+        assert_eq!(heatmap.pattern, LossPattern::Tail);
+    }
+
+    #[test]
+    fn test_runs_and_scattered_loss() {
+        let mut clustered = ChunkHeatmap::default();
+        for _ in 0..5 {
+            clustered.record(&frame(20, &[4, 5, 6, 7, 8]));
+        }
+        assert_eq!(clustered.in_runs, 20);
+        assert_eq!(clustered.pattern, LossPattern::Clustered);
+
+        let mut scattered = ChunkHeatmap::default();
+        for _ in 0..10 {
+            scattered.record(&frame(20, &[2, 11]));
+        }
+        assert_eq!(scattered.by_position[1], 10);
+        assert_eq!(scattered.pattern, LossPattern::Scattered);
+    }
+}
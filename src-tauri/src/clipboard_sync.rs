@@ -0,0 +1,240 @@
+// Clipboard Sync - shares copied text between the server and its viewers
+// Runs over TCP beside the multicast stream, since a lost clipboard update
+// is worse than a late one. The server accepts viewers on CLIPBOARD_PORT and
+// relays each change to everyone else; a viewer connects to the address the
+// stream comes from.
+//
+// Frames: length u32 (big-endian) | UTF-8 text
+//
+// The clipboard has no change notification that works everywhere, so the
+// owner thread polls it. That thread also applies incoming text, because on
+// Linux the clipboard contents live only as long as the instance that set them.
+
+use std::io::{Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const CLIPBOARD_PORT: u16 = 9998;
+const POLL_INTERVAL_MS: u64 = 500;
+const ACCEPT_INTERVAL_MS: u64 = 200;
+const MAX_TEXT_BYTES: usize = 1024 * 1024; // Larger copies are not synced
+
+type PeerId = u64;
+
+/// Connected peers, written to by the clipboard thread
+type Peers = Arc<Mutex<Vec<(PeerId, TcpStream)>>>;
+
+/// Running clipboard sync, stopped on `stop()` or drop
+pub struct ClipboardSync {
+    running: Arc<AtomicBool>,
+    peers: Peers,
+}
+
+impl ClipboardSync {
+    /// Accept viewers and keep their clipboards in sync with ours and each other's
+    pub fn start_server(port: u16) -> Result<Self, String> {
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .map_err(|e| format!("Failed to bind clipboard sync on port {}: {}", port, e))?;
+        listener.set_nonblocking(true)
+            .map_err(|e| format!("Failed to configure clipboard listener: {}", e))?;
+
+        let sync = Self::new();
+        let incoming = sync.spawn_clipboard_thread(true)?;
+
+        let running = sync.running.clone();
+        let peers = sync.peers.clone();
+        std::thread::spawn(move || {
+            let mut next_id: PeerId = 0;
+            while running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, addr)) => {
+                        let id = next_id;
+                        next_id += 1;
+                        if let Err(e) = add_peer(&peers, id, stream, addr, incoming.clone(), running.clone()) {
+                            eprintln!("❌ Clipboard peer {}: {}", addr, e);
+                        }
+                    }
+                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(ACCEPT_INTERVAL_MS));
+                    }
+                    Err(e) => eprintln!("❌ Clipboard accept error: {}", e),
+                }
+            }
+        });
+
+        eprintln!("📋 Clipboard sync listening on port {}", port);
+        Ok(sync)
+    }
+
+    /// Connect to the server the stream comes from
+    pub fn start_client(server: IpAddr, port: u16) -> Result<Self, String> {
+        let addr = SocketAddr::new(server, port);
+        let stream = TcpStream::connect_timeout(&addr, Duration::from_secs(3))
+            .map_err(|e| format!("Failed to connect clipboard sync to {}: {}", addr, e))?;
+
+        let sync = Self::new();
+        let incoming = sync.spawn_clipboard_thread(false)?;
+        add_peer(&sync.peers, 0, stream, addr, incoming, sync.running.clone())?;
+
+        eprintln!("📋 Clipboard sync connected to {}", addr);
+        Ok(sync)
+    }
+
+    fn new() -> Self {
+        Self {
+            running: Arc::new(AtomicBool::new(true)),
+            peers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    // Owns the clipboard: applies text from peers and sends local changes out.
+    // Returns where peers deliver incoming text.
+    fn spawn_clipboard_thread(&self, relay: bool) -> Result<Sender<(PeerId, String)>, String> {
+        let mut clipboard = arboard::Clipboard::new()
+            .map_err(|e| format!("Failed to open clipboard: {}", e))?;
+        let (incoming_tx, incoming_rx) = mpsc::channel::<(PeerId, String)>();
+
+        let running = self.running.clone();
+        let peers = self.peers.clone();
+        std::thread::spawn(move || {
+            // What is on the clipboard now, so our own writes aren't echoed back
+            let mut last_text = clipboard.get_text().ok();
+
+            while running.load(Ordering::Relaxed) {
+                match incoming_rx.recv_timeout(Duration::from_millis(POLL_INTERVAL_MS)) {
+                    Ok((from, text)) => {
+                        if last_text.as_deref() == Some(text.as_str()) {
+                            continue;
+                        }
+                        if let Err(e) = clipboard.set_text(text.clone()) {
+                            eprintln!("❌ Failed to set clipboard: {}", e);
+                            continue;
+                        }
+                        eprintln!("📋 Clipboard updated from peer ({} bytes)", text.len());
+                        if relay {
+                            broadcast(&peers, &text, Some(from));
+                        }
+                        last_text = Some(text);
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        // Non-text contents (images, files) read as errors and are skipped
+                        let Ok(text) = clipboard.get_text() else {
+                            continue;
+                        };
+                        if last_text.as_deref() != Some(text.as_str()) {
+                            broadcast(&peers, &text, None);
+                            last_text = Some(text);
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+
+        Ok(incoming_tx)
+    }
+
+    pub fn stop(&self) {
+        if !self.running.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        // Unblocks the reader threads
+        for (_, stream) in self.peers.lock().unwrap().drain(..) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        eprintln!("🔴 Clipboard sync stopped");
+    }
+}
+
+impl Drop for ClipboardSync {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn add_peer(
+    peers: &Peers,
+    id: PeerId,
+    stream: TcpStream,
+    addr: SocketAddr,
+    incoming: Sender<(PeerId, String)>,
+    running: Arc<AtomicBool>,
+) -> Result<(), String> {
+    // Accepted sockets may inherit the listener's non-blocking mode
+    stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+    let _ = stream.set_nodelay(true);
+    let mut reader = stream.try_clone().map_err(|e| e.to_string())?;
+    peers.lock().unwrap().push((id, stream));
+    eprintln!("📋 Clipboard peer connected: {}", addr);
+
+    let peers = peers.clone();
+    std::thread::spawn(move || {
+        while running.load(Ordering::Relaxed) {
+            match read_text(&mut reader) {
+                Ok(Some(text)) => {
+                    if incoming.send((id, text)).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => {} // Oversized or not UTF-8, skipped
+                Err(_) => break,
+            }
+        }
+        peers.lock().unwrap().retain(|(peer, _)| *peer != id);
+        eprintln!("📋 Clipboard peer disconnected: {}", addr);
+    });
+    Ok(())
+}
+
+fn broadcast(peers: &Peers, text: &str, except: Option<PeerId>) {
+    if text.len() > MAX_TEXT_BYTES {
+        eprintln!("⚠️  Clipboard text too large to sync ({} bytes)", text.len());
+        return;
+    }
+    let frame = encode_text(text);
+    // Peers that can't be written to are dropped, their reader cleans up too
+    peers.lock().unwrap().retain_mut(|(id, stream)| {
+        Some(*id) == except || stream.write_all(&frame).is_ok()
+    });
+}
+
+fn encode_text(text: &str) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(4 + text.len());
+    frame.extend_from_slice(&(text.len() as u32).to_be_bytes());
+    frame.extend_from_slice(text.as_bytes());
+    frame
+}
+
+/// Read one frame. `Ok(None)` for frames that are skipped but leave the stream usable.
+fn read_text(reader: &mut impl Read) -> std::io::Result<Option<String>> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+
+    if len > MAX_TEXT_BYTES {
+        std::io::copy(&mut reader.take(len as u64), &mut std::io::sink())?;
+        return Ok(None);
+    }
+
+    let mut text = vec![0u8; len];
+    reader.read_exact(&mut text)?;
+    Ok(String::from_utf8(text).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_frames() {
+        let mut stream = encode_text("Xin chào 👋");
+        stream.extend_from_slice(&encode_text(""));
+        let mut reader = std::io::Cursor::new(stream);
+        assert_eq!(read_text(&mut reader).unwrap(), Some("Xin chào 👋".to_string()));
+        assert_eq!(read_text(&mut reader).unwrap(), Some(String::new()));
+        assert!(read_text(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+// Stream Clock - the single time base for a streaming session
+// Everything is measured from one monotonic `Instant`, so timestamps never
+// jump with wall-clock changes. Wire timestamps are microseconds since the
+// session started and wrap every ~71 minutes; only differences are meaningful.
+
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy)]
+pub struct StreamClock {
+    epoch: Instant,
+}
+
+impl StreamClock {
+    pub fn new() -> Self {
+        Self { epoch: Instant::now() }
+    }
+
+    /// Wire timestamp for a moment during this session
+    pub fn timestamp(&self, at: Instant) -> u32 {
+        at.saturating_duration_since(self.epoch).as_micros() as u32
+    }
+
+    pub fn now(&self) -> u32 {
+        self.timestamp(Instant::now())
+    }
+}
+
+impl Default for StreamClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Difference between two wire timestamps in microseconds, wrap-safe
+pub fn elapsed_us(earlier: u32, later: u32) -> i64 {
+    later.wrapping_sub(earlier) as i32 as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elapsed_across_wrap() {
+        assert_eq!(elapsed_us(u32::MAX - 9, 10), 20);
+        assert_eq!(elapsed_us(10, 5), -5);
+    }
+}
@@ -0,0 +1,245 @@
+// Remote Control API - HTTP endpoints mirroring the Tauri commands
+// Lets an admin console start/stop and monitor many sender machines centrally
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Request, State,
+    },
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::sync::oneshot;
+
+use crate::settings::StreamSettings;
+use crate::virtual_display::VirtualDisplayConfig;
+use crate::AppState;
+
+pub const DEFAULT_PORT: u16 = 8787;
+const TOKEN_LENGTH: usize = 32;
+
+#[derive(Clone)]
+struct ApiState {
+    app: AppHandle,
+    token: Arc<String>,
+}
+
+/// Running HTTP control server, shut down on `stop()` or drop
+pub struct ControlApi {
+    port: u16,
+    token: String,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl ControlApi {
+    pub async fn start(app: AppHandle, port: u16, token: Option<String>) -> Result<Self, String> {
+        let token = match token {
+            Some(t) if !t.is_empty() => t,
+            _ => generate_token(),
+        };
+
+        let listener = tokio::net::TcpListener::bind(("0.0.0.0", port))
+            .await
+            .map_err(|e| format!("Failed to bind control API on port {}: {}", port, e))?;
+
+        let api = ApiState {
+            app,
+            token: Arc::new(token.clone()),
+        };
+
+        let router = Router::new()
+            .route("/api/status", get(status))
+            .route("/api/server/start", post(start_server))
+            .route("/api/server/stop", post(stop_server))
+            .route("/api/client/start", post(start_client))
+            .route("/api/client/stop", post(stop_client))
+            .route("/api/settings", get(get_settings).put(update_settings))
+            .route("/api/virtual-display", post(start_virtual_display).delete(stop_virtual_display))
+            .route("/api/stats", get(get_stats))
+            .route("/api/stats/ws", get(stats_socket))
+            .layer(middleware::from_fn_with_state(api.clone(), require_token))
+            .with_state(api);
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        tokio::spawn(async move {
+            let result = axum::serve(listener, router)
+                .with_graceful_shutdown(async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+            if let Err(e) = result {
+                eprintln!("❌ Control API error: {}", e);
+            }
+            eprintln!("🔴 Control API stopped");
+        });
+
+        eprintln!("🌐 Control API listening on 0.0.0.0:{}", port);
+
+        Ok(Self {
+            port,
+            token,
+            shutdown: Some(shutdown_tx),
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+impl Drop for ControlApi {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn generate_token() -> String {
+    use rand::distributions::Alphanumeric;
+    use rand::Rng;
+
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+// Compare without short-circuiting so response timing doesn't leak the token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Browsers can't set headers on WebSocket upgrades, so `?token=` is accepted too
+fn request_token(request: &Request) -> Option<&str> {
+    let from_header = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    from_header.or_else(|| {
+        request
+            .uri()
+            .query()?
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+    })
+}
+
+async fn require_token(State(api): State<ApiState>, request: Request, next: Next) -> Response {
+    let authorized = request_token(&request)
+        .map(|t| constant_time_eq(t.as_bytes(), api.token.as_bytes()))
+        .unwrap_or(false);
+
+    if !authorized {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "Missing or invalid bearer token" })),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+fn respond(result: Result<String, String>) -> Response {
+    match result {
+        Ok(message) => Json(json!({ "message": message })).into_response(),
+        Err(error) => (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))).into_response(),
+    }
+}
+
+async fn status(State(api): State<ApiState>) -> Response {
+    let state = api.app.state::<AppState>();
+    Json(crate::status_snapshot(&state)).into_response()
+}
+
+async fn start_server(State(api): State<ApiState>) -> Response {
+    let state = api.app.state::<AppState>();
+    respond(crate::server_start(api.app.clone(), &state).await)
+}
+
+async fn stop_server(State(api): State<ApiState>) -> Response {
+    let state = api.app.state::<AppState>();
+    respond(crate::server_stop(&state))
+}
+
+async fn start_client(State(api): State<ApiState>) -> Response {
+    let state = api.app.state::<AppState>();
+    respond(crate::client_start(api.app.clone(), &state))
+}
+
+async fn stop_client(State(api): State<ApiState>) -> Response {
+    let state = api.app.state::<AppState>();
+    respond(crate::client_stop(&state))
+}
+
+async fn start_virtual_display(State(api): State<ApiState>, config: Option<Json<VirtualDisplayConfig>>) -> Response {
+    let state = api.app.state::<AppState>();
+    let config = config.map(|Json(c)| c).unwrap_or_default();
+    respond(crate::virtual_display_start(api.app.clone(), &state, config).await)
+}
+
+async fn stop_virtual_display(State(api): State<ApiState>) -> Response {
+    let state = api.app.state::<AppState>();
+    respond(crate::virtual_display_stop(api.app.clone(), &state).await)
+}
+
+async fn get_settings(State(api): State<ApiState>) -> Response {
+    let state = api.app.state::<AppState>();
+    let settings = state.settings.lock().unwrap().clone();
+    Json(settings).into_response()
+}
+
+async fn update_settings(State(api): State<ApiState>, Json(settings): Json<StreamSettings>) -> Response {
+    let state = api.app.state::<AppState>();
+    respond(crate::apply_settings(&state, settings))
+}
+
+async fn get_stats(State(api): State<ApiState>) -> Response {
+    let state = api.app.state::<AppState>();
+    Json(crate::stats_snapshot(&state)).into_response()
+}
+
+async fn stats_socket(State(api): State<ApiState>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| push_stats(socket, api.app))
+}
+
+// Push a stats snapshot every second until the console disconnects
+async fn push_stats(mut socket: WebSocket, app: AppHandle) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+
+        let payload = {
+            let state = app.state::<AppState>();
+            serde_json::to_string(&crate::stats_snapshot(&state))
+        };
+        let Ok(payload) = payload else { break };
+
+        if socket.send(Message::Text(payload)).await.is_err() {
+            break;
+        }
+    }
+}
@@ -1,208 +1,348 @@
-// Mouse Cursor Capture for Windows
-// Draws cursor on captured frames
+// Cursor Capture - composites the mouse pointer onto captured frames
+// Neither DXGI duplication nor scrap include the pointer, so it is drawn into
+// the full resolution capture before it is scaled, at its position relative
+// to the captured display.
+//
+// Windows draws the real cursor: color icons with their alpha, and monochrome
+// ones from their AND/XOR masks, inverting where they invert (the I-beam).
+// Elsewhere the position comes from enigo and a built-in arrow stands in; only
+// the primary display's origin is known there, so other displays go without.
 
-#[cfg(windows)]
-use windows::Win32::{
-    UI::WindowsAndMessaging::*,
-    Graphics::Gdi::*,
-    Foundation::*,
-};
-
-#[derive(Debug, Clone)]
-pub struct CursorInfo {
-    pub x: i32,
-    pub y: i32,
-    pub visible: bool,
-    pub icon_data: Option<Vec<u8>>,
-    pub width: u32,
-    pub height: u32,
+use image::RgbaImage;
+use crate::screen_capture::DirtyRect;
+
+/// A pointer image, `hotspot` is the pixel that sits on the pointer position
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorIcon {
+    pub width: usize,
+    pub height: usize,
+    pub hotspot: (i32, i32),
+    pub rgba: Vec<u8>,
+    /// Pixels that invert what is under them, empty for icons without any
+    pub invert: Vec<bool>,
 }
 
-#[cfg(windows)]
+/// One capture session's view of the pointer; icons are cached per cursor shape
 pub struct CursorCapturer {
-    last_cursor: Option<HCURSOR>,
+    /// Handle of the cursor shape the cached icon is for
+    #[cfg_attr(not(windows), allow(dead_code))]
+    shape: Option<isize>,
+    icon: Option<CursorIcon>,
+    icon_hash: u32,
+}
+
+impl CursorIcon {
+    /// FNV-1a over the image, the same on every machine for the same pointer
+    pub fn hash(&self) -> u32 {
+        let (hx, hy) = self.hotspot;
+        let invert = self.invert.iter().map(|&i| i as u8);
+        let dimensions = [self.width as u32, self.height as u32, hx as u32, hy as u32];
+        dimensions.iter().flat_map(|d| d.to_be_bytes())
+            .chain(self.rgba.iter().copied())
+            .chain(invert)
+            .fold(0x811c_9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+    }
 }
 
-#[cfg(windows)]
 impl CursorCapturer {
     pub fn new() -> Self {
-        Self {
-            last_cursor: None,
-        }
+        Self { shape: None, icon: None, icon_hash: 0 }
     }
 
-    /// Get current cursor information
-    pub fn get_cursor_info(&mut self) -> Option<CursorInfo> {
-        unsafe {
-            // Get cursor position and visibility
-            let mut cursor_info = CURSORINFO {
-                cbSize: std::mem::size_of::<CURSORINFO>() as u32,
-                ..Default::default()
-            };
+    /// Pointer position on the desktop and the hash of its image, `None` while hidden
+    pub fn sample(&mut self) -> Option<((i32, i32), u32)> {
+        let position = self.pointer()?;
+        Some((position, self.icon_hash))
+    }
 
-            if GetCursorInfo(&mut cursor_info).is_err() {
-                return None;
-            }
+    /// Draw the pointer into `frame`, a capture of the display whose top-left
+    /// corner is at `origin` on the desktop. Nothing when the pointer is hidden.
+    /// Returns the part of `frame` the pointer covers, `None` when it isn't on it
+    pub fn draw(&mut self, frame: &mut RgbaImage, origin: (i32, i32)) -> Option<DirtyRect> {
+        let (x, y) = self.pointer()?;
+        let icon = self.icon.as_ref()?;
+        let (x, y) = (x - origin.0 - icon.hotspot.0, y - origin.1 - icon.hotspot.1);
+        composite(frame, icon, x, y);
 
-            if cursor_info.flags != CURSOR_SHOWING {
-                return Some(CursorInfo {
-                    x: cursor_info.ptScreenPos.x,
-                    y: cursor_info.ptScreenPos.y,
-                    visible: false,
-                    icon_data: None,
-                    width: 0,
-                    height: 0,
-                });
-            }
+        let (left, top) = (x.max(0), y.max(0));
+        let right = (x + icon.width as i32).min(frame.width() as i32);
+        let bottom = (y + icon.height as i32).min(frame.height() as i32);
+        (right > left && bottom > top).then(|| DirtyRect {
+            x: left as usize,
+            y: top as usize,
+            width: (right - left) as usize,
+            height: (bottom - top) as usize,
+        })
+    }
 
-            // Get cursor icon info
-            let mut icon_info = ICONINFO::default();
-            if GetIconInfo(cursor_info.hCursor, &mut icon_info).is_err() {
-                return Some(CursorInfo {
-                    x: cursor_info.ptScreenPos.x,
-                    y: cursor_info.ptScreenPos.y,
-                    visible: true,
-                    icon_data: None,
-                    width: 0,
-                    height: 0,
-                });
-            }
+    // Pointer position on the desktop, with the icon for its current shape
+    #[cfg(windows)]
+    fn pointer(&mut self) -> Option<(i32, i32)> {
+        let (x, y, cursor) = platform::cursor()?;
+        let shape = cursor.0 as isize;
+        if self.shape != Some(shape) {
+            self.shape = Some(shape);
+            self.set_icon(platform::icon(cursor));
+        }
+        Some((x, y))
+    }
 
-            // Get cursor bitmap dimensions
-            let mut bitmap = BITMAP::default();
-            if GetObjectW(
-                icon_info.hbmColor,
-                std::mem::size_of::<BITMAP>() as i32,
-                Some(&mut bitmap as *mut _ as *mut _),
-            ) == 0 {
-                // Monochrome cursor
-                if GetObjectW(
-                    icon_info.hbmMask,
-                    std::mem::size_of::<BITMAP>() as i32,
-                    Some(&mut bitmap as *mut _ as *mut _),
-                ) == 0 {
-                    return None;
-                }
-            }
+    #[cfg(not(windows))]
+    fn pointer(&mut self) -> Option<(i32, i32)> {
+        if self.icon.is_none() {
+            self.set_icon(Some(arrow()));
+        }
+        platform::location()
+    }
 
-            let width = bitmap.bmWidth as u32;
-            let height = bitmap.bmHeight as u32;
+    fn set_icon(&mut self, icon: Option<CursorIcon>) {
+        self.icon_hash = icon.as_ref().map_or(0, CursorIcon::hash);
+        self.icon = icon;
+    }
+}
 
-            // For simplicity, we'll return cursor info without icon data
-            // Full implementation would convert HBITMAP to RGBA
-            // See RustDesk's implementation for full details
+impl Default for CursorCapturer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-            // Cleanup
-            if !icon_info.hbmColor.is_invalid() {
-                DeleteObject(icon_info.hbmColor).ok();
+/// Top-left corner of a display on the desktop, `None` when it isn't known.
+/// The primary display is always at the desktop origin.
+pub fn display_origin(display: Option<usize>) -> Option<(i32, i32)> {
+    match display {
+        None => Some((0, 0)),
+        Some(index) => platform::display_origin(index),
+    }
+}
+
+/// Blend `icon` onto `frame` with its top-left corner at (`x`, `y`), clipped to the frame
+pub fn composite(frame: &mut RgbaImage, icon: &CursorIcon, x: i32, y: i32) {
+    let (width, height) = (frame.width() as i32, frame.height() as i32);
+    for row in 0..icon.height {
+        let py = y + row as i32;
+        if py < 0 || py >= height {
+            continue;
+        }
+        for col in 0..icon.width {
+            let px = x + col as i32;
+            if px < 0 || px >= width {
+                continue;
             }
-            if !icon_info.hbmMask.is_invalid() {
-                DeleteObject(icon_info.hbmMask).ok();
+            let i = row * icon.width + col;
+            let pixel = frame.get_pixel_mut(px as u32, py as u32);
+            if icon.invert.get(i).copied().unwrap_or(false) {
+                for channel in &mut pixel.0[..3] {
+                    *channel = 255 - *channel;
+                }
+                continue;
+            }
+            let source = &icon.rgba[i * 4..i * 4 + 4];
+            let alpha = source[3] as u32;
+            for (channel, &value) in pixel.0[..3].iter_mut().zip(&source[..3]) {
+                *channel = ((value as u32 * alpha + *channel as u32 * (255 - alpha)) / 255) as u8;
             }
-
-            Some(CursorInfo {
-                x: cursor_info.ptScreenPos.x - icon_info.xHotspot as i32,
-                y: cursor_info.ptScreenPos.y - icon_info.yHotspot as i32,
-                visible: true,
-                icon_data: None, // TODO: Convert HBITMAP to RGBA
-                width,
-                height,
-            })
         }
     }
+}
 
-    /// Draw cursor onto RGBA frame buffer
-    pub fn draw_cursor_on_frame(
-        &mut self,
-        frame: &mut [u8],
-        frame_width: usize,
-        frame_height: usize,
-        display_x: i32,
-        display_y: i32,
-    ) {
-        if let Some(cursor) = self.get_cursor_info() {
-            if !cursor.visible {
-                return;
-            }
+// Classic arrow: B outline, W fill
+#[cfg_attr(windows, allow(dead_code))]
+const ARROW: [&str; 19] = [
+    "B",
+    "BB",
+    "BWB",
+    "BWWB",
+    "BWWWB",
+    "BWWWWB",
+    "BWWWWWB",
+    "BWWWWWWB",
+    "BWWWWWWWB",
+    "BWWWWWWWWB",
+    "BWWWWWWWWWB",
+    "BWWWWWWBBBBB",
+    "BWWWBWWB",
+    "BWWBBWWB",
+    "BWB  BWWB",
+    "BB   BWWB",
+    "B     BWWB",
+    "      BWWB",
+    "       BB",
+];
 
-            // Simple cross-hair cursor for now
-            // Full implementation would draw actual cursor icon
-            let cursor_x = (cursor.x - display_x) as usize;
-            let cursor_y = (cursor.y - display_y) as usize;
+#[cfg_attr(windows, allow(dead_code))]
+fn arrow() -> CursorIcon {
+    let width = ARROW.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut rgba = vec![0u8; width * ARROW.len() * 4];
+    for (y, row) in ARROW.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            let color = match c {
+                'B' => [0, 0, 0, 255],
+                'W' => [255, 255, 255, 255],
+                _ => continue,
+            };
+            let i = (y * width + x) * 4;
+            rgba[i..i + 4].copy_from_slice(&color);
+        }
+    }
+    CursorIcon { width, height: ARROW.len(), hotspot: (0, 0), rgba, invert: Vec::new() }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::CursorIcon;
+    use windows::Win32::Foundation::{BOOL, HWND, LPARAM, RECT};
+    use windows::Win32::Graphics::Gdi::{
+        DeleteObject, EnumDisplayMonitors, GetDC, GetDIBits, GetObjectW, ReleaseDC, BITMAP, BITMAPINFO,
+        BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HBITMAP, HDC, HMONITOR,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{GetCursorInfo, GetIconInfo, CURSORINFO, CURSOR_SHOWING, HCURSOR, HICON, ICONINFO};
 
-            self.draw_crosshair(frame, frame_width, frame_height, cursor_x, cursor_y);
+    /// Pointer position on the desktop and its shape, `None` while hidden
+    pub fn cursor() -> Option<(i32, i32, HCURSOR)> {
+        let mut info = CURSORINFO { cbSize: std::mem::size_of::<CURSORINFO>() as u32, ..Default::default() };
+        unsafe { GetCursorInfo(&mut info) }.ok()?;
+        if info.flags.0 & CURSOR_SHOWING.0 == 0 || info.hCursor.is_invalid() {
+            return None;
         }
+        Some((info.ptScreenPos.x, info.ptScreenPos.y, info.hCursor))
     }
 
-    /// Draw a simple crosshair (placeholder for actual cursor)
-    fn draw_crosshair(
-        &self,
-        frame: &mut [u8],
-        width: usize,
-        height: usize,
-        x: usize,
-        y: usize,
-    ) {
-        let size = 10; // crosshair size
-        let color = [255u8, 0, 0, 255]; // Red with full opacity
-
-        // Draw horizontal line
-        for dx in 0..size {
-            let px = x.saturating_add(dx).saturating_sub(size / 2);
-            if px < width && y < height {
-                let idx = (y * width + px) * 4;
-                if idx + 3 < frame.len() {
-                    frame[idx..idx + 4].copy_from_slice(&color);
-                }
+    /// The cursor's image as RGBA, from its color bitmap or its masks
+    pub fn icon(cursor: HCURSOR) -> Option<CursorIcon> {
+        let mut info = ICONINFO::default();
+        unsafe { GetIconInfo(HICON(cursor.0), &mut info) }.ok()?;
+        let icon = unsafe { icon_from_bitmaps(info.hbmColor, info.hbmMask) };
+        unsafe {
+            if !info.hbmColor.is_invalid() {
+                let _ = DeleteObject(info.hbmColor);
+            }
+            if !info.hbmMask.is_invalid() {
+                let _ = DeleteObject(info.hbmMask);
             }
         }
+        icon.map(|icon| CursorIcon { hotspot: (info.xHotspot as i32, info.yHotspot as i32), ..icon })
+    }
 
-        // Draw vertical line
-        for dy in 0..size {
-            let py = y.saturating_add(dy).saturating_sub(size / 2);
-            if x < width && py < height {
-                let idx = (py * width + x) * 4;
-                if idx + 3 < frame.len() {
-                    frame[idx..idx + 4].copy_from_slice(&color);
+    unsafe fn icon_from_bitmaps(color: HBITMAP, mask: HBITMAP) -> Option<CursorIcon> {
+        let screen = GetDC(HWND::default());
+        let result = if color.is_invalid() {
+            // Monochrome: AND mask on top of the XOR mask, each half the height
+            read_bitmap(screen, mask).map(|(width, height, pixels)| {
+                let height = height / 2;
+                let mut rgba = vec![0u8; width * height * 4];
+                let mut invert = vec![false; width * height];
+                for i in 0..width * height {
+                    let and = pixels[i * 4] != 0;
+                    let xor = pixels[(i + width * height) * 4] != 0;
+                    match (and, xor) {
+                        (false, false) => rgba[i * 4..i * 4 + 4].copy_from_slice(&[0, 0, 0, 255]),
+                        (false, true) => rgba[i * 4..i * 4 + 4].copy_from_slice(&[255, 255, 255, 255]),
+                        (true, false) => {}
+                        (true, true) => invert[i] = true,
+                    }
                 }
-            }
+                CursorIcon { width, height, hotspot: (0, 0), rgba, invert }
+            })
+        } else {
+            read_bitmap(screen, color).map(|(width, height, mut pixels)| {
+                // BGRA to RGBA; icons without alpha take it from the AND mask
+                let has_alpha = pixels.chunks_exact(4).any(|p| p[3] != 0);
+                let mask = if has_alpha { None } else { read_bitmap(screen, mask).map(|(_, _, mask)| mask) };
+                for (i, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+                    pixel.swap(0, 2);
+                    if let Some(mask) = mask.as_ref() {
+                        pixel[3] = if mask.get(i * 4).copied().unwrap_or(0) == 0 { 255 } else { 0 };
+                    }
+                }
+                CursorIcon { width, height, hotspot: (0, 0), rgba: pixels, invert: Vec::new() }
+            })
+        };
+        ReleaseDC(HWND::default(), screen);
+        result
+    }
+
+    // 32-bit top-down BGRA pixels of a bitmap, whatever its own depth
+    unsafe fn read_bitmap(screen: HDC, bitmap: HBITMAP) -> Option<(usize, usize, Vec<u8>)> {
+        let mut header = BITMAP::default();
+        if GetObjectW(bitmap, std::mem::size_of::<BITMAP>() as i32, Some(&mut header as *mut _ as *mut _)) == 0 {
+            return None;
         }
+        let (width, height) = (header.bmWidth as usize, header.bmHeight as usize);
+        let mut info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                biWidth: width as i32,
+                biHeight: -(height as i32),
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut pixels = vec![0u8; width * height * 4];
+        let lines = GetDIBits(screen, bitmap, 0, height as u32, Some(pixels.as_mut_ptr().cast()), &mut info, DIB_RGB_COLORS);
+        (lines == height as i32).then_some((width, height, pixels))
+    }
+
+    /// Monitors in the order Windows enumerates them, the same order DXGI lists its outputs in
+    pub fn display_origin(index: usize) -> Option<(i32, i32)> {
+        unsafe extern "system" fn collect(_: HMONITOR, _: HDC, rect: *mut RECT, data: LPARAM) -> BOOL {
+            let origins = &mut *(data.0 as *mut Vec<(i32, i32)>);
+            origins.push(((*rect).left, (*rect).top));
+            BOOL(1)
+        }
+        let mut origins: Vec<(i32, i32)> = Vec::new();
+        unsafe {
+            let _ = EnumDisplayMonitors(HDC::default(), None, Some(collect), LPARAM(&mut origins as *mut _ as isize));
+        }
+        origins.get(index).copied()
     }
 }
 
 #[cfg(not(windows))]
-pub struct CursorCapturer;
+mod platform {
+    use std::cell::RefCell;
+    use enigo::{Enigo, Mouse, Settings};
 
-#[cfg(not(windows))]
-impl CursorCapturer {
-    pub fn new() -> Self {
-        Self
+    // Enigo holds a connection to the display server, kept per capturing thread
+    thread_local! {
+        static ENIGO: RefCell<Option<Enigo>> = const { RefCell::new(None) };
     }
 
-    pub fn get_cursor_info(&mut self) -> Option<CursorInfo> {
-        None
+    pub fn location() -> Option<(i32, i32)> {
+        ENIGO.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            if slot.is_none() {
+                *slot = Enigo::new(&Settings::default()).ok();
+            }
+            slot.as_ref()?.location().ok()
+        })
     }
 
-    pub fn draw_cursor_on_frame(
-        &mut self,
-        _frame: &mut [u8],
-        _frame_width: usize,
-        _frame_height: usize,
-        _display_x: i32,
-        _display_y: i32,
-    ) {
-        // Not supported on non-Windows platforms
+    pub fn display_origin(_index: usize) -> Option<(i32, i32)> {
+        None
     }
 }
 
-// Utility function to draw cursor on frame
-pub fn draw_cursor(
-    frame: &mut [u8],
-    frame_width: usize,
-    frame_height: usize,
-    display_x: i32,
-    display_y: i32,
-) {
-    let mut capturer = CursorCapturer::new();
-    capturer.draw_cursor_on_frame(frame, frame_width, frame_height, display_x, display_y);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_composite_clips_and_inverts() {
+        let mut frame = RgbaImage::from_pixel(20, 20, image::Rgba([200, 200, 200, 255]));
+        composite(&mut frame, &arrow(), 2, 3);
+        assert_eq!(frame.get_pixel(2, 3).0, [0, 0, 0, 255]);
+        assert_eq!(frame.get_pixel(3, 5).0, [255, 255, 255, 255]);
+        assert_eq!(frame.get_pixel(1, 3).0, [200, 200, 200, 255]);
+
+        // Partly off the frame
+        composite(&mut frame, &arrow(), 15, -10);
+
+        let invert = CursorIcon { width: 1, height: 1, hotspot: (0, 0), rgba: vec![0; 4], invert: vec![true] };
+        composite(&mut frame, &invert, 0, 0);
+        assert_eq!(frame.get_pixel(0, 0).0, [55, 55, 55, 255]);
+    }
 }
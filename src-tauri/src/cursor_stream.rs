@@ -0,0 +1,125 @@
+// Cursor Stream - the pointer as metadata instead of pixels
+// Burnt into the frames, the pointer only moves as often as the stream sends
+// one and costs a changed tile every time it does. With `cursor_metadata` on,
+// frames leave it out and a small datagram with its position and the hash of
+// its image goes out 60 times a second while it moves (once a second while it
+// rests, for viewers who join), so viewers draw a smooth pointer of their own.
+// Nothing is sent while the stream is frozen, the pointer would move over a
+// picture that doesn't.
+
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::access_control::Delivery;
+use crate::clock::StreamClock;
+use crate::cursor_capture::{self, CursorCapturer};
+use crate::packet::{CursorHeader, CursorState};
+use crate::screen_capture;
+use crate::stream_crypto::StreamCipher;
+
+const POLL_INTERVAL: Duration = Duration::from_micros(16_667);
+const RESEND_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sends the pointer until `stop()` or drop
+pub struct CursorSender {
+    running: Arc<AtomicBool>,
+}
+
+impl CursorSender {
+    /// Pointer updates for `display` (`None` is the primary display)
+    pub fn start(
+        display: Option<usize>,
+        socket: Arc<UdpSocket>,
+        destination: Delivery,
+        clock: StreamClock,
+        cipher: Option<Arc<StreamCipher>>,
+        frozen: Arc<AtomicBool>,
+    ) -> Result<Self, String> {
+        let origin = cursor_capture::display_origin(display)
+            .ok_or("The pointer position is only known for the primary display on this platform")?;
+        let size = screen_capture::display_size(display)?;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        std::thread::spawn(move || {
+            let mut cursor = CursorCapturer::new();
+            let mut sequence = 0u32;
+            let mut last: Option<(CursorState, Instant)> = None;
+            while thread_running.load(Ordering::Relaxed) {
+                std::thread::sleep(POLL_INTERVAL);
+                if frozen.load(Ordering::Relaxed) {
+                    continue;
+                }
+                let now = Instant::now();
+                let state = locate(cursor.sample(), origin, size, clock.timestamp(now));
+                let unchanged = last.is_some_and(|(sent, at)| {
+                    (sent.x, sent.y, sent.visible, sent.shape) == (state.x, state.y, state.visible, state.shape)
+                        && now.duration_since(at) < RESEND_INTERVAL
+                });
+                if unchanged {
+                    continue;
+                }
+
+                let header = CursorHeader { encrypted: cipher.is_some(), sequence };
+                sequence = sequence.wrapping_add(1);
+                let mut packet = Vec::with_capacity(crate::packet::CURSOR_HEADER_SIZE + 64);
+                header.write(&mut packet);
+                match &cipher {
+                    Some(cipher) => match cipher.seal(&packet, &state.serialize()) {
+                        Ok(sealed) => packet.extend_from_slice(&sealed),
+                        Err(e) => {
+                            eprintln!("❌ Cursor {}", e);
+                            continue;
+                        }
+                    },
+                    None => packet.extend_from_slice(&state.serialize()),
+                }
+                let _ = destination.send(&socket, &packet);
+                last = Some((state, now));
+            }
+        });
+        eprintln!("🖱️  Sending the pointer apart from the frames");
+        Ok(Self { running })
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for CursorSender {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// The pointer relative to a display at `origin` of `size`, hidden when it's on another one
+fn locate(sample: Option<((i32, i32), u32)>, origin: (i32, i32), size: (usize, usize), capture_ts: u32) -> CursorState {
+    let hidden = CursorState { capture_ts, x: 0, y: 0, visible: false, shape: 0 };
+    let Some(((x, y), shape)) = sample else {
+        return hidden;
+    };
+    let (x, y) = (x - origin.0, y - origin.1);
+    let (width, height) = (size.0 as i32, size.1 as i32);
+    if x < 0 || y < 0 || x >= width || y >= height {
+        return hidden;
+    }
+    let scale = |value: i32, extent: i32| (value as i64 * u16::MAX as i64 / (extent - 1).max(1) as i64) as u16;
+    CursorState { capture_ts, x: scale(x, width), y: scale(y, height), visible: true, shape }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locate_on_display() {
+        // Second display to the right of a 1920 wide primary
+        let state = locate(Some(((1920 + 1279, 360), 42)), (1920, 0), (1280, 721), 5);
+        assert_eq!((state.x, state.y, state.visible, state.shape), (u16::MAX, u16::MAX / 2, true, 42));
+        assert!(!locate(Some(((100, 100), 42)), (1920, 0), (1280, 720), 5).visible);
+        assert!(!locate(None, (0, 0), (1280, 720), 5).visible);
+    }
+}
@@ -0,0 +1,181 @@
+// Discovery - finds running servers on the LAN
+// Viewers broadcast a probe and every server streaming on this network
+// answers with what it is sharing, so the UI can list streams instead of
+// users having to know addresses.
+//
+// Probe (viewer -> broadcast):  "DISC"
+// Answer (server -> viewer):    "DISA" | JSON ServerInfo
+// The server address is taken from where the answer came from. Viewers also
+// probe a server directly when its stream shows up, to learn its port mapping.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::settings::{PortMapping, SimulcastLayer};
+use crate::display_streams::DisplayStream;
+
+pub const DEFAULT_TIMEOUT_MS: u64 = 1000;
+const PROBE_MAGIC: &[u8; 4] = b"DISC";
+const ANSWER_MAGIC: &[u8; 4] = b"DISA";
+const READ_TIMEOUT_MS: u64 = 200;
+
+/// What a server advertises about its stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    /// Multicast group the stream is sent to
+    pub group: String,
+    /// Data port of the stream and where viewers talk back to
+    #[serde(default)]
+    pub ports: PortMapping,
+    /// Viewers have to ask to join (and get approved) first
+    pub requires_approval: bool,
+    pub encrypted: bool,
+    /// Also streams over TCP on the data port, for viewers that get no multicast
+    #[serde(default)]
+    pub tcp_fallback: bool,
+    /// SHA-256 of the QUIC certificate, set when the server accepts QUIC viewers
+    #[serde(default)]
+    pub quic_fingerprint: Option<String>,
+    /// Lower renditions on the groups after `group`, layer 1 first
+    #[serde(default)]
+    pub simulcast: Vec<SimulcastLayer>,
+    /// Other displays sent next to the main one
+    #[serde(default)]
+    pub displays: Vec<DisplayStream>,
+    /// What `start_client` subscribes to, 0 from servers before stream IDs
+    #[serde(default)]
+    pub stream_id: u32,
+    /// Filled in by the viewer from where the answer came from
+    #[serde(default)]
+    pub address: String,
+}
+
+/// Answers probes while a server runs, stopped on `stop()` or drop
+pub struct DiscoveryResponder {
+    running: Arc<AtomicBool>,
+}
+
+impl DiscoveryResponder {
+    pub fn start(info: ServerInfo) -> Result<Self, String> {
+        let port = info.ports.discovery;
+        let socket = UdpSocket::bind(("0.0.0.0", port))
+            .map_err(|e| format!("Failed to bind discovery on port {}: {}", port, e))?;
+        socket.set_read_timeout(Some(Duration::from_millis(READ_TIMEOUT_MS)))
+            .map_err(|e| format!("Failed to set timeout: {}", e))?;
+
+        let mut answer = ANSWER_MAGIC.to_vec();
+        answer.extend_from_slice(&serde_json::to_vec(&info).map_err(|e| e.to_string())?);
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            while thread_running.load(Ordering::Relaxed) {
+                if let Ok((size, from)) = socket.recv_from(&mut buf) {
+                    if &buf[..size] == PROBE_MAGIC {
+                        let _ = socket.send_to(&answer, from);
+                    }
+                }
+            }
+        });
+
+        eprintln!("📣 Advertising stream on discovery port {}", port);
+        Ok(Self { running })
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for DiscoveryResponder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Broadcast a probe to `port` and collect answers for `timeout`
+pub fn discover(timeout: Duration, port: u16) -> Result<Vec<ServerInfo>, String> {
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .map_err(|e| format!("Failed to bind socket: {}", e))?;
+    socket.set_broadcast(true)
+        .map_err(|e| format!("Failed to enable broadcast: {}", e))?;
+    socket.set_read_timeout(Some(Duration::from_millis(READ_TIMEOUT_MS)))
+        .map_err(|e| format!("Failed to set timeout: {}", e))?;
+    send_probe(&socket, (Ipv4Addr::BROADCAST, port).into())?;
+
+    let mut servers: HashMap<SocketAddr, ServerInfo> = HashMap::new();
+    let mut buf = [0u8; 2048];
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if let Ok((size, from)) = socket.recv_from(&mut buf) {
+            if let Some(mut info) = parse_answer(&buf[..size]) {
+                info.address = from.ip().to_string();
+                servers.insert(from, info);
+            }
+        }
+    }
+
+    let mut servers: Vec<ServerInfo> = servers.into_values().collect();
+    servers.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(servers)
+}
+
+/// Ask for answers at `to`, a broadcast address or a single server
+pub fn send_probe(socket: &UdpSocket, to: SocketAddr) -> Result<(), String> {
+    socket.send_to(PROBE_MAGIC, to)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to send discovery probe: {}", e))
+}
+
+pub fn parse_answer(buf: &[u8]) -> Option<ServerInfo> {
+    serde_json::from_slice(buf.strip_prefix(ANSWER_MAGIC)?).ok()
+}
+
+/// This machine's name, shown to the other side
+pub fn host_name() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok().map(|h| h.trim().to_string()))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "SmartLab".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answer_round_trip() {
+        let info = ServerInfo {
+            name: "GV-01".to_string(),
+            width: 1920,
+            height: 1080,
+            group: "239.0.0.1".to_string(),
+            ports: PortMapping { control: 7000, ..PortMapping::default() },
+            requires_approval: true,
+            encrypted: false,
+            tcp_fallback: false,
+            quic_fingerprint: None,
+            simulcast: vec![SimulcastLayer::default()],
+            displays: Vec::new(),
+            stream_id: 42,
+            address: String::new(),
+        };
+        let mut answer = ANSWER_MAGIC.to_vec();
+        answer.extend_from_slice(&serde_json::to_vec(&info).unwrap());
+
+        let parsed = parse_answer(&answer).unwrap();
+        assert_eq!((parsed.name.as_str(), parsed.width, parsed.requires_approval), ("GV-01", 1920, true));
+        assert_eq!(parsed.ports.control, 7000);
+        assert_eq!(parsed.simulcast, info.simulcast);
+        assert!(parse_answer(PROBE_MAGIC).is_none());
+    }
+}
@@ -0,0 +1,170 @@
+// Display Streams - several monitors shared at once
+// Servers send the displays in `StreamSettings::extra_displays` next to the
+// main one, each as a stream of its own on the same group and session: own
+// frame IDs and sequence numbers, told apart by the stream ID in the packet
+// header. The main display keeps everything it always had (simulcast,
+// recording, preview, WebRTC, ...); the others are plain JPEG or H.264 at the
+// main frame rate. Viewers reassemble the ones they subscribed to here and
+// show each on its own.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::packet::{self, FrameType, PacketHeader};
+use crate::tile_delta;
+use crate::video_decoder::{self, VideoDecoder};
+
+const FRAME_TIMEOUT: Duration = Duration::from_millis(500); // Same as the main stream
+const DECODED_JPEG_QUALITY: u8 = 85;
+
+/// An extra display a server sends, advertised through discovery
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisplayStream {
+    /// Stream ID in the packet header, 1 and up
+    pub stream: u8,
+    /// Index into the server's display list
+    pub display: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// What a chunk of an extra display led to
+pub enum Received {
+    /// The frame isn't complete yet, or the decoder wants more
+    Pending,
+    Frame { stream: u8, jpeg: Vec<u8> },
+    /// A delta frame came without the one before it, the sender should send a keyframe
+    OutOfSync,
+    Invalid(String),
+}
+
+struct PartialFrame {
+    chunks: Vec<Vec<u8>>,
+    updated: Instant,
+}
+
+#[derive(Default)]
+struct StreamState {
+    last_sequence: Option<u32>,
+    in_sync: bool,
+    decoder: Option<VideoDecoder>,
+}
+
+/// Viewer side: reassembles and decodes the extra displays
+#[derive(Default)]
+pub struct DisplayFrames {
+    frames: HashMap<(u8, u32), PartialFrame>,
+    streams: HashMap<u8, StreamState>,
+}
+
+impl DisplayFrames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget everything, for a new server session
+    pub fn reset(&mut self) {
+        self.frames.clear();
+        self.streams.clear();
+    }
+
+    /// Add one (already decrypted) chunk of stream `header.stream`
+    pub fn on_chunk(&mut self, header: &PacketHeader, chunk: Vec<u8>) -> Received {
+        let now = Instant::now();
+        self.frames.retain(|_, frame| now.duration_since(frame.updated) < FRAME_TIMEOUT);
+
+        let key = (header.stream, header.frame_id);
+        let frame = self.frames.entry(key).or_insert_with(|| PartialFrame {
+            chunks: vec![Vec::new(); header.total_chunks as usize],
+            updated: now,
+        });
+        frame.updated = now;
+        match frame.chunks.get_mut(header.chunk_idx as usize) {
+            Some(slot) => *slot = chunk,
+            None => return Received::Invalid(format!("chunk index {} >= {}", header.chunk_idx, frame.chunks.len())),
+        }
+        // Only whole frames, there's no salvaging for the extra displays
+        if frame.chunks.iter().any(Vec::is_empty) {
+            return Received::Pending;
+        }
+        let data = self.frames.remove(&key).map(|frame| frame.chunks.concat()).unwrap_or_default();
+        // Chunks of an earlier frame with the same ID must not count as a frame
+        let Some(payload) = packet::verify_frame_checksum(&data) else {
+            return Received::Invalid("frame doesn't match its checksum".to_string());
+        };
+
+        let state = self.streams.entry(header.stream).or_default();
+        let follows_previous = state.last_sequence.is_some_and(|last| header.sequence == last.wrapping_add(1));
+        state.last_sequence = Some(header.sequence);
+        state.in_sync = match header.frame_type {
+            FrameType::Key => true,
+            FrameType::Delta => state.in_sync && follows_previous,
+        };
+        if !state.in_sync {
+            return Received::OutOfSync;
+        }
+
+        if video_decoder::is_h264_payload(&payload) {
+            if state.decoder.is_none() {
+                match VideoDecoder::for_payload(&payload) {
+                    Ok(decoder) => state.decoder = Some(decoder),
+                    Err(e) => return Received::Invalid(e),
+                }
+            }
+            match state.decoder.as_mut().map(|d| d.decode_to_jpeg(&payload, DECODED_JPEG_QUALITY)) {
+                Some(Ok(Some(jpeg))) => Received::Frame { stream: header.stream, jpeg },
+                Some(Ok(None)) | None => Received::Pending,
+                Some(Err(e)) => Received::Invalid(e),
+            }
+        } else if tile_delta::is_tile_payload(&payload) {
+            Received::Invalid("tile deltas are only sent for the main display".to_string())
+        } else if payload.starts_with(&[0xFF, 0xD8]) && payload.ends_with(&[0xFF, 0xD9]) {
+            Received::Frame { stream: header.stream, jpeg: payload.to_vec() }
+        } else {
+            Received::Invalid(format!("not a JPEG or H.264 frame ({} bytes)", payload.len()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(stream: u8, frame_id: u32, chunk_idx: u32, frame_type: FrameType) -> PacketHeader {
+        PacketHeader {
+            frame_id,
+            chunk_idx,
+            total_chunks: 2,
+            frame_type,
+            sequence: frame_id,
+            capture_ts: 0,
+            send_ts: 0,
+            epoch: 1,
+            encrypted: false,
+            layer: 0,
+            stream_id: 1,
+            stream,
+        }
+    }
+
+    #[test]
+    fn test_interleaved_displays() {
+        let jpeg = [&[0xFF, 0xD8][..], &[0x11; 20], &[0xFF, 0xD9]].concat();
+        let data = packet::add_frame_checksum(&jpeg);
+        let (first, second) = data.split_at(10);
+        let mut frames = DisplayFrames::new();
+
+        // Chunks of two displays arrive mixed, with the same frame IDs
+        assert!(matches!(frames.on_chunk(&header(1, 0, 0, FrameType::Key), first.to_vec()), Received::Pending));
+        assert!(matches!(frames.on_chunk(&header(2, 0, 1, FrameType::Key), second.to_vec()), Received::Pending));
+        match frames.on_chunk(&header(1, 0, 1, FrameType::Key), second.to_vec()) {
+            Received::Frame { stream, jpeg: shown } => assert_eq!((stream, shown), (1, jpeg.clone())),
+            _ => panic!("display 1 should be complete"),
+        }
+        assert!(matches!(frames.on_chunk(&header(2, 0, 0, FrameType::Key), first.to_vec()), Received::Frame { stream: 2, .. }));
+
+        // Frame 1 of display 2 went missing, its delta can't be shown
+        assert!(matches!(frames.on_chunk(&header(2, 2, 0, FrameType::Delta), first.to_vec()), Received::Pending));
+        assert!(matches!(frames.on_chunk(&header(2, 2, 1, FrameType::Delta), second.to_vec()), Received::OutOfSync));
+    }
+}
@@ -1,6 +1,10 @@
 // DXGI Desktop Duplication API for Windows
 // Much faster than GDI/scrap for screen capture
 // Based on RustDesk implementation but simplified for LAN
+// The texture's pixel format is checked on every frame, see capture_format.rs
+// Only the rectangles the OS reports dirty are converted into the kept RGBA
+// frame, after the ones it reports moved (scrolling) are moved in it; both are
+// passed on so the tile delta encoder only looks at those.
 
 #[cfg(windows)]
 use windows::Win32::{
@@ -14,44 +18,65 @@ use windows::Win32::{
 };
 #[cfg(windows)]
 use std::ptr;
+#[cfg(windows)]
+use crate::capture_format::CaptureFormat;
+#[cfg(windows)]
+use crate::screen_capture::{DirtyRect, MovedRect};
+
+/// `capture_frame`'s error once a mode change or fullscreen app took the
+/// duplication away; a new capturer has to be created
+#[cfg(windows)]
+pub const ACCESS_LOST: &str = "AccessLost - display changed";
+
+/// A desktop image and what changed since the previous one
+#[cfg(windows)]
+pub struct DxgiFrame {
+    pub rgba: Vec<u8>,
+    /// Changed apart from `moves`, `None` after a full conversion
+    pub dirty: Option<Vec<DirtyRect>>,
+    pub moves: Vec<MovedRect>,
+}
 
 #[cfg(windows)]
 pub struct DxgiCapturer {
     device: Option<ID3D11Device>,
     context: Option<ID3D11DeviceContext>,
     duplication: Option<IDXGIOutputDuplication>,
+    /// CPU-readable copy of the desktop, kept while its size and format stay the same
+    staging: Option<(ID3D11Texture2D, D3D11_TEXTURE2D_DESC)>,
+    /// The last frame as RGBA, updated where the desktop changed; empty before the first
+    frame: Vec<u8>,
     width: usize,
     height: usize,
+    /// Top-left corner of the output on the desktop
+    origin: (i32, i32),
     timeout_ms: u32,
 }
 
 #[cfg(windows)]
 impl DxgiCapturer {
-    pub fn new(display_index: usize) -> Result<Self, String> {
+    /// Duplicate `display` (an index in scrap's display order, `None` is the
+    /// primary display). `formats` is offered to the duplication API in order
+    /// of preference.
+    pub fn new(display: Option<usize>, formats: &[CaptureFormat]) -> Result<Self, String> {
         unsafe {
             // 1. Create DXGI factory
             let factory: IDXGIFactory1 = CreateDXGIFactory1()
                 .map_err(|e| format!("Failed to create DXGI factory: {:?}", e))?;
 
-            // 2. Get adapter (GPU)
-            let adapter = factory.EnumAdapters1(0)
-                .map_err(|e| format!("Failed to enumerate adapters: {:?}", e))?;
-
-            // 3. Get output (monitor)
-            let output = adapter.EnumOutputs(display_index as u32)
-                .map_err(|e| format!("Failed to get output {}: {:?}", display_index, e))?;
+            // 2-4. Adapter (GPU) and output (monitor) of the display, with its description
+            let (adapter, output, desc) = find_output(&factory, display)?;
 
             let output1: IDXGIOutput1 = output.cast()
                 .map_err(|e| format!("Failed to cast to IDXGIOutput1: {:?}", e))?;
-
-            // 4. Get output description
-            let desc = output.GetDesc()
-                .map_err(|e| format!("Failed to get output desc: {:?}", e))?;
             
             let width = (desc.DesktopCoordinates.right - desc.DesktopCoordinates.left) as usize;
             let height = (desc.DesktopCoordinates.bottom - desc.DesktopCoordinates.top) as usize;
 
-            eprintln!("🖥️  DXGI Display {}: {}x{}", display_index, width, height);
+            match display {
+                Some(index) => eprintln!("🖥️  DXGI Display {}: {}x{}", index + 1, width, height),
+                None => eprintln!("🖥️  DXGI primary display: {}x{}", width, height),
+            }
 
             // 5. Create D3D11 device
             let mut device: Option<ID3D11Device> = None;
@@ -84,8 +109,16 @@ impl DxgiCapturer {
 
             eprintln!("✅ D3D11 device created, feature level: {:?}", feature_level);
 
-            // 6. Create output duplication
-            let duplication = output1.DuplicateOutput(&device)
+            // 6. Create output duplication, in one of our formats where the OS lets us choose
+            let requested: Vec<DXGI_FORMAT> = formats.iter()
+                .filter(|format| format.is_requestable())
+                .map(|format| DXGI_FORMAT(format.dxgi() as i32))
+                .collect();
+            let duplication = match output.cast::<IDXGIOutput5>() {
+                Ok(output5) if !requested.is_empty() => output5.DuplicateOutput1(&device, 0, &requested),
+                // Before Windows 10 1703: BGRA only
+                _ => output1.DuplicateOutput(&device),
+            }
                 .map_err(|e| format!("Failed to create output duplication: {:?}\n\
                     This may happen if:\n\
                     - Running in RDP session\n\
@@ -98,14 +131,18 @@ impl DxgiCapturer {
                 device: Some(device),
                 context: Some(context),
                 duplication: Some(duplication),
+                staging: None,
+                frame: Vec::new(),
                 width,
                 height,
+                origin: (desc.DesktopCoordinates.left, desc.DesktopCoordinates.top),
                 timeout_ms: 100,
             })
         }
     }
 
-    pub fn capture_frame(&mut self) -> Result<Vec<u8>, String> {
+    /// Returns `None` when the desktop hasn't changed within the timeout
+    pub fn capture_frame(&mut self) -> Result<Option<DxgiFrame>, String> {
         unsafe {
             let duplication = self.duplication.as_ref()
                 .ok_or("Duplication not initialized")?;
@@ -129,18 +166,26 @@ impl DxgiCapturer {
                     // Got a new frame
                 }
                 Err(e) if e.code() == DXGI_ERROR_WAIT_TIMEOUT => {
-                    // No new frame yet, return WouldBlock
-                    return Err("WouldBlock".to_string());
+                    // No new frame yet
+                    return Ok(None);
                 }
                 Err(e) if e.code() == DXGI_ERROR_ACCESS_LOST => {
                     // Display mode changed, need to recreate duplication
-                    return Err("AccessLost - display changed".to_string());
+                    return Err(ACCESS_LOST.to_string());
                 }
                 Err(e) => {
                     return Err(format!("AcquireNextFrame failed: {:?}", e));
                 }
             }
 
+            // Only the pointer moved, the desktop image is the one we have
+            if frame_info.LastPresentTime == 0 && !self.frame.is_empty() {
+                duplication.ReleaseFrame()
+                    .map_err(|e| format!("Failed to release frame: {:?}", e))?;
+                return Ok(Some(DxgiFrame { rgba: self.frame.clone(), dirty: Some(Vec::new()), moves: Vec::new() }));
+            }
+            let changes = Self::changed_rects(duplication, &frame_info);
+
             let desktop_resource = desktop_resource
                 .ok_or("Desktop resource is None")?;
 
@@ -148,7 +193,8 @@ impl DxgiCapturer {
             let texture: ID3D11Texture2D = desktop_resource.cast()
                 .map_err(|e| format!("Failed to cast to texture: {:?}", e))?;
 
-            // 3. Create staging texture to read data
+            // 3. Staging texture to read data, created again only when the
+            // desktop's size or format changed
             let mut texture_desc = D3D11_TEXTURE2D_DESC::default();
             texture.GetDesc(&mut texture_desc);
 
@@ -157,52 +203,107 @@ impl DxgiCapturer {
             texture_desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ;
             texture_desc.MiscFlags = D3D11_RESOURCE_MISC_FLAG(0);
 
-            let staging_texture = device.CreateTexture2D(&texture_desc, None)
-                .map_err(|e| format!("Failed to create staging texture: {:?}", e))?;
+            let reusable = self.staging.as_ref().is_some_and(|(_, desc)| {
+                (desc.Width, desc.Height, desc.Format, desc.ArraySize, desc.MipLevels)
+                    == (texture_desc.Width, texture_desc.Height, texture_desc.Format, texture_desc.ArraySize, texture_desc.MipLevels)
+            });
+            if !reusable {
+                // The old one goes first, so there are never two
+                self.staging = None;
+                let created = device.CreateTexture2D(&texture_desc, None)
+                    .map_err(|e| format!("Failed to create staging texture: {:?}", e));
+                let staging = match created {
+                    Ok(staging) => staging,
+                    Err(e) => {
+                        let _ = duplication.ReleaseFrame();
+                        return Err(e);
+                    }
+                };
+                eprintln!("🧱 DXGI staging texture {}x{} ({:?})", texture_desc.Width, texture_desc.Height, texture_desc.Format);
+                self.staging = Some((staging, texture_desc));
+            }
+            let (staging_texture, _) = self.staging.as_ref().ok_or("Staging texture missing")?;
 
             // 4. Copy texture to staging
-            context.CopyResource(&staging_texture, &texture);
+            context.CopyResource(staging_texture, &texture);
 
             // 5. Map staging texture to read pixels
             let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
             context.Map(
-                &staging_texture,
+                staging_texture,
                 0,
                 D3D11_MAP_READ,
                 0,
                 Some(&mut mapped),
             ).map_err(|e| format!("Failed to map texture: {:?}", e))?;
 
-            // 6. Convert BGRA to RGBA
+            // 6. Convert whatever the driver handed back to RGBA, just moving
+            // and converting the changes when the last frame is there to update
             let row_pitch = mapped.RowPitch as usize;
-            let src_data = std::slice::from_raw_parts(
-                mapped.pData as *const u8,
-                row_pitch * self.height,
-            );
-
-            let mut rgba_data = Vec::with_capacity(self.width * self.height * 4);
-            
-            for y in 0..self.height {
-                let row_start = y * row_pitch;
-                for x in 0..self.width {
-                    let pixel_start = row_start + x * 4;
-                    if pixel_start + 3 < src_data.len() {
-                        // BGRA → RGBA
-                        rgba_data.push(src_data[pixel_start + 2]); // R
-                        rgba_data.push(src_data[pixel_start + 1]); // G
-                        rgba_data.push(src_data[pixel_start]);     // B
-                        rgba_data.push(src_data[pixel_start + 3]); // A
+            let (width, height) = (self.width, self.height);
+            let frame = &mut self.frame;
+            let converted = CaptureFormat::from_dxgi(texture_desc.Format.0 as u32).and_then(|format| {
+                let src_data = std::slice::from_raw_parts(
+                    mapped.pData as *const u8,
+                    row_pitch * format.mapped_rows(height),
+                );
+                match changes {
+                    Some((moves, rects))
+                        if format.converts_regions()
+                            && frame.len() == width * height * 4
+                            && moves.iter().all(|moved| moved.fits(width, height)) =>
+                    {
+                        for moved in &moves {
+                            moved.apply(frame, width, 4);
+                        }
+                        let rects: Vec<DirtyRect> = rects.iter().filter_map(|rect| rect.clipped(width, height)).collect();
+                        format.update_rgba(src_data, row_pitch, frame, width, &rects).map(|()| (Some(rects), moves))
                     }
+                    _ => format.to_rgba(src_data, row_pitch, width, height).map(|rgba| {
+                        *frame = rgba;
+                        (None, Vec::new())
+                    }),
                 }
-            }
+            });
 
-            // 7. Cleanup
-            context.Unmap(&staging_texture, 0);
+            // 7. Cleanup, also when the format was unusable
+            context.Unmap(staging_texture, 0);
             duplication.ReleaseFrame()
                 .map_err(|e| format!("Failed to release frame: {:?}", e))?;
 
-            Ok(rgba_data)
+            match converted {
+                Ok((dirty, moves)) => Ok(Some(DxgiFrame { rgba: self.frame.clone(), dirty, moves })),
+                Err(e) => {
+                    // Start over from a full conversion
+                    self.frame.clear();
+                    Err(e.to_string())
+                }
+            }
+        }
+    }
+
+    // The acquired frame's move rects (to apply first) and dirty rects,
+    // `None` when the OS didn't say
+    unsafe fn changed_rects(duplication: &IDXGIOutputDuplication, info: &DXGI_OUTDUPL_FRAME_INFO) -> Option<(Vec<MovedRect>, Vec<DirtyRect>)> {
+        let size = info.TotalMetadataBufferSize as usize;
+        if size == 0 {
+            return None;
         }
+        let mut required = 0u32;
+        let move_size = std::mem::size_of::<DXGI_OUTDUPL_MOVE_RECT>();
+        let mut moves = vec![DXGI_OUTDUPL_MOVE_RECT::default(); size / move_size + 1];
+        duplication.GetFrameMoveRects((moves.len() * move_size) as u32, moves.as_mut_ptr(), &mut required).ok()?;
+        moves.truncate(required as usize / move_size);
+        let rect_size = std::mem::size_of::<RECT>();
+        let mut rects = vec![RECT::default(); size / rect_size + 1];
+        duplication.GetFrameDirtyRects((rects.len() * rect_size) as u32, rects.as_mut_ptr(), &mut required).ok()?;
+        rects.truncate(required as usize / rect_size);
+
+        let moves = moves.iter().map(|moved| MovedRect {
+            from: (moved.SourcePoint.x.max(0) as usize, moved.SourcePoint.y.max(0) as usize),
+            to: to_dirty_rect(&moved.DestinationRect),
+        }).collect();
+        Some((moves, rects.iter().map(to_dirty_rect).collect()))
     }
 
     pub fn width(&self) -> usize {
@@ -212,6 +313,51 @@ impl DxgiCapturer {
     pub fn height(&self) -> usize {
         self.height
     }
+
+    pub fn origin(&self) -> (i32, i32) {
+        self.origin
+    }
+}
+
+// Outputs attached to the desktop, adapter by adapter, are scrap's display
+// order. The primary display is the one at the desktop's origin.
+#[cfg(windows)]
+unsafe fn find_output(factory: &IDXGIFactory1, display: Option<usize>) -> Result<(IDXGIAdapter1, IDXGIOutput, DXGI_OUTPUT_DESC), String> {
+    let mut attached = Vec::new();
+    let mut adapter_index = 0;
+    while let Ok(adapter) = factory.EnumAdapters1(adapter_index) {
+        let mut output_index = 0;
+        while let Ok(output) = adapter.EnumOutputs(output_index) {
+            if let Ok(desc) = output.GetDesc() {
+                if desc.AttachedToDesktop.as_bool() {
+                    attached.push((adapter.clone(), output, desc));
+                }
+            }
+            output_index += 1;
+        }
+        adapter_index += 1;
+    }
+
+    let index = match display {
+        Some(index) => index,
+        None => attached.iter()
+            .position(|(_, _, desc)| (desc.DesktopCoordinates.left, desc.DesktopCoordinates.top) == (0, 0))
+            .unwrap_or(0),
+    };
+    let count = attached.len();
+    attached.into_iter().nth(index)
+        .ok_or_else(|| format!("No DXGI output for display {} ({} attached)", index + 1, count))
+}
+
+#[cfg(windows)]
+fn to_dirty_rect(rect: &RECT) -> DirtyRect {
+    let (left, top) = (rect.left.max(0), rect.top.max(0));
+    DirtyRect {
+        x: left as usize,
+        y: top as usize,
+        width: (rect.right - left).max(0) as usize,
+        height: (rect.bottom - top).max(0) as usize,
+    }
 }
 
 #[cfg(windows)]
@@ -223,12 +369,12 @@ impl Drop for DxgiCapturer {
 
 // Public API for cross-platform compatibility
 #[cfg(windows)]
-pub fn create_dxgi_capturer(display_index: usize) -> Result<DxgiCapturer, String> {
-    DxgiCapturer::new(display_index)
+pub fn create_dxgi_capturer(display: Option<usize>, formats: &[CaptureFormat]) -> Result<DxgiCapturer, String> {
+    DxgiCapturer::new(display, formats)
 }
 
 #[cfg(not(windows))]
-pub fn create_dxgi_capturer(_display_index: usize) -> Result<(), String> {
+pub fn create_dxgi_capturer(_display: Option<usize>, _formats: &[crate::capture_format::CaptureFormat]) -> Result<(), String> {
     Err("DXGI capture is Windows-only".to_string())
 }
 
@@ -0,0 +1,276 @@
+// Encode Pool - whole frames encoded side by side, handed on in order
+// One core can't JPEG-encode a 4K screen at the frame rate, but JPEG frames
+// don't depend on each other. Encoders that say so (VideoEncoder::fork) get a
+// copy on every worker thread and a frame goes to whichever worker is free.
+// Whatever comes after encoding that is heavy (recompressing) runs on the
+// worker too; then whichever worker finishes hands on every frame that is
+// next in line, so frames leave in the order they came in even when a later
+// one was quicker. Encoders that build on the previous frame get one worker.
+//
+// Every frame submitted fills its place in line, a worker that panicked on
+// one leaves a gap that is skipped, so the frames after it still go.
+
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::hw_encoder::VideoEncoder;
+use crate::performance;
+use crate::screen_capture::RawFrame;
+use crate::settings::PerformanceSettings;
+
+/// A frame to encode and how
+pub struct Job<T> {
+    pub frame: Arc<RawFrame>,
+    pub quality: u8,
+    pub keyframe: bool,
+    /// Encode all of it, the frame's dirty and moved rects don't cover every change
+    pub whole: bool,
+    /// Comes back with the result
+    pub tag: T,
+}
+
+pub struct Done<T> {
+    pub frame: Arc<RawFrame>,
+    pub data: Result<Vec<u8>, String>,
+    pub encode_time: Duration,
+    pub tag: T,
+}
+
+struct InOrder<R> {
+    next: u64,
+    /// `None` for frames a worker panicked on
+    finished: BTreeMap<u64, Option<R>>,
+    /// A worker is handing frames on, the others leave theirs to it
+    delivering: bool,
+    /// `deliver` said no more, workers quit as they finish
+    stopped: bool,
+}
+
+struct Handoff<R, D> {
+    in_order: Mutex<InOrder<R>>,
+    /// Called without `in_order` held, so a slow receiver holds up only the worker calling it
+    deliver: Mutex<D>,
+}
+
+pub struct EncodePool<T> {
+    jobs: mpsc::SyncSender<(u64, Job<T>)>,
+    submitted: u64,
+    workers: usize,
+}
+
+impl<T: Send + 'static> EncodePool<T> {
+    /// Run `encoder`, and forks of it up to `workers` in all, each on a thread
+    /// of its own tuned by `performance`. `finish` gets each frame right after
+    /// it's encoded, on its worker; `deliver` what `finish` made of it, in the
+    /// order the frames were submitted. Once `deliver` returns false the pool stops.
+    pub fn start<R, F, D>(
+        encoder: Box<dyn VideoEncoder>,
+        workers: usize,
+        performance: &PerformanceSettings,
+        finish: F,
+        deliver: D,
+    ) -> Result<Self, String>
+    where
+        R: Send + 'static,
+        F: Fn(Done<T>) -> R + Send + Sync + 'static,
+        D: FnMut(R) -> bool + Send + 'static,
+    {
+        let mut encoders: Vec<Box<dyn VideoEncoder>> = (1..workers.max(1))
+            .map_while(|_| encoder.fork())
+            .collect();
+        encoders.insert(0, encoder);
+        let workers = encoders.len();
+
+        // Nothing queues up here: a frame waits in submit() until a worker is free
+        let (jobs, queue) = mpsc::sync_channel(0);
+        let queue = Arc::new(Mutex::new(queue));
+        let finish = Arc::new(finish);
+        let delivery = Arc::new(Handoff {
+            in_order: Mutex::new(InOrder { next: 0, finished: BTreeMap::new(), delivering: false, stopped: false }),
+            deliver: Mutex::new(deliver),
+        });
+        for (index, encoder) in encoders.into_iter().enumerate() {
+            let (queue, finish, delivery) = (queue.clone(), finish.clone(), delivery.clone());
+            let performance = performance.clone();
+            std::thread::Builder::new()
+                .name(format!("encode-worker-{}", index))
+                .spawn(move || {
+                    if performance.is_enabled() {
+                        performance::apply_to_current_thread(&performance);
+                    }
+                    run_worker(encoder, queue, &*finish, &delivery)
+                })
+                .map_err(|e| format!("Failed to spawn encode worker: {}", e))?;
+        }
+        Ok(Self { jobs, submitted: 0, workers })
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Hand a frame to the next free worker, waiting for one; false once the pool stopped
+    pub fn submit(&mut self, job: Job<T>) -> bool {
+        let index = self.submitted;
+        self.submitted += 1;
+        self.jobs.send((index, job)).is_ok()
+    }
+}
+
+fn run_worker<T, R, D>(
+    mut encoder: Box<dyn VideoEncoder>,
+    queue: Arc<Mutex<mpsc::Receiver<(u64, Job<T>)>>>,
+    finish: &dyn Fn(Done<T>) -> R,
+    delivery: &Handoff<R, D>,
+) where
+    D: FnMut(R) -> bool,
+{
+    let mut quality = None;
+    loop {
+        // The pool was dropped, or every worker quit
+        let Ok((index, job)) = queue.lock().unwrap().recv() else {
+            return;
+        };
+
+        let finished = panic::catch_unwind(AssertUnwindSafe(|| {
+            if quality != Some(job.quality) {
+                encoder.set_quality(job.quality);
+                quality = Some(job.quality);
+            }
+            if job.keyframe {
+                encoder.request_keyframe();
+            }
+            if job.whole {
+                encoder.set_dirty_rects(None);
+                encoder.set_moved_rects(&[]);
+            } else {
+                encoder.set_dirty_rects(job.frame.dirty.as_deref());
+                encoder.set_moved_rects(&job.frame.moves);
+            }
+            let encode_start = Instant::now();
+            let data = encoder.encode(&job.frame.rgba);
+            finish(Done { frame: job.frame, data, encode_time: encode_start.elapsed(), tag: job.tag })
+        }));
+        if finished.is_err() {
+            eprintln!("❌ Encode worker panicked on frame {}, skipping it", index);
+        }
+
+        let mut in_order = delivery.in_order.lock().unwrap();
+        if in_order.stopped {
+            return;
+        }
+        in_order.finished.insert(index, finished.ok());
+        if in_order.delivering {
+            continue;
+        }
+        in_order.delivering = true;
+        loop {
+            let next = in_order.next;
+            let Some(finished) = in_order.finished.remove(&next) else {
+                in_order.delivering = false;
+                break;
+            };
+            in_order.next += 1;
+            drop(in_order);
+            let keep_going = match finished {
+                Some(finished) => (*delivery.deliver.lock().unwrap())(finished),
+                None => true,
+            };
+            in_order = delivery.in_order.lock().unwrap();
+            if !keep_going {
+                // Once the last worker is gone, so is the queue and submit() fails
+                in_order.stopped = true;
+                in_order.delivering = false;
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hw_encoder::{EncoderConfig, EncoderType, JpegEncoder};
+
+    fn solid_frame(width: usize, height: usize, shade: u8) -> Arc<RawFrame> {
+        Arc::new(RawFrame {
+            rgba: [shade, shade, shade, 255].repeat(width * height),
+            width,
+            height,
+            captured_at: Instant::now(),
+            dirty: None,
+            moves: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_frames_come_back_in_order() {
+        let (width, height) = (320, 240);
+        let encoder = JpegEncoder::new(&EncoderConfig {
+            width,
+            height,
+            fps: 30,
+            bitrate: 0,
+            encoder_type: EncoderType::Software,
+            quality: 80,
+            workers: 1,
+            speed: crate::hw_encoder::DEFAULT_ENCODER_SPEED,
+        }).unwrap();
+        let mut serial = encoder.clone();
+
+        let (tx, rx) = mpsc::channel();
+        let mut pool = EncodePool::start(
+            Box::new(encoder),
+            4,
+            &PerformanceSettings::default(),
+            |done: Done<usize>| (done.tag, done.data),
+            move |finished| tx.send(finished).is_ok(),
+        ).unwrap();
+        assert_eq!(pool.workers(), 4);
+        let frames: Vec<_> = (0..12).map(|i| solid_frame(width, height, i as u8 * 20)).collect();
+        for (i, frame) in frames.iter().enumerate() {
+            // Quality changes take effect from the frame that carries them
+            let quality = if i < 6 { 80 } else { 40 };
+            assert!(pool.submit(Job { frame: frame.clone(), quality, keyframe: false, whole: false, tag: i }));
+        }
+
+        for (i, frame) in frames.iter().enumerate() {
+            let (tag, data) = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+            assert_eq!(tag, i);
+            serial.set_quality(if i < 6 { 80 } else { 40 });
+            assert_eq!(data.unwrap(), serial.encode(&frame.rgba).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_panicked_frame_is_skipped() {
+        let encoder = JpegEncoder::new(&EncoderConfig {
+            width: 64,
+            height: 64,
+            fps: 30,
+            bitrate: 0,
+            encoder_type: EncoderType::Software,
+            quality: 80,
+            workers: 1,
+            speed: crate::hw_encoder::DEFAULT_ENCODER_SPEED,
+        }).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let mut pool = EncodePool::start(
+            Box::new(encoder),
+            2,
+            &PerformanceSettings::default(),
+            |done: Done<usize>| {
+                assert_ne!(done.tag, 2, "frame 2 breaks its worker");
+                done.tag
+            },
+            move |tag| tx.send(tag).is_ok(),
+        ).unwrap();
+        for i in 0..5 {
+            assert!(pool.submit(Job { frame: solid_frame(64, 64, 0), quality: 80, keyframe: false, whole: false, tag: i }));
+        }
+        let delivered: Vec<usize> = (0..4).map(|_| rx.recv_timeout(Duration::from_secs(10)).unwrap()).collect();
+        assert_eq!(delivered, vec![0, 1, 3, 4]);
+    }
+}
@@ -3,6 +3,40 @@
 
 use std::time::{Duration, Instant};
 
+const QUALITY_STEP: u8 = 10;   // JPEG quality change per loss adjustment
+pub(crate) const MIN_QUALITY: u8 = 20; // Below this JPEG artifacts make text unreadable
+const SLOW_FRAMES_BEFORE_ADJUST: u32 = 5;
+
+/// Where one frame's time went
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameTimings {
+    pub capture: Duration,
+    pub encode: Duration,
+    pub send: Duration,
+}
+
+impl FrameTimings {
+    pub fn total(&self) -> Duration {
+        self.capture + self.encode + self.send
+    }
+
+    /// The stage that took longest, which is what holds back the next frame
+    /// when the stages run side by side
+    pub fn slowest(&self) -> Duration {
+        self.capture.max(self.encode).max(self.send)
+    }
+}
+
+/// What the pacer changed after a run of slow frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PacerAction {
+    None,
+    /// Capture/encode can't keep up, so do less of it
+    LowerFps,
+    /// The network can't keep up, so send less per frame
+    LowerQuality,
+}
+
 /// Manages frame pacing to ensure consistent FPS
 pub struct FramePacer {
     target_fps: u32,
@@ -90,6 +124,8 @@ pub struct AdaptiveFramePacer {
     max_fps: u32,
     packet_loss_threshold: f32,
     consecutive_slow_frames: u32,
+    quality: u8,
+    max_quality: u8,
 }
 
 impl AdaptiveFramePacer {
@@ -100,6 +136,8 @@ impl AdaptiveFramePacer {
             max_fps,
             packet_loss_threshold: 0.1, // 10% packet loss
             consecutive_slow_frames: 0,
+            quality: crate::hw_encoder::DEFAULT_JPEG_QUALITY,
+            max_quality: crate::hw_encoder::DEFAULT_JPEG_QUALITY,
         }
     }
 
@@ -111,9 +149,21 @@ impl AdaptiveFramePacer {
         self.pacer.sleep_until_next()
     }
 
-    /// Adjust FPS based on packet loss
+    pub fn spf(&self) -> Duration {
+        self.pacer.spf()
+    }
+
+    /// Adjust FPS and JPEG quality based on packet loss.
+    /// Under loss both step down; once the link clears they recover, quality first.
     pub fn adjust_for_packet_loss(&mut self, loss_rate: f32) {
         if loss_rate > self.packet_loss_threshold {
+            let new_quality = self.quality.saturating_sub(QUALITY_STEP).max(MIN_QUALITY);
+            if new_quality != self.quality {
+                eprintln!("📉 Reducing quality due to packet loss: {} → {} (loss: {:.1}%)",
+                    self.quality, new_quality, loss_rate * 100.0);
+                self.quality = new_quality;
+            }
+            
             // High packet loss → reduce FPS
             let new_fps = (self.pacer.target_fps() as f32 * 0.8) as u32;
             let new_fps = new_fps.max(self.min_fps);
@@ -124,6 +174,13 @@ impl AdaptiveFramePacer {
                 self.pacer.set_fps(new_fps);
             }
         } else if loss_rate < self.packet_loss_threshold / 2.0 {
+            if self.quality < self.max_quality {
+                let new_quality = (self.quality + QUALITY_STEP).min(self.max_quality);
+                eprintln!("📈 Increasing quality (low packet loss): {} → {}", self.quality, new_quality);
+                self.quality = new_quality;
+                return;
+            }
+            
             // Low packet loss → can increase FPS
             let new_fps = (self.pacer.target_fps() as f32 * 1.1) as u32;
             let new_fps = new_fps.min(self.max_fps);
@@ -136,27 +193,40 @@ impl AdaptiveFramePacer {
         }
     }
 
-    /// Adjust FPS based on encoding/capture performance
-    pub fn adjust_for_slow_frame(&mut self, frame_time_ms: u64) {
-        let target_frame_time = 1000 / self.pacer.target_fps() as u64;
-        
-        if frame_time_ms > target_frame_time * 2 {
-            // Frame took 2x longer than it should
-            self.consecutive_slow_frames += 1;
-            
-            if self.consecutive_slow_frames >= 5 {
-                // 5 consecutive slow frames → reduce FPS
-                let new_fps = (self.pacer.target_fps() as f32 * 0.9) as u32;
-                let new_fps = new_fps.max(self.min_fps);
-                
-                eprintln!("📉 Reducing FPS due to slow encoding: {} → {} ({} ms/frame)",
-                    self.pacer.target_fps(), new_fps, frame_time_ms);
-                self.pacer.set_fps(new_fps);
-                self.consecutive_slow_frames = 0;
-            }
-        } else {
+    /// Adjust for frames that take too long. Capture/encode-bound frames cost
+    /// FPS; send-bound frames (or receivers reporting loss) cost quality, which
+    /// shrinks the frames instead, until quality is at its floor.
+    pub fn adjust_for_frame(&mut self, timings: FrameTimings, loss_rate: f32) -> PacerAction {
+        // A stage took longer than a frame period, the ones before it are backing up
+        if timings.slowest() <= self.spf() {
             self.consecutive_slow_frames = 0;
+            return PacerAction::None;
         }
+        self.consecutive_slow_frames += 1;
+        if self.consecutive_slow_frames < SLOW_FRAMES_BEFORE_ADJUST {
+            return PacerAction::None;
+        }
+        self.consecutive_slow_frames = 0;
+
+        let network_bound = timings.send >= timings.slowest()
+            || loss_rate > self.packet_loss_threshold;
+        if network_bound && self.quality > MIN_QUALITY {
+            let new_quality = self.quality.saturating_sub(QUALITY_STEP).max(MIN_QUALITY);
+            eprintln!("📉 Reducing quality due to slow sending: {} → {} (send {} ms, loss {:.1}%)",
+                self.quality, new_quality, timings.send.as_millis(), loss_rate * 100.0);
+            self.quality = new_quality;
+            return PacerAction::LowerQuality;
+        }
+
+        let new_fps = ((self.pacer.target_fps() as f32 * 0.9) as u32).max(self.min_fps);
+        if new_fps == self.pacer.target_fps() {
+            return PacerAction::None;
+        }
+        eprintln!("📉 Reducing FPS due to slow frames: {} → {} (capture {} ms, encode {} ms, send {} ms)",
+            self.pacer.target_fps(), new_fps,
+            timings.capture.as_millis(), timings.encode.as_millis(), timings.send.as_millis());
+        self.pacer.set_fps(new_fps);
+        PacerAction::LowerFps
     }
 
     pub fn actual_fps(&self) -> f32 {
@@ -166,6 +236,23 @@ impl AdaptiveFramePacer {
     pub fn target_fps(&self) -> u32 {
         self.pacer.target_fps()
     }
+
+    /// Pace at `fps` from now on, kept within the pacer's range; loss and slow
+    /// frames adjust from there as usual
+    pub fn set_target_fps(&mut self, fps: u32) {
+        self.pacer.set_fps(fps.clamp(self.min_fps, self.max_fps));
+    }
+
+    /// JPEG quality to encode with, given recent packet loss
+    pub fn quality(&self) -> u8 {
+        self.quality
+    }
+
+    /// Start at `quality` and never recover above it
+    pub fn set_max_quality(&mut self, quality: u8) {
+        self.quality = quality.max(MIN_QUALITY);
+        self.max_quality = self.quality;
+    }
 }
 
 #[cfg(test)]
@@ -201,4 +288,97 @@ mod tests {
         pacer.adjust_for_packet_loss(0.01);
         // (May or may not increase depending on implementation)
     }
+
+    #[test]
+    fn test_set_target_fps() {
+        let mut pacer = AdaptiveFramePacer::new(30, 10, 60);
+        // Set by hand, but never outside the range
+        pacer.set_target_fps(45);
+        assert_eq!(pacer.target_fps(), 45);
+        pacer.set_target_fps(120);
+        assert_eq!(pacer.target_fps(), 60);
+        pacer.set_target_fps(1);
+        assert_eq!(pacer.target_fps(), 10);
+    }
+
+    #[test]
+    fn test_quality_follows_packet_loss() {
+        let mut pacer = AdaptiveFramePacer::new(30, 10, 60);
+        let start_quality = pacer.quality();
+        
+        for _ in 0..10 {
+            pacer.adjust_for_packet_loss(0.3);
+        }
+        assert_eq!(pacer.quality(), MIN_QUALITY);
+        assert_eq!(pacer.target_fps(), 10);
+        
+        // Quality recovers before FPS does
+        pacer.adjust_for_packet_loss(0.0);
+        assert!(pacer.quality() > MIN_QUALITY);
+        assert_eq!(pacer.target_fps(), 10);
+        
+        for _ in 0..30 {
+            pacer.adjust_for_packet_loss(0.0);
+        }
+        assert_eq!(pacer.quality(), start_quality);
+        assert!(pacer.target_fps() > 10);
+    }
+
+    fn timings(capture_ms: u64, encode_ms: u64, send_ms: u64) -> FrameTimings {
+        FrameTimings {
+            capture: Duration::from_millis(capture_ms),
+            encode: Duration::from_millis(encode_ms),
+            send: Duration::from_millis(send_ms),
+        }
+    }
+
+    fn run_slow_frames(pacer: &mut AdaptiveFramePacer, frame: FrameTimings, loss_rate: f32) -> PacerAction {
+        (0..SLOW_FRAMES_BEFORE_ADJUST)
+            .map(|_| pacer.adjust_for_frame(frame, loss_rate))
+            .last()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fast_frames_change_nothing() {
+        let mut pacer = AdaptiveFramePacer::new(30, 10, 60);
+        for _ in 0..20 {
+            assert_eq!(pacer.adjust_for_frame(timings(5, 10, 5), 0.0), PacerAction::None);
+        }
+        assert_eq!((pacer.target_fps(), pacer.quality()), (30, crate::hw_encoder::DEFAULT_JPEG_QUALITY));
+    }
+
+    #[test]
+    fn test_encode_bound_lowers_fps() {
+        let mut pacer = AdaptiveFramePacer::new(30, 10, 60);
+        assert_eq!(run_slow_frames(&mut pacer, timings(10, 80, 5), 0.0), PacerAction::LowerFps);
+        assert_eq!(pacer.target_fps(), 27);
+        assert_eq!(pacer.quality(), crate::hw_encoder::DEFAULT_JPEG_QUALITY);
+    }
+
+    #[test]
+    fn test_send_bound_lowers_quality() {
+        let mut pacer = AdaptiveFramePacer::new(30, 10, 60);
+        assert_eq!(run_slow_frames(&mut pacer, timings(5, 10, 80), 0.0), PacerAction::LowerQuality);
+        assert_eq!(pacer.target_fps(), 30);
+        assert!(pacer.quality() < crate::hw_encoder::DEFAULT_JPEG_QUALITY);
+    }
+
+    #[test]
+    fn test_loss_makes_slow_frames_network_bound() {
+        let mut pacer = AdaptiveFramePacer::new(30, 10, 60);
+        // Encode dominates, but receivers are losing packets
+        assert_eq!(run_slow_frames(&mut pacer, timings(10, 60, 20), 0.2), PacerAction::LowerQuality);
+        assert_eq!(pacer.target_fps(), 30);
+    }
+
+    #[test]
+    fn test_network_bound_falls_back_to_fps_at_min_quality() {
+        let mut pacer = AdaptiveFramePacer::new(30, 10, 60);
+        while pacer.quality() > MIN_QUALITY {
+            run_slow_frames(&mut pacer, timings(5, 10, 80), 0.0);
+        }
+        assert_eq!(run_slow_frames(&mut pacer, timings(5, 10, 80), 0.0), PacerAction::LowerFps);
+        assert!(pacer.target_fps() < 30);
+    }
 }
@@ -0,0 +1,60 @@
+// Frame Source - where the streaming pipeline pulls raw frames from
+// Sources never sleep or retry: the pipeline owns all capture timing
+
+use crate::screen_capture::RawFrame;
+
+pub trait FrameSource: Send {
+    /// Grab the latest frame. `Ok(None)` means nothing new is ready yet,
+    /// which is normal (static screen, capture still warming up) and not an error.
+    fn next_frame(&mut self) -> Result<Option<RawFrame>, String>;
+}
+
+// Plain capture functions work as sources
+impl<F> FrameSource for F
+where
+    F: FnMut() -> Result<Option<RawFrame>, String> + Send,
+{
+    fn next_frame(&mut self) -> Result<Option<RawFrame>, String> {
+        self()
+    }
+}
+
+/// Generated test pattern, for exercising the pipeline without a display.
+/// A bar sweeps across a gradient so every frame differs from the last.
+pub struct SyntheticSource {
+    width: usize,
+    height: usize,
+    frame: usize,
+}
+
+impl SyntheticSource {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, frame: 0 }
+    }
+}
+
+impl FrameSource for SyntheticSource {
+    fn next_frame(&mut self) -> Result<Option<RawFrame>, String> {
+        let bar = (self.frame * 8) % self.width.max(1);
+        let mut rgba = Vec::with_capacity(self.width * self.height * 4);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if x.abs_diff(bar) < 4 {
+                    rgba.extend_from_slice(&[255, 255, 255, 255]);
+                } else {
+                    rgba.extend_from_slice(&[(x * 255 / self.width) as u8, (y * 255 / self.height) as u8, 128, 255]);
+                }
+            }
+        }
+        self.frame += 1;
+
+        Ok(Some(RawFrame {
+            rgba,
+            width: self.width,
+            height: self.height,
+            captured_at: std::time::Instant::now(),
+            dirty: None,
+            moves: Vec::new(),
+        }))
+    }
+}
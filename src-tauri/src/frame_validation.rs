@@ -0,0 +1,170 @@
+// Frame Validation - makes sure a JPEG decodes before the viewer shows it
+// The SOI/EOI marker checks catch truncated frames, but a frame with a corrupt
+// chunk in the middle passes them and shows up smeared. Decoding catches those
+// too, and the decoded frame is reused for black frame detection.
+//
+// Weak viewers can't always afford a full decode per frame. When decoding
+// takes longer than the budget on average, validation drops to marker checks
+// for the rest of the session (black frame detection goes with it, it needs the
+// decoded frame) and the stats say so.
+
+use std::time::{Duration, Instant};
+use image::DynamicImage;
+use serde::Serialize;
+
+const AVERAGE_WEIGHT: f32 = 0.1; // Weight of the newest decode in the moving average
+const MIN_SAMPLES: u64 = 10; // Decodes before the budget is enforced
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationLevel {
+    /// SOI/EOI markers only
+    #[default]
+    Markers,
+    /// Every JPEG frame is decoded
+    Decode,
+}
+
+/// Decode timings and the validation they allow, reported in the viewer's stats
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DecodeStats {
+    pub level: ValidationLevel,
+    /// JPEG frames decoded for validation plus H.264 frames decoded for display
+    pub frames_decoded: u64,
+    /// Frames that passed the marker checks but failed to decode
+    pub corrupt_frames: u64,
+    /// Moving average over recent decodes
+    pub avg_decode_ms: f32,
+    pub max_decode_ms: f32,
+    /// Decoding went over the budget and validation fell back to markers
+    pub downgraded: bool,
+}
+
+pub struct FrameValidator {
+    enabled: bool,
+    budget_ms: u64,
+    /// Validation decodes timed since the last (re)configuration
+    samples: u64,
+    stats: DecodeStats,
+}
+
+impl FrameValidator {
+    pub fn new(enabled: bool, budget_ms: u64) -> Self {
+        Self {
+            enabled,
+            budget_ms,
+            samples: 0,
+            stats: DecodeStats { level: level(enabled), ..DecodeStats::default() },
+        }
+    }
+
+    /// Follow the viewer settings; a change gives a downgraded viewer another try
+    pub fn configure(&mut self, enabled: bool, budget_ms: u64) {
+        if (enabled, budget_ms) == (self.enabled, self.budget_ms) {
+            return;
+        }
+        self.enabled = enabled;
+        self.budget_ms = budget_ms;
+        self.samples = 0;
+        self.stats.downgraded = false;
+        self.stats.level = level(enabled);
+    }
+
+    pub fn stats(&self) -> &DecodeStats {
+        &self.stats
+    }
+
+    /// Decode `jpeg` if validation is on. `Ok(None)` means only the markers were
+    /// checked, `Err` that the frame is corrupt and shouldn't be shown.
+    pub fn check(&mut self, jpeg: &[u8]) -> Result<Option<DynamicImage>, String> {
+        if self.stats.level != ValidationLevel::Decode {
+            return Ok(None);
+        }
+
+        let start = Instant::now();
+        let decoded = image::load_from_memory_with_format(jpeg, image::ImageFormat::Jpeg);
+        self.record_decode(start.elapsed());
+        self.samples += 1;
+        self.enforce_budget();
+
+        match decoded {
+            Ok(image) => Ok(Some(image)),
+            Err(e) => {
+                self.stats.corrupt_frames += 1;
+                Err(e.to_string())
+            }
+        }
+    }
+
+    /// Count a decode done for display, which has to happen whatever the budget
+    pub fn record_decode(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_secs_f32() * 1000.0;
+        self.stats.avg_decode_ms = if self.stats.frames_decoded == 0 {
+            ms
+        } else {
+            self.stats.avg_decode_ms + (ms - self.stats.avg_decode_ms) * AVERAGE_WEIGHT
+        };
+        self.stats.max_decode_ms = self.stats.max_decode_ms.max(ms);
+        self.stats.frames_decoded += 1;
+    }
+
+    fn enforce_budget(&mut self) {
+        if self.budget_ms == 0 || self.samples < MIN_SAMPLES || self.stats.avg_decode_ms <= self.budget_ms as f32 {
+            return;
+        }
+        eprintln!(
+            "🐢 Decoding takes {:.1} ms per frame (budget {} ms), validating frames by their markers only",
+            self.stats.avg_decode_ms, self.budget_ms
+        );
+        self.stats.level = ValidationLevel::Markers;
+        self.stats.downgraded = true;
+    }
+}
+
+fn level(enabled: bool) -> ValidationLevel {
+    if enabled { ValidationLevel::Decode } else { ValidationLevel::Markers }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jpeg() -> Vec<u8> {
+        let img = image::RgbImage::from_pixel(64, 48, image::Rgb([90, 120, 200]));
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut buffer, image::ImageFormat::Jpeg).unwrap();
+        buffer.into_inner()
+    }
+
+    #[test]
+    fn test_corrupt_frames_are_caught() {
+        let mut validator = FrameValidator::new(true, 0);
+        assert_eq!(validator.check(&jpeg()).unwrap().unwrap().width(), 64);
+
+        // Start and end markers intact, garbage in between
+        let frame = [&[0xFF, 0xD8][..], &[0x5A; 200], &[0xFF, 0xD9]].concat();
+        assert!(validator.check(&frame).is_err());
+        assert_eq!((validator.stats().frames_decoded, validator.stats().corrupt_frames), (2, 1));
+
+        let mut markers_only = FrameValidator::new(false, 0);
+        assert!(markers_only.check(&frame).unwrap().is_none());
+        assert_eq!(markers_only.stats().level, ValidationLevel::Markers);
+    }
+
+    #[test]
+    fn test_slow_decoding_downgrades() {
+        let mut validator = FrameValidator::new(true, 5);
+        for _ in 0..MIN_SAMPLES {
+            validator.samples += 1;
+            validator.record_decode(Duration::from_millis(40));
+            validator.enforce_budget();
+        }
+        assert!(validator.stats().downgraded);
+        assert!(validator.check(&jpeg()).unwrap().is_none());
+
+        // Changed settings re-arm it
+        validator.configure(true, 50);
+        assert_eq!(validator.stats().level, ValidationLevel::Decode);
+        assert!(!validator.stats().downgraded);
+    }
+}
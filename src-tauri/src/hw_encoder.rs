@@ -2,10 +2,21 @@
 // Simplified version of RustDesk's hardware encoding
 
 use std::sync::Arc;
+use crate::screen_capture::{DirtyRect, MovedRect};
+
+pub const DEFAULT_JPEG_QUALITY: u8 = 50; // Lower quality for smaller packets
+pub const DEFAULT_ENCODER_SPEED: u8 = 10; // Fastest, streams can't wait on the encoder
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum EncoderType {
     Software,      // JPEG
+    TileDelta,     // JPEG keyframes + changed 64x64 tiles
+    Lossless,      // zstd-compressed RGB
+    WebP,          // Lossy WebP
+    WebPLossless,  // Lossless WebP
+    Av1,           // rav1e (CPU)
+    Png,           // Lossless PNG
+    SoftwareH264,  // OpenH264 (CPU)
     HardwareH264,  // NVENC, QuickSync, AMF, VideoToolbox
     HardwareH265,  // HEVC
 }
@@ -17,6 +28,9 @@ pub struct EncoderConfig {
     pub bitrate: u32,          // bps
     pub encoder_type: EncoderType,
     pub quality: u8,           // 1-100 for JPEG, or CRF for H264
+    pub workers: usize,        // Threads for encoders that can split a frame (tile delta)
+    #[cfg_attr(not(feature = "av1"), allow(dead_code))]
+    pub speed: u8,             // Speed preset for encoders that have one, 0 (best) - 10 (fastest) for AV1
 }
 
 pub trait VideoEncoder: Send {
@@ -24,9 +38,26 @@ pub trait VideoEncoder: Send {
     fn encoder_type(&self) -> EncoderType;
     fn set_bitrate(&mut self, bitrate: u32) -> Result<(), String>;
     fn set_fps(&mut self, fps: u32) -> Result<(), String>;
+    /// Make the next frame a keyframe (intra-only encoders can ignore this)
+    fn request_keyframe(&mut self) {}
+    /// Change JPEG quality on the fly (encoders without a quality knob ignore this)
+    fn set_quality(&mut self, _quality: u8) {}
+    /// Where the next frame differs from the one before, `None` for anywhere
+    /// (encoders that look at the whole frame anyway ignore this)
+    fn set_dirty_rects(&mut self, _rects: Option<&[DirtyRect]>) {}
+    /// Regions that moved before the next frame's dirty rects changed
+    /// (encoders that can't tell receivers to copy pixels ignore this)
+    fn set_moved_rects(&mut self, _moves: &[MovedRect]) {}
+    /// Another encoder just like this one, so frames can be encoded side by
+    /// side (see encode_pool.rs). `None` from encoders whose frames build on
+    /// the one before, which must see every frame in order.
+    fn fork(&self) -> Option<Box<dyn VideoEncoder>> {
+        None
+    }
 }
 
 // JPEG Software Encoder (current implementation)
+#[derive(Clone)]
 pub struct JpegEncoder {
     quality: u8,
     width: usize,
@@ -45,134 +76,699 @@ impl JpegEncoder {
 
 impl VideoEncoder for JpegEncoder {
     fn encode(&mut self, rgba: &[u8]) -> Result<Vec<u8>, String> {
-        // Convert RGBA to RGB
-        let mut rgb = Vec::with_capacity(self.width * self.height * 3);
-        for chunk in rgba.chunks_exact(4) {
-            rgb.push(chunk[0]); // R
-            rgb.push(chunk[1]); // G
-            rgb.push(chunk[2]); // B
-        }
+        check_frame_size(rgba, self.width, self.height)?;
+        crate::jpeg::encode(rgba, self.width, self.height, crate::jpeg::Layout::Rgba, self.quality)
+    }
 
-        // Encode to JPEG
-        use image::{ImageBuffer, RgbImage};
-        use std::io::Cursor;
+    fn encoder_type(&self) -> EncoderType {
+        EncoderType::Software
+    }
 
-        let img: RgbImage = ImageBuffer::from_raw(
-            self.width as u32,
-            self.height as u32,
-            rgb,
-        ).ok_or("Failed to create image buffer")?;
+    fn set_bitrate(&mut self, _bitrate: u32) -> Result<(), String> {
+        // JPEG doesn't use bitrate, ignore
+        Ok(())
+    }
 
-        let mut buffer = Cursor::new(Vec::new());
-        let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(
-            &mut buffer,
-            self.quality,
-        );
+    fn set_fps(&mut self, _fps: u32) -> Result<(), String> {
+        // JPEG is per-frame, FPS handled externally
+        Ok(())
+    }
+
+    fn set_quality(&mut self, quality: u8) {
+        self.quality = quality;
+    }
 
-        encoder.encode(
-            img.as_raw(),
-            self.width as u32,
-            self.height as u32,
-            image::ExtendedColorType::Rgb8,
-        ).map_err(|e| format!("JPEG encoding failed: {}", e))?;
+    fn fork(&self) -> Option<Box<dyn VideoEncoder>> {
+        Some(Box::new(self.clone()))
+    }
+}
 
-        Ok(buffer.into_inner())
+/// Encoders are sized at startup, reject frames captured at another resolution
+pub fn check_frame_size(rgba: &[u8], width: usize, height: usize) -> Result<(), String> {
+    if rgba.len() != width * height * 4 {
+        return Err(format!(
+            "Frame size mismatch: {} bytes, encoder expects {}x{} RGBA",
+            rgba.len(), width, height
+        ));
+    }
+    Ok(())
+}
+
+/// Drop the alpha channel for encoders that take packed RGB
+pub fn rgba_to_rgb(rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for chunk in rgba.chunks_exact(4) {
+        rgb.extend_from_slice(&chunk[..3]);
+    }
+    rgb
+}
+
+// WebP Encoder - libwebp, lossy at the JPEG quality or lossless
+// Screen content (flat areas, text) comes out noticeably smaller than JPEG
+// and the webview decodes it natively, so receivers pass it straight through
+#[cfg(feature = "webp")]
+pub struct WebpEncoder {
+    quality: u8,
+    lossless: bool,
+    width: usize,
+    height: usize,
+}
+
+#[cfg(feature = "webp")]
+impl WebpEncoder {
+    pub fn new(config: &EncoderConfig) -> Result<Self, String> {
+        // WebP images are limited to 16383 pixels a side
+        if config.width > 16383 || config.height > 16383 {
+            return Err(format!("{}x{} is too large for WebP", config.width, config.height));
+        }
+        Ok(Self {
+            quality: config.quality,
+            lossless: config.encoder_type == EncoderType::WebPLossless,
+            width: config.width,
+            height: config.height,
+        })
+    }
+}
+
+#[cfg(feature = "webp")]
+impl VideoEncoder for WebpEncoder {
+    fn encode(&mut self, rgba: &[u8]) -> Result<Vec<u8>, String> {
+        check_frame_size(rgba, self.width, self.height)?;
+        let webp = webp::Encoder::from_rgba(rgba, self.width as u32, self.height as u32)
+            .encode_simple(self.lossless, self.quality as f32)
+            .map_err(|e| format!("WebP encoding failed: {:?}", e))?;
+        Ok(webp.to_vec())
     }
 
     fn encoder_type(&self) -> EncoderType {
-        EncoderType::Software
+        if self.lossless { EncoderType::WebPLossless } else { EncoderType::WebP }
     }
 
     fn set_bitrate(&mut self, _bitrate: u32) -> Result<(), String> {
-        // JPEG doesn't use bitrate, ignore
         Ok(())
     }
 
     fn set_fps(&mut self, _fps: u32) -> Result<(), String> {
-        // JPEG is per-frame, FPS handled externally
         Ok(())
     }
+
+    fn set_quality(&mut self, quality: u8) {
+        self.quality = quality;
+    }
 }
 
-// Hardware H264 Encoder (placeholder - requires platform-specific implementation)
-#[cfg(feature = "hwcodec")]
-pub struct H264HardwareEncoder {
+// OpenH264 Software Encoder - H.264 without GPU support
+// Emits Annex-B NAL units (00 00 00 01 start codes), one access unit per frame
+#[cfg(feature = "openh264")]
+pub struct OpenH264Encoder {
+    encoder: openh264::encoder::Encoder,
     width: usize,
     height: usize,
     bitrate: u32,
     fps: u32,
-    // Platform-specific encoder would go here
 }
 
-#[cfg(feature = "hwcodec")]
-impl H264HardwareEncoder {
+#[cfg(feature = "openh264")]
+impl OpenH264Encoder {
     pub fn new(config: &EncoderConfig) -> Result<Self, String> {
-        // Check for hardware encoder availability
-        if !Self::is_available() {
-            return Err("Hardware H264 encoder not available".to_string());
+        // H.264 works on 2x2 chroma blocks
+        if config.width % 2 != 0 || config.height % 2 != 0 {
+            return Err(format!("OpenH264 needs even dimensions, got {}x{}", config.width, config.height));
         }
 
-        eprintln!("🎬 Initializing hardware H264 encoder");
+        let bitrate = if config.bitrate > 0 {
+            config.bitrate
+        } else {
+            calculate_bitrate(config.width, config.height, config.fps)
+        };
+
+        eprintln!("🎬 Initializing OpenH264 software encoder");
         eprintln!("   Resolution: {}x{}", config.width, config.height);
-        eprintln!("   Bitrate: {} Mbps", config.bitrate / 1_000_000);
+        eprintln!("   Bitrate: {} kbps", bitrate / 1000);
         eprintln!("   FPS: {}", config.fps);
 
         Ok(Self {
+            encoder: Self::build(bitrate, config.fps)?,
             width: config.width,
             height: config.height,
-            bitrate: config.bitrate,
+            bitrate,
             fps: config.fps,
         })
     }
 
-    pub fn is_available() -> bool {
-        // Check for NVENC, QuickSync, AMF, etc.
+    fn build(bitrate: u32, fps: u32) -> Result<openh264::encoder::Encoder, String> {
+        use openh264::encoder::{BitRate, Encoder, EncoderConfig as H264Config, FrameRate};
+        use openh264::OpenH264API;
+
+        let config = H264Config::new()
+            .bitrate(BitRate::from_bps(bitrate))
+            .max_frame_rate(FrameRate::from_hz(fps as f32));
+
+        Encoder::with_api_config(OpenH264API::from_source(), config)
+            .map_err(|e| format!("Failed to create OpenH264 encoder: {}", e))
+    }
+
+    /// Next frame will be an IDR frame decoders can start from
+    pub fn force_keyframe(&mut self) {
+        self.encoder.force_intra_frame();
+    }
+}
+
+#[cfg(feature = "openh264")]
+impl VideoEncoder for OpenH264Encoder {
+    fn encode(&mut self, rgba: &[u8]) -> Result<Vec<u8>, String> {
+        use openh264::formats::{RgbSliceU8, YUVBuffer};
+
+        let rgb = rgba_to_rgb(rgba, self.width, self.height);
+        let yuv = YUVBuffer::from_rgb_source(RgbSliceU8::new(&rgb, (self.width, self.height)));
+
+        let bitstream = self.encoder.encode(&yuv)
+            .map_err(|e| format!("H264 encoding failed: {}", e))?;
+
+        Ok(bitstream.to_vec())
+    }
+
+    fn encoder_type(&self) -> EncoderType {
+        EncoderType::SoftwareH264
+    }
+
+    fn set_bitrate(&mut self, bitrate: u32) -> Result<(), String> {
+        // OpenH264's safe API has no runtime rate control, rebuild instead
+        self.encoder = Self::build(bitrate, self.fps)?;
+        self.bitrate = bitrate;
+        Ok(())
+    }
+
+    fn set_fps(&mut self, fps: u32) -> Result<(), String> {
+        self.encoder = Self::build(self.bitrate, fps)?;
+        self.fps = fps;
+        Ok(())
+    }
+
+    fn request_keyframe(&mut self) {
+        self.force_keyframe();
+    }
+}
+
+// AV1 Software Encoder - rav1e, low latency (no frame reordering)
+// Each frame's OBUs go out behind a small header saying whether it's a keyframe,
+// see video_decoder::av1_payload
+#[cfg(feature = "av1")]
+pub struct Av1Encoder {
+    context: rav1e::Context<u8>,
+    width: usize,
+    height: usize,
+    bitrate: u32,
+    fps: u32,
+    speed: u8,
+    keyframe_requested: bool,
+}
+
+#[cfg(feature = "av1")]
+impl Av1Encoder {
+    pub fn new(config: &EncoderConfig) -> Result<Self, String> {
+        if config.width % 2 != 0 || config.height % 2 != 0 {
+            return Err(format!("AV1 4:2:0 needs even dimensions, got {}x{}", config.width, config.height));
+        }
+        let bitrate = if config.bitrate > 0 {
+            config.bitrate
+        } else {
+            // AV1 needs about half the bits of H.264 for the same picture
+            calculate_bitrate(config.width, config.height, config.fps) / 2
+        };
+
+        eprintln!("🎬 Initializing rav1e AV1 encoder");
+        eprintln!("   Resolution: {}x{}", config.width, config.height);
+        eprintln!("   Bitrate: {} kbps", bitrate / 1000);
+        eprintln!("   Speed preset: {}", config.speed);
+
+        Ok(Self {
+            context: Self::build(config.width, config.height, bitrate, config.fps, config.speed)?,
+            width: config.width,
+            height: config.height,
+            bitrate,
+            fps: config.fps,
+            speed: config.speed,
+            keyframe_requested: false,
+        })
+    }
+
+    fn build(width: usize, height: usize, bitrate: u32, fps: u32, speed: u8) -> Result<rav1e::Context<u8>, String> {
+        use rav1e::prelude::*;
+
+        let mut encoder = rav1e::EncoderConfig::with_speed_preset(speed.min(10));
+        encoder.width = width;
+        encoder.height = height;
+        encoder.bitrate = (bitrate / 1000) as i32;
+        encoder.time_base = Rational::new(1, fps.max(1) as u64);
+        encoder.low_latency = true;
+        encoder.max_key_frame_interval = fps.max(1) as u64 * 2;
+        // Every frame out as soon as it's in
+        encoder.speed_settings.rdo_lookahead_frames = 1;
+
+        Config::new()
+            .with_encoder_config(encoder)
+            .new_context()
+            .map_err(|e| format!("Failed to create AV1 encoder: {}", e))
+    }
+
+    fn rebuild(&mut self) -> Result<(), String> {
+        self.context = Self::build(self.width, self.height, self.bitrate, self.fps, self.speed)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "av1")]
+impl VideoEncoder for Av1Encoder {
+    fn encode(&mut self, rgba: &[u8]) -> Result<Vec<u8>, String> {
+        use rav1e::prelude::*;
+
+        check_frame_size(rgba, self.width, self.height)?;
+        let (y, u, v) = rgba_to_i420(rgba, self.width, self.height);
+        let mut frame = self.context.new_frame();
+        frame.planes[0].copy_from_raw_u8(&y, self.width, 1);
+        frame.planes[1].copy_from_raw_u8(&u, self.width / 2, 1);
+        frame.planes[2].copy_from_raw_u8(&v, self.width / 2, 1);
+
+        let params = FrameParameters {
+            frame_type_override: if std::mem::take(&mut self.keyframe_requested) { FrameTypeOverride::Key } else { FrameTypeOverride::No },
+            ..Default::default()
+        };
+        self.context.send_frame((frame, params))
+            .map_err(|e| format!("AV1 encoding failed: {}", e))?;
+
+        let mut obus = Vec::new();
+        let mut keyframe = false;
+        loop {
+            match self.context.receive_packet() {
+                Ok(packet) => {
+                    keyframe |= packet.frame_type == FrameType::KEY;
+                    obus.extend_from_slice(&packet.data);
+                }
+                Err(EncoderStatus::Encoded) => continue,
+                Err(EncoderStatus::NeedMoreData) => break,
+                Err(e) => return Err(format!("AV1 encoding failed: {}", e)),
+            }
+        }
+        if obus.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(crate::video_decoder::av1_payload(&obus, keyframe))
+    }
+
+    fn encoder_type(&self) -> EncoderType {
+        EncoderType::Av1
+    }
+
+    fn set_bitrate(&mut self, bitrate: u32) -> Result<(), String> {
+        // rav1e fixes rate control per context
+        self.bitrate = bitrate;
+        self.rebuild()
+    }
+
+    fn set_fps(&mut self, fps: u32) -> Result<(), String> {
+        self.fps = fps;
+        self.rebuild()
+    }
+
+    fn request_keyframe(&mut self) {
+        self.keyframe_requested = true;
+    }
+}
+
+/// Planar 4:2:0 YUV (BT.709 limited range) from RGBA with even dimensions,
+/// chroma averaged over each 2x2 block
+#[cfg(feature = "av1")]
+fn rgba_to_i420(rgba: &[u8], width: usize, height: usize) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    let luma = |p: &[u8]| 16.0 + 0.183 * p[0] as f32 + 0.614 * p[1] as f32 + 0.062 * p[2] as f32;
+    let y: Vec<u8> = rgba.chunks_exact(4).map(|p| luma(p).round() as u8).collect();
+
+    let (chroma_width, chroma_height) = (width / 2, height / 2);
+    let mut u = Vec::with_capacity(chroma_width * chroma_height);
+    let mut v = Vec::with_capacity(chroma_width * chroma_height);
+    for row in 0..chroma_height {
+        for col in 0..chroma_width {
+            let mut sum = [0f32; 3];
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let i = ((row * 2 + dy) * width + col * 2 + dx) * 4;
+                for c in 0..3 {
+                    sum[c] += rgba[i + c] as f32 / 4.0;
+                }
+            }
+            let [r, g, b] = sum;
+            u.push((128.0 - 0.101 * r - 0.339 * g + 0.439 * b).round().clamp(0.0, 255.0) as u8);
+            v.push((128.0 + 0.439 * r - 0.399 * g - 0.040 * b).round().clamp(0.0, 255.0) as u8);
+        }
+    }
+    (y, u, v)
+}
+
+// Hardware H264 Encoder - GPU encoders driven through FFmpeg
+// Frames are converted from RGBA on the CPU, then handed to the vendor encoder
+#[cfg(feature = "hwcodec")]
+use ffmpeg_next as ffmpeg;
+
+#[cfg(feature = "hwcodec")]
+pub const VAAPI_RENDER_NODE: &str = "/dev/dri/renderD128";
+
+#[cfg(feature = "hwcodec")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HardwareBackend {
+    Nvenc,        // NVIDIA GPUs (Windows, Linux)
+    VideoToolbox, // Apple media engine (macOS)
+    Vaapi,        // Intel/AMD GPUs (Linux)
+}
+
+#[cfg(feature = "hwcodec")]
+impl HardwareBackend {
+    /// Backends worth probing on this platform, best first
+    fn candidates() -> &'static [HardwareBackend] {
         #[cfg(target_os = "windows")]
         {
-            // Check for NVIDIA, Intel, AMD encoders
-            // For now, return false (not implemented)
-            false
+            &[HardwareBackend::Nvenc]
+        }
+        #[cfg(target_os = "linux")]
+        {
+            &[HardwareBackend::Nvenc, HardwareBackend::Vaapi]
         }
         #[cfg(target_os = "macos")]
         {
-            // VideoToolbox is usually available
-            false // Not implemented yet
+            &[HardwareBackend::VideoToolbox]
         }
-        #[cfg(target_os = "linux")]
+        #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
         {
-            // Check for VAAPI
-            false
+            &[]
+        }
+    }
+
+    fn codec_name(self, hevc: bool) -> &'static str {
+        match (self, hevc) {
+            (HardwareBackend::Nvenc, false) => "h264_nvenc",
+            (HardwareBackend::Nvenc, true) => "hevc_nvenc",
+            (HardwareBackend::VideoToolbox, false) => "h264_videotoolbox",
+            (HardwareBackend::VideoToolbox, true) => "hevc_videotoolbox",
+            (HardwareBackend::Vaapi, false) => "h264_vaapi",
+            (HardwareBackend::Vaapi, true) => "hevc_vaapi",
+        }
+    }
+
+    /// Layout the scaler produces before the frame reaches the encoder
+    fn input_format(self) -> ffmpeg::format::Pixel {
+        match self {
+            HardwareBackend::Vaapi => ffmpeg::format::Pixel::NV12,
+            _ => ffmpeg::format::Pixel::YUV420P,
         }
     }
+
+    // Low-latency settings: no B-frames, no lookahead, constant bitrate
+    fn options(self) -> ffmpeg::Dictionary<'static> {
+        let mut options = ffmpeg::Dictionary::new();
+        match self {
+            HardwareBackend::Nvenc => {
+                options.set("preset", "p1");
+                options.set("tune", "ull");
+                options.set("zerolatency", "1");
+                options.set("rc", "cbr");
+            }
+            HardwareBackend::VideoToolbox => {
+                options.set("realtime", "1");
+                options.set("prio_speed", "1");
+                options.set("allow_sw", "0"); // Don't silently fall back to Apple's software encoder
+            }
+            HardwareBackend::Vaapi => {
+                options.set("rc_mode", "CBR");
+                options.set("async_depth", "1");
+            }
+        }
+        options
+    }
+
+    // FFmpeg ships the wrapper even without the GPU, so actually open a tiny session
+    fn probe(self) -> bool {
+        if self == HardwareBackend::Vaapi && !std::path::Path::new(VAAPI_RENDER_NODE).exists() {
+            return false;
+        }
+        open_hardware_encoder(self, false, 256, 256, 1_000_000, 30).is_ok()
+    }
+}
+
+#[cfg(feature = "hwcodec")]
+fn open_hardware_encoder(
+    backend: HardwareBackend,
+    hevc: bool,
+    width: usize,
+    height: usize,
+    bitrate: u32,
+    fps: u32,
+) -> Result<ffmpeg::encoder::video::Encoder, String> {
+    ffmpeg::init().map_err(|e| format!("Failed to initialize FFmpeg: {}", e))?;
+
+    let codec_name = backend.codec_name(hevc);
+    let codec = ffmpeg::encoder::find_by_name(codec_name)
+        .ok_or_else(|| format!("{} not available in this FFmpeg build", codec_name))?;
+
+    let mut video = ffmpeg::codec::context::Context::new_with_codec(codec)
+        .encoder()
+        .video()
+        .map_err(|e| format!("Failed to create {} context: {}", codec_name, e))?;
+
+    video.set_width(width as u32);
+    video.set_height(height as u32);
+    video.set_format(ffmpeg::format::Pixel::YUV420P);
+    video.set_time_base((1, fps as i32));
+    video.set_frame_rate(Some((fps as i32, 1)));
+    video.set_bit_rate(bitrate as usize);
+    video.set_max_b_frames(0);
+    video.set_gop(fps * 2); // Keyframe every 2s so late joiners can start decoding
+
+    if backend == HardwareBackend::Vaapi {
+        unsafe { attach_vaapi_frames(&mut video, width, height)? };
+    }
+
+    video.open_with(backend.options())
+        .map_err(|e| format!("Failed to open {}: {}", codec_name, e))
+}
+
+// VAAPI encodes from GPU surfaces, so the context needs a device and a pool of NV12 frames
+#[cfg(feature = "hwcodec")]
+unsafe fn attach_vaapi_frames(
+    video: &mut ffmpeg::encoder::video::Video,
+    width: usize,
+    height: usize,
+) -> Result<(), String> {
+    use ffmpeg::ffi::*;
+
+    let node = std::ffi::CString::new(VAAPI_RENDER_NODE).unwrap();
+    let mut device: *mut AVBufferRef = std::ptr::null_mut();
+    let ret = av_hwdevice_ctx_create(
+        &mut device,
+        AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI,
+        node.as_ptr(),
+        std::ptr::null_mut(),
+        0,
+    );
+    if ret < 0 {
+        return Err(format!("Failed to open VAAPI device {}: {}", VAAPI_RENDER_NODE, ffmpeg::Error::from(ret)));
+    }
+
+    // The frames context keeps its own reference to the device
+    let mut frames_ref = av_hwframe_ctx_alloc(device);
+    av_buffer_unref(&mut device);
+    if frames_ref.is_null() {
+        return Err("Failed to allocate VAAPI frame pool".to_string());
+    }
+
+    let frames = (*frames_ref).data as *mut AVHWFramesContext;
+    (*frames).format = AVPixelFormat::AV_PIX_FMT_VAAPI;
+    (*frames).sw_format = AVPixelFormat::AV_PIX_FMT_NV12;
+    (*frames).width = width as i32;
+    (*frames).height = height as i32;
+    (*frames).initial_pool_size = 20;
+
+    let ret = av_hwframe_ctx_init(frames_ref);
+    if ret < 0 {
+        av_buffer_unref(&mut frames_ref);
+        return Err(format!("Failed to initialize VAAPI frame pool: {}", ffmpeg::Error::from(ret)));
+    }
+
+    // Ownership moves to the codec context, which unrefs it when freed
+    let context = video.as_mut_ptr();
+    (*context).hw_frames_ctx = frames_ref;
+    (*context).pix_fmt = AVPixelFormat::AV_PIX_FMT_VAAPI;
+    Ok(())
+}
+
+#[cfg(feature = "hwcodec")]
+pub struct H264HardwareEncoder {
+    backend: HardwareBackend,
+    hevc: bool,
+    encoder: ffmpeg::encoder::video::Encoder,
+    scaler: ffmpeg::software::scaling::Context,
+    width: usize,
+    height: usize,
+    bitrate: u32,
+    fps: u32,
+    frame_index: i64,
+    force_keyframe: bool,
+}
+
+// The FFmpeg contexts are only ever touched by the thread that owns the encoder
+#[cfg(feature = "hwcodec")]
+unsafe impl Send for H264HardwareEncoder {}
+
+#[cfg(feature = "hwcodec")]
+impl H264HardwareEncoder {
+    pub fn new(config: &EncoderConfig) -> Result<Self, String> {
+        // Check for hardware encoder availability
+        let backend = Self::detect_backend()
+            .ok_or("Hardware H264 encoder not available")?;
+        let hevc = config.encoder_type == EncoderType::HardwareH265;
+
+        let bitrate = if config.bitrate > 0 {
+            config.bitrate
+        } else {
+            calculate_bitrate(config.width, config.height, config.fps)
+        };
+
+        eprintln!("🎬 Initializing hardware encoder ({})", backend.codec_name(hevc));
+        eprintln!("   Resolution: {}x{}", config.width, config.height);
+        eprintln!("   Bitrate: {} Mbps", bitrate / 1_000_000);
+        eprintln!("   FPS: {}", config.fps);
+
+        let encoder = open_hardware_encoder(backend, hevc, config.width, config.height, bitrate, config.fps)?;
+        let scaler = ffmpeg::software::scaling::Context::get(
+            ffmpeg::format::Pixel::RGBA,
+            config.width as u32,
+            config.height as u32,
+            backend.input_format(),
+            config.width as u32,
+            config.height as u32,
+            ffmpeg::software::scaling::Flags::BILINEAR,
+        ).map_err(|e| format!("Failed to create color converter: {}", e))?;
+
+        Ok(Self {
+            backend,
+            hevc,
+            encoder,
+            scaler,
+            width: config.width,
+            height: config.height,
+            bitrate,
+            fps: config.fps,
+            frame_index: 0,
+            force_keyframe: false,
+        })
+    }
+
+    pub fn is_available() -> bool {
+        Self::detect_backend().is_some()
+    }
+
+    /// First backend that opens successfully, probed once per process
+    pub fn detect_backend() -> Option<HardwareBackend> {
+        static DETECTED: std::sync::OnceLock<Option<HardwareBackend>> = std::sync::OnceLock::new();
+        *DETECTED.get_or_init(|| {
+            HardwareBackend::candidates().iter().copied().find(|b| b.probe())
+        })
+    }
+
+    pub fn backend(&self) -> HardwareBackend {
+        self.backend
+    }
+
+    /// Next frame will be an IDR frame decoders can start from
+    pub fn force_keyframe(&mut self) {
+        self.force_keyframe = true;
+    }
+
+    // Copy a converted frame into a surface from the encoder's VAAPI pool
+    fn upload_to_gpu(&self, frame: &ffmpeg::frame::Video) -> Result<ffmpeg::frame::Video, String> {
+        use ffmpeg::ffi::*;
+
+        let mut surface = ffmpeg::frame::Video::empty();
+        unsafe {
+            let ret = av_hwframe_get_buffer((*self.encoder.as_ptr()).hw_frames_ctx, surface.as_mut_ptr(), 0);
+            if ret < 0 {
+                return Err(format!("Failed to get VAAPI surface: {}", ffmpeg::Error::from(ret)));
+            }
+            let ret = av_hwframe_transfer_data(surface.as_mut_ptr(), frame.as_ptr(), 0);
+            if ret < 0 {
+                return Err(format!("Failed to upload frame to VAAPI: {}", ffmpeg::Error::from(ret)));
+            }
+        }
+        Ok(surface)
+    }
+
+    fn reopen(&mut self) -> Result<(), String> {
+        self.encoder = open_hardware_encoder(self.backend, self.hevc, self.width, self.height, self.bitrate, self.fps)?;
+        self.frame_index = 0;
+        Ok(())
+    }
 }
 
 #[cfg(feature = "hwcodec")]
 impl VideoEncoder for H264HardwareEncoder {
-    fn encode(&mut self, _rgba: &[u8]) -> Result<Vec<u8>, String> {
-        // TODO: Implement hardware encoding
-        // This would use:
-        // - NVENC on NVIDIA GPUs
-        // - QuickSync on Intel
-        // - AMF on AMD
-        // - VideoToolbox on macOS
-        // - VAAPI on Linux
-        Err("Hardware H264 encoding not yet implemented".to_string())
+    fn encode(&mut self, rgba: &[u8]) -> Result<Vec<u8>, String> {
+        let row_bytes = self.width * 4;
+        if rgba.len() < row_bytes * self.height {
+            return Err(format!("Frame buffer too small: {} bytes for {}x{}", rgba.len(), self.width, self.height));
+        }
+
+        // Copy row by row, FFmpeg frames may be padded
+        let mut input = ffmpeg::frame::Video::new(ffmpeg::format::Pixel::RGBA, self.width as u32, self.height as u32);
+        let stride = input.stride(0);
+        let plane = input.data_mut(0);
+        for (y, row) in rgba.chunks_exact(row_bytes).take(self.height).enumerate() {
+            plane[y * stride..y * stride + row_bytes].copy_from_slice(row);
+        }
+
+        let mut yuv = ffmpeg::frame::Video::empty();
+        self.scaler.run(&input, &mut yuv)
+            .map_err(|e| format!("Color conversion failed: {}", e))?;
+        if self.backend == HardwareBackend::Vaapi {
+            yuv = self.upload_to_gpu(&yuv)?;
+        }
+        yuv.set_pts(Some(self.frame_index));
+        if std::mem::take(&mut self.force_keyframe) {
+            yuv.set_kind(ffmpeg::picture::Type::I);
+        }
+        self.frame_index += 1;
+
+        self.encoder.send_frame(&yuv)
+            .map_err(|e| format!("{} encoding failed: {}", self.backend.codec_name(self.hevc), e))?;
+
+        let mut output = Vec::new();
+        let mut packet = ffmpeg::Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            if let Some(data) = packet.data() {
+                output.extend_from_slice(data);
+            }
+        }
+
+        Ok(output)
     }
 
     fn encoder_type(&self) -> EncoderType {
-        EncoderType::HardwareH264
+        if self.hevc {
+            EncoderType::HardwareH265
+        } else {
+            EncoderType::HardwareH264
+        }
     }
 
     fn set_bitrate(&mut self, bitrate: u32) -> Result<(), String> {
+        // Rate control is fixed at open time, so reopen the session
         self.bitrate = bitrate;
-        // TODO: Update hardware encoder bitrate
-        Ok(())
+        self.reopen()
     }
 
     fn set_fps(&mut self, fps: u32) -> Result<(), String> {
         self.fps = fps;
-        // TODO: Update hardware encoder FPS
-        Ok(())
+        self.reopen()
+    }
+
+    fn request_keyframe(&mut self) {
+        self.force_keyframe();
     }
 }
 
@@ -183,8 +779,98 @@ pub fn create_encoder(config: EncoderConfig) -> Result<Box<dyn VideoEncoder>, St
             eprintln!("📹 Using JPEG software encoder (quality: {})", config.quality);
             Ok(Box::new(JpegEncoder::new(&config)?))
         }
+        EncoderType::TileDelta => {
+            eprintln!("📹 Using tile delta encoder (quality: {})", config.quality);
+            Ok(Box::new(crate::tile_delta::TileDeltaVideoEncoder::new(&config)))
+        }
+        EncoderType::Lossless => {
+            eprintln!("📹 Using lossless zstd encoder");
+            Ok(Box::new(crate::lossless::LosslessEncoder::new(&config)?))
+        }
+        EncoderType::Png => {
+            eprintln!("📹 Using PNG encoder");
+            Ok(Box::new(crate::lossless::PngEncoder::new(&config)))
+        }
+        #[cfg(feature = "av1")]
+        EncoderType::Av1 => {
+            match Av1Encoder::new(&config) {
+                Ok(encoder) => {
+                    eprintln!("✅ AV1 encoder initialized");
+                    Ok(Box::new(encoder))
+                }
+                Err(e) => {
+                    eprintln!("⚠️  AV1 encoder failed: {}, falling back to JPEG", e);
+                    let jpeg_config = EncoderConfig {
+                        encoder_type: EncoderType::Software,
+                        ..config
+                    };
+                    Ok(Box::new(JpegEncoder::new(&jpeg_config)?))
+                }
+            }
+        }
+        #[cfg(not(feature = "av1"))]
+        EncoderType::Av1 => {
+            eprintln!("⚠️  AV1 not compiled in, using JPEG");
+            let jpeg_config = EncoderConfig {
+                encoder_type: EncoderType::Software,
+                ..config
+            };
+            Ok(Box::new(JpegEncoder::new(&jpeg_config)?))
+        }
+        #[cfg(feature = "webp")]
+        EncoderType::WebP | EncoderType::WebPLossless => {
+            match WebpEncoder::new(&config) {
+                Ok(encoder) => {
+                    eprintln!("📹 Using WebP encoder ({})", if encoder.lossless { "lossless".to_string() } else { format!("quality: {}", config.quality) });
+                    Ok(Box::new(encoder))
+                }
+                Err(e) => {
+                    eprintln!("⚠️  WebP encoder failed: {}, falling back to JPEG", e);
+                    let jpeg_config = EncoderConfig {
+                        encoder_type: EncoderType::Software,
+                        ..config
+                    };
+                    Ok(Box::new(JpegEncoder::new(&jpeg_config)?))
+                }
+            }
+        }
+        #[cfg(not(feature = "webp"))]
+        EncoderType::WebP | EncoderType::WebPLossless => {
+            eprintln!("⚠️  WebP not compiled in, using JPEG");
+            let jpeg_config = EncoderConfig {
+                encoder_type: EncoderType::Software,
+                ..config
+            };
+            Ok(Box::new(JpegEncoder::new(&jpeg_config)?))
+        }
+        #[cfg(feature = "openh264")]
+        EncoderType::SoftwareH264 => {
+            match OpenH264Encoder::new(&config) {
+                Ok(encoder) => {
+                    eprintln!("✅ OpenH264 encoder initialized");
+                    Ok(Box::new(encoder))
+                }
+                Err(e) => {
+                    eprintln!("⚠️  OpenH264 encoder failed: {}, falling back to JPEG", e);
+                    let jpeg_config = EncoderConfig {
+                        encoder_type: EncoderType::Software,
+                        ..config
+                    };
+                    Ok(Box::new(JpegEncoder::new(&jpeg_config)?))
+                }
+            }
+        }
+        #[cfg(not(feature = "openh264"))]
+        EncoderType::SoftwareH264 => {
+            eprintln!("⚠️  OpenH264 not compiled in, using JPEG");
+            let jpeg_config = EncoderConfig {
+                encoder_type: EncoderType::Software,
+                ..config
+            };
+            Ok(Box::new(JpegEncoder::new(&jpeg_config)?))
+        }
         #[cfg(feature = "hwcodec")]
-        EncoderType::HardwareH264 => {
+        EncoderType::HardwareH264 | EncoderType::HardwareH265 => {
             match H264HardwareEncoder::new(&config) {
                 Ok(encoder) => {
                     eprintln!("✅ Hardware H264 encoder initialized");
@@ -209,14 +895,6 @@ pub fn create_encoder(config: EncoderConfig) -> Result<Box<dyn VideoEncoder>, St
             };
             Ok(Box::new(JpegEncoder::new(&jpeg_config)?))
         }
-        EncoderType::HardwareH265 => {
-            eprintln!("⚠️  H265 not implemented, using JPEG");
-            let jpeg_config = EncoderConfig {
-                encoder_type: EncoderType::Software,
-                ..config
-            };
-            Ok(Box::new(JpegEncoder::new(&jpeg_config)?))
-        }
     }
 }
 
@@ -224,8 +902,8 @@ pub fn create_encoder(config: EncoderConfig) -> Result<Box<dyn VideoEncoder>, St
 pub fn auto_detect_encoder(width: usize, height: usize, fps: u32) -> EncoderConfig {
     #[cfg(feature = "hwcodec")]
     {
-        if H264HardwareEncoder::is_available() {
-            eprintln!("🎯 Auto-detected: Hardware H264 encoder available");
+        if let Some(backend) = H264HardwareEncoder::detect_backend() {
+            eprintln!("🎯 Auto-detected: Hardware H264 encoder available ({:?})", backend);
             return EncoderConfig {
                 width,
                 height,
@@ -233,6 +911,8 @@ pub fn auto_detect_encoder(width: usize, height: usize, fps: u32) -> EncoderConf
                 bitrate: calculate_bitrate(width, height, fps),
                 encoder_type: EncoderType::HardwareH264,
                 quality: 23, // CRF value
+                workers: 1,
+                speed: DEFAULT_ENCODER_SPEED,
             };
         }
     }
@@ -244,7 +924,9 @@ pub fn auto_detect_encoder(width: usize, height: usize, fps: u32) -> EncoderConf
         fps,
         bitrate: 0, // Not used for JPEG
         encoder_type: EncoderType::Software,
-        quality: 70, // JPEG quality
+        quality: DEFAULT_JPEG_QUALITY,
+        workers: 1,
+        speed: DEFAULT_ENCODER_SPEED,
     }
 }
 
@@ -0,0 +1,138 @@
+// Jitter Buffer - hands completed frames on in sequence order
+// A frame that lost a chunk to a slow network path can complete after the
+// one sent next, and shown as they complete the picture jumps back for a
+// frame. Completed frames wait here until every earlier one is out or one of
+// them waited the delay; a frame completing after a later one was shown is
+// dropped, so time never runs backwards on screen. With no delay nothing
+// waits and only the late frames go.
+//
+// Frames are ordered by `sequence` (see packet.rs), which wraps around.
+
+use std::time::{Duration, Instant};
+
+/// Longest wait, well under the time incomplete frames are kept
+pub const MAX_DELAY_MS: u64 = 200;
+
+struct Held<K> {
+    sequence: u32,
+    key: K,
+    since: Instant,
+}
+
+pub struct JitterBuffer<K> {
+    delay: Duration,
+    held: Vec<Held<K>>,
+    /// Sequence of the last frame handed on
+    released: Option<u32>,
+}
+
+impl<K: Copy + PartialEq> JitterBuffer<K> {
+    pub fn new() -> Self {
+        Self { delay: Duration::ZERO, held: Vec::new(), released: None }
+    }
+
+    /// How long a frame waits for the ones before it, capped at MAX_DELAY_MS
+    pub fn set_delay(&mut self, delay_ms: u64) {
+        self.delay = Duration::from_millis(delay_ms.min(MAX_DELAY_MS));
+    }
+
+    pub fn holds(&self, key: &K) -> bool {
+        self.held.iter().any(|held| held.key == *key)
+    }
+
+    /// Queue a completed frame; false when a later frame was already handed on
+    pub fn push(&mut self, sequence: u32, key: K, now: Instant) -> bool {
+        if self.released.is_some_and(|released| !is_after(sequence, released)) {
+            return false;
+        }
+        if !self.holds(&key) {
+            self.held.push(Held { sequence, key, since: now });
+        }
+        true
+    }
+
+    /// Frames that may be shown now, in sequence order
+    pub fn release(&mut self, now: Instant) -> Vec<K> {
+        let mut ready = Vec::new();
+        while let Some(index) = self.earliest() {
+            let sequence = self.held[index].sequence;
+            let next_in_line = self.released.is_some_and(|released| sequence == released.wrapping_add(1));
+            // Whatever is missing won't make it in time once anything waited the delay
+            let gave_up = self.held.iter().any(|held| now.duration_since(held.since) >= self.delay);
+            if !next_in_line && !gave_up {
+                break;
+            }
+            let held = self.held.swap_remove(index);
+            self.released = Some(held.sequence);
+            ready.push(held.key);
+        }
+        ready
+    }
+
+    /// Sequence numbers start over (new session, other simulcast layer)
+    pub fn reset(&mut self) {
+        self.held.clear();
+        self.released = None;
+    }
+
+    fn earliest(&self) -> Option<usize> {
+        (0..self.held.len()).reduce(|a, b| {
+            if is_after(self.held[a].sequence, self.held[b].sequence) { b } else { a }
+        })
+    }
+}
+
+fn is_after(sequence: u32, other: u32) -> bool {
+    (sequence.wrapping_sub(other) as i32) > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_leave_in_order() {
+        let start = Instant::now();
+        let mut jitter = JitterBuffer::new();
+        jitter.set_delay(50);
+
+        // The first frame waits, nothing tells it apart from a late one yet
+        assert!(jitter.push(u32::MAX, 'a', start));
+        assert!(jitter.release(start).is_empty());
+        assert_eq!(jitter.release(start + Duration::from_millis(50)), vec!['a']);
+
+        // 1 completes before 0, across the wraparound
+        assert!(jitter.push(1, 'c', start + Duration::from_millis(60)));
+        assert!(jitter.release(start + Duration::from_millis(60)).is_empty());
+        assert!(jitter.push(0, 'b', start + Duration::from_millis(70)));
+        assert_eq!(jitter.release(start + Duration::from_millis(70)), vec!['b', 'c']);
+
+        // 2 never completes, 3 goes once it waited; 2 is late after that
+        assert!(jitter.push(3, 'e', start + Duration::from_millis(80)));
+        assert!(jitter.release(start + Duration::from_millis(100)).is_empty());
+        assert_eq!(jitter.release(start + Duration::from_millis(130)), vec!['e']);
+        assert!(!jitter.push(2, 'd', start + Duration::from_millis(140)));
+
+        // Without a delay frames go as they complete, late ones still don't
+        jitter.set_delay(0);
+        assert!(jitter.push(5, 'g', start + Duration::from_millis(150)));
+        assert_eq!(jitter.release(start + Duration::from_millis(150)), vec!['g']);
+        assert!(!jitter.push(4, 'f', start + Duration::from_millis(150)));
+    }
+    #[test]
+    fn test_held_frame_goes_without_more_frames() {
+        let start = Instant::now();
+        let mut jitter = JitterBuffer::new();
+        jitter.set_delay(40);
+        assert!(jitter.push(0, 'a', start));
+        assert_eq!(jitter.release(start + Duration::from_millis(40)), vec!['a']);
+
+        // 1 never completes and nothing else comes in, the receive loop asks
+        // again each time it wakes up
+        assert!(jitter.push(2, 'c', start + Duration::from_millis(50)));
+        assert!(jitter.release(start + Duration::from_millis(50)).is_empty());
+        assert!(jitter.release(start + Duration::from_millis(70)).is_empty());
+        assert_eq!(jitter.release(start + Duration::from_millis(90)), vec!['c']);
+        assert!(jitter.release(start + Duration::from_millis(290)).is_empty());
+    }
+}
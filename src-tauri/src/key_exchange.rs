@@ -0,0 +1,238 @@
+// Key Exchange - hands out the stream key to viewers that know the PIN
+// Instead of everyone typing the same long passphrase, the server picks a
+// fresh random stream key every session and viewers fetch it over TCP with a
+// SPAKE2 handshake keyed by a short PIN. SPAKE2 never reveals the PIN, even
+// to a recorded handshake, so an attacker only gets one online guess per
+// connection; failed attempts are slowed down on top of that.
+//
+// Handshake (viewer = A, server = B):
+//   A -> B  "KEYX" | version u8 | SPAKE2 message A (33 bytes)
+//   B -> A  SPAKE2 message B (33 bytes)
+//   A -> B  sealed(empty) under the shared key, proves A knows the PIN
+//   B -> A  sealed(stream key) under the shared key
+// Both sealed messages bind the whole transcript as associated data.
+//
+// A restarted server has a new key (and a new epoch), so viewers run the
+// handshake again whenever the session changes.
+
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+use crate::stream_crypto::{StreamCipher, KEY_SIZE, OVERHEAD};
+
+pub const KEY_EXCHANGE_PORT: u16 = 9997;
+const MAGIC: &[u8; 4] = b"KEYX";
+const VERSION: u8 = 1;
+const SPAKE_MESSAGE_SIZE: usize = 33;
+const HANDSHAKE_TIMEOUT_MS: u64 = 3000;
+const ACCEPT_INTERVAL_MS: u64 = 200;
+const FAILED_ATTEMPT_DELAY_MS: u64 = 1000;
+const RETRY_INTERVAL_MS: u64 = 5000;
+const VIEWER_ID: &[u8] = b"smartlab-viewer";
+const SERVER_ID: &[u8] = b"smartlab-server";
+
+/// Serves the current session's stream key, stopped on `stop()` or drop
+pub struct KeyExchangeServer {
+    running: Arc<AtomicBool>,
+}
+
+impl KeyExchangeServer {
+    pub fn start(port: u16, pin: &str, stream_key: [u8; KEY_SIZE]) -> Result<Self, String> {
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .map_err(|e| format!("Failed to bind key exchange on port {}: {}", port, e))?;
+        listener.set_nonblocking(true)
+            .map_err(|e| format!("Failed to configure key exchange listener: {}", e))?;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let pin = pin.to_string();
+        // Handshakes run one at a time, so the failure delay also holds back parallel guessing
+        std::thread::spawn(move || {
+            while thread_running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((mut stream, addr)) => match serve_handshake(&mut stream, &pin, &stream_key) {
+                        Ok(()) => eprintln!("🔑 Stream key sent to {}", addr),
+                        Err(e) => {
+                            eprintln!("🔑 Key exchange with {} failed: {}", addr, e);
+                            std::thread::sleep(Duration::from_millis(FAILED_ATTEMPT_DELAY_MS));
+                        }
+                    },
+                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(ACCEPT_INTERVAL_MS));
+                    }
+                    Err(e) => eprintln!("❌ Key exchange accept error: {}", e),
+                }
+            }
+        });
+
+        eprintln!("🔑 Key exchange listening on port {}", port);
+        Ok(Self { running })
+    }
+
+    pub fn stop(&self) {
+        if self.running.swap(false, Ordering::Relaxed) {
+            eprintln!("🔴 Key exchange stopped");
+        }
+    }
+}
+
+impl Drop for KeyExchangeServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn serve_handshake(stream: &mut TcpStream, pin: &str, stream_key: &[u8; KEY_SIZE]) -> Result<(), String> {
+    configure(stream)?;
+
+    let mut hello = [0u8; MAGIC.len() + 1 + SPAKE_MESSAGE_SIZE];
+    stream.read_exact(&mut hello).map_err(|e| e.to_string())?;
+    if &hello[..MAGIC.len()] != MAGIC {
+        return Err("not a key exchange request".to_string());
+    }
+    if hello[MAGIC.len()] != VERSION {
+        return Err(format!("unsupported version {}", hello[MAGIC.len()]));
+    }
+    let message_a = &hello[MAGIC.len() + 1..];
+
+    let (spake, message_b) = Spake2::<Ed25519Group>::start_b(
+        &Password::new(pin.as_bytes()),
+        &Identity::new(VIEWER_ID),
+        &Identity::new(SERVER_ID),
+    );
+    stream.write_all(&message_b).map_err(|e| e.to_string())?;
+    let cipher = session_cipher(spake.finish(message_a).map_err(|e| format!("{:?}", e))?)?;
+    let transcript = transcript(message_a, &message_b);
+
+    let mut confirmation = [0u8; OVERHEAD];
+    stream.read_exact(&mut confirmation).map_err(|e| e.to_string())?;
+    cipher.open(&[transcript.as_slice(), b"viewer"].concat(), &confirmation)
+        .map_err(|_| "wrong PIN".to_string())?;
+
+    let sealed = cipher.seal(&[transcript.as_slice(), b"key"].concat(), stream_key)?;
+    stream.write_all(&sealed).map_err(|e| e.to_string())
+}
+
+/// Run the handshake against `server` and return the stream cipher it hands out
+pub fn request_key(server: SocketAddr, pin: &str) -> Result<StreamCipher, String> {
+    let mut stream = TcpStream::connect_timeout(&server, Duration::from_millis(HANDSHAKE_TIMEOUT_MS))
+        .map_err(|e| format!("Failed to connect key exchange to {}: {}", server, e))?;
+    configure(&mut stream)?;
+    let io_error = |e: std::io::Error| format!("Key exchange with {} failed: {}", server, e);
+
+    let (spake, message_a) = Spake2::<Ed25519Group>::start_a(
+        &Password::new(pin.as_bytes()),
+        &Identity::new(VIEWER_ID),
+        &Identity::new(SERVER_ID),
+    );
+    stream.write_all(&[MAGIC.as_slice(), &[VERSION], &message_a].concat()).map_err(io_error)?;
+
+    let mut message_b = [0u8; SPAKE_MESSAGE_SIZE];
+    stream.read_exact(&mut message_b).map_err(io_error)?;
+    let cipher = session_cipher(spake.finish(&message_b).map_err(|e| format!("Invalid key exchange reply: {:?}", e))?)?;
+    let transcript = transcript(&message_a, &message_b);
+
+    let confirmation = cipher.seal(&[transcript.as_slice(), b"viewer"].concat(), &[])?;
+    stream.write_all(&confirmation).map_err(io_error)?;
+
+    // The server hangs up without a key when the PIN is wrong
+    let mut sealed = [0u8; KEY_SIZE + OVERHEAD];
+    stream.read_exact(&mut sealed).map_err(|_| "Key exchange rejected (wrong PIN?)".to_string())?;
+    let key = cipher.open(&[transcript.as_slice(), b"key"].concat(), &sealed)
+        .map_err(|_| "Key exchange reply could not be verified (wrong PIN?)".to_string())?;
+    let key: [u8; KEY_SIZE] = key.try_into().map_err(|_| "Invalid stream key length".to_string())?;
+    Ok(StreamCipher::from_key(&key))
+}
+
+fn configure(stream: &mut TcpStream) -> Result<(), String> {
+    let timeout = Some(Duration::from_millis(HANDSHAKE_TIMEOUT_MS));
+    // Accepted sockets may inherit the listener's non-blocking mode
+    stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(timeout).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(timeout).map_err(|e| e.to_string())?;
+    let _ = stream.set_nodelay(true);
+    Ok(())
+}
+
+// SPAKE2 output is already a hash of the transcript and the PIN
+fn session_cipher(shared: Vec<u8>) -> Result<StreamCipher, String> {
+    let key: [u8; KEY_SIZE] = shared.try_into().map_err(|_| "Invalid shared key length".to_string())?;
+    Ok(StreamCipher::from_key(&key))
+}
+
+fn transcript(message_a: &[u8], message_b: &[u8]) -> Vec<u8> {
+    [MAGIC.as_slice(), &[VERSION], message_a, message_b].concat()
+}
+
+/// Fetches the stream key in the background, one handshake at a time
+pub struct KeyFetcher {
+    busy: Arc<AtomicBool>,
+    last_attempt: Option<Instant>,
+}
+
+impl KeyFetcher {
+    pub fn new() -> Self {
+        Self {
+            busy: Arc::new(AtomicBool::new(false)),
+            last_attempt: None,
+        }
+    }
+
+    /// Start a handshake with `server` unless one is in flight. Without `force`
+    /// (a new session always forces) recent attempts are not repeated.
+    pub fn fetch<F>(&mut self, server: IpAddr, pin: String, force: bool, on_key: F)
+    where
+        F: FnOnce(StreamCipher) + Send + 'static,
+    {
+        if !force && self.last_attempt.is_some_and(|t| t.elapsed() < Duration::from_millis(RETRY_INTERVAL_MS)) {
+            return;
+        }
+        if self.busy.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.last_attempt = Some(Instant::now());
+
+        let busy = self.busy.clone();
+        std::thread::spawn(move || {
+            match request_key(SocketAddr::new(server, KEY_EXCHANGE_PORT), &pin) {
+                Ok(cipher) => {
+                    eprintln!("🔑 Received stream key from {}", server);
+                    on_key(cipher);
+                }
+                Err(e) => eprintln!("❌ {}", e),
+            }
+            busy.store(false, Ordering::Release);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_delivers_stream_key() {
+        let stream_key = [7u8; KEY_SIZE];
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let results: Vec<_> = (0..2)
+                .map(|_| serve_handshake(&mut listener.accept().unwrap().0, "482913", &stream_key))
+                .collect();
+            results
+        });
+
+        let cipher = request_key(addr, "482913").unwrap();
+        let expected = StreamCipher::from_key(&stream_key);
+        assert_eq!(expected.open(b"h", &cipher.seal(b"h", b"frame").unwrap()).unwrap(), b"frame");
+
+        assert!(request_key(addr, "000000").is_err());
+
+        let results = server.join().unwrap();
+        assert!(results[0].is_ok());
+        assert_eq!(results[1], Err("wrong PIN".to_string()));
+    }
+}
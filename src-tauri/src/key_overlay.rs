@@ -0,0 +1,337 @@
+// Key Overlay - shows the presenter's keystrokes on the stream
+// For tutorials: shortcuts ("Ctrl+C") and typed text appear in the bottom-left
+// corner for a few seconds, burned into the frames before they are encoded.
+//
+// Keys are picked up with a global hook (rdev). The hook can't be removed
+// again, so it is installed once and simply ignored while no overlay runs.
+//
+// Privacy mode masks typed characters when focus looks like a password
+// field. Windows checks the focused control for ES_PASSWORD and the window
+// title for login wording; elsewhere focus can't be inspected, so all typed
+// characters are masked and only shortcuts and named keys are shown.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, Once};
+use std::time::{Duration, Instant};
+use crate::screen_capture::RawFrame;
+use crate::settings::KeyOverlaySettings;
+use crate::text_render::TextRenderer;
+
+const MAX_ENTRIES: usize = 5;
+const ENTRY_LIFETIME_MS: u64 = 2500;
+/// Characters typed within this gap of each other join one line
+const TYPING_GAP_MS: u64 = 1000;
+const MAX_LINE_CHARS: usize = 24;
+const MASK_CHAR: char = '*';
+const MARGIN: usize = 16;
+const PADDING: usize = 6;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static PRIVACY: AtomicBool = AtomicBool::new(true);
+static HOOK: Once = Once::new();
+static LOG: Mutex<KeyLog> = Mutex::new(KeyLog::new());
+
+/// Overlay for one stream session, the hook goes quiet again on drop
+pub struct KeyOverlay {
+    text: TextRenderer,
+}
+
+impl KeyOverlay {
+    /// `locale` picks fallback fonts for typed text in non-Latin scripts
+    pub fn start(settings: &KeyOverlaySettings, locale: Option<&str>) -> Self {
+        PRIVACY.store(settings.privacy_mode, Ordering::Relaxed);
+        LOG.lock().unwrap().clear();
+        ACTIVE.store(true, Ordering::Relaxed);
+
+        HOOK.call_once(|| {
+            std::thread::spawn(|| {
+                // Only returns if the hook could not be installed
+                if let Err(e) = rdev::listen(on_event) {
+                    eprintln!("❌ Key overlay unavailable, could not listen to the keyboard: {:?}", e);
+                }
+            });
+        });
+        eprintln!("⌨️  Key overlay enabled{}", if settings.privacy_mode { " (privacy mode)" } else { "" });
+        Self { text: TextRenderer::for_locale(locale) }
+    }
+
+    /// Burn the recent keystrokes into `frame`
+    pub fn draw(&self, frame: &mut RawFrame) {
+        let lines = LOG.lock().unwrap().visible_lines();
+        if lines.is_empty() {
+            return;
+        }
+        draw_lines(frame, &lines, &self.text);
+    }
+}
+
+impl Drop for KeyOverlay {
+    fn drop(&mut self) {
+        ACTIVE.store(false, Ordering::Relaxed);
+        LOG.lock().unwrap().clear();
+    }
+}
+
+struct Entry {
+    text: String,
+    /// Typed text that further characters may be appended to
+    typing: bool,
+    at: Instant,
+}
+
+struct KeyLog {
+    entries: VecDeque<Entry>,
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    meta: bool,
+}
+
+impl KeyLog {
+    const fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            ctrl: false,
+            alt: false,
+            shift: false,
+            meta: false,
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    fn modifier(&mut self, key: rdev::Key, pressed: bool) -> bool {
+        use rdev::Key::*;
+        match key {
+            ControlLeft | ControlRight => self.ctrl = pressed,
+            Alt | AltGr => self.alt = pressed,
+            ShiftLeft | ShiftRight => self.shift = pressed,
+            MetaLeft | MetaRight => self.meta = pressed,
+            _ => return false,
+        }
+        true
+    }
+
+    fn press(&mut self, key: rdev::Key, name: Option<&str>, now: Instant) {
+        let typed = name
+            .and_then(|n| {
+                let mut chars = n.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if !c.is_control() && c != ' ' => Some(c),
+                    _ => None,
+                }
+            })
+            .filter(|_| !(self.ctrl || self.alt || self.meta));
+
+        if let Some(c) = typed {
+            let c = if PRIVACY.load(Ordering::Relaxed) && focus_is_private() { MASK_CHAR } else { c };
+            self.type_char(c, now);
+            return;
+        }
+
+        let mut label = String::new();
+        for (held, name) in [(self.ctrl, "Ctrl"), (self.alt, "Alt"), (self.shift, "Shift"), (self.meta, META_NAME)] {
+            if held {
+                label.push_str(name);
+                label.push('+');
+            }
+        }
+        label.push_str(&key_name(key, name));
+        self.push(Entry { text: label, typing: false, at: now });
+    }
+
+    fn type_char(&mut self, c: char, now: Instant) {
+        if let Some(last) = self.entries.back_mut() {
+            if last.typing && now.duration_since(last.at) < Duration::from_millis(TYPING_GAP_MS) {
+                last.text.push(c);
+                let excess = last.text.chars().count().saturating_sub(MAX_LINE_CHARS);
+                if excess > 0 {
+                    last.text = last.text.chars().skip(excess).collect();
+                }
+                last.at = now;
+                return;
+            }
+        }
+        self.push(Entry { text: c.to_string(), typing: true, at: now });
+    }
+
+    fn push(&mut self, entry: Entry) {
+        self.entries.push_back(entry);
+        while self.entries.len() > MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+    }
+
+    fn visible_lines(&mut self) -> Vec<String> {
+        let lifetime = Duration::from_millis(ENTRY_LIFETIME_MS);
+        self.entries.retain(|e| e.at.elapsed() < lifetime);
+        self.entries.iter().map(|e| e.text.clone()).collect()
+    }
+}
+
+fn on_event(event: rdev::Event) {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut log = LOG.lock().unwrap();
+    match event.event_type {
+        rdev::EventType::KeyPress(key) => {
+            if !log.modifier(key, true) {
+                log.press(key, event.name.as_deref(), Instant::now());
+            }
+        }
+        rdev::EventType::KeyRelease(key) => {
+            log.modifier(key, false);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(target_os = "macos")]
+const META_NAME: &str = "Cmd";
+#[cfg(not(target_os = "macos"))]
+const META_NAME: &str = "Win";
+
+fn key_name(key: rdev::Key, name: Option<&str>) -> String {
+    use rdev::Key::*;
+    let named = match key {
+        Return | KpReturn => "Enter",
+        Backspace => "Backspace",
+        Tab => "Tab",
+        Escape => "Esc",
+        Space => "Space",
+        Delete => "Del",
+        Home => "Home",
+        End => "End",
+        PageUp => "PgUp",
+        PageDown => "PgDn",
+        LeftArrow => "Left",
+        RightArrow => "Right",
+        UpArrow => "Up",
+        DownArrow => "Down",
+        F1 => "F1",
+        F2 => "F2",
+        F3 => "F3",
+        F4 => "F4",
+        F5 => "F5",
+        F6 => "F6",
+        F7 => "F7",
+        F8 => "F8",
+        F9 => "F9",
+        F10 => "F10",
+        F11 => "F11",
+        F12 => "F12",
+        _ => {
+            // Shortcut letters read better in capitals ("Ctrl+C")
+            return match name.filter(|n| n.chars().all(|c| !c.is_control()) && !n.is_empty()) {
+                Some(n) => n.to_uppercase(),
+                None => format!("{:?}", key).trim_start_matches("Key").to_string(),
+            };
+        }
+    };
+    named.to_string()
+}
+
+#[cfg(target_os = "windows")]
+fn focus_is_private() -> bool {
+    use windows::Win32::UI::WindowsAndMessaging::*;
+
+    // Edit controls created with ES_PASSWORD
+    const ES_PASSWORD: i32 = 0x0020;
+    const LOGIN_WORDS: [&str; 6] = ["password", "mật khẩu", "sign in", "log in", "login", "đăng nhập"];
+
+    unsafe {
+        let foreground = GetForegroundWindow();
+        let thread = GetWindowThreadProcessId(foreground, None);
+        let mut info = GUITHREADINFO {
+            cbSize: std::mem::size_of::<GUITHREADINFO>() as u32,
+            ..Default::default()
+        };
+        if GetGUIThreadInfo(thread, &mut info).is_ok() && !info.hwndFocus.is_invalid() {
+            let mut class = [0u16; 64];
+            let len = GetClassNameW(info.hwndFocus, &mut class) as usize;
+            let class = String::from_utf16_lossy(&class[..len]);
+            if class.eq_ignore_ascii_case("Edit") && GetWindowLongW(info.hwndFocus, GWL_STYLE) & ES_PASSWORD != 0 {
+                return true;
+            }
+        }
+
+        // Browsers draw their own fields, so fall back to the page/window title
+        let mut title = [0u16; 512];
+        let len = GetWindowTextW(foreground, &mut title) as usize;
+        let title = String::from_utf16_lossy(&title[..len]).to_lowercase();
+        LOGIN_WORDS.iter().any(|word| title.contains(word))
+    }
+}
+
+// Focus can't be inspected here, so every typed character counts as private
+#[cfg(not(target_os = "windows"))]
+fn focus_is_private() -> bool {
+    true
+}
+
+fn draw_lines(frame: &mut RawFrame, lines: &[String], text: &TextRenderer) {
+    // Readable on anything from 720p to 4K
+    let px = (frame.height / 45).clamp(16, 40);
+    let line_height = text.line_height(px) + PADDING * 2;
+
+    let mut bottom = frame.height.saturating_sub(MARGIN);
+    for line in lines.iter().rev() {
+        let width = text.measure(line, px).width + PADDING * 2;
+        let top = bottom.saturating_sub(line_height);
+        fill_rect(frame, MARGIN, top, width, line_height);
+        text.draw(frame, line, MARGIN + PADDING, top + PADDING, px, [255, 255, 255]);
+        bottom = top.saturating_sub(PADDING);
+    }
+}
+
+// Dark, mostly opaque box behind the text
+fn fill_rect(frame: &mut RawFrame, x: usize, y: usize, width: usize, height: usize) {
+    for row in y..(y + height).min(frame.height) {
+        for col in x..(x + width).min(frame.width) {
+            let i = (row * frame.width + col) * 4;
+            for channel in &mut frame.rgba[i..i + 3] {
+                *channel /= 4;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shortcuts_and_typing() {
+        PRIVACY.store(false, Ordering::Relaxed);
+        let mut log = KeyLog::new();
+        let now = Instant::now();
+
+        log.modifier(rdev::Key::ControlLeft, true);
+        log.press(rdev::Key::KeyC, Some("\u{3}"), now);
+        log.modifier(rdev::Key::ControlLeft, false);
+        log.press(rdev::Key::KeyH, Some("h"), now);
+        log.press(rdev::Key::KeyI, Some("i"), now + Duration::from_millis(200));
+        log.press(rdev::Key::Return, Some("\r"), now + Duration::from_millis(300));
+
+        let lines: Vec<_> = log.entries.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(lines, ["Ctrl+C", "hi", "Enter"]);
+    }
+
+    #[test]
+    fn test_draw_stays_inside_small_frames() {
+        let mut frame = RawFrame {
+            rgba: vec![200; 64 * 48 * 4],
+            width: 64,
+            height: 48,
+            captured_at: Instant::now(),
+            dirty: None,
+            moves: Vec::new(),
+        };
+        draw_lines(&mut frame, &["Ctrl+Shift+Esc".to_string()], &TextRenderer::bitmap());
+        assert!(frame.rgba.iter().any(|&b| b != 200));
+    }
+}
@@ -0,0 +1,176 @@
+// Latest Frame - the viewer's newest frame at stream://localhost/latest-frame
+// The frame channel pushes every frame to the main window. Anything that only
+// wants a look now and then (an <img>, a thumbnail, another window) fetches
+// this URL instead, when the tiny "frame-ready" event says there is something
+// new or at its own pace. Tile deltas are kept as they arrive and only drawn
+// onto the last full frame when somebody asks, so nobody asking costs nothing.
+//
+// On Windows the webview reaches custom protocols as http://stream.localhost/;
+// `convertFileSrc("latest-frame", "stream")` gives the right URL either way.
+
+use std::sync::Mutex;
+use tauri::http::{header, Response, StatusCode};
+use crate::jpeg;
+use crate::tile_delta;
+
+pub const SCHEME: &str = "stream";
+const PATH: &str = "latest-frame";
+// Compositing pays off before deltas pile up for a page nobody looks at
+const MAX_PENDING_TILES: usize = 64;
+const COMPOSITE_QUALITY: u8 = 90;
+
+#[derive(Default)]
+struct Latest {
+    /// Counts every frame, so pollers can tell whether they saw this one
+    id: u64,
+    /// Last full frame as an image file (JPEG, PNG or WebP)
+    image: Option<Vec<u8>>,
+    /// "TILE" payloads received since, oldest first
+    pending: Vec<Vec<u8>>,
+}
+
+/// Newest frame the viewer received, shared with the protocol handler
+#[derive(Default)]
+pub struct LatestFrame {
+    latest: Mutex<Latest>,
+}
+
+impl LatestFrame {
+    /// A full frame arrived; returns its ID
+    pub fn set_image(&self, image: &[u8]) -> u64 {
+        let mut latest = self.latest.lock().unwrap();
+        latest.id += 1;
+        latest.image = Some(image.to_vec());
+        latest.pending.clear();
+        latest.id
+    }
+
+    /// A tile delta arrived; returns its ID, `None` while there is no frame to draw it on
+    pub fn add_tiles(&self, payload: &[u8]) -> Option<u64> {
+        let mut latest = self.latest.lock().unwrap();
+        latest.image.as_ref()?;
+        latest.id += 1;
+        latest.pending.push(payload.to_vec());
+        if latest.pending.len() > MAX_PENDING_TILES {
+            composite(&mut latest);
+        }
+        Some(latest.id)
+    }
+
+    pub fn clear(&self) {
+        *self.latest.lock().unwrap() = Latest::default();
+    }
+
+    /// The newest frame with its ID and content type, tiles drawn in
+    pub fn get(&self) -> Option<(u64, Vec<u8>, &'static str)> {
+        let mut latest = self.latest.lock().unwrap();
+        composite(&mut latest);
+        let image = latest.image.clone()?;
+        let content_type = content_type(&image);
+        Some((latest.id, image, content_type))
+    }
+}
+
+// Draw the pending tiles onto the full frame, which becomes a JPEG again
+fn composite(latest: &mut Latest) {
+    if latest.pending.is_empty() {
+        return;
+    }
+    let pending = std::mem::take(&mut latest.pending);
+    let Some(image) = latest.image.as_deref() else {
+        return;
+    };
+    let result = (|| {
+        let mut canvas = image::load_from_memory(image)
+            .map_err(|e| format!("Failed to decode the latest frame: {}", e))?
+            .to_rgba8();
+        let width = canvas.width() as usize;
+        for payload in &pending {
+            let update = tile_delta::parse(payload)?;
+            if (update.width as u32, update.height as u32) != canvas.dimensions() {
+                continue;
+            }
+            for copy in &update.copies {
+                copy.apply(&mut canvas, width, 4);
+            }
+            for tile in &update.tiles {
+                let tile_image = image::load_from_memory_with_format(&tile.jpeg, image::ImageFormat::Jpeg)
+                    .map_err(|e| format!("Failed to decode a tile: {}", e))?;
+                image::imageops::replace(&mut canvas, &tile_image.to_rgba8(), tile.x as i64, tile.y as i64);
+            }
+        }
+        let (width, height) = (canvas.width() as usize, canvas.height() as usize);
+        jpeg::encode(canvas.as_raw(), width, height, jpeg::Layout::Rgba, COMPOSITE_QUALITY)
+    })();
+    match result {
+        Ok(jpeg) => latest.image = Some(jpeg),
+        // The full frame as it was beats none at all
+        Err(e) => eprintln!("⚠️  Latest frame shown without its tiles: {}", e),
+    }
+}
+
+fn content_type(image: &[u8]) -> &'static str {
+    if image.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if image.len() >= 12 && image.starts_with(b"RIFF") && &image[8..12] == b"WEBP" {
+        "image/webp"
+    } else {
+        "image/jpeg"
+    }
+}
+
+/// Answer a stream:// request, 204 until the first frame arrived
+pub fn respond(latest: &LatestFrame, path: &str) -> Response<Vec<u8>> {
+    let response = Response::builder()
+        .header(header::CACHE_CONTROL, "no-store")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+    let response = if path.trim_matches('/') != PATH {
+        response.status(StatusCode::NOT_FOUND).body(Vec::new())
+    } else {
+        match latest.get() {
+            Some((id, image, content_type)) => response
+                .header(header::CONTENT_TYPE, content_type)
+                .header("X-Frame-Id", id.to_string())
+                .body(image),
+            None => response.status(StatusCode::NO_CONTENT).body(Vec::new()),
+        }
+    };
+    response.unwrap_or_else(|_| Response::new(Vec::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+    use crate::tile_delta::TileDeltaEncoder;
+
+    #[test]
+    fn test_tiles_are_drawn_when_asked() {
+        let latest = LatestFrame::default();
+        assert!(latest.get().is_none());
+        assert!(latest.add_tiles(b"TILE").is_none());
+
+        let mut encoder = TileDeltaEncoder::new();
+        let mut img = RgbImage::from_pixel(256, 64, Rgb([20, 20, 20]));
+        let full = encoder.encode(&img, 90).unwrap().unwrap();
+        assert!(!tile_delta::is_tile_payload(&full));
+        assert_eq!(latest.set_image(&full), 1);
+
+        for y in 0..32 {
+            for x in 192..256 {
+                img.put_pixel(x, y, Rgb([230, 230, 230]));
+            }
+        }
+        let delta = encoder.encode(&img, 90).unwrap().unwrap();
+        assert!(tile_delta::is_tile_payload(&delta));
+        assert_eq!(latest.add_tiles(&delta), Some(2));
+
+        let (id, jpeg, content_type) = latest.get().unwrap();
+        assert_eq!((id, content_type), (2, "image/jpeg"));
+        let shown = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+        assert!(shown.get_pixel(220, 10).0[0] > 200);
+        assert!(shown.get_pixel(10, 50).0[0] < 50);
+        assert_eq!(respond(&latest, "/nothing").status(), StatusCode::NOT_FOUND);
+        assert_eq!(respond(&latest, "/latest-frame").body(), &jpeg);
+    }
+}
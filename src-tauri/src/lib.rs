@@ -4,15 +4,73 @@ mod udp_client;
 mod frame_pacer;
 mod cursor_capture;
 mod hw_encoder;
+mod settings;
+mod control_api;
+mod tile_delta;
+mod ws_receiver;
+mod video_decoder;
+mod black_frame;
+mod frame_source;
+mod preview;
+mod packet;
+mod trigger;
+mod clock;
+mod audio_capture;
+mod audio_playback;
+mod power;
+mod performance;
+mod remote_input;
+mod clipboard_sync;
+mod stream_crypto;
+mod key_exchange;
+mod virtual_display;
+mod key_overlay;
+mod text_render;
+mod access_control;
+mod discovery;
+mod net_interfaces;
+mod calibration;
+mod tcp_transport;
+mod stats;
+mod quic_transport;
+mod simulcast;
+mod webrtc_output;
+mod rtp_output;
+mod mjpeg_server;
+mod capture_format;
+mod chunk_heatmap;
+mod recording;
+mod capture_manager;
+mod screenshot;
+mod frame_validation;
+mod window_capture;
+mod recording_crypto;
+mod display_streams;
+mod session_timer;
+mod title_card;
+mod rooms;
+mod cursor_stream;
+mod pixel_convert;
+mod jpeg;
+mod lossless;
+mod latest_frame;
+mod peers;
+mod jitter_buffer;
+mod bandwidth_limit;
+mod encode_pool;
 
 #[cfg(all(target_os = "windows", feature = "dxgi"))]
 mod windows_capture;
 #[cfg(all(target_os = "windows", feature = "dxgi"))]
 mod dxgi_capture;
+#[cfg(all(target_os = "linux", feature = "wayland"))]
+mod wayland_capture;
 
-use tauri::State;
-use std::sync::Mutex;
+use tauri::{Emitter, Manager, Runtime, State};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use serde::Serialize;
+use settings::{StreamSettings, ViewerSettings};
 
 #[derive(Serialize)]
 struct DisplayInfo {
@@ -21,35 +79,288 @@ struct DisplayInfo {
     height: usize,
 }
 
+#[derive(Serialize)]
+struct StatusSnapshot {
+    server_running: bool,
+    client_running: bool,
+    /// PIN viewers enter to ask for approval, when the server requires it
+    session_pin: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ControlApiInfo {
+    port: u16,
+    token: String,
+}
+
+/// Where a started server takes its frames from
+#[derive(Clone, Copy)]
+enum CaptureInput {
+    Screen,
+    /// Generated frames of a fixed size, for running without a display
+    #[cfg_attr(not(test), allow(dead_code))]
+    Synthetic { width: usize, height: usize },
+}
+
 struct AppState {
+    capture_input: CaptureInput,
+    capture: capture_manager::CaptureManager,
+    /// Shared instead of the whole display, when set
+    window: Mutex<Option<window_capture::WindowSelector>>,
     server: Mutex<Option<udp_server::UdpServer>>,
     client: Mutex<Option<udp_client::UdpClient>>,
+    /// Where the viewer's frames go, the webview subscribes on load
+    frames: udp_client::FrameChannel,
+    /// Served at stream://localhost/latest-frame
+    latest_frame: Arc<latest_frame::LatestFrame>,
+    /// Frames the webview acknowledged drawing
+    frame_acks: Arc<udp_client::FrameAcks>,
+    settings: Mutex<StreamSettings>,
+    viewer_settings: Mutex<ViewerSettings>,
+    control_api: Mutex<Option<control_api::ControlApi>>,
+    trigger: Mutex<Option<trigger::TriggerListener>>,
+    clipboard: Mutex<Option<clipboard_sync::ClipboardSync>>,
+    virtual_display: Mutex<Option<virtual_display::VirtualDisplay>>,
+    calibration: Mutex<Option<calibration::Calibration>>,
+    /// Ends the running stream at its time limit
+    session_timer: Mutex<Option<session_timer::SessionTimer>>,
+    /// Broadcasts of their own next to the main stream, by name
+    rooms: Mutex<HashMap<String, rooms::Room>>,
 }
 
-#[tauri::command]
-async fn start_server(state: State<'_, AppState>) -> Result<String, String> {
-    let server = udp_server::UdpServer::new()?;
-    
-    // Use platform-specific capture
-    #[cfg(target_os = "windows")]
-    {
-        // Try Windows.Graphics.Capture, fallback to scrap if not available
-        server.start_streaming(|| {
-            windows_capture::capture_screen_platform_specific()
-        }).await?;
+impl AppState {
+    fn new(capture_input: CaptureInput) -> Self {
+        Self {
+            capture_input,
+            capture: capture_manager::CaptureManager::new(),
+            window: Mutex::new(None),
+            server: Mutex::new(None),
+            client: Mutex::new(None),
+            frames: Arc::new(Mutex::new(None)),
+            latest_frame: Arc::new(latest_frame::LatestFrame::default()),
+            frame_acks: Arc::new(udp_client::FrameAcks::default()),
+            settings: Mutex::new(StreamSettings::default()),
+            viewer_settings: Mutex::new(ViewerSettings::default()),
+            control_api: Mutex::new(None),
+            trigger: Mutex::new(None),
+            clipboard: Mutex::new(None),
+            virtual_display: Mutex::new(None),
+            calibration: Mutex::new(None),
+            session_timer: Mutex::new(None),
+            rooms: Mutex::new(HashMap::new()),
+        }
     }
-    
-    #[cfg(not(target_os = "windows"))]
-    {
-        server.start_streaming(screen_capture::capture_screen).await?;
+}
+
+// Shared by the Tauri commands and the remote control API
+
+// Pick the encoder once per session, sized to what the capture will produce
+fn create_stream_encoder(settings: &StreamSettings, width: usize, height: usize) -> Result<Box<dyn hw_encoder::VideoEncoder>, String> {
+    let config = if settings.lossless {
+        hw_encoder::EncoderConfig {
+            width,
+            height,
+            fps: settings.target_fps,
+            bitrate: 0,
+            encoder_type: hw_encoder::EncoderType::Lossless,
+            quality: settings.jpeg_quality,
+            workers: 1,
+            speed: hw_encoder::DEFAULT_ENCODER_SPEED,
+        }
+    } else if settings.png {
+        hw_encoder::EncoderConfig {
+            width,
+            height,
+            fps: settings.target_fps,
+            bitrate: 0,
+            encoder_type: hw_encoder::EncoderType::Png,
+            quality: settings.jpeg_quality,
+            workers: 1,
+            speed: hw_encoder::DEFAULT_ENCODER_SPEED,
+        }
+    } else if settings.av1.enabled {
+        hw_encoder::EncoderConfig {
+            width,
+            height,
+            fps: settings.target_fps,
+            bitrate: 0,
+            encoder_type: hw_encoder::EncoderType::Av1,
+            quality: settings.jpeg_quality,
+            workers: 1,
+            speed: settings.av1.speed,
+        }
+    } else if settings.webp != settings::WebpMode::Off {
+        hw_encoder::EncoderConfig {
+            width,
+            height,
+            fps: settings.target_fps,
+            bitrate: 0,
+            encoder_type: match settings.webp {
+                settings::WebpMode::Lossless => hw_encoder::EncoderType::WebPLossless,
+                _ => hw_encoder::EncoderType::WebP,
+            },
+            quality: settings.jpeg_quality,
+            workers: 1,
+            speed: hw_encoder::DEFAULT_ENCODER_SPEED,
+        }
+    } else if settings.tile_delta {
+        hw_encoder::EncoderConfig {
+            width,
+            height,
+            fps: settings.target_fps,
+            bitrate: 0,
+            encoder_type: hw_encoder::EncoderType::TileDelta,
+            quality: settings.jpeg_quality,
+            workers: settings.encode_threads(),
+            speed: hw_encoder::DEFAULT_ENCODER_SPEED,
+        }
+    } else {
+        let mut config = hw_encoder::auto_detect_encoder(width, height, settings.target_fps);
+        // H.264 takes a CRF instead, only JPEG uses the calibrated quality
+        if config.encoder_type == hw_encoder::EncoderType::Software {
+            config.quality = settings.jpeg_quality;
+        }
+        config
+    };
+    hw_encoder::create_encoder(config)
+}
+
+// Other displays are shown as they arrive, only the main display gets tile deltas and the other encoder modes
+fn create_display_encoder(settings: &StreamSettings, width: usize, height: usize) -> Result<Box<dyn hw_encoder::VideoEncoder>, String> {
+    let mut settings = settings.clone();
+    settings.set_encoder_mode(settings::EncoderMode::Auto);
+    create_stream_encoder(&settings, width, height)
+}
+
+async fn server_start<R: Runtime>(app: tauri::AppHandle<R>, state: &AppState) -> Result<String, String> {
+    if state.server.lock().unwrap().as_ref().is_some_and(|s| s.is_running()) {
+        return Err("Server is already running".to_string());
     }
-    
+
+    let settings = state.settings.lock().unwrap().clone();
+    rooms::check_ports(&settings.ports, state.rooms.lock().unwrap().values().map(|room| &room.server.settings().ports))?;
+    let capture_formats = settings.capture_formats.clone();
+    let session_limit = settings.session_limit;
+    let max_width = settings.max_width;
+    let timer_app = app.clone();
+    let window = match (state.capture_input, state.window.lock().unwrap().clone()) {
+        (CaptureInput::Screen, Some(selector)) => Some(window_capture::WindowCapture::new(&selector, max_width)?),
+        _ => None,
+    };
+    let (width, height) = match state.capture_input {
+        CaptureInput::Screen => match &window {
+            Some(window) => window.output_size(),
+            None => screen_capture::stream_output_size(max_width)?,
+        },
+        CaptureInput::Synthetic { width, height } => (width, height),
+    };
+    let encoder = create_stream_encoder(&settings, width, height)?;
+    let preview = settings.preview.enabled
+        .then(|| preview::PreviewTap::new(app.clone(), settings.preview.clone()));
+    let encoder_tap = Some(preview::EncoderTap::new(app.clone()));
+    let server = udp_server::UdpServer::new(settings)?;
+    let stats_app = app.clone();
+    server.on_stats(move |report| {
+        let _ = stats_app.emit("server-stats", report);
+    });
+    let peers_app = app.clone();
+    server.peers().on_change(move |change| {
+        let _ = match change {
+            peers::PeerChange::Joined(client) => peers_app.emit("client-joined", client),
+            peers::PeerChange::Left(client) => peers_app.emit("client-left", client),
+        };
+    });
+    if let Some(access) = server.access_control() {
+        access.on_request(move |request| {
+            let _ = app.emit("viewer-join-request", request);
+        });
+    }
+
+    match state.capture_input {
+        CaptureInput::Synthetic { width, height } => {
+            let source = frame_source::SyntheticSource::new(width, height);
+            let source = title_card::WithTitleCard::new(source, server.settings(), width, height);
+            server.start_streaming(source, encoder, preview, encoder_tap).await?;
+        }
+        CaptureInput::Screen => match window {
+            Some(window) => {
+                let source = title_card::WithTitleCard::new(window, server.settings(), width, height);
+                server.start_streaming(source, encoder, preview, encoder_tap).await?;
+            }
+            None => {
+                // Best backend this machine offers, see get_capture_capabilities
+                let mut capture = state.capture.session(&capture_formats).with_max_width(max_width);
+                if server.settings().cursor_metadata {
+                    capture = with_cursor_stream(&server, screen_capture::selected_display(), capture);
+                }
+                let source = title_card::WithTitleCard::new(capture, server.settings(), width, height);
+                server.start_streaming(source, encoder, preview, encoder_tap).await?;
+            }
+        },
+    }
+
+    // The other displays, each as a stream of its own; the main one goes on without them
+    if matches!(state.capture_input, CaptureInput::Screen) {
+        for (i, &index) in server.settings().extra_displays.iter().enumerate() {
+            let started = screen_capture::display_output_size(Some(index), max_width).and_then(|(width, height)| {
+                let encoder = create_display_encoder(server.settings(), width, height)?;
+                let display = display_streams::DisplayStream { stream: i as u8 + 1, display: index, width, height };
+                let capture = state.capture.display_session(index, &capture_formats).with_max_width(max_width);
+                server.start_display_stream(display, capture, encoder)
+            });
+            if let Err(e) = started {
+                eprintln!("⚠️  Display {} not streamed: {}", index + 1, e);
+            }
+        }
+    }
+
+    server.advertise(width, height);
     *state.server.lock().unwrap() = Some(server);
+    arm_session_limit(timer_app, state, session_limit);
     Ok("Server started successfully (using platform-optimized capture)".to_string())
 }
 
-#[tauri::command]
-fn stop_server(state: State<'_, AppState>) -> Result<String, String> {
+// The pointer goes out as metadata, or stays in the frames when it can't
+fn with_cursor_stream(server: &udp_server::UdpServer, display: Option<usize>, capture: capture_manager::CaptureSession) -> capture_manager::CaptureSession {
+    match server.start_cursor_stream(display) {
+        Ok(()) => capture.without_cursor(),
+        Err(e) => {
+            eprintln!("⚠️  Pointer stays in the frames: {}", e);
+            capture
+        }
+    }
+}
+
+// Replaces the running stream's limit; the UI counts down with `session-countdown`
+fn arm_session_limit<R: Runtime>(app: tauri::AppHandle<R>, state: &AppState, limit: session_timer::SessionLimit) {
+    let countdown_app = app.clone();
+    let timer = session_timer::SessionTimer::start(
+        limit,
+        move |countdown| {
+            let _ = countdown_app.emit("session-countdown", countdown);
+        },
+        move |action| {
+            let state = app.state::<AppState>();
+            let result = match action {
+                session_timer::LimitAction::Stop => server_stop(&state),
+                session_timer::LimitAction::Pause => match state.server.lock().unwrap().as_ref() {
+                    Some(server) => {
+                        server.set_frozen(true);
+                        Ok("Stream paused".to_string())
+                    }
+                    None => Ok("Server is not running".to_string()),
+                },
+            };
+            if let Err(e) = result {
+                eprintln!("❌ {}", e);
+            }
+        },
+    );
+    *state.session_timer.lock().unwrap() = timer;
+}
+
+fn server_stop(state: &AppState) -> Result<String, String> {
+    state.session_timer.lock().unwrap().take();
     if let Some(server) = state.server.lock().unwrap().as_ref() {
         server.stop();
     }
@@ -57,17 +368,296 @@ fn stop_server(state: State<'_, AppState>) -> Result<String, String> {
     Ok("Server stopped".to_string())
 }
 
-#[tauri::command]
-fn start_client(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<String, String> {
-    let client = udp_client::UdpClient::new()?;
-    client.start_receiving(app)?;
-    
+fn server_toggle_freeze(state: &AppState) -> Result<String, String> {
+    let server = state.server.lock().unwrap();
+    let server = server.as_ref().ok_or("Server is not running")?;
+    server.set_frozen(!server.is_frozen());
+    Ok(if server.is_frozen() { "Stream frozen" } else { "Stream resumed" }.to_string())
+}
+
+// The running stream changes right away, the next one starts at it too
+// The pacer never goes below its floor, checked before the settings keep a quality it would ignore
+fn quality_set(state: &AppState, quality: u8) -> Result<String, String> {
+    if !(frame_pacer::MIN_QUALITY..=100).contains(&quality) {
+        return Err(format!("Quality ({}) must be within {}-100", quality, frame_pacer::MIN_QUALITY));
+    }
+    let mut settings = state.settings.lock().unwrap().clone();
+    settings.jpeg_quality = quality;
+    apply_settings(state, settings)?;
+    if let Some(server) = state.server.lock().unwrap().as_ref().filter(|s| s.is_running()) {
+        server.set_quality(quality)?;
+    }
+    Ok(format!("Stream quality {}", quality))
+}
+
+// The running stream checks against its own range, which may predate the settings
+fn fps_set(state: &AppState, fps: u32) -> Result<String, String> {
+    let mut settings = state.settings.lock().unwrap().clone();
+    settings.target_fps = fps;
+    settings.validate()?;
+    if let Some(server) = state.server.lock().unwrap().as_ref().filter(|s| s.is_running()) {
+        server.set_target_fps(fps)?;
+    }
+    apply_settings(state, settings)?;
+    Ok(format!("Target {} FPS", fps))
+}
+
+// Kept for the next stream like the quality; rooms started later each get a cap of their own
+fn bandwidth_set(state: &AppState, mbps: f32) -> Result<String, String> {
+    let mut settings = state.settings.lock().unwrap().clone();
+    settings.max_bitrate_mbps = mbps;
+    apply_settings(state, settings)?;
+    if let Some(server) = state.server.lock().unwrap().as_ref().filter(|s| s.is_running()) {
+        server.set_bandwidth_limit(mbps)?;
+    }
+    Ok(if mbps > 0.0 { format!("Bandwidth limited to {} Mbit/s", mbps) } else { "Bandwidth unlimited".to_string() })
+}
+
+fn server_decide_viewer(state: &AppState, id: u64, approve: bool) -> Result<String, String> {
+    let server = state.server.lock().unwrap();
+    let access = server.as_ref()
+        .ok_or("Server is not running")?
+        .access_control()
+        .ok_or("Viewer approval is not enabled")?;
+    access.decide(id, approve)?;
+    Ok(if approve { "Viewer approved" } else { "Viewer denied" }.to_string())
+}
+
+// Off again for every new session, so control is never handed out by accident
+fn server_set_remote_control(state: &AppState, enabled: bool) -> Result<String, String> {
+    let server = state.server.lock().unwrap();
+    let server = server.as_ref().ok_or("Server is not running")?;
+    server.set_remote_control(enabled);
+    eprintln!("🖱️  Remote control {}", if enabled { "enabled" } else { "disabled" });
+    Ok(if enabled { "Remote control enabled" } else { "Remote control disabled" }.to_string())
+}
+
+// Takes the main stream's settings, see rooms.rs; join requests come as `room-join-request`
+async fn room_start<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    state: &AppState,
+    name: String,
+    display: usize,
+    ports: settings::PortMapping,
+    passphrase: Option<String>,
+) -> Result<String, String> {
+    if name.trim().is_empty() {
+        return Err("Room needs a name".to_string());
+    }
+    if state.rooms.lock().unwrap().get(&name).is_some_and(|room| room.server.is_running()) {
+        return Err(format!("Room {} is already running", name));
+    }
+
+    let main = state.settings.lock().unwrap().clone();
+    {
+        let rooms = state.rooms.lock().unwrap();
+        let others = rooms.iter().filter(|(other, _)| **other != name).map(|(_, room)| &room.server.settings().ports);
+        rooms::check_ports(&ports, others.chain([&main.ports]))?;
+    }
+    let settings = rooms::room_settings(&main, ports, passphrase)?;
+    let (width, height) = screen_capture::display_output_size(Some(display), settings.max_width)?;
+    let encoder = create_stream_encoder(&settings, width, height)?;
+    let server = udp_server::UdpServer::new(settings)?;
+    if let Some(access) = server.access_control() {
+        let room = name.clone();
+        access.on_request(move |request| {
+            let _ = app.emit("room-join-request", rooms::RoomJoinRequest { room: room.clone(), request });
+        });
+    }
+
+    let mut capture = state.capture.display_session(display, &server.settings().capture_formats)
+        .with_max_width(server.settings().max_width);
+    if server.settings().cursor_metadata {
+        capture = with_cursor_stream(&server, Some(display), capture);
+    }
+    let source = title_card::WithTitleCard::new(capture, server.settings(), width, height);
+    server.start_streaming(source, encoder, None, None).await?;
+    server.advertise(width, height);
+    eprintln!("🏫 Room {} streaming display {} on port {}", name, display + 1, ports.data);
+    state.rooms.lock().unwrap().insert(name.clone(), rooms::Room { display, server });
+    Ok(format!("Room {} started", name))
+}
+
+fn room_stop(state: &AppState, name: &str) -> Result<String, String> {
+    let room = state.rooms.lock().unwrap().remove(name).ok_or_else(|| format!("No room named {}", name))?;
+    room.server.stop();
+    Ok(format!("Room {} stopped", name))
+}
+
+fn room_toggle_freeze(state: &AppState, name: &str) -> Result<String, String> {
+    let rooms = state.rooms.lock().unwrap();
+    let server = &rooms.get(name).ok_or_else(|| format!("No room named {}", name))?.server;
+    server.set_frozen(!server.is_frozen());
+    Ok(if server.is_frozen() { "Room frozen" } else { "Room resumed" }.to_string())
+}
+
+fn room_decide_viewer(state: &AppState, name: &str, id: u64, approve: bool) -> Result<String, String> {
+    let rooms = state.rooms.lock().unwrap();
+    let access = rooms.get(name)
+        .ok_or_else(|| format!("No room named {}", name))?
+        .server
+        .access_control()
+        .ok_or("Viewer approval is not enabled")?;
+    access.decide(id, approve)?;
+    Ok(if approve { "Viewer approved" } else { "Viewer denied" }.to_string())
+}
+
+// A different display usually means a different resolution, so a running
+// stream is restarted with an encoder sized for it
+async fn restart_server_if_running<R: Runtime>(app: tauri::AppHandle<R>, state: &AppState) -> Result<(), String> {
+    let running = state.server.lock().unwrap().as_ref().is_some_and(|s| s.is_running());
+    if running {
+        // Same session as far as its time limit goes
+        let timer = state.session_timer.lock().unwrap().take();
+        server_stop(state)?;
+        server_start(app, state).await?;
+        if timer.is_some() {
+            *state.session_timer.lock().unwrap() = timer;
+        }
+    }
+    Ok(())
+}
+
+async fn display_select(app: tauri::AppHandle, state: &AppState, index: usize) -> Result<String, String> {
+    screen_capture::select_display(index)?;
+    // Picking a display means sharing all of it
+    *state.window.lock().unwrap() = None;
+    restart_server_if_running(app, state).await?;
+    Ok(format!("Sharing display {}", index + 1))
+}
+
+// The first display is the main stream, the others follow as streams 1, 2, ...
+async fn displays_select(app: tauri::AppHandle, state: &AppState, indices: Vec<usize>) -> Result<String, String> {
+    let (&main, extra) = indices.split_first().ok_or("Select at least one display")?;
+    let mut settings = state.settings.lock().unwrap().clone();
+    settings.extra_displays = extra.to_vec();
+    settings.validate()?;
+    screen_capture::select_display(main)?;
+    apply_settings(state, settings)?;
+    *state.window.lock().unwrap() = None;
+    restart_server_if_running(app, state).await?;
+    Ok(format!("Sharing {} displays", indices.len()))
+}
+
+// The encoder is sized for the stream, so a running one restarts at the new size
+async fn resolution_set(app: tauri::AppHandle, state: &AppState, max_width: u32) -> Result<String, String> {
+    let mut settings = state.settings.lock().unwrap().clone();
+    settings.max_width = max_width;
+    apply_settings(state, settings)?;
+    restart_server_if_running(app, state).await?;
+    Ok(match max_width {
+        0 => "Streaming at native resolution".to_string(),
+        width => format!("Streaming at up to {}px wide", width),
+    })
+}
+
+async fn encoder_set(app: tauri::AppHandle, state: &AppState, mode: settings::EncoderMode) -> Result<String, String> {
+    let mut settings = state.settings.lock().unwrap().clone();
+    settings.set_encoder_mode(mode);
+    apply_settings(state, settings)?;
+    restart_server_if_running(app, state).await?;
+    Ok(format!("Encoding the stream as {:?}", mode))
+}
+
+async fn window_select(app: tauri::AppHandle, state: &AppState, window: Option<window_capture::WindowSelector>) -> Result<String, String> {
+    let message = match &window {
+        Some(selector) => format!("Sharing window \"{}\"", window_capture::find_window(selector)?.title),
+        None => "Sharing the whole display".to_string(),
+    };
+    *state.window.lock().unwrap() = window;
+    restart_server_if_running(app, state).await?;
+    Ok(message)
+}
+
+// Capture follows the virtual display while it exists
+async fn virtual_display_start(
+    app: tauri::AppHandle,
+    state: &AppState,
+    config: virtual_display::VirtualDisplayConfig,
+) -> Result<String, String> {
+    // The old one restores the original display when dropped, so it goes first
+    state.virtual_display.lock().unwrap().take();
+    let display = virtual_display::VirtualDisplay::start(&config)?;
+    let name = display.name().to_string();
+    *state.virtual_display.lock().unwrap() = Some(display);
+
+    restart_server_if_running(app, state).await?;
+    Ok(format!("Virtual display {} started", name))
+}
+
+async fn virtual_display_stop(app: tauri::AppHandle, state: &AppState) -> Result<String, String> {
+    if state.virtual_display.lock().unwrap().take().is_some() {
+        restart_server_if_running(app, state).await?;
+    }
+    Ok("Virtual display stopped".to_string())
+}
+
+// After sleep the capturer, encoder session and sockets are likely dead, so
+// everything running is rebuilt. The restarted server picks a new epoch,
+// which makes viewers drop their state and resync on the next keyframe.
+async fn system_resumed(app: tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    screen_capture::reset_capture();
+
+    let server_running = state.server.lock().unwrap().as_ref().is_some_and(|s| s.is_running());
+    if server_running {
+        let _ = server_stop(&state);
+        match server_start(app.clone(), &state).await {
+            Ok(_) => eprintln!("✅ Stream restarted after resume"),
+            Err(e) => eprintln!("❌ Failed to restart stream after resume: {}", e),
+        }
+    }
+
+    // Multicast memberships don't survive the network coming back up
+    let client_running = state.client.lock().unwrap().as_ref().is_some_and(|c| c.is_running());
+    if client_running {
+        let _ = client_stop(&state);
+        if let Err(e) = client_start(app.clone(), &state) {
+            eprintln!("❌ Failed to rejoin stream after resume: {}", e);
+        }
+    }
+
+    let _ = app.emit("system-resumed", ());
+}
+
+fn client_start(app: tauri::AppHandle, state: &AppState) -> Result<String, String> {
+    if state.client.lock().unwrap().as_ref().is_some_and(|c| c.is_running()) {
+        return Err("Client is already running".to_string());
+    }
+
+    let viewer_settings = state.viewer_settings.lock().unwrap().clone();
+    let client = udp_client::UdpClient::new(
+        viewer_settings.ip_version,
+        viewer_settings.multicast_interface,
+        viewer_settings.multicast_group,
+        viewer_settings.ports,
+    )?;
+    client.set_stale_threshold(viewer_settings.stale_threshold_ms);
+    client.set_jitter_delay(viewer_settings.jitter_buffer_ms);
+    client.set_stream_lost_after(viewer_settings.stream_lost_ms);
+    client.set_max_emit_fps(viewer_settings.max_emit_fps);
+    client.set_memory_limit(viewer_settings.memory_limit_mb);
+    client.set_receive_buffer(viewer_settings.receive_buffer_kb);
+    client.set_tcp_fallback(viewer_settings.tcp_fallback);
+    client.set_quic(viewer_settings.quic);
+    client.set_displays(viewer_settings.displays.clone());
+    client.set_stream_id(viewer_settings.stream_id);
+    client.set_chunk_diagnostics(viewer_settings.chunk_diagnostics);
+    client.set_decode_validation(viewer_settings.decode_validation, viewer_settings.validation_budget_ms);
+    client.set_passphrase(viewer_settings.passphrase.as_deref())?;
+    client.set_pin(viewer_settings.pin.as_deref())?;
+    if let Some(address) = viewer_settings.server_address.as_deref().filter(|a| !a.trim().is_empty()) {
+        let server = access_control::resolve_server(address, viewer_settings.ip_version, viewer_settings.ports.control)?;
+        client.request_access(server)?;
+    }
+    state.latest_frame.clear();
+    client.start_receiving(udp_client::FrameOutput::Webview(app, state.frames.clone(), state.latest_frame.clone(), state.frame_acks.clone()))?;
+
     *state.client.lock().unwrap() = Some(client);
     Ok("Client started successfully".to_string())
 }
 
-#[tauri::command]
-fn stop_client(state: State<'_, AppState>) -> Result<String, String> {
+fn client_stop(state: &AppState) -> Result<String, String> {
     if let Some(client) = state.client.lock().unwrap().as_ref() {
         client.stop();
     }
@@ -75,6 +665,652 @@ fn stop_client(state: State<'_, AppState>) -> Result<String, String> {
     Ok("Client stopped".to_string())
 }
 
+fn apply_settings(state: &AppState, settings: StreamSettings) -> Result<String, String> {
+    settings.validate()?;
+    *state.settings.lock().unwrap() = settings;
+    Ok("Settings updated (applied on next server start)".to_string())
+}
+
+// Calibrated values replace the built-in defaults, the UI hears about it
+fn apply_calibration<R: Runtime>(app: &tauri::AppHandle<R>, state: &AppState, calibration: calibration::Calibration) {
+    let defaults = calibration.defaults();
+    let mut settings = state.settings.lock().unwrap().clone();
+    defaults.apply(&mut settings);
+    if let Err(e) = apply_settings(state, settings) {
+        eprintln!("❌ Calibrated settings rejected: {}", e);
+    }
+    *state.calibration.lock().unwrap() = Some(calibration);
+    let _ = app.emit("calibrated", defaults);
+}
+
+// First run measures the machine, later runs reuse the stored result
+fn calibrate_on_startup(app: tauri::AppHandle) {
+    let dir = match app.path().app_data_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("⚠️  No app data directory, keeping default settings: {}", e);
+            return;
+        }
+    };
+    match calibration::Calibration::load_or_run(&dir) {
+        Ok(calibration) => apply_calibration(&app, &app.state::<AppState>(), calibration),
+        Err(e) => eprintln!("⚠️  Calibration failed, keeping default settings: {}", e),
+    }
+}
+
+fn status_snapshot(state: &AppState) -> StatusSnapshot {
+    StatusSnapshot {
+        server_running: state.server.lock().unwrap().as_ref().is_some_and(|s| s.is_running()),
+        client_running: state.client.lock().unwrap().as_ref().is_some_and(|c| c.is_running()),
+        session_pin: state.server.lock().unwrap().as_ref()
+            .and_then(|s| s.access_control())
+            .map(|access| access.pin().to_string()),
+    }
+}
+
+fn stats_snapshot(state: &AppState) -> stats::StatsSnapshot {
+    stats::StatsSnapshot::new(
+        state.server.lock().unwrap().as_ref().map(|s| s.stats_report()),
+        state.client.lock().unwrap().as_ref().map(|c| c.stats_report()),
+    )
+}
+
+fn stream_stats(state: &AppState) -> stats::StreamStats {
+    let approved = state.server.lock().unwrap().as_ref()
+        .and_then(|s| s.access_control())
+        .map_or(0, |access| access.approved().len());
+    stats::StreamStats::new(&stats_snapshot(state), approved)
+}
+
+/// `max_duration_secs` and `limit_action` override the settings' session limit for this stream
+#[tauri::command]
+async fn start_server(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    max_duration_secs: Option<u64>,
+    limit_action: Option<session_timer::LimitAction>,
+) -> Result<String, String> {
+    let message = server_start(app.clone(), &state).await?;
+    if max_duration_secs.is_some() || limit_action.is_some() {
+        let mut limit = state.settings.lock().unwrap().session_limit;
+        limit.max_duration_secs = max_duration_secs.unwrap_or(limit.max_duration_secs);
+        limit.action = limit_action.unwrap_or(limit.action);
+        arm_session_limit(app, &state, limit);
+    }
+    Ok(message)
+}
+
+#[tauri::command]
+fn stop_server(state: State<'_, AppState>) -> Result<String, String> {
+    server_stop(&state)
+}
+
+#[tauri::command]
+fn toggle_freeze(state: State<'_, AppState>) -> Result<String, String> {
+    server_toggle_freeze(&state)
+}
+
+#[tauri::command]
+fn set_stream_quality(state: State<'_, AppState>, quality: u8) -> Result<String, String> {
+    quality_set(&state, quality)
+}
+
+#[tauri::command]
+fn set_target_fps(state: State<'_, AppState>, fps: u32) -> Result<String, String> {
+    fps_set(&state, fps)
+}
+
+#[tauri::command]
+fn set_bandwidth_limit(state: State<'_, AppState>, mbps: f32) -> Result<String, String> {
+    bandwidth_set(&state, mbps)
+}
+
+#[tauri::command]
+fn approve_viewer(state: State<'_, AppState>, id: u64) -> Result<String, String> {
+    server_decide_viewer(&state, id, true)
+}
+
+#[tauri::command]
+fn deny_viewer(state: State<'_, AppState>, id: u64) -> Result<String, String> {
+    server_decide_viewer(&state, id, false)
+}
+
+/// A broadcast of `display` (index into get_displays) on `ports`, next to the main stream
+#[tauri::command]
+async fn start_room(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+    display: usize,
+    ports: settings::PortMapping,
+    passphrase: Option<String>,
+) -> Result<String, String> {
+    room_start(app, &state, name, display, ports, passphrase).await
+}
+
+#[tauri::command]
+fn stop_room(state: State<'_, AppState>, name: String) -> Result<String, String> {
+    room_stop(&state, &name)
+}
+
+#[tauri::command]
+fn toggle_room_freeze(state: State<'_, AppState>, name: String) -> Result<String, String> {
+    room_toggle_freeze(&state, &name)
+}
+
+#[tauri::command]
+fn approve_room_viewer(state: State<'_, AppState>, name: String, id: u64) -> Result<String, String> {
+    room_decide_viewer(&state, &name, id, true)
+}
+
+#[tauri::command]
+fn deny_room_viewer(state: State<'_, AppState>, name: String, id: u64) -> Result<String, String> {
+    room_decide_viewer(&state, &name, id, false)
+}
+
+#[tauri::command]
+fn list_rooms(state: State<'_, AppState>) -> Vec<rooms::RoomInfo> {
+    let mut rooms: Vec<_> = state.rooms.lock().unwrap().iter().map(|(name, room)| room.info(name)).collect();
+    rooms.sort_by(|a, b| a.name.cmp(&b.name));
+    rooms
+}
+
+#[tauri::command]
+fn get_status(state: State<'_, AppState>) -> StatusSnapshot {
+    status_snapshot(&state)
+}
+
+/// Same schema as the control API's /api/stats
+#[tauri::command]
+fn get_stats(state: State<'_, AppState>) -> stats::StatsSnapshot {
+    stats_snapshot(&state)
+}
+
+/// Current FPS, bitrate, drops and peers, see stats::StreamStats
+#[tauri::command]
+fn get_stream_stats(state: State<'_, AppState>) -> stats::StreamStats {
+    stream_stats(&state)
+}
+
+/// Viewers talking back to the running server, none when it's stopped
+#[tauri::command]
+fn get_connected_clients(state: State<'_, AppState>) -> Vec<peers::ConnectedClient> {
+    state.server.lock().unwrap().as_ref()
+        .map(|server| server.peers().clients())
+        .unwrap_or_default()
+}
+
+/// Capture backends of this build and which one the last stream ended up on
+#[tauri::command]
+fn get_capture_capabilities(state: State<'_, AppState>) -> capture_manager::CaptureCapabilities {
+    state.capture.capabilities()
+}
+
+/// SDP for playing the RTP output in VLC or ffplay
+#[tauri::command]
+fn get_rtp_sdp(state: State<'_, AppState>) -> Result<String, String> {
+    let settings = state.settings.lock().unwrap();
+    if !settings.rtp.enabled {
+        return Err("RTP output is disabled".to_string());
+    }
+    Ok(rtp_output::sdp(settings.ip_version, settings.rtp.port, &discovery::host_name()))
+}
+
+/// Archive the running stream; the extension of `path` follows the encoder (.mp4 or .avi).
+/// A `passphrase` seals the file, see recording_crypto.rs.
+#[tauri::command]
+fn start_recording(state: State<'_, AppState>, path: String, passphrase: Option<String>) -> Result<String, String> {
+    let server = state.server.lock().unwrap();
+    let path = server
+        .as_ref()
+        .ok_or("Server is not running")?
+        .start_recording(std::path::Path::new(&path), passphrase.as_deref())?;
+    Ok(format!("Recording to {}", path.display()))
+}
+
+#[tauri::command]
+fn stop_recording(state: State<'_, AppState>) -> Result<recording::RecordingSummary, String> {
+    let server = state.server.lock().unwrap();
+    server.as_ref().ok_or("Server is not running")?.stop_recording()
+}
+
+/// Keep what this viewer shows as an MJPEG .avi, with a .csv of when each frame arrived
+#[tauri::command]
+fn start_viewer_recording(state: State<'_, AppState>, path: String, passphrase: Option<String>) -> Result<String, String> {
+    let encrypt = state.viewer_settings.lock().unwrap().encrypt_recordings;
+    let client = state.client.lock().unwrap();
+    let path = client
+        .as_ref()
+        .ok_or("Client is not running")?
+        .start_recording(std::path::Path::new(&path), encrypt, passphrase.as_deref())?;
+    Ok(format!("Recording to {}", path.display()))
+}
+
+#[tauri::command]
+fn stop_viewer_recording(state: State<'_, AppState>) -> Result<recording::RecordingSummary, String> {
+    let client = state.client.lock().unwrap();
+    client.as_ref().ok_or("Client is not running")?.stop_recording()
+}
+
+/// Decrypt a sealed recording for playback, by default next to it without the .enc
+#[tauri::command]
+async fn decrypt_recording(path: String, passphrase: String, output: Option<String>) -> Result<String, String> {
+    let sealed = std::path::PathBuf::from(path);
+    let plain = match output {
+        Some(output) => std::path::PathBuf::from(output),
+        None if sealed.extension().is_some_and(|e| e == recording_crypto::EXTENSION) => sealed.with_extension(""),
+        None => return Err("Recording has no .enc extension, choose where to decrypt it to".to_string()),
+    };
+    let (plain, bytes) = tokio::task::spawn_blocking(move || {
+        recording_crypto::open_file(&sealed, &plain, &passphrase).map(|bytes| (plain, bytes))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    Ok(format!("Decrypted {} bytes to {}", bytes, plain.display()))
+}
+
+#[tauri::command]
+fn enable_remote_control(state: State<'_, AppState>) -> Result<String, String> {
+    server_set_remote_control(&state, true)
+}
+
+#[tauri::command]
+fn disable_remote_control(state: State<'_, AppState>) -> Result<String, String> {
+    server_set_remote_control(&state, false)
+}
+
+#[tauri::command]
+fn send_remote_input(state: State<'_, AppState>, event: remote_input::InputEvent) -> Result<(), String> {
+    let client = state.client.lock().unwrap();
+    client.as_ref().ok_or("Client is not running")?.send_input(&event)
+}
+
+#[tauri::command]
+async fn select_display(app: tauri::AppHandle, state: State<'_, AppState>, index: usize) -> Result<String, String> {
+    display_select(app, &state, index).await
+}
+
+/// Share several displays at once, see display_streams.rs
+#[tauri::command]
+async fn select_displays(app: tauri::AppHandle, state: State<'_, AppState>, indices: Vec<usize>) -> Result<String, String> {
+    displays_select(app, &state, indices).await
+}
+
+/// Scale wider displays down to `max_width`, 0 for native resolution
+#[tauri::command]
+async fn set_stream_resolution(app: tauri::AppHandle, state: State<'_, AppState>, max_width: u32) -> Result<String, String> {
+    resolution_set(app, &state, max_width).await
+}
+
+/// Pick the main display's encoder for this and later sessions
+#[tauri::command]
+async fn set_stream_encoder(app: tauri::AppHandle, state: State<'_, AppState>, encoder: settings::EncoderMode) -> Result<String, String> {
+    encoder_set(app, &state, encoder).await
+}
+
+/// Share one application window, or the whole display again with `None`
+#[tauri::command]
+async fn capture_window(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    window: Option<window_capture::WindowSelector>,
+) -> Result<String, String> {
+    window_select(app, &state, window).await
+}
+
+#[tauri::command]
+fn list_windows() -> Result<Vec<window_capture::WindowInfo>, String> {
+    window_capture::list_windows()
+}
+
+#[tauri::command]
+async fn start_virtual_display(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    config: Option<virtual_display::VirtualDisplayConfig>,
+) -> Result<String, String> {
+    virtual_display_start(app, &state, config.unwrap_or_default()).await
+}
+
+#[tauri::command]
+async fn stop_virtual_display(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<String, String> {
+    virtual_display_stop(app, &state).await
+}
+
+/// Receive the viewer's frames as raw bytes: image files, or "TILE" payloads
+#[tauri::command]
+fn subscribe_frames(channel: tauri::ipc::Channel, state: State<'_, AppState>) {
+    *state.frames.lock().unwrap() = Some(channel);
+    state.frame_acks.reset();
+}
+
+/// The webview drew a frame it got over the channel
+#[tauri::command]
+fn ack_frame(state: State<'_, AppState>) {
+    state.frame_acks.acknowledge();
+}
+
+/// `stream_id` subscribes to one server's stream (see ServerInfo). `port` and
+/// `group` receive a server not on the default data port and group; all three
+/// are kept for restarts.
+#[tauri::command]
+fn start_client(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    stream_id: Option<u32>,
+    port: Option<u16>,
+    group: Option<String>,
+) -> Result<String, String> {
+    {
+        let mut settings = state.viewer_settings.lock().unwrap();
+        settings.stream_id = stream_id;
+        if let Some(port) = port {
+            // A rejected port mustn't stay behind for the next start
+            let mut ports = settings.ports;
+            ports.data = port;
+            ports.validate()?;
+            settings.ports = ports;
+        }
+        if let Some(group) = group {
+            settings.multicast_group = Some(net_interfaces::parse_multicast_group(&group, settings.ip_version)?);
+        }
+    }
+    client_start(app, &state)
+}
+
+#[tauri::command]
+fn stop_client(state: State<'_, AppState>) -> Result<String, String> {
+    client_stop(&state)
+}
+
+#[tauri::command]
+fn get_settings(state: State<'_, AppState>) -> StreamSettings {
+    state.settings.lock().unwrap().clone()
+}
+
+#[tauri::command]
+fn update_settings(state: State<'_, AppState>, settings: StreamSettings) -> Result<String, String> {
+    apply_settings(&state, settings)
+}
+
+// Applied on the next server start, like the other stream settings
+#[tauri::command]
+fn set_stream_passphrase(state: State<'_, AppState>, passphrase: Option<String>) -> Result<String, String> {
+    let mut settings = state.settings.lock().unwrap().clone();
+    settings.encryption_passphrase = passphrase.filter(|p| !p.is_empty());
+    let enabled = settings.encryption_passphrase.is_some();
+    if enabled {
+        settings.pairing_pin = None;
+    }
+    apply_settings(&state, settings)?;
+    Ok(format!("Stream encryption {}", if enabled { "enabled" } else { "disabled" }))
+}
+
+// A PIN replaces the passphrase: viewers pair with it and get a fresh key every session
+#[tauri::command]
+fn set_stream_pin(state: State<'_, AppState>, pin: Option<String>) -> Result<String, String> {
+    let mut settings = state.settings.lock().unwrap().clone();
+    settings.pairing_pin = pin.filter(|p| !p.is_empty());
+    let enabled = settings.pairing_pin.is_some();
+    if enabled {
+        settings.encryption_passphrase = None;
+    }
+    apply_settings(&state, settings)?;
+    Ok(format!("PIN pairing {}", if enabled { "enabled" } else { "disabled" }))
+}
+
+#[tauri::command]
+fn set_require_approval(state: State<'_, AppState>, enabled: bool) -> Result<String, String> {
+    let mut settings = state.settings.lock().unwrap().clone();
+    settings.require_approval = enabled;
+    apply_settings(&state, settings)?;
+    Ok(format!("Viewer approval {}", if enabled { "required" } else { "not required" }))
+}
+
+#[tauri::command]
+fn get_viewer_settings(state: State<'_, AppState>) -> ViewerSettings {
+    state.viewer_settings.lock().unwrap().clone()
+}
+
+// Store viewer settings, push them to the live client and notify the UI
+fn apply_viewer_settings(app: &tauri::AppHandle, state: &AppState, settings: ViewerSettings) {
+    if let Some(client) = state.client.lock().unwrap().as_ref() {
+        client.set_stale_threshold(settings.stale_threshold_ms);
+        client.set_jitter_delay(settings.jitter_buffer_ms);
+        client.set_stream_lost_after(settings.stream_lost_ms);
+        client.set_max_emit_fps(settings.max_emit_fps);
+        client.set_memory_limit(settings.memory_limit_mb);
+        client.set_receive_buffer(settings.receive_buffer_kb);
+        client.set_tcp_fallback(settings.tcp_fallback);
+        client.set_quic(settings.quic);
+        client.set_displays(settings.displays.clone());
+        client.set_chunk_diagnostics(settings.chunk_diagnostics);
+        client.set_decode_validation(settings.decode_validation, settings.validation_budget_ms);
+        if let Err(e) = client.set_passphrase(settings.passphrase.as_deref()) {
+            eprintln!("❌ {}", e);
+        }
+        if let Err(e) = client.set_pin(settings.pin.as_deref()) {
+            eprintln!("❌ {}", e);
+        }
+    }
+    *state.viewer_settings.lock().unwrap() = settings.clone();
+    let _ = app.emit("viewer-settings", settings);
+}
+
+#[tauri::command]
+fn update_viewer_settings(app: tauri::AppHandle, state: State<'_, AppState>, settings: ViewerSettings) -> Result<String, String> {
+    settings.validate()?;
+    apply_viewer_settings(&app, &state, settings);
+    Ok("Viewer settings updated".to_string())
+}
+
+#[tauri::command]
+fn set_smoothing(app: tauri::AppHandle, state: State<'_, AppState>, enabled: bool) -> Result<String, String> {
+    let mut settings = state.viewer_settings.lock().unwrap().clone();
+    settings.smoothing = enabled;
+    apply_viewer_settings(&app, &state, settings);
+    Ok(format!("Smoothing {}", if enabled { "enabled" } else { "disabled" }))
+}
+
+/// Histogram of missing chunk indices in `get_stats`, for telling buffer, pacing and FEC problems apart
+#[tauri::command]
+fn set_chunk_diagnostics(app: tauri::AppHandle, state: State<'_, AppState>, enabled: bool) -> Result<String, String> {
+    let mut settings = state.viewer_settings.lock().unwrap().clone();
+    settings.chunk_diagnostics = enabled;
+    apply_viewer_settings(&app, &state, settings);
+    Ok(format!("Chunk diagnostics {}", if enabled { "enabled" } else { "disabled" }))
+}
+
+#[tauri::command]
+fn set_viewer_passphrase(app: tauri::AppHandle, state: State<'_, AppState>, passphrase: Option<String>) -> Result<String, String> {
+    let mut settings = state.viewer_settings.lock().unwrap().clone();
+    settings.passphrase = passphrase.filter(|p| !p.is_empty());
+    apply_viewer_settings(&app, &state, settings);
+    Ok("Passphrase updated".to_string())
+}
+
+#[tauri::command]
+fn set_viewer_pin(app: tauri::AppHandle, state: State<'_, AppState>, pin: Option<String>) -> Result<String, String> {
+    let pin = pin.filter(|p| !p.is_empty());
+    if let Some(pin) = pin.as_deref() {
+        settings::validate_pin(pin)?;
+    }
+    let mut settings = state.viewer_settings.lock().unwrap().clone();
+    settings.pin = pin;
+    apply_viewer_settings(&app, &state, settings);
+    Ok("PIN updated".to_string())
+}
+
+// Taken from a discovered server, so the viewer listens where it sends (next start)
+#[tauri::command]
+fn set_viewer_ports(app: tauri::AppHandle, state: State<'_, AppState>, ports: settings::PortMapping) -> Result<String, String> {
+    ports.validate()?;
+    let mut settings = state.viewer_settings.lock().unwrap().clone();
+    settings.ports = ports;
+    apply_viewer_settings(&app, &state, settings);
+    Ok(format!("Viewer ports: data {}, control {}, discovery {}", ports.data, ports.control, ports.discovery))
+}
+
+#[tauri::command]
+fn set_viewer_server_address(app: tauri::AppHandle, state: State<'_, AppState>, address: Option<String>) -> Result<String, String> {
+    let mut settings = state.viewer_settings.lock().unwrap().clone();
+    settings.server_address = address.filter(|a| !a.trim().is_empty());
+    apply_viewer_settings(&app, &state, settings);
+    Ok("Server address updated".to_string())
+}
+
+#[tauri::command]
+async fn start_control_api(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    port: Option<u16>,
+    token: Option<String>,
+) -> Result<ControlApiInfo, String> {
+    if state.control_api.lock().unwrap().is_some() {
+        return Err("Control API is already running".to_string());
+    }
+
+    let api = control_api::ControlApi::start(app, port.unwrap_or(control_api::DEFAULT_PORT), token).await?;
+    let info = ControlApiInfo {
+        port: api.port(),
+        token: api.token().to_string(),
+    };
+
+    *state.control_api.lock().unwrap() = Some(api);
+    Ok(info)
+}
+
+#[tauri::command]
+fn stop_control_api(state: State<'_, AppState>) -> Result<String, String> {
+    if let Some(mut api) = state.control_api.lock().unwrap().take() {
+        api.stop();
+    }
+    Ok("Control API stopped".to_string())
+}
+
+#[tauri::command]
+async fn start_trigger_listener(app: tauri::AppHandle, state: State<'_, AppState>, port: Option<u16>) -> Result<u16, String> {
+    if state.trigger.lock().unwrap().is_some() {
+        return Err("Trigger listener is already running".to_string());
+    }
+
+    let listener = trigger::TriggerListener::start(app, port.unwrap_or(trigger::DEFAULT_PORT)).await?;
+    let port = listener.port();
+    *state.trigger.lock().unwrap() = Some(listener);
+    Ok(port)
+}
+
+#[tauri::command]
+fn stop_trigger_listener(state: State<'_, AppState>) -> Result<String, String> {
+    if let Some(mut listener) = state.trigger.lock().unwrap().take() {
+        listener.stop();
+    }
+    Ok("Trigger listener stopped".to_string())
+}
+
+// The server shares its clipboard with every viewer; a viewer syncs with
+// the server it is watching, so it needs to have received the stream first
+#[tauri::command]
+fn enable_clipboard_sync(state: State<'_, AppState>) -> Result<String, String> {
+    let server_running = state.server.lock().unwrap().as_ref().is_some_and(|s| s.is_running());
+    let sync = if server_running {
+        clipboard_sync::ClipboardSync::start_server(clipboard_sync::CLIPBOARD_PORT)?
+    } else {
+        let server = state.client.lock().unwrap().as_ref()
+            .ok_or("Start sharing or viewing before enabling clipboard sync")?
+            .server_addr()
+            .ok_or("No stream received yet")?;
+        clipboard_sync::ClipboardSync::start_client(server.ip(), clipboard_sync::CLIPBOARD_PORT)?
+    };
+
+    // Replaces (and stops) any previous sync
+    *state.clipboard.lock().unwrap() = Some(sync);
+    Ok("Clipboard sync enabled".to_string())
+}
+
+#[tauri::command]
+fn disable_clipboard_sync(state: State<'_, AppState>) -> Result<String, String> {
+    if let Some(sync) = state.clipboard.lock().unwrap().take() {
+        sync.stop();
+    }
+    Ok("Clipboard sync disabled".to_string())
+}
+
+#[tauri::command]
+async fn discover_servers(state: State<'_, AppState>, timeout_ms: Option<u64>) -> Result<Vec<discovery::ServerInfo>, String> {
+    let timeout = std::time::Duration::from_millis(timeout_ms.unwrap_or(discovery::DEFAULT_TIMEOUT_MS));
+    let port = state.viewer_settings.lock().unwrap().ports.discovery;
+    tokio::task::spawn_blocking(move || discovery::discover(timeout, port))
+        .await
+        .map_err(|e| format!("Discovery failed: {}", e))?
+}
+
+#[tauri::command]
+fn get_network_interfaces() -> Result<Vec<net_interfaces::NetworkInterface>, String> {
+    net_interfaces::list_interfaces()
+}
+
+/// Used by both sides: the server sends and the viewer joins on it (next start)
+#[tauri::command]
+fn set_multicast_interface(app: tauri::AppHandle, state: State<'_, AppState>, address: Option<String>) -> Result<String, String> {
+    let interface = match address.as_deref().filter(|a| !a.trim().is_empty()) {
+        Some(address) => Some(net_interfaces::parse_interface(address)?),
+        None => None,
+    };
+    let mut settings = state.settings.lock().unwrap().clone();
+    settings.multicast_interface = interface;
+    apply_settings(&state, settings)?;
+    let mut viewer_settings = state.viewer_settings.lock().unwrap().clone();
+    viewer_settings.multicast_interface = interface;
+    apply_viewer_settings(&app, &state, viewer_settings);
+    Ok(match interface {
+        Some(interface) => format!("Multicast interface set to {}", interface),
+        None => "Multicast interface chosen by the system".to_string(),
+    })
+}
+
+/// Both sides switch together, viewers only see servers of the same version
+#[tauri::command]
+fn set_ip_version(app: tauri::AppHandle, state: State<'_, AppState>, version: net_interfaces::IpVersion) -> Result<String, String> {
+    let mut settings = state.settings.lock().unwrap().clone();
+    settings.ip_version = version;
+    apply_settings(&state, settings)?;
+    let mut viewer_settings = state.viewer_settings.lock().unwrap().clone();
+    viewer_settings.ip_version = version;
+    apply_viewer_settings(&app, &state, viewer_settings);
+    Ok(format!("Streaming over {:?} (applied on next start)", version))
+}
+
+#[tauri::command]
+fn get_calibration(state: State<'_, AppState>) -> Option<calibration::Calibration> {
+    state.calibration.lock().unwrap().clone()
+}
+
+/// Measure again, e.g. after a hardware or network change
+#[tauri::command]
+async fn recalibrate(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<calibration::MachineDefaults, String> {
+    let calibration = tokio::task::spawn_blocking(calibration::Calibration::run)
+        .await
+        .map_err(|e| format!("Calibration failed: {}", e))??;
+    let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    calibration.save(&dir)?;
+    let defaults = calibration.defaults();
+    apply_calibration(&app, &state, calibration);
+    Ok(defaults)
+}
+
+/// A full-resolution still of a display, returned as base64 or saved to `path`
+#[tauri::command]
+async fn capture_screenshot(
+    display_index: Option<usize>,
+    format: screenshot::ScreenshotFormat,
+    path: Option<String>,
+) -> Result<screenshot::Screenshot, String> {
+    tokio::task::spawn_blocking(move || screenshot::take(display_index, format, path.as_deref().map(std::path::Path::new)))
+        .await
+        .map_err(|e| format!("Screenshot failed: {}", e))?
+}
+
 #[tauri::command]
 fn get_displays() -> Result<Vec<DisplayInfo>, String> {
     let displays = screen_capture::get_displays()?;
@@ -84,21 +1320,192 @@ fn get_displays() -> Result<Vec<DisplayInfo>, String> {
         .collect())
 }
 
+/// Receive the multicast stream without a window and re-serve it over WebSocket
+pub fn run_headless_receiver(port: Option<u16>, ipv6: bool) -> Result<(), String> {
+    let port = port.unwrap_or(ws_receiver::DEFAULT_PORT);
+    let ip_version = if ipv6 { net_interfaces::IpVersion::V6 } else { net_interfaces::IpVersion::V4 };
+    ws_receiver::run(port, ip_version)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .manage(AppState {
-            server: Mutex::new(None),
-            client: Mutex::new(None),
+        .manage(AppState::new(CaptureInput::Screen))
+        // Compositing tiles into the latest frame takes a while, off the webview's thread
+        .register_asynchronous_uri_scheme_protocol(latest_frame::SCHEME, |ctx, request, responder| {
+            let latest = ctx.app_handle().state::<AppState>().latest_frame.clone();
+            let path = request.uri().path().to_string();
+            tauri::async_runtime::spawn_blocking(move || responder.respond(latest_frame::respond(&latest, &path)));
+        })
+        .setup(|app| {
+            let handle = app.handle().clone();
+            power::watch_resume(move |_| {
+                tauri::async_runtime::spawn(system_resumed(handle.clone()));
+            });
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn_blocking(move || calibrate_on_startup(handle));
+            Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             start_server,
             stop_server,
+            toggle_freeze,
+            set_stream_quality,
+            set_target_fps,
+            set_bandwidth_limit,
+            approve_viewer,
+            deny_viewer,
+            start_room,
+            stop_room,
+            toggle_room_freeze,
+            approve_room_viewer,
+            deny_room_viewer,
+            list_rooms,
+            get_status,
+            get_stats,
+            get_stream_stats,
+            get_connected_clients,
+            enable_remote_control,
+            disable_remote_control,
+            send_remote_input,
+            select_display,
+            select_displays,
+            set_stream_resolution,
+            set_stream_encoder,
+            start_virtual_display,
+            stop_virtual_display,
+            subscribe_frames,
+            ack_frame,
             start_client,
             stop_client,
-            get_displays
+            get_settings,
+            update_settings,
+            get_viewer_settings,
+            update_viewer_settings,
+            set_smoothing,
+            set_stream_passphrase,
+            set_viewer_passphrase,
+            set_stream_pin,
+            set_viewer_pin,
+            set_require_approval,
+            set_viewer_server_address,
+            set_viewer_ports,
+            start_control_api,
+            stop_control_api,
+            start_trigger_listener,
+            stop_trigger_listener,
+            enable_clipboard_sync,
+            disable_clipboard_sync,
+            get_displays,
+            discover_servers,
+            get_network_interfaces,
+            set_multicast_interface,
+            set_ip_version,
+            get_calibration,
+            recalibrate,
+            get_rtp_sdp,
+            set_chunk_diagnostics,
+            start_recording,
+            stop_recording,
+            start_viewer_recording,
+            stop_viewer_recording,
+            decrypt_recording,
+            get_capture_capabilities,
+            capture_screenshot,
+            list_windows,
+            capture_window
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tauri::test::{mock_app, MockRuntime};
+
+    // Synthetic frames and the mock runtime: no display or window needed
+    fn test_app() -> tauri::App<MockRuntime> {
+        let app = mock_app();
+        app.manage(AppState::new(CaptureInput::Synthetic { width: 320, height: 240 }));
+        app
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_server_start_stop_sequence() {
+        let app = test_app();
+        let state = app.state::<AppState>();
+        assert!(!status_snapshot(&state).server_running);
+
+        server_start(app.handle().clone(), &state).await.unwrap();
+        assert!(status_snapshot(&state).server_running);
+
+        server_stop(&state).unwrap();
+        assert!(!status_snapshot(&state).server_running);
+        // Stopping twice is harmless
+        server_stop(&state).unwrap();
+
+        // A stopped server can be started again
+        server_start(app.handle().clone(), &state).await.unwrap();
+        assert!(status_snapshot(&state).server_running);
+        server_stop(&state).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_double_start_is_rejected() {
+        let app = test_app();
+        let state = app.state::<AppState>();
+
+        server_start(app.handle().clone(), &state).await.unwrap();
+        let err = server_start(app.handle().clone(), &state).await.unwrap_err();
+        assert!(err.contains("already running"), "{}", err);
+        // The first session keeps running
+        assert!(status_snapshot(&state).server_running);
+        server_stop(&state).unwrap();
+    }
+
+    #[test]
+    fn test_apply_settings() {
+        let app = test_app();
+        let state = app.state::<AppState>();
+
+        let invalid = StreamSettings { min_fps: 0, ..StreamSettings::default() };
+        assert!(apply_settings(&state, invalid).is_err());
+        assert_eq!(state.settings.lock().unwrap().min_fps, StreamSettings::default().min_fps);
+        assert!(apply_settings(&state, StreamSettings { max_width: 100, ..StreamSettings::default() }).is_err());
+        let mut png = StreamSettings::default();
+        png.set_encoder_mode(settings::EncoderMode::Png);
+        assert_eq!(png.encoder_mode(), settings::EncoderMode::Png);
+        assert!(apply_settings(&state, StreamSettings { tile_delta: true, ..png }).is_err());
+
+        let settings = StreamSettings { target_fps: 10, min_fps: 5, max_fps: 15, ..StreamSettings::default() };
+        apply_settings(&state, settings).unwrap();
+        let applied = state.settings.lock().unwrap().clone();
+        assert_eq!((applied.target_fps, applied.min_fps, applied.max_fps), (10, 5, 15));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_stats_follow_server_lifecycle() {
+        let app = test_app();
+        let state = app.state::<AppState>();
+        let settings = StreamSettings { target_fps: 10, min_fps: 5, max_fps: 15, ..StreamSettings::default() };
+        apply_settings(&state, settings).unwrap();
+
+        let stats = stats_snapshot(&state);
+        assert!(stats.server.is_none() && stats.client.is_none());
+
+        server_start(app.handle().clone(), &state).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let server = stats_snapshot(&state).server.expect("server stats while running").current;
+        // Sends may fail without a network, but whatever was sent respects the settings
+        if server.frames_sent > 0 {
+            assert!((5..=15).contains(&server.target_fps), "target_fps {}", server.target_fps);
+        }
+        assert_eq!(server.reporting_receivers, 0);
+
+        server_stop(&state).unwrap();
+        assert!(stats_snapshot(&state).server.is_none());
+    }
+}
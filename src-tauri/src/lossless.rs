@@ -0,0 +1,166 @@
+// Lossless - zstd-compressed RGB and PNG frames
+// JPEG smears the edges of small text and thin lines, which is what code
+// reviews and CAD work are all about. With `lossless` on, the main display is
+// sent as its raw RGB pixels squeezed with zstd instead: pixel-perfect, but
+// several times the bandwidth of JPEG, so only for gigabit links. Every frame
+// stands alone like a JPEG does. Receivers hand the webview a PNG, the only
+// lossless format it decodes natively.
+//
+// `png` sends PNG in the first place: far smaller than zstd for slides and
+// dashboards, but slow enough to encode that the frame rate drops, which
+// content that rarely changes doesn't mind. Receivers pass it straight through.
+//
+// Payload: "ZRGB" | width u16 | height u16 | zstd(RGB rows)
+
+use image::codecs::png::{CompressionType, FilterType};
+use image::RgbImage;
+use crate::hw_encoder::{self, EncoderConfig, EncoderType, VideoEncoder};
+
+pub const LOSSLESS_MAGIC: &[u8; 4] = b"ZRGB";
+const PAYLOAD_HEADER_SIZE: usize = 8;
+// Level 1 keeps a 1080p frame around 10ms; higher levels cost far more time than they save bytes
+const ZSTD_LEVEL: i32 = 1;
+
+pub struct LosslessEncoder {
+    width: usize,
+    height: usize,
+}
+
+impl LosslessEncoder {
+    pub fn new(config: &EncoderConfig) -> Result<Self, String> {
+        if config.width > u16::MAX as usize || config.height > u16::MAX as usize {
+            return Err(format!("{}x{} is too large for lossless frames", config.width, config.height));
+        }
+        Ok(Self { width: config.width, height: config.height })
+    }
+}
+
+impl VideoEncoder for LosslessEncoder {
+    fn encode(&mut self, rgba: &[u8]) -> Result<Vec<u8>, String> {
+        hw_encoder::check_frame_size(rgba, self.width, self.height)?;
+        let rgb = hw_encoder::rgba_to_rgb(rgba, self.width, self.height);
+        let compressed = zstd::bulk::compress(&rgb, ZSTD_LEVEL)
+            .map_err(|e| format!("zstd compression failed: {}", e))?;
+
+        let mut out = Vec::with_capacity(PAYLOAD_HEADER_SIZE + compressed.len());
+        out.extend_from_slice(LOSSLESS_MAGIC);
+        out.extend_from_slice(&(self.width as u16).to_be_bytes());
+        out.extend_from_slice(&(self.height as u16).to_be_bytes());
+        out.extend_from_slice(&compressed);
+        Ok(out)
+    }
+
+    fn encoder_type(&self) -> EncoderType {
+        EncoderType::Lossless
+    }
+
+    fn set_bitrate(&mut self, _bitrate: u32) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn set_fps(&mut self, _fps: u32) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+pub struct PngEncoder {
+    width: usize,
+    height: usize,
+}
+
+impl PngEncoder {
+    pub fn new(config: &EncoderConfig) -> Self {
+        Self { width: config.width, height: config.height }
+    }
+}
+
+impl VideoEncoder for PngEncoder {
+    fn encode(&mut self, rgba: &[u8]) -> Result<Vec<u8>, String> {
+        hw_encoder::check_frame_size(rgba, self.width, self.height)?;
+        let rgb = hw_encoder::rgba_to_rgb(rgba, self.width, self.height);
+        // Adaptive filtering pays off on the flat areas and gradients of slides
+        encode_png(&rgb, self.width as u32, self.height as u32, FilterType::Adaptive)
+    }
+
+    fn encoder_type(&self) -> EncoderType {
+        EncoderType::Png
+    }
+
+    fn set_bitrate(&mut self, _bitrate: u32) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn set_fps(&mut self, _fps: u32) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+pub fn is_png_payload(data: &[u8]) -> bool {
+    data.starts_with(b"\x89PNG")
+}
+
+pub fn is_lossless_payload(data: &[u8]) -> bool {
+    data.starts_with(LOSSLESS_MAGIC)
+}
+
+/// The frame in a lossless payload
+pub fn decode(data: &[u8]) -> Result<RgbImage, String> {
+    if data.len() < PAYLOAD_HEADER_SIZE || !is_lossless_payload(data) {
+        return Err("Not a lossless frame".to_string());
+    }
+    let width = u16::from_be_bytes([data[4], data[5]]) as u32;
+    let height = u16::from_be_bytes([data[6], data[7]]) as u32;
+    let expected = width as usize * height as usize * 3;
+    let rgb = zstd::bulk::decompress(&data[PAYLOAD_HEADER_SIZE..], expected)
+        .map_err(|e| format!("zstd decompression failed: {}", e))?;
+    if rgb.len() != expected {
+        return Err(format!("Lossless frame holds {} bytes, expected {} for {}x{}", rgb.len(), expected, width, height));
+    }
+    RgbImage::from_raw(width, height, rgb).ok_or_else(|| "Failed to create image buffer".to_string())
+}
+
+/// A lossless payload as PNG for the webview, lightly filtered since it only crosses IPC
+pub fn decode_to_png(data: &[u8]) -> Result<Vec<u8>, String> {
+    let img = decode(data)?;
+    encode_png(img.as_raw(), img.width(), img.height(), FilterType::Sub)
+}
+
+fn encode_png(rgb: &[u8], width: u32, height: u32, filter: FilterType) -> Result<Vec<u8>, String> {
+    use image::ImageEncoder;
+
+    let mut png = Vec::new();
+    image::codecs::png::PngEncoder::new_with_quality(&mut png, CompressionType::Fast, filter)
+        .write_image(rgb, width, height, image::ExtendedColorType::Rgb8)
+        .map_err(|e| format!("PNG encoding failed: {}", e))?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lossless_round_trip() {
+        let config = EncoderConfig {
+            width: 7,
+            height: 5,
+            fps: 30,
+            bitrate: 0,
+            encoder_type: EncoderType::Lossless,
+            quality: 0,
+            workers: 1,
+            speed: hw_encoder::DEFAULT_ENCODER_SPEED,
+        };
+        let rgba: Vec<u8> = (0..7 * 5 * 4).map(|i| (i * 37 % 256) as u8).collect();
+        let payload = LosslessEncoder::new(&config).unwrap().encode(&rgba).unwrap();
+        assert!(is_lossless_payload(&payload));
+
+        let img = decode(&payload).unwrap();
+        assert_eq!(img.as_raw(), &hw_encoder::rgba_to_rgb(&rgba, 7, 5));
+        assert!(is_png_payload(&decode_to_png(&payload).unwrap()));
+        let png = PngEncoder::new(&config).encode(&rgba).unwrap();
+        let decoded = image::load_from_memory_with_format(&png, image::ImageFormat::Png).unwrap().to_rgb8();
+        assert_eq!(decoded.as_raw(), img.as_raw());
+        assert!(decode(&payload[..payload.len() - 1]).is_err());
+    }
+}
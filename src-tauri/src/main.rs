@@ -2,5 +2,17 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // `--headless-receiver [port] [--ipv6]` re-serves the stream over WebSocket without opening a window
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(pos) = args.iter().position(|a| a == "--headless-receiver") {
+        let port = args.get(pos + 1).and_then(|p| p.parse().ok());
+        let ipv6 = args.iter().any(|a| a == "--ipv6");
+        if let Err(e) = screensharing_capturescreen_udpboaarrdcast_lib::run_headless_receiver(port, ipv6) {
+            eprintln!("❌ Headless receiver failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     screensharing_capturescreen_udpboaarrdcast_lib::run()
 }
@@ -0,0 +1,186 @@
+// MJPEG Server - the live stream at a plain browser URL
+// http://<host>:<port>/stream answers with multipart/x-mixed-replace, one JPEG
+// per part, which browsers, <img> tags and most signage players show as video
+// without any script. http://<host>:<port>/ wraps it in a full-window page.
+//
+// JPEG encoders' frames are passed through as they are. Tile deltas and H.264
+// can't be shown on their own, so for those the raw frame is encoded again,
+// and only while somebody is watching.
+//
+// No access control or encryption here, so servers requiring either don't
+// offer it (see StreamSettings::validate).
+
+use std::convert::Infallible;
+use std::sync::Mutex;
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::header,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use futures_util::stream;
+use tokio::sync::{broadcast::error::RecvError, oneshot};
+use crate::preview;
+use crate::screen_capture::RawFrame;
+use crate::ws_receiver::FrameBroadcast;
+
+pub const DEFAULT_PORT: u16 = 8090;
+const BOUNDARY: &str = "frame";
+
+/// Running MJPEG endpoint, stopped on `stop()` or drop
+pub struct MjpegServer {
+    frames: FrameBroadcast,
+    quality: u8,
+    shutdown: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl MjpegServer {
+    pub fn start(port: u16, quality: u8) -> Result<Self, String> {
+        // Its own runtime, so frames can be handed over from any thread
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(|e| format!("Failed to create MJPEG runtime: {}", e))?;
+        let listener = match runtime.block_on(tokio::net::TcpListener::bind(("0.0.0.0", port))) {
+            Ok(listener) => listener,
+            Err(e) => {
+                runtime.shutdown_background();
+                return Err(format!("Failed to bind MJPEG server on port {}: {}", port, e));
+            }
+        };
+
+        let frames = FrameBroadcast::new();
+        let router = Router::new()
+            .route("/", get(viewer_page))
+            .route("/stream", get(stream_parts))
+            .with_state(frames.clone());
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+
+        std::thread::Builder::new()
+            .name("mjpeg-server".to_string())
+            .spawn(move || {
+                // Not a graceful shutdown: open streams never finish on their own
+                let result = runtime.block_on(async {
+                    tokio::select! {
+                        result = axum::serve(listener, router) => result,
+                        _ = shutdown_rx => Ok(()),
+                    }
+                });
+                if let Err(e) = result {
+                    eprintln!("❌ MJPEG server error: {}", e);
+                }
+                runtime.shutdown_background();
+                eprintln!("🔴 MJPEG server stopped");
+            })
+            .map_err(|e| format!("Failed to spawn MJPEG thread: {}", e))?;
+
+        eprintln!("🌐 MJPEG stream on http://0.0.0.0:{}/stream", port);
+        Ok(Self {
+            frames,
+            quality,
+            shutdown: Mutex::new(Some(shutdown)),
+        })
+    }
+
+    /// Show the frame encoded as `payload`, encoding `frame` when the payload isn't a JPEG
+    pub fn publish(&self, payload: &[u8], frame: &RawFrame) {
+        if !self.frames.has_viewers() {
+            return;
+        }
+        let jpeg = if payload.starts_with(&[0xFF, 0xD8]) {
+            payload.to_vec()
+        } else {
+            match preview::scaled_jpeg(frame, frame.width as u32, self.quality) {
+                Ok(jpeg) => jpeg,
+                Err(e) => {
+                    eprintln!("❌ MJPEG encode error: {}", e);
+                    return;
+                }
+            }
+        };
+        self.frames.publish(jpeg, true);
+    }
+
+    pub fn stop(&self) {
+        if let Some(shutdown) = self.shutdown.lock().unwrap().take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+impl Drop for MjpegServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// One multipart part holding `jpeg`
+fn part(jpeg: &[u8]) -> Bytes {
+    let mut part = format!(
+        "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        BOUNDARY,
+        jpeg.len()
+    )
+    .into_bytes();
+    part.extend_from_slice(jpeg);
+    part.extend_from_slice(b"\r\n");
+    Bytes::from(part)
+}
+
+async fn stream_parts(State(frames): State<FrameBroadcast>) -> Response {
+    // New viewers see the latest frame right away instead of waiting for a change
+    let (latest, rx) = frames.subscribe();
+    let parts = stream::unfold((latest, rx), |(latest, mut rx)| async move {
+        if let Some(jpeg) = latest {
+            return Some((Ok::<_, Infallible>(part(&jpeg)), (None, rx)));
+        }
+        loop {
+            match rx.recv().await {
+                Ok(jpeg) => return Some((Ok(part(&jpeg)), (None, rx))),
+                // Slow viewer - skip to the live frames
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    (
+        [
+            (header::CONTENT_TYPE, format!("multipart/x-mixed-replace; boundary={}", BOUNDARY)),
+            (header::CACHE_CONTROL, "no-cache".to_string()),
+        ],
+        Body::from_stream(parts),
+    )
+        .into_response()
+}
+
+async fn viewer_page() -> Html<&'static str> {
+    Html(VIEWER_HTML)
+}
+
+const VIEWER_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>SmartLab ScreenShare</title>
+<style>html,body{margin:0;height:100%;background:#000}img{width:100%;height:100%;object-fit:contain}</style>
+</head>
+<body>
+<img src="/stream" alt="">
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_part_framing() {
+        let part = part(&[0xFF, 0xD8, 0xFF, 0xD9]);
+        let expected = b"--frame\r\nContent-Type: image/jpeg\r\nContent-Length: 4\r\n\r\n\xFF\xD8\xFF\xD9\r\n";
+        assert_eq!(&part[..], &expected[..]);
+    }
+}
@@ -0,0 +1,209 @@
+// Network Interfaces - pick which NIC and IP version the multicast stream uses
+// With VPN adapters or several NICs, letting the OS choose (UNSPECIFIED)
+// often sends or joins the group on the wrong one, e.g. the VPN tunnel.
+// Server and viewer can name the interface by one of its addresses instead.
+// IPv6 streams use a link-local group, so they never leave the lab segment.
+// Both ends also ask for big socket buffers here, see set_socket_buffer.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, UdpSocket};
+use serde::{Deserialize, Serialize};
+use socket2::SockRef;
+
+pub const MULTICAST_GROUP_V4: Ipv4Addr = Ipv4Addr::new(239, 0, 0, 1);
+pub const MULTICAST_GROUP_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0xef00, 1);
+
+/// Which multicast group the stream uses, chosen before start
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpVersion {
+    #[default]
+    V4,
+    /// ff02:: group, for IPv6-only networks
+    V6,
+}
+
+impl IpVersion {
+    pub fn group(&self) -> IpAddr {
+        match self {
+            IpVersion::V4 => MULTICAST_GROUP_V4.into(),
+            IpVersion::V6 => MULTICAST_GROUP_V6.into(),
+        }
+    }
+
+    /// Where the stream is sent; link-local IPv6 groups need the interface as scope
+    pub fn stream_addr(&self, port: u16, interface_index: u32) -> SocketAddr {
+        self.layer_addr(0, port, interface_index)
+    }
+
+    /// Group of a simulcast layer: the main group for layer 0, the ones after it for the rest
+    pub fn layer_group(&self, layer: u8) -> IpAddr {
+        layer_group_of(self.group(), layer)
+    }
+
+    pub fn layer_addr(&self, layer: u8, port: u16, interface_index: u32) -> SocketAddr {
+        match self.layer_group(layer) {
+            IpAddr::V4(group) => SocketAddr::new(group.into(), port),
+            IpAddr::V6(group) => SocketAddrV6::new(group, port, 0, interface_index).into(),
+        }
+    }
+
+    pub fn unspecified(&self) -> IpAddr {
+        match self {
+            IpVersion::V4 => Ipv4Addr::UNSPECIFIED.into(),
+            IpVersion::V6 => Ipv6Addr::UNSPECIFIED.into(),
+        }
+    }
+
+    pub fn of(address: &IpAddr) -> Self {
+        match address {
+            IpAddr::V4(_) => IpVersion::V4,
+            IpAddr::V6(_) => IpVersion::V6,
+        }
+    }
+
+    pub fn matches(&self, address: &IpAddr) -> bool {
+        matches!((self, address), (IpVersion::V4, IpAddr::V4(_)) | (IpVersion::V6, IpAddr::V6(_)))
+    }
+}
+
+/// Group of a simulcast layer of the stream on `group`, see IpVersion::layer_group
+pub fn layer_group_of(group: IpAddr, layer: u8) -> IpAddr {
+    match group {
+        IpAddr::V4(group) => {
+            let [a, b, c, d] = group.octets();
+            Ipv4Addr::new(a, b, c, d.wrapping_add(layer)).into()
+        }
+        IpAddr::V6(group) => {
+            let mut segments = group.segments();
+            segments[7] = segments[7].wrapping_add(layer as u16);
+            Ipv6Addr::from(segments).into()
+        }
+    }
+}
+
+/// Parse a multicast group for `ip_version` streams, for servers not on the default one
+pub fn parse_multicast_group(address: &str, ip_version: IpVersion) -> Result<IpAddr, String> {
+    let group: IpAddr = address.trim().parse()
+        .map_err(|_| format!("Invalid multicast group {:?}", address))?;
+    if !group.is_multicast() {
+        return Err(format!("{} is not a multicast address", group));
+    }
+    if !ip_version.matches(&group) {
+        return Err(format!("{} is not an {:?} group", group, ip_version));
+    }
+    Ok(group)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub address: IpAddr,
+    /// OS interface index, what IPv6 multicast selects interfaces by
+    pub index: u32,
+}
+
+/// Interfaces that can carry the stream (loopback left out)
+pub fn list_interfaces() -> Result<Vec<NetworkInterface>, String> {
+    let interfaces = if_addrs::get_if_addrs()
+        .map_err(|e| format!("Failed to list network interfaces: {}", e))?;
+    Ok(interfaces
+        .into_iter()
+        .filter(|iface| !iface.is_loopback())
+        .map(|iface| NetworkInterface {
+            address: iface.ip(),
+            index: iface.index.unwrap_or(0),
+            name: iface.name,
+        })
+        .collect())
+}
+
+/// Parse a chosen interface address and check this machine has it
+pub fn parse_interface(address: &str) -> Result<IpAddr, String> {
+    let address: IpAddr = address.trim().parse()
+        .map_err(|_| format!("Invalid interface address {:?}", address))?;
+    if !list_interfaces()?.iter().any(|iface| iface.address == address) {
+        return Err(format!("No network interface with address {}", address));
+    }
+    Ok(address)
+}
+
+/// IPv4 interface to use for multicast, the OS default when none was chosen
+pub fn multicast_interface(chosen: Option<IpAddr>) -> Result<Ipv4Addr, String> {
+    match chosen {
+        None => Ok(Ipv4Addr::UNSPECIFIED),
+        Some(IpAddr::V4(address)) => Ok(address),
+        Some(IpAddr::V6(address)) => Err(format!("IPv4 multicast needs an IPv4 interface address, not {}", address)),
+    }
+}
+
+/// Index of the interface holding `chosen`, 0 (the OS default) when none was chosen
+pub fn multicast_interface_index(chosen: Option<IpAddr>) -> Result<u32, String> {
+    let Some(address) = chosen else {
+        return Ok(0);
+    };
+    list_interfaces()?
+        .into_iter()
+        .find(|iface| iface.address == address)
+        .map(|iface| iface.index)
+        .ok_or_else(|| format!("No network interface with address {}", address))
+}
+
+/// Which of a socket's kernel buffers to size
+#[derive(Debug, Clone, Copy)]
+pub enum SocketBuffer {
+    Send,
+    Receive,
+}
+
+/// Ask the OS for a `kb` buffer on `socket`, 0 leaves it as it is. What
+/// doesn't fit is dropped before it's sent or read; OSes may grant less,
+/// which is logged.
+pub fn set_socket_buffer(socket: &UdpSocket, buffer: SocketBuffer, kb: usize) {
+    if kb == 0 {
+        return;
+    }
+    let requested = kb * 1024;
+    let socket = SockRef::from(socket);
+    let (name, sysctl, granted) = match buffer {
+        SocketBuffer::Send => ("send", "wmem_max", socket.set_send_buffer_size(requested).and_then(|()| socket.send_buffer_size())),
+        SocketBuffer::Receive => ("receive", "rmem_max", socket.set_recv_buffer_size(requested).and_then(|()| socket.recv_buffer_size())),
+    };
+    match granted {
+        // Linux reports twice what it granted, a full grant never shows up here
+        Ok(granted) if granted < requested => {
+            eprintln!("⚠️  The {} buffer is {} KB, not {} KB; the OS caps it (net.core.{} on Linux)", name, granted / 1024, kb, sysctl);
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("⚠️  Failed to set the {} buffer size: {}", name, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_addresses() {
+        assert_eq!(IpVersion::V4.stream_addr(9999, 3), "239.0.0.1:9999".parse().unwrap());
+        let v6 = IpVersion::V6.stream_addr(9999, 3);
+        assert!(v6.ip().is_multicast());
+        assert_eq!(v6, "[ff02::ef00:1%3]:9999".parse().unwrap());
+        assert!(IpVersion::V6.matches(&v6.ip()));
+        assert!(!IpVersion::V4.matches(&v6.ip()));
+
+        assert_eq!(IpVersion::V4.layer_addr(2, 9999, 0), "239.0.0.3:9999".parse().unwrap());
+        assert_eq!(IpVersion::V6.layer_addr(1, 9999, 3), "[ff02::ef00:2%3]:9999".parse().unwrap());
+
+        let group = parse_multicast_group("239.1.2.10", IpVersion::V4).unwrap();
+        assert_eq!(layer_group_of(group, 1), "239.1.2.11".parse::<IpAddr>().unwrap());
+        assert!(parse_multicast_group("192.168.1.10", IpVersion::V4).is_err());
+        assert!(parse_multicast_group("239.1.2.10", IpVersion::V6).is_err());
+    }
+
+    #[test]
+    fn test_ipv4_interface_must_be_ipv4() {
+        assert_eq!(multicast_interface(None), Ok(Ipv4Addr::UNSPECIFIED));
+        assert!(multicast_interface(Some(Ipv6Addr::LOCALHOST.into())).is_err());
+        assert_eq!(multicast_interface_index(None), Ok(0));
+    }
+}
@@ -0,0 +1,697 @@
+// Packet Protocol - header carried by every UDP chunk
+//
+// Layout (big-endian, 35 bytes):
+//   "SLV" | version u8 | frame_id u32 | chunk_idx u32 | total_chunks u32 | frame_type u8 | sequence u32
+//   | capture_ts u32 | send_ts u32 | epoch u16 | stream_id u32
+// followed by the chunk and a CRC-32 of everything before it (u32).
+// Before it's split up every frame gets a CRC-32 of its own put in front, so
+// chunk 0 starts with it.
+//
+// The top bit of the frame_type byte marks a payload sealed with the
+// session passphrase (see stream_crypto.rs); the header itself stays readable.
+// Bits 4-6 carry the simulcast layer (see simulcast.rs), 0 for the main stream,
+// so main stream packets look the same as before layers existed.
+// Bits 1-3 carry the stream ID of servers sharing several displays at once
+// (see display_streams.rs), 0 for the main display. Viewers from before
+// stream IDs drop the other displays' packets as invalid.
+//
+// `epoch` is picked at random for every server session. Frame IDs, sequence
+// numbers and timestamps restart with it, so receivers reset when it changes.
+// `stream_id` names the server's stream as a whole, all its displays and
+// layers, and stays the same across restarts when configured (see
+// StreamSettings::stream_id). With several servers sending to one group a
+// viewer that subscribed to an ID only reassembles that server's frames.
+// Viewers from before stream IDs can't read these packets.
+//
+// The magic and PROTOCOL_VERSION let receivers drop whatever else lands on
+// the port, and tell a server speaking another version apart from noise. The
+// version goes up with every change to the layout after it, so a viewer never
+// misreads a grown header as a shorter one. Version 1 had no checksums,
+// version 2 no frame checksum.
+//
+// Some Wi-Fi drivers hand over datagrams with flipped bits despite the UDP
+// checksum (or with it zeroed). The CRC catches those, and the chunk is
+// dropped as lost instead of ending up inside a JPEG as garbled blocks. The
+// frame's CRC catches what every chunk passing its own can't: chunks of two
+// frames with the same ID, after the frame ID wrapped around or the stream
+// restarted, put together as one. Only a complete frame can be checked; one
+// missing chunks is shown unchecked, and only when it's JPEG, which a mix-up
+// garbles for that frame alone (decoders with state never see partial frames).
+//
+// Timestamps are microseconds on the sender's monotonic stream clock (see clock.rs).
+//
+// `sequence` counts every encoded frame, including ones that failed to send,
+// so a receiver can tell when it missed a frame a delta depends on.
+// Receivers that lost sync send KEYFRAME_REQUEST back to the sender, and every
+// receiver sends a ReceiverReport about once a second.
+//
+// Audio travels on the same multicast group as separate datagrams:
+//   "AUDI" | channel u8 | sequence u32 | capture_ts u32 | Opus packet
+// with the same encryption bit in the channel byte.
+// So does the pointer, when it is sent apart from the frames (cursor_stream.rs):
+//   "CURS" | flags u8 | sequence u32 | capture_ts u32 | x u16 | y u16
+//   | visible u8 | shape u32
+// with everything after the sequence sealed when the stream is encrypted.
+// And once a second, frames or not, a heartbeat:
+//   "BEAT" | epoch u16 | stream_id u32 | send_ts u32
+// so a server with nothing new to show can be told apart from one that's gone.
+// Sessions are announced when they start and whenever the frame size or
+// codec changes, and again every few seconds for viewers who join, and say
+// goodbye when the host stops, so viewers reset right away instead of timing out:
+//   "SESS" | kind u8 | epoch u16 | stream_id u32
+// with width u16 | height u16 | codec u8 following when a session started.
+// Neither carries anything worth sealing.
+// Every kind of datagram starts with a magic of its own.
+
+use crate::hw_encoder::EncoderType;
+use crate::tile_delta;
+use crate::video_decoder;
+
+pub const HEADER_SIZE: usize = 35;
+pub const VIDEO_MAGIC: &[u8; 3] = b"SLV";
+/// Layout of the video header, see above
+pub const PROTOCOL_VERSION: u8 = 3;
+pub const CHECKSUM_SIZE: usize = 4;
+pub const FRAME_CHECKSUM_SIZE: usize = 4;
+// Magic and version, ahead of the fields
+const PREFIX_SIZE: usize = 4;
+const FLAG_ENCRYPTED: u8 = 0x80;
+const LAYER_SHIFT: u8 = 4;
+const LAYER_MASK: u8 = 0x70;
+/// Highest simulcast layer the header can carry
+pub const MAX_LAYER: u8 = LAYER_MASK >> LAYER_SHIFT;
+const STREAM_SHIFT: u8 = 1;
+const STREAM_MASK: u8 = 0x0E;
+/// Highest display stream ID the header can carry
+pub const MAX_STREAM: u8 = STREAM_MASK >> STREAM_SHIFT;
+pub const KEYFRAME_REQUEST: &[u8; 8] = b"KEYFRAME";
+pub const REPORT_MAGIC: &[u8; 4] = b"RRPT";
+const REPORT_SIZE: usize = 24;
+pub const AUDIO_MAGIC: &[u8; 4] = b"AUDI";
+pub const AUDIO_HEADER_SIZE: usize = 13;
+/// Channel ID of the system audio stream (video is implicitly channel 0)
+pub const AUDIO_CHANNEL: u8 = 1;
+pub const CURSOR_MAGIC: &[u8; 4] = b"CURS";
+pub const CURSOR_HEADER_SIZE: usize = 9;
+const CURSOR_STATE_SIZE: usize = 13;
+pub const HEARTBEAT_MAGIC: &[u8; 4] = b"BEAT";
+const HEARTBEAT_SIZE: usize = 14;
+pub const SESSION_MAGIC: &[u8; 4] = b"SESS";
+const SESSION_STOPPED_SIZE: usize = 11;
+const SESSION_STARTED_SIZE: usize = 16;
+const SESSION_STARTED: u8 = 1;
+const SESSION_STOPPED: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    /// Decodable on its own, a reset point for the decoder
+    Key = 1,
+    /// Only valid on top of the frames before it
+    Delta = 0,
+}
+
+impl FrameType {
+    /// Work out the frame type from an encoded payload
+    pub fn of_payload(data: &[u8]) -> Self {
+        if tile_delta::is_tile_payload(data) {
+            FrameType::Delta
+        } else if video_decoder::is_av1_payload(data) {
+            if video_decoder::is_av1_keyframe(data) { FrameType::Key } else { FrameType::Delta }
+        } else if data.starts_with(&[0, 0, 1]) || data.starts_with(&[0, 0, 0, 1]) {
+            if has_idr_nal(data) { FrameType::Key } else { FrameType::Delta }
+        } else {
+            // JPEG and other intra-only payloads
+            FrameType::Key
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketHeader {
+    pub frame_id: u32,
+    pub chunk_idx: u32,
+    pub total_chunks: u32,
+    pub frame_type: FrameType,
+    pub sequence: u32,
+    /// When the frame was captured (or encoded, per `TimestampSource`)
+    pub capture_ts: u32,
+    /// When this packet was handed to the socket
+    pub send_ts: u32,
+    /// Server session this packet belongs to
+    pub epoch: u16,
+    /// Payload is sealed with the stream key
+    pub encrypted: bool,
+    /// Simulcast rendition, 0 is the main stream
+    pub layer: u8,
+    /// Display this frame shows, 0 is the main display
+    pub stream: u8,
+    /// Server stream the packet belongs to
+    pub stream_id: u32,
+}
+
+impl PacketHeader {
+    pub fn write(&self, packet: &mut Vec<u8>) {
+        packet.extend_from_slice(VIDEO_MAGIC);
+        packet.push(PROTOCOL_VERSION);
+        packet.extend_from_slice(&self.frame_id.to_be_bytes());
+        packet.extend_from_slice(&self.chunk_idx.to_be_bytes());
+        packet.extend_from_slice(&self.total_chunks.to_be_bytes());
+        packet.push(
+            self.frame_type as u8
+                | flag(self.encrypted)
+                | ((self.layer << LAYER_SHIFT) & LAYER_MASK)
+                | ((self.stream << STREAM_SHIFT) & STREAM_MASK),
+        );
+        packet.extend_from_slice(&self.sequence.to_be_bytes());
+        packet.extend_from_slice(&self.capture_ts.to_be_bytes());
+        packet.extend_from_slice(&self.send_ts.to_be_bytes());
+        packet.extend_from_slice(&self.epoch.to_be_bytes());
+        packet.extend_from_slice(&self.stream_id.to_be_bytes());
+    }
+
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < HEADER_SIZE || protocol_version(buf) != Some(PROTOCOL_VERSION) {
+            return None;
+        }
+        let buf = &buf[PREFIX_SIZE..];
+        let u32_at = |i: usize| u32::from_be_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        let frame_type = match buf[12] & !(FLAG_ENCRYPTED | LAYER_MASK | STREAM_MASK) {
+            0 => FrameType::Delta,
+            1 => FrameType::Key,
+            _ => return None,
+        };
+        Some(Self {
+            frame_id: u32_at(0),
+            chunk_idx: u32_at(4),
+            total_chunks: u32_at(8),
+            frame_type,
+            sequence: u32_at(13),
+            capture_ts: u32_at(17),
+            send_ts: u32_at(21),
+            epoch: u16::from_be_bytes([buf[25], buf[26]]),
+            encrypted: buf[12] & FLAG_ENCRYPTED != 0,
+            layer: (buf[12] & LAYER_MASK) >> LAYER_SHIFT,
+            stream: (buf[12] & STREAM_MASK) >> STREAM_SHIFT,
+            stream_id: u32_at(27),
+        })
+    }
+}
+
+/// Close a video packet, header and chunk written, with its checksum
+pub fn append_checksum(packet: &mut Vec<u8>) {
+    let checksum = crc32fast::hash(packet);
+    packet.extend_from_slice(&checksum.to_be_bytes());
+}
+
+/// The video packet in `buf` without its checksum, `None` when it doesn't match
+pub fn verify_checksum(buf: &[u8]) -> Option<&[u8]> {
+    let split = buf.len().checked_sub(CHECKSUM_SIZE)?;
+    let (packet, checksum) = buf.split_at(split);
+    (crc32fast::hash(packet).to_be_bytes() == checksum).then_some(packet)
+}
+
+/// `frame` led by its checksum, ready to be split into chunks
+pub fn add_frame_checksum(frame: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(FRAME_CHECKSUM_SIZE + frame.len());
+    data.extend_from_slice(&crc32fast::hash(frame).to_be_bytes());
+    data.extend_from_slice(frame);
+    data
+}
+
+/// The frame in reassembled `data`, `None` when it doesn't match its checksum
+pub fn verify_frame_checksum(data: &[u8]) -> Option<&[u8]> {
+    if data.len() < FRAME_CHECKSUM_SIZE {
+        return None;
+    }
+    let (checksum, frame) = data.split_at(FRAME_CHECKSUM_SIZE);
+    (crc32fast::hash(frame).to_be_bytes() == checksum).then_some(frame)
+}
+
+/// The frame in reassembled `data`: a complete one only when it matches its
+/// checksum, one missing chunks unchecked, without the checksum if chunk 0
+/// (`has_checksum`) arrived
+pub fn frame_payload(data: &[u8], complete: bool, has_checksum: bool) -> Option<&[u8]> {
+    match (complete, has_checksum) {
+        (true, _) => verify_frame_checksum(data),
+        (false, true) => Some(&data[FRAME_CHECKSUM_SIZE.min(data.len())..]),
+        (false, false) => Some(data),
+    }
+}
+
+/// Version of the video header `buf` starts with, `None` when it's no video packet
+pub fn protocol_version(buf: &[u8]) -> Option<u8> {
+    buf.starts_with(VIDEO_MAGIC).then(|| buf.get(VIDEO_MAGIC.len()).copied()).flatten()
+}
+
+/// Audio and pointer datagrams, which travel next to the video packets
+pub fn is_side_channel(buf: &[u8]) -> bool {
+    buf.starts_with(AUDIO_MAGIC) || buf.starts_with(CURSOR_MAGIC)
+}
+
+/// Header of one Opus packet, each carrying 20ms of audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioHeader {
+    pub channel: u8,
+    pub encrypted: bool,
+    pub sequence: u32,
+    /// Stream clock time of the first sample, same time base as video
+    pub capture_ts: u32,
+}
+
+impl AudioHeader {
+    pub fn write(&self, packet: &mut Vec<u8>) {
+        packet.extend_from_slice(AUDIO_MAGIC);
+        packet.push(self.channel | flag(self.encrypted));
+        packet.extend_from_slice(&self.sequence.to_be_bytes());
+        packet.extend_from_slice(&self.capture_ts.to_be_bytes());
+    }
+
+    /// Split an audio datagram into header and Opus payload
+    pub fn parse(buf: &[u8]) -> Option<(Self, &[u8])> {
+        if buf.len() < AUDIO_HEADER_SIZE || !buf.starts_with(AUDIO_MAGIC) {
+            return None;
+        }
+        let u32_at = |i: usize| u32::from_be_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        let header = Self {
+            channel: buf[4] & !FLAG_ENCRYPTED,
+            encrypted: buf[4] & FLAG_ENCRYPTED != 0,
+            sequence: u32_at(5),
+            capture_ts: u32_at(9),
+        };
+        Some((header, &buf[AUDIO_HEADER_SIZE..]))
+    }
+}
+
+/// Header of one pointer update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorHeader {
+    pub encrypted: bool,
+    pub sequence: u32,
+}
+
+impl CursorHeader {
+    pub fn write(&self, packet: &mut Vec<u8>) {
+        packet.extend_from_slice(CURSOR_MAGIC);
+        packet.push(flag(self.encrypted));
+        packet.extend_from_slice(&self.sequence.to_be_bytes());
+    }
+
+    /// Split a pointer datagram into header and (possibly sealed) state
+    pub fn parse(buf: &[u8]) -> Option<(Self, &[u8])> {
+        if buf.len() < CURSOR_HEADER_SIZE || !buf.starts_with(CURSOR_MAGIC) {
+            return None;
+        }
+        let header = Self {
+            encrypted: buf[4] & FLAG_ENCRYPTED != 0,
+            sequence: u32::from_be_bytes([buf[5], buf[6], buf[7], buf[8]]),
+        };
+        Some((header, &buf[CURSOR_HEADER_SIZE..]))
+    }
+}
+
+/// Where the pointer is on the captured display, in 1/65535ths of its width and height
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorState {
+    /// Stream clock time, same time base as video
+    pub capture_ts: u32,
+    pub x: u16,
+    pub y: u16,
+    /// Hidden, or off the captured display
+    pub visible: bool,
+    /// Hash of the pointer image, changes with its shape (see cursor_capture.rs)
+    pub shape: u32,
+}
+
+impl CursorState {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(CURSOR_STATE_SIZE);
+        out.extend_from_slice(&self.capture_ts.to_be_bytes());
+        out.extend_from_slice(&self.x.to_be_bytes());
+        out.extend_from_slice(&self.y.to_be_bytes());
+        out.push(self.visible as u8);
+        out.extend_from_slice(&self.shape.to_be_bytes());
+        out
+    }
+
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() != CURSOR_STATE_SIZE {
+            return None;
+        }
+        let u32_at = |i: usize| u32::from_be_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        Some(Self {
+            capture_ts: u32_at(0),
+            x: u16::from_be_bytes([buf[4], buf[5]]),
+            y: u16::from_be_bytes([buf[6], buf[7]]),
+            visible: buf[8] != 0,
+            shape: u32_at(9),
+        })
+    }
+}
+
+/// Sign of life from a server, sent whether or not frames are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heartbeat {
+    pub epoch: u16,
+    pub stream_id: u32,
+    /// Stream clock time it was sent, same time base as video
+    pub send_ts: u32,
+}
+
+impl Heartbeat {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEARTBEAT_SIZE);
+        out.extend_from_slice(HEARTBEAT_MAGIC);
+        out.extend_from_slice(&self.epoch.to_be_bytes());
+        out.extend_from_slice(&self.stream_id.to_be_bytes());
+        out.extend_from_slice(&self.send_ts.to_be_bytes());
+        out
+    }
+
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() != HEARTBEAT_SIZE || !buf.starts_with(HEARTBEAT_MAGIC) {
+            return None;
+        }
+        let u32_at = |i: usize| u32::from_be_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        Some(Self {
+            epoch: u16::from_be_bytes([buf[4], buf[5]]),
+            stream_id: u32_at(6),
+            send_ts: u32_at(10),
+        })
+    }
+}
+
+/// A stream session starting or ending
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionMessage {
+    /// Frames of this size and codec follow
+    StreamStarted { epoch: u16, stream_id: u32, width: u16, height: u16, codec: EncoderType },
+    /// The host stopped streaming, no more frames follow
+    StreamStopped { epoch: u16, stream_id: u32 },
+}
+
+impl SessionMessage {
+    pub fn epoch(&self) -> u16 {
+        match *self {
+            Self::StreamStarted { epoch, .. } | Self::StreamStopped { epoch, .. } => epoch,
+        }
+    }
+
+    pub fn stream_id(&self) -> u32 {
+        match *self {
+            Self::StreamStarted { stream_id, .. } | Self::StreamStopped { stream_id, .. } => stream_id,
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(SESSION_STARTED_SIZE);
+        out.extend_from_slice(SESSION_MAGIC);
+        out.push(match self {
+            Self::StreamStarted { .. } => SESSION_STARTED,
+            Self::StreamStopped { .. } => SESSION_STOPPED,
+        });
+        out.extend_from_slice(&self.epoch().to_be_bytes());
+        out.extend_from_slice(&self.stream_id().to_be_bytes());
+        if let Self::StreamStarted { width, height, codec, .. } = *self {
+            out.extend_from_slice(&width.to_be_bytes());
+            out.extend_from_slice(&height.to_be_bytes());
+            out.push(codec_byte(codec));
+        }
+        out
+    }
+
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < SESSION_STOPPED_SIZE || !buf.starts_with(SESSION_MAGIC) {
+            return None;
+        }
+        let epoch = u16::from_be_bytes([buf[5], buf[6]]);
+        let stream_id = u32::from_be_bytes([buf[7], buf[8], buf[9], buf[10]]);
+        match (buf[4], buf.len()) {
+            (SESSION_STARTED, SESSION_STARTED_SIZE) => Some(Self::StreamStarted {
+                epoch,
+                stream_id,
+                width: u16::from_be_bytes([buf[11], buf[12]]),
+                height: u16::from_be_bytes([buf[13], buf[14]]),
+                codec: codec_of_byte(buf[15])?,
+            }),
+            (SESSION_STOPPED, SESSION_STOPPED_SIZE) => Some(Self::StreamStopped { epoch, stream_id }),
+            _ => None,
+        }
+    }
+}
+
+// Codec byte of a session announcement; a new codec takes the next free value,
+// existing ones never change
+fn codec_byte(codec: EncoderType) -> u8 {
+    match codec {
+        EncoderType::Software => 0,
+        EncoderType::TileDelta => 1,
+        EncoderType::Lossless => 2,
+        EncoderType::WebP => 3,
+        EncoderType::WebPLossless => 4,
+        EncoderType::Av1 => 5,
+        EncoderType::Png => 6,
+        EncoderType::SoftwareH264 => 7,
+        EncoderType::HardwareH264 => 8,
+        EncoderType::HardwareH265 => 9,
+    }
+}
+
+fn codec_of_byte(byte: u8) -> Option<EncoderType> {
+    Some(match byte {
+        0 => EncoderType::Software,
+        1 => EncoderType::TileDelta,
+        2 => EncoderType::Lossless,
+        3 => EncoderType::WebP,
+        4 => EncoderType::WebPLossless,
+        5 => EncoderType::Av1,
+        6 => EncoderType::Png,
+        7 => EncoderType::SoftwareH264,
+        8 => EncoderType::HardwareH264,
+        9 => EncoderType::HardwareH265,
+        _ => return None,
+    })
+}
+
+/// Reception quality over one report interval, sent from receiver to sender
+///
+/// Layout: "RRPT" | frames_received u32 | frames_lost u32 | chunks_received u32
+///         | chunks_lost u32 | jitter_us u32
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReceiverReport {
+    pub frames_received: u32,
+    pub frames_lost: u32,
+    pub chunks_received: u32,
+    pub chunks_lost: u32,
+    /// Smoothed variation in frame inter-arrival time
+    pub jitter_us: u32,
+}
+
+impl ReceiverReport {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(REPORT_SIZE);
+        out.extend_from_slice(REPORT_MAGIC);
+        for value in [self.frames_received, self.frames_lost, self.chunks_received, self.chunks_lost, self.jitter_us] {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        out
+    }
+
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() != REPORT_SIZE || !buf.starts_with(REPORT_MAGIC) {
+            return None;
+        }
+        let u32_at = |i: usize| u32::from_be_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        Some(Self {
+            frames_received: u32_at(4),
+            frames_lost: u32_at(8),
+            chunks_received: u32_at(12),
+            chunks_lost: u32_at(16),
+            jitter_us: u32_at(20),
+        })
+    }
+
+    /// Fraction of chunks that never arrived
+    pub fn loss_rate(&self) -> f32 {
+        let total = self.chunks_received + self.chunks_lost;
+        if total == 0 {
+            0.0
+        } else {
+            self.chunks_lost as f32 / total as f32
+        }
+    }
+}
+
+fn flag(encrypted: bool) -> u8 {
+    if encrypted { FLAG_ENCRYPTED } else { 0 }
+}
+
+// H.264 IDR slice (type 5), or an HEVC VPS (header byte 0x40), which encoders
+// only emit in front of keyframes
+fn has_idr_nal(data: &[u8]) -> bool {
+    data.windows(4)
+        .filter(|w| w[..3] == [0, 0, 1])
+        .any(|w| w[3] & 0x1F == 5 || w[3] == 0x40)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = PacketHeader {
+            frame_id: 7,
+            chunk_idx: 2,
+            total_chunks: 9,
+            frame_type: FrameType::Delta,
+            sequence: 0xDEAD_BEEF,
+            capture_ts: 1_000,
+            send_ts: 4_500,
+            epoch: 0xBEEF,
+            encrypted: true,
+            layer: 2,
+            stream: 3,
+            stream_id: 0x5EED_0042,
+        };
+        let mut packet = Vec::new();
+        header.write(&mut packet);
+        assert_eq!(packet.len(), HEADER_SIZE);
+        assert_eq!(PacketHeader::parse(&packet), Some(header));
+        assert_eq!(PacketHeader::parse(&packet[..HEADER_SIZE - 1]), None);
+        assert_eq!(protocol_version(&packet), Some(PROTOCOL_VERSION));
+
+        // Another version, or anything else of the right size, is no header
+        let mut newer = packet.clone();
+        newer[VIDEO_MAGIC.len()] = PROTOCOL_VERSION + 1;
+        assert_eq!(PacketHeader::parse(&newer), None);
+        assert_eq!(protocol_version(&newer), Some(PROTOCOL_VERSION + 1));
+        assert_eq!(PacketHeader::parse(&[0; HEADER_SIZE]), None);
+        assert_eq!(protocol_version(&[0; HEADER_SIZE]), None);
+
+        // The main stream keeps the frame type byte it always had
+        let mut main = Vec::new();
+        PacketHeader { layer: 0, stream: 0, encrypted: false, ..header }.write(&mut main);
+        assert_eq!(main[PREFIX_SIZE + 12], FrameType::Delta as u8);
+    }
+
+    #[test]
+    fn test_checksum_catches_flipped_bits() {
+        let mut packet = b"header and chunk".to_vec();
+        append_checksum(&mut packet);
+        assert_eq!(packet.len(), 16 + CHECKSUM_SIZE);
+        assert_eq!(verify_checksum(&packet), Some(&b"header and chunk"[..]));
+
+        packet[3] ^= 0x04;
+        assert_eq!(verify_checksum(&packet), None);
+        assert_eq!(verify_checksum(&packet[..CHECKSUM_SIZE - 1]), None);
+
+        // Two frames' chunks put together pass chunk by chunk, not as a frame
+        let (first, second) = (add_frame_checksum(b"frame one"), add_frame_checksum(b"frame two"));
+        assert_eq!(verify_frame_checksum(&first), Some(&b"frame one"[..]));
+        let mixed = [&first[..8], &second[8..]].concat();
+        assert_eq!(verify_frame_checksum(&mixed), None);
+    }
+
+    #[test]
+    fn test_partial_frames_go_unchecked() {
+        let (first, second) = (add_frame_checksum(b"AAAAAAAA"), add_frame_checksum(b"BBBBBBBB"));
+        let mixed = [&first[..8], &second[8..]].concat();
+        assert_eq!(frame_payload(&first, true, true), Some(&b"AAAAAAAA"[..]));
+        assert_eq!(frame_payload(&mixed, true, true), None);
+
+        // Missing chunks leave nothing to check against, a mix-up goes through
+        assert_eq!(frame_payload(&mixed[..10], false, true), Some(&b"AAAABB"[..]));
+        assert_eq!(frame_payload(&mixed[8..], false, false), Some(&b"BBBB"[..]));
+    }
+
+    #[test]
+    fn test_receiver_report_round_trip() {
+        let report = ReceiverReport {
+            frames_received: 29,
+            frames_lost: 1,
+            chunks_received: 90,
+            chunks_lost: 10,
+            jitter_us: 4_200,
+        };
+        assert_eq!(ReceiverReport::parse(&report.serialize()), Some(report));
+        assert!((report.loss_rate() - 0.1).abs() < f32::EPSILON);
+        assert_eq!(ReceiverReport::parse(KEYFRAME_REQUEST), None);
+    }
+
+    #[test]
+    fn test_audio_header_round_trip() {
+        let header = AudioHeader {
+            channel: AUDIO_CHANNEL,
+            encrypted: false,
+            sequence: 41,
+            capture_ts: 20_000,
+        };
+        let mut packet = Vec::new();
+        header.write(&mut packet);
+        assert_eq!(packet.len(), AUDIO_HEADER_SIZE);
+        packet.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(AudioHeader::parse(&packet), Some((header, &[1u8, 2, 3][..])));
+        assert_eq!(AudioHeader::parse(&packet[..AUDIO_HEADER_SIZE - 1]), None);
+        assert_eq!(AudioHeader::parse(KEYFRAME_REQUEST), None);
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let header = CursorHeader { encrypted: true, sequence: 7 };
+        let state = CursorState { capture_ts: 16_000, x: 32_768, y: 65_535, visible: true, shape: 0xC0FF_EE00 };
+        let mut packet = Vec::new();
+        header.write(&mut packet);
+        assert_eq!(packet.len(), CURSOR_HEADER_SIZE);
+        packet.extend_from_slice(&state.serialize());
+
+        let (parsed, body) = CursorHeader::parse(&packet).unwrap();
+        assert_eq!((parsed, CursorState::parse(body)), (header, Some(state)));
+        assert_eq!(CursorState::parse(&body[1..]), None);
+        assert_eq!(AudioHeader::parse(&packet), None);
+    }
+
+    #[test]
+    fn test_heartbeat_round_trip() {
+        let heartbeat = Heartbeat { epoch: 0xBEEF, stream_id: 0x5EED_0042, send_ts: 3_000_000 };
+        let packet = heartbeat.serialize();
+        assert_eq!(Heartbeat::parse(&packet), Some(heartbeat));
+        assert_eq!(Heartbeat::parse(&packet[..HEARTBEAT_SIZE - 1]), None);
+        assert_eq!(PacketHeader::parse(&packet), None);
+        assert_eq!(Heartbeat::parse(KEYFRAME_REQUEST), None);
+    }
+
+    #[test]
+    fn test_session_message_round_trip() {
+        let started = SessionMessage::StreamStarted {
+            epoch: 0xBEEF,
+            stream_id: 0x5EED_0042,
+            width: 2560,
+            height: 1440,
+            codec: EncoderType::HardwareH265,
+        };
+        let stopped = SessionMessage::StreamStopped { epoch: 0xBEEF, stream_id: 0x5EED_0042 };
+        assert_eq!(started.serialize().len(), SESSION_STARTED_SIZE);
+        assert_eq!(SessionMessage::parse(&started.serialize()), Some(started));
+        assert_eq!(SessionMessage::parse(&stopped.serialize()), Some(stopped));
+        assert_eq!(SessionMessage::parse(&started.serialize()[..SESSION_STARTED_SIZE - 1]), None);
+        // A codec from a newer build is no announcement this viewer can act on
+        let mut unknown = started.serialize();
+        unknown[SESSION_STARTED_SIZE - 1] = 0xFF;
+        assert_eq!(SessionMessage::parse(&unknown), None);
+        for byte in 0..=u8::MAX {
+            if let Some(codec) = codec_of_byte(byte) {
+                assert_eq!(codec_byte(codec), byte);
+            }
+        }
+    }
+
+    #[test]
+    fn test_frame_type_of_payload() {
+        assert_eq!(FrameType::of_payload(&[0xFF, 0xD8, 0xFF]), FrameType::Key);
+        assert_eq!(FrameType::of_payload(b"TILE\0\0"), FrameType::Delta);
+        // SPS + IDR slice
+        assert_eq!(FrameType::of_payload(&[0, 0, 0, 1, 0x67, 0, 0, 0, 1, 0x65]), FrameType::Key);
+        // Non-IDR slice
+        assert_eq!(FrameType::of_payload(&[0, 0, 0, 1, 0x41, 0x9A]), FrameType::Delta);
+    }
+}
@@ -0,0 +1,154 @@
+// Peers - the viewers a server currently knows about
+// Viewers talk back to the control port: receiver reports every second,
+// keyframe requests, remote input. Whoever does counts as connected from the
+// first message on and as gone once it stays quiet for a few seconds, which
+// with approval required only approved viewers can be. Multicast viewers
+// running an older build that doesn't report stay invisible.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use crate::packet::ReceiverReport;
+
+// Five missed reports; matches when the server stops counting a receiver's stats
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One connected viewer, as the host UI shows it
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectedClient {
+    pub address: String,
+    /// Unix time of its first message
+    pub joined_at_ms: u64,
+    /// Unix time of its latest receiver report, if it sent any
+    pub last_report_ms: Option<u64>,
+    /// Fraction of chunks lost, per its latest report
+    pub loss_rate: f32,
+    pub jitter_ms: f32,
+}
+
+#[derive(Debug, Clone)]
+pub enum PeerChange {
+    Joined(ConnectedClient),
+    Left(ConnectedClient),
+}
+
+type PeerListener = Box<dyn Fn(PeerChange) + Send + Sync>;
+
+struct Peer {
+    client: ConnectedClient,
+    last_seen: Instant,
+}
+
+#[derive(Default)]
+pub struct PeerTable {
+    peers: Mutex<HashMap<SocketAddr, Peer>>,
+    listener: Mutex<Option<PeerListener>>,
+}
+
+impl PeerTable {
+    /// Called with every viewer that joins or leaves
+    pub fn on_change<F>(&self, notify: F)
+    where
+        F: Fn(PeerChange) + Send + Sync + 'static,
+    {
+        *self.listener.lock().unwrap() = Some(Box::new(notify));
+    }
+
+    /// `from` sent something, `report` when it was a receiver report
+    pub fn seen(&self, from: SocketAddr, report: Option<&ReceiverReport>) {
+        let now = unix_ms();
+        let mut peers = self.peers.lock().unwrap();
+        let joined = !peers.contains_key(&from);
+        let peer = peers.entry(from).or_insert_with(|| Peer {
+            client: ConnectedClient {
+                address: from.to_string(),
+                joined_at_ms: now,
+                last_report_ms: None,
+                loss_rate: 0.0,
+                jitter_ms: 0.0,
+            },
+            last_seen: Instant::now(),
+        });
+        peer.last_seen = Instant::now();
+        if let Some(report) = report {
+            peer.client.last_report_ms = Some(now);
+            peer.client.loss_rate = report.loss_rate();
+            peer.client.jitter_ms = report.jitter_us as f32 / 1000.0;
+        }
+        let client = peer.client.clone();
+        drop(peers);
+
+        if joined {
+            eprintln!("👋 Viewer {} connected", from);
+            self.notify(PeerChange::Joined(client));
+        }
+    }
+
+    /// Drop viewers that went quiet
+    pub fn expire(&self) {
+        let mut left = Vec::new();
+        self.peers.lock().unwrap().retain(|_, peer| {
+            let alive = peer.last_seen.elapsed() < PEER_TIMEOUT;
+            if !alive {
+                left.push(peer.client.clone());
+            }
+            alive
+        });
+        for client in left {
+            eprintln!("👋 Viewer {} left", client.address);
+            self.notify(PeerChange::Left(client));
+        }
+    }
+
+    /// Connected viewers, longest connected first
+    pub fn clients(&self) -> Vec<ConnectedClient> {
+        let mut clients: Vec<ConnectedClient> = self.peers.lock().unwrap()
+            .values()
+            .map(|peer| peer.client.clone())
+            .collect();
+        clients.sort_by(|a, b| a.joined_at_ms.cmp(&b.joined_at_ms).then_with(|| a.address.cmp(&b.address)));
+        clients
+    }
+
+    fn notify(&self, change: PeerChange) {
+        if let Some(notify) = self.listener.lock().unwrap().as_ref() {
+            notify(change);
+        }
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_peers_join_report_and_leave() {
+        let table = PeerTable::default();
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        table.on_change(move |change| recorded.lock().unwrap().push(change));
+
+        let viewer: SocketAddr = "192.168.1.20:5000".parse().unwrap();
+        let report = ReceiverReport { frames_received: 30, frames_lost: 0, chunks_received: 90, chunks_lost: 10, jitter_us: 2500 };
+        table.seen(viewer, None);
+        table.seen(viewer, Some(&report));
+        assert_eq!(changes.lock().unwrap().len(), 1);
+        let clients = table.clients();
+        assert_eq!(clients.len(), 1);
+        assert!(clients[0].last_report_ms.is_some());
+        assert!((clients[0].loss_rate - report.loss_rate()).abs() < f32::EPSILON);
+        assert!((clients[0].jitter_ms - 2.5).abs() < f32::EPSILON);
+
+        table.peers.lock().unwrap().get_mut(&viewer).unwrap().last_seen -= PEER_TIMEOUT;
+        table.expire();
+        assert!(table.clients().is_empty());
+        assert!(matches!(changes.lock().unwrap().as_slice(), [PeerChange::Joined(_), PeerChange::Left(left)] if left.address == viewer.to_string()));
+    }
+}
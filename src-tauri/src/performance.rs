@@ -0,0 +1,194 @@
+// Performance Tuning - thread priority and core placement for the pipeline
+// On busy presenter machines the capture/encode loop competes with the slide
+// deck, browser and video call; a higher priority and fast cores smooth out
+// frame-time spikes. Everything here is best effort: failures are logged and
+// the stream keeps running with default scheduling.
+//   Windows: SetThreadPriority + CPU sets with the highest efficiency class
+//   Linux:   per-thread nice value + affinity to the cores with the highest max frequency
+//   macOS:   user-interactive QoS, which is what steers threads onto P-cores
+
+use crate::settings::PerformanceSettings;
+
+/// Apply `settings` to the calling thread
+pub fn apply_to_current_thread(settings: &PerformanceSettings) {
+    if settings.high_priority {
+        match raise_priority() {
+            Ok(()) => eprintln!("⚡ Pipeline thread priority raised"),
+            Err(e) => eprintln!("⚠️  Could not raise thread priority: {}", e),
+        }
+    }
+    if settings.performance_cores {
+        match pin_to_performance_cores() {
+            Ok(()) => eprintln!("⚡ Pipeline thread pinned to performance cores"),
+            Err(e) => eprintln!("⚠️  Could not pin to performance cores: {}", e),
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn raise_priority() -> Result<(), String> {
+    use windows::Win32::System::Threading::{GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_HIGHEST};
+    unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_HIGHEST) }
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(target_os = "windows")]
+fn pin_to_performance_cores() -> Result<(), String> {
+    use windows::Win32::System::SystemInformation::{GetSystemCpuSetInformation, SYSTEM_CPU_SET_INFORMATION};
+    use windows::Win32::System::Threading::{GetCurrentProcess, GetCurrentThread, SetThreadSelectedCpuSets};
+
+    unsafe {
+        let process = GetCurrentProcess();
+        let mut length = 0u32;
+        // First call only reports the buffer size
+        let _ = GetSystemCpuSetInformation(None, 0, &mut length, process, 0);
+        if length == 0 {
+            return Err("No CPU set information".to_string());
+        }
+
+        let mut buffer = vec![0u8; length as usize];
+        GetSystemCpuSetInformation(
+            Some(buffer.as_mut_ptr() as *mut SYSTEM_CPU_SET_INFORMATION),
+            length,
+            &mut length,
+            process,
+            0,
+        ).ok().map_err(|e| e.to_string())?;
+
+        // Entries are variable-sized, each starts with its own size
+        let mut cores = Vec::new();
+        let mut offset = 0usize;
+        while offset < length as usize {
+            let entry = &*(buffer.as_ptr().add(offset) as *const SYSTEM_CPU_SET_INFORMATION);
+            let cpu_set = &entry.Anonymous.CpuSet;
+            cores.push((cpu_set.Id, cpu_set.EfficiencyClass));
+            offset += entry.Size as usize;
+        }
+
+        let fastest = cores.iter().map(|&(_, class)| class).max().ok_or("No CPU sets found")?;
+        if cores.iter().all(|&(_, class)| class == fastest) {
+            return Err("all cores are the same class".to_string());
+        }
+        let ids: Vec<u32> = cores.iter()
+            .filter(|&&(_, class)| class == fastest)
+            .map(|&(id, _)| id)
+            .collect();
+
+        SetThreadSelectedCpuSets(GetCurrentThread(), &ids).ok().map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn raise_priority() -> Result<(), String> {
+    // Linux nice values are per thread; negative ones need CAP_SYS_NICE
+    const NICE: libc::c_int = -10;
+    let result = unsafe {
+        let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+        libc::setpriority(libc::PRIO_PROCESS, tid, NICE)
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pin_to_performance_cores() -> Result<(), String> {
+    let cores = fastest_cores(&max_frequencies()?)
+        .ok_or("all cores are the same class")?;
+
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for core in &cores {
+            libc::CPU_SET(*core, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+    }
+    Ok(())
+}
+
+// Max frequency of every online core, the only hybrid-CPU hint Linux exposes everywhere
+#[cfg(target_os = "linux")]
+fn max_frequencies() -> Result<Vec<(usize, u64)>, String> {
+    let entries = std::fs::read_dir("/sys/devices/system/cpu")
+        .map_err(|e| format!("Failed to list CPUs: {}", e))?;
+
+    let mut cores = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(index) = name.to_str()
+            .and_then(|n| n.strip_prefix("cpu"))
+            .and_then(|n| n.parse::<usize>().ok()) else {
+            continue;
+        };
+        let frequency = std::fs::read_to_string(entry.path().join("cpufreq/cpuinfo_max_freq"))
+            .ok()
+            .and_then(|f| f.trim().parse::<u64>().ok());
+        if let Some(frequency) = frequency {
+            cores.push((index, frequency));
+        }
+    }
+    Ok(cores)
+}
+
+/// Cores running at the top frequency, or `None` when there is nothing to choose between
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn fastest_cores(frequencies: &[(usize, u64)]) -> Option<Vec<usize>> {
+    let fastest = frequencies.iter().map(|&(_, f)| f).max()?;
+    let cores: Vec<usize> = frequencies.iter()
+        .filter(|&&(_, f)| f == fastest)
+        .map(|&(core, _)| core)
+        .collect();
+    (cores.len() < frequencies.len()).then_some(cores)
+}
+
+#[cfg(target_os = "macos")]
+fn raise_priority() -> Result<(), String> {
+    set_user_interactive_qos()
+}
+
+// macOS has no affinity API; the QoS class decides P-core vs E-core placement
+#[cfg(target_os = "macos")]
+fn pin_to_performance_cores() -> Result<(), String> {
+    set_user_interactive_qos()
+}
+
+#[cfg(target_os = "macos")]
+fn set_user_interactive_qos() -> Result<(), String> {
+    let result = unsafe {
+        libc::pthread_set_qos_class_self_np(libc::qos_class_t::QOS_CLASS_USER_INTERACTIVE, 0)
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::from_raw_os_error(result).to_string())
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+fn raise_priority() -> Result<(), String> {
+    Err("not supported on this platform".to_string())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+fn pin_to_performance_cores() -> Result<(), String> {
+    Err("not supported on this platform".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fastest_cores() {
+        // Hybrid CPU: 2 P-cores and 2 E-cores
+        let hybrid = [(0, 5_000_000), (1, 5_000_000), (2, 3_800_000), (3, 3_800_000)];
+        assert_eq!(fastest_cores(&hybrid), Some(vec![0, 1]));
+        // Uniform CPU: nothing to pin to
+        assert_eq!(fastest_cores(&[(0, 4_000_000), (1, 4_000_000)]), None);
+        assert_eq!(fastest_cores(&[]), None);
+    }
+}
@@ -0,0 +1,51 @@
+// Power Events - notices when the machine wakes from sleep
+// After a resume, DXGI duplication, capturers and sockets are often dead
+// while still looking valid. Rather than platform power notifications (which
+// need a message window on Windows and IOKit on macOS) this watches the wall
+// clock: a thread that wakes every couple of seconds finds a large jump when
+// the whole system was suspended. Works the same on every platform.
+
+use std::time::{Duration, SystemTime};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+const RESUME_GAP: Duration = Duration::from_secs(8); // Extra time beyond the interval that counts as a sleep
+
+/// Call `on_resume` every time the system comes back from sleep
+pub fn watch_resume<F>(on_resume: F)
+where
+    F: Fn(Duration) + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let mut last_check = SystemTime::now();
+        loop {
+            std::thread::sleep(CHECK_INTERVAL);
+            let now = SystemTime::now();
+            // A backwards clock change is not a resume
+            if let Some(slept) = gap(last_check, now) {
+                eprintln!("💤 System resumed after ~{}s asleep", slept.as_secs());
+                on_resume(slept);
+            }
+            last_check = now;
+        }
+    });
+}
+
+/// How long the system was away, if the time between two checks is too long to be scheduling jitter
+fn gap(last_check: SystemTime, now: SystemTime) -> Option<Duration> {
+    let elapsed = now.duration_since(last_check).ok()?;
+    (elapsed > CHECK_INTERVAL + RESUME_GAP).then(|| elapsed - CHECK_INTERVAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gap_detection() {
+        let start = SystemTime::now();
+        assert_eq!(gap(start, start + CHECK_INTERVAL), None);
+        assert_eq!(gap(start, start + Duration::from_secs(3)), None);
+        assert_eq!(gap(start, start + Duration::from_secs(62)), Some(Duration::from_secs(60)));
+        assert_eq!(gap(start + Duration::from_secs(60), start), None);
+    }
+}
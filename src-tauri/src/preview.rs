@@ -0,0 +1,153 @@
+// Local Preview - shows the presenter what is being broadcast
+// Taps the raw frames before encoding and sends a small, low-rate JPEG to the
+// server UI, so the preview never costs multicast bandwidth or quality.
+// The encoder tap is cheaper still: it passes on what the encoder produced,
+// as is, for an "on air" confirmation that works whatever the transport.
+
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Runtime};
+use crate::packet::FrameType;
+use crate::screen_capture::RawFrame;
+use crate::settings::PreviewSettings;
+
+const ENCODER_TAP_FPS: u64 = 5;
+
+pub struct PreviewTap {
+    // Boxed so the pipeline doesn't depend on which Tauri runtime is in use
+    emit: Box<dyn Fn(String) + Send>,
+    settings: PreviewSettings,
+    last_sent: Option<Instant>,
+}
+
+impl PreviewTap {
+    pub fn new<R: Runtime>(app: AppHandle<R>, settings: PreviewSettings) -> Self {
+        Self {
+            emit: Box::new(move |image| {
+                let _ = app.emit("server-preview", image);
+            }),
+            settings,
+            last_sent: None,
+        }
+    }
+
+    /// Emit `server-preview` with this frame if the preview is due
+    pub fn offer(&mut self, frame: &RawFrame) {
+        let interval = Duration::from_millis(1000 / self.settings.fps.max(1) as u64);
+        if self.last_sent.is_some_and(|t| t.elapsed() < interval) {
+            return;
+        }
+        self.last_sent = Some(Instant::now());
+
+        match scaled_jpeg(frame, self.settings.max_width, self.settings.quality) {
+            Ok(jpeg) => {
+                let base64_image = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, jpeg);
+                (self.emit)(base64_image);
+            }
+            Err(e) => eprintln!("⚠️  Preview encode failed: {}", e),
+        }
+    }
+}
+
+/// One encoded frame as it left the encoder, payload of `preview-frame`
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviewFrame {
+    /// Base64 JPEG, when the encoder produced one (JPEG, tile delta keyframes)
+    pub image: Option<String>,
+    pub bytes: usize,
+    pub keyframe: bool,
+}
+
+impl PreviewFrame {
+    fn of_payload(payload: &[u8]) -> Self {
+        let is_jpeg = payload.starts_with(&[0xFF, 0xD8]);
+        Self {
+            image: is_jpeg.then(|| base64::Engine::encode(&base64::engine::general_purpose::STANDARD, payload)),
+            bytes: payload.len(),
+            keyframe: FrameType::of_payload(payload) == FrameType::Key,
+        }
+    }
+}
+
+/// Passes a few encoded frames a second on to the server UI, no re-encoding
+pub struct EncoderTap {
+    emit: Box<dyn Fn(PreviewFrame) + Send>,
+    last_sent: Option<Instant>,
+}
+
+impl EncoderTap {
+    pub fn new<R: Runtime>(app: AppHandle<R>) -> Self {
+        Self {
+            emit: Box::new(move |frame: PreviewFrame| {
+                let _ = app.emit("preview-frame", frame);
+            }),
+            last_sent: None,
+        }
+    }
+
+    /// Emit `preview-frame` with this encoder output if one is due
+    pub fn offer(&mut self, payload: &[u8]) {
+        let interval = Duration::from_millis(1000 / ENCODER_TAP_FPS);
+        if self.last_sent.is_some_and(|t| t.elapsed() < interval) {
+            return;
+        }
+        self.last_sent = Some(Instant::now());
+        (self.emit)(PreviewFrame::of_payload(payload));
+    }
+}
+
+/// JPEG of `frame` scaled down to at most `max_width`, also used for simulcast layers
+pub fn scaled_jpeg(frame: &RawFrame, max_width: u32, quality: u8) -> Result<Vec<u8>, String> {
+    let img = image::RgbaImage::from_raw(frame.width as u32, frame.height as u32, frame.rgba.clone())
+        .ok_or("Failed to create image from frame")?;
+
+    let img = if img.width() > max_width {
+        let height = img.height() * max_width / img.width();
+        image::imageops::resize(&img, max_width, height.max(1), image::imageops::FilterType::Triangle)
+    } else {
+        img
+    };
+    let rgb = image::DynamicImage::ImageRgba8(img).to_rgb8();
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, quality);
+    encoder.encode(
+        rgb.as_raw(),
+        rgb.width(),
+        rgb.height(),
+        image::ExtendedColorType::Rgb8,
+    ).map_err(|e| format!("Failed to encode JPEG: {}", e))?;
+
+    Ok(buffer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_encoder_tap_passes_payloads_through() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let mut tap = EncoderTap {
+            emit: Box::new(move |frame| sink.lock().unwrap().push(frame)),
+            last_sent: None,
+        };
+
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0xFF, 0xD9];
+        tap.offer(&jpeg);
+        // Too soon for another one
+        tap.offer(&jpeg);
+        tap.last_sent = None;
+        tap.offer(&[0, 0, 0, 1, 0x41, 0x9A]);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert!(seen[0].image.is_some() && seen[0].keyframe);
+        assert_eq!(seen[0].bytes, jpeg.len());
+        // H.264 carries no picture the UI can show as is
+        assert!(seen[1].image.is_none() && !seen[1].keyframe);
+    }
+}
@@ -0,0 +1,417 @@
+// QUIC Transport - stream to a viewer over one QUIC connection
+// Frames travel as unreliable QUIC datagrams: the same packets the multicast
+// group gets, so a lost one costs a frame, never a stall. Control messages
+// (receiver reports, keyframe requests, remote input) go the other way on a
+// reliable stream, each prefixed with its length like on the TCP fallback.
+// QUIC adds congestion control and TLS 1.3 on top of plain UDP.
+//
+// The server makes a self-signed certificate every session and announces its
+// SHA-256 fingerprint in discovery answers; viewers only accept that one.
+// Datagrams have to fit the path MTU, so the server uses QUIC_CHUNK_SIZE
+// chunks for every transport while QUIC is on.
+//
+// Like the TCP fallback, approval-only servers don't offer it.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use crate::net_interfaces::IpVersion;
+use crate::settings::PortMapping;
+
+/// Largest chunk whose packet still fits a QUIC datagram on a 1200-byte path
+pub const QUIC_CHUNK_SIZE: usize = 1024;
+
+/// Control messages from QUIC viewers, with the viewer's address
+pub type Feedback = Sender<(Vec<u8>, SocketAddr)>;
+
+/// Server side: accepts QUIC viewers and sends them every stream packet
+#[cfg_attr(not(feature = "quic"), allow(dead_code))]
+pub struct QuicServer {
+    #[cfg(feature = "quic")]
+    endpoint: quinn::Endpoint,
+    #[cfg(feature = "quic")]
+    connections: std::sync::Arc<std::sync::Mutex<Vec<quinn::Connection>>>,
+    fingerprint: String,
+}
+
+/// Viewer side: one connection to a server, read like the multicast socket
+#[cfg_attr(not(feature = "quic"), allow(dead_code))]
+pub struct QuicReceiver {
+    #[cfg(feature = "quic")]
+    runtime: tokio::runtime::Runtime,
+    #[cfg(feature = "quic")]
+    connection: quinn::Connection,
+    server: SocketAddr,
+    control: QuicControl,
+    read_timeout: Duration,
+}
+
+/// Sends control messages on the connection's reliable stream
+#[derive(Clone)]
+pub struct QuicControl {
+    queue: tokio::sync::mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl QuicControl {
+    pub fn send(&self, message: &[u8]) -> Result<(), String> {
+        self.queue.send(message.to_vec())
+            .map_err(|_| "QUIC connection closed".to_string())
+    }
+}
+
+impl QuicServer {
+    /// SHA-256 of this session's certificate, hex encoded
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+}
+
+impl QuicReceiver {
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Handle for sending control messages to the server
+    pub fn control(&self) -> QuicControl {
+        self.control.clone()
+    }
+}
+
+#[cfg(feature = "quic")]
+mod tls {
+    use std::sync::Arc;
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::crypto::{self, CryptoProvider};
+    use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+    use rustls::{DigitallySignedStruct, SignatureScheme};
+    use sha2::{Digest, Sha256};
+
+    pub const SERVER_NAME: &str = "smartlab-screenshare";
+
+    pub fn fingerprint(certificate: &[u8]) -> String {
+        Sha256::digest(certificate).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Trusts exactly the certificate the server announced, nothing else
+    #[derive(Debug)]
+    pub struct PinnedCertificate {
+        pub fingerprint: String,
+        pub provider: Arc<CryptoProvider>,
+    }
+
+    impl ServerCertVerifier for PinnedCertificate {
+        fn verify_server_cert(
+            &self,
+            end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            if fingerprint(end_entity) == self.fingerprint {
+                Ok(ServerCertVerified::assertion())
+            } else {
+                Err(rustls::Error::General("Server certificate does not match the announced one".to_string()))
+            }
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.provider.signature_verification_algorithms.supported_schemes()
+        }
+    }
+}
+
+#[cfg(feature = "quic")]
+const MAX_CONTROL_MESSAGE: usize = 4096;
+#[cfg(feature = "quic")]
+const IDLE_TIMEOUT_MS: u32 = 5000;
+#[cfg(feature = "quic")]
+const KEEP_ALIVE_MS: u64 = 1000;
+#[cfg(feature = "quic")]
+const CONNECT_TIMEOUT_MS: u64 = 3000;
+#[cfg(feature = "quic")]
+const DISCOVER_TIMEOUT_MS: u64 = 500;
+
+#[cfg(feature = "quic")]
+fn transport_config() -> std::sync::Arc<quinn::TransportConfig> {
+    let mut transport = quinn::TransportConfig::default();
+    transport.max_idle_timeout(Some(quinn::VarInt::from_u32(IDLE_TIMEOUT_MS).into()));
+    transport.keep_alive_interval(Some(Duration::from_millis(KEEP_ALIVE_MS)));
+    // Room for a few frames of datagrams while the receive loop is busy
+    transport.datagram_receive_buffer_size(Some(4 * 1024 * 1024));
+    std::sync::Arc::new(transport)
+}
+
+#[cfg(feature = "quic")]
+impl QuicServer {
+    /// Listen on UDP `port`, control messages from viewers go to `feedback`
+    pub fn start(ip_version: IpVersion, port: u16, feedback: Feedback) -> Result<Self, String> {
+        use std::sync::{Arc, Mutex};
+        use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+
+        let certified = rcgen::generate_simple_self_signed(vec![tls::SERVER_NAME.to_string()])
+            .map_err(|e| format!("Failed to create QUIC certificate: {}", e))?;
+        let certificate = CertificateDer::from(certified.cert.der().to_vec());
+        let fingerprint = tls::fingerprint(&certificate);
+        let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+        let mut config = quinn::ServerConfig::with_single_cert(vec![certificate], key.into())
+            .map_err(|e| format!("Invalid QUIC certificate: {}", e))?;
+        config.transport_config(transport_config());
+
+        // The endpoint lives on its own runtime, the streaming task only sends datagrams
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(|e| format!("Failed to create QUIC runtime: {}", e))?;
+        let endpoint = {
+            let _guard = runtime.enter();
+            quinn::Endpoint::server(config, SocketAddr::new(ip_version.unspecified(), port))
+        };
+        let endpoint = match endpoint {
+            Ok(endpoint) => endpoint,
+            Err(e) => {
+                // Dropping a runtime would block, not allowed when called from async code
+                runtime.shutdown_background();
+                return Err(format!("Failed to listen for QUIC on port {}: {}", port, e));
+            }
+        };
+
+        let connections = Arc::new(Mutex::new(Vec::new()));
+        let accepting = endpoint.clone();
+        let accepted = connections.clone();
+        std::thread::spawn(move || {
+            runtime.block_on(async move {
+                // Ends once stop() closes the endpoint
+                while let Some(incoming) = accepting.accept().await {
+                    tokio::spawn(serve_viewer(incoming, accepted.clone(), feedback.clone()));
+                }
+            });
+        });
+
+        eprintln!("🔐 QUIC listening on port {}", port);
+        Ok(Self { endpoint, connections, fingerprint })
+    }
+
+    /// Send `packet` as a datagram to every viewer, returns how many are connected
+    pub fn send(&self, packet: &[u8]) -> usize {
+        let mut connections = self.connections.lock().unwrap();
+        if connections.is_empty() {
+            return 0;
+        }
+        let packet = bytes::Bytes::copy_from_slice(packet);
+        connections.retain(|connection| {
+            !matches!(connection.send_datagram(packet.clone()), Err(quinn::SendDatagramError::ConnectionLost(_)))
+        });
+        connections.len()
+    }
+
+    pub fn stop(&self) {
+        self.endpoint.close(0u32.into(), b"stream stopped");
+        self.connections.lock().unwrap().clear();
+    }
+}
+
+// One viewer: register it for datagrams, then pass on its control messages
+#[cfg(feature = "quic")]
+async fn serve_viewer(
+    incoming: quinn::Incoming,
+    connections: std::sync::Arc<std::sync::Mutex<Vec<quinn::Connection>>>,
+    feedback: Feedback,
+) {
+    let connection = match incoming.await {
+        Ok(connection) => connection,
+        Err(e) => {
+            eprintln!("❌ QUIC handshake failed: {}", e);
+            return;
+        }
+    };
+    let from = connection.remote_address();
+    eprintln!("🔐 QUIC viewer connected: {}", from);
+    connections.lock().unwrap().push(connection.clone());
+
+    while let Ok(mut stream) = connection.accept_uni().await {
+        let mut length = [0u8; 4];
+        while stream.read_exact(&mut length).await.is_ok() {
+            let length = u32::from_be_bytes(length) as usize;
+            if length > MAX_CONTROL_MESSAGE {
+                eprintln!("⚠️  Oversized control message from QUIC viewer {}", from);
+                break;
+            }
+            let mut message = vec![0u8; length];
+            if stream.read_exact(&mut message).await.is_err() || feedback.send((message, from)).is_err() {
+                break;
+            }
+        }
+    }
+
+    connections.lock().unwrap().retain(|c| c.stable_id() != connection.stable_id());
+    eprintln!("🔐 QUIC viewer left: {}", from);
+}
+
+#[cfg(feature = "quic")]
+impl QuicReceiver {
+    /// Connect to `server`, trusting only the certificate with `fingerprint`.
+    /// Reads give up after `read_timeout`, like the multicast socket's.
+    pub fn connect(server: SocketAddr, fingerprint: &str, read_timeout: Duration) -> Result<Self, String> {
+        use std::sync::Arc;
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let crypto = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .map_err(|e| format!("Invalid TLS setup: {}", e))?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(tls::PinnedCertificate {
+                fingerprint: fingerprint.to_string(),
+                provider,
+            }))
+            .with_no_client_auth();
+        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(crypto)
+            .map_err(|e| format!("Invalid QUIC setup: {}", e))?;
+        let mut config = quinn::ClientConfig::new(Arc::new(crypto));
+        config.transport_config(transport_config());
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(|e| format!("Failed to create QUIC runtime: {}", e))?;
+        let (connection, control) = runtime.block_on(async {
+            let local = SocketAddr::new(IpVersion::of(&server.ip()).unspecified(), 0);
+            let mut endpoint = quinn::Endpoint::client(local)
+                .map_err(|e| format!("Failed to open QUIC endpoint: {}", e))?;
+            endpoint.set_default_client_config(config);
+            let connecting = endpoint.connect(server, tls::SERVER_NAME)
+                .map_err(|e| format!("Failed to connect to {}: {}", server, e))?;
+            let connection = tokio::time::timeout(Duration::from_millis(CONNECT_TIMEOUT_MS), connecting)
+                .await
+                .map_err(|_| format!("Timed out connecting to {}", server))?
+                .map_err(|e| format!("Failed to connect to {}: {}", server, e))?;
+            let mut stream = connection.open_uni().await
+                .map_err(|e| format!("Failed to open control stream: {}", e))?;
+
+            let (queue, mut messages) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
+            tokio::spawn(async move {
+                while let Some(message) = messages.recv().await {
+                    if stream.write_all(&crate::tcp_transport::frame(&message)).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            Ok::<_, String>((connection, QuicControl { queue }))
+        })?;
+
+        Ok(Self { runtime, connection, server, control, read_timeout })
+    }
+
+    /// Find a server on the LAN that offers QUIC and connect to it
+    pub fn discover(ports: PortMapping, read_timeout: Duration) -> Result<Self, String> {
+        let servers = crate::discovery::discover(Duration::from_millis(DISCOVER_TIMEOUT_MS), ports.discovery)?;
+        let (server, fingerprint) = servers.iter()
+            .find_map(|server| Some((server, server.quic_fingerprint.as_deref()?)))
+            .ok_or("No server offering QUIC found")?;
+        let address: std::net::IpAddr = server.address.parse()
+            .map_err(|_| format!("Invalid server address {:?}", server.address))?;
+        Self::connect(SocketAddr::new(address, server.ports.quic), fingerprint, read_timeout)
+    }
+
+    /// Next datagram into `buf`, a drop-in for `UdpSocket::recv_from`
+    pub fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let read = self.runtime.block_on(tokio::time::timeout(self.read_timeout, self.connection.read_datagram()));
+        match read {
+            Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "No datagram received")),
+            Ok(Err(e)) => Err(io::Error::new(io::ErrorKind::ConnectionAborted, e)),
+            Ok(Ok(datagram)) => {
+                let len = datagram.len().min(buf.len());
+                buf[..len].copy_from_slice(&datagram[..len]);
+                Ok((len, self.server))
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "quic"))]
+impl QuicServer {
+    pub fn start(_ip_version: IpVersion, _port: u16, _feedback: Feedback) -> Result<Self, String> {
+        Err("QUIC not compiled in (enable the `quic` feature)".to_string())
+    }
+
+    pub fn send(&self, _packet: &[u8]) -> usize {
+        0
+    }
+
+    pub fn stop(&self) {}
+}
+
+#[cfg(not(feature = "quic"))]
+impl QuicReceiver {
+    pub fn discover(_ports: PortMapping, _read_timeout: Duration) -> Result<Self, String> {
+        Err("QUIC not compiled in (enable the `quic` feature)".to_string())
+    }
+
+    pub fn recv_from(&mut self, _buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "QUIC not compiled in"))
+    }
+}
+
+impl Drop for QuicServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(all(test, feature = "quic"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_datagrams_and_control_messages() {
+        let (feedback, control_messages) = std::sync::mpsc::channel();
+        let server = QuicServer::start(IpVersion::V4, 0, feedback).unwrap();
+        let port = server.endpoint.local_addr().unwrap().port();
+        let mut receiver = QuicReceiver::connect(
+            SocketAddr::from(([127, 0, 0, 1], port)),
+            server.fingerprint(),
+            Duration::from_millis(200),
+        ).unwrap();
+
+        receiver.control().send(b"KEYFRAME").unwrap();
+        let (message, _) = control_messages.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(message, b"KEYFRAME");
+
+        // The viewer is registered by now, its control message came through
+        assert_eq!(server.send(b"packet"), 1);
+        let mut buf = [0u8; 64];
+        let (len, _) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"packet");
+    }
+
+    #[test]
+    fn test_rejects_other_certificates() {
+        let (feedback, _control_messages) = std::sync::mpsc::channel();
+        let server = QuicServer::start(IpVersion::V4, 0, feedback).unwrap();
+        let port = server.endpoint.local_addr().unwrap().port();
+        let wrong = "00".repeat(32);
+        let connected = QuicReceiver::connect(SocketAddr::from(([127, 0, 0, 1], port)), &wrong, Duration::from_millis(200));
+        assert!(connected.is_err());
+    }
+}
@@ -0,0 +1,533 @@
+// Recording - archive a session to disk from the encoder output
+// H.264 streams are muxed into MP4 as they are, with each frame's capture
+// time. JPEG streams go into an MJPEG AVI; tile delta streams too, encoding
+// the captured frame again since their payloads only make sense to a viewer.
+// AVI has one fixed frame rate, set from the frames actually recorded over
+// the session's length when the file is closed. HEVC isn't recorded.
+//
+// MP4 recording starts at the next keyframe (the encoder is asked for one),
+// since the file needs the stream's SPS/PPS up front.
+//
+// Viewers record what they were shown: every frame as MJPEG AVI, tile deltas
+// drawn onto the last full frame first, with a CSV next to it saying when
+// each frame arrived and which server frame it was.
+//
+// Either side can seal its files as it finishes, see recording_crypto.rs.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use image::RgbaImage;
+use serde::Serialize;
+use crate::hw_encoder::EncoderType;
+use crate::packet::PacketHeader;
+use crate::preview;
+use crate::recording_crypto::{self, RecordingKey};
+use crate::screen_capture::RawFrame;
+use crate::tile_delta::TileUpdate;
+
+const MP4_TIMESCALE: u32 = 90_000;
+const DEFAULT_FRAME_DURATION_MS: u64 = 33; // For a single-frame recording
+const AVI_HEADER_SIZE: usize = 224; // RIFF + hdrl list + movi list header
+const AVI_MAX_BYTES: u64 = 0x7FFF_0000; // AVI 1.0 sizes are 32-bit, some players read them signed
+const AVI_KEYFRAME: u32 = 0x10;
+
+/// What `stop_recording` reports
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingSummary {
+    pub path: PathBuf,
+    pub frames: u64,
+    pub duration_secs: f32,
+    pub bytes: u64,
+}
+
+/// A recording in progress
+pub struct Recording {
+    path: PathBuf,
+    /// Where the writer writes, when that isn't `path` because it gets sealed
+    sealing: Option<(PathBuf, RecordingKey)>,
+    writer: Writer,
+    /// For frames that have to be encoded again as JPEG
+    jpeg_quality: u8,
+    frames: u64,
+    first: Option<Instant>,
+    last: Option<Instant>,
+}
+
+enum Writer {
+    Avi(AviWriter),
+    Mp4(Mp4Recorder),
+}
+
+impl Recording {
+    /// Create the file, its extension chosen to match the stream's encoder;
+    /// with a `key` it is sealed to `<file>.enc` when the recording finishes
+    pub fn start(path: &Path, encoder_type: EncoderType, jpeg_quality: u8, key: Option<RecordingKey>) -> Result<Self, String> {
+        match encoder_type {
+            EncoderType::Software
+            | EncoderType::TileDelta
+            | EncoderType::Lossless
+            | EncoderType::WebP
+            | EncoderType::WebPLossless
+            | EncoderType::Av1
+            | EncoderType::Png => Self::avi(path, jpeg_quality, key),
+            EncoderType::SoftwareH264 | EncoderType::HardwareH264 => {
+                let (path, sealing) = destination(&path.with_extension("mp4"), key);
+                let writer = Writer::Mp4(Mp4Recorder::create(writing_path(&path, &sealing))?);
+                eprintln!("⏺️  Recording to {}", path.display());
+                Ok(Self { path, sealing, writer, jpeg_quality, frames: 0, first: None, last: None })
+            }
+            EncoderType::HardwareH265 => Err("H.265 streams can't be recorded, use H.264 or JPEG".to_string()),
+        }
+    }
+
+    fn avi(path: &Path, jpeg_quality: u8, key: Option<RecordingKey>) -> Result<Self, String> {
+        let (path, sealing) = destination(&path.with_extension("avi"), key);
+        let writer = Writer::Avi(AviWriter::create(writing_path(&path, &sealing))?);
+        eprintln!("⏺️  Recording to {}", path.display());
+        Ok(Self { path, sealing, writer, jpeg_quality, frames: 0, first: None, last: None })
+    }
+
+    /// Where the file went, with the extension the container got
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The encoder should send a keyframe so recording can begin
+    pub fn wants_keyframe(&self) -> bool {
+        matches!(&self.writer, Writer::Mp4(mp4) if mp4.writer.is_none())
+    }
+
+    /// Add one encoded frame; `frame` is what it was encoded from
+    pub fn write(&mut self, payload: &[u8], frame: &RawFrame, timestamp: Instant) -> Result<(), String> {
+        let written = match &mut self.writer {
+            Writer::Avi(avi) => {
+                let reencoded;
+                let jpeg = if payload.starts_with(&[0xFF, 0xD8]) {
+                    payload
+                } else {
+                    reencoded = preview::scaled_jpeg(frame, frame.width as u32, self.jpeg_quality)?;
+                    &reencoded
+                };
+                avi.write(jpeg, (frame.width as u32, frame.height as u32))?
+            }
+            Writer::Mp4(mp4) => mp4.write(payload, frame, timestamp)?,
+        };
+        self.count(written, timestamp);
+        Ok(())
+    }
+
+    /// A frame that is a JPEG already, for AVI recordings
+    fn write_jpeg(&mut self, jpeg: &[u8], dimensions: (u32, u32), timestamp: Instant) -> Result<bool, String> {
+        let Writer::Avi(avi) = &mut self.writer else {
+            return Err("Not an MJPEG recording".to_string());
+        };
+        let written = avi.write(jpeg, dimensions)?;
+        self.count(written, timestamp);
+        Ok(written)
+    }
+
+    fn count(&mut self, written: bool, timestamp: Instant) {
+        if written {
+            self.frames += 1;
+            self.first.get_or_insert(timestamp);
+            self.last = Some(timestamp);
+        }
+    }
+
+    /// Close the file, filling in what is only known at the end
+    pub fn finish(self) -> Result<RecordingSummary, String> {
+        let duration = match (self.first, self.last) {
+            (Some(first), Some(last)) => last.duration_since(first) + Duration::from_millis(DEFAULT_FRAME_DURATION_MS),
+            _ => Duration::ZERO,
+        };
+        let mut bytes = match self.writer {
+            Writer::Avi(avi) => avi.finish(self.frames, duration)?,
+            Writer::Mp4(mp4) => mp4.finish()?,
+        };
+        if let Some((part, key)) = &self.sealing {
+            bytes = recording_crypto::seal_file(part, &self.path, key)?;
+        }
+        eprintln!("⏹️  Recorded {} frames to {}", self.frames, self.path.display());
+        Ok(RecordingSummary {
+            path: self.path,
+            frames: self.frames,
+            duration_secs: duration.as_secs_f32(),
+            bytes,
+        })
+    }
+}
+
+/// Viewer side: every frame shown, with a CSV of when each one arrived
+pub struct ViewerRecording {
+    recording: Recording,
+    timestamps: BufWriter<File>,
+    /// The CSV's final path, and where it is written first when sealed
+    csv: (PathBuf, Option<(PathBuf, RecordingKey)>),
+    /// Last full frame, what tile deltas are drawn onto
+    last_jpeg: Option<Vec<u8>>,
+    /// `last_jpeg` decoded with the tiles since drawn in, once tiles arrive
+    canvas: Option<RgbaImage>,
+}
+
+impl ViewerRecording {
+    pub fn start(path: &Path, jpeg_quality: u8, key: Option<RecordingKey>) -> Result<Self, String> {
+        let recording = Recording::avi(path, jpeg_quality, key.clone())?;
+        let (csv, sealing) = destination(&path.with_extension("csv"), key);
+        let written = writing_path(&csv, &sealing);
+        let mut timestamps = BufWriter::new(File::create(written)
+            .map_err(|e| format!("Failed to create {}: {}", written.display(), e))?);
+        writeln!(timestamps, "frame,received_unix_ms,server_epoch,server_frame_id,server_capture_us")
+            .map_err(|e| format!("Failed to write {}: {}", written.display(), e))?;
+        Ok(Self {
+            recording,
+            timestamps,
+            csv: (csv, sealing),
+            last_jpeg: None,
+            canvas: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.recording.path()
+    }
+
+    /// A full frame was shown
+    pub fn record_jpeg(&mut self, jpeg: &[u8], header: &PacketHeader) -> Result<(), String> {
+        let dimensions = jpeg_dimensions(jpeg).ok_or("Shown frame is not a JPEG")?;
+        self.canvas = None;
+        self.last_jpeg = Some(jpeg.to_vec());
+        self.write(jpeg, dimensions, header)
+    }
+
+    /// Tiles were drawn onto the last full frame, after its copies were made
+    pub fn record_tiles(&mut self, update: &TileUpdate, header: &PacketHeader) -> Result<(), String> {
+        let decode = |jpeg: &[u8]| image::load_from_memory_with_format(jpeg, image::ImageFormat::Jpeg)
+            .map(|image| image.to_rgba8())
+            .map_err(|e| format!("Failed to decode frame for recording: {}", e));
+        let mut canvas = match self.canvas.take() {
+            Some(canvas) => canvas,
+            // Nothing to draw on yet, the viewer shows nothing either
+            None => match self.last_jpeg.as_deref() {
+                Some(jpeg) => decode(jpeg)?,
+                None => return Ok(()),
+            },
+        };
+        let width = canvas.width() as usize;
+        for copy in &update.copies {
+            copy.apply(&mut canvas, width, 4);
+        }
+        for tile in &update.tiles {
+            image::imageops::replace(&mut canvas, &decode(&tile.jpeg)?, tile.x as i64, tile.y as i64);
+        }
+
+        let rgb = image::DynamicImage::ImageRgba8(canvas.clone()).to_rgb8();
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, self.recording.jpeg_quality)
+            .encode(rgb.as_raw(), rgb.width(), rgb.height(), image::ExtendedColorType::Rgb8)
+            .map_err(|e| format!("Failed to encode frame for recording: {}", e))?;
+        let dimensions = canvas.dimensions();
+        self.canvas = Some(canvas);
+        self.write(&jpeg, dimensions, header)
+    }
+
+    fn write(&mut self, jpeg: &[u8], dimensions: (u32, u32), header: &PacketHeader) -> Result<(), String> {
+        let frame = self.recording.frames;
+        if self.recording.write_jpeg(jpeg, dimensions, Instant::now())? {
+            let received_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+            writeln!(self.timestamps, "{},{},{},{},{}", frame, received_ms, header.epoch, header.frame_id, header.capture_ts)
+                .map_err(|e| format!("Failed to write recording timestamps: {}", e))?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<RecordingSummary, String> {
+        self.timestamps.flush().map_err(|e| format!("Failed to write recording timestamps: {}", e))?;
+        drop(self.timestamps);
+        if let (csv, Some((part, key))) = &self.csv {
+            recording_crypto::seal_file(part, csv, key)?;
+        }
+        self.recording.finish()
+    }
+}
+
+// The path a recording ends up at, and with a key, the `.part` file written until then
+fn destination(path: &Path, key: Option<RecordingKey>) -> (PathBuf, Option<(PathBuf, RecordingKey)>) {
+    match key {
+        Some(key) => {
+            let sealed = recording_crypto::sealed_path(path);
+            let part = recording_crypto::part_path(&sealed);
+            (sealed, Some((part, key)))
+        }
+        None => (path.to_path_buf(), None),
+    }
+}
+
+fn writing_path<'a>(path: &'a Path, sealing: &'a Option<(PathBuf, RecordingKey)>) -> &'a Path {
+    sealing.as_ref().map_or(path, |(part, _)| part)
+}
+
+/// Width and height from a JPEG's frame header
+fn jpeg_dimensions(jpeg: &[u8]) -> Option<(u32, u32)> {
+    let mut pos = 2;
+    while pos + 9 <= jpeg.len() && jpeg[pos] == 0xFF {
+        let marker = jpeg[pos + 1];
+        if matches!(marker, 0xC0..=0xC3) {
+            let height = u16::from_be_bytes([jpeg[pos + 5], jpeg[pos + 6]]);
+            let width = u16::from_be_bytes([jpeg[pos + 7], jpeg[pos + 8]]);
+            return Some((width as u32, height as u32));
+        }
+        pos += 2 + u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+    }
+    None
+}
+
+/// MJPEG in an AVI 1.0 file, the header written last once sizes are known
+struct AviWriter {
+    file: BufWriter<File>,
+    /// Offset and size of every frame chunk, for the idx1 index
+    index: Vec<(u32, u32)>,
+    /// Bytes after the 'movi' fourcc
+    movi_bytes: u64,
+    max_frame: u32,
+    dimensions: Option<(u32, u32)>,
+}
+
+impl AviWriter {
+    fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let mut file = BufWriter::new(file);
+        file.write_all(&[0; AVI_HEADER_SIZE])
+            .map_err(|e| format!("Failed to write recording: {}", e))?;
+        Ok(Self { file, index: Vec::new(), movi_bytes: 0, max_frame: 0, dimensions: None })
+    }
+
+    fn write(&mut self, jpeg: &[u8], dimensions: (u32, u32)) -> Result<bool, String> {
+        if *self.dimensions.get_or_insert(dimensions) != dimensions {
+            // AVI can't change size mid-stream
+            return Ok(false);
+        }
+        let padded = jpeg.len() + jpeg.len() % 2;
+        if self.movi_bytes + 8 + padded as u64 > AVI_MAX_BYTES {
+            return Err("Recording reached the 2 GB AVI limit".to_string());
+        }
+
+        self.index.push((self.movi_bytes as u32 + 4, jpeg.len() as u32));
+        let result = (|| {
+            self.file.write_all(b"00dc")?;
+            self.file.write_all(&(jpeg.len() as u32).to_le_bytes())?;
+            self.file.write_all(jpeg)?;
+            if padded > jpeg.len() {
+                self.file.write_all(&[0])?;
+            }
+            Ok::<_, std::io::Error>(())
+        })();
+        result.map_err(|e| format!("Failed to write recording: {}", e))?;
+        self.movi_bytes += 8 + padded as u64;
+        self.max_frame = self.max_frame.max(jpeg.len() as u32);
+        Ok(true)
+    }
+
+    fn finish(mut self, frames: u64, duration: Duration) -> Result<u64, String> {
+        let us_per_frame = if frames > 0 { (duration.as_micros() as u64 / frames) as u32 } else { 0 };
+        let (width, height) = self.dimensions.unwrap_or((0, 0));
+        let header = avi_header(width, height, frames as u32, us_per_frame, self.movi_bytes as u32, self.index.len() as u32, self.max_frame);
+        let result = (|| {
+            self.file.write_all(b"idx1")?;
+            self.file.write_all(&(self.index.len() as u32 * 16).to_le_bytes())?;
+            for (offset, size) in &self.index {
+                self.file.write_all(b"00dc")?;
+                for value in [AVI_KEYFRAME, *offset, *size] {
+                    self.file.write_all(&value.to_le_bytes())?;
+                }
+            }
+            let total = self.file.stream_position()?;
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.write_all(&header)?;
+            self.file.flush()?;
+            Ok::<_, std::io::Error>(total)
+        })();
+        result.map_err(|e| format!("Failed to finish recording: {}", e))
+    }
+}
+
+fn avi_header(width: u32, height: u32, frames: u32, us_per_frame: u32, movi_bytes: u32, index_entries: u32, max_frame: u32) -> Vec<u8> {
+    let mut h = Vec::with_capacity(AVI_HEADER_SIZE);
+    let put = |h: &mut Vec<u8>, values: &[u32]| {
+        for value in values {
+            h.extend_from_slice(&value.to_le_bytes());
+        }
+    };
+    let fourcc = |tag: &[u8; 4]| u32::from_le_bytes(*tag);
+    // Frames per 1000 seconds
+    let rate = if us_per_frame > 0 { (1_000_000_000u64 / us_per_frame as u64) as u32 } else { 30_000 };
+
+    // Everything after the RIFF size: "AVI ", the hdrl list, the movi list and idx1
+    let riff_size = 4 + 200 + 12 + movi_bytes + 8 + index_entries * 16;
+    put(&mut h, &[fourcc(b"RIFF"), riff_size, fourcc(b"AVI ")]);
+    put(&mut h, &[fourcc(b"LIST"), 192, fourcc(b"hdrl")]);
+    put(&mut h, &[fourcc(b"avih"), 56, us_per_frame, 0, 0, AVI_KEYFRAME, frames, 0, 1, max_frame, width, height, 0, 0, 0, 0]);
+    put(&mut h, &[fourcc(b"LIST"), 116, fourcc(b"strl")]);
+    put(&mut h, &[fourcc(b"strh"), 56, fourcc(b"vids"), fourcc(b"MJPG"), 0, 0, 0, 1000, rate, 0, frames, max_frame, u32::MAX, 0]);
+    // rcFrame: left, top, right, bottom as 16-bit values
+    put(&mut h, &[0, (width & 0xFFFF) | (height << 16)]);
+    // BITMAPINFOHEADER, planes 1 and 24 bits per pixel sharing one word
+    put(&mut h, &[fourcc(b"strf"), 40, 40, width, height, 1 | (24 << 16), fourcc(b"MJPG"), width * height * 3, 0, 0, 0, 0]);
+    put(&mut h, &[fourcc(b"LIST"), 4 + movi_bytes, fourcc(b"movi")]);
+    h
+}
+
+/// H.264 into MP4, starting at the first keyframe
+struct Mp4Recorder {
+    file: Option<BufWriter<File>>,
+    writer: Option<mp4::Mp4Writer<BufWriter<File>>>,
+    start: Option<Instant>,
+    /// Held until the next frame says how long it lasts
+    pending: Option<(Instant, bool, Vec<u8>)>,
+}
+
+impl Mp4Recorder {
+    fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        Ok(Self { file: Some(BufWriter::new(file)), writer: None, start: None, pending: None })
+    }
+
+    fn write(&mut self, access_unit: &[u8], frame: &RawFrame, timestamp: Instant) -> Result<bool, String> {
+        let mut sps = None;
+        let mut pps = None;
+        let mut keyframe = false;
+        let mut sample = Vec::with_capacity(access_unit.len());
+        for nal in nal_units(access_unit) {
+            match nal.first().map(|b| b & 0x1F) {
+                Some(7) => sps = Some(nal),
+                Some(8) => pps = Some(nal),
+                // Access unit delimiters aren't stored in MP4
+                Some(9) | None => {}
+                Some(kind) => {
+                    keyframe |= kind == 5;
+                    sample.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+                    sample.extend_from_slice(nal);
+                }
+            }
+        }
+
+        if self.writer.is_none() {
+            let (Some(sps), Some(pps), true) = (sps, pps, keyframe) else {
+                return Ok(false);
+            };
+            self.open(sps, pps, frame)?;
+        }
+        if sample.is_empty() {
+            return Ok(false);
+        }
+        if let Some(previous) = self.pending.replace((timestamp, keyframe, sample)) {
+            let duration = timestamp.saturating_duration_since(previous.0);
+            self.write_sample(previous, duration)?;
+        }
+        Ok(true)
+    }
+
+    fn open(&mut self, sps: &[u8], pps: &[u8], frame: &RawFrame) -> Result<(), String> {
+        use mp4::{AvcConfig, MediaConfig, Mp4Config, TrackConfig, TrackType};
+
+        let file = self.file.take().ok_or("Recording already closed")?;
+        let config = Mp4Config {
+            major_brand: str::parse("isom").unwrap(),
+            minor_version: 512,
+            compatible_brands: ["isom", "iso2", "avc1", "mp41"].iter().map(|brand| str::parse(brand).unwrap()).collect(),
+            timescale: 1000,
+        };
+        let mut writer = mp4::Mp4Writer::write_start(file, &config)
+            .map_err(|e| format!("Failed to start MP4: {}", e))?;
+        writer.add_track(&TrackConfig {
+            track_type: TrackType::Video,
+            timescale: MP4_TIMESCALE,
+            language: "und".to_string(),
+            media_conf: MediaConfig::AvcConfig(AvcConfig {
+                width: frame.width as u16,
+                height: frame.height as u16,
+                seq_param_set: sps.to_vec(),
+                pic_param_set: pps.to_vec(),
+            }),
+        }).map_err(|e| format!("Failed to add MP4 track: {}", e))?;
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    fn write_sample(&mut self, (timestamp, keyframe, bytes): (Instant, bool, Vec<u8>), duration: Duration) -> Result<(), String> {
+        let writer = self.writer.as_mut().ok_or("Recording not started")?;
+        let start = *self.start.get_or_insert(timestamp);
+        let ticks = |d: Duration| (d.as_micros() as u64 * MP4_TIMESCALE as u64 / 1_000_000) as u32;
+        writer.write_sample(1, &mp4::Mp4Sample {
+            start_time: ticks(timestamp.saturating_duration_since(start)) as u64,
+            duration: ticks(duration).max(1),
+            rendering_offset: 0,
+            is_sync: keyframe,
+            bytes: bytes::Bytes::from(bytes),
+        }).map_err(|e| format!("Failed to write MP4 sample: {}", e))
+    }
+
+    fn finish(mut self) -> Result<u64, String> {
+        if let Some(last) = self.pending.take() {
+            self.write_sample(last, Duration::from_millis(DEFAULT_FRAME_DURATION_MS))?;
+        }
+        // Nothing recorded: the file stays empty
+        let Some(mut writer) = self.writer.take() else {
+            return Ok(0);
+        };
+        writer.write_end().map_err(|e| format!("Failed to finish MP4: {}", e))?;
+        let mut file = writer.into_writer();
+        file.flush().map_err(|e| format!("Failed to finish MP4: {}", e))?;
+        file.stream_position().map_err(|e| format!("Failed to finish MP4: {}", e))
+    }
+}
+
+/// NAL units of an Annex B access unit, start codes stripped
+fn nal_units(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0, 0, 1] {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    let ends: Vec<usize> = starts.iter().skip(1).map(|&s| s - 3).chain(std::iter::once(data.len())).collect();
+    starts.into_iter().zip(ends).map(move |(start, end)| {
+        // A 4-byte start code leaves its leading zero on the previous unit
+        let mut end = end;
+        while end > start && data[end - 1] == 0 && end < data.len() {
+            end -= 1;
+        }
+        &data[start..end]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nal_units() {
+        let access_unit = [0, 0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x68, 3, 0, 0, 0, 1, 0x65, 4, 5];
+        let units: Vec<&[u8]> = nal_units(&access_unit).collect();
+        assert_eq!(units, [&[0x67, 1, 2][..], &[0x68, 3], &[0x65, 4, 5]]);
+    }
+
+    #[test]
+    fn test_avi_header_layout() {
+        let header = avi_header(640, 480, 30, 33_333, 1000, 30, 200);
+        assert_eq!(header.len(), AVI_HEADER_SIZE);
+        assert_eq!(&header[..4], b"RIFF");
+        assert_eq!(&header[216..224], b"\xEC\x03\0\0movi");
+        // hdrl list covers everything up to the movi list
+        assert_eq!(u32::from_le_bytes(header[16..20].try_into().unwrap()), 192);
+        assert_eq!(&header[100..104], b"strh");
+        assert_eq!(&header[164..168], b"strf");
+    }
+}
@@ -0,0 +1,263 @@
+// Recording Encryption - sealed recording files for shared lab machines
+// Recordings of encrypted sessions shouldn't be readable by whoever uses the
+// machine next. The MP4 and AVI writers seek back to fill in their headers,
+// so a recording is written to a hidden `.part` file next to its destination
+// and sealed into `<name>.enc` when it finishes; the `.part` file is then
+// removed. A crash mid-recording leaves the `.part` file in the clear.
+//
+// File:  "SLREC" | version | key source | salt (16) | wrapped file key | chunks
+// Chunk: length (u32 LE) | nonce | ciphertext | tag, up to CHUNK_SIZE bytes of plain data
+// Every recording gets a random file key, sealed ("wrapped") with either
+//   the stream key      derived from the stream passphrase, so anyone who may
+//                       watch the stream may play the recording back
+//   a passphrase        given for the recording, with a salt of its own
+// Chunks are bound to their index and to whether they are the last one, so
+// they can't be reordered, dropped or cut off without decryption failing.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::stream_crypto::{self, StreamCipher, KEY_SIZE, OVERHEAD};
+
+const MAGIC: &[u8; 5] = b"SLREC";
+const VERSION: u8 = 1;
+const SALT_SIZE: usize = 16;
+const PREFIX_SIZE: usize = MAGIC.len() + 2 + SALT_SIZE;
+const CHUNK_SIZE: usize = 1 << 20;
+pub const EXTENSION: &str = "enc";
+
+/// What seals a recording's file key
+#[derive(Clone)]
+pub enum RecordingKey {
+    /// The running stream's key, which must come from the stream passphrase
+    Stream(Arc<StreamCipher>),
+    Passphrase(String),
+}
+
+impl RecordingKey {
+    fn source(&self) -> u8 {
+        match self {
+            RecordingKey::Stream(_) => 0,
+            RecordingKey::Passphrase(_) => 1,
+        }
+    }
+}
+
+/// The key for a new recording: `passphrase` if given, else the stream key when
+/// `encrypt` asks for it. `paired` streams have a throwaway key nobody could
+/// play the recording back with, so they need a passphrase.
+pub fn choose_key(
+    encrypt: bool,
+    passphrase: Option<&str>,
+    stream: Option<Arc<StreamCipher>>,
+    paired: bool,
+) -> Result<Option<RecordingKey>, String> {
+    match passphrase.filter(|p| !p.is_empty()) {
+        Some(passphrase) => Ok(Some(RecordingKey::Passphrase(passphrase.to_string()))),
+        None if !encrypt => Ok(None),
+        None if paired => Err("Paired sessions have a one-off key, give the recording a passphrase".to_string()),
+        None => stream
+            .map(|cipher| Some(RecordingKey::Stream(cipher)))
+            .ok_or_else(|| "Encrypting recordings needs stream encryption or a recording passphrase".to_string()),
+    }
+}
+
+/// Hidden file to record into, next to where the sealed file will go
+pub fn part_path(sealed: &Path) -> PathBuf {
+    let name = sealed.file_stem().unwrap_or_default().to_string_lossy();
+    sealed.with_file_name(format!(".{}.part", name))
+}
+
+/// `path` with `.enc` added after its extension
+pub fn sealed_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Seal `plain` into `sealed` and remove `plain`. Returns the sealed size.
+pub fn seal_file(plain: &Path, sealed: &Path, key: &RecordingKey) -> Result<u64, String> {
+    let salt: [u8; SALT_SIZE] = rand::random();
+    let mut prefix = Vec::with_capacity(PREFIX_SIZE);
+    prefix.extend_from_slice(MAGIC);
+    prefix.extend_from_slice(&[VERSION, key.source()]);
+    prefix.extend_from_slice(&salt);
+
+    let wrapping = match key {
+        RecordingKey::Stream(cipher) => cipher.clone(),
+        RecordingKey::Passphrase(passphrase) => Arc::new(StreamCipher::from_key(&stream_crypto::derive_key(passphrase, &salt))),
+    };
+    let file_key = StreamCipher::generate_key();
+    let wrapped = wrapping.seal(&prefix, &file_key)?;
+    let cipher = StreamCipher::from_key(&file_key);
+
+    let mut input = BufReader::new(File::open(plain).map_err(|e| format!("Failed to open {}: {}", plain.display(), e))?);
+    let write_error = |e: std::io::Error| format!("Failed to write {}: {}", sealed.display(), e);
+    let mut output = BufWriter::new(File::create(sealed).map_err(write_error)?);
+    output.write_all(&prefix).map_err(write_error)?;
+    output.write_all(&wrapped).map_err(write_error)?;
+
+    let mut bytes = (prefix.len() + wrapped.len()) as u64;
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    let mut filled = read_full(&mut input, &mut chunk, plain)?;
+    for index in 0u64.. {
+        // Read ahead to know whether this chunk is the last
+        let mut next = vec![0u8; CHUNK_SIZE];
+        let next_filled = if filled == CHUNK_SIZE { read_full(&mut input, &mut next, plain)? } else { 0 };
+        let last = next_filled == 0;
+        let sealed_chunk = cipher.seal(&chunk_aad(&prefix, index, last), &chunk[..filled])?;
+        output.write_all(&(sealed_chunk.len() as u32).to_le_bytes()).map_err(write_error)?;
+        output.write_all(&sealed_chunk).map_err(write_error)?;
+        bytes += 4 + sealed_chunk.len() as u64;
+        if last {
+            break;
+        }
+        chunk = next;
+        filled = next_filled;
+    }
+    output.flush().map_err(write_error)?;
+    drop(input);
+
+    std::fs::remove_file(plain).map_err(|e| format!("Failed to remove {}: {}", plain.display(), e))?;
+    eprintln!("🔒 Recording sealed to {}", sealed.display());
+    Ok(bytes)
+}
+
+/// Decrypt `sealed` into `plain`, with the stream or recording passphrase it was sealed with
+pub fn open_file(sealed: &Path, plain: &Path, passphrase: &str) -> Result<u64, String> {
+    let mut input = BufReader::new(File::open(sealed).map_err(|e| format!("Failed to open {}: {}", sealed.display(), e))?);
+    let mut prefix = [0u8; PREFIX_SIZE];
+    input.read_exact(&mut prefix).map_err(|_| "Not an encrypted recording".to_string())?;
+    if &prefix[..MAGIC.len()] != MAGIC {
+        return Err("Not an encrypted recording".to_string());
+    }
+    if prefix[MAGIC.len()] != VERSION {
+        return Err(format!("Unsupported recording encryption version {}", prefix[MAGIC.len()]));
+    }
+    let salt = &prefix[MAGIC.len() + 2..];
+    let wrapping = match prefix[MAGIC.len() + 1] {
+        0 => StreamCipher::from_passphrase(passphrase)?,
+        1 => StreamCipher::from_key(&stream_crypto::derive_key(passphrase, salt)),
+        source => return Err(format!("Unknown recording key source {}", source)),
+    };
+
+    let mut wrapped = [0u8; KEY_SIZE + OVERHEAD];
+    input.read_exact(&mut wrapped).map_err(|_| "Encrypted recording is truncated".to_string())?;
+    let file_key: [u8; KEY_SIZE] = wrapping
+        .open(&prefix, &wrapped)
+        .map_err(|_| "Wrong passphrase for this recording".to_string())?
+        .try_into()
+        .map_err(|_| "Corrupt recording key".to_string())?;
+    let cipher = StreamCipher::from_key(&file_key);
+
+    let write_error = |e: std::io::Error| format!("Failed to write {}: {}", plain.display(), e);
+    let mut output = BufWriter::new(File::create(plain).map_err(write_error)?);
+    let mut bytes = 0u64;
+    let mut index = 0u64;
+    let mut next_length = read_length(&mut input)?;
+    while let Some(length) = next_length {
+        if length > CHUNK_SIZE + OVERHEAD {
+            return Err("Corrupt encrypted recording".to_string());
+        }
+        let mut sealed_chunk = vec![0u8; length];
+        input.read_exact(&mut sealed_chunk).map_err(|_| "Encrypted recording is truncated".to_string())?;
+        next_length = read_length(&mut input)?;
+        let chunk = cipher
+            .open(&chunk_aad(&prefix, index, next_length.is_none()), &sealed_chunk)
+            .map_err(|_| "Encrypted recording is corrupt or incomplete".to_string())?;
+        output.write_all(&chunk).map_err(write_error)?;
+        bytes += chunk.len() as u64;
+        index += 1;
+    }
+    if index == 0 {
+        return Err("Encrypted recording is truncated".to_string());
+    }
+    output.flush().map_err(write_error)?;
+    Ok(bytes)
+}
+
+fn chunk_aad(prefix: &[u8], index: u64, last: bool) -> Vec<u8> {
+    let mut aad = prefix.to_vec();
+    aad.extend_from_slice(&index.to_le_bytes());
+    aad.push(last as u8);
+    aad
+}
+
+// Fill `buffer` unless the file ends first
+fn read_full(input: &mut impl Read, buffer: &mut [u8], path: &Path) -> Result<usize, String> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match input.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+    Ok(filled)
+}
+
+// `None` at the end of the file
+fn read_length(input: &mut impl Read) -> Result<Option<usize>, String> {
+    let mut length = [0u8; 4];
+    match read_full(input, &mut length, Path::new("recording"))? {
+        0 => Ok(None),
+        4 => Ok(Some(u32::from_le_bytes(length) as usize)),
+        _ => Err("Encrypted recording is truncated".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_key() {
+        let stream = Some(Arc::new(StreamCipher::from_passphrase("stream-pass").unwrap()));
+        assert!(choose_key(false, None, stream.clone(), false).unwrap().is_none());
+        assert!(matches!(choose_key(false, Some("pw"), None, false), Ok(Some(RecordingKey::Passphrase(_)))));
+        assert!(matches!(choose_key(true, Some(""), stream.clone(), false), Ok(Some(RecordingKey::Stream(_)))));
+        assert!(choose_key(true, None, stream, true).is_err());
+        assert!(choose_key(true, None, None, false).is_err());
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let dir = std::env::temp_dir().join(format!("recording-crypto-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sealed = sealed_path(&dir.join("class.avi"));
+        assert_eq!(sealed.file_name().unwrap(), "class.avi.enc");
+        let plain = part_path(&sealed);
+        assert_eq!(plain.file_name().unwrap(), ".class.avi.part");
+
+        // Just over one chunk, so the last-chunk binding is exercised
+        let data: Vec<u8> = (0..CHUNK_SIZE + 1000).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&plain, &data).unwrap();
+        let key = RecordingKey::Passphrase("lop-hoc-42".to_string());
+        let size = seal_file(&plain, &sealed, &key).unwrap();
+        assert!(!plain.exists());
+        assert_eq!(std::fs::metadata(&sealed).unwrap().len(), size);
+
+        let opened = dir.join("opened.avi");
+        assert!(open_file(&sealed, &opened, "doan-sai").is_err());
+        assert_eq!(open_file(&sealed, &opened, "lop-hoc-42").unwrap(), data.len() as u64);
+        assert_eq!(std::fs::read(&opened).unwrap(), data);
+
+        // Dropping the last chunk must not go unnoticed
+        let bytes = std::fs::read(&sealed).unwrap();
+        let first_chunk = PREFIX_SIZE + KEY_SIZE + OVERHEAD + 4 + CHUNK_SIZE + OVERHEAD;
+        std::fs::write(&sealed, &bytes[..first_chunk]).unwrap();
+        assert!(open_file(&sealed, &opened, "lop-hoc-42").is_err());
+
+        // Stream-key recordings open with the stream passphrase
+        std::fs::write(&plain, b"frames").unwrap();
+        let stream = RecordingKey::Stream(Arc::new(StreamCipher::from_passphrase("stream-pass").unwrap()));
+        seal_file(&plain, &sealed, &stream).unwrap();
+        open_file(&sealed, &opened, "stream-pass").unwrap();
+        assert_eq!(std::fs::read(&opened).unwrap(), b"frames");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+// Remote Input - mouse/keyboard events from a viewer, injected on the server
+// For "remote assist": the viewer sends events back to the stream's source
+// address, the same reverse path as keyframe requests and receiver reports.
+// The server ignores them unless remote control was explicitly enabled.
+//
+// Wire format: "INPT" | JSON-encoded InputEvent
+// Pointer positions are normalized (0.0 - 1.0) to the shared screen, so the
+// viewer doesn't need to know the server's resolution. They map to the main display.
+//   Windows: SendInput
+//   macOS/Linux: enigo
+
+use serde::{Deserialize, Serialize};
+
+pub const INPUT_MAGIC: &[u8; 4] = b"INPT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputEvent {
+    MouseMove { x: f32, y: f32 },
+    MouseButton { button: MouseButton, pressed: bool },
+    /// Wheel notches, positive is down / right
+    Scroll { dx: i32, dy: i32 },
+    /// `key` is a DOM `KeyboardEvent.key` value ("a", "Enter", "ArrowLeft" ...)
+    Key { key: String, pressed: bool },
+}
+
+impl InputEvent {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = INPUT_MAGIC.to_vec();
+        // Serializing a plain enum can't fail
+        out.extend_from_slice(&serde_json::to_vec(self).unwrap_or_default());
+        out
+    }
+
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let json = buf.strip_prefix(INPUT_MAGIC)?;
+        serde_json::from_slice(json).ok()
+    }
+}
+
+/// Non-character keys the viewer can send
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NamedKey {
+    Enter,
+    Backspace,
+    Tab,
+    Escape,
+    Space,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Left,
+    Right,
+    Up,
+    Down,
+    Shift,
+    Control,
+    Alt,
+    Meta,
+}
+
+enum KeyInput {
+    Named(NamedKey),
+    Char(char),
+}
+
+fn key_input(key: &str) -> Option<KeyInput> {
+    let named = match key {
+        "Enter" => NamedKey::Enter,
+        "Backspace" => NamedKey::Backspace,
+        "Tab" => NamedKey::Tab,
+        "Escape" => NamedKey::Escape,
+        " " => NamedKey::Space,
+        "Delete" => NamedKey::Delete,
+        "Home" => NamedKey::Home,
+        "End" => NamedKey::End,
+        "PageUp" => NamedKey::PageUp,
+        "PageDown" => NamedKey::PageDown,
+        "ArrowLeft" => NamedKey::Left,
+        "ArrowRight" => NamedKey::Right,
+        "ArrowUp" => NamedKey::Up,
+        "ArrowDown" => NamedKey::Down,
+        "Shift" => NamedKey::Shift,
+        "Control" => NamedKey::Control,
+        "Alt" => NamedKey::Alt,
+        "Meta" => NamedKey::Meta,
+        _ => {
+            let mut chars = key.chars();
+            return match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(KeyInput::Char(c)),
+                _ => None, // F-keys, dead keys etc. are not forwarded
+            };
+        }
+    };
+    Some(KeyInput::Named(named))
+}
+
+#[cfg(target_os = "windows")]
+pub struct InputInjector;
+
+#[cfg(target_os = "windows")]
+impl InputInjector {
+    pub fn new() -> Result<Self, String> {
+        Ok(Self)
+    }
+
+    pub fn inject(&mut self, event: &InputEvent) -> Result<(), String> {
+        use windows::Win32::UI::Input::KeyboardAndMouse::*;
+
+        const WHEEL_DELTA: i32 = 120;
+
+        let mouse = |dx: i32, dy: i32, data: i32, flags: MOUSE_EVENT_FLAGS| INPUT {
+            r#type: INPUT_MOUSE,
+            Anonymous: INPUT_0 {
+                mi: MOUSEINPUT { dx, dy, mouseData: data, dwFlags: flags, time: 0, dwExtraInfo: 0 },
+            },
+        };
+        let keyboard = |vk: VIRTUAL_KEY, scan: u16, flags: KEYBD_EVENT_FLAGS| INPUT {
+            r#type: INPUT_KEYBOARD,
+            Anonymous: INPUT_0 {
+                ki: KEYBDINPUT { wVk: vk, wScan: scan, dwFlags: flags, time: 0, dwExtraInfo: 0 },
+            },
+        };
+
+        let inputs = match event {
+            InputEvent::MouseMove { x, y } => {
+                // Absolute coordinates span the main display as 0 - 65535
+                let dx = (x.clamp(0.0, 1.0) * 65535.0) as i32;
+                let dy = (y.clamp(0.0, 1.0) * 65535.0) as i32;
+                vec![mouse(dx, dy, 0, MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE)]
+            }
+            InputEvent::MouseButton { button, pressed } => {
+                let flags = match (button, pressed) {
+                    (MouseButton::Left, true) => MOUSEEVENTF_LEFTDOWN,
+                    (MouseButton::Left, false) => MOUSEEVENTF_LEFTUP,
+                    (MouseButton::Middle, true) => MOUSEEVENTF_MIDDLEDOWN,
+                    (MouseButton::Middle, false) => MOUSEEVENTF_MIDDLEUP,
+                    (MouseButton::Right, true) => MOUSEEVENTF_RIGHTDOWN,
+                    (MouseButton::Right, false) => MOUSEEVENTF_RIGHTUP,
+                };
+                vec![mouse(0, 0, 0, flags)]
+            }
+            InputEvent::Scroll { dx, dy } => {
+                let mut inputs = Vec::new();
+                if *dy != 0 {
+                    // Windows wheel is positive away from the user (up)
+                    inputs.push(mouse(0, 0, -dy * WHEEL_DELTA, MOUSEEVENTF_WHEEL));
+                }
+                if *dx != 0 {
+                    inputs.push(mouse(0, 0, dx * WHEEL_DELTA, MOUSEEVENTF_HWHEEL));
+                }
+                inputs
+            }
+            InputEvent::Key { key, pressed } => {
+                let up = if *pressed { KEYBD_EVENT_FLAGS(0) } else { KEYEVENTF_KEYUP };
+                match key_input(key).ok_or_else(|| format!("Unsupported key: {:?}", key))? {
+                    KeyInput::Named(named) => {
+                        let vk = match named {
+                            NamedKey::Enter => VK_RETURN,
+                            NamedKey::Backspace => VK_BACK,
+                            NamedKey::Tab => VK_TAB,
+                            NamedKey::Escape => VK_ESCAPE,
+                            NamedKey::Space => VK_SPACE,
+                            NamedKey::Delete => VK_DELETE,
+                            NamedKey::Home => VK_HOME,
+                            NamedKey::End => VK_END,
+                            NamedKey::PageUp => VK_PRIOR,
+                            NamedKey::PageDown => VK_NEXT,
+                            NamedKey::Left => VK_LEFT,
+                            NamedKey::Right => VK_RIGHT,
+                            NamedKey::Up => VK_UP,
+                            NamedKey::Down => VK_DOWN,
+                            NamedKey::Shift => VK_SHIFT,
+                            NamedKey::Control => VK_CONTROL,
+                            NamedKey::Alt => VK_MENU,
+                            NamedKey::Meta => VK_LWIN,
+                        };
+                        vec![keyboard(vk, 0, up)]
+                    }
+                    // Characters go in as Unicode so the server's keyboard layout doesn't matter
+                    KeyInput::Char(c) => {
+                        let mut units = [0u16; 2];
+                        c.encode_utf16(&mut units)
+                            .iter()
+                            .map(|&unit| keyboard(VIRTUAL_KEY(0), unit, KEYEVENTF_UNICODE | up))
+                            .collect()
+                    }
+                }
+            }
+        };
+
+        if inputs.is_empty() {
+            return Ok(());
+        }
+        let sent = unsafe { SendInput(&inputs, std::mem::size_of::<INPUT>() as i32) };
+        if sent as usize != inputs.len() {
+            return Err(format!("SendInput injected {} of {} events", sent, inputs.len()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub struct InputInjector {
+    enigo: enigo::Enigo,
+}
+
+#[cfg(not(target_os = "windows"))]
+impl InputInjector {
+    pub fn new() -> Result<Self, String> {
+        let enigo = enigo::Enigo::new(&enigo::Settings::default())
+            .map_err(|e| format!("Failed to set up input injection: {}", e))?;
+        Ok(Self { enigo })
+    }
+
+    pub fn inject(&mut self, event: &InputEvent) -> Result<(), String> {
+        use enigo::{Axis, Button, Coordinate, Direction, Key, Keyboard, Mouse};
+
+        let direction = |pressed: bool| if pressed { Direction::Press } else { Direction::Release };
+
+        let result = match event {
+            InputEvent::MouseMove { x, y } => {
+                let (width, height) = self.enigo.main_display()
+                    .map_err(|e| format!("Failed to get display size: {}", e))?;
+                let px = (x.clamp(0.0, 1.0) * (width - 1) as f32) as i32;
+                let py = (y.clamp(0.0, 1.0) * (height - 1) as f32) as i32;
+                self.enigo.move_mouse(px, py, Coordinate::Abs)
+            }
+            InputEvent::MouseButton { button, pressed } => {
+                let button = match button {
+                    MouseButton::Left => Button::Left,
+                    MouseButton::Middle => Button::Middle,
+                    MouseButton::Right => Button::Right,
+                };
+                self.enigo.button(button, direction(*pressed))
+            }
+            InputEvent::Scroll { dx, dy } => {
+                if *dy != 0 {
+                    self.enigo.scroll(*dy, Axis::Vertical)
+                        .map_err(|e| format!("Failed to scroll: {}", e))?;
+                }
+                if *dx != 0 {
+                    self.enigo.scroll(*dx, Axis::Horizontal)
+                        .map_err(|e| format!("Failed to scroll: {}", e))?;
+                }
+                Ok(())
+            }
+            InputEvent::Key { key, pressed } => {
+                let key = match key_input(key).ok_or_else(|| format!("Unsupported key: {:?}", key))? {
+                    KeyInput::Named(named) => match named {
+                        NamedKey::Enter => Key::Return,
+                        NamedKey::Backspace => Key::Backspace,
+                        NamedKey::Tab => Key::Tab,
+                        NamedKey::Escape => Key::Escape,
+                        NamedKey::Space => Key::Space,
+                        NamedKey::Delete => Key::Delete,
+                        NamedKey::Home => Key::Home,
+                        NamedKey::End => Key::End,
+                        NamedKey::PageUp => Key::PageUp,
+                        NamedKey::PageDown => Key::PageDown,
+                        NamedKey::Left => Key::LeftArrow,
+                        NamedKey::Right => Key::RightArrow,
+                        NamedKey::Up => Key::UpArrow,
+                        NamedKey::Down => Key::DownArrow,
+                        NamedKey::Shift => Key::Shift,
+                        NamedKey::Control => Key::Control,
+                        NamedKey::Alt => Key::Alt,
+                        NamedKey::Meta => Key::Meta,
+                    },
+                    KeyInput::Char(c) => Key::Unicode(c),
+                };
+                self.enigo.key(key, direction(*pressed))
+            }
+        };
+        result.map_err(|e| format!("Failed to inject input: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_event_round_trip() {
+        let events = [
+            InputEvent::MouseMove { x: 0.25, y: 0.75 },
+            InputEvent::MouseButton { button: MouseButton::Right, pressed: true },
+            InputEvent::Scroll { dx: 0, dy: -3 },
+            InputEvent::Key { key: "Enter".to_string(), pressed: false },
+        ];
+        for event in events {
+            assert_eq!(InputEvent::parse(&event.serialize()), Some(event));
+        }
+        assert_eq!(InputEvent::parse(b"KEYFRAME"), None);
+    }
+
+    #[test]
+    fn test_key_input() {
+        assert!(matches!(key_input("ArrowLeft"), Some(KeyInput::Named(NamedKey::Left))));
+        assert!(matches!(key_input(" "), Some(KeyInput::Named(NamedKey::Space))));
+        assert!(matches!(key_input("ă"), Some(KeyInput::Char('ă'))));
+        assert!(key_input("F13").is_none());
+    }
+}
@@ -0,0 +1,103 @@
+// Rooms - independent broadcasts next to the main stream
+// One machine feeding several groups different content at once, e.g. display 1
+// to room A and display 2 to room B. Each room is a server of its own, keyed
+// by name: its own display, ports, epoch, encryption and viewers. Viewers join
+// a room by its ports (see `set_viewer_ports`); everything else is copied from
+// the main stream's settings, minus what needs a port only one server can have.
+
+use serde::Serialize;
+use crate::access_control::ViewerRequest;
+use crate::settings::{PortMapping, StreamSettings};
+use crate::udp_server::UdpServer;
+
+pub struct Room {
+    pub display: usize,
+    pub server: UdpServer,
+}
+
+/// What `list_rooms` reports
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomInfo {
+    pub name: String,
+    pub display: usize,
+    pub ports: PortMapping,
+    pub running: bool,
+    pub frozen: bool,
+    /// PIN viewers enter to ask for approval, when the room requires it
+    pub session_pin: Option<String>,
+}
+
+/// A viewer asking to join a room, emitted as `room-join-request`
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomJoinRequest {
+    pub room: String,
+    pub request: ViewerRequest,
+}
+
+impl Room {
+    pub fn info(&self, name: &str) -> RoomInfo {
+        RoomInfo {
+            name: name.to_string(),
+            display: self.display,
+            ports: self.server.settings().ports,
+            running: self.server.is_running(),
+            frozen: self.server.is_frozen(),
+            session_pin: self.server.access_control().map(|access| access.pin().to_string()),
+        }
+    }
+}
+
+/// The main stream's settings for a room on `ports`. PIN pairing, WebRTC, RTP
+/// and MJPEG listen on fixed ports of their own, so they stay with the main
+/// stream; so do the extra displays, the preview and the session limit.
+pub fn room_settings(main: &StreamSettings, ports: PortMapping, passphrase: Option<String>) -> Result<StreamSettings, String> {
+    let mut settings = StreamSettings {
+        ports,
+        encryption_passphrase: passphrase,
+        pairing_pin: None,
+        extra_displays: Vec::new(),
+        session_limit: Default::default(),
+        // Each room picks its own
+        stream_id: 0,
+        ..main.clone()
+    };
+    settings.preview.enabled = false;
+    settings.webrtc.enabled = false;
+    settings.rtp.enabled = false;
+    settings.mjpeg.enabled = false;
+    settings.validate()?;
+    Ok(settings)
+}
+
+/// `ports` must not share a port with any of `taken`, the main stream's and the other rooms'
+pub fn check_ports<'a>(ports: &PortMapping, taken: impl IntoIterator<Item = &'a PortMapping>) -> Result<(), String> {
+    let wanted = [ports.data, ports.control, ports.discovery, ports.quic];
+    for other in taken {
+        let used = [other.data, other.control, other.discovery, other.quic];
+        if let Some(port) = wanted.iter().find(|port| used.contains(port)) {
+            return Err(format!("Port {} is already used by another stream", port));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room_ports_and_settings() {
+        let main = PortMapping::default();
+        let room = PortMapping { data: 6000, control: 6001, discovery: 6002, quic: 6003 };
+        assert!(check_ports(&room, [&main]).is_ok());
+        assert!(check_ports(&PortMapping { quic: main.control, ..room }, [&main]).is_err());
+
+        let mut settings = StreamSettings::default();
+        settings.pairing_pin = Some("1234".to_string());
+        settings.extra_displays = vec![1];
+        let room_settings = room_settings(&settings, room, None).unwrap();
+        assert_eq!(room_settings.ports, room);
+        assert!(room_settings.pairing_pin.is_none() && room_settings.extra_displays.is_empty());
+        assert!(super::room_settings(&settings, PortMapping { control: 6000, ..room }, None).is_err());
+    }
+}
@@ -0,0 +1,295 @@
+// RTP Output - the stream as standard RTP/JPEG (RFC 2435)
+// For kiosk receivers that can't run the app: VLC, ffplay or GStreamer
+// play it from the SDP that `get_rtp_sdp` returns (save it as stream.sdp).
+// Frames go to the stream's multicast group on their own port, next to the
+// regular stream, as baseline JPEG split over RTP packets:
+//
+//   RTP header (PT 26, 90kHz clock) | JPEG header | [quantization tables] | scan data
+//
+// RFC 2435 only carries 4:2:0/4:2:2 baseline JPEG with the standard Huffman
+// tables, at most 2040 pixels a side. The encoder's JPEG is sent as it is when
+// it fits; everything else (tile deltas, H.264, 4:4:4) is encoded again from
+// the raw frame.
+//
+// Like WebRTC, it can't be encrypted or access controlled, so servers that
+// require either don't offer it.
+
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Instant;
+use crate::access_control::Delivery;
+use crate::net_interfaces::IpVersion;
+use crate::screen_capture::RawFrame;
+
+pub const DEFAULT_PORT: u16 = 5004;
+const PAYLOAD_TYPE: u8 = 26; // JPEG, static payload type
+const CLOCK_RATE: u64 = 90_000;
+const MAX_PAYLOAD: usize = 1400; // Stays under a 1500 byte MTU with IP/UDP/RTP headers
+const MAX_DIMENSION: usize = 2040; // Width and height travel in units of 8 pixels, one byte each
+const MAX_WIDTH: usize = 1920;
+const JPEG_HEADER_SIZE: usize = 8;
+const RTP_HEADER_SIZE: usize = 12;
+const DYNAMIC_TABLES: u8 = 255; // Q values 128-255: the tables travel in the first packet
+
+/// The parts of a baseline JPEG that RFC 2435 sends
+#[derive(Debug, PartialEq)]
+struct JpegParts<'a> {
+    /// 0 for 4:2:2, 1 for 4:2:0
+    kind: u8,
+    width: usize,
+    height: usize,
+    /// Luma then chroma table, 64 bytes each, zigzag order as in the file
+    tables: Vec<u8>,
+    scan: &'a [u8],
+}
+
+/// Sends every frame as RTP/JPEG to the stream group's RTP port
+pub struct RtpOutput {
+    delivery: Delivery,
+    destination: SocketAddr,
+    quality: u8,
+    sequence: u16,
+    ssrc: u32,
+    started: Instant,
+}
+
+impl RtpOutput {
+    pub fn new(ip_version: IpVersion, port: u16, interface_index: u32, quality: u8) -> Self {
+        let destination = ip_version.stream_addr(port, interface_index);
+        eprintln!("📼 RTP/JPEG to {}", destination);
+        Self {
+            delivery: Delivery::Multicast { group: destination, tcp: None, quic: None },
+            destination,
+            quality,
+            sequence: rand::random(),
+            ssrc: rand::random(),
+            started: Instant::now(),
+        }
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+
+    /// Send `frame`, reusing the encoder's `payload` when RTP can carry it
+    pub fn send(&mut self, socket: &UdpSocket, payload: &[u8], frame: &RawFrame) -> Result<(), String> {
+        let reencoded;
+        let parts = match parse_jpeg(payload) {
+            Ok(parts) => parts,
+            Err(_) => {
+                reencoded = encode_rtp_jpeg(frame, self.quality)?;
+                parse_jpeg(&reencoded)?
+            }
+        };
+        let elapsed = frame.captured_at.saturating_duration_since(self.started);
+        let timestamp = (elapsed.as_micros() as u64 * CLOCK_RATE / 1_000_000) as u32;
+        for packet in self.packetize(&parts, timestamp) {
+            self.delivery.send(socket, &packet)
+                .map_err(|e| format!("RTP send failed: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn packetize(&mut self, jpeg: &JpegParts, timestamp: u32) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+        let mut offset = 0;
+        while offset < jpeg.scan.len() {
+            let mut packet = Vec::with_capacity(RTP_HEADER_SIZE + JPEG_HEADER_SIZE + MAX_PAYLOAD);
+            // Marker on the last packet is filled in below
+            packet.push(0x80);
+            packet.push(PAYLOAD_TYPE);
+            packet.extend_from_slice(&self.sequence.to_be_bytes());
+            packet.extend_from_slice(&timestamp.to_be_bytes());
+            packet.extend_from_slice(&self.ssrc.to_be_bytes());
+            self.sequence = self.sequence.wrapping_add(1);
+
+            packet.push(0); // Type-specific
+            packet.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+            packet.push(jpeg.kind);
+            packet.push(DYNAMIC_TABLES);
+            packet.push((jpeg.width / 8) as u8);
+            packet.push((jpeg.height / 8) as u8);
+            if offset == 0 {
+                packet.push(0); // MBZ
+                packet.push(0); // 8-bit precision
+                packet.extend_from_slice(&(jpeg.tables.len() as u16).to_be_bytes());
+                packet.extend_from_slice(&jpeg.tables);
+            }
+
+            let room = RTP_HEADER_SIZE + MAX_PAYLOAD - packet.len();
+            let end = (offset + room).min(jpeg.scan.len());
+            packet.extend_from_slice(&jpeg.scan[offset..end]);
+            offset = end;
+            if offset == jpeg.scan.len() {
+                packet[1] |= 0x80;
+            }
+            packets.push(packet);
+        }
+        packets
+    }
+}
+
+/// SDP players open to receive the RTP output
+pub fn sdp(ip_version: IpVersion, port: u16, name: &str) -> String {
+    let (family, group) = match ip_version {
+        IpVersion::V4 => ("IP4", format!("{}/32", ip_version.group())),
+        IpVersion::V6 => ("IP6", ip_version.group().to_string()),
+    };
+    format!(
+        "v=0\r\no=- 0 0 IN {family} {address}\r\ns={name}\r\nc=IN {family} {group}\r\nt=0 0\r\nm=video {port} RTP/AVP {pt}\r\na=rtpmap:{pt} JPEG/{clock}\r\na=recvonly\r\n",
+        address = ip_version.group(),
+        pt = PAYLOAD_TYPE,
+        clock = CLOCK_RATE,
+    )
+}
+
+/// 4:2:0 JPEG with the standard Huffman tables, dimensions a multiple of 16
+fn encode_rtp_jpeg(frame: &RawFrame, quality: u8) -> Result<Vec<u8>, String> {
+    let width = frame.width.min(MAX_WIDTH);
+    let height = (frame.height * width / frame.width.max(1)).min(MAX_DIMENSION);
+    let (width, height) = (width / 16 * 16, height / 16 * 16);
+    if width == 0 || height == 0 {
+        return Err(format!("Frame of {}x{} is too small for RTP", frame.width, frame.height));
+    }
+
+    let image = image::RgbaImage::from_raw(frame.width as u32, frame.height as u32, frame.rgba.clone())
+        .ok_or("Failed to create image from frame")?;
+    let image = if (width, height) == (frame.width, frame.height) {
+        image
+    } else {
+        image::imageops::resize(&image, width as u32, height as u32, image::imageops::FilterType::Triangle)
+    };
+
+    let mut jpeg = Vec::new();
+    let mut encoder = jpeg_encoder::Encoder::new(&mut jpeg, quality);
+    encoder.set_sampling_factor(jpeg_encoder::SamplingFactor::F_2_2);
+    encoder.encode(image.as_raw(), width as u16, height as u16, jpeg_encoder::ColorType::Rgba)
+        .map_err(|e| format!("Failed to encode JPEG for RTP: {}", e))?;
+    Ok(jpeg)
+}
+
+/// Pick apart a baseline JPEG, failing for anything RFC 2435 can't describe
+fn parse_jpeg(data: &[u8]) -> Result<JpegParts<'_>, String> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Err("Not a JPEG".to_string());
+    }
+    let mut tables: [Option<&[u8]>; 4] = [None; 4];
+    let mut frame: Option<(u8, usize, usize, [u8; 2])> = None;
+    let mut pos = 2;
+    loop {
+        if pos + 4 > data.len() || data[pos] != 0xFF {
+            return Err("Truncated JPEG".to_string());
+        }
+        let marker = data[pos + 1];
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + length).ok_or("Truncated JPEG segment")?;
+        match marker {
+            // Quantization tables, possibly several per segment
+            0xDB => {
+                let mut rest = segment;
+                while let Some((&info, tail)) = rest.split_first() {
+                    if info >> 4 != 0 || tail.len() < 64 {
+                        return Err("Only 8-bit quantization tables fit RTP/JPEG".to_string());
+                    }
+                    tables[(info & 0x03) as usize] = Some(&tail[..64]);
+                    rest = &tail[64..];
+                }
+            }
+            0xC0 => {
+                if segment.len() < 15 || segment[5] != 3 {
+                    return Err("RTP/JPEG needs a three component image".to_string());
+                }
+                let height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+                let width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+                let kind = match (segment[7], segment[10], segment[13]) {
+                    (0x21, 0x11, 0x11) => 0,
+                    (0x22, 0x11, 0x11) => 1,
+                    _ => return Err("RTP/JPEG needs 4:2:2 or 4:2:0 sampling".to_string()),
+                };
+                if segment[11] != segment[14] {
+                    return Err("Both chroma components must share a table".to_string());
+                }
+                frame = Some((kind, width, height, [segment[8], segment[11]]));
+            }
+            0xC1..=0xCF if marker != 0xC4 && marker != 0xC8 && marker != 0xCC => {
+                return Err("Only baseline JPEG fits RTP/JPEG".to_string());
+            }
+            0xDD if segment.len() >= 2 && segment[..2] != [0, 0] => {
+                return Err("Restart markers are not supported".to_string());
+            }
+            0xDA => {
+                let (kind, width, height, table_ids) = frame.ok_or("Scan before frame header")?;
+                if width > MAX_DIMENSION || height > MAX_DIMENSION || width % 8 != 0 || height % 8 != 0 {
+                    return Err(format!("{}x{} doesn't fit RTP/JPEG", width, height));
+                }
+                let mut all_tables = Vec::with_capacity(128);
+                for id in table_ids {
+                    all_tables.extend_from_slice(tables[(id & 0x03) as usize].ok_or("Missing quantization table")?);
+                }
+                let scan = &data[pos + 2 + length..];
+                let scan = scan.strip_suffix(&[0xFF, 0xD9]).unwrap_or(scan);
+                return Ok(JpegParts { kind, width, height, tables: all_tables, scan });
+            }
+            _ => {}
+        }
+        pos += 2 + length;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_frame(width: usize, height: usize) -> RawFrame {
+        RawFrame {
+            rgba: (0..width * height * 4).map(|i| (i % 251) as u8).collect(),
+            width,
+            height,
+            captured_at: Instant::now(),
+            dirty: None,
+            moves: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_reencoded_frame_fits_rtp() {
+        let jpeg = encode_rtp_jpeg(&test_frame(200, 100), 60).unwrap();
+        let parts = parse_jpeg(&jpeg).unwrap();
+        assert_eq!((parts.kind, parts.width, parts.height), (1, 192, 96));
+        assert_eq!(parts.tables.len(), 128);
+        assert!(!parts.scan.is_empty());
+        assert!(parse_jpeg(b"TILE").is_err());
+    }
+
+    #[test]
+    fn test_packets_cover_the_scan() {
+        let jpeg = encode_rtp_jpeg(&test_frame(640, 480), 90).unwrap();
+        let parts = parse_jpeg(&jpeg).unwrap();
+        let mut output = RtpOutput::new(IpVersion::V4, DEFAULT_PORT, 0, 90);
+        assert_eq!(output.destination(), "239.0.0.1:5004".parse().unwrap());
+        let packets = output.packetize(&parts, 1234);
+
+        let mut scan = Vec::new();
+        for (i, packet) in packets.iter().enumerate() {
+            assert!(packet.len() <= RTP_HEADER_SIZE + MAX_PAYLOAD);
+            let last = i == packets.len() - 1;
+            assert_eq!(packet[1], PAYLOAD_TYPE | if last { 0x80 } else { 0 });
+            let offset = u32::from_be_bytes([0, packet[13], packet[14], packet[15]]) as usize;
+            assert_eq!(offset, scan.len());
+            assert_eq!((packet[18], packet[19]), (80, 60));
+            let mut data = RTP_HEADER_SIZE + JPEG_HEADER_SIZE;
+            if i == 0 {
+                data += 4 + 128;
+            }
+            scan.extend_from_slice(&packet[data..]);
+        }
+        assert_eq!(scan, parts.scan);
+    }
+
+    #[test]
+    fn test_sdp_names_group_and_port() {
+        let sdp = sdp(IpVersion::V4, 5004, "GV-01");
+        assert!(sdp.contains("c=IN IP4 239.0.0.1/32\r\n"));
+        assert!(sdp.contains("m=video 5004 RTP/AVP 26\r\n"));
+    }
+}
@@ -1,107 +1,402 @@
 use scrap::{Capturer, Display};
-use image::{ImageBuffer, RgbaImage, DynamicImage};
-use std::io::Cursor;
-use std::thread;
-use std::time::Duration;
-
-const JPEG_QUALITY: u8 = 50; // Lower quality for smaller packets
-const MAX_WIDTH: u32 = 1280; // Scale down large screens
+use image::{ImageBuffer, RgbaImage};
+use std::cell::RefCell;
+use std::time::Instant;
+use crate::capture_format::CaptureFormat;
+use crate::capture_manager::{CaptureBackend, CaptureReport};
+use crate::cursor_capture::{self, CursorCapturer};
+use crate::pixel_convert;
+use crate::settings::DEFAULT_MAX_WIDTH;
 
+const GRAB_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+// A lost duplication is recreated this many times, this far apart, before scrap takes over
 #[cfg(all(target_os = "windows", feature = "dxgi"))]
-use std::sync::Mutex;
+const DXGI_REOPEN_ATTEMPTS: u32 = 10;
 #[cfg(all(target_os = "windows", feature = "dxgi"))]
-use crate::dxgi_capture::DxgiCapturer;
+const DXGI_REOPEN_INTERVAL: std::time::Duration = std::time::Duration::from_millis(300);
+
+// Capturers are not Send, so each thread that captures keeps its own.
+// A fresh capturer often has no frame ready, so it must outlive a single call.
+thread_local! {
+    static SCRAP_CAPTURER: RefCell<Option<ScrapCapture>> = const { RefCell::new(None) };
+}
+
+struct ScrapCapture {
+    capturer: Capturer,
+    width: usize,
+    height: usize,
+    display: Option<usize>,
+    generation: u64,
+}
+
+// Bumped by reset_capture() so every thread and session reopens its capturer
+static CAPTURE_GENERATION: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+// Display to capture, `None` means the primary display
+static SELECTED_DISPLAY: std::sync::Mutex<Option<usize>> = std::sync::Mutex::new(None);
+
+/// A captured frame as packed RGBA, already scaled to the stream size
+#[derive(Clone)]
+pub struct RawFrame {
+    pub rgba: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    /// Monotonic time the screen contents were grabbed
+    pub captured_at: Instant,
+    /// What changed since the source's previous frame apart from `moves`,
+    /// `None` when the source can't tell (anything may have)
+    pub dirty: Option<Vec<DirtyRect>>,
+    /// Regions that moved since the previous frame (scrolling), in order
+    pub moves: Vec<MovedRect>,
+}
+
+/// A region of a frame, in its pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl DirtyRect {
+    /// The part inside a `width` x `height` frame, `None` when nothing is
+    pub fn clipped(&self, width: usize, height: usize) -> Option<DirtyRect> {
+        let right = (self.x + self.width).min(width);
+        let bottom = (self.y + self.height).min(height);
+        (right > self.x && bottom > self.y).then(|| DirtyRect { width: right - self.x, height: bottom - self.y, ..*self })
+    }
+
+    pub fn intersects(&self, x: usize, y: usize, width: usize, height: usize) -> bool {
+        self.x < x + width && x < self.x + self.width && self.y < y + height && y < self.y + self.height
+    }
+
+    /// The same region once a `from` sized frame is scaled to `to`, grown by
+    /// `margin` source pixels for the pixels the filter blends in
+    pub fn scaled(&self, from: (usize, usize), to: (usize, usize), margin: usize) -> DirtyRect {
+        let (left, top) = (self.x.saturating_sub(margin), self.y.saturating_sub(margin));
+        let right = (self.x + self.width + margin).min(from.0);
+        let bottom = (self.y + self.height + margin).min(from.1);
+        let (x, y) = (left * to.0 / from.0.max(1), top * to.1 / from.1.max(1));
+        DirtyRect {
+            x,
+            y,
+            width: (right * to.0).div_ceil(from.0.max(1)).saturating_sub(x),
+            height: (bottom * to.1).div_ceil(from.1.max(1)).saturating_sub(y),
+        }
+    }
+}
+
+/// Pixels that moved within a frame: the `to` sized block at `from` went to `to`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovedRect {
+    pub from: (usize, usize),
+    pub to: DirtyRect,
+}
+
+impl MovedRect {
+    /// Whether both ends lie inside a `width` x `height` frame
+    pub fn fits(&self, width: usize, height: usize) -> bool {
+        self.from.0 + self.to.width <= width
+            && self.from.1 + self.to.height <= height
+            && self.to.clipped(width, height) == Some(self.to)
+    }
+
+    /// Move the pixels in `frame`, packed rows `width` pixels of `channels`
+    /// bytes. The move has to fit the frame.
+    pub fn apply(&self, frame: &mut [u8], width: usize, channels: usize) {
+        let row_bytes = self.to.width * channels;
+        // Rows go bottom up when moving down, so none is overwritten before it's read
+        let down = self.to.y > self.from.1;
+        for i in 0..self.to.height {
+            let row = if down { self.to.height - 1 - i } else { i };
+            let src = ((self.from.1 + row) * width + self.from.0) * channels;
+            let dst = ((self.to.y + row) * width + self.to.x) * channels;
+            frame.copy_within(src..src + row_bytes, dst);
+        }
+    }
+}
 
 #[cfg(all(target_os = "windows", feature = "dxgi"))]
-static DXGI_CAPTURER: Mutex<Option<DxgiCapturer>> = Mutex::new(None);
+use crate::dxgi_capture::DxgiCapturer;
+
+/// Capture a specific display (index into `get_displays`) from now on
+pub fn select_display(index: usize) -> Result<(), String> {
+    let count = Display::all()
+        .map_err(|e| format!("Failed to get displays: {}", e))?
+        .len();
+    if index >= count {
+        return Err(format!("Display {} does not exist ({} connected)", index + 1, count));
+    }
+    *SELECTED_DISPLAY.lock().unwrap() = Some(index);
+    Ok(())
+}
+
+/// The display the main stream captures, `None` is the primary display
+pub fn selected_display() -> Option<usize> {
+    *SELECTED_DISPLAY.lock().unwrap()
+}
+
+fn open_display(selection: Option<usize>) -> Result<Display, String> {
+    match selection {
+        None => Display::primary()
+            .map_err(|e| format!("Failed to get primary display: {}", e)),
+        Some(index) => Display::all()
+            .map_err(|e| format!("Failed to get displays: {}", e))?
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| format!("Display {} is no longer connected", index + 1)),
+    }
+}
+
+/// Drop every cached capturer, e.g. after a system resume left them dead.
+/// They are reopened on the next capture.
+pub fn reset_capture() {
+    CAPTURE_GENERATION.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// One streaming session's hold on a display. The DXGI duplication
+/// lives here rather than in a static, so it is released as soon as the
+/// pipeline drops the session and the next one can open another display.
+pub struct ScreenCapture {
+    display: Option<usize>,
+    /// Formats offered to DXGI duplication, best first; empty means BGRA only
+    #[cfg_attr(not(all(target_os = "windows", feature = "dxgi")), allow(dead_code))]
+    formats: Vec<CaptureFormat>,
+    #[cfg(all(target_os = "windows", feature = "dxgi"))]
+    dxgi: DxgiState,
+    report: CaptureReport,
+    /// `None` when the pointer is sent as metadata instead (see cursor_stream.rs)
+    cursor: Option<CursorCapturer>,
+    /// See `StreamSettings::max_width`
+    max_width: u32,
+    /// Where the pointer was drawn into the last DXGI frame, dirty again in the next
+    #[cfg(all(target_os = "windows", feature = "dxgi"))]
+    last_cursor: Option<DirtyRect>,
+}
+
 #[cfg(all(target_os = "windows", feature = "dxgi"))]
-static TRIED_DXGI: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+enum DxgiState {
+    /// Not opened yet for this generation
+    Untried,
+    Open { capturer: DxgiCapturer, generation: u64 },
+    /// Lost to a mode change or fullscreen app, recreated from `retry_at` on
+    Lost { generation: u64, attempts: u32, retry_at: Instant },
+    /// Failed or unavailable, scrap is used until the next reset_capture()
+    Failed { generation: u64 },
+}
+
+impl ScreenCapture {
+    /// Capture `display` (`None` is the primary display) for the whole session
+    pub fn new(display: Option<usize>, formats: &[CaptureFormat], report: CaptureReport) -> Self {
+        Self {
+            display,
+            formats: formats.to_vec(),
+            #[cfg(all(target_os = "windows", feature = "dxgi"))]
+            dxgi: DxgiState::Untried,
+            report,
+            cursor: Some(CursorCapturer::new()),
+            max_width: DEFAULT_MAX_WIDTH,
+            #[cfg(all(target_os = "windows", feature = "dxgi"))]
+            last_cursor: None,
+        }
+    }
+
+    /// Scale to at most this width, 0 keeps the native resolution
+    pub fn set_max_width(&mut self, max_width: u32) {
+        self.max_width = max_width;
+    }
+
+    #[cfg_attr(not(any(all(target_os = "windows", feature = "dxgi"), all(target_os = "linux", feature = "wayland"))), allow(dead_code))]
+    pub fn max_width(&self) -> u32 {
+        self.max_width
+    }
 
-pub fn capture_screen() -> Result<Vec<u8>, String> {
+    /// Leave the pointer out of the frames
+    pub fn hide_cursor(&mut self) {
+        self.cursor = None;
+    }
+
+    /// `None` is the primary display
+    pub fn display(&self) -> Option<usize> {
+        self.display
+    }
+
+    /// `Ok(None)` means no new frame is ready yet
+    pub fn capture(&mut self) -> Result<Option<RawFrame>, String> {
+        #[cfg(all(target_os = "windows", feature = "dxgi"))]
+        if let Some(frame) = self.capture_dxgi()? {
+            return Ok(frame);
+        }
+
+        // Fallback to scrap (always available on all platforms)
+        match capture_full_resolution(self.display) {
+            Ok(Some(mut img)) => {
+                let captured_at = Instant::now();
+                self.report.activate(CaptureBackend::Scrap);
+                if let (Some(cursor), Some(origin)) = (self.cursor.as_mut(), cursor_capture::display_origin(self.display)) {
+                    cursor.draw(&mut img, origin);
+                }
+                Ok(Some(scale_frame(img, self.max_width, captured_at, None, Vec::new())))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                self.report.fail(CaptureBackend::Scrap, e.clone());
+                Err(e)
+            }
+        }
+    }
+
+    // `None` means DXGI isn't in use and scrap should capture instead
     #[cfg(all(target_os = "windows", feature = "dxgi"))]
-    {
-        // Try DXGI capture first (10x faster than scrap on Windows)
-        if !TRIED_DXGI.load(std::sync::atomic::Ordering::Relaxed) {
-            if crate::dxgi_capture::is_dxgi_available() {
-                match crate::dxgi_capture::create_dxgi_capturer(0) {
-                    Ok(capturer) => {
-                        eprintln!("✅ Using DXGI Desktop Duplication (high performance)");
-                        *DXGI_CAPTURER.lock().unwrap() = Some(capturer);
-                    }
-                    Err(e) => {
-                        eprintln!("⚠️  DXGI init failed: {}", e);
-                        eprintln!("   Falling back to scrap library");
+    fn capture_dxgi(&mut self) -> Result<Option<Option<RawFrame>>, String> {
+        let generation = CAPTURE_GENERATION.load(std::sync::atomic::Ordering::Relaxed);
+        let current = match &self.dxgi {
+            DxgiState::Untried => false,
+            DxgiState::Open { generation: opened, .. }
+            | DxgiState::Lost { generation: opened, .. }
+            | DxgiState::Failed { generation: opened } => *opened == generation,
+        };
+        if !current {
+            // Release the old duplication before asking for a new one
+            self.dxgi = DxgiState::Untried;
+            self.dxgi = self.open_dxgi(generation);
+        }
+        if let DxgiState::Lost { attempts, retry_at, .. } = self.dxgi {
+            // The screen is mid-change, no frames until the duplication is back
+            if Instant::now() < retry_at {
+                return Ok(Some(None));
+            }
+            self.dxgi = self.reopen_dxgi(generation, attempts);
+        }
+
+        let DxgiState::Open { capturer, .. } = &mut self.dxgi else {
+            return Ok(None);
+        };
+        match capturer.capture_frame() {
+            Ok(Some(frame)) => {
+                let captured_at = Instant::now();
+                let mut img: RgbaImage = ImageBuffer::from_raw(
+                    capturer.width() as u32,
+                    capturer.height() as u32,
+                    frame.rgba,
+                ).ok_or("Failed to create image buffer from DXGI frame")?;
+                self.report.activate(CaptureBackend::Dxgi);
+                let mut dirty = frame.dirty;
+                if let Some(cursor) = self.cursor.as_mut() {
+                    let drawn = cursor.draw(&mut img, capturer.origin());
+                    // Where the pointer was and is changed too
+                    if let Some(rects) = dirty.as_mut() {
+                        rects.extend(self.last_cursor.into_iter().chain(drawn));
                     }
+                    self.last_cursor = drawn;
                 }
-            } else {
-                eprintln!("ℹ️  DXGI not available, using scrap library");
+                Ok(Some(Some(scale_frame(img, self.max_width, captured_at, dirty, frame.moves))))
+            }
+            // No new frame available, this is normal
+            Ok(None) => Ok(Some(None)),
+            Err(e) if e == crate::dxgi_capture::ACCESS_LOST => {
+                eprintln!("⚠️  DXGI access lost (display mode changed), recreating the duplication");
+                self.dxgi = DxgiState::Lost { generation, attempts: 0, retry_at: Instant::now() };
+                Ok(Some(None))
+            }
+            Err(e) => {
+                eprintln!("❌ DXGI capture error: {}, switching to scrap", e);
+                self.report.fail(CaptureBackend::Dxgi, e);
+                self.dxgi = DxgiState::Failed { generation };
+                Ok(None)
             }
-            TRIED_DXGI.store(true, std::sync::atomic::Ordering::Relaxed);
         }
+    }
 
-        // Try to use DXGI if initialized
-        let mut dxgi_guard = DXGI_CAPTURER.lock().unwrap();
-        if let Some(ref mut capturer) = *dxgi_guard {
-            match capturer.capture_frame() {
-                Ok(rgba_data) => {
-                    // Successfully captured with DXGI
-                    return encode_rgba_to_jpeg(
-                        &rgba_data,
-                        capturer.width(),
-                        capturer.height(),
-                    );
-                }
-                Err(e) if e == "WouldBlock" => {
-                    // No new frame available, this is normal
-                    return Err("WouldBlock".to_string());
-                }
-                Err(e) => {
-                    eprintln!("❌ DXGI capture error: {}, switching to scrap", e);
-                    *dxgi_guard = None; // Disable DXGI, fallback to scrap
-                }
+    #[cfg(all(target_os = "windows", feature = "dxgi"))]
+    fn open_dxgi(&self, generation: u64) -> DxgiState {
+        if !crate::dxgi_capture::is_dxgi_available() {
+            eprintln!("ℹ️  DXGI not available, using scrap library");
+            self.report.fail(CaptureBackend::Dxgi, "no DXGI factory");
+            return DxgiState::Failed { generation };
+        }
+        match crate::dxgi_capture::create_dxgi_capturer(self.display, &self.formats) {
+            Ok(capturer) => {
+                eprintln!("✅ Using DXGI Desktop Duplication (high performance)");
+                DxgiState::Open { capturer, generation }
+            }
+            Err(e) => {
+                eprintln!("⚠️  DXGI init failed: {}", e);
+                eprintln!("   Falling back to scrap library");
+                self.report.fail(CaptureBackend::Dxgi, e);
+                DxgiState::Failed { generation }
             }
         }
-        drop(dxgi_guard);
     }
 
-    // Fallback to scrap (always available on all platforms)
-    capture_screen_scrap()
+    // Another go at a lost duplication, scrap after the last of the attempts
+    #[cfg(all(target_os = "windows", feature = "dxgi"))]
+    fn reopen_dxgi(&self, generation: u64, attempts: u32) -> DxgiState {
+        match crate::dxgi_capture::create_dxgi_capturer(self.display, &self.formats) {
+            Ok(capturer) => {
+                eprintln!("✅ DXGI duplication recreated");
+                DxgiState::Open { capturer, generation }
+            }
+            Err(_) if attempts + 1 < DXGI_REOPEN_ATTEMPTS => {
+                DxgiState::Lost { generation, attempts: attempts + 1, retry_at: Instant::now() + DXGI_REOPEN_INTERVAL }
+            }
+            Err(e) => {
+                eprintln!("❌ DXGI could not be recreated after {} attempts: {}, switching to scrap", DXGI_REOPEN_ATTEMPTS, e);
+                self.report.fail(CaptureBackend::Dxgi, e);
+                DxgiState::Failed { generation }
+            }
+        }
+    }
 }
 
-// Original scrap-based capture (fallback)
-fn capture_screen_scrap() -> Result<Vec<u8>, String> {
-    // Get primary display
-    let display = Display::primary()
-        .map_err(|e| format!("Failed to get primary display: {}", e))?;
-    
-    let width = display.width();
-    let height = display.height();
-    
-    // Create capturer
-    let mut capturer = Capturer::new(display)
-        .map_err(|e| format!("Failed to create capturer: {}", e))?;
+impl Drop for ScreenCapture {
+    fn drop(&mut self) {
+        // The scrap capturer can't leave its thread; the session ends on the
+        // thread that captured last, so that is where it gets released
+        SCRAP_CAPTURER.with(|slot| slot.borrow_mut().take());
+    }
+}
+
+/// The display as it is, not scaled to the stream size, through this thread's
+/// cached capturer. `Ok(None)` means no new frame is ready yet.
+pub fn capture_full_resolution(selection: Option<usize>) -> Result<Option<RgbaImage>, String> {
+    let generation = CAPTURE_GENERATION.load(std::sync::atomic::Ordering::Relaxed);
     
-    // Try to capture frame with timeout to prevent infinite loops
-    let max_retries = 30; // Max 300ms wait
-    let buffer = {
-        let mut retries = 0;
-        loop {
-            match capturer.frame() {
-                Ok(frame) => break frame,
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    retries += 1;
-                    if retries >= max_retries {
-                        return Err(format!("Capture timeout after {} retries - screen may be locked or unavailable", max_retries));
-                    }
-                    // Frame not ready yet, wait a bit
-                    thread::sleep(Duration::from_millis(10));
-                    continue;
-                }
-                Err(e) => return Err(format!("Failed to capture frame: {}", e)),
-            }
+    SCRAP_CAPTURER.with(|slot| {
+        let mut slot = slot.borrow_mut();
+        if !matches!(slot.as_ref(), Some(c) if c.display == selection && c.generation == generation) {
+            // Release the old capturer before opening the display again
+            *slot = None;
+            let display = open_display(selection)?;
+            let (width, height) = (display.width(), display.height());
+            
+            // Create capturer
+            let capturer = Capturer::new(display)
+                .map_err(|e| format!("Failed to create capturer: {}", e))?;
+            *slot = Some(ScrapCapture { capturer, width, height, display: selection, generation });
         }
-    };
-    
+        
+        let capture = slot.as_mut().unwrap();
+        let (width, height) = (capture.width, capture.height);
+        
+        // Frame not ready yet - the pipeline decides when to ask again
+        let result = match capture.capturer.frame() {
+            Ok(buffer) => bgra_to_image(&buffer, width, height).map(Some),
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(None),
+            Err(e) => Err(format!("Failed to capture frame: {}", e)),
+        };
+        
+        // Recreate the capturer on the next call after any failure
+        if result.is_err() {
+            *slot = None;
+        }
+        result
+    })
+}
+
+// Convert a (possibly padded) BGRA buffer into a full-size RGBA image
+fn bgra_to_image(buffer: &[u8], width: usize, height: usize) -> Result<RgbaImage, String> {
     // Validate buffer size before processing
     // Note: Buffer may have padding/stride, so it can be larger than expected
     let min_expected_size = width * height * 4; // BGRA = 4 bytes per pixel
@@ -121,135 +416,98 @@ fn capture_screen_scrap() -> Result<Vec<u8>, String> {
         ));
     }
     
-    // Convert BGRA to RGBA, handling stride properly
-    let mut rgba_data = Vec::with_capacity(width * height * 4);
-    for y in 0..height {
-        let row_start = y * stride;
-        for x in 0..width {
-            let pixel_offset = row_start + x * 4;
-            if pixel_offset + 3 < buffer.len() {
-                rgba_data.push(buffer[pixel_offset + 2]); // R
-                rgba_data.push(buffer[pixel_offset + 1]); // G
-                rgba_data.push(buffer[pixel_offset]);     // B
-                rgba_data.push(buffer[pixel_offset + 3]); // A
-            }
-        }
+    // Convert BGRA to RGBA row by row, skipping the stride padding
+    let mut rgba_data = vec![0; width * height * 4];
+    for (y, row) in rgba_data.chunks_exact_mut((width * 4).max(1)).enumerate() {
+        let start = y * stride;
+        pixel_convert::bgra_to_rgba(&buffer[start..start + width * 4], row, false);
     }
     
     // Create image
-    let img: RgbaImage = ImageBuffer::from_raw(width as u32, height as u32, rgba_data)
-        .ok_or("Failed to create image buffer - invalid dimensions or data")?;
-    
-    let mut dynamic_img = DynamicImage::ImageRgba8(img);
-    
-    // Scale down if too large
-    if width as u32 > MAX_WIDTH {
-        let scale = MAX_WIDTH as f32 / width as f32;
-        let new_height = (height as f32 * scale) as u32;
-        dynamic_img = dynamic_img.resize(MAX_WIDTH, new_height, image::imageops::FilterType::Lanczos3);
-    }
-    
-    // Convert RGBA to RGB (JPEG doesn't support alpha channel)
-    let rgb_img = dynamic_img.to_rgb8();
-    
-    // Encode to JPEG with compression
-    let mut buffer = Cursor::new(Vec::new());
-    let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, JPEG_QUALITY);
-    encoder.encode(
-        rgb_img.as_raw(),
-        rgb_img.width(),
-        rgb_img.height(),
-        image::ExtendedColorType::Rgb8
-    ).map_err(|e| format!("Failed to encode JPEG: {}", e))?;
-    
-    Ok(buffer.into_inner())
+    ImageBuffer::from_raw(width as u32, height as u32, rgba_data)
+        .ok_or_else(|| "Failed to create image buffer - invalid dimensions or data".to_string())
 }
 
-// Helper function to encode RGBA to JPEG
-fn encode_rgba_to_jpeg(rgba: &[u8], width: usize, height: usize) -> Result<Vec<u8>, String> {
-    // Convert RGBA to RGB
-    let mut rgb = Vec::with_capacity(width * height * 3);
-    for chunk in rgba.chunks_exact(4) {
-        rgb.push(chunk[0]); // R
-        rgb.push(chunk[1]); // G
-        rgb.push(chunk[2]); // B
-    }
-
-    let img: image::RgbImage = ImageBuffer::from_raw(width as u32, height as u32, rgb)
-        .ok_or("Failed to create RGB image buffer")?;
-
-    let mut dynamic_img = DynamicImage::ImageRgb8(img);
-
-    // Scale down if too large
-    if width as u32 > MAX_WIDTH {
-        let scale = MAX_WIDTH as f32 / width as f32;
-        let new_height = (height as f32 * scale) as u32;
-        dynamic_img = dynamic_img.resize(MAX_WIDTH, new_height, image::imageops::FilterType::Lanczos3);
-    }
-
-    // Encode to JPEG
-    let mut buffer = Cursor::new(Vec::new());
-    let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, JPEG_QUALITY);
-    
-    let rgb_img = dynamic_img.to_rgb8();
-    encoder.encode(
-        rgb_img.as_raw(),
-        rgb_img.width(),
-        rgb_img.height(),
-        image::ExtendedColorType::Rgb8,
-    ).map_err(|e| format!("Failed to encode JPEG: {}", e))?;
-
-    Ok(buffer.into_inner())
-}
-
-// Alternative: Capture with quality control
-pub fn capture_screen_with_quality(quality: u8) -> Result<Vec<u8>, String> {
-    let display = Display::primary()
-        .map_err(|e| format!("Failed to get primary display: {}", e))?;
-    
-    let width = display.width();
-    let height = display.height();
-    
+/// One frame of a display at full resolution, through a capturer of its own so
+/// a running stream is left alone. `None` is the primary display.
+pub fn grab_display(index: Option<usize>) -> Result<RgbaImage, String> {
+    let display = open_display(index)?;
+    let (width, height) = (display.width(), display.height());
     let mut capturer = Capturer::new(display)
         .map_err(|e| format!("Failed to create capturer: {}", e))?;
-    
-    let buffer = loop {
+
+    // A fresh capturer usually has nothing until the screen next updates
+    let deadline = Instant::now() + GRAB_TIMEOUT;
+    loop {
         match capturer.frame() {
-            Ok(frame) => break frame,
+            Ok(buffer) => return bgra_to_image(&buffer, width, height),
             Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                thread::sleep(Duration::from_millis(10));
-                continue;
+                if Instant::now() >= deadline {
+                    return Err(format!("No frame from the display within {:?}", GRAB_TIMEOUT));
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
             }
             Err(e) => return Err(format!("Failed to capture frame: {}", e)),
         }
+    }
+}
+
+/// Stream dimensions for a display: capped at `max_width` (0 for no cap), rounded
+/// down to even numbers because H.264 works on 2x2 chroma blocks
+pub fn output_size(width: usize, height: usize, max_width: u32) -> (usize, usize) {
+    let (width, height) = if max_width != 0 && width as u32 > max_width {
+        let scale = max_width as f32 / width as f32;
+        (max_width as usize, (height as f32 * scale) as usize)
+    } else {
+        (width, height)
+    };
+    (width & !1, height & !1)
+}
+
+/// Stream dimensions of the selected display, used to size the encoder up front
+pub fn stream_output_size(max_width: u32) -> Result<(usize, usize), String> {
+    display_output_size(selected_display(), max_width)
+}
+
+/// Stream dimensions of any display, `None` is the primary display
+pub fn display_output_size(index: Option<usize>, max_width: u32) -> Result<(usize, usize), String> {
+    let (width, height) = display_size(index)?;
+    Ok(output_size(width, height, max_width))
+}
+
+/// Full resolution of a display, `None` is the primary display
+pub fn display_size(index: Option<usize>) -> Result<(usize, usize), String> {
+    let display = open_display(index)?;
+    Ok((display.width(), display.height()))
+}
+
+/// Scale a full-resolution capture down to the stream size
+pub fn scale_frame(img: RgbaImage, max_width: u32, captured_at: Instant, dirty: Option<Vec<DirtyRect>>, moves: Vec<MovedRect>) -> RawFrame {
+    let native = (img.width() as usize, img.height() as usize);
+    let (width, height) = output_size(native.0, native.1, max_width);
+    let (img, dirty, moves) = if (width, height) != native {
+        // Lanczos3 reaches 3 output pixels out, that many times the scale in source pixels.
+        // Scaled moves rarely land on whole pixels, so their targets count as dirty instead.
+        let margin = 3 * native.0.div_ceil(width.max(1)) + 1;
+        let dirty = dirty.map(|rects| {
+            rects.iter()
+                .chain(moves.iter().map(|moved| &moved.to))
+                .map(|rect| rect.scaled(native, (width, height), margin))
+                .collect()
+        });
+        (image::imageops::resize(&img, width as u32, height as u32, image::imageops::FilterType::Lanczos3), dirty, Vec::new())
+    } else {
+        (img, dirty, moves)
     };
     
-    // Convert BGRA to RGBA
-    let mut rgba_data = Vec::with_capacity(buffer.len());
-    for chunk in buffer.chunks_exact(4) {
-        rgba_data.push(chunk[2]); // R
-        rgba_data.push(chunk[1]); // G
-        rgba_data.push(chunk[0]); // B
-        rgba_data.push(chunk[3]); // A
+    RawFrame {
+        rgba: img.into_raw(),
+        width,
+        height,
+        captured_at,
+        dirty,
+        moves,
     }
-    
-    let img: RgbaImage = ImageBuffer::from_raw(width as u32, height as u32, rgba_data)
-        .ok_or("Failed to create image buffer")?;
-    
-    // Convert RGBA to RGB (JPEG doesn't support alpha channel)
-    let rgb_img = DynamicImage::ImageRgba8(img).to_rgb8();
-    
-    // Encode with custom quality
-    let mut buffer = Cursor::new(Vec::new());
-    let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, quality);
-    encoder.encode(
-        rgb_img.as_raw(),
-        width as u32,
-        height as u32,
-        image::ExtendedColorType::Rgb8
-    ).map_err(|e| format!("Failed to encode JPEG: {}", e))?;
-    
-    Ok(buffer.into_inner())
 }
 
 // Get available displays
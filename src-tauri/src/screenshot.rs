@@ -0,0 +1,85 @@
+// Screenshot - one still of a display, outside the streaming loop
+// Full resolution and full quality, unlike stream frames, which are scaled
+// to the stream size and compressed for the network. Works with or without a
+// running stream.
+
+use std::io::Cursor;
+use std::path::Path;
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use crate::screen_capture;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScreenshotFormat {
+    Png,
+    Jpeg,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub format: ScreenshotFormat,
+    /// Base64 image, when it wasn't saved to a file
+    pub data: Option<String>,
+    /// Where it was saved
+    pub path: Option<String>,
+}
+
+/// Grab `display` (`None` is the primary one) and return it, or save it to `path`
+pub fn take(display: Option<usize>, format: ScreenshotFormat, path: Option<&Path>) -> Result<Screenshot, String> {
+    let image = screen_capture::grab_display(display)?;
+    let encoded = encode(&image, format)?;
+
+    let (data, path) = match path {
+        Some(path) => {
+            std::fs::write(path, &encoded)
+                .map_err(|e| format!("Failed to save screenshot to {}: {}", path.display(), e))?;
+            eprintln!("📸 Screenshot saved to {}", path.display());
+            (None, Some(path.display().to_string()))
+        }
+        None => (Some(base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &encoded)), None),
+    };
+    Ok(Screenshot {
+        width: image.width(),
+        height: image.height(),
+        format,
+        data,
+        path,
+    })
+}
+
+fn encode(image: &RgbaImage, format: ScreenshotFormat) -> Result<Vec<u8>, String> {
+    let mut buffer = Cursor::new(Vec::new());
+    match format {
+        ScreenshotFormat::Png => image
+            .write_to(&mut buffer, image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to encode PNG: {}", e))?,
+        ScreenshotFormat::Jpeg => {
+            // JPEG has no alpha
+            let rgb = image::DynamicImage::ImageRgba8(image.clone()).to_rgb8();
+            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, 100)
+                .encode(rgb.as_raw(), rgb.width(), rgb.height(), image::ExtendedColorType::Rgb8)
+                .map_err(|e| format!("Failed to encode JPEG: {}", e))?;
+        }
+    }
+    Ok(buffer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_formats() {
+        let image = RgbaImage::from_pixel(4, 2, image::Rgba([10, 200, 30, 255]));
+        let png = encode(&image, ScreenshotFormat::Png).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        assert_eq!(image::load_from_memory(&png).unwrap().to_rgba8(), image);
+
+        let jpeg = encode(&image, ScreenshotFormat::Jpeg).unwrap();
+        assert!(jpeg.starts_with(&[0xFF, 0xD8]));
+        assert_eq!(image::load_from_memory(&jpeg).unwrap().width(), 4);
+    }
+}
@@ -0,0 +1,104 @@
+// Session Timer - ends a broadcast after a set time
+// Teachers forget to stop sharing. With a limit the stream counts down through
+// its last minute, once a second, so both ends can show a banner, and then
+// stops, or pauses on the last frame (see `UdpServer::set_frozen`).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+pub const COUNTDOWN_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitAction {
+    #[default]
+    Stop,
+    /// Freeze on the last frame, the presenter resumes or stops by hand
+    Pause,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionLimit {
+    /// How long a stream may run, 0 for as long as it likes
+    pub max_duration_secs: u64,
+    pub action: LimitAction,
+}
+
+/// Time left, sent every second of the last minute; 0 when the limit was reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Countdown {
+    pub remaining_secs: u64,
+    pub action: LimitAction,
+}
+
+/// Runs until the limit is reached, cancelled on `cancel()` or drop
+pub struct SessionTimer {
+    running: Arc<AtomicBool>,
+}
+
+impl SessionTimer {
+    /// `on_countdown` hears about every second of the last minute, then `on_expired`
+    /// runs once with the action to take. `None` for a limit that isn't set.
+    pub fn start(
+        limit: SessionLimit,
+        on_countdown: impl Fn(Countdown) + Send + 'static,
+        on_expired: impl FnOnce(LimitAction) + Send + 'static,
+    ) -> Option<Self> {
+        if limit.max_duration_secs == 0 {
+            return None;
+        }
+        let deadline = Instant::now() + Duration::from_secs(limit.max_duration_secs);
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        eprintln!("⏲️  Stream {} after {}s", if limit.action == LimitAction::Stop { "stops" } else { "pauses" }, limit.max_duration_secs);
+        std::thread::spawn(move || {
+            while thread_running.load(Ordering::Relaxed) {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let remaining_secs = remaining.as_secs_f64().ceil() as u64;
+                if remaining_secs == 0 {
+                    eprintln!("⏲️  Session time is up");
+                    on_countdown(Countdown { remaining_secs: 0, action: limit.action });
+                    on_expired(limit.action);
+                    return;
+                }
+                if remaining_secs <= COUNTDOWN_SECS {
+                    on_countdown(Countdown { remaining_secs, action: limit.action });
+                }
+                // Wake up on whole seconds before the deadline
+                std::thread::sleep(remaining.saturating_sub(Duration::from_secs(remaining_secs - 1)));
+            }
+        });
+        Some(Self { running })
+    }
+
+    pub fn cancel(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for SessionTimer {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_counts_down_then_expires() {
+        let (tx, rx) = mpsc::channel();
+        let countdown = tx.clone();
+        let limit = SessionLimit { max_duration_secs: 2, action: LimitAction::Pause };
+        let _timer = SessionTimer::start(limit, move |c| countdown.send(Ok(c.remaining_secs)).unwrap(), move |action| tx.send(Err(action)).unwrap());
+
+        let events: Vec<_> = rx.iter().take(4).collect();
+        assert_eq!(events, vec![Ok(2), Ok(1), Ok(0), Err(LimitAction::Pause)]);
+        assert!(SessionTimer::start(SessionLimit::default(), |_| {}, |_| {}).is_none());
+    }
+}
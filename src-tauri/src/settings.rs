@@ -0,0 +1,669 @@
+// Stream Settings - shared between Tauri commands and the remote control API
+
+use std::net::IpAddr;
+use serde::{Deserialize, Serialize};
+use crate::capture_format::CaptureFormat;
+use crate::net_interfaces::IpVersion;
+use crate::session_timer::SessionLimit;
+
+pub const DEFAULT_TARGET_FPS: u32 = 30; // Target 30 FPS
+pub const DEFAULT_MIN_FPS: u32 = 10;    // Minimum 10 FPS
+pub const DEFAULT_MAX_FPS: u32 = 60;    // Maximum 60 FPS
+pub const DEFAULT_STALE_THRESHOLD_MS: u64 = 2000;
+pub const DEFAULT_STREAM_LOST_MS: u64 = 5000;
+pub const DEFAULT_MEMORY_LIMIT_MB: u64 = 256;
+pub const DEFAULT_MAX_EMIT_FPS: u32 = 60; // Faster than displays refresh only piles up in the webview
+pub const DEFAULT_VALIDATION_BUDGET_MS: u64 = 15;
+pub const DEFAULT_CHUNK_SIZE: usize = 8192; // Smaller chunks for UDP safety (8KB)
+pub const DEFAULT_SEND_BUFFER_KB: usize = 4096; // A few 1080p frames; OS defaults hold a fraction of one
+pub const DEFAULT_RECEIVE_BUFFER_KB: usize = 8192; // Rides out the viewer falling behind for a moment
+const MAX_SOCKET_BUFFER_KB: usize = 256 * 1024;
+pub const DEFAULT_ENCODE_WORKERS: usize = 0; // One per CPU core
+pub const MAX_ENCODE_WORKERS: usize = 16;
+pub const DEFAULT_DATA_PORT: u16 = 9999;
+pub const DEFAULT_CONTROL_PORT: u16 = 9996;
+pub const DEFAULT_DISCOVERY_PORT: u16 = 9995;
+pub const DEFAULT_QUIC_PORT: u16 = 9993;
+pub const DEFAULT_PREVIEW_FPS: u32 = 2;
+pub const DEFAULT_PREVIEW_QUALITY: u8 = 40;
+pub const DEFAULT_PREVIEW_MAX_WIDTH: u32 = 480;
+pub const DEFAULT_MAX_WIDTH: u32 = 1280; // Scale down large screens
+const MIN_MAX_WIDTH: u32 = 160;
+
+/// Which moment a frame's wire timestamp records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampSource {
+    /// When the frame was grabbed from the screen
+    #[default]
+    Capture,
+    /// When encoding finished, for sources whose capture time is unreliable
+    Encode,
+}
+
+/// WebP instead of JPEG frames
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebpMode {
+    #[default]
+    Off,
+    /// Smaller than JPEG at the same `jpeg_quality`, sharper text
+    Lossy,
+    /// Pixel-perfect, smaller than `lossless` frames but slower to encode
+    Lossless,
+}
+
+/// Settings applied to the next server session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamSettings {
+    pub target_fps: u32,
+    pub min_fps: u32,
+    pub max_fps: u32,
+    /// Starting JPEG quality, and the ceiling the pacer recovers to
+    pub jpeg_quality: u8,
+    /// Wider displays are scaled down to this width, 0 streams them at native resolution
+    pub max_width: u32,
+    /// Payload bytes per datagram
+    pub chunk_size: usize,
+    /// Most the video may send, in Mbit/s, so it leaves room on a shared uplink (0 is unlimited)
+    pub max_bitrate_mbps: f32,
+    /// Kernel buffer of the sending socket, OSes may grant less (0 keeps the OS default)
+    pub send_buffer_kb: usize,
+    /// Threads encoding in parallel, 0 for one per CPU core: whole frames
+    /// side by side for JPEG, a frame's changed tiles for tile delta
+    pub encode_workers: usize,
+    /// Send only changed 64x64 tiles between periodic full frames
+    pub tile_delta: bool,
+    /// Send the main display as zstd-compressed RGB, pixel-perfect but for fast LANs only
+    pub lossless: bool,
+    /// Encode the main display as WebP (needs the `webp` feature, JPEG otherwise)
+    pub webp: WebpMode,
+    /// Send the main display as PNG, for slides and dashboards where fidelity beats frame rate
+    pub png: bool,
+    pub av1: Av1Settings,
+    /// Local preview shown in the server UI, independent of the multicast quality
+    pub preview: PreviewSettings,
+    pub timestamp_source: TimestampSource,
+    /// Capture system audio and send it as Opus alongside the video
+    pub audio: bool,
+    /// Send the pointer apart from the frames for viewers to draw (see cursor_stream.rs);
+    /// recordings and the other outputs are then without it
+    pub cursor_metadata: bool,
+    /// Opt-in scheduling tweaks for the capture/encode pipeline
+    pub performance: PerformanceSettings,
+    /// Encrypt the stream with a key derived from this passphrase (None or empty sends in the clear)
+    pub encryption_passphrase: Option<String>,
+    /// Seal recordings with the stream key, so only those who know the passphrase can play them
+    pub encrypt_recordings: bool,
+    /// Encrypt with a fresh key every session, handed to viewers who know this PIN
+    pub pairing_pin: Option<String>,
+    /// Show the presenter's keystrokes in a corner of the stream
+    pub key_overlay: KeyOverlaySettings,
+    /// Locale for overlay text fonts, e.g. "ja" or "th" (None follows the system)
+    pub overlay_locale: Option<String>,
+    /// Shown before the first live frame, see title_card.rs
+    pub title_card: TitleCardSettings,
+    /// Unicast only to viewers the host approved after they entered the session PIN
+    pub require_approval: bool,
+    /// Send the multicast stream out of the NIC with this address (None lets the OS pick)
+    pub multicast_interface: Option<IpAddr>,
+    /// Multicast over IPv4 (239.0.0.1) or link-local IPv6 (ff02::ef00:1)
+    pub ip_version: IpVersion,
+    pub ports: PortMapping,
+    /// Tells this stream apart from other servers' on the same group, 0 picks one per session
+    pub stream_id: u32,
+    /// Also stream over TCP on the data port, for viewers whose network drops multicast
+    pub tcp_fallback: bool,
+    /// Accept QUIC viewers on the quic port (needs the `quic` feature)
+    pub quic: bool,
+    /// Lower renditions multicast next to the main stream, best first (multicast only)
+    pub simulcast: Vec<SimulcastLayer>,
+    /// Let browsers watch over WebRTC (H.264 encoders, needs the `webrtc` feature)
+    pub webrtc: WebRtcSettings,
+    /// Also multicast RTP/JPEG that VLC or ffplay can play from an SDP file
+    pub rtp: RtpSettings,
+    /// Serve the stream as MJPEG over HTTP, for browsers and signage players
+    pub mjpeg: MjpegSettings,
+    /// Pixel formats DXGI capture asks the driver for, best first (`dxgi` feature)
+    pub capture_formats: Vec<CaptureFormat>,
+    /// Displays sent next to the selected one, as streams 1, 2, ... (see display_streams.rs)
+    pub extra_displays: Vec<usize>,
+    /// Stop or pause the stream after a while, in case the presenter forgets to
+    pub session_limit: SessionLimit,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebRtcSettings {
+    pub enabled: bool,
+    /// Serves the viewer page and takes offers
+    pub port: u16,
+}
+
+impl Default for WebRtcSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: crate::webrtc_output::DEFAULT_PORT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RtpSettings {
+    pub enabled: bool,
+    /// Port on the stream's multicast group, even as RTP expects
+    pub port: u16,
+    /// JPEG quality when the encoder's output has to be encoded again for RTP
+    pub jpeg_quality: u8,
+}
+
+impl Default for RtpSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: crate::rtp_output::DEFAULT_PORT,
+            jpeg_quality: 70,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MjpegSettings {
+    pub enabled: bool,
+    /// Serves http://<host>:<port>/stream
+    pub port: u16,
+    /// JPEG quality when the encoder doesn't produce JPEG frames itself
+    pub jpeg_quality: u8,
+}
+
+impl Default for MjpegSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: crate::mjpeg_server::DEFAULT_PORT,
+            jpeg_quality: 70,
+        }
+    }
+}
+
+/// The main display's encoder, one switch over the per-encoder settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncoderMode {
+    /// Hardware H.264 when available, JPEG otherwise
+    Auto,
+    TileDelta,
+    Lossless,
+    Png,
+    Webp,
+    WebpLossless,
+    Av1,
+}
+
+/// AV1 frames, much smaller than JPEG or H.264 at the same quality but CPU heavy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Av1Settings {
+    /// Encode the main display as AV1 (needs the `av1` feature, JPEG otherwise)
+    pub enabled: bool,
+    /// rav1e speed preset, 0 (best) - 10 (fastest)
+    pub speed: u8,
+}
+
+impl Default for Av1Settings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            speed: crate::hw_encoder::DEFAULT_ENCODER_SPEED,
+        }
+    }
+}
+
+/// One extra rendition of the stream, for viewers whose link can't keep up
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulcastLayer {
+    pub max_width: u32,
+    pub jpeg_quality: u8,
+}
+
+impl Default for SimulcastLayer {
+    fn default() -> Self {
+        Self {
+            max_width: 960,
+            jpeg_quality: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyOverlaySettings {
+    pub enabled: bool,
+    /// Mask typed characters when focus looks like a password field
+    pub privacy_mode: bool,
+}
+
+impl Default for KeyOverlaySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            privacy_mode: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TitleCardSettings {
+    pub enabled: bool,
+    /// Stream name on the card (None uses the host name)
+    pub title: Option<String>,
+    pub presenter: Option<String>,
+    /// How long the card shows before live frames
+    pub duration_ms: u64,
+    /// Time zone of the start time on the card, minutes east of UTC
+    pub utc_offset_minutes: i32,
+}
+
+impl Default for TitleCardSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            title: None,
+            presenter: None,
+            duration_ms: 3000,
+            utc_offset_minutes: 0,
+        }
+    }
+}
+
+/// Separate ports per kind of traffic, so firewall and QoS rules can tell them apart.
+/// Servers announce theirs in discovery answers.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortMapping {
+    /// Stream packets, server -> viewers
+    pub data: u16,
+    /// Join requests, receiver reports, keyframe requests and remote input, viewers -> server
+    pub control: u16,
+    /// Probes and the answers describing a stream
+    pub discovery: u16,
+    /// QUIC connections from viewers, when the server offers QUIC
+    pub quic: u16,
+}
+
+impl Default for PortMapping {
+    fn default() -> Self {
+        Self {
+            data: DEFAULT_DATA_PORT,
+            control: DEFAULT_CONTROL_PORT,
+            discovery: DEFAULT_DISCOVERY_PORT,
+            quic: DEFAULT_QUIC_PORT,
+        }
+    }
+}
+
+impl PortMapping {
+    pub fn validate(&self) -> Result<(), String> {
+        let ports = [self.data, self.control, self.discovery, self.quic];
+        if ports.contains(&0) {
+            return Err("ports must not be 0".to_string());
+        }
+        if (1..ports.len()).any(|i| ports[i..].contains(&ports[i - 1])) {
+            return Err(format!(
+                "data ({}), control ({}), discovery ({}) and quic ({}) ports must differ",
+                self.data, self.control, self.discovery, self.quic
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Runs the pipeline on its own tuned thread when anything is enabled
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PerformanceSettings {
+    /// Raise the pipeline thread's scheduling priority
+    pub high_priority: bool,
+    /// Keep the pipeline thread on performance cores of hybrid CPUs
+    pub performance_cores: bool,
+}
+
+impl PerformanceSettings {
+    pub fn is_enabled(&self) -> bool {
+        self.high_priority || self.performance_cores
+    }
+}
+
+/// Low-rate copy of the outgoing frames for the presenter's own screen
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreviewSettings {
+    pub enabled: bool,
+    pub fps: u32,
+    pub quality: u8,
+    pub max_width: u32,
+}
+
+impl Default for PreviewSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            fps: DEFAULT_PREVIEW_FPS,
+            quality: DEFAULT_PREVIEW_QUALITY,
+            max_width: DEFAULT_PREVIEW_MAX_WIDTH,
+        }
+    }
+}
+
+impl Default for StreamSettings {
+    fn default() -> Self {
+        Self {
+            target_fps: DEFAULT_TARGET_FPS,
+            min_fps: DEFAULT_MIN_FPS,
+            max_fps: DEFAULT_MAX_FPS,
+            jpeg_quality: crate::hw_encoder::DEFAULT_JPEG_QUALITY,
+            max_width: DEFAULT_MAX_WIDTH,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            max_bitrate_mbps: 0.0,
+            send_buffer_kb: DEFAULT_SEND_BUFFER_KB,
+            encode_workers: DEFAULT_ENCODE_WORKERS,
+            tile_delta: false,
+            lossless: false,
+            webp: WebpMode::Off,
+            png: false,
+            av1: Av1Settings::default(),
+            preview: PreviewSettings::default(),
+            timestamp_source: TimestampSource::default(),
+            audio: false,
+            cursor_metadata: false,
+            performance: PerformanceSettings::default(),
+            encryption_passphrase: None,
+            encrypt_recordings: false,
+            pairing_pin: None,
+            key_overlay: KeyOverlaySettings::default(),
+            overlay_locale: None,
+            title_card: TitleCardSettings::default(),
+            require_approval: false,
+            multicast_interface: None,
+            ip_version: IpVersion::default(),
+            ports: PortMapping::default(),
+            stream_id: 0,
+            tcp_fallback: true,
+            quic: false,
+            simulcast: Vec::new(),
+            extra_displays: Vec::new(),
+            session_limit: SessionLimit::default(),
+            webrtc: WebRtcSettings::default(),
+            rtp: RtpSettings::default(),
+            mjpeg: MjpegSettings::default(),
+            capture_formats: vec![CaptureFormat::Bgra8],
+        }
+    }
+}
+
+impl StreamSettings {
+    pub fn encoder_mode(&self) -> EncoderMode {
+        match (self.tile_delta, self.lossless, self.png, self.webp, self.av1.enabled) {
+            (true, ..) => EncoderMode::TileDelta,
+            (_, true, ..) => EncoderMode::Lossless,
+            (_, _, true, ..) => EncoderMode::Png,
+            (_, _, _, WebpMode::Lossy, _) => EncoderMode::Webp,
+            (_, _, _, WebpMode::Lossless, _) => EncoderMode::WebpLossless,
+            (.., true) => EncoderMode::Av1,
+            _ => EncoderMode::Auto,
+        }
+    }
+
+    /// Turn on `mode`'s setting and every other encoder's off
+    pub fn set_encoder_mode(&mut self, mode: EncoderMode) {
+        self.tile_delta = mode == EncoderMode::TileDelta;
+        self.lossless = mode == EncoderMode::Lossless;
+        self.png = mode == EncoderMode::Png;
+        self.webp = match mode {
+            EncoderMode::Webp => WebpMode::Lossy,
+            EncoderMode::WebpLossless => WebpMode::Lossless,
+            _ => WebpMode::Off,
+        };
+        self.av1.enabled = mode == EncoderMode::Av1;
+    }
+
+    /// How many encode threads `encode_workers` comes to on this machine
+    pub fn encode_threads(&self) -> usize {
+        match self.encode_workers {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()).min(MAX_ENCODE_WORKERS),
+            workers => workers,
+        }
+    }
+
+    /// Reject settings the pacer can't work with
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_fps == 0 {
+            return Err("min_fps must be at least 1".to_string());
+        }
+        if self.min_fps > self.max_fps {
+            return Err(format!("min_fps ({}) is greater than max_fps ({})", self.min_fps, self.max_fps));
+        }
+        if self.target_fps < self.min_fps || self.target_fps > self.max_fps {
+            return Err(format!(
+                "target_fps ({}) must be within {}-{}",
+                self.target_fps, self.min_fps, self.max_fps
+            ));
+        }
+        if self.jpeg_quality < 10 || self.jpeg_quality > 100 {
+            return Err(format!("jpeg_quality ({}) must be within 10-100", self.jpeg_quality));
+        }
+        // Header and encryption overhead have to fit in one datagram too
+        if self.chunk_size < 512 || self.chunk_size > 60_000 {
+            return Err(format!("chunk_size ({}) must be within 512-60000", self.chunk_size));
+        }
+        if !self.max_bitrate_mbps.is_finite() || self.max_bitrate_mbps < 0.0 {
+            return Err(format!("max_bitrate_mbps ({}) must be 0 (unlimited) or more", self.max_bitrate_mbps));
+        }
+        if self.send_buffer_kb > MAX_SOCKET_BUFFER_KB {
+            return Err(format!("send_buffer_kb ({}) must be at most {}", self.send_buffer_kb, MAX_SOCKET_BUFFER_KB));
+        }
+        if self.encode_workers > MAX_ENCODE_WORKERS {
+            return Err(format!("encode_workers ({}) must be 0 (one per CPU core) or at most {}", self.encode_workers, MAX_ENCODE_WORKERS));
+        }
+        if [self.lossless, self.tile_delta, self.webp != WebpMode::Off, self.av1.enabled, self.png].iter().filter(|&&on| on).count() > 1 {
+            return Err("Only one of lossless, tile_delta, webp, av1 and png can be on".to_string());
+        }
+        if self.av1.speed > 10 {
+            return Err(format!("av1.speed ({}) must be within 0-10", self.av1.speed));
+        }
+        self.ports.validate()?;
+        if let Some(interface) = self.multicast_interface {
+            // IPv6 finds the NIC by any of its addresses, IPv4 sends from one
+            if self.ip_version == IpVersion::V4 && !interface.is_ipv4() {
+                return Err(format!("multicast_interface ({}) must be an IPv4 address for IPv4 streams", interface));
+            }
+        }
+        if let Some(pin) = self.pairing_pin.as_deref() {
+            validate_pin(pin)?;
+            if self.encryption_passphrase.as_deref().is_some_and(|p| !p.is_empty()) {
+                return Err("Set either an encryption passphrase or a pairing PIN, not both".to_string());
+            }
+        }
+        if self.max_width != 0 && self.max_width < MIN_MAX_WIDTH {
+            return Err(format!("max_width ({}) must be 0 (native) or at least {}", self.max_width, MIN_MAX_WIDTH));
+        }
+        if self.extra_displays.len() > crate::packet::MAX_STREAM as usize {
+            return Err(format!("At most {} extra displays", crate::packet::MAX_STREAM));
+        }
+        if self.simulcast.len() > crate::packet::MAX_LAYER as usize {
+            return Err(format!("At most {} simulcast layers", crate::packet::MAX_LAYER));
+        }
+        for (i, layer) in self.simulcast.iter().enumerate() {
+            if layer.max_width < 64 {
+                return Err(format!("simulcast[{}].max_width ({}) must be at least 64", i, layer.max_width));
+            }
+            if layer.jpeg_quality < 10 || layer.jpeg_quality > 100 {
+                return Err(format!("simulcast[{}].jpeg_quality ({}) must be within 10-100", i, layer.jpeg_quality));
+            }
+        }
+        if self.webrtc.enabled {
+            // Browsers can't enter a PIN or passphrase
+            let encrypted = self.pairing_pin.is_some() || self.encryption_passphrase.as_deref().is_some_and(|p| !p.is_empty());
+            if encrypted || self.require_approval {
+                return Err("WebRTC viewers can't be used with encryption or viewer approval".to_string());
+            }
+            if self.webrtc.port == 0 {
+                return Err("webrtc.port must not be 0".to_string());
+            }
+        }
+        if self.rtp.enabled {
+            let encrypted = self.pairing_pin.is_some() || self.encryption_passphrase.as_deref().is_some_and(|p| !p.is_empty());
+            if encrypted || self.require_approval {
+                return Err("RTP output can't be used with encryption or viewer approval".to_string());
+            }
+            if self.rtp.port == 0 || self.rtp.port % 2 != 0 {
+                return Err(format!("rtp.port ({}) must be an even port number", self.rtp.port));
+            }
+            if self.rtp.port == self.ports.data {
+                return Err(format!("rtp.port ({}) must differ from the data port", self.rtp.port));
+            }
+            if self.rtp.jpeg_quality < 10 || self.rtp.jpeg_quality > 100 {
+                return Err(format!("rtp.jpeg_quality ({}) must be within 10-100", self.rtp.jpeg_quality));
+            }
+        }
+        if self.mjpeg.enabled {
+            let encrypted = self.pairing_pin.is_some() || self.encryption_passphrase.as_deref().is_some_and(|p| !p.is_empty());
+            if encrypted || self.require_approval {
+                return Err("The MJPEG server can't be used with encryption or viewer approval".to_string());
+            }
+            if self.mjpeg.port == 0 {
+                return Err("mjpeg.port must not be 0".to_string());
+            }
+            if self.webrtc.enabled && self.webrtc.port == self.mjpeg.port {
+                return Err(format!("mjpeg.port ({}) is already used by WebRTC", self.mjpeg.port));
+            }
+            if self.mjpeg.jpeg_quality < 10 || self.mjpeg.jpeg_quality > 100 {
+                return Err(format!("mjpeg.jpeg_quality ({}) must be within 10-100", self.mjpeg.jpeg_quality));
+            }
+        }
+        if self.capture_formats.is_empty() {
+            return Err("capture_formats must list at least one format".to_string());
+        }
+        if let Some(format) = self.capture_formats.iter().find(|format| !format.is_requestable()) {
+            return Err(format!("{:?} can't be requested from DXGI capture, use bgra8, rgb10_a2 or rgba16_float", format));
+        }
+        if self.preview.enabled {
+            if self.preview.fps == 0 || self.preview.fps > 10 {
+                return Err(format!("preview.fps ({}) must be within 1-10", self.preview.fps));
+            }
+            if self.preview.quality == 0 || self.preview.quality > 100 {
+                return Err(format!("preview.quality ({}) must be within 1-100", self.preview.quality));
+            }
+            if self.preview.max_width < 64 {
+                return Err(format!("preview.max_width ({}) must be at least 64", self.preview.max_width));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pairing PINs are short on purpose, but still need enough digits to be worth guessing
+pub fn validate_pin(pin: &str) -> Result<(), String> {
+    if !(4..=10).contains(&pin.len()) || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err("PIN must be 4-10 digits".to_string());
+    }
+    Ok(())
+}
+
+/// Display preferences for the viewer side
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewerSettings {
+    /// Cross-fade between consecutive frames when the frame rate drops
+    pub smoothing: bool,
+    /// No new frame for this long marks the stream stale (0 disables)
+    pub stale_threshold_ms: u64,
+    /// Dim the canvas and show the last update time while stale
+    pub stale_overlay: bool,
+    /// Nothing at all received for this long means the stream is lost (0 disables)
+    pub stream_lost_ms: u64,
+    /// Wait this long for a late frame before showing the ones after it (0 only drops late frames)
+    pub jitter_buffer_ms: u64,
+    /// Most frames per second shown, the newest of those arriving faster wins (0 for no cap)
+    pub max_emit_fps: u32,
+    /// Ceiling for received data held in client buffers (0 disables)
+    pub memory_limit_mb: u64,
+    /// Kernel buffer of the receiving socket; too small drops chunks of big frames
+    /// before they're read. OSes may grant less (0 keeps the OS default)
+    pub receive_buffer_kb: usize,
+    /// Passphrase for encrypted streams, must match the server's
+    pub passphrase: Option<String>,
+    /// PIN for streams that hand out their key through pairing (takes precedence over the passphrase)
+    pub pin: Option<String>,
+    /// Seal recordings with the stream key, so only those who know the passphrase can play them
+    pub encrypt_recordings: bool,
+    /// Server to ask for approval, for servers that only stream to approved viewers
+    pub server_address: Option<String>,
+    /// Join the multicast group on the NIC with this address (None lets the OS pick)
+    pub multicast_interface: Option<IpAddr>,
+    /// Group the server sends to, None for the default group of `ip_version`
+    pub multicast_group: Option<IpAddr>,
+    /// Must match the server's
+    pub ip_version: IpVersion,
+    /// The data port must match the server's, the control port is learned from it
+    pub ports: PortMapping,
+    /// Switch to a server's TCP stream when no multicast arrives
+    pub tcp_fallback: bool,
+    /// Receive over QUIC from a server that offers it instead of multicast
+    pub quic: bool,
+    /// Record which chunk indices go missing, reported in the stats
+    pub chunk_diagnostics: bool,
+    /// Decode JPEG frames before showing them, to catch corrupt ones the marker checks miss
+    pub decode_validation: bool,
+    /// Average decode time per frame above which validation falls back to marker checks (0 never does)
+    pub validation_budget_ms: u64,
+    /// The server's other displays to show as well, by stream ID (see display_streams.rs)
+    pub displays: Vec<u8>,
+    /// Only reassemble this server stream (see packet.rs), None takes whatever arrives
+    pub stream_id: Option<u32>,
+}
+
+impl Default for ViewerSettings {
+    fn default() -> Self {
+        Self {
+            smoothing: false,
+            stale_threshold_ms: DEFAULT_STALE_THRESHOLD_MS,
+            stale_overlay: true,
+            stream_lost_ms: DEFAULT_STREAM_LOST_MS,
+            jitter_buffer_ms: 0,
+            max_emit_fps: DEFAULT_MAX_EMIT_FPS,
+            memory_limit_mb: DEFAULT_MEMORY_LIMIT_MB,
+            receive_buffer_kb: DEFAULT_RECEIVE_BUFFER_KB,
+            passphrase: None,
+            pin: None,
+            encrypt_recordings: false,
+            server_address: None,
+            multicast_interface: None,
+            multicast_group: None,
+            ip_version: IpVersion::default(),
+            ports: PortMapping::default(),
+            tcp_fallback: true,
+            quic: false,
+            chunk_diagnostics: false,
+            decode_validation: true,
+            validation_budget_ms: DEFAULT_VALIDATION_BUDGET_MS,
+            displays: Vec::new(),
+            stream_id: None,
+        }
+    }
+}
+
+impl ViewerSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.receive_buffer_kb > MAX_SOCKET_BUFFER_KB {
+            return Err(format!("receive_buffer_kb ({}) must be at most {}", self.receive_buffer_kb, MAX_SOCKET_BUFFER_KB));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+// Simulcast - lower renditions of the stream for viewers on weaker links
+// Next to the main stream the server can multicast a few extra layers, each a
+// downscaled JPEG of the same frame on its own group (the groups after the
+// main one, see IpVersion::layer_group). Layers are intra-only, so viewers can
+// switch onto one at any frame.
+//
+// Viewers pick their own layer from what they measure for their receiver
+// reports: step down one layer while loss stays high, back up after a quiet
+// spell. A switch joins the new group first and only leaves the old one once a
+// full frame of the new layer has arrived, so the picture never goes blank.
+// The main stream may be delta coded, so going back to it asks for a keyframe.
+
+use std::net::{IpAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use crate::access_control::Delivery;
+use crate::net_interfaces::{self, IpVersion};
+use crate::packet::{FrameType, PacketHeader};
+use crate::preview;
+use crate::screen_capture::RawFrame;
+use crate::settings::SimulcastLayer;
+
+const DOWN_LOSS_RATE: f32 = 0.05; // One bad report interval is enough to step down
+const UP_LOSS_RATE: f32 = 0.01;
+const UP_AFTER_SECS: u64 = 10; // Clean reception this long before trying a better layer
+const SWITCH_HOLD_SECS: u64 = 3; // Let a switch settle before judging the new layer
+
+/// Server side: encodes and sends one extra layer
+pub struct LayerEncoder {
+    layer: u8,
+    settings: SimulcastLayer,
+    delivery: Delivery,
+    /// Each layer numbers its own frames, like a stream of its own
+    frame_id: u32,
+    sequence: u32,
+}
+
+impl LayerEncoder {
+    pub fn new(layer: u8, settings: SimulcastLayer, ip_version: IpVersion, port: u16, interface_index: u32) -> Self {
+        let delivery = Delivery::Multicast {
+            group: ip_version.layer_addr(layer, port, interface_index),
+            tcp: None,
+            quic: None,
+        };
+        Self { layer, settings, delivery, frame_id: 0, sequence: 0 }
+    }
+
+    pub fn layer(&self) -> u8 {
+        self.layer
+    }
+
+    pub fn delivery(&self) -> &Delivery {
+        &self.delivery
+    }
+
+    pub fn encode(&self, frame: &RawFrame) -> Result<Vec<u8>, String> {
+        preview::scaled_jpeg(frame, self.settings.max_width, self.settings.jpeg_quality)
+    }
+
+    /// `main`'s timestamps with this layer's frame ID and sequence number
+    pub fn next_header(&mut self, main: PacketHeader) -> PacketHeader {
+        let header = PacketHeader {
+            frame_id: self.frame_id,
+            sequence: self.sequence,
+            frame_type: FrameType::Key,
+            layer: self.layer,
+            ..main
+        };
+        self.frame_id = self.frame_id.wrapping_add(1);
+        self.sequence = self.sequence.wrapping_add(1);
+        header
+    }
+}
+
+/// Viewer side: decides which layer reception can sustain
+pub struct LayerSelector {
+    /// Highest layer the server sends, the lowest quality
+    lowest: u8,
+    current: u8,
+    clean_since: Option<Instant>,
+    last_switch: Option<Instant>,
+}
+
+impl LayerSelector {
+    pub fn new(layers: usize) -> Self {
+        Self {
+            lowest: layers as u8,
+            current: 0,
+            clean_since: None,
+            last_switch: None,
+        }
+    }
+
+    /// Whether the server's offer still matches
+    pub fn layers(&self) -> usize {
+        self.lowest as usize
+    }
+
+    /// Feed the loss of one report interval, returns the layer to switch to
+    pub fn on_report(&mut self, loss_rate: f32) -> Option<u8> {
+        self.on_report_at(Instant::now(), loss_rate)
+    }
+
+    fn on_report_at(&mut self, now: Instant, loss_rate: f32) -> Option<u8> {
+        if loss_rate > UP_LOSS_RATE {
+            self.clean_since = None;
+        } else if self.clean_since.is_none() {
+            self.clean_since = Some(now);
+        }
+        if self.last_switch.is_some_and(|at| now.duration_since(at) < Duration::from_secs(SWITCH_HOLD_SECS)) {
+            return None;
+        }
+
+        let clean_long_enough = self.clean_since
+            .is_some_and(|since| now.duration_since(since) >= Duration::from_secs(UP_AFTER_SECS));
+        let next = if loss_rate >= DOWN_LOSS_RATE && self.current < self.lowest {
+            self.current + 1
+        } else if clean_long_enough && self.current > 0 {
+            self.current - 1
+        } else {
+            return None;
+        };
+        self.current = next;
+        self.last_switch = Some(now);
+        self.clean_since = None;
+        Some(next)
+    }
+}
+
+/// Viewer side: which layer groups the socket is in, switching without a gap
+pub struct LayerSubscription {
+    /// The main stream's group
+    group: IpAddr,
+    interface: Option<IpAddr>,
+    active: u8,
+    pending: Option<u8>,
+}
+
+impl LayerSubscription {
+    /// The client socket starts out in the main group
+    pub fn new(group: IpAddr, interface: Option<IpAddr>) -> Self {
+        Self { group, interface, active: 0, pending: None }
+    }
+
+    /// Layer whose frames are shown
+    pub fn active(&self) -> u8 {
+        self.active
+    }
+
+    /// Packets of any other layer are leftovers of an earlier subscription
+    pub fn wants(&self, layer: u8) -> bool {
+        layer == self.active || self.pending == Some(layer)
+    }
+
+    /// Start receiving `layer`, shown from its first full frame on
+    pub fn switch_to(&mut self, socket: &UdpSocket, layer: u8) -> Result<(), String> {
+        if let Some(pending) = self.pending.take() {
+            self.membership(socket, pending, false)?;
+        }
+        if layer != self.active {
+            self.membership(socket, layer, true)?;
+            self.pending = Some(layer);
+        }
+        Ok(())
+    }
+
+    /// A full frame of `layer` arrived, true if that completes a switch
+    pub fn on_full_frame(&mut self, socket: &UdpSocket, layer: u8) -> bool {
+        if self.pending != Some(layer) {
+            return false;
+        }
+        if let Err(e) = self.membership(socket, self.active, false) {
+            eprintln!("⚠️  {}", e);
+        }
+        self.active = layer;
+        self.pending = None;
+        true
+    }
+
+    /// Leave and join the group shown again, for a membership the network may have forgotten
+    pub fn rejoin(&self, socket: &UdpSocket) -> Result<(), String> {
+        // Not being a member any more is what this is for
+        let _ = self.membership(socket, self.active, false);
+        self.membership(socket, self.active, true)
+    }
+
+    fn membership(&self, socket: &UdpSocket, layer: u8, join: bool) -> Result<(), String> {
+        let result = match net_interfaces::layer_group_of(self.group, layer) {
+            IpAddr::V4(group) => {
+                let interface = net_interfaces::multicast_interface(self.interface)?;
+                if join { socket.join_multicast_v4(&group, &interface) } else { socket.leave_multicast_v4(&group, &interface) }
+            }
+            IpAddr::V6(group) => {
+                let interface = net_interfaces::multicast_interface_index(self.interface)?;
+                if join { socket.join_multicast_v6(&group, interface) } else { socket.leave_multicast_v6(&group, interface) }
+            }
+        };
+        let action = if join { "join" } else { "leave" };
+        result.map_err(|e| format!("Failed to {} simulcast layer {}: {}", action, layer, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selector_steps_down_and_back_up() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut selector = LayerSelector::new(2);
+
+        assert_eq!(selector.on_report_at(at(0), 0.0), None);
+        assert_eq!(selector.on_report_at(at(1), 0.2), Some(1));
+        // Still lossy, but the switch gets time to settle
+        assert_eq!(selector.on_report_at(at(2), 0.2), None);
+        assert_eq!(selector.on_report_at(at(4), 0.2), Some(2));
+        // Nothing lower than the lowest layer
+        assert_eq!(selector.on_report_at(at(8), 0.2), None);
+
+        for second in 9..19 {
+            assert_eq!(selector.on_report_at(at(second), 0.0), None);
+        }
+        assert_eq!(selector.on_report_at(at(19), 0.0), Some(1));
+        // A blip restarts the clean period
+        assert_eq!(selector.on_report_at(at(25), 0.02), None);
+        assert_eq!(selector.on_report_at(at(30), 0.0), None);
+        assert_eq!(selector.on_report_at(at(40), 0.0), Some(0));
+    }
+
+    #[test]
+    fn test_layer_headers_count_on_their_own() {
+        let main = PacketHeader {
+            frame_id: 500,
+            chunk_idx: 0,
+            total_chunks: 0,
+            frame_type: FrameType::Delta,
+            sequence: 900,
+            capture_ts: 1234,
+            send_ts: 0,
+            epoch: 7,
+            encrypted: false,
+            layer: 0,
+            stream: 0,
+            stream_id: 1,
+        };
+        let mut encoder = LayerEncoder::new(1, SimulcastLayer::default(), IpVersion::V4, 9999, 0);
+        let first = encoder.next_header(main);
+        let second = encoder.next_header(main);
+        assert_eq!((first.frame_id, first.sequence, first.layer), (0, 0, 1));
+        assert_eq!((second.frame_id, second.sequence), (1, 1));
+        assert_eq!((second.capture_ts, second.epoch, second.frame_type), (1234, 7, FrameType::Key));
+    }
+}
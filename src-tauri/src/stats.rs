@@ -0,0 +1,403 @@
+// Stream Statistics - the one schema the UI, the REST API and the stats WebSocket share
+// Every snapshot carries SCHEMA_VERSION. The schema only ever grows:
+//   - new fields are added with a sensible zero/empty default, and the version bumps
+//   - existing fields keep their name, type and meaning; nothing is removed
+//   - consumers ignore fields they don't know, and treat missing ones as their default
+// A change that can't follow these rules needs a new field instead.
+//
+// Each side reports its current counters plus aggregates over the trailing
+// 1s, 10s and 60s, built from samples taken about once a second.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::chunk_heatmap::ChunkHeatmap;
+use crate::frame_validation::DecodeStats;
+use crate::tcp_transport::Transport;
+
+// 2: client `transport` can be "quic"
+// 3: client `layer`
+// 4: client `chunk_heatmap`
+// 5: client `decode`
+// 6: `bytes_sent`, `bytes_received`, server `avg_frame_time_ms`, window `kbps`
+pub const SCHEMA_VERSION: u32 = 6;
+const SAMPLE_INTERVAL_MS: u64 = 1000;
+const WINDOWS_SECS: [u64; 3] = [1, 10, 60];
+
+/// Snapshot of the running stream, refreshed by the streaming task
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ServerStats {
+    pub frames_sent: u64,
+    pub actual_fps: f32,
+    pub target_fps: u32,
+    pub last_frame_time_ms: u64,
+    /// Capture, encode and send time per frame, smoothed
+    pub avg_frame_time_ms: f32,
+    /// Main stream payload bytes, before chunking and encryption
+    pub bytes_sent: u64,
+    /// Receivers that sent a report recently
+    pub reporting_receivers: usize,
+    /// Worst chunk loss rate among reporting receivers (0.0 - 1.0)
+    pub worst_loss_rate: f32,
+    /// Worst inter-arrival jitter among reporting receivers
+    pub worst_jitter_ms: f32,
+    /// Frames of any display dropped to stay under the bandwidth limit
+    pub bandwidth_drops: u64,
+}
+
+/// Snapshot of the receive loop, refreshed as frames arrive
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClientStats {
+    pub frames_received: u64,
+    /// Every datagram, whatever it carried
+    pub bytes_received: u64,
+    pub invalid_frames: u64,
+    pub incomplete_frames: usize,
+    /// Sudden all-black frames dropped in favour of the last good frame
+    pub black_frames: u64,
+    /// Delta frames dropped because a frame they build on was lost
+    pub out_of_sync_frames: u64,
+    pub audio_packets: u64,
+    /// Bytes held in the reassembly buffer at the last measurement
+    pub buffered_bytes: usize,
+    /// Incomplete frames evicted to stay under the memory limit
+    pub memory_evictions: u64,
+    /// Encrypted packets that could not be opened (no or wrong passphrase)
+    pub decrypt_failures: u64,
+    /// Chunks dropped because their checksum didn't match
+    pub corrupt_chunks: u64,
+    /// Frames that completed after a later one was shown
+    pub late_frames: u64,
+    /// Frames held back while the webview was still drawing earlier ones
+    pub render_drops: u64,
+    /// Average time from sending a frame to the webview to it being drawn
+    pub render_ms: f32,
+    /// Unix time of the server's latest heartbeat, sent every second even while no frames are
+    pub last_heartbeat_ms: Option<u64>,
+    /// Multicast, or straight from the server over TCP (fallback) or QUIC
+    pub transport: Transport,
+    /// Simulcast layer being shown, 0 is the main stream
+    pub layer: u8,
+    /// Which chunks go missing, while the viewer's chunk diagnostics are on
+    pub chunk_heatmap: Option<ChunkHeatmap>,
+    /// Decode timings and how thoroughly frames are validated
+    pub decode: DecodeStats,
+}
+
+/// Server activity over a trailing window
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ServerWindow {
+    /// Time actually covered, shorter than the window right after start
+    pub seconds: f32,
+    pub frames_sent: u64,
+    pub fps: f32,
+    pub kbps: f32,
+    pub avg_loss_rate: f32,
+    pub max_loss_rate: f32,
+    pub avg_jitter_ms: f32,
+    pub max_jitter_ms: f32,
+    pub max_receivers: usize,
+}
+
+/// Viewer activity over a trailing window
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ClientWindow {
+    /// Time actually covered, shorter than the window right after start
+    pub seconds: f32,
+    pub frames_received: u64,
+    pub fps: f32,
+    pub kbps: f32,
+    pub invalid_frames: u64,
+    pub black_frames: u64,
+    pub out_of_sync_frames: u64,
+    pub audio_packets: u64,
+    pub memory_evictions: u64,
+    pub decrypt_failures: u64,
+    pub corrupt_chunks: u64,
+    pub late_frames: u64,
+    pub max_buffered_bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Windows<W> {
+    #[serde(rename = "1s")]
+    pub one_second: W,
+    #[serde(rename = "10s")]
+    pub ten_seconds: W,
+    #[serde(rename = "60s")]
+    pub one_minute: W,
+}
+
+/// Current counters plus their windowed aggregates
+#[derive(Debug, Clone, Serialize)]
+pub struct Report<T, W> {
+    pub current: T,
+    pub windows: Windows<W>,
+}
+
+pub type ServerReport = Report<ServerStats, ServerWindow>;
+pub type ClientReport = Report<ClientStats, ClientWindow>;
+
+/// What `/api/stats`, `/api/stats/ws` and `get_stats` return
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsSnapshot {
+    pub version: u32,
+    pub server: Option<ServerReport>,
+    pub client: Option<ClientReport>,
+}
+
+impl StatsSnapshot {
+    pub fn new(server: Option<ServerReport>, client: Option<ClientReport>) -> Self {
+        Self { version: SCHEMA_VERSION, server, client }
+    }
+}
+
+/// What `get_stream_stats` returns: the handful of numbers a status bar shows,
+/// from the server when this machine streams, otherwise from the viewer
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StreamStats {
+    pub version: u32,
+    pub server_running: bool,
+    pub client_running: bool,
+    /// Over the last 10 seconds
+    pub fps: f32,
+    /// What the server's pacer aims for, 0 on a viewer
+    pub target_fps: u32,
+    /// Over the last 10 seconds
+    pub bitrate_kbps: f32,
+    /// Frames the viewer couldn't show: invalid, out of sync or evicted
+    pub dropped_frames: u64,
+    /// Viewers the server hears from, or the server a viewer receives from
+    pub connected_peers: usize,
+}
+
+impl StreamStats {
+    /// `approved_viewers` are the viewers let in on a server that requires approval
+    pub fn new(snapshot: &StatsSnapshot, approved_viewers: usize) -> Self {
+        let mut stats = Self {
+            version: SCHEMA_VERSION,
+            server_running: snapshot.server.is_some(),
+            client_running: snapshot.client.is_some(),
+            ..Self::default()
+        };
+        if let Some(client) = snapshot.client.as_ref() {
+            let window = &client.windows.ten_seconds;
+            stats.fps = window.fps;
+            stats.bitrate_kbps = window.kbps;
+            stats.dropped_frames = client.current.invalid_frames + client.current.out_of_sync_frames + client.current.memory_evictions + client.current.late_frames;
+            stats.connected_peers = usize::from(client.windows.one_second.frames_received > 0);
+        }
+        if let Some(server) = snapshot.server.as_ref() {
+            let window = &server.windows.ten_seconds;
+            stats.fps = window.fps;
+            stats.target_fps = server.current.target_fps;
+            stats.bitrate_kbps = window.kbps;
+            stats.connected_peers = server.current.reporting_receivers.max(approved_viewers);
+        }
+        stats
+    }
+}
+
+/// Stats types that can be summarized over a run of samples
+pub trait Aggregate: Clone {
+    type Window;
+
+    /// `samples` oldest first, the first one being the baseline counters are measured from
+    fn aggregate(samples: &[&Self], seconds: f32) -> Self::Window;
+}
+
+impl Aggregate for ServerStats {
+    type Window = ServerWindow;
+
+    fn aggregate(samples: &[&Self], seconds: f32) -> ServerWindow {
+        let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+            return ServerWindow::default();
+        };
+        let frames_sent = last.frames_sent.saturating_sub(first.frames_sent);
+        ServerWindow {
+            seconds,
+            frames_sent,
+            fps: rate(frames_sent, seconds),
+            kbps: rate(last.bytes_sent.saturating_sub(first.bytes_sent) * 8, seconds) / 1000.0,
+            avg_loss_rate: average(samples.iter().map(|s| s.worst_loss_rate)),
+            max_loss_rate: samples.iter().map(|s| s.worst_loss_rate).fold(0.0, f32::max),
+            avg_jitter_ms: average(samples.iter().map(|s| s.worst_jitter_ms)),
+            max_jitter_ms: samples.iter().map(|s| s.worst_jitter_ms).fold(0.0, f32::max),
+            max_receivers: samples.iter().map(|s| s.reporting_receivers).max().unwrap_or(0),
+        }
+    }
+}
+
+impl Aggregate for ClientStats {
+    type Window = ClientWindow;
+
+    fn aggregate(samples: &[&Self], seconds: f32) -> ClientWindow {
+        let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+            return ClientWindow::default();
+        };
+        let frames_received = last.frames_received.saturating_sub(first.frames_received);
+        ClientWindow {
+            seconds,
+            frames_received,
+            fps: rate(frames_received, seconds),
+            kbps: rate(last.bytes_received.saturating_sub(first.bytes_received) * 8, seconds) / 1000.0,
+            invalid_frames: last.invalid_frames.saturating_sub(first.invalid_frames),
+            black_frames: last.black_frames.saturating_sub(first.black_frames),
+            out_of_sync_frames: last.out_of_sync_frames.saturating_sub(first.out_of_sync_frames),
+            audio_packets: last.audio_packets.saturating_sub(first.audio_packets),
+            memory_evictions: last.memory_evictions.saturating_sub(first.memory_evictions),
+            decrypt_failures: last.decrypt_failures.saturating_sub(first.decrypt_failures),
+            corrupt_chunks: last.corrupt_chunks.saturating_sub(first.corrupt_chunks),
+            late_frames: last.late_frames.saturating_sub(first.late_frames),
+            max_buffered_bytes: samples.iter().map(|s| s.buffered_bytes).max().unwrap_or(0),
+        }
+    }
+}
+
+fn rate(count: u64, seconds: f32) -> f32 {
+    if seconds > 0.0 { count as f32 / seconds } else { 0.0 }
+}
+
+fn average(values: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    if count > 0 { sum / count as f32 } else { 0.0 }
+}
+
+/// About a minute of once-a-second samples
+pub struct StatsHistory<T> {
+    samples: VecDeque<(Instant, T)>,
+}
+
+impl<T: Aggregate> StatsHistory<T> {
+    pub fn new() -> Self {
+        Self { samples: VecDeque::new() }
+    }
+
+    /// Keep `stats` if the last sample is a second old, call as often as convenient
+    pub fn record(&mut self, stats: &T) {
+        self.record_at(Instant::now(), stats);
+    }
+
+    fn record_at(&mut self, now: Instant, stats: &T) {
+        let due = self.samples.back()
+            .is_none_or(|(at, _)| now.duration_since(*at) >= Duration::from_millis(SAMPLE_INTERVAL_MS));
+        if !due {
+            return;
+        }
+        self.samples.push_back((now, stats.clone()));
+        // One sample older than the longest window stays as its baseline
+        let oldest = Duration::from_secs(WINDOWS_SECS[WINDOWS_SECS.len() - 1]);
+        while self.samples.len() > 1 && now.duration_since(self.samples[1].0) >= oldest {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn report(&self, current: T) -> Report<T, T::Window> {
+        self.report_at(Instant::now(), current)
+    }
+
+    fn report_at(&self, now: Instant, current: T) -> Report<T, T::Window> {
+        let [one_second, ten_seconds, one_minute] = WINDOWS_SECS.map(|secs| self.window(now, &current, secs));
+        Report { current, windows: Windows { one_second, ten_seconds, one_minute } }
+    }
+
+    // From the newest sample at least `secs` old (or the oldest there is) up to now
+    fn window(&self, now: Instant, current: &T, secs: u64) -> T::Window {
+        let span = Duration::from_secs(secs);
+        let start = self.samples.iter()
+            .rposition(|(at, _)| now.duration_since(*at) >= span)
+            .unwrap_or(0);
+        let seconds = self.samples.get(start).map_or(0.0, |(at, _)| now.duration_since(*at).as_secs_f32());
+        let samples: Vec<&T> = self.samples.iter().skip(start)
+            .map(|(_, stats)| stats)
+            .chain(std::iter::once(current))
+            .collect();
+        T::aggregate(&samples, seconds)
+    }
+}
+
+impl<T: Aggregate> Default for StatsHistory<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent(frames_sent: u64, worst_loss_rate: f32) -> ServerStats {
+        ServerStats { frames_sent, worst_loss_rate, bytes_sent: frames_sent * 1000, ..ServerStats::default() }
+    }
+
+    #[test]
+    fn test_windows_cover_their_span() {
+        let start = Instant::now();
+        let mut history = StatsHistory::new();
+        // 30 FPS for 90 seconds, loss only in the last 5
+        for second in 0..90u64 {
+            let loss = if second >= 85 { 0.1 } else { 0.0 };
+            history.record_at(start + Duration::from_secs(second), &sent(second * 30, loss));
+        }
+        let now = start + Duration::from_secs(90);
+        let report = history.report_at(now, sent(90 * 30, 0.1));
+
+        let windows = &report.windows;
+        assert_eq!(windows.one_second.frames_sent, 30);
+        assert_eq!(windows.ten_seconds.frames_sent, 300);
+        assert_eq!(windows.one_minute.frames_sent, 1800);
+        assert!((windows.one_minute.fps - 30.0).abs() < 0.01);
+        assert!((windows.one_minute.kbps - 240.0).abs() < 0.1);
+        assert_eq!(windows.one_second.max_loss_rate, 0.1);
+        assert_eq!(windows.one_minute.max_loss_rate, 0.1);
+        assert!(windows.one_minute.avg_loss_rate < windows.ten_seconds.avg_loss_rate);
+
+        // Samples past the longest window are dropped
+        assert!(history.samples.len() <= 61);
+    }
+
+    #[test]
+    fn test_short_history_and_sample_rate() {
+        let start = Instant::now();
+        let mut history = StatsHistory::new();
+        history.record_at(start, &sent(0, 0.0));
+        // Too soon for another sample
+        history.record_at(start + Duration::from_millis(300), &sent(9, 0.0));
+        assert_eq!(history.samples.len(), 1);
+
+        let report = history.report_at(start + Duration::from_secs(2), sent(60, 0.0));
+        assert_eq!(report.windows.one_minute.seconds, 2.0);
+        assert_eq!(report.windows.one_minute.frames_sent, 60);
+        assert_eq!(report.windows.one_minute, report.windows.ten_seconds);
+    }
+
+    #[test]
+    fn test_stream_stats_prefer_the_server() {
+        let start = Instant::now();
+        let mut history = StatsHistory::new();
+        history.record_at(start, &sent(0, 0.0));
+        let server = ServerStats { target_fps: 30, reporting_receivers: 2, ..sent(300, 0.0) };
+        let client = ClientStats { invalid_frames: 2, out_of_sync_frames: 1, ..ClientStats::default() };
+        let snapshot = StatsSnapshot::new(
+            Some(history.report_at(start + Duration::from_secs(10), server)),
+            Some(StatsHistory::new().report(client)),
+        );
+
+        let stats = StreamStats::new(&snapshot, 3);
+        assert!(stats.server_running && stats.client_running);
+        assert_eq!((stats.fps, stats.target_fps, stats.bitrate_kbps), (30.0, 30, 240.0));
+        assert_eq!((stats.dropped_frames, stats.connected_peers), (3, 3));
+    }
+
+    #[test]
+    fn test_snapshot_schema() {
+        let snapshot = StatsSnapshot::new(Some(StatsHistory::new().report(ServerStats::default())), None);
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["version"], SCHEMA_VERSION);
+        assert!(json["client"].is_null());
+        assert_eq!(json["server"]["current"]["frames_sent"], 0);
+        for window in ["1s", "10s", "60s"] {
+            assert_eq!(json["server"]["windows"][window]["frames_sent"], 0);
+        }
+    }
+}
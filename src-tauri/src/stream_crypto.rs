@@ -0,0 +1,100 @@
+// Stream Encryption - AES-256-GCM over chunk payloads
+// Anyone on the LAN can join the multicast group, so with a passphrase set
+// every payload is sealed and only viewers with the same passphrase can
+// watch. The packet header stays readable (receivers need it to reassemble)
+// but is authenticated as associated data, so it can't be tampered with.
+//
+// Sealed payload: nonce (12 bytes, random per packet) | ciphertext | tag (16 bytes)
+// Random nonces never repeat in practice, which keeps resends and restarted
+// sessions safe without coordinating counters between sender threads.
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use sha2::Sha256;
+
+pub const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+pub const OVERHEAD: usize = NONCE_SIZE + TAG_SIZE;
+const KDF_SALT: &[u8] = b"SmartLab ScreenShare stream key v1";
+const KDF_ROUNDS: u32 = 100_000;
+
+pub struct StreamCipher {
+    cipher: Aes256Gcm,
+}
+
+impl StreamCipher {
+    /// Derive the stream key from a user-entered passphrase (PBKDF2-HMAC-SHA256)
+    pub fn from_passphrase(passphrase: &str) -> Result<Self, String> {
+        if passphrase.is_empty() {
+            return Err("Passphrase must not be empty".to_string());
+        }
+        Ok(Self::from_key(&derive_key(passphrase, KDF_SALT)))
+    }
+
+    /// Use a raw key, e.g. one handed out by the key exchange
+    pub fn from_key(key: &[u8; KEY_SIZE]) -> Self {
+        Self { cipher: Aes256Gcm::new(key.into()) }
+    }
+
+    /// Fresh random key for a new session
+    pub fn generate_key() -> [u8; KEY_SIZE] {
+        rand::random()
+    }
+
+    /// Encrypt `payload`, binding it to `header`
+    pub fn seal(&self, header: &[u8], payload: &[u8]) -> Result<Vec<u8>, String> {
+        let nonce: [u8; NONCE_SIZE] = rand::random();
+        let sealed = self.cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: payload, aad: header })
+            .map_err(|_| "Encryption failed".to_string())?;
+
+        let mut out = Vec::with_capacity(NONCE_SIZE + sealed.len());
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&sealed);
+        Ok(out)
+    }
+
+    /// Decrypt a sealed payload. Fails on a wrong passphrase or any tampering.
+    pub fn open(&self, header: &[u8], sealed: &[u8]) -> Result<Vec<u8>, String> {
+        if sealed.len() < OVERHEAD {
+            return Err("Encrypted payload too short".to_string());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+            .map_err(|_| "Decryption failed (wrong passphrase?)".to_string())
+    }
+}
+
+/// PBKDF2-HMAC-SHA256 key for `passphrase`, the stream's KDF with a caller's salt
+pub fn derive_key(passphrase: &str, salt: &[u8]) -> [u8; KEY_SIZE] {
+    let mut key = [0u8; KEY_SIZE];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, KDF_ROUNDS, &mut key);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_round_trip() {
+        let cipher = StreamCipher::from_passphrase("lop-hoc-42").unwrap();
+        let sealed = cipher.seal(b"header", b"frame data").unwrap();
+        assert_eq!(sealed.len(), b"frame data".len() + OVERHEAD);
+        assert_eq!(cipher.open(b"header", &sealed).unwrap(), b"frame data");
+    }
+
+    #[test]
+    fn test_open_rejects_wrong_key_and_tampering() {
+        let cipher = StreamCipher::from_passphrase("lop-hoc-42").unwrap();
+        let sealed = cipher.seal(b"header", b"frame data").unwrap();
+
+        let other = StreamCipher::from_passphrase("doan-sai").unwrap();
+        assert!(other.open(b"header", &sealed).is_err());
+        assert!(cipher.open(b"HEADER", &sealed).is_err());
+        assert!(cipher.open(b"header", &sealed[..OVERHEAD - 1]).is_err());
+        assert!(StreamCipher::from_passphrase("").is_err());
+    }
+}
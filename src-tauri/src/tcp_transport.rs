@@ -0,0 +1,252 @@
+// TCP Transport - fallback for networks that drop multicast
+// Some switches and Wi-Fi access points filter multicast, so viewers behind
+// them see nothing. The server also accepts TCP connections on the data port
+// and writes every stream packet to them, each prefixed with its length:
+//
+//   u32 length (big endian) | packet
+//
+// Packets are the same datagrams the multicast group gets (header, chunking,
+// encryption), so viewers reassemble them with the same code. A viewer only
+// connects after hearing no multicast traffic for FALLBACK_AFTER_MS, and goes
+// back to multicast when the connection drops.
+//
+// Approval-only servers don't offer it: they already unicast to every viewer.
+
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::Serialize;
+use crate::discovery;
+use crate::net_interfaces::IpVersion;
+use crate::settings::PortMapping;
+
+pub const FALLBACK_AFTER_MS: u64 = 3000;
+const LENGTH_SIZE: usize = 4;
+const MAX_PACKET_SIZE: usize = 65535;
+const QUEUE_PACKETS: usize = 1024; // A viewer further behind loses packets, like it would over UDP
+const ACCEPT_POLL_MS: u64 = 200;
+const CONNECT_TIMEOUT_MS: u64 = 2000;
+const DISCOVER_TIMEOUT_MS: u64 = 500;
+
+/// How the viewer currently receives the stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    #[default]
+    Multicast,
+    Tcp,
+    Quic,
+}
+
+/// Server side: copies stream packets to every connected TCP viewer
+pub struct TcpFanout {
+    viewers: Arc<Mutex<Vec<SyncSender<Arc<Vec<u8>>>>>>,
+    is_running: Arc<AtomicBool>,
+    #[cfg_attr(not(test), allow(dead_code))]
+    port: u16,
+}
+
+impl TcpFanout {
+    pub fn start(ip_version: IpVersion, port: u16) -> Result<Self, String> {
+        let listener = TcpListener::bind((ip_version.unspecified(), port))
+            .map_err(|e| format!("Failed to listen on TCP port {}: {}", port, e))?;
+        // Polled, so stop() is noticed without another connection coming in
+        listener.set_nonblocking(true)
+            .map_err(|e| format!("Failed to set non-blocking: {}", e))?;
+        let port = listener.local_addr().map_or(port, |addr| addr.port());
+
+        let viewers = Arc::new(Mutex::new(Vec::new()));
+        let is_running = Arc::new(AtomicBool::new(true));
+        let accepted = viewers.clone();
+        let running = is_running.clone();
+        std::thread::spawn(move || {
+            while running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, from)) => match Self::add_viewer(stream, from) {
+                        Ok(queue) => accepted.lock().unwrap().push(queue),
+                        Err(e) => eprintln!("❌ TCP viewer {}: {}", from, e),
+                    },
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(ACCEPT_POLL_MS));
+                    }
+                    Err(e) => eprintln!("❌ TCP accept failed: {}", e),
+                }
+            }
+        });
+
+        eprintln!("🔌 TCP fallback listening on port {}", port);
+        Ok(Self { viewers, is_running, port })
+    }
+
+    // Each viewer gets its own writer, so one slow connection can't hold up the stream
+    fn add_viewer(stream: TcpStream, from: SocketAddr) -> io::Result<SyncSender<Arc<Vec<u8>>>> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        let (tx, rx) = mpsc::sync_channel::<Arc<Vec<u8>>>(QUEUE_PACKETS);
+        std::thread::spawn(move || {
+            eprintln!("🔌 TCP viewer connected: {}", from);
+            let mut stream = stream;
+            for framed in rx {
+                if stream.write_all(&framed).is_err() {
+                    break;
+                }
+            }
+            eprintln!("🔌 TCP viewer left: {}", from);
+        });
+        Ok(tx)
+    }
+
+    /// Queue `packet` for every viewer, returns how many are connected
+    pub fn send(&self, packet: &[u8]) -> usize {
+        let mut viewers = self.viewers.lock().unwrap();
+        if viewers.is_empty() {
+            return 0;
+        }
+        let framed = Arc::new(frame(packet));
+        viewers.retain(|viewer| !matches!(viewer.try_send(framed.clone()), Err(TrySendError::Disconnected(_))));
+        viewers.len()
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Stop accepting and close every viewer connection
+    pub fn stop(&self) {
+        self.is_running.store(false, Ordering::Relaxed);
+        // Writers finish once their queue is gone, closing the connection
+        self.viewers.lock().unwrap().clear();
+    }
+}
+
+impl Drop for TcpFanout {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+pub fn frame(packet: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(LENGTH_SIZE + packet.len());
+    framed.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+    framed.extend_from_slice(packet);
+    framed
+}
+
+/// Move the first complete packet in `pending` into `buf`, returning its length
+fn take_packet(pending: &mut Vec<u8>, buf: &mut [u8]) -> io::Result<Option<usize>> {
+    if pending.len() < LENGTH_SIZE {
+        return Ok(None);
+    }
+    let len = u32::from_be_bytes([pending[0], pending[1], pending[2], pending[3]]) as usize;
+    if len > MAX_PACKET_SIZE || len > buf.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Packet of {} bytes is too large", len)));
+    }
+    if pending.len() < LENGTH_SIZE + len {
+        return Ok(None);
+    }
+    buf[..len].copy_from_slice(&pending[LENGTH_SIZE..LENGTH_SIZE + len]);
+    pending.drain(..LENGTH_SIZE + len);
+    Ok(Some(len))
+}
+
+/// Viewer side: reads stream packets off a connection to the server
+pub struct TcpReceiver {
+    stream: TcpStream,
+    server: SocketAddr,
+    pending: Vec<u8>,
+}
+
+impl TcpReceiver {
+    /// Reads give up after `read_timeout`, like the multicast socket's
+    pub fn connect(server: SocketAddr, read_timeout: Duration) -> Result<Self, String> {
+        let stream = TcpStream::connect_timeout(&server, Duration::from_millis(CONNECT_TIMEOUT_MS))
+            .map_err(|e| format!("Failed to connect to {}: {}", server, e))?;
+        stream.set_read_timeout(Some(read_timeout))
+            .map_err(|e| format!("Failed to set timeout: {}", e))?;
+        Ok(Self { stream, server, pending: Vec::new() })
+    }
+
+    /// Find a server on the LAN that offers the fallback and connect to it
+    pub fn discover(ports: PortMapping, read_timeout: Duration) -> Result<Self, String> {
+        let servers = discovery::discover(Duration::from_millis(DISCOVER_TIMEOUT_MS), ports.discovery)?;
+        let server = servers.iter()
+            .find(|server| server.tcp_fallback)
+            .ok_or("No server offering TCP found")?;
+        let address: IpAddr = server.address.parse()
+            .map_err(|_| format!("Invalid server address {:?}", server.address))?;
+        Self::connect(SocketAddr::new(address, server.ports.data), read_timeout)
+    }
+
+    /// Next packet into `buf`, a drop-in for `UdpSocket::recv_from`
+    pub fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let mut chunk = [0u8; 16 * 1024];
+        loop {
+            if let Some(len) = take_packet(&mut self.pending, buf)? {
+                return Ok((len, self.server));
+            }
+            // Timeouts surface as WouldBlock/TimedOut, partial packets stay pending
+            let read = self.stream.read(&mut chunk)?;
+            if read == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Server closed the connection"));
+            }
+            self.pending.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packets_split_across_reads() {
+        let mut wire = frame(b"first");
+        wire.extend(frame(&[7u8; 300]));
+        let mut buf = [0u8; 512];
+
+        // Arrives a few bytes at a time
+        let mut pending = Vec::new();
+        let mut packets = Vec::new();
+        for piece in wire.chunks(3) {
+            pending.extend_from_slice(piece);
+            while let Some(len) = take_packet(&mut pending, &mut buf).unwrap() {
+                packets.push(buf[..len].to_vec());
+            }
+        }
+        assert_eq!(packets, vec![b"first".to_vec(), vec![7u8; 300]]);
+        assert!(pending.is_empty());
+
+        let mut bogus = (MAX_PACKET_SIZE as u32 + 1).to_be_bytes().to_vec();
+        assert!(take_packet(&mut bogus, &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_fanout_reaches_connected_viewer() {
+        let fanout = TcpFanout::start(IpVersion::V4, 0).unwrap();
+        let server = SocketAddr::from(([127, 0, 0, 1], fanout.port()));
+        let mut receiver = TcpReceiver::connect(server, Duration::from_millis(200)).unwrap();
+
+        // Accepting is polled, keep sending until the viewer is registered
+        let mut buf = [0u8; 64];
+        let received = (0..20).find_map(|_| {
+            fanout.send(b"packet");
+            receiver.recv_from(&mut buf).ok()
+        });
+        assert_eq!(received.map(|(len, _)| buf[..len].to_vec()), Some(b"packet".to_vec()));
+
+        fanout.stop();
+        let closed = (0..50).find_map(|_| match receiver.recv_from(&mut buf) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Some(()),
+            _ => None,
+        });
+        assert!(closed.is_some());
+    }
+}
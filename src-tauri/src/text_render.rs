@@ -0,0 +1,273 @@
+// Text Render - draws UTF-8 text into raw frames for overlays
+// Glyphs come from the system's TrueType fonts, rasterized with fontdue.
+// A Latin font covers Vietnamese and Cyrillic; the overlay locale adds the
+// fonts for its script (CJK, Thai) as fallbacks, and every character is
+// drawn with the first font that has it. Without any usable font file, e.g.
+// on a bare headless box, the built-in 8x8 bitmap font takes over so there is
+// always something readable.
+//
+// There is no shaping: fine for Latin, CJK and Thai, but scripts that join
+// letters (Arabic, Devanagari) come out as separate glyphs.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use font8x8::UnicodeFonts;
+use fontdue::{Font, FontSettings, Metrics};
+use crate::screen_capture::RawFrame;
+
+const BITMAP_GLYPH_SIZE: usize = 8;
+
+// Tried in order, the first one that loads is the primary font
+const LATIN_FONTS: &[&str] = &[
+    "C:\\Windows\\Fonts\\segoeui.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+    "/System/Library/Fonts/Helvetica.ttc",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+];
+
+const CJK_FONTS: &[&str] = &[
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+];
+
+/// Fallback fonts for a locale's script, most specific first
+fn locale_fonts(locale: &str) -> Vec<&'static str> {
+    let language = locale.split(['-', '_', '.']).next().unwrap_or("").to_ascii_lowercase();
+    let mut fonts: Vec<&'static str> = match language.as_str() {
+        "zh" => vec![
+            "C:\\Windows\\Fonts\\msyh.ttc",
+            "C:\\Windows\\Fonts\\simsun.ttc",
+            "/System/Library/Fonts/PingFang.ttc",
+        ],
+        "ja" => vec![
+            "C:\\Windows\\Fonts\\YuGothM.ttc",
+            "C:\\Windows\\Fonts\\meiryo.ttc",
+            "/System/Library/Fonts/ヒラギノ角ゴシック W3.ttc",
+        ],
+        "ko" => vec![
+            "C:\\Windows\\Fonts\\malgun.ttf",
+            "/System/Library/Fonts/AppleSDGothicNeo.ttc",
+        ],
+        "th" => vec![
+            "C:\\Windows\\Fonts\\LeelawUI.ttf",
+            "C:\\Windows\\Fonts\\tahoma.ttf",
+            "/System/Library/Fonts/Supplemental/Thonburi.ttc",
+            "/usr/share/fonts/truetype/noto/NotoSansThai-Regular.ttf",
+            "/usr/share/fonts/noto/NotoSansThai-Regular.ttf",
+            "/usr/share/fonts/truetype/tlwg/Loma.ttf",
+        ],
+        _ => Vec::new(),
+    };
+    if matches!(language.as_str(), "zh" | "ja" | "ko") {
+        fonts.extend_from_slice(CJK_FONTS);
+    }
+    fonts
+}
+
+/// Locale to pick fallback fonts for when none is configured
+pub fn system_locale() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty() && value != "C" && value != "POSIX")
+        .unwrap_or_else(|| "en".to_string())
+}
+
+/// Size of rendered text in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+type GlyphCache = HashMap<(char, u32), (Metrics, Vec<u8>)>;
+
+pub struct TextRenderer {
+    /// Primary first, then the locale's fallbacks; empty means the bitmap font
+    fonts: Vec<Font>,
+    /// Rasterized glyphs, overlays redraw the same few lines every frame
+    cache: Mutex<GlyphCache>,
+}
+
+impl TextRenderer {
+    /// Fonts for `locale` ("vi", "ja-JP", "zh_CN.UTF-8"...), the system locale when None
+    pub fn for_locale(locale: Option<&str>) -> Self {
+        let locale = locale.map(str::to_string).unwrap_or_else(system_locale);
+        let primary = LATIN_FONTS.iter().find_map(|path| load_font(path));
+        let fonts: Vec<Font> = primary.into_iter()
+            .chain(locale_fonts(&locale).iter().filter_map(|path| load_font(path)))
+            .collect();
+        if fonts.is_empty() {
+            eprintln!("⚠️  No system fonts found, overlay text limited to Latin characters");
+        }
+        Self { fonts, cache: Mutex::new(HashMap::new()) }
+    }
+
+    /// Only the built-in 8x8 font, no font files needed
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn bitmap() -> Self {
+        Self { fonts: Vec::new(), cache: Mutex::new(HashMap::new()) }
+    }
+
+    /// Line height for `px` sized text
+    pub fn line_height(&self, px: usize) -> usize {
+        match self.fonts.first().and_then(|font| font.horizontal_line_metrics(px as f32)) {
+            Some(line) => (line.ascent - line.descent).ceil() as usize,
+            None => bitmap_scale(px) * BITMAP_GLYPH_SIZE,
+        }
+    }
+
+    pub fn measure(&self, text: &str, px: usize) -> TextSize {
+        let width = if self.fonts.is_empty() {
+            text.chars().count() * bitmap_scale(px) * BITMAP_GLYPH_SIZE
+        } else {
+            let mut cache = self.cache.lock().unwrap();
+            text.chars()
+                .map(|c| self.glyph(&mut cache, c, px).0.advance_width)
+                .sum::<f32>()
+                .ceil() as usize
+        };
+        TextSize { width, height: self.line_height(px) }
+    }
+
+    /// Draw `text` with its top-left corner at (`x`, `y`), clipped to the frame
+    pub fn draw(&self, frame: &mut RawFrame, text: &str, x: usize, y: usize, px: usize, color: [u8; 3]) {
+        if self.fonts.is_empty() {
+            let scale = bitmap_scale(px);
+            for (i, c) in text.chars().enumerate() {
+                draw_bitmap_glyph(frame, c, x + i * BITMAP_GLYPH_SIZE * scale, y, scale, color);
+            }
+            return;
+        }
+
+        let ascent = self.fonts[0].horizontal_line_metrics(px as f32).map_or(px as f32, |line| line.ascent);
+        let baseline = y as f32 + ascent;
+        let mut pen = x as f32;
+        let mut cache = self.cache.lock().unwrap();
+        for c in text.chars() {
+            let (metrics, coverage) = self.glyph(&mut cache, c, px);
+            let left = (pen + metrics.xmin as f32).round() as i64;
+            let top = (baseline - (metrics.height as i32 + metrics.ymin) as f32).round() as i64;
+            blend_coverage(frame, left, top, metrics.width, coverage, color);
+            pen += metrics.advance_width;
+        }
+    }
+
+    // First font that has the character, tofu from the primary otherwise
+    fn glyph<'a>(&self, cache: &'a mut GlyphCache, c: char, px: usize) -> &'a (Metrics, Vec<u8>) {
+        cache.entry((c, px as u32)).or_insert_with(|| {
+            let font = self.fonts.iter()
+                .find(|font| font.lookup_glyph_index(c) != 0)
+                .unwrap_or(&self.fonts[0]);
+            font.rasterize(c, px as f32)
+        })
+    }
+}
+
+fn load_font(path: &str) -> Option<Font> {
+    let data = std::fs::read(path).ok()?;
+    // Collections (.ttc) use their first face, the regular weight for the ones listed
+    match Font::from_bytes(data, FontSettings::default()) {
+        Ok(font) => Some(font),
+        Err(e) => {
+            eprintln!("⚠️  Could not load font {}: {}", path, e);
+            None
+        }
+    }
+}
+
+// The bitmap font only scales in whole steps
+fn bitmap_scale(px: usize) -> usize {
+    (px / BITMAP_GLYPH_SIZE).max(1)
+}
+
+fn blend_coverage(frame: &mut RawFrame, left: i64, top: i64, width: usize, coverage: &[u8], color: [u8; 3]) {
+    if width == 0 {
+        return;
+    }
+    for (row, line) in coverage.chunks_exact(width).enumerate() {
+        let py = top + row as i64;
+        if py < 0 || py >= frame.height as i64 {
+            continue;
+        }
+        for (col, &alpha) in line.iter().enumerate() {
+            let px = left + col as i64;
+            if alpha == 0 || px < 0 || px >= frame.width as i64 {
+                continue;
+            }
+            let i = (py as usize * frame.width + px as usize) * 4;
+            for (channel, &target) in frame.rgba[i..i + 3].iter_mut().zip(&color) {
+                *channel = ((*channel as u32 * (255 - alpha as u32) + target as u32 * alpha as u32) / 255) as u8;
+            }
+        }
+    }
+}
+
+fn draw_bitmap_glyph(frame: &mut RawFrame, c: char, x: usize, y: usize, scale: usize, color: [u8; 3]) {
+    let Some(bitmap) = font8x8::BASIC_FONTS.get(c)
+        .or_else(|| font8x8::LATIN_FONTS.get(c))
+        .or_else(|| font8x8::BASIC_FONTS.get('?')) else {
+        return;
+    };
+    for (row, bits) in bitmap.iter().enumerate() {
+        for col in 0..BITMAP_GLYPH_SIZE {
+            if bits & (1 << col) == 0 {
+                continue;
+            }
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    let (px, py) = (x + col * scale + dx, y + row * scale + dy);
+                    if px < frame.width && py < frame.height {
+                        let i = (py * frame.width + px) * 4;
+                        frame.rgba[i..i + 3].copy_from_slice(&color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn frame(width: usize, height: usize) -> RawFrame {
+        RawFrame { rgba: vec![0; width * height * 4], width, height, captured_at: Instant::now(), dirty: None, moves: Vec::new() }
+    }
+
+    #[test]
+    fn test_locale_fallbacks() {
+        assert!(locale_fonts("en_US.UTF-8").is_empty());
+        assert!(locale_fonts("vi").is_empty());
+        assert!(locale_fonts("ja-JP").iter().any(|path| path.contains("NotoSansCJK")));
+        assert!(locale_fonts("th_TH").iter().any(|path| path.contains("Thai")));
+    }
+
+    #[test]
+    fn test_bitmap_measure_and_clip() {
+        let renderer = TextRenderer::bitmap();
+        assert_eq!(renderer.measure("Ctrl+C", 16), TextSize { width: 6 * 16, height: 16 });
+
+        // Characters outside the bitmap set show as '?', text running off the edge is clipped
+        let mut small = frame(40, 10);
+        renderer.draw(&mut small, "Đăng nhập", 30, 4, 16, [255, 255, 255]);
+        assert!(small.rgba.iter().any(|&b| b == 255));
+    }
+
+    #[test]
+    fn test_system_fonts_draw_non_latin() {
+        let renderer = TextRenderer::for_locale(Some("ja"));
+        let size = renderer.measure("日本語 ok", 24);
+        assert!(size.width > 0 && size.height > 0);
+
+        let mut target = frame(300, 60);
+        renderer.draw(&mut target, "日本語 ok", 4, 4, 24, [255, 255, 255]);
+        assert!(target.rgba.iter().any(|&b| b > 0));
+    }
+}
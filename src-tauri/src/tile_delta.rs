@@ -0,0 +1,503 @@
+// Tile Delta Encoder - only send screen regions that changed
+// Splits each frame into 64x64 tiles, hashes them against the previous frame
+// and JPEG-encodes just the dirty tiles. Full frames stay plain JPEG.
+// When the capturer reports dirty rects, tiles outside them keep their old
+// hash without being hashed again. Regions it reports moved (scrolling) are
+// sent as copies receivers make on their own picture before drawing the
+// tiles, so only what scrolled into view is encoded.
+//
+// Delta payload layout (big-endian):
+//   "TILE" | width u16 | height u16 | tile_count u16
+//   per tile: x u16 | y u16 | w u16 | h u16 | len u32 | JPEG bytes
+//   copy_count u16 (missing from older senders)
+//   per copy: from_x u16 | from_y u16 | x u16 | y u16 | w u16 | h u16
+
+use crate::hw_encoder::{self, EncoderConfig, EncoderType, VideoEncoder};
+use crate::jpeg;
+use crate::screen_capture::{DirtyRect, MovedRect};
+use image::RgbImage;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+pub const TILE_SIZE: u32 = 64;
+pub const TILE_MAGIC: &[u8; 4] = b"TILE";
+const KEYFRAME_INTERVAL: u32 = 60; // Full frame every ~2s at 30 FPS so late joiners recover
+const MAX_DIRTY_RATIO: f32 = 0.5;  // Above this a full frame is cheaper than many tiles
+const PAYLOAD_HEADER_SIZE: usize = 10;
+const TILE_HEADER_SIZE: usize = 12;
+const COPY_SIZE: usize = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    pub jpeg: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileUpdate {
+    pub width: u16,
+    pub height: u16,
+    pub tiles: Vec<Tile>,
+    /// Applied in order before the tiles are drawn
+    pub copies: Vec<MovedRect>,
+}
+
+#[derive(Default)]
+pub struct TileDeltaEncoder {
+    width: u32,
+    height: u32,
+    tile_hashes: Vec<u64>,
+    frames_since_keyframe: u32,
+    /// Threads encoding dirty tiles, serial at 0 or 1
+    workers: usize,
+    /// The previous frame, kept once the source reports its changes, to play moves on
+    previous: Option<RgbImage>,
+}
+
+impl TileDeltaEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_workers(workers: usize) -> Self {
+        Self { workers, ..Self::default() }
+    }
+
+    /// Next call to `encode` will produce a full frame
+    pub fn force_keyframe(&mut self) {
+        self.tile_hashes.clear();
+    }
+
+    /// Encode a frame as either a full JPEG or a tile delta.
+    /// Returns `None` when nothing changed since the previous frame.
+    pub fn encode(&mut self, img: &RgbImage, quality: u8) -> Result<Option<Vec<u8>>, String> {
+        self.encode_dirty(img, quality, None, &[])
+    }
+
+    /// `encode`, trusting that nothing outside `dirty` changed since the
+    /// previous frame apart from the targets of `moves`
+    pub fn encode_dirty(&mut self, img: &RgbImage, quality: u8, dirty: Option<&[DirtyRect]>, moves: &[MovedRect]) -> Result<Option<Vec<u8>>, String> {
+        let (width, height) = img.dimensions();
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(format!("Frame {}x{} too large for tile delta", width, height));
+        }
+
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        let tiles_y = height.div_ceil(TILE_SIZE);
+        let tile_count = (tiles_x * tiles_y) as usize;
+        let origin = |index: usize| ((index as u32 % tiles_x) * TILE_SIZE, (index as u32 / tiles_x) * TILE_SIZE);
+        let touches = |rect: &DirtyRect, (x, y): (u32, u32)| {
+            rect.intersects(x as usize, y as usize, TILE_SIZE as usize, TILE_SIZE as usize)
+        };
+        let known = (width, height) == (self.width, self.height) && self.tile_hashes.len() == tile_count;
+        let hashes: Vec<u64> = (0..tile_count)
+            .map(|index| {
+                let clean = known && dirty.is_some_and(|rects| {
+                    !rects.iter().chain(moves.iter().map(|moved| &moved.to)).any(|rect| touches(rect, origin(index)))
+                });
+                let (x, y) = origin(index);
+                if clean { self.tile_hashes[index] } else { hash_tile(img, x, y) }
+            })
+            .collect();
+
+        // Receivers' picture after the moves, what the tiles are compared against
+        let predicted = self.previous.as_ref()
+            .filter(|previous| known && previous.dimensions() == (width, height))
+            .and_then(|previous| {
+                let fitting: Vec<MovedRect> = moves.iter().copied()
+                    .filter(|moved| moved.fits(width as usize, height as usize))
+                    .collect();
+                (!fitting.is_empty()).then(|| {
+                    let mut predicted = previous.clone();
+                    for moved in &fitting {
+                        moved.apply(&mut predicted, width as usize, 3);
+                    }
+                    let hashes: Vec<u64> = (0..tile_count)
+                        .map(|index| {
+                            let (x, y) = origin(index);
+                            if fitting.iter().any(|moved| touches(&moved.to, (x, y))) {
+                                hash_tile(&predicted, x, y)
+                            } else {
+                                self.tile_hashes[index]
+                            }
+                        })
+                        .collect();
+                    (fitting, hashes)
+                })
+            });
+        let (copies, predicted_hashes) = predicted.unzip();
+        let copies = copies.unwrap_or_default();
+        let previous_hashes = predicted_hashes.as_ref().unwrap_or(&self.tile_hashes);
+
+        let needs_keyframe = width != self.width
+            || height != self.height
+            || self.tile_hashes.len() != hashes.len()
+            || self.frames_since_keyframe >= KEYFRAME_INTERVAL;
+
+        let dirty_tiles: Vec<usize> = if needs_keyframe {
+            Vec::new()
+        } else {
+            (0..hashes.len()).filter(|&i| hashes[i] != previous_hashes[i]).collect()
+        };
+
+        if self.previous.is_some() || dirty.is_some() || !moves.is_empty() {
+            self.previous = Some(img.clone());
+        }
+        self.width = width;
+        self.height = height;
+        self.tile_hashes = hashes;
+        let dirty = dirty_tiles;
+
+        if needs_keyframe || dirty.len() as f32 > self.tile_hashes.len() as f32 * MAX_DIRTY_RATIO {
+            self.frames_since_keyframe = 0;
+            return encode_jpeg(img, quality).map(Some);
+        }
+
+        self.frames_since_keyframe += 1;
+
+        if dirty.is_empty() && copies.is_empty() {
+            return Ok(None);
+        }
+
+        let encode_tiles = |indices: &[usize]| -> Result<Vec<Tile>, String> {
+            indices.iter().map(|&index| {
+                let x = (index as u32 % tiles_x) * TILE_SIZE;
+                let y = (index as u32 / tiles_x) * TILE_SIZE;
+                let w = TILE_SIZE.min(width - x);
+                let h = TILE_SIZE.min(height - y);
+                let tile_img = image::imageops::crop_imm(img, x, y, w, h).to_image();
+
+                Ok(Tile {
+                    x: x as u16,
+                    y: y as u16,
+                    width: w as u16,
+                    height: h as u16,
+                    jpeg: encode_jpeg(&tile_img, quality)?,
+                })
+            }).collect()
+        };
+
+        // Each worker takes a contiguous run of tiles, so the order stays the same
+        let tiles = if self.workers > 1 && dirty.len() > 1 {
+            let per_worker = dirty.len().div_ceil(self.workers);
+            let encode_tiles = &encode_tiles;
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = dirty.chunks(per_worker)
+                    .map(|indices| scope.spawn(move || encode_tiles(indices)))
+                    .collect();
+                let mut tiles = Vec::with_capacity(dirty.len());
+                for handle in handles {
+                    tiles.extend(handle.join().map_err(|_| "Tile encode worker panicked".to_string())??);
+                }
+                Ok::<_, String>(tiles)
+            })?
+        } else {
+            encode_tiles(&dirty)?
+        };
+
+        Ok(Some(serialize(&TileUpdate {
+            width: width as u16,
+            height: height as u16,
+            tiles,
+            copies,
+        })))
+    }
+}
+
+/// Tile deltas behind the common encoder interface.
+/// An empty output means nothing changed and there is nothing to send.
+pub struct TileDeltaVideoEncoder {
+    encoder: TileDeltaEncoder,
+    width: usize,
+    height: usize,
+    quality: u8,
+    /// For the next frame, see `set_dirty_rects` and `set_moved_rects`
+    dirty: Option<Vec<DirtyRect>>,
+    moves: Vec<MovedRect>,
+}
+
+impl TileDeltaVideoEncoder {
+    pub fn new(config: &EncoderConfig) -> Self {
+        Self {
+            encoder: TileDeltaEncoder::with_workers(config.workers),
+            width: config.width,
+            height: config.height,
+            quality: config.quality,
+            dirty: None,
+            moves: Vec::new(),
+        }
+    }
+}
+
+impl VideoEncoder for TileDeltaVideoEncoder {
+    fn encode(&mut self, rgba: &[u8]) -> Result<Vec<u8>, String> {
+        hw_encoder::check_frame_size(rgba, self.width, self.height)?;
+
+        let rgb = hw_encoder::rgba_to_rgb(rgba, self.width, self.height);
+        let img = RgbImage::from_raw(self.width as u32, self.height as u32, rgb)
+            .ok_or("Failed to create image buffer")?;
+
+        let (dirty, moves) = (self.dirty.take(), std::mem::take(&mut self.moves));
+        Ok(self.encoder.encode_dirty(&img, self.quality, dirty.as_deref(), &moves)?.unwrap_or_default())
+    }
+
+    fn encoder_type(&self) -> EncoderType {
+        EncoderType::TileDelta
+    }
+
+    fn set_bitrate(&mut self, _bitrate: u32) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn set_fps(&mut self, _fps: u32) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn request_keyframe(&mut self) {
+        self.encoder.force_keyframe();
+    }
+
+    fn set_quality(&mut self, quality: u8) {
+        self.quality = quality;
+    }
+
+    fn set_dirty_rects(&mut self, rects: Option<&[DirtyRect]>) {
+        self.dirty = rects.map(<[DirtyRect]>::to_vec);
+    }
+
+    fn set_moved_rects(&mut self, moves: &[MovedRect]) {
+        self.moves = moves.to_vec();
+    }
+}
+
+fn hash_tile(img: &RgbImage, x: u32, y: u32) -> u64 {
+    let (width, height) = img.dimensions();
+    let w = TILE_SIZE.min(width - x) as usize;
+    let h = TILE_SIZE.min(height - y);
+    let stride = width as usize * 3;
+    let raw = img.as_raw();
+
+    let mut hasher = DefaultHasher::new();
+    for row in y..y + h {
+        let start = row as usize * stride + x as usize * 3;
+        hasher.write(&raw[start..start + w * 3]);
+    }
+    hasher.finish()
+}
+
+fn encode_jpeg(img: &RgbImage, quality: u8) -> Result<Vec<u8>, String> {
+    jpeg::encode(img.as_raw(), img.width() as usize, img.height() as usize, jpeg::Layout::Rgb, quality)
+        .map_err(|e| format!("Failed to encode tile: {}", e))
+}
+
+pub fn is_tile_payload(data: &[u8]) -> bool {
+    data.starts_with(TILE_MAGIC)
+}
+
+pub fn serialize(update: &TileUpdate) -> Vec<u8> {
+    let body: usize = update.tiles.iter().map(|t| TILE_HEADER_SIZE + t.jpeg.len()).sum::<usize>()
+        + 2 + update.copies.len() * COPY_SIZE;
+    let mut out = Vec::with_capacity(PAYLOAD_HEADER_SIZE + body);
+
+    out.extend_from_slice(TILE_MAGIC);
+    out.extend_from_slice(&update.width.to_be_bytes());
+    out.extend_from_slice(&update.height.to_be_bytes());
+    out.extend_from_slice(&(update.tiles.len() as u16).to_be_bytes());
+
+    for tile in &update.tiles {
+        out.extend_from_slice(&tile.x.to_be_bytes());
+        out.extend_from_slice(&tile.y.to_be_bytes());
+        out.extend_from_slice(&tile.width.to_be_bytes());
+        out.extend_from_slice(&tile.height.to_be_bytes());
+        out.extend_from_slice(&(tile.jpeg.len() as u32).to_be_bytes());
+        out.extend_from_slice(&tile.jpeg);
+    }
+
+    out.extend_from_slice(&(update.copies.len() as u16).to_be_bytes());
+    for copy in &update.copies {
+        for value in [copy.from.0, copy.from.1, copy.to.x, copy.to.y, copy.to.width, copy.to.height] {
+            out.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+    }
+
+    out
+}
+
+pub fn parse(data: &[u8]) -> Result<TileUpdate, String> {
+    if data.len() < PAYLOAD_HEADER_SIZE || !is_tile_payload(data) {
+        return Err("Not a tile delta payload".to_string());
+    }
+
+    let read_u16 = |at: usize| u16::from_be_bytes([data[at], data[at + 1]]);
+    let width = read_u16(4);
+    let height = read_u16(6);
+    let count = read_u16(8) as usize;
+
+    let mut tiles = Vec::with_capacity(count);
+    let mut offset = PAYLOAD_HEADER_SIZE;
+    for i in 0..count {
+        if offset + TILE_HEADER_SIZE > data.len() {
+            return Err(format!("Tile {} header truncated", i));
+        }
+        let len = u32::from_be_bytes([
+            data[offset + 8], data[offset + 9], data[offset + 10], data[offset + 11],
+        ]) as usize;
+        let start = offset + TILE_HEADER_SIZE;
+        if start + len > data.len() {
+            return Err(format!("Tile {} data truncated ({} bytes expected)", i, len));
+        }
+
+        let tile = Tile {
+            x: read_u16(offset),
+            y: read_u16(offset + 2),
+            width: read_u16(offset + 4),
+            height: read_u16(offset + 6),
+            jpeg: data[start..start + len].to_vec(),
+        };
+        if tile.x as u32 + tile.width as u32 > width as u32 || tile.y as u32 + tile.height as u32 > height as u32 {
+            return Err(format!("Tile {} at {},{} outside {}x{} frame", i, tile.x, tile.y, width, height));
+        }
+
+        tiles.push(tile);
+        offset = start + len;
+    }
+
+    let mut copies = Vec::new();
+    if offset + 2 <= data.len() {
+        let count = read_u16(offset) as usize;
+        offset += 2;
+        for i in 0..count {
+            if offset + COPY_SIZE > data.len() {
+                return Err(format!("Copy {} truncated", i));
+            }
+            let value = |n: usize| read_u16(offset + n * 2) as usize;
+            let copy = MovedRect {
+                from: (value(0), value(1)),
+                to: DirtyRect { x: value(2), y: value(3), width: value(4), height: value(5) },
+            };
+            if !copy.fits(width as usize, height as usize) {
+                return Err(format!("Copy {} to {},{} outside {}x{} frame", i, copy.to.x, copy.to.y, width, height));
+            }
+            copies.push(copy);
+            offset += COPY_SIZE;
+        }
+    }
+
+    Ok(TileUpdate { width, height, tiles, copies })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, value: u8) -> RgbImage {
+        RgbImage::from_pixel(width, height, image::Rgb([value, value, value]))
+    }
+
+    #[test]
+    fn test_first_frame_is_full_jpeg() {
+        let mut encoder = TileDeltaEncoder::new();
+        let payload = encoder.encode(&solid(200, 100, 10), 50).unwrap().unwrap();
+        assert!(payload.starts_with(&[0xFF, 0xD8]));
+    }
+
+    #[test]
+    fn test_unchanged_frame_produces_nothing() {
+        let mut encoder = TileDeltaEncoder::new();
+        let frame = solid(200, 100, 10);
+        encoder.encode(&frame, 50).unwrap();
+        assert!(encoder.encode(&frame, 50).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_single_dirty_tile() {
+        let mut encoder = TileDeltaEncoder::new();
+        let mut frame = solid(200, 100, 10);
+        encoder.encode(&frame, 50).unwrap();
+
+        // Touch one pixel in the tile at (64, 64), which is clipped to 64x36
+        frame.put_pixel(70, 70, image::Rgb([255, 0, 0]));
+        let payload = encoder.encode(&frame, 50).unwrap().unwrap();
+        let update = parse(&payload).unwrap();
+
+        assert_eq!((update.width, update.height), (200, 100));
+        assert_eq!(update.tiles.len(), 1);
+        let tile = &update.tiles[0];
+        assert_eq!((tile.x, tile.y, tile.width, tile.height), (64, 64, 64, 36));
+        assert!(tile.jpeg.starts_with(&[0xFF, 0xD8]));
+    }
+
+    #[test]
+    fn test_dirty_rects_skip_clean_tiles() {
+        let mut encoder = TileDeltaEncoder::new();
+        let mut frame = solid(200, 100, 10);
+        encoder.encode(&frame, 50).unwrap();
+
+        // Changes outside the reported rects go unseen, inside them they're sent
+        frame.put_pixel(10, 10, image::Rgb([255, 0, 0]));
+        frame.put_pixel(70, 70, image::Rgb([255, 0, 0]));
+        let rects = [DirtyRect { x: 65, y: 65, width: 10, height: 10 }];
+        let payload = encoder.encode_dirty(&frame, 50, Some(&rects), &[]).unwrap().unwrap();
+        let update = parse(&payload).unwrap();
+        assert_eq!(update.tiles.iter().map(|t| (t.x, t.y)).collect::<Vec<_>>(), [(64, 64)]);
+        assert!(encoder.encode_dirty(&frame, 50, Some(&[]), &[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_scroll_sends_copy_and_new_rows() {
+        let rows = |first: u32| RgbImage::from_fn(256, 256, |_, y| image::Rgb([((y + first) * 3 % 256) as u8, 0, 0]));
+        let mut encoder = TileDeltaEncoder::new();
+        encoder.encode_dirty(&rows(0), 50, None, &[]).unwrap();
+        encoder.encode_dirty(&rows(0), 50, Some(&[]), &[]).unwrap();
+
+        // Scrolled up 64 rows, the bottom tile row is new
+        let moved = MovedRect { from: (0, 64), to: DirtyRect { x: 0, y: 0, width: 256, height: 192 } };
+        let rects = [DirtyRect { x: 0, y: 192, width: 256, height: 64 }];
+        let payload = encoder.encode_dirty(&rows(64), 50, Some(&rects), &[moved]).unwrap().unwrap();
+        let update = parse(&payload).unwrap();
+        assert_eq!(update.copies, [moved]);
+        assert!(update.tiles.iter().all(|t| t.y == 192));
+        assert_eq!(update.tiles.len(), 4);
+    }
+
+    #[test]
+    fn test_workers_match_serial_encoding() {
+        let mut frame = solid(640, 320, 10);
+        let mut serial = TileDeltaEncoder::new();
+        let mut parallel = TileDeltaEncoder::with_workers(3);
+        serial.encode(&frame, 50).unwrap();
+        parallel.encode(&frame, 50).unwrap();
+
+        // Dirty a handful of tiles, few enough to stay a delta
+        for x in [10, 100, 300, 500, 630] {
+            frame.put_pixel(x, 200, image::Rgb([255, 0, 0]));
+        }
+        let expected = serial.encode(&frame, 50).unwrap().unwrap();
+        assert_eq!(parse(&expected).unwrap().tiles.len(), 5);
+        assert_eq!(parallel.encode(&frame, 50).unwrap().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_resolution_change_forces_keyframe() {
+        let mut encoder = TileDeltaEncoder::new();
+        encoder.encode(&solid(200, 100, 10), 50).unwrap();
+        let payload = encoder.encode(&solid(100, 100, 10), 50).unwrap().unwrap();
+        assert!(!is_tile_payload(&payload));
+    }
+
+    #[test]
+    fn test_parse_rejects_truncated_payload() {
+        let update = TileUpdate {
+            width: 64,
+            height: 64,
+            tiles: vec![Tile { x: 0, y: 0, width: 64, height: 64, jpeg: vec![1, 2, 3, 4] }],
+            copies: vec![MovedRect { from: (0, 8), to: DirtyRect { x: 0, y: 0, width: 64, height: 56 } }],
+        };
+        let payload = serialize(&update);
+        assert_eq!(parse(&payload).unwrap(), update);
+        assert!(parse(&payload[..payload.len() - 1]).is_err());
+    }
+}
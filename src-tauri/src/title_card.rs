@@ -0,0 +1,133 @@
+// Title Card - what viewers see before the first live frame
+// A stream used to start on an empty canvas until the screen first changed.
+// Now every stream (including the one restarted after a resume) opens with a
+// generated card, the stream name, presenter and start time, for a few
+// seconds before capture takes over. It is resent twice a second so viewers
+// joining during those seconds see it too.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::frame_source::FrameSource;
+use crate::screen_capture::RawFrame;
+use crate::settings::StreamSettings;
+use crate::text_render::TextRenderer;
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+const BACKGROUND: [u8; 3] = [22, 30, 46];
+const TITLE_COLOR: [u8; 3] = [255, 255, 255];
+const DETAIL_COLOR: [u8; 3] = [170, 185, 205];
+
+/// `source` with the title card in front of it
+pub struct WithTitleCard<S> {
+    card: Option<RawFrame>,
+    until: Instant,
+    last_sent: Option<Instant>,
+    source: S,
+}
+
+impl<S: FrameSource> WithTitleCard<S> {
+    /// A card sized like the stream, or straight through to `source` when the card is off
+    pub fn new(source: S, settings: &StreamSettings, width: usize, height: usize) -> Self {
+        let locale = settings.overlay_locale.as_deref();
+        let settings = &settings.title_card;
+        let card = settings.enabled.then(|| {
+            let title = settings.title.clone().unwrap_or_else(crate::discovery::host_name);
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+            let started = format_timestamp(now + settings.utc_offset_minutes as i64 * 60);
+            render(&TextRenderer::for_locale(locale), width, height, &title, settings.presenter.as_deref(), &started)
+        });
+        Self {
+            card,
+            until: Instant::now() + Duration::from_millis(settings.duration_ms),
+            last_sent: None,
+            source,
+        }
+    }
+}
+
+impl<S: FrameSource> FrameSource for WithTitleCard<S> {
+    fn next_frame(&mut self) -> Result<Option<RawFrame>, String> {
+        let now = Instant::now();
+        match self.card.as_ref() {
+            Some(card) if now < self.until => {
+                if self.last_sent.is_some_and(|t| now.duration_since(t) < REFRESH_INTERVAL) {
+                    return Ok(None);
+                }
+                self.last_sent = Some(now);
+                Ok(Some(RawFrame { captured_at: now, ..card.clone() }))
+            }
+            Some(_) => {
+                self.card = None;
+                // Changed against the card, everywhere
+                self.source.next_frame().map(|frame| frame.map(|frame| RawFrame { dirty: None, ..frame }))
+            }
+            None => self.source.next_frame(),
+        }
+    }
+}
+
+/// The card: title, then presenter and start time, centered
+pub fn render(text: &TextRenderer, width: usize, height: usize, title: &str, presenter: Option<&str>, started: &str) -> RawFrame {
+    let mut frame = RawFrame {
+        rgba: [BACKGROUND[0], BACKGROUND[1], BACKGROUND[2], 255].repeat(width * height),
+        width,
+        height,
+        captured_at: Instant::now(),
+        dirty: None,
+        moves: Vec::new(),
+    };
+    let title_px = (height / 12).clamp(16, 96);
+    let detail_px = (height / 24).clamp(12, 48);
+    let mut lines = vec![(title, title_px, TITLE_COLOR)];
+    if let Some(presenter) = presenter.filter(|p| !p.is_empty()) {
+        lines.push((presenter, detail_px, DETAIL_COLOR));
+    }
+    lines.push((started, detail_px, DETAIL_COLOR));
+
+    let gap = detail_px / 2;
+    let total: usize = lines.iter().map(|(_, px, _)| text.line_height(*px) + gap).sum::<usize>() - gap;
+    let mut y = height.saturating_sub(total) / 2;
+    for (line, px, color) in lines {
+        let x = width.saturating_sub(text.measure(line, px).width) / 2;
+        text.draw(&mut frame, line, x, y, px, color);
+        y += text.line_height(px) + gap;
+    }
+    frame
+}
+
+/// "YYYY-MM-DD HH:MM" for seconds since the Unix epoch
+fn format_timestamp(secs: i64) -> String {
+    let days = secs.div_euclid(86_400);
+    let minutes = secs.rem_euclid(86_400) / 60;
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_card_then_live_frames() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+        assert_eq!(format_timestamp(1_709_210_096), "2024-02-29 12:34");
+
+        let card = render(&TextRenderer::bitmap(), 320, 240, "Lab 3", Some("Ms. Lan"), "2024-02-29 12:34");
+        assert_eq!(card.rgba.len(), 320 * 240 * 4);
+        assert!(card.rgba.chunks_exact(4).any(|p| p[..3] == TITLE_COLOR));
+
+        let live = || Ok(Some(RawFrame { rgba: vec![0; 4], width: 1, height: 1, captured_at: Instant::now(), dirty: None, moves: Vec::new() }));
+        let mut settings = StreamSettings::default();
+        settings.title_card.duration_ms = 0;
+        let mut source = WithTitleCard::new(live, &settings, 320, 240);
+        assert_eq!(source.next_frame().unwrap().unwrap().width, 1);
+    }
+}
@@ -0,0 +1,158 @@
+// Trigger Listener - lets macro pads (Stream Deck etc.) and AV control systems
+// drive the broadcaster with one-word commands, on localhost only:
+//   UDP:  echo -n "display 2" | nc -u -w0 127.0.0.1 8790
+//   HTTP: curl http://127.0.0.1:8790/trigger/display/2
+//
+// Commands: start, stop, freeze (toggles), display <n> (1-based)
+
+use axum::{extract::{Path, State}, routing::get, Router};
+use std::future::IntoFuture;
+use tauri::{AppHandle, Manager};
+use tokio::sync::oneshot;
+
+use crate::AppState;
+
+pub const DEFAULT_PORT: u16 = 8790;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerCommand {
+    Start,
+    Stop,
+    Freeze,
+    /// 0-based display index
+    Display(usize),
+}
+
+impl TriggerCommand {
+    /// Accepts "display 2", "display/2" or "display2", case-insensitive
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().to_ascii_lowercase();
+        let text = text.trim_start_matches('/');
+        match text {
+            "start" => Some(TriggerCommand::Start),
+            "stop" => Some(TriggerCommand::Stop),
+            "freeze" => Some(TriggerCommand::Freeze),
+            _ => {
+                let number = text.strip_prefix("display")?.trim_start_matches([' ', '/']);
+                match number.parse::<usize>() {
+                    Ok(n) if n >= 1 => Some(TriggerCommand::Display(n - 1)),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+async fn execute(app: &AppHandle, text: &str) -> Result<String, String> {
+    let command = TriggerCommand::parse(text)
+        .ok_or_else(|| format!("Unknown trigger command: {:?}", text.trim()))?;
+    eprintln!("🎛️  Trigger: {:?}", command);
+
+    let state = app.state::<AppState>();
+    match command {
+        TriggerCommand::Start => crate::server_start(app.clone(), &state).await,
+        TriggerCommand::Stop => crate::server_stop(&state),
+        TriggerCommand::Freeze => crate::server_toggle_freeze(&state),
+        TriggerCommand::Display(index) => crate::display_select(app.clone(), &state, index).await,
+    }
+}
+
+/// Running trigger listener, shut down on `stop()` or drop
+pub struct TriggerListener {
+    port: u16,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl TriggerListener {
+    pub async fn start(app: AppHandle, port: u16) -> Result<Self, String> {
+        let tcp = tokio::net::TcpListener::bind(("127.0.0.1", port))
+            .await
+            .map_err(|e| format!("Failed to bind trigger listener on TCP port {}: {}", port, e))?;
+        let udp = tokio::net::UdpSocket::bind(("127.0.0.1", port))
+            .await
+            .map_err(|e| format!("Failed to bind trigger listener on UDP port {}: {}", port, e))?;
+
+        let router = Router::new()
+            .route("/trigger/*command", get(http_trigger).post(http_trigger))
+            .with_state(app.clone());
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        tokio::spawn(async move {
+            let http = axum::serve(tcp, router).into_future();
+            tokio::select! {
+                result = http => {
+                    if let Err(e) = result {
+                        eprintln!("❌ Trigger listener error: {}", e);
+                    }
+                }
+                _ = serve_udp(udp, app) => {}
+                _ = shutdown_rx => {}
+            }
+            eprintln!("🔴 Trigger listener stopped");
+        });
+
+        eprintln!("🎛️  Trigger listener on 127.0.0.1:{} (UDP + HTTP)", port);
+
+        Ok(Self {
+            port,
+            shutdown: Some(shutdown_tx),
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+impl Drop for TriggerListener {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+async fn http_trigger(State(app): State<AppHandle>, Path(command): Path<String>) -> (axum::http::StatusCode, String) {
+    match execute(&app, &command).await {
+        Ok(message) => (axum::http::StatusCode::OK, message),
+        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e),
+    }
+}
+
+// Each datagram is one command; the result is sent back to the sender
+async fn serve_udp(socket: tokio::net::UdpSocket, app: AppHandle) {
+    let mut buf = [0u8; 256];
+    loop {
+        let Ok((size, from)) = socket.recv_from(&mut buf).await else {
+            continue;
+        };
+        let text = String::from_utf8_lossy(&buf[..size]).into_owned();
+        let reply = match execute(&app, &text).await {
+            Ok(message) => message,
+            Err(e) => format!("ERROR: {}", e),
+        };
+        let _ = socket.send_to(reply.as_bytes(), from).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(TriggerCommand::parse("start\n"), Some(TriggerCommand::Start));
+        assert_eq!(TriggerCommand::parse("STOP"), Some(TriggerCommand::Stop));
+        assert_eq!(TriggerCommand::parse("/freeze"), Some(TriggerCommand::Freeze));
+        assert_eq!(TriggerCommand::parse("display 2"), Some(TriggerCommand::Display(1)));
+        assert_eq!(TriggerCommand::parse("display/1"), Some(TriggerCommand::Display(0)));
+        assert_eq!(TriggerCommand::parse("display1"), Some(TriggerCommand::Display(0)));
+        assert_eq!(TriggerCommand::parse("display 0"), None);
+        assert_eq!(TriggerCommand::parse("reboot"), None);
+    }
+}
@@ -1,105 +1,1610 @@
-use std::collections::HashMap;
-use std::net::{UdpSocket, Ipv4Addr};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tauri::{Emitter, AppHandle};
+use tauri::ipc::{Channel, InvokeResponseBody};
 use socket2::{Socket, Domain, Type, Protocol};
+use serde::Serialize;
+use crate::access_control::{JoinRequester, JoinStatus};
+use crate::audio_playback::AudioPlayer;
+use crate::black_frame::BlackFrameDetector;
+use crate::clock;
+use crate::discovery;
+use crate::hw_encoder::EncoderType;
+use crate::display_streams::{DisplayFrames, Received};
+use crate::key_exchange::KeyFetcher;
+use crate::latest_frame::LatestFrame;
+use crate::net_interfaces::{self, IpVersion, SocketBuffer};
+use crate::packet::{self, AudioHeader, CursorHeader, CursorState, FrameType, Heartbeat, PacketHeader, ReceiverReport, SessionMessage};
+use crate::remote_input::InputEvent;
+use crate::settings::PortMapping;
+use crate::simulcast::{LayerSelector, LayerSubscription};
+use crate::stream_crypto::StreamCipher;
+use crate::stats::{ClientReport, ClientStats, StatsHistory};
+use crate::tcp_transport::{self, TcpReceiver, Transport};
+use crate::quic_transport::{QuicControl, QuicReceiver};
+use crate::lossless;
+use crate::tile_delta;
+use crate::video_decoder::{self, VideoDecoder};
+use crate::ws_receiver::FrameBroadcast;
+use crate::chunk_heatmap::ChunkHeatmap;
+use crate::frame_validation::FrameValidator;
+use crate::jitter_buffer::JitterBuffer;
+use crate::recording::{RecordingSummary, ViewerRecording};
+use crate::recording_crypto;
 
 const FRAME_TIMEOUT_MS: u64 = 500; // Discard incomplete frames after 500ms (faster recovery)
 const MIN_FRAME_COMPLETION: f32 = 0.98; // Accept frames with 98%+ chunks (stricter to avoid black screens) 
+const DECODED_JPEG_QUALITY: u8 = 85; // Re-encode quality for frames decoded from H.264 and AV1
+const RECV_TIMEOUT_MS: u64 = 200; // Wake up regularly so staleness is noticed promptly
+const KEYFRAME_REQUEST_INTERVAL_MS: u64 = 500; // Don't flood the sender while out of sync
+const MAX_CHUNKS_PER_FRAME: u32 = 8192; // 64MB at 8KB chunks, anything larger is a bogus header
+const MEMORY_CHECK_BYTES: usize = 1024 * 1024; // Re-measure buffers after this much new data
+const DECRYPT_WARNING_INTERVAL_MS: u64 = 5000; // One log line per burst of undecryptable packets
+const MAX_FRAMES_IN_FLIGHT: usize = 2; // One being drawn, one waiting; more is latency
+const ACK_TIMEOUT_MS: u64 = 1000; // Frames not acknowledged by then count as dropped by the webview
+const REJOIN_INTERVAL_MS: u64 = 5000; // Multicast membership renewed this often while the stream is lost
+
+/// Partially received frames by simulcast layer and frame ID: chunks, last chunk time, header
+type FrameBuffer = HashMap<(u8, u32), (Vec<Vec<u8>>, Instant, PacketHeader)>;
+const REPORT_INTERVAL_MS: u64 = 1000; // Receiver report cadence
+const JITTER_RESET_GAP_MS: u64 = 5000; // Longer gaps (pause, sleep/resume) restart the jitter baseline
+const DONE_FRAMES_KEPT: usize = 64; // Finished frames remembered, well past their redundant chunks
+
+/// A frame of one of the server's other displays
+#[derive(Clone, Serialize)]
+struct DisplayFrameEvent {
+    stream: u8,
+    data: String, // base64 JPEG
+}
+
+/// Where to draw the pointer over the frame, as fractions of its width and height
+#[derive(Clone, Serialize)]
+struct CursorUpdateEvent {
+    x: f32,
+    y: f32,
+    visible: bool,
+    /// Hash of the pointer image, changes with its shape
+    shape: u32,
+}
+
+impl From<CursorState> for CursorUpdateEvent {
+    fn from(state: CursorState) -> Self {
+        Self {
+            x: state.x as f32 / u16::MAX as f32,
+            y: state.y as f32 / u16::MAX as f32,
+            visible: state.visible,
+            shape: state.shape,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct MemoryPressureEvent {
+    buffered_bytes: usize,
+    limit_bytes: usize,
+    evicted_frames: usize,
+}
+
+#[derive(Clone, Serialize)]
+struct StaleEvent {
+    stale: bool,
+    last_frame_age_ms: u64,
+}
+
+#[derive(Clone, Serialize)]
+struct StreamLostEvent {
+    last_packet_age_ms: u64,
+}
+
+#[derive(Clone, Serialize)]
+struct StreamResumedEvent {
+    lost_for_ms: u64,
+}
+
+#[derive(Clone, Serialize)]
+struct StreamStartedEvent {
+    width: u16,
+    height: u16,
+    /// Encoder the server uses, as named in EncoderType
+    codec: String,
+}
+
+/// The webview's binary frame channel, once it subscribed. Shared so a
+/// reloaded webview picks up a running stream.
+pub type FrameChannel = Arc<Mutex<Option<Channel>>>;
+
+/// Frames sent to the webview that it hasn't drawn yet. The webview
+/// acknowledges every frame once drawn; while it's behind by
+/// MAX_FRAMES_IN_FLIGHT, newer frames wait in LatestFrame instead of queueing
+/// up behind the ones it's still busy with. Frontends that never acknowledge
+/// are never held back.
+#[derive(Default)]
+pub struct FrameAcks {
+    /// When each unacknowledged frame was sent, oldest first
+    in_flight: Mutex<VecDeque<Instant>>,
+    acknowledging: AtomicBool,
+    /// Moving average of send to acknowledgment
+    avg_render_us: AtomicU64,
+}
+
+impl FrameAcks {
+    /// The webview drew the oldest frame it was sent
+    pub fn acknowledge(&self) {
+        self.acknowledging.store(true, Ordering::Relaxed);
+        if let Some(sent) = self.in_flight.lock().unwrap().pop_front() {
+            let render_us = sent.elapsed().as_micros() as u64;
+            let avg = self.avg_render_us.load(Ordering::Relaxed);
+            self.avg_render_us.store(if avg == 0 { render_us } else { (avg * 7 + render_us) / 8 }, Ordering::Relaxed);
+        }
+    }
+    
+    /// A new subscriber has nothing in flight
+    pub fn reset(&self) {
+        self.in_flight.lock().unwrap().clear();
+        self.acknowledging.store(false, Ordering::Relaxed);
+        self.avg_render_us.store(0, Ordering::Relaxed);
+    }
+    
+    fn sent(&self) {
+        if self.acknowledging.load(Ordering::Relaxed) {
+            self.in_flight.lock().unwrap().push_back(Instant::now());
+        }
+    }
+    
+    fn saturated(&self) -> bool {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        // An acknowledgment that never came (a frame the webview dropped) doesn't block for good
+        while in_flight.front().is_some_and(|sent| sent.elapsed() >= Duration::from_millis(ACK_TIMEOUT_MS)) {
+            in_flight.pop_front();
+        }
+        in_flight.len() >= MAX_FRAMES_IN_FLIGHT
+    }
+    
+    fn render_ms(&self) -> f32 {
+        self.avg_render_us.load(Ordering::Relaxed) as f32 / 1000.0
+    }
+}
+
+/// Where reassembled frames are delivered
+pub enum FrameOutput {
+    /// Events to the Tauri webview, frames go raw over its channel and are
+    /// kept for the stream:// protocol
+    Webview(AppHandle, FrameChannel, Arc<LatestFrame>, Arc<FrameAcks>),
+    /// Raw binary payloads for the headless WebSocket receiver
+    Broadcast(FrameBroadcast),
+}
+
+impl FrameOutput {
+    fn emit_frame(&self, jpeg: &[u8], limiter: &mut EmitLimiter) {
+        match self {
+            FrameOutput::Webview(app, frames, latest, acks) => {
+                let id = latest.set_image(jpeg);
+                if limiter.admit(acks) {
+                    limiter.take_skipped();
+                    send_frame(frames, acks, jpeg);
+                    let _ = app.emit("frame-ready", id);
+                }
+            }
+            FrameOutput::Broadcast(broadcast) => broadcast.publish(jpeg.to_vec(), true),
+        }
+    }
+    
+    fn emit_tiles(&self, payload: &[u8], limiter: &mut EmitLimiter) {
+        match self {
+            // Same "TILE" payload the WebSocket viewers get, parsed by the frontend
+            FrameOutput::Webview(app, frames, latest, acks) => {
+                let id = latest.add_tiles(payload);
+                if !limiter.admit(acks) {
+                    return;
+                }
+                // The webview missed the tiles before these, it gets them drawn in
+                if limiter.take_skipped() {
+                    if let Some((_, image, _)) = latest.get() {
+                        send_frame(frames, acks, &image);
+                    }
+                } else {
+                    send_frame(frames, acks, payload);
+                }
+                if let Some(id) = id {
+                    let _ = app.emit("frame-ready", id);
+                }
+            }
+            FrameOutput::Broadcast(broadcast) => broadcast.publish(payload.to_vec(), false),
+        }
+    }
+    
+    /// The newest frame, once frames were held back and the next slot came
+    fn flush_frame(&self, limiter: &mut EmitLimiter) {
+        match self {
+            FrameOutput::Webview(app, frames, latest, acks) => {
+                if !limiter.skipped || acks.saturated() || !limiter.admit(acks) {
+                    return;
+                }
+                limiter.take_skipped();
+                if let Some((id, image, _)) = latest.get() {
+                    send_frame(frames, acks, &image);
+                    let _ = app.emit("frame-ready", id);
+                }
+            }
+            // WebSocket viewers get every frame, their sockets pace themselves
+            FrameOutput::Broadcast(_) => {}
+        }
+    }
+    
+    /// How long the webview takes to draw a frame, 0 until it acknowledged one
+    fn render_ms(&self) -> f32 {
+        match self {
+            FrameOutput::Webview(.., acks) => acks.render_ms(),
+            FrameOutput::Broadcast(_) => 0.0,
+        }
+    }
+    
+    fn emit_display_frame(&self, stream: u8, jpeg: &[u8]) {
+        match self {
+            FrameOutput::Webview(app, ..) => {
+                let data = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, jpeg);
+                let _ = app.emit("display-frame", DisplayFrameEvent { stream, data });
+            }
+            // WebSocket viewers get the main display only
+            FrameOutput::Broadcast(_) => {}
+        }
+    }
+    
+    fn emit_cursor(&self, state: CursorState) {
+        match self {
+            FrameOutput::Webview(app, ..) => {
+                let _ = app.emit("cursor-update", CursorUpdateEvent::from(state));
+            }
+            // WebSocket viewers see the frames only
+            FrameOutput::Broadcast(_) => {}
+        }
+    }
+    
+    fn emit_staleness(&self, stale: bool, last_frame_age_ms: u64) {
+        match self {
+            FrameOutput::Webview(app, ..) => {
+                let _ = app.emit("stream-stale", StaleEvent { stale, last_frame_age_ms });
+            }
+            // WebSocket viewers just stop receiving messages
+            FrameOutput::Broadcast(_) => {}
+        }
+    }
+    
+    fn emit_stream_lost(&self, last_packet_age_ms: u64) {
+        match self {
+            FrameOutput::Webview(app, ..) => {
+                let _ = app.emit("stream-lost", StreamLostEvent { last_packet_age_ms });
+            }
+            FrameOutput::Broadcast(_) => {}
+        }
+    }
+    
+    fn emit_stream_resumed(&self, lost_for_ms: u64) {
+        match self {
+            FrameOutput::Webview(app, ..) => {
+                let _ = app.emit("stream-resumed", StreamResumedEvent { lost_for_ms });
+            }
+            FrameOutput::Broadcast(_) => {}
+        }
+    }
+    
+    fn emit_stream_started(&self, width: u16, height: u16, codec: EncoderType) {
+        match self {
+            FrameOutput::Webview(app, ..) => {
+                let _ = app.emit("stream-started", StreamStartedEvent { width, height, codec: format!("{:?}", codec) });
+            }
+            FrameOutput::Broadcast(_) => {}
+        }
+    }
+    
+    fn emit_stream_stopped(&self) {
+        match self {
+            FrameOutput::Webview(app, _, latest, _) => {
+                // Nothing left to show at stream:// either
+                latest.clear();
+                let _ = app.emit("stream-stopped", ());
+            }
+            FrameOutput::Broadcast(_) => {}
+        }
+    }
+    
+    fn emit_memory_pressure(&self, event: MemoryPressureEvent) {
+        match self {
+            FrameOutput::Webview(app, ..) => {
+                let _ = app.emit("memory-pressure", event);
+            }
+            FrameOutput::Broadcast(_) => {}
+        }
+    }
+    
+    fn emit_stats(&self, report: ClientReport) {
+        match self {
+            FrameOutput::Webview(app, ..) => {
+                let _ = app.emit("client-stats", report);
+            }
+            // The headless receiver has no UI to show them
+            FrameOutput::Broadcast(_) => {}
+        }
+    }
+    
+    fn emit_join_status(&self, status: JoinStatus) {
+        match self {
+            FrameOutput::Webview(app, ..) => {
+                let _ = app.emit("join-status", status);
+            }
+            FrameOutput::Broadcast(_) => {}
+        }
+    }
+}
+
+// Image bytes as they arrived, no base64 or JSON on the way to the canvas.
+// Without a subscribed webview nobody is watching, the frame is dropped.
+fn send_frame(frames: &FrameChannel, acks: &FrameAcks, payload: &[u8]) {
+    if let Some(channel) = frames.lock().unwrap().as_ref() {
+        if channel.send(InvokeResponseBody::Raw(payload.to_vec())).is_ok() {
+            acks.sent();
+        }
+    }
+}
+
+/// Caps how many frames per second reach the webview, which falls behind when
+/// the network delivers a burst, and holds frames back while it's still
+/// drawing (see FrameAcks). Frames that come too soon are only kept in
+/// LatestFrame, and whichever is newest goes out with the next slot.
+struct EmitLimiter {
+    /// 0 for no cap
+    max_fps: u32,
+    last_emit: Option<Instant>,
+    /// Frames came too soon since the last one sent
+    skipped: bool,
+    /// Frames held back because the webview was still drawing earlier ones
+    render_drops: u64,
+}
+
+impl EmitLimiter {
+    fn new() -> Self {
+        Self {
+            max_fps: 0,
+            last_emit: None,
+            skipped: false,
+            render_drops: 0,
+        }
+    }
+    
+    /// Whether a frame may go out now, noting that one was held back if not
+    fn admit(&mut self, acks: &FrameAcks) -> bool {
+        if acks.saturated() {
+            self.render_drops += 1;
+            self.skipped = true;
+            return false;
+        }
+        let due = self.max_fps == 0
+            || self.last_emit.is_none_or(|t| t.elapsed() >= Duration::from_secs(1) / self.max_fps);
+        if due {
+            self.last_emit = Some(Instant::now());
+        } else {
+            self.skipped = true;
+        }
+        due
+    }
+    
+    fn take_skipped(&mut self) -> bool {
+        std::mem::take(&mut self.skipped)
+    }
+}
+
+/// Tracks whether delta frames can be applied, i.e. every frame since the
+/// last keyframe arrived. Asks the sender for a keyframe when sync is lost.
+struct SyncTracker {
+    last_sequence: Option<u32>,
+    in_sync: bool,
+    last_request: Option<Instant>,
+}
+
+impl SyncTracker {
+    fn new() -> Self {
+        Self {
+            last_sequence: None,
+            in_sync: false,
+            last_request: None,
+        }
+    }
+    
+    /// Returns false when a delta frame must be dropped
+    fn accept(&mut self, header: &PacketHeader) -> bool {
+        let follows_previous = self.last_sequence
+            .is_some_and(|last| header.sequence == last.wrapping_add(1));
+        self.last_sequence = Some(header.sequence);
+        
+        self.in_sync = match header.frame_type {
+            FrameType::Key => true,
+            FrameType::Delta => self.in_sync && follows_previous,
+        };
+        self.in_sync
+    }
+    
+    /// A frame that was accepted turned out unusable, deltas wait for a keyframe
+    fn lost(&mut self) {
+        self.in_sync = false;
+    }
+    
+    fn request_keyframe(&mut self, control: &ControlPath) {
+        let due = match self.last_request {
+            Some(t) => t.elapsed().as_millis() as u64 >= KEYFRAME_REQUEST_INTERVAL_MS,
+            None => true,
+        };
+        if due {
+            self.last_request = Some(Instant::now());
+            if let Err(e) = control.send(packet::KEYFRAME_REQUEST) {
+                eprintln!("⚠️  Failed to request keyframe: {}", e);
+            }
+        }
+    }
+}
+
+/// Hand a frame that was just shown to the recording, ending it if writing fails
+fn record_shown(recording: &Mutex<Option<ViewerRecording>>, write: impl FnOnce(&mut ViewerRecording) -> Result<(), String>) {
+    let mut slot = recording.lock().unwrap();
+    let Some(active) = slot.as_mut() else {
+        return;
+    };
+    if let Err(e) = write(active) {
+        eprintln!("❌ Recording stopped: {}", e);
+        if let Err(e) = slot.take().map(ViewerRecording::finish).transpose() {
+            eprintln!("❌ {}", e);
+        }
+    }
+}
+
+/// Accumulates reception quality and periodically reports it to the server
+struct ReportBuilder {
+    report: ReceiverReport,
+    jitter_ms: f64,
+    /// Arrival time and sender timestamp of the previous frame
+    last_arrival: Option<(Instant, u32)>,
+    last_sent: Instant,
+}
+
+impl ReportBuilder {
+    fn new() -> Self {
+        Self {
+            report: ReceiverReport::default(),
+            jitter_ms: 0.0,
+            last_arrival: None,
+            last_sent: Instant::now(),
+        }
+    }
+    
+    fn frame_completed(&mut self, received_chunks: usize, total_chunks: usize, send_ts: u32) {
+        self.report.frames_received += 1;
+        self.count_chunks(received_chunks, total_chunks);
+        
+        // RFC 3550 interarrival jitter: how much the transit time varies,
+        // comparing our monotonic arrival times with the sender's send times
+        let now = Instant::now();
+        if let Some((last_arrival, last_send_ts)) = self.last_arrival {
+            let arrival_ms = now.duration_since(last_arrival).as_secs_f64() * 1000.0;
+            if arrival_ms < JITTER_RESET_GAP_MS as f64 {
+                let send_ms = clock::elapsed_us(last_send_ts, send_ts) as f64 / 1000.0;
+                self.jitter_ms += ((arrival_ms - send_ms).abs() - self.jitter_ms) / 16.0;
+            }
+        }
+        self.last_arrival = Some((now, send_ts));
+    }
+    
+    fn frame_lost(&mut self, received_chunks: usize, total_chunks: usize) {
+        self.report.frames_lost += 1;
+        self.count_chunks(received_chunks, total_chunks);
+    }
+    
+    fn count_chunks(&mut self, received_chunks: usize, total_chunks: usize) {
+        self.report.chunks_received += received_chunks as u32;
+        self.report.chunks_lost += total_chunks.saturating_sub(received_chunks) as u32;
+    }
+    
+    /// Returns the report when one went out
+    fn send_if_due(&mut self, control: &ControlPath) -> Option<ReceiverReport> {
+        if self.last_sent.elapsed().as_millis() < REPORT_INTERVAL_MS as u128 {
+            return None;
+        }
+        self.last_sent = Instant::now();
+        
+        self.report.jitter_us = (self.jitter_ms * 1000.0) as u32;
+        // Nowhere to send it before the stream shows up, keep counting
+        control.send(&self.report.serialize()).ok()?;
+        Some(std::mem::take(&mut self.report))
+    }
+}
+
+/// Notices when no frame has been shown for too long, so viewers never
+/// mistake a frozen screen for live content
+struct StaleWatch {
+    last_frame_at: Instant,
+    frames_seen: u64,
+    is_stale: bool,
+}
+
+impl StaleWatch {
+    fn new() -> Self {
+        Self {
+            last_frame_at: Instant::now(),
+            frames_seen: 0,
+            is_stale: false,
+        }
+    }
+    
+    fn update(&mut self, frames_received: u64, threshold_ms: u64, output: &FrameOutput) {
+        if frames_received != self.frames_seen {
+            self.frames_seen = frames_received;
+            self.last_frame_at = Instant::now();
+            if self.is_stale {
+                self.is_stale = false;
+                output.emit_staleness(false, 0);
+            }
+            return;
+        }
+        
+        let age_ms = self.last_frame_at.elapsed().as_millis() as u64;
+        if !self.is_stale && threshold_ms > 0 && age_ms > threshold_ms {
+            self.is_stale = true;
+            eprintln!("⏸️  No new frame for {}ms, stream is stale", age_ms);
+            output.emit_staleness(true, age_ms);
+        }
+    }
+}
+
+enum LossChange {
+    /// Nothing arrived for this long
+    Lost(u64),
+    /// Frames are back after this long
+    Resumed(u64),
+}
+
+/// Notices when nothing arrives at all any more: the server stopped, the
+/// network went away or a switch forgot our multicast membership. Frozen
+/// frames are StaleWatch's business; this is about the connection.
+struct LossWatch {
+    /// When the last packet before the loss arrived
+    lost_since: Option<Instant>,
+    frames_at_loss: u64,
+    last_rejoin: Option<Instant>,
+}
+
+impl LossWatch {
+    fn new() -> Self {
+        Self {
+            lost_since: None,
+            frames_at_loss: 0,
+            last_rejoin: None,
+        }
+    }
+    
+    fn update(&mut self, last_packet_at: Instant, frames_received: u64, lost_after_ms: u64) -> Option<LossChange> {
+        match self.lost_since {
+            None => {
+                let age_ms = last_packet_at.elapsed().as_millis() as u64;
+                if lost_after_ms == 0 || age_ms < lost_after_ms {
+                    return None;
+                }
+                self.lost_since = Some(last_packet_at);
+                self.frames_at_loss = frames_received;
+                self.last_rejoin = Some(Instant::now());
+                Some(LossChange::Lost(age_ms))
+            }
+            Some(since) if frames_received != self.frames_at_loss => {
+                self.lost_since = None;
+                Some(LossChange::Resumed(since.elapsed().as_millis() as u64))
+            }
+            Some(_) => None,
+        }
+    }
+    
+    /// The server said it stopped: as good as lost, without telling anyone
+    fn stopped(&mut self, frames_received: u64) {
+        if self.lost_since.is_none() {
+            self.lost_since = Some(Instant::now());
+            self.frames_at_loss = frames_received;
+            self.last_rejoin = Some(Instant::now());
+        }
+    }
+    
+    /// While lost, whether it's time to join the multicast group afresh
+    fn rejoin_due(&mut self) -> bool {
+        let due = self.lost_since.is_some()
+            && self.last_rejoin.is_none_or(|t| t.elapsed() >= Duration::from_millis(REJOIN_INTERVAL_MS));
+        if due {
+            self.last_rejoin = Some(Instant::now());
+        }
+        due
+    }
+}
+
+/// Keeps buffered stream data under a ceiling on long-lived lossy sessions.
+/// The reassembly buffer is the only one that grows with loss; the audio
+/// queue is bounded on its own.
+struct MemoryGuard {
+    bytes_since_check: usize,
+}
+
+impl MemoryGuard {
+    fn new() -> Self {
+        Self { bytes_since_check: 0 }
+    }
+    
+    /// Account for a newly buffered chunk, evicting the least recently
+    /// updated frames when the buffer is over `limit_bytes`
+    fn on_chunk(&mut self, len: usize, buffer: &mut FrameBuffer, limit_bytes: usize) -> Option<MemoryPressureEvent> {
+        self.bytes_since_check += len;
+        if self.bytes_since_check < MEMORY_CHECK_BYTES {
+            return None;
+        }
+        self.bytes_since_check = 0;
+        
+        let buffered_bytes = buffered_bytes(buffer);
+        if limit_bytes == 0 || buffered_bytes <= limit_bytes {
+            return None;
+        }
+        
+        let mut frames: Vec<(Instant, (u8, u32), usize)> = buffer.iter()
+            .map(|(id, (chunks, updated, _))| (*updated, *id, chunks.iter().map(Vec::len).sum()))
+            .collect();
+        frames.sort_unstable();
+        
+        let mut remaining = buffered_bytes;
+        let mut evicted_frames = 0;
+        for (_, id, bytes) in frames {
+            if remaining <= limit_bytes {
+                break;
+            }
+            buffer.remove(&id);
+            remaining -= bytes;
+            evicted_frames += 1;
+        }
+        
+        Some(MemoryPressureEvent {
+            buffered_bytes,
+            limit_bytes,
+            evicted_frames,
+        })
+    }
+}
+
+/// Where messages for the server go: the QUIC connection while there is one,
+/// otherwise the server's control port
+#[derive(Clone)]
+struct ControlPath {
+    socket: Arc<UdpSocket>,
+    addr: Arc<Mutex<Option<SocketAddr>>>,
+    quic: Arc<Mutex<Option<QuicControl>>>,
+}
+
+impl ControlPath {
+    fn send(&self, message: &[u8]) -> Result<(), String> {
+        if let Some(quic) = self.quic.lock().unwrap().as_ref() {
+            return quic.send(message);
+        }
+        let addr = (*self.addr.lock().unwrap()).ok_or("No stream received yet")?;
+        self.socket.send_to(message, addr)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// The stream straight from one server instead of the multicast group
+enum Unicast {
+    Tcp(TcpReceiver),
+    Quic(QuicReceiver),
+}
+
+impl Unicast {
+    // QUIC when the viewer asked for it; the TCP fallback when multicast is missing,
+    // or no server offers QUIC
+    fn connect(quic: bool, tcp: bool, ports: PortMapping) -> Result<Self, String> {
+        let timeout = Duration::from_millis(RECV_TIMEOUT_MS);
+        let mut errors = Vec::new();
+        if quic {
+            match QuicReceiver::discover(ports, timeout) {
+                Ok(receiver) => return Ok(Unicast::Quic(receiver)),
+                Err(e) => errors.push(e),
+            }
+        }
+        if tcp {
+            match TcpReceiver::discover(ports, timeout) {
+                Ok(receiver) => return Ok(Unicast::Tcp(receiver)),
+                Err(e) => errors.push(e),
+            }
+        }
+        Err(errors.join(", "))
+    }
+    
+    fn recv_from(&mut self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+        match self {
+            Unicast::Tcp(receiver) => receiver.recv_from(buf),
+            Unicast::Quic(receiver) => receiver.recv_from(buf),
+        }
+    }
+    
+    fn transport(&self) -> Transport {
+        match self {
+            Unicast::Tcp(_) => Transport::Tcp,
+            Unicast::Quic(_) => Transport::Quic,
+        }
+    }
+    
+    fn server(&self) -> SocketAddr {
+        match self {
+            Unicast::Tcp(receiver) => receiver.server(),
+            Unicast::Quic(receiver) => receiver.server(),
+        }
+    }
+}
+
+/// Payload of a received packet, decrypted when the sender sealed it
+// Paired viewers (re)fetch the key when they can't open the stream
+fn fetch_stream_key(
+    fetcher: &mut KeyFetcher,
+    pin: &Mutex<Option<String>>,
+    cipher: &Arc<Mutex<Option<Arc<StreamCipher>>>>,
+    server: IpAddr,
+    force: bool,
+) {
+    let Some(pin) = pin.lock().unwrap().clone() else {
+        return;
+    };
+    let cipher = cipher.clone();
+    fetcher.fetch(server, pin, force, move |key| {
+        *cipher.lock().unwrap() = Some(Arc::new(key));
+    });
+}
+
+fn open_payload(cipher: Option<&StreamCipher>, encrypted: bool, header: &[u8], payload: &[u8]) -> Result<Vec<u8>, String> {
+    match (encrypted, cipher) {
+        (false, _) => Ok(payload.to_vec()),
+        (true, Some(cipher)) => cipher.open(header, payload),
+        (true, None) => Err("Stream is encrypted, set the passphrase or PIN to watch it".to_string()),
+    }
+}
+
+fn buffered_bytes(buffer: &FrameBuffer) -> usize {
+    buffer.values()
+        .map(|(chunks, _, _)| chunks.iter().map(Vec::len).sum::<usize>())
+        .sum()
+}
+
+/// Frames the reassembly buffer is done with. The server sends a frame's
+/// first and last chunks twice; the copy that comes in after the frame was
+/// handed on must not start it over as a frame that then times out as lost.
+struct DoneFrames {
+    recent: VecDeque<(u8, u32)>,
+}
+
+impl DoneFrames {
+    fn new() -> Self {
+        Self { recent: VecDeque::with_capacity(DONE_FRAMES_KEPT) }
+    }
+    
+    fn contains(&self, key: &(u8, u32)) -> bool {
+        self.recent.contains(key)
+    }
+    
+    /// Take a frame out of the buffer for good
+    fn finish(&mut self, buffer: &mut FrameBuffer, key: (u8, u32)) {
+        buffer.remove(&key);
+        if self.recent.len() == DONE_FRAMES_KEPT {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(key);
+    }
+    
+    /// Frame IDs start over (new session)
+    fn clear(&mut self) {
+        self.recent.clear();
+    }
+}
+
+/// File a chunk with its frame; false when it's of a frame that's done already or out of range
+fn store_chunk(buffer: &mut FrameBuffer, done: &DoneFrames, header: PacketHeader, chunk: Vec<u8>, now: Instant) -> bool {
+    let frame_key = (header.layer, header.frame_id);
+    if done.contains(&frame_key) {
+        return false;
+    }
+    let (chunks, timestamp, _) = buffer.entry(frame_key).or_insert_with(|| {
+        (vec![Vec::new(); header.total_chunks as usize], now, header)
+    });
+    
+    // Update timestamp on each chunk received
+    *timestamp = now;
+    
+    let Some(slot) = chunks.get_mut(header.chunk_idx as usize) else {
+        eprintln!("Invalid chunk index: {} >= {}", header.chunk_idx, chunks.len());
+        return false;
+    };
+    *slot = chunk;
+    true
+}
+
+/// Drop frames that stopped getting chunks, they count as lost. Finished frames
+/// never get back into the buffer (see DoneFrames), so these really went missing.
+fn expire_frames(buffer: &mut FrameBuffer, now: Instant, reports: &mut ReportBuilder, stats: &Mutex<ClientStats>) {
+    let old_count = buffer.len();
+    buffer.retain(|id, (chunks, timestamp, _)| {
+        let is_fresh = now.duration_since(*timestamp).as_millis() < FRAME_TIMEOUT_MS as u128;
+        if !is_fresh {
+            eprintln!("Discarding incomplete frame {} (timeout)", id.1);
+            if let Some(heatmap) = stats.lock().unwrap().chunk_heatmap.as_mut() {
+                heatmap.record(chunks);
+            }
+            reports.frame_lost(chunks.iter().filter(|c| !c.is_empty()).count(), chunks.len());
+        }
+        is_fresh
+    });
+    
+    // Log cleanup if frames were removed
+    if buffer.len() < old_count {
+        println!("Cleaned up {} incomplete frames", old_count - buffer.len());
+    }
+}
 
 pub struct UdpClient {
     socket: Arc<UdpSocket>,
     is_running: Arc<Mutex<bool>>,
-    frame_buffer: Arc<Mutex<HashMap<u32, (Vec<Vec<u8>>, std::time::Instant)>>>,
+    frame_buffer: Arc<Mutex<FrameBuffer>>,
+    stats: Arc<Mutex<ClientStats>>,
+    history: Arc<Mutex<StatsHistory<ClientStats>>>,
+    stale_threshold_ms: Arc<AtomicU64>,
+    /// How long completed frames wait for earlier ones (see jitter_buffer.rs)
+    jitter_delay_ms: Arc<AtomicU64>,
+    /// Nothing received for this long counts as a lost stream (0 never does)
+    stream_lost_ms: Arc<AtomicU64>,
+    /// Most frames per second handed to the webview (0 for no cap)
+    max_emit_fps: Arc<AtomicU32>,
+    memory_limit_bytes: Arc<AtomicU64>,
+    decode_validation: Arc<AtomicBool>,
+    validation_budget_ms: Arc<AtomicU64>,
+    cipher: Arc<Mutex<Option<Arc<StreamCipher>>>>,
+    /// Pairing PIN, the cipher is then fetched from the server every session
+    pin: Arc<Mutex<Option<String>>>,
+    /// Where the stream comes from
+    server_addr: Arc<Mutex<Option<std::net::SocketAddr>>>,
+    /// The server's control port (learned from its discovery answer) or QUIC connection
+    control: ControlPath,
+    ports: PortMapping,
+    /// Asks a server that requires approval to let us in
+    join: Arc<Mutex<Option<JoinRequester>>>,
+    tcp_fallback: Arc<AtomicBool>,
+    quic: Arc<AtomicBool>,
+    /// Multicast group of the main stream
+    group: IpAddr,
+    interface: Option<IpAddr>,
+    /// Every frame shown, kept on disk while set
+    recording: Arc<Mutex<Option<ViewerRecording>>>,
+    /// Other displays of the server to show, by stream ID
+    displays: Arc<Mutex<Vec<u8>>>,
+    /// The one server stream to reassemble, when several share the group
+    stream_id: Mutex<Option<u32>>,
 }
 
 impl UdpClient {
-    pub fn new() -> Result<Self, String> {
+    /// Join the `ip_version` stream on `interface`, or wherever the OS routes multicast when None.
+    /// `group` is for servers not on the default group of `ip_version`. The stream is
+    /// received on `ports.data`; the others are a guess until the server answers.
+    pub fn new(ip_version: IpVersion, interface: Option<IpAddr>, group: Option<IpAddr>, ports: PortMapping) -> Result<Self, String> {
+        let group = group.unwrap_or_else(|| ip_version.group());
+        if !group.is_multicast() || !ip_version.matches(&group) {
+            return Err(format!("{} is not an {:?} multicast group", group, ip_version));
+        }
+        let domain = match ip_version {
+            IpVersion::V4 => Domain::IPV4,
+            IpVersion::V6 => Domain::IPV6,
+        };
         // Create socket with SO_REUSEADDR to allow rebinding
-        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))
+        let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP))
             .map_err(|e| format!("Failed to create socket: {}", e))?;
         
         socket.set_reuse_address(true)
             .map_err(|e| format!("Failed to set reuse address: {}", e))?;
         
-        let addr = "0.0.0.0:9999".parse::<std::net::SocketAddr>().unwrap();
+        if ip_version == IpVersion::V6 {
+            // Don't also take IPv4 traffic for the port
+            socket.set_only_v6(true)
+                .map_err(|e| format!("Failed to set IPv6 only: {}", e))?;
+        }
+        
+        let addr = SocketAddr::new(ip_version.unspecified(), ports.data);
         socket.bind(&addr.into())
             .map_err(|e| format!("Failed to bind: {}", e))?;
         
         let socket: UdpSocket = socket.into();
         
-        socket.join_multicast_v4(
-            &"239.0.0.1".parse::<Ipv4Addr>().unwrap(),
-            &Ipv4Addr::UNSPECIFIED
-        ).map_err(|e| format!("Failed to join multicast: {}", e))?;
+        let joined = match group {
+            IpAddr::V4(group) => socket.join_multicast_v4(
+                &group,
+                &net_interfaces::multicast_interface(interface)?
+            ),
+            IpAddr::V6(group) => socket.join_multicast_v6(
+                &group,
+                net_interfaces::multicast_interface_index(interface)?
+            ),
+        };
+        joined.map_err(|e| format!("Failed to join multicast: {}", e))?;
         
-        socket.set_read_timeout(Some(std::time::Duration::from_secs(1)))
+        socket.set_read_timeout(Some(std::time::Duration::from_millis(RECV_TIMEOUT_MS)))
             .map_err(|e| format!("Failed to set timeout: {}", e))?;
         
+        let socket = Arc::new(socket);
         Ok(Self {
-            socket: Arc::new(socket),
+            control: ControlPath {
+                socket: socket.clone(),
+                addr: Arc::new(Mutex::new(None)),
+                quic: Arc::new(Mutex::new(None)),
+            },
+            socket,
             is_running: Arc::new(Mutex::new(false)),
             frame_buffer: Arc::new(Mutex::new(HashMap::new())),
+            stats: Arc::new(Mutex::new(ClientStats::default())),
+            history: Arc::new(Mutex::new(StatsHistory::new())),
+            stale_threshold_ms: Arc::new(AtomicU64::new(crate::settings::DEFAULT_STALE_THRESHOLD_MS)),
+            jitter_delay_ms: Arc::new(AtomicU64::new(0)),
+            stream_lost_ms: Arc::new(AtomicU64::new(crate::settings::DEFAULT_STREAM_LOST_MS)),
+            max_emit_fps: Arc::new(AtomicU32::new(crate::settings::DEFAULT_MAX_EMIT_FPS)),
+            memory_limit_bytes: Arc::new(AtomicU64::new(crate::settings::DEFAULT_MEMORY_LIMIT_MB * 1024 * 1024)),
+            decode_validation: Arc::new(AtomicBool::new(true)),
+            validation_budget_ms: Arc::new(AtomicU64::new(crate::settings::DEFAULT_VALIDATION_BUDGET_MS)),
+            cipher: Arc::new(Mutex::new(None)),
+            pin: Arc::new(Mutex::new(None)),
+            server_addr: Arc::new(Mutex::new(None)),
+            ports,
+            join: Arc::new(Mutex::new(None)),
+            tcp_fallback: Arc::new(AtomicBool::new(true)),
+            quic: Arc::new(AtomicBool::new(false)),
+            group,
+            interface,
+            recording: Arc::new(Mutex::new(None)),
+            displays: Arc::new(Mutex::new(Vec::new())),
+            stream_id: Mutex::new(None),
         })
     }
     
-    pub fn start_receiving(&self, app: AppHandle) -> Result<(), String> {
+    pub fn start_receiving(&self, output: FrameOutput) -> Result<(), String> {
         *self.is_running.lock().unwrap() = true;
         let socket = self.socket.clone();
         let is_running = self.is_running.clone();
         let frame_buffer = self.frame_buffer.clone();
+        let stats = self.stats.clone();
+        let history = self.history.clone();
+        let stale_threshold_ms = self.stale_threshold_ms.clone();
+        let jitter_delay_ms = self.jitter_delay_ms.clone();
+        let stream_lost_ms = self.stream_lost_ms.clone();
+        let max_emit_fps = self.max_emit_fps.clone();
+        let server_addr = self.server_addr.clone();
+        let control = self.control.clone();
+        let control_addr = self.control.addr.clone();
+        let ports = self.ports;
+        let memory_limit_bytes = self.memory_limit_bytes.clone();
+        let decode_validation = self.decode_validation.clone();
+        let validation_budget_ms = self.validation_budget_ms.clone();
+        let cipher_slot = self.cipher.clone();
+        let pin = self.pin.clone();
+        let join = self.join.clone();
+        let tcp_fallback = self.tcp_fallback.clone();
+        let quic = self.quic.clone();
+        let mut layers = LayerSubscription::new(self.group, self.interface);
+        let recording = self.recording.clone();
+        let displays = self.displays.clone();
+        let stream_id = *self.stream_id.lock().unwrap();
         
         std::thread::spawn(move || {
             let mut buf = vec![0u8; 65535];
             let mut frames_received = 0u64;
+            let mut stream_decoder: Option<VideoDecoder> = None;
+            let mut stale_watch = StaleWatch::new();
+            let mut loss_watch = LossWatch::new();
+            let mut black_frames = BlackFrameDetector::new();
+            let mut validator = FrameValidator::new(
+                decode_validation.load(Ordering::Relaxed),
+                validation_budget_ms.load(Ordering::Relaxed),
+            );
+            let mut sync = SyncTracker::new();
+            let mut jitter = JitterBuffer::new();
+            let mut emit_limiter = EmitLimiter::new();
+            let mut reports = ReportBuilder::new();
+            let mut memory_guard = MemoryGuard::new();
+            let mut done_frames = DoneFrames::new();
+            let mut last_decrypt_warning: Option<Instant> = None;
+            let mut last_sender: Option<std::net::SocketAddr> = None;
+            let mut audio_player: Option<AudioPlayer> = None;
+            let mut audio_unavailable = false;
+            let mut epoch: Option<u16> = None;
+            let mut key_fetcher = KeyFetcher::new();
             let mut last_log_time = std::time::Instant::now();
+            let mut unicast: Option<Unicast> = None;
+            let mut last_packet_at = Instant::now();
+            let mut last_unicast_attempt: Option<Instant> = None;
+            let mut layer_selector: Option<LayerSelector> = None;
+            let mut display_frames = DisplayFrames::new();
+            let mut cursor_sequence: Option<u32> = None;
+            let mut ignored_streams: Vec<u32> = Vec::new();
+            let mut ignored_version: Option<u8> = None;
+            let mut session: Option<SessionMessage> = None;
+            // Completed frames that may be shown, see jitter_buffer.rs
+            let mut ready: Vec<(u8, u32)> = Vec::new();
             
             while *is_running.lock().unwrap() {
-                match socket.recv_from(&mut buf) {
-                    Ok((size, _)) => {
-                        if size < 12 { 
-                            eprintln!("Received packet too small: {} bytes", size);
-                            continue; 
+                // Held frames go once they waited, whether or not anything else arrives
+                jitter.set_delay(jitter_delay_ms.load(Ordering::Relaxed));
+                let now = Instant::now();
+                ready.extend(jitter.release(now));
+                if !ready.is_empty() {
+                    let mut buffer = frame_buffer.lock().unwrap();
+                    for frame_key in ready.drain(..) {
+                        let Some((chunks, _, frame_header)) = buffer.get(&frame_key) else {
+                            continue;
+                        };
+                        let frame_header = *frame_header;
+                        let frame_id = frame_key.1;
+                        let received_chunks = chunks.iter().filter(|c| !c.is_empty()).count();
+                        let total_chunks = chunks.len();
+                        let completion_ratio = received_chunks as f32 / total_chunks as f32;
+                        let is_complete = completion_ratio >= 1.0;
+                        
+                        // A layer we're switching to is shown from its first full frame on
+                        if frame_header.layer != layers.active() {
+                            let switched = is_complete
+                                && frame_header.frame_type == FrameType::Key
+                                && layers.on_full_frame(&socket, frame_header.layer);
+                            if !switched {
+                                done_frames.finish(&mut buffer, frame_key);
+                                stats.lock().unwrap().incomplete_frames = buffer.len();
+                                continue;
+                            }
+                            eprintln!("📶 Showing simulcast layer {}", frame_header.layer);
+                            sync = SyncTracker::new();
+                            jitter.reset();
+                            stats.lock().unwrap().layer = frame_header.layer;
                         }
                         
-                        let frame_id = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
-                        let chunk_idx = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
-                        let total_chunks = u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]);
-                        let chunk_data = buf[12..size].to_vec();
+                        // Deltas on top of a missing frame would corrupt the picture
+                        if !sync.accept(&frame_header) {
+                            sync.request_keyframe(&control);
+                            stats.lock().unwrap().out_of_sync_frames += 1;
+                            done_frames.finish(&mut buffer, frame_key);
+                            stats.lock().unwrap().incomplete_frames = buffer.len();
+                            continue;
+                        }
                         
-                        let mut buffer = frame_buffer.lock().unwrap();
+                        // Chunk 0 leads with the frame's checksum (see packet.rs)
+                        let has_checksum = !chunks[0].is_empty();
+                        // For incomplete frames, try to salvage what we can
+                        let complete_frame: Vec<u8> = if !is_complete {
+                            // Log missing chunks
+                            let missing: Vec<usize> = chunks.iter()
+                                .enumerate()
+                                .filter(|(_, c)| c.is_empty())
+                                .map(|(i, _)| i)
+                                .collect();
+                            eprintln!(
+                                "⚠️  Frame {} partially complete ({:.1}%), missing {} chunks: {:?}",
+                                frame_id,
+                                completion_ratio * 100.0,
+                                total_chunks - received_chunks,
+                                missing
+                            );
+                            if let Some(heatmap) = stats.lock().unwrap().chunk_heatmap.as_mut() {
+                                heatmap.record(chunks);
+                            }
+                            
+                            // Concatenate only non-empty chunks (skip missing ones)
+                            chunks.iter()
+                                .filter(|c| !c.is_empty())
+                                .flatten()
+                                .copied()
+                                .collect()
+                        } else {
+                            chunks.concat()
+                        };
                         
-                        // Clean up old incomplete frames
-                        let now = std::time::Instant::now();
-                        let old_count = buffer.len();
-                        buffer.retain(|id, (_, timestamp)| {
-                            let is_fresh = now.duration_since(*timestamp).as_millis() < FRAME_TIMEOUT_MS as u128;
-                            if !is_fresh {
-                                eprintln!("Discarding incomplete frame {} (timeout)", id);
+                        // Chunks of two frames that share an ID, after wraparound or a restart;
+                        // partial frames can't be checked, only JPEG shows them below
+                        let complete_frame = match packet::frame_payload(&complete_frame, is_complete, has_checksum) {
+                            Some(payload) => payload.to_vec(),
+                            None => {
+                                eprintln!("❌ Frame {} doesn't match its checksum, dropped", frame_id);
+                                stats.lock().unwrap().invalid_frames += 1;
+                                sync.lost();
+                                sync.request_keyframe(&control);
+                                done_frames.finish(&mut buffer, frame_key);
+                                stats.lock().unwrap().incomplete_frames = buffer.len();
+                                continue;
+                            }
+                        };
+                        
+                        // Tile deltas are composited by the frontend onto its last frame
+                        if tile_delta::is_tile_payload(&complete_frame) {
+                            if !is_complete {
+                                // Missing tiles would shift every tile after the gap
+                                stats.lock().unwrap().invalid_frames += 1;
+                            } else {
+                                match tile_delta::parse(&complete_frame) {
+                                    Ok(update) => {
+                                        record_shown(&recording, |r| r.record_tiles(&update, &frame_header));
+                                        output.emit_tiles(&complete_frame, &mut emit_limiter);
+                                        frames_received += 1;
+                                        stats.lock().unwrap().frames_received = frames_received;
+                                    }
+                                    Err(e) => {
+                                        stats.lock().unwrap().invalid_frames += 1;
+                                        eprintln!("❌ Invalid tile frame {}: {}", frame_id, e);
+                                    }
+                                }
+                            }
+                        } else if lossless::is_lossless_payload(&complete_frame) {
+                            // A gap in the compressed stream loses the whole frame
+                            if !is_complete {
+                                stats.lock().unwrap().invalid_frames += 1;
+                            } else {
+                                match lossless::decode_to_png(&complete_frame) {
+                                    Ok(png) => {
+                                        output.emit_frame(&png, &mut emit_limiter);
+                                        frames_received += 1;
+                                        stats.lock().unwrap().frames_received = frames_received;
+                                    }
+                                    Err(e) => {
+                                        stats.lock().unwrap().invalid_frames += 1;
+                                        eprintln!("❌ Invalid lossless frame {}: {}", frame_id, e);
+                                    }
+                                }
+                            }
+                        } else if video_decoder::is_webp_payload(&complete_frame) || lossless::is_png_payload(&complete_frame) {
+                            // WebP and PNG both lose everything after a gap
+                            if !is_complete {
+                                stats.lock().unwrap().invalid_frames += 1;
+                            } else {
+                                output.emit_frame(&complete_frame, &mut emit_limiter);
+                                frames_received += 1;
+                                stats.lock().unwrap().frames_received = frames_received;
+                            }
+                        } else if video_decoder::is_video_payload(&complete_frame) {
+                            if !stream_decoder.as_ref().is_some_and(|d| d.handles(&complete_frame)) {
+                                stream_decoder = VideoDecoder::for_payload(&complete_frame)
+                                    .map_err(|e| eprintln!("❌ Cannot decode video frame {}: {}", frame_id, e))
+                                    .ok();
+                            }
+                            
+                            // Partial access units corrupt the reference chain, skip until complete
+                            match stream_decoder.as_mut().filter(|_| is_complete) {
+                                Some(decoder) => {
+                                    let start = Instant::now();
+                                    let decoded = decoder.decode_to_jpeg(&complete_frame, DECODED_JPEG_QUALITY);
+                                    validator.record_decode(start.elapsed());
+                                    stats.lock().unwrap().decode = validator.stats().clone();
+                                    match decoded {
+                                        Ok(Some(jpeg)) if black_frames.should_suppress(&jpeg) => {
+                                            stats.lock().unwrap().black_frames += 1;
+                                        }
+                                        Ok(Some(jpeg)) => {
+                                            record_shown(&recording, |r| r.record_jpeg(&jpeg, &frame_header));
+                                            output.emit_frame(&jpeg, &mut emit_limiter);
+                                            frames_received += 1;
+                                            stats.lock().unwrap().frames_received = frames_received;
+                                        }
+                                        Ok(None) => {} // Decoder waiting for a keyframe
+                                        Err(e) => {
+                                            stats.lock().unwrap().invalid_frames += 1;
+                                            eprintln!("❌ Video frame {}: {}", frame_id, e);
+                                        }
+                                    }
+                                }
+                                None => stats.lock().unwrap().invalid_frames += 1,
+                            }
+                        } else if complete_frame.len() >= 100 {
+                            // Validate frame is not empty and looks like valid JPEG
+                            // Check JPEG magic bytes
+                            let has_jpeg_start = complete_frame.starts_with(&[0xFF, 0xD8]);
+                            let has_jpeg_end = complete_frame.ends_with(&[0xFF, 0xD9]);
+                            
+                            // For partial frames, we might not have the end marker
+                            if has_jpeg_start && (has_jpeg_end || completion_ratio < 1.0) {
+                                validator.configure(
+                                    decode_validation.load(Ordering::Relaxed),
+                                    validation_budget_ms.load(Ordering::Relaxed),
+                                );
+                                let checked = validator.check(&complete_frame);
+                                stats.lock().unwrap().decode = validator.stats().clone();
+                                match checked {
+                                    Err(e) => {
+                                        stats.lock().unwrap().invalid_frames += 1;
+                                        eprintln!("❌ Corrupt JPEG frame {}: {}", frame_id, e);
+                                    }
+                                    // Only decoded frames can be checked for black
+                                    Ok(Some(image)) if black_frames.should_suppress_image(&image) => {
+                                        stats.lock().unwrap().black_frames += 1;
+                                        eprintln!("⚫ Dropped black frame {}, keeping last good frame", frame_id);
+                                    }
+                                    Ok(_) => {
+                                        record_shown(&recording, |r| r.record_jpeg(&complete_frame, &frame_header));
+                                        output.emit_frame(&complete_frame, &mut emit_limiter);
+                                        frames_received += 1;
+                                        stats.lock().unwrap().frames_received = frames_received;
+                                    }
+                                }
+                            } else {
+                                stats.lock().unwrap().invalid_frames += 1;
+                                eprintln!(
+                                    "❌ Invalid JPEG frame {} (size: {}, start: {}, end: {})", 
+                                    frame_id,
+                                    complete_frame.len(),
+                                    has_jpeg_start,
+                                    has_jpeg_end
+                                );
+                            }
+                        } else {
+                            stats.lock().unwrap().invalid_frames += 1;
+                            eprintln!(
+                                "❌ Frame {} too small: {} bytes (min 100)", 
+                                frame_id,
+                                complete_frame.len()
+                            );
+                        }
+                        
+                        done_frames.finish(&mut buffer, frame_key);
+                        stats.lock().unwrap().incomplete_frames = buffer.len();
+                        
+                        // The UI hears about it every 5 seconds
+                        if now.duration_since(last_log_time).as_secs() >= 5 {
+                            let current = stats.lock().unwrap().clone();
+                            output.emit_stats(history.lock().unwrap().report(current));
+                            last_log_time = now;
+                        }
+                    }
+                }
+                
+                stale_watch.update(frames_received, stale_threshold_ms.load(Ordering::Relaxed), &output);
+                match loss_watch.update(last_packet_at, frames_received, stream_lost_ms.load(Ordering::Relaxed)) {
+                    Some(LossChange::Lost(age_ms)) => {
+                        eprintln!("📡 Nothing received for {}ms, stream lost; still listening", age_ms);
+                        output.emit_stream_lost(age_ms);
+                        // Half frames from before can't be finished, and whoever sends next is the server
+                        frame_buffer.lock().unwrap().clear();
+                        jitter.reset();
+                        last_sender = None;
+                    }
+                    Some(LossChange::Resumed(lost_for_ms)) => {
+                        eprintln!("📡 Stream back after {}ms", lost_for_ms);
+                        output.emit_stream_resumed(lost_for_ms);
+                    }
+                    None => {}
+                }
+                // Switches drop memberships nobody renewed, e.g. after the querier went away
+                if loss_watch.rejoin_due() && unicast.is_none() {
+                    if let Err(e) = layers.rejoin(&socket) {
+                        eprintln!("⚠️  {}", e);
+                    }
+                }
+                emit_limiter.max_fps = max_emit_fps.load(Ordering::Relaxed);
+                output.flush_frame(&mut emit_limiter);
+                {
+                    let mut stats = stats.lock().unwrap();
+                    stats.render_drops = emit_limiter.render_drops;
+                    stats.render_ms = output.render_ms();
+                }
+                history.lock().unwrap().record(&stats.lock().unwrap());
+                if let Some(report) = reports.send_if_due(&control) {
+                    // Only multicast has layers to choose from
+                    let next = layer_selector.as_mut()
+                        .filter(|_| unicast.is_none())
+                        .and_then(|selector| selector.on_report(report.loss_rate()));
+                    if let Some(layer) = next {
+                        eprintln!("📶 Loss {:.1}%, switching to simulcast layer {}", report.loss_rate() * 100.0, layer);
+                        match layers.switch_to(&socket, layer) {
+                            // The main stream may be waiting on its next keyframe
+                            Ok(()) if layer == 0 => sync.request_keyframe(&control),
+                            Ok(()) => {}
+                            Err(e) => eprintln!("⚠️  {}", e),
+                        }
+                    }
+                }
+                if let Some(join) = join.lock().unwrap().as_mut() {
+                    join.send_if_due(&socket);
+                }
+                
+                // QUIC when asked for, TCP when nothing arrived over multicast for a while.
+                // Approval-only servers unicast already, there's nothing to switch to.
+                let attempt_due = last_unicast_attempt
+                    .is_none_or(|t| t.elapsed() >= Duration::from_millis(tcp_transport::FALLBACK_AFTER_MS));
+                if unicast.is_none() && attempt_due && join.lock().unwrap().is_none() {
+                    let want_quic = quic.load(Ordering::Relaxed);
+                    let want_tcp = tcp_fallback.load(Ordering::Relaxed)
+                        && last_packet_at.elapsed() >= Duration::from_millis(tcp_transport::FALLBACK_AFTER_MS);
+                    if want_quic || want_tcp {
+                        last_unicast_attempt = Some(Instant::now());
+                        match Unicast::connect(want_quic, want_tcp, ports) {
+                            Ok(connection) => {
+                                eprintln!("🔌 Streaming over {:?} from {}", connection.transport(), connection.server());
+                                if let Unicast::Quic(receiver) = &connection {
+                                    *control.quic.lock().unwrap() = Some(receiver.control());
+                                }
+                                stats.lock().unwrap().transport = connection.transport();
+                                // The server only sends the main stream directly
+                                if let Err(e) = layers.switch_to(&socket, 0) {
+                                    eprintln!("⚠️  {}", e);
+                                }
+                                unicast = Some(connection);
                             }
-                            is_fresh
-                        });
+                            Err(e) => eprintln!("⚠️  Staying on multicast: {}", e),
+                        }
+                    }
+                }
+                
+                let received = match unicast.as_mut() {
+                    Some(connection) => connection.recv_from(&mut buf),
+                    None => socket.recv_from(&mut buf),
+                };
+                match received {
+                    Ok((size, sender)) => {
+                        last_packet_at = Instant::now();
+                        stats.lock().unwrap().bytes_received += size as u64;
+                        let cipher = cipher_slot.lock().unwrap().clone();
+                        let mut decrypt_failed = |e: String| {
+                            stats.lock().unwrap().decrypt_failures += 1;
+                            let due = match last_decrypt_warning {
+                                Some(t) => t.elapsed().as_millis() as u64 >= DECRYPT_WARNING_INTERVAL_MS,
+                                None => true,
+                            };
+                            if due {
+                                eprintln!("🔒 {}", e);
+                                last_decrypt_warning = Some(Instant::now());
+                            }
+                        };
                         
-                        // Log cleanup if frames were removed
-                        if buffer.len() < old_count {
-                            println!("Cleaned up {} incomplete frames", old_count - buffer.len());
+                        if let Some(status) = JoinStatus::parse(&buf[..size]) {
+                            let changed = join.lock().unwrap().as_mut().is_some_and(|j| j.update(sender, status));
+                            if changed {
+                                eprintln!("🙋 Join request: {:?}", status);
+                                output.emit_join_status(status);
+                            }
+                            continue;
                         }
                         
-                        let (chunks, timestamp) = buffer.entry(frame_id).or_insert_with(|| {
-                            (vec![Vec::new(); total_chunks as usize], now)
-                        });
+                        if let Some(info) = discovery::parse_answer(&buf[..size]) {
+                            if last_sender.is_some_and(|server| server.ip() == sender.ip()) {
+                                let learned = SocketAddr::new(sender.ip(), info.ports.control);
+                                if *control_addr.lock().unwrap() != Some(learned) {
+                                    eprintln!("🔌 Server control port {}", info.ports.control);
+                                }
+                                *control_addr.lock().unwrap() = Some(learned);
+                                if layer_selector.as_ref().map_or(0, LayerSelector::layers) != info.simulcast.len() {
+                                    if !info.simulcast.is_empty() {
+                                        eprintln!("📶 Server offers {} simulcast layers", info.simulcast.len());
+                                    }
+                                    layer_selector = (!info.simulcast.is_empty()).then(|| LayerSelector::new(info.simulcast.len()));
+                                }
+                            }
+                            continue;
+                        }
                         
-                        // Update timestamp on each chunk received
-                        *timestamp = now;
+                        // The server is there, whether or not it has frames to send
+                        if let Some(heartbeat) = Heartbeat::parse(&buf[..size]) {
+                            if stream_id.is_none_or(|id| id == heartbeat.stream_id) {
+                                stats.lock().unwrap().last_heartbeat_ms = Some(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64);
+                            }
+                            continue;
+                        }
                         
-                        // Store chunk if index is valid
-                        if (chunk_idx as usize) < chunks.len() {
-                            chunks[chunk_idx as usize] = chunk_data;
-                        } else {
-                            eprintln!("Invalid chunk index: {} >= {}", chunk_idx, chunks.len());
+                        // Announced again every few seconds, only changes count
+                        if let Some(message) = SessionMessage::parse(&buf[..size]) {
+                            if stream_id.is_some_and(|id| id != message.stream_id()) || session == Some(message) {
+                                continue;
+                            }
+                            let changed = session.is_some();
+                            session = Some(message);
+                            match message {
+                                SessionMessage::StreamStarted { width, height, codec, .. } => {
+                                    eprintln!("🎬 Stream {}x{} ({:?})", width, height, codec);
+                                    // Half frames of another size or codec won't be any use
+                                    if changed {
+                                        frame_buffer.lock().unwrap().clear();
+                                        jitter.reset();
+                                    }
+                                    output.emit_stream_started(width, height, codec);
+                                }
+                                SessionMessage::StreamStopped { .. } => {
+                                    eprintln!("🛑 Server stopped streaming; still listening");
+                                    frame_buffer.lock().unwrap().clear();
+                                    jitter.reset();
+                                    last_sender = None;
+                                    loss_watch.stopped(frames_received);
+                                    output.emit_stream_stopped();
+                                }
+                            }
+                            continue;
+                        }
+                        
+                        // Audio and pointer carry no stream ID, they count when their sender's frames do
+                        if stream_id.is_some() && last_sender != Some(sender) && packet::is_side_channel(&buf[..size]) {
+                            continue;
+                        }
+                        
+                        if let Some((audio_header, payload)) = AudioHeader::parse(&buf[..size]) {
+                            if audio_header.channel != packet::AUDIO_CHANNEL {
+                                continue;
+                            }
+                            let payload = match open_payload(cipher.as_deref(), audio_header.encrypted, &buf[..packet::AUDIO_HEADER_SIZE], payload) {
+                                Ok(payload) => payload,
+                                Err(e) => {
+                                    decrypt_failed(e);
+                                    fetch_stream_key(&mut key_fetcher, &pin, &cipher_slot, sender.ip(), false);
+                                    continue;
+                                }
+                            };
+                            if audio_player.is_none() && !audio_unavailable {
+                                match AudioPlayer::new() {
+                                    Ok(player) => audio_player = Some(player),
+                                    Err(e) => {
+                                        eprintln!("❌ Cannot play stream audio: {}", e);
+                                        audio_unavailable = true;
+                                    }
+                                }
+                            }
+                            if let Some(player) = audio_player.as_ref() {
+                                player.play(audio_header, &payload);
+                            }
+                            stats.lock().unwrap().audio_packets += 1;
+                            continue;
+                        }
+                        
+                        if let Some((cursor_header, body)) = CursorHeader::parse(&buf[..size]) {
+                            // Updates overtaken by a newer one are stale already
+                            if cursor_sequence.is_some_and(|last| cursor_header.sequence.wrapping_sub(last) as i32 <= 0) {
+                                continue;
+                            }
+                            let body = match open_payload(cipher.as_deref(), cursor_header.encrypted, &buf[..packet::CURSOR_HEADER_SIZE], body) {
+                                Ok(body) => body,
+                                Err(e) => {
+                                    decrypt_failed(e);
+                                    fetch_stream_key(&mut key_fetcher, &pin, &cipher_slot, sender.ip(), false);
+                                    continue;
+                                }
+                            };
+                            if let Some(state) = CursorState::parse(&body) {
+                                cursor_sequence = Some(cursor_header.sequence);
+                                output.emit_cursor(state);
+                            }
                             continue;
                         }
                         
+                        let Some(header) = PacketHeader::parse(&buf[..size]) else {
+                            match packet::protocol_version(&buf[..size]) {
+                                Some(version) if version != packet::PROTOCOL_VERSION => {
+                                    if ignored_version != Some(version) {
+                                        eprintln!("⚠️  {} speaks protocol v{}, this viewer v{}; update the older side", sender, version, packet::PROTOCOL_VERSION);
+                                        ignored_version = Some(version);
+                                    }
+                                }
+                                _ => eprintln!("Received invalid packet: {} bytes", size),
+                            }
+                            continue;
+                        };
+                        // Garbled on the way, lost as far as the frame is concerned
+                        let Some(datagram) = packet::verify_checksum(&buf[..size]) else {
+                            stats.lock().unwrap().corrupt_chunks += 1;
+                            continue;
+                        };
+                        let size = datagram.len();
+                        // Another server sending to the same group
+                        if stream_id.is_some_and(|id| id != header.stream_id) {
+                            if !ignored_streams.contains(&header.stream_id) {
+                                eprintln!("📡 Ignoring stream {:08x} from {}", header.stream_id, sender);
+                                ignored_streams.push(header.stream_id);
+                            }
+                            continue;
+                        }
+                        // Left over from a layer we no longer follow
+                        if !layers.wants(header.layer) {
+                            continue;
+                        }
+                        if last_sender != Some(sender) {
+                            *server_addr.lock().unwrap() = Some(sender);
+                            // Our own control port until the server tells us its own
+                            *control_addr.lock().unwrap() = Some(SocketAddr::new(sender.ip(), ports.control));
+                            if let Err(e) = discovery::send_probe(&socket, SocketAddr::new(sender.ip(), ports.discovery)) {
+                                eprintln!("⚠️  {}", e);
+                            }
+                        }
+                        last_sender = Some(sender);
+                        
+                        // A new server session (restart, resume from sleep) starts
+                        // its frame IDs over, so nothing buffered is still valid
+                        if epoch != Some(header.epoch) {
+                            if epoch.is_some() {
+                                eprintln!("🔄 New stream session {:04x}, resynchronizing", header.epoch);
+                            }
+                            epoch = Some(header.epoch);
+                            frame_buffer.lock().unwrap().clear();
+                            done_frames.clear();
+                            sync = SyncTracker::new();
+                            jitter.reset();
+                            reports = ReportBuilder::new();
+                            stream_decoder = None;
+                            display_frames.reset();
+                            cursor_sequence = None;
+                            black_frames = BlackFrameDetector::new();
+                            // The new session announces its own layers, start over on the main stream
+                            layer_selector = None;
+                            if let Err(e) = layers.switch_to(&socket, 0) {
+                                eprintln!("⚠️  {}", e);
+                            }
+                            // Every session has its own key, the old one can't open it
+                            if header.encrypted && pin.lock().unwrap().is_some() {
+                                *cipher_slot.lock().unwrap() = None;
+                                fetch_stream_key(&mut key_fetcher, &pin, &cipher_slot, sender.ip(), true);
+                            }
+                        }
+                        
+                        let frame_id = header.frame_id;
+                        let frame_key = (header.layer, frame_id);
+                        let total_chunks = header.total_chunks;
+                        if total_chunks == 0 || total_chunks > MAX_CHUNKS_PER_FRAME {
+                            eprintln!("Invalid chunk count {} for frame {}", total_chunks, frame_id);
+                            continue;
+                        }
+                        let chunk_data = match open_payload(cipher.as_deref(), header.encrypted, &buf[..packet::HEADER_SIZE], &buf[packet::HEADER_SIZE..size]) {
+                            Ok(data) => data,
+                            Err(e) => {
+                                decrypt_failed(e);
+                                fetch_stream_key(&mut key_fetcher, &pin, &cipher_slot, sender.ip(), false);
+                                continue;
+                            }
+                        };
+                        
+                        // The server's other displays, shown on their own when subscribed
+                        if header.stream != 0 {
+                            if !displays.lock().unwrap().contains(&header.stream) {
+                                continue;
+                            }
+                            match display_frames.on_chunk(&header, chunk_data) {
+                                Received::Pending => {}
+                                Received::Frame { stream, jpeg } => output.emit_display_frame(stream, &jpeg),
+                                Received::OutOfSync => sync.request_keyframe(&control),
+                                Received::Invalid(e) => {
+                                    stats.lock().unwrap().invalid_frames += 1;
+                                    eprintln!("❌ Stream {} frame {}: {}", header.stream, frame_id, e);
+                                }
+                            }
+                            continue;
+                        }
+                        
+                        let mut buffer = frame_buffer.lock().unwrap();
+                        
+                        // Clean up old incomplete frames
+                        let now = std::time::Instant::now();
+                        expire_frames(&mut buffer, now, &mut reports, &stats);
+                        
+                        let chunk_len = chunk_data.len();
+                        if !store_chunk(&mut buffer, &done_frames, header, chunk_data, now) {
+                            continue;
+                        }
+                        
+                        let limit_bytes = memory_limit_bytes.load(Ordering::Relaxed) as usize;
+                        if let Some(pressure) = memory_guard.on_chunk(chunk_len, &mut buffer, limit_bytes) {
+                            eprintln!("⚠️  Client buffers at {} KB (limit {} KB), evicted {} incomplete frames",
+                                     pressure.buffered_bytes / 1024, limit_bytes / 1024, pressure.evicted_frames);
+                            {
+                                let mut stats = stats.lock().unwrap();
+                                stats.memory_evictions += pressure.evicted_frames as u64;
+                                stats.buffered_bytes = buffered_bytes(&buffer);
+                            }
+                            output.emit_memory_pressure(pressure);
+                        }
+                        
+                        // The frame itself may have been evicted
+                        let Some((chunks, _, frame_header)) = buffer.get(&frame_key) else {
+                            continue;
+                        };
+                        let frame_header = *frame_header;
+                        
                         // Check frame completion status
                         let received_chunks = chunks.iter().filter(|c| !c.is_empty()).count();
                         let total_chunks = chunks.len();
@@ -110,72 +1615,16 @@ impl UdpClient {
                         let is_complete = completion_ratio >= 1.0;
                         let should_process = is_complete || (completion_ratio >= MIN_FRAME_COMPLETION && completion_ratio > 0.98);
                         
-                        if should_process {
-                            // For incomplete frames, try to salvage what we can
-                            let complete_frame: Vec<u8> = if !is_complete {
-                                // Log missing chunks
-                                let missing: Vec<usize> = chunks.iter()
-                                    .enumerate()
-                                    .filter(|(_, c)| c.is_empty())
-                                    .map(|(i, _)| i)
-                                    .collect();
-                                eprintln!(
-                                    "⚠️  Frame {} partially complete ({:.1}%), missing {} chunks: {:?}",
-                                    frame_id,
-                                    completion_ratio * 100.0,
-                                    total_chunks - received_chunks,
-                                    missing
-                                );
-                                
-                                // Concatenate only non-empty chunks (skip missing ones)
-                                chunks.iter()
-                                    .filter(|c| !c.is_empty())
-                                    .flatten()
-                                    .copied()
-                                    .collect()
-                            } else {
-                                chunks.concat()
-                            };
-                            
-                            // Validate frame is not empty and looks like valid JPEG
-                            if complete_frame.len() >= 100 {
-                                // Check JPEG magic bytes
-                                let has_jpeg_start = complete_frame.starts_with(&[0xFF, 0xD8]);
-                                let has_jpeg_end = complete_frame.ends_with(&[0xFF, 0xD9]);
-                                
-                                // For partial frames, we might not have the end marker
-                                if has_jpeg_start && (has_jpeg_end || completion_ratio < 1.0) {
-                                    let base64_image = base64::Engine::encode(
-                                        &base64::engine::general_purpose::STANDARD, 
-                                        &complete_frame
-                                    );
-                                    
-                                    let _ = app.emit("screen-frame", base64_image);
-                                    frames_received += 1;
-                                } else {
-                                    eprintln!(
-                                        "❌ Invalid JPEG frame {} (size: {}, start: {}, end: {})", 
-                                        frame_id,
-                                        complete_frame.len(),
-                                        has_jpeg_start,
-                                        has_jpeg_end
-                                    );
-                                }
-                            } else {
-                                eprintln!(
-                                    "❌ Frame {} too small: {} bytes (min 100)", 
-                                    frame_id,
-                                    complete_frame.len()
-                                );
-                            }
-                            
-                            buffer.remove(&frame_id);
-                            
-                            // Log stats every 5 seconds
-                            if now.duration_since(last_log_time).as_secs() >= 5 {
-                                println!("📊 Stats: {} frames received, {} incomplete frames in buffer", 
-                                         frames_received, buffer.len());
-                                last_log_time = now;
+                        if should_process && !jitter.holds(&frame_key) {
+                            reports.frame_completed(received_chunks, total_chunks, frame_header.send_ts);
+                            // Frames of a layer we're switching to are only looked at once
+                            if frame_header.layer != layers.active() {
+                                ready.push(frame_key);
+                            } else if !jitter.push(frame_header.sequence, frame_key, now) {
+                                // A later frame is on screen already
+                                stats.lock().unwrap().late_frames += 1;
+                                done_frames.finish(&mut buffer, frame_key);
+                                stats.lock().unwrap().incomplete_frames = buffer.len();
                             }
                         }
                     }
@@ -184,6 +1633,13 @@ impl UdpClient {
                         if e.kind() != std::io::ErrorKind::WouldBlock && 
                            e.kind() != std::io::ErrorKind::TimedOut {
                             eprintln!("Receive error: {}", e);
+                            // Lost the server, see whether multicast works again
+                            if let Some(connection) = unicast.take() {
+                                eprintln!("🔌 {:?} stream ended, listening for multicast again", connection.transport());
+                                *control.quic.lock().unwrap() = None;
+                                stats.lock().unwrap().transport = Transport::Multicast;
+                                last_packet_at = Instant::now();
+                            }
                         }
                         continue;
                     }
@@ -196,5 +1652,277 @@ impl UdpClient {
     
     pub fn stop(&self) {
         *self.is_running.lock().unwrap() = false;
+        if self.recording.lock().unwrap().is_some() {
+            if let Err(e) = self.stop_recording() {
+                eprintln!("❌ {}", e);
+            }
+        }
+    }
+    
+    /// Keep every frame shown from now on, as `path` with an .avi extension plus a .csv of arrival times.
+    /// Sealed with `passphrase` if given, or with the stream key when `encrypt` (see recording_crypto.rs).
+    pub fn start_recording(&self, path: &std::path::Path, encrypt: bool, passphrase: Option<&str>) -> Result<std::path::PathBuf, String> {
+        let key = recording_crypto::choose_key(
+            encrypt,
+            passphrase,
+            self.cipher.lock().unwrap().clone(),
+            self.pin.lock().unwrap().is_some(),
+        )?;
+        let mut recording = self.recording.lock().unwrap();
+        if recording.is_some() {
+            return Err("Already recording".to_string());
+        }
+        let started = ViewerRecording::start(path, DECODED_JPEG_QUALITY, key)?;
+        let path = started.path().to_path_buf();
+        *recording = Some(started);
+        Ok(path)
+    }
+    
+    pub fn stop_recording(&self) -> Result<RecordingSummary, String> {
+        let recording = self.recording.lock().unwrap().take().ok_or("Not recording")?;
+        recording.finish()
+    }
+    
+    pub fn is_running(&self) -> bool {
+        *self.is_running.lock().unwrap()
+    }
+    
+    pub fn stats(&self) -> ClientStats {
+        self.stats.lock().unwrap().clone()
+    }
+    
+    /// Current stats with their 1s/10s/60s aggregates
+    pub fn stats_report(&self) -> ClientReport {
+        let current = self.stats();
+        self.history.lock().unwrap().report(current)
+    }
+    
+    /// Milliseconds without a new frame before `stream-stale` fires (0 disables)
+    pub fn set_stale_threshold(&self, threshold_ms: u64) {
+        self.stale_threshold_ms.store(threshold_ms, Ordering::Relaxed);
+    }
+    
+    pub fn set_stream_lost_after(&self, lost_after_ms: u64) {
+        self.stream_lost_ms.store(lost_after_ms, Ordering::Relaxed);
+    }
+    
+    pub fn set_max_emit_fps(&self, max_fps: u32) {
+        self.max_emit_fps.store(max_fps, Ordering::Relaxed);
+    }
+    
+    /// Ask the OS for a receive buffer of `kb`, 0 leaves it as it is. At 1080p
+    /// the usual default overflows with every big frame, losing chunks of it.
+    pub fn set_receive_buffer(&self, kb: usize) {
+        net_interfaces::set_socket_buffer(&self.socket, SocketBuffer::Receive, kb);
+    }
+    
+    /// Hold completed frames up to this long so they're shown in order (0 never waits)
+    pub fn set_jitter_delay(&self, delay_ms: u64) {
+        self.jitter_delay_ms.store(delay_ms, Ordering::Relaxed);
+    }
+    
+    /// Receive over QUIC from a server that offers it, instead of multicast
+    pub fn set_quic(&self, enabled: bool) {
+        self.quic.store(enabled, Ordering::Relaxed);
+    }
+    
+    /// Also show these of the server's other displays (stream IDs from its discovery answer)
+    pub fn set_displays(&self, streams: Vec<u8>) {
+        *self.displays.lock().unwrap() = streams;
+    }
+    
+    /// Only reassemble the server stream with this ID (see packet.rs), from the next start
+    pub fn set_stream_id(&self, stream_id: Option<u32>) {
+        *self.stream_id.lock().unwrap() = stream_id;
+    }
+    
+    /// Track which chunk indices go missing, see chunk_heatmap.rs; turning it off drops the data
+    pub fn set_chunk_diagnostics(&self, enabled: bool) {
+        let mut stats = self.stats.lock().unwrap();
+        if enabled != stats.chunk_heatmap.is_some() {
+            stats.chunk_heatmap = enabled.then(ChunkHeatmap::default);
+        }
+    }
+    
+    /// Switch to the server's TCP stream when no multicast arrives
+    pub fn set_tcp_fallback(&self, enabled: bool) {
+        self.tcp_fallback.store(enabled, Ordering::Relaxed);
+    }
+    
+    /// Decode JPEG frames before showing them, falling back to marker checks when
+    /// decoding averages over `budget_ms` (0 never falls back); see frame_validation.rs
+    pub fn set_decode_validation(&self, enabled: bool, budget_ms: u64) {
+        self.decode_validation.store(enabled, Ordering::Relaxed);
+        self.validation_budget_ms.store(budget_ms, Ordering::Relaxed);
+    }
+    
+    /// Ceiling for buffered stream data in MB (0 disables the guard)
+    pub fn set_memory_limit(&self, limit_mb: u64) {
+        self.memory_limit_bytes.store(limit_mb * 1024 * 1024, Ordering::Relaxed);
+    }
+    
+    /// Passphrase for encrypted streams, `None` or empty to only accept plain ones
+    pub fn set_passphrase(&self, passphrase: Option<&str>) -> Result<(), String> {
+        let cipher = match passphrase {
+            Some(passphrase) if !passphrase.is_empty() => Some(Arc::new(StreamCipher::from_passphrase(passphrase)?)),
+            _ => None,
+        };
+        *self.cipher.lock().unwrap() = cipher;
+        Ok(())
+    }
+    
+    /// Pairing PIN for streams that hand out their key, `None` or empty to use the passphrase
+    pub fn set_pin(&self, pin: Option<&str>) -> Result<(), String> {
+        let pin = pin.filter(|p| !p.is_empty());
+        if let Some(pin) = pin {
+            crate::settings::validate_pin(pin)?;
+        }
+        *self.pin.lock().unwrap() = pin.map(str::to_string);
+        Ok(())
+    }
+    
+    /// Ask `server` to let us in (with the PIN set through `set_pin`), for
+    /// servers that only stream to viewers the host approved
+    pub fn request_access(&self, server: SocketAddr) -> Result<(), String> {
+        let pin = self.pin.lock().unwrap().clone()
+            .ok_or("Enter the session PIN to join this server")?;
+        *self.join.lock().unwrap() = Some(JoinRequester::new(server, &pin));
+        Ok(())
+    }
+    
+    /// Address the stream is coming from, once anything was received
+    pub fn server_addr(&self) -> Option<std::net::SocketAddr> {
+        *self.server_addr.lock().unwrap()
+    }
+    
+    /// Send a mouse/keyboard event to the server (it only acts on it if remote control is enabled)
+    pub fn send_input(&self, event: &InputEvent) -> Result<(), String> {
+        self.control.send(&event.serialize())
+            .map_err(|e| format!("Failed to send input: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access_control::Delivery;
+    use crate::bandwidth_limit::BandwidthLimit;
+    use crate::clock::StreamClock;
+    use crate::frame_pacer::AdaptiveFramePacer;
+    use crate::udp_server::UdpServer;
+
+    fn chunk_header(frame_id: u32, chunk_idx: u32, total_chunks: u32) -> PacketHeader {
+        PacketHeader {
+            frame_id,
+            chunk_idx,
+            total_chunks,
+            frame_type: FrameType::Key,
+            sequence: frame_id,
+            capture_ts: 0,
+            send_ts: 0,
+            epoch: 1,
+            encrypted: false,
+            layer: 0,
+            stream: 0,
+            stream_id: 0,
+        }
+    }
+
+    #[test]
+    fn test_redundant_chunks_dont_count_as_lost() {
+        let stats = Mutex::new(ClientStats::default());
+        let mut buffer = FrameBuffer::new();
+        let mut done = DoneFrames::new();
+        let mut reports = ReportBuilder::new();
+        let start = Instant::now();
+        let timed_out = start + Duration::from_millis(FRAME_TIMEOUT_MS);
+
+        for idx in 0..3 {
+            assert!(store_chunk(&mut buffer, &done, chunk_header(7, idx, 3), vec![idx as u8], start));
+        }
+        reports.frame_completed(3, 3, 0);
+        done.finish(&mut buffer, (0, 7));
+
+        // The copies of its first and last chunk, right behind the frame
+        assert!(!store_chunk(&mut buffer, &done, chunk_header(7, 0, 3), vec![0], start));
+        assert!(!store_chunk(&mut buffer, &done, chunk_header(7, 2, 3), vec![2], start));
+        expire_frames(&mut buffer, timed_out, &mut reports, &stats);
+        assert_eq!(reports.report.frames_received, 1);
+        assert_eq!(reports.report.frames_lost, 0);
+        assert_eq!(reports.report.chunks_lost, 0);
+
+        // A frame that did lose chunks still counts
+        assert!(store_chunk(&mut buffer, &done, chunk_header(8, 0, 3), vec![0], start));
+        expire_frames(&mut buffer, timed_out, &mut reports, &stats);
+        assert!(buffer.is_empty());
+        assert_eq!(reports.report.frames_lost, 1);
+        assert_eq!(reports.report.chunks_lost, 2);
+    }
+    #[test]
+    fn test_heatmap_records_only_missing_frames() {
+        let stats = Mutex::new(ClientStats { chunk_heatmap: Some(ChunkHeatmap::default()), ..ClientStats::default() });
+        let mut buffer = FrameBuffer::new();
+        let mut done = DoneFrames::new();
+        let mut reports = ReportBuilder::new();
+        let start = Instant::now();
+        let timed_out = start + Duration::from_millis(FRAME_TIMEOUT_MS);
+
+        for idx in 0..4 {
+            store_chunk(&mut buffer, &done, chunk_header(3, idx, 4), vec![idx as u8], start);
+        }
+        done.finish(&mut buffer, (0, 3));
+        store_chunk(&mut buffer, &done, chunk_header(3, 3, 4), vec![3], start);
+        // Chunk 2 of the next frame never makes it
+        for idx in [0, 1, 3] {
+            store_chunk(&mut buffer, &done, chunk_header(4, idx, 4), vec![idx as u8], start);
+        }
+        expire_frames(&mut buffer, timed_out, &mut reports, &stats);
+
+        let heatmap = stats.lock().unwrap().chunk_heatmap.clone().unwrap();
+        assert_eq!(heatmap.frames_with_loss, 1);
+        assert_eq!(heatmap.missing_chunks, 1);
+        assert_eq!(heatmap.by_index[2], 1);
+    }
+
+    #[tokio::test]
+    async fn test_clean_stream_keeps_quality_and_fps() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let delivery = Delivery::Multicast { group: receiver.local_addr().unwrap(), tcp: None, quic: None };
+        for frame_id in 0..5 {
+            let data = vec![frame_id as u8; 10 * 1000];
+            UdpServer::send_chunked(&socket, &delivery, &data, 1000, chunk_header(frame_id, 0, 0), &StreamClock::new(), None, &BandwidthLimit::new(0.0), Duration::ZERO)
+                .await
+                .unwrap();
+        }
+
+        // Reassembled the way the receive loop does, redundant chunks and all
+        let stats = Mutex::new(ClientStats::default());
+        let mut buffer = FrameBuffer::new();
+        let mut done = DoneFrames::new();
+        let mut reports = ReportBuilder::new();
+        let mut buf = [0u8; 2048];
+        while let Ok(size) = receiver.recv(&mut buf) {
+            let header = PacketHeader::parse(&buf[..size]).unwrap();
+            let datagram = packet::verify_checksum(&buf[..size]).unwrap();
+            let frame_key = (header.layer, header.frame_id);
+            if !store_chunk(&mut buffer, &done, header, datagram[packet::HEADER_SIZE..].to_vec(), Instant::now()) {
+                continue;
+            }
+            let chunks = &buffer[&frame_key].0;
+            if chunks.iter().all(|c| !c.is_empty()) {
+                reports.frame_completed(chunks.len(), chunks.len(), header.send_ts);
+                done.finish(&mut buffer, frame_key);
+            }
+        }
+        expire_frames(&mut buffer, Instant::now() + Duration::from_millis(FRAME_TIMEOUT_MS), &mut reports, &stats);
+        assert_eq!(reports.report.frames_received, 5);
+        assert_eq!(reports.report.loss_rate(), 0.0);
+
+        let mut pacer = AdaptiveFramePacer::new(30, 10, 30);
+        let (quality, fps) = (pacer.quality(), pacer.target_fps());
+        pacer.adjust_for_packet_loss(reports.report.loss_rate());
+        assert_eq!((pacer.quality(), pacer.target_fps()), (quality, fps));
     }
 }
@@ -1,140 +1,932 @@
-use std::net::UdpSocket;
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
 use std::time::{Duration, Instant};
-use crate::frame_pacer::AdaptiveFramePacer;
+use socket2::{Domain, Protocol, Socket, Type};
+use crate::access_control::{AccessControl, Delivery, JoinRequest};
+use crate::audio_capture::AudioCapture;
+use crate::bandwidth_limit::BandwidthLimit;
+use crate::cursor_stream::CursorSender;
+use crate::discovery::{DiscoveryResponder, ServerInfo};
+use crate::display_streams::DisplayStream;
+use crate::encode_pool::{Done, EncodePool, Job};
+use crate::clock::StreamClock;
+use crate::frame_pacer::{self, AdaptiveFramePacer, FrameTimings};
+use crate::frame_source::FrameSource;
+use crate::peers::PeerTable;
+use crate::performance;
+use crate::hw_encoder::{EncoderType, VideoEncoder};
+use crate::key_exchange::{KeyExchangeServer, KEY_EXCHANGE_PORT};
+use crate::key_overlay::KeyOverlay;
+use crate::net_interfaces::{self, IpVersion, SocketBuffer};
+use crate::packet::{self, FrameType, PacketHeader, ReceiverReport, SessionMessage};
+use crate::preview::{EncoderTap, PreviewTap};
+use crate::remote_input::{InputEvent, InputInjector};
+use crate::stream_crypto::{self, StreamCipher};
+use crate::screen_capture::RawFrame;
+use crate::settings::{StreamSettings, TimestampSource};
+use crate::simulcast::LayerEncoder;
+use crate::webrtc_output::WebRtcOutput;
+use crate::rtp_output::RtpOutput;
+use crate::mjpeg_server::MjpegServer;
+use crate::recording::{Recording, RecordingSummary};
+use crate::recording_crypto;
+use crate::stats::{ServerReport, ServerStats, StatsHistory};
+
+/// Hears the stream's stats every few seconds
+type StatsListener = Box<dyn Fn(ServerReport) + Send + Sync>;
+use crate::tcp_transport::TcpFanout;
+use crate::quic_transport::{Feedback, QuicServer, QUIC_CHUNK_SIZE};
 
-const MULTICAST_ADDR: &str = "239.0.0.1:9999";
-const CHUNK_SIZE: usize = 8192; // Smaller chunks for UDP safety (8KB)
 const JPEG_QUALITY: u8 = 60; // Lower quality for smaller size
 const REDUNDANT_PACKETS: bool = true; // Send critical packets twice for reliability
-const TARGET_FPS: u32 = 30; // Target 30 FPS
-const MIN_FPS: u32 = 10;    // Minimum 10 FPS
-const MAX_FPS: u32 = 60;    // Maximum 60 FPS
+const CAPTURE_QUEUE: usize = 1; // Raw frames waiting for the encoder, capture drops frames past that
+const ENCODED_QUEUE: usize = 2; // Encoded frames waiting for the sender
+const SEND_SPREAD: f32 = 0.8; // Share of the frame period a frame's chunks are spread over, the rest is slack
+const CAPTURE_RETRY_INTERVAL_MS: u64 = 10; // Re-poll a source with no new frame until the next frame is due
+const FREEZE_REFRESH_MS: u64 = 1000; // Resend the frozen frame so viewers don't go stale
+const REPORT_EXPIRY_SECS: u64 = 5; // Receivers that stopped reporting no longer count
+const LOSS_ADJUST_INTERVAL_MS: u64 = 1000; // Match the receiver report cadence
+const FEEDBACK_TIMEOUT_MS: u64 = 200; // Lets the feedback listener notice stop() promptly
+const HEARTBEAT_INTERVAL_MS: u64 = 1000; // Viewers call a stream lost after a few seconds of silence
+const ANNOUNCE_INTERVAL_MS: u64 = 5000; // Viewers who join late learn the frame size and codec
+
+/// Latest report from each receiver, keyed by its address
+type ReceiverReports = HashMap<SocketAddr, (ReceiverReport, Instant)>;
+
+/// A raw frame on its way from the capture stage to the encoder
+struct Captured {
+    frame: Arc<RawFrame>,
+    /// Freeze refresh, viewers get it as a keyframe
+    keyframe: bool,
+    /// Encode all of it, dirty rects don't cover what changed since the last frame encoded
+    whole: bool,
+    capture_time: Duration,
+}
+
+/// An encoded frame on its way to the sender
+struct Encoded {
+    data: Vec<u8>,
+    frame: Arc<RawFrame>,
+    frame_time: Instant,
+    codec: EncoderType,
+    capture_time: Duration,
+    encode_time: Duration,
+}
 
 pub struct UdpServer {
+    /// Sends the stream
     socket: Arc<UdpSocket>,
+    /// Bound to the control port, receives everything viewers send back
+    control: Arc<UdpSocket>,
     is_running: Arc<Mutex<bool>>,
+    settings: StreamSettings,
+    stats: Arc<Mutex<ServerStats>>,
+    history: Arc<Mutex<StatsHistory<ServerStats>>>,
+    stats_listener: Arc<Mutex<Option<StatsListener>>>,
+    frozen: Arc<AtomicBool>,
+    /// JPEG quality the running stream should switch to, 0 when there is nothing to change
+    requested_quality: Arc<AtomicU8>,
+    /// Frame rate the running stream should switch to, 0 when there is nothing to change
+    requested_fps: Arc<AtomicU32>,
+    /// Caps what the video sends, see bandwidth_limit.rs
+    bandwidth: Arc<BandwidthLimit>,
+    /// Random per session, so receivers can tell a restarted stream apart
+    epoch: u16,
+    /// In every packet, so viewers can pick this stream out of several on one group
+    stream_id: u32,
+    /// Viewers may inject mouse/keyboard input, off unless explicitly enabled
+    remote_control: Arc<AtomicBool>,
+    /// Seals every payload when a passphrase or pairing PIN is set
+    cipher: Option<Arc<StreamCipher>>,
+    /// Hands this session's key to viewers that know the pairing PIN
+    key_exchange: Option<KeyExchangeServer>,
+    /// Set when viewers need the host's approval, packets are then unicast to them
+    access: Option<Arc<AccessControl>>,
+    delivery: Delivery,
+    /// Answers viewers looking for streams on the LAN
+    discovery: Mutex<Option<DiscoveryResponder>>,
+    /// Messages from viewers, whether they came to the control port or over QUIC
+    feedback: Feedback,
+    feedback_rx: Mutex<Option<mpsc::Receiver<(Vec<u8>, SocketAddr)>>>,
+    /// Lower renditions, handed to the stream when it starts
+    layers: Mutex<Vec<LayerEncoder>>,
+    /// RTP/JPEG for standard players, handed to the stream when it starts
+    rtp: Mutex<Option<RtpOutput>>,
+    /// Known once the stream starts, decides the recording container
+    encoder_type: Mutex<Option<EncoderType>>,
+    recording: Arc<Mutex<Option<Recording>>>,
+    /// Time base of video and audio, shared by every display
+    clock: StreamClock,
+    /// Other displays sent next to the main one, with their keyframe request flags
+    displays: Mutex<Vec<DisplayStream>>,
+    display_keyframes: Arc<Mutex<Vec<Arc<AtomicBool>>>>,
+    /// The pointer of the main display, when it isn't drawn into the frames
+    cursor: Mutex<Option<CursorSender>>,
+    /// Viewers that talk back, for the host to see who is watching
+    peers: Arc<PeerTable>,
 }
 
 impl UdpServer {
-    pub fn new() -> Result<Self, String> {
-        let socket = UdpSocket::bind("0.0.0.0:0")
+    pub fn new(mut settings: StreamSettings) -> Result<Self, String> {
+        settings.validate()?;
+        
+        let socket = UdpSocket::bind((settings.ip_version.unspecified(), 0))
             .map_err(|e| format!("Failed to bind socket: {}", e))?;
+        net_interfaces::set_socket_buffer(&socket, SocketBuffer::Send, settings.send_buffer_kb);
+        
+        // Everything viewers send back arrives on its own port, or over QUIC
+        let control = Self::bind_control(settings.ip_version, settings.ports.control)?;
+        let (feedback, feedback_rx) = mpsc::channel();
+        
+        let mut interface_index = 0;
+        match settings.ip_version {
+            IpVersion::V4 => {
+                socket.set_multicast_ttl_v4(32)
+                    .map_err(|e| format!("Failed to set TTL: {}", e))?;
+                
+                if let Some(interface) = settings.multicast_interface {
+                    let interface = net_interfaces::multicast_interface(Some(interface))?;
+                    socket.set_multicast_if_v4(&interface)
+                        .map_err(|e| format!("Failed to use interface {} for multicast: {}", interface, e))?;
+                    eprintln!("🌐 Multicasting on interface {}", interface);
+                }
+            }
+            IpVersion::V6 => {
+                // Link-local group, the default hop limit of 1 is all it needs
+                interface_index = net_interfaces::multicast_interface_index(settings.multicast_interface)?;
+                if interface_index != 0 {
+                    socket2::SockRef::from(&socket).set_multicast_if_v6(interface_index)
+                        .map_err(|e| format!("Failed to use interface {} for multicast: {}", interface_index, e))?;
+                }
+                eprintln!("🌐 Multicasting over IPv6 to {}", settings.ip_version.stream_addr(settings.ports.data, interface_index));
+            }
+        }
+        
+        let mut key_exchange = None;
+        let cipher = match (settings.pairing_pin.as_deref(), settings.encryption_passphrase.as_deref()) {
+            // A new random key every session, so restarts also rotate the key
+            (Some(pin), _) => {
+                let key = StreamCipher::generate_key();
+                key_exchange = Some(KeyExchangeServer::start(KEY_EXCHANGE_PORT, pin, key)?);
+                eprintln!("🔒 Stream encryption enabled (AES-256-GCM, key via PIN pairing)");
+                Some(Arc::new(StreamCipher::from_key(&key)))
+            }
+            (None, Some(passphrase)) if !passphrase.is_empty() => {
+                eprintln!("🔒 Stream encryption enabled (AES-256-GCM)");
+                Some(Arc::new(StreamCipher::from_passphrase(passphrase)?))
+            }
+            _ => None,
+        };
+        
+        // One PIN for viewers when pairing is on as well
+        let access = settings.require_approval
+            .then(|| Arc::new(AccessControl::new(settings.pairing_pin.clone())));
+        let delivery = match access.as_ref() {
+            Some(access) => {
+                eprintln!("🙋 Viewers need approval, session PIN {}", access.pin());
+                Delivery::Approved(access.clone())
+            }
+            None => {
+                let tcp = if settings.tcp_fallback {
+                    match TcpFanout::start(settings.ip_version, settings.ports.data) {
+                        Ok(tcp) => Some(Arc::new(tcp)),
+                        Err(e) => {
+                            // Multicast viewers are unaffected
+                            eprintln!("⚠️  TCP fallback unavailable: {}", e);
+                            None
+                        }
+                    }
+                } else {
+                    None
+                };
+                let quic = if settings.quic {
+                    match QuicServer::start(settings.ip_version, settings.ports.quic, feedback.clone()) {
+                        Ok(quic) => Some(Arc::new(quic)),
+                        Err(e) => {
+                            eprintln!("⚠️  QUIC unavailable: {}", e);
+                            None
+                        }
+                    }
+                } else {
+                    None
+                };
+                // Every packet has to fit a QUIC datagram
+                if quic.is_some() && settings.chunk_size > QUIC_CHUNK_SIZE {
+                    eprintln!("🔐 Chunk size {} → {} for QUIC viewers", settings.chunk_size, QUIC_CHUNK_SIZE);
+                    settings.chunk_size = QUIC_CHUNK_SIZE;
+                }
+                Delivery::Multicast {
+                    group: settings.ip_version.stream_addr(settings.ports.data, interface_index),
+                    tcp,
+                    quic,
+                }
+            }
+        };
+        
+        // Approved viewers are unicast the main stream, there are no groups to choose from
+        if access.is_some() && !settings.simulcast.is_empty() {
+            eprintln!("⚠️  Simulcast needs multicast, sending the main stream only");
+            settings.simulcast.clear();
+        }
+        let layers = settings.simulcast.iter().enumerate()
+            .map(|(i, layer)| LayerEncoder::new(i as u8 + 1, layer.clone(), settings.ip_version, settings.ports.data, interface_index))
+            .collect::<Vec<_>>();
+        for (layer, settings) in layers.iter().zip(&settings.simulcast) {
+            eprintln!("📶 Simulcast layer {}: up to {}px wide, quality {}", layer.layer(), settings.max_width, settings.jpeg_quality);
+        }
+        let rtp = settings.rtp.enabled
+            .then(|| RtpOutput::new(settings.ip_version, settings.rtp.port, interface_index, settings.rtp.jpeg_quality));
         
-        socket.set_multicast_ttl_v4(32)
-            .map_err(|e| format!("Failed to set TTL: {}", e))?;
+        // Lets the control reader notice stop() promptly
+        control.set_read_timeout(Some(Duration::from_millis(200)))
+            .map_err(|e| format!("Failed to set timeout: {}", e))?;
+        
+        // Configured ones stay the same across restarts, viewers stay subscribed
+        let stream_id = match settings.stream_id {
+            0 => rand::random::<u32>().max(1),
+            id => id,
+        };
+        let bandwidth = Arc::new(BandwidthLimit::new(settings.max_bitrate_mbps));
         
         Ok(Self {
             socket: Arc::new(socket),
+            control: Arc::new(control),
             is_running: Arc::new(Mutex::new(false)),
+            settings,
+            stats: Arc::new(Mutex::new(ServerStats::default())),
+            history: Arc::new(Mutex::new(StatsHistory::new())),
+            stats_listener: Arc::new(Mutex::new(None)),
+            frozen: Arc::new(AtomicBool::new(false)),
+            requested_quality: Arc::new(AtomicU8::new(0)),
+            requested_fps: Arc::new(AtomicU32::new(0)),
+            bandwidth,
+            epoch: rand::random(),
+            stream_id,
+            remote_control: Arc::new(AtomicBool::new(false)),
+            cipher,
+            key_exchange,
+            access,
+            delivery,
+            discovery: Mutex::new(None),
+            feedback,
+            feedback_rx: Mutex::new(Some(feedback_rx)),
+            layers: Mutex::new(layers),
+            rtp: Mutex::new(rtp),
+            encoder_type: Mutex::new(None),
+            recording: Arc::new(Mutex::new(None)),
+            clock: StreamClock::new(),
+            displays: Mutex::new(Vec::new()),
+            display_keyframes: Arc::new(Mutex::new(Vec::new())),
+            cursor: Mutex::new(None),
+            peers: Arc::new(PeerTable::default()),
         })
     }
     
-    pub async fn start_streaming<F>(&self, capture_fn: F) -> Result<(), String>
+    // Reusable, so a restart can bind again while the previous session's
+    // listener is still winding down
+    fn bind_control(ip_version: IpVersion, port: u16) -> Result<UdpSocket, String> {
+        let addr = SocketAddr::new(ip_version.unspecified(), port);
+        let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))
+            .map_err(|e| format!("Failed to create control socket: {}", e))?;
+        socket.set_reuse_address(true)
+            .map_err(|e| format!("Failed to set reuse address: {}", e))?;
+        if ip_version == IpVersion::V6 {
+            socket.set_only_v6(true)
+                .map_err(|e| format!("Failed to set IPv6 only: {}", e))?;
+        }
+        socket.bind(&addr.into())
+            .map_err(|e| format!("Failed to bind control port {}: {}", port, e))?;
+        Ok(socket.into())
+    }
+    
+    /// Pull raw frames from `source` and send them through `encoder`.
+    /// `preview` gets a look at every raw frame before it is encoded,
+    /// `encoder_tap` at everything the encoder produces.
+    pub async fn start_streaming<S>(
+        &self,
+        mut source: S,
+        encoder: Box<dyn VideoEncoder>,
+        mut preview: Option<PreviewTap>,
+        mut encoder_tap: Option<EncoderTap>,
+    ) -> Result<(), String>
     where
-        F: Fn() -> Result<Vec<u8>, String> + Send + 'static,
+        S: FrameSource + 'static,
     {
+        let feedback = self.feedback_rx.lock().unwrap().take()
+            .ok_or("Stream already started")?;
         *self.is_running.lock().unwrap() = true;
         let socket = self.socket.clone();
         let is_running = self.is_running.clone();
+        let settings = self.settings.clone();
+        let stats = self.stats.clone();
+        let history = self.history.clone();
+        let stats_listener = self.stats_listener.clone();
+        let frozen = self.frozen.clone();
+        let requested_quality = self.requested_quality.clone();
+        let requested_fps = self.requested_fps.clone();
+        let bandwidth = self.bandwidth.clone();
+        let epoch = self.epoch;
+        let stream_id = self.stream_id;
+        let cipher = self.cipher.clone();
+        let delivery = self.delivery.clone();
+        let mut layers = std::mem::take(&mut *self.layers.lock().unwrap());
+        let mut rtp = self.rtp.lock().unwrap().take();
+        let recording = self.recording.clone();
+        *self.encoder_type.lock().unwrap() = Some(encoder.encoder_type());
+        let keyframe_requested = Arc::new(AtomicBool::new(false));
+        Self::spawn_control_reader(self.control.clone(), is_running.clone(), self.feedback.clone());
+        Self::spawn_feedback_listener(
+            self.control.clone(),
+            feedback,
+            is_running.clone(),
+            keyframe_requested.clone(),
+            self.display_keyframes.clone(),
+            stats.clone(),
+            self.history.clone(),
+            self.remote_control.clone(),
+            self.access.clone(),
+            self.peers.clone(),
+        );
+        Self::spawn_heartbeat(socket.clone(), delivery.clone(), is_running.clone(), epoch, stream_id, self.clock);
         
-        tokio::spawn(async move {
-            let mut frame_id = 0u32;
-            let mut consecutive_errors = 0u32;
-            const MAX_CONSECUTIVE_ERRORS: u32 = 10;
-            
-            // Use adaptive frame pacer for consistent FPS
-            let mut pacer = AdaptiveFramePacer::new(TARGET_FPS, MIN_FPS, MAX_FPS);
-            let mut last_stats_log = Instant::now();
-            let mut frames_sent = 0u32;
-            
-            eprintln!("🎬 Starting stream with adaptive FPS (target: {}, range: {}-{})", 
-                     TARGET_FPS, MIN_FPS, MAX_FPS);
-            
-            while *is_running.lock().unwrap() {
-                // Frame pacing - only capture when it's time
-                if !pacer.should_capture() {
-                    // Sleep briefly to avoid busy loop
-                    tokio::time::sleep(Duration::from_millis(1)).await;
-                    continue;
+        // Audio and video share one time base
+        let clock = self.clock;
+        let audio = if settings.audio {
+            match AudioCapture::start(socket.clone(), delivery.clone(), clock, cipher.clone()) {
+                Ok(audio) => Some(audio),
+                Err(e) => {
+                    // Video still works without sound
+                    eprintln!("❌ Audio capture unavailable: {}", e);
+                    None
                 }
+            }
+        } else {
+            None
+        };
+        
+        let webrtc = if !settings.webrtc.enabled {
+            None
+        } else if !WebRtcOutput::is_h264(encoder.encoder_type()) {
+            eprintln!("⚠️  WebRTC needs an H.264 encoder, not {:?}; browsers can't watch", encoder.encoder_type());
+            None
+        } else {
+            match WebRtcOutput::start(settings.webrtc.port, settings.target_fps, keyframe_requested.clone()) {
+                Ok(webrtc) => Some(webrtc),
+                Err(e) => {
+                    eprintln!("❌ WebRTC output unavailable: {}", e);
+                    None
+                }
+            }
+        };
+        
+        let mjpeg = if settings.mjpeg.enabled {
+            match MjpegServer::start(settings.mjpeg.port, settings.mjpeg.jpeg_quality) {
+                Ok(mjpeg) => Some(mjpeg),
+                Err(e) => {
+                    eprintln!("❌ MJPEG server unavailable: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        
+        let key_overlay = settings.key_overlay.enabled.then(|| KeyOverlay::start(&settings.key_overlay, settings.overlay_locale.as_deref()));
+        
+        let performance = settings.performance.clone();
+        const MAX_CONSECUTIVE_ERRORS: u32 = 10;
+        
+        // Three stages, each working on a different frame: capture on a thread of
+        // its own, encode on another, send on the async side. The capture stage
+        // paces and adjusts, the others follow what it hands them.
+        let pacer = Arc::new(Mutex::new(AdaptiveFramePacer::new(settings.target_fps, settings.min_fps, settings.max_fps)));
+        pacer.lock().unwrap().set_max_quality(settings.jpeg_quality);
+        let (captured_tx, captured_rx) = mpsc::sync_channel::<Captured>(CAPTURE_QUEUE);
+        let (encoded_tx, mut encoded_rx) = tokio::sync::mpsc::channel::<Encoded>(ENCODED_QUEUE);
+        
+        // A stage that keeps failing ends the stream, and viewers hear so
+        let give_up = {
+            let (socket, delivery, is_running) = (socket.clone(), delivery.clone(), is_running.clone());
+            move || {
+                *is_running.lock().unwrap() = false;
+                Self::announce(&socket, &delivery, SessionMessage::StreamStopped { epoch, stream_id });
+            }
+        };
+        
+        eprintln!("🆕 Stream session epoch {:04x}", epoch);
+        eprintln!("🎬 Starting stream with adaptive FPS (target: {}, range: {}-{}), encoder: {:?}", 
+                 settings.target_fps, settings.min_fps, settings.max_fps, encoder.encoder_type());
+        
+        let capture = {
+            let is_running = is_running.clone();
+            let pacer = pacer.clone();
+            let stats = stats.clone();
+            let performance = performance.clone();
+            let give_up = give_up.clone();
+            move || {
+                if performance.is_enabled() {
+                    performance::apply_to_current_thread(&performance);
+                }
+                let mut last_frame: Option<Arc<RawFrame>> = None;
+                let mut last_freeze_refresh = Instant::now();
+                let mut last_loss_adjust = Instant::now();
+                let mut consecutive_errors = 0u32;
+                // A frame the encoder had no room for took its dirty rects along
+                let mut skipped = false;
                 
-                let capture_start = Instant::now();
-                
-                match capture_fn() {
-                    Ok(data) => {
-                        // Reset error counter on success
-                        consecutive_errors = 0;
+                while *is_running.lock().unwrap() {
+                    // Frame pacing - only capture when it's time
+                    if !pacer.lock().unwrap().should_capture() {
+                        // Sleep briefly to avoid busy loop
+                        std::thread::sleep(Duration::from_millis(1));
+                        continue;
+                    }
+                    
+                    let capture_start = Instant::now();
+                    let spf = {
+                        let mut pacer = pacer.lock().unwrap();
                         
-                        // Skip empty frames (black screens)
-                        if data.is_empty() || data.len() < 100 {
-                            eprintln!("⚠️  Captured frame too small ({} bytes), skipping", data.len());
-                            continue;
+                        // A new ceiling from set_quality, loss and slow frames still lower it from there
+                        let quality = requested_quality.swap(0, Ordering::Relaxed);
+                        if quality != 0 {
+                            pacer.set_max_quality(quality);
+                            eprintln!("🎚️  JPEG quality {}", pacer.quality());
+                        }
+                        let fps = requested_fps.swap(0, Ordering::Relaxed);
+                        if fps != 0 {
+                            pacer.set_target_fps(fps);
+                            eprintln!("🎚️  Target {} FPS", pacer.target_fps());
                         }
                         
-                        // Compress more if still too large
-                        let compressed = if data.len() > 500_000 {
-                            match Self::recompress_jpeg(&data, JPEG_QUALITY) {
-                                Ok(d) => d,
-                                Err(e) => {
-                                    eprintln!("❌ Recompress error: {}", e);
-                                    continue;
+                        // Degrade FPS and quality under loss reported by receivers, recover when it clears
+                        if last_loss_adjust.elapsed() >= Duration::from_millis(LOSS_ADJUST_INTERVAL_MS) {
+                            last_loss_adjust = Instant::now();
+                            let (receivers, loss_rate) = {
+                                let stats = stats.lock().unwrap();
+                                (stats.reporting_receivers, stats.worst_loss_rate)
+                            };
+                            if receivers > 0 {
+                                pacer.adjust_for_packet_loss(loss_rate);
+                            }
+                        }
+                        pacer.spf()
+                    };
+                    
+                    let captured = if frozen.load(Ordering::Relaxed) {
+                        // Hold the last frame, resent now and then as a keyframe
+                        match &last_frame {
+                            Some(frame) if last_freeze_refresh.elapsed() >= Duration::from_millis(FREEZE_REFRESH_MS) => {
+                                last_freeze_refresh = Instant::now();
+                                Ok(Some((frame.clone(), true)))
+                            }
+                            _ => Ok(None),
+                        }
+                    } else {
+                        // Poll until the source has a frame or the next frame is due
+                        let deadline = capture_start + spf;
+                        let captured = loop {
+                            match source.next_frame() {
+                                Ok(None) if Instant::now() + Duration::from_millis(CAPTURE_RETRY_INTERVAL_MS) < deadline => {
+                                    std::thread::sleep(Duration::from_millis(CAPTURE_RETRY_INTERVAL_MS));
                                 }
+                                other => break other,
                             }
-                        } else {
-                            data
                         };
-                        
-                        let send_start = Instant::now();
-                        
-                        if let Err(e) = Self::send_chunked(&socket, &compressed, frame_id).await {
-                            eprintln!("❌ Send error: {}", e);
-                        } else {
-                            // Only increment frame ID on successful send
-                            frame_id = frame_id.wrapping_add(1);
-                            frames_sent += 1;
-                            
-                            let total_time = capture_start.elapsed().as_millis() as u64;
-                            
-                            // Adjust FPS based on performance
-                            pacer.adjust_for_slow_frame(total_time);
+                        // Only live frames, nothing typed while frozen reaches viewers
+                        let captured = match (captured, key_overlay.as_ref()) {
+                            (Ok(Some(mut frame)), Some(overlay)) => {
+                                overlay.draw(&mut frame);
+                                // The keys come and go outside the capturer's dirty rects
+                                frame.dirty = None;
+                                Ok(Some(frame))
+                            }
+                            (captured, _) => captured,
+                        };
+                        captured.map(|frame| frame.map(|frame| (Arc::new(frame), false)))
+                    };
+                    
+                    match captured {
+                        Ok(Some((frame, refresh))) => {
+                            consecutive_errors = 0;
+                            if let Some(preview) = preview.as_mut() {
+                                preview.offer(&frame);
+                            }
+                            last_frame = Some(frame.clone());
+                            let captured = Captured {
+                                frame,
+                                keyframe: refresh,
+                                whole: refresh || skipped,
+                                capture_time: capture_start.elapsed(),
+                            };
+                            match captured_tx.try_send(captured) {
+                                Ok(()) => skipped = false,
+                                // The encoder is still on the one before, a newer frame comes soon
+                                Err(mpsc::TrySendError::Full(_)) => skipped = true,
+                                Err(mpsc::TrySendError::Disconnected(_)) => break,
+                            }
+                        }
+                        Ok(None) => {
+                            // No new frame this slot (static screen), this is normal
+                        }
+                        Err(e) => {
+                            consecutive_errors += 1;
+                            eprintln!("❌ Capture error ({}/{}): {}", consecutive_errors, MAX_CONSECUTIVE_ERRORS, e);
                             
-                            // Log stats every 5 seconds
-                            if last_stats_log.elapsed().as_secs() >= 5 {
-                                let actual_fps = pacer.actual_fps();
-                                let target_fps = pacer.target_fps();
-                                eprintln!("📊 Server Stats (5s): {} frames sent, {:.1} FPS (target: {}), avg time: {}ms",
-                                         frames_sent, actual_fps, target_fps, total_time);
-                                frames_sent = 0;
-                                last_stats_log = Instant::now();
+                            // Stop streaming if too many consecutive errors
+                            if consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
+                                eprintln!("🛑 Too many consecutive capture errors. Stopping stream.");
+                                give_up();
+                                break;
                             }
                         }
                     }
-                    Err(e) if e == "WouldBlock" => {
-                        // No new frame from DXGI, this is normal - just skip
-                        // Don't increment error counter for WouldBlock
-                    }
+                }
+                // The key overlay stops with the capture
+            }
+        };
+        
+        // JPEG frames are encoded several at a time, encoders that build each
+        // frame on the last (H.264, tile deltas) on one worker; see encode_pool.rs
+        let codec = encoder.encoder_type();
+        let timestamp_source = settings.timestamp_source;
+        let finish = move |done: Done<Duration>| -> Result<Option<Encoded>, String> {
+            let capture_time = done.tag;
+            let data = done.data?;
+            let frame_time = match timestamp_source {
+                TimestampSource::Capture => done.frame.captured_at,
+                TimestampSource::Encode => Instant::now(),
+            };
+            
+            // Encoder had nothing to send (unchanged tiles, buffering)
+            if data.is_empty() {
+                return Ok(None);
+            }
+            
+            // Compress more if still too large (tile deltas are already small JPEG tiles)
+            let data = if data.len() > 500_000 && data.starts_with(&[0xFF, 0xD8]) {
+                match Self::recompress_jpeg(&data, JPEG_QUALITY) {
+                    Ok(d) => d,
                     Err(e) => {
-                        consecutive_errors += 1;
-                        eprintln!("❌ Capture error ({}/{}): {}", consecutive_errors, MAX_CONSECUTIVE_ERRORS, e);
-                        
-                        // Stop streaming if too many consecutive errors
-                        if consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
-                            eprintln!("🛑 Too many consecutive capture errors. Stopping stream.");
-                            *is_running.lock().unwrap() = false;
-                            break;
+                        eprintln!("❌ Recompress error: {}", e);
+                        return Ok(None);
+                    }
+                }
+            } else {
+                data
+            };
+            
+            Ok(Some(Encoded {
+                data,
+                frame: done.frame,
+                frame_time,
+                codec,
+                capture_time,
+                encode_time: done.encode_time,
+            }))
+        };
+        let deliver = {
+            let mut consecutive_errors = 0u32;
+            move |finished: Result<Option<Encoded>, String>| match finished {
+                Ok(encoded) => {
+                    // Reset error counter on success
+                    consecutive_errors = 0;
+                    // Encoded frames all go, each one may be the next one's reference;
+                    // while the sender is behind, capture drops raw frames instead
+                    encoded.is_none_or(|encoded| encoded_tx.blocking_send(encoded).is_ok())
+                }
+                Err(e) => {
+                    consecutive_errors += 1;
+                    eprintln!("❌ Encode error ({}/{}): {}", consecutive_errors, MAX_CONSECUTIVE_ERRORS, e);
+                    if consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
+                        eprintln!("🛑 Too many consecutive encode errors. Stopping stream.");
+                        give_up();
+                        return false;
+                    }
+                    true
+                }
+            }
+        };
+        let mut pool = EncodePool::start(encoder, settings.encode_threads(), &performance, finish, deliver)?;
+        eprintln!("🧵 Encoding on {} worker(s)", pool.workers());
+        
+        let encode = {
+            let is_running = is_running.clone();
+            let pacer = pacer.clone();
+            let keyframe_requested = keyframe_requested.clone();
+            move || {
+                while *is_running.lock().unwrap() {
+                    let captured = match captured_rx.recv_timeout(Duration::from_millis(FEEDBACK_TIMEOUT_MS)) {
+                        Ok(captured) => captured,
+                        Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                    };
+                    let job = Job {
+                        // The pacer decides quality, each worker catches up with it
+                        quality: pacer.lock().unwrap().quality(),
+                        keyframe: keyframe_requested.swap(false, Ordering::Relaxed) || captured.keyframe,
+                        whole: captured.whole,
+                        frame: captured.frame,
+                        tag: captured.capture_time,
+                    };
+                    // Waits for a free worker, capture drops frames meanwhile
+                    if !pool.submit(job) {
+                        break;
+                    }
+                }
+            }
+        };
+        
+        let stream = async move {
+            // Audio, WebRTC and MJPEG stop when the stream does
+            let _audio = audio;
+            let mut frame_id = 0u32;
+            let mut sequence = 0u32;
+            let mut last_stats_log = Instant::now();
+            let mut announced: Option<(SessionMessage, Instant)> = None;
+            
+            while let Some(encoded) = encoded_rx.recv().await {
+                // Frames still queued when the stream stopped stay unsent
+                if !*is_running.lock().unwrap() {
+                    break;
+                }
+                let Encoded { data: compressed, frame, frame_time, codec, capture_time, encode_time } = encoded;
+                
+                if let Some(tap) = encoder_tap.as_mut() {
+                    tap.offer(&compressed);
+                }
+                if let Some(webrtc) = webrtc.as_ref() {
+                    webrtc.publish(&compressed);
+                }
+                if let Some(mjpeg) = mjpeg.as_ref() {
+                    mjpeg.publish(&compressed, &frame);
+                }
+                {
+                    let mut slot = recording.lock().unwrap();
+                    if let Some(active) = slot.as_mut() {
+                        if let Err(e) = active.write(&compressed, &frame, frame_time) {
+                            eprintln!("❌ Recording stopped: {}", e);
+                            if let Err(e) = slot.take().map(Recording::finish).transpose() {
+                                eprintln!("❌ {}", e);
+                            }
+                        } else if active.wants_keyframe() {
+                            keyframe_requested.store(true, Ordering::Relaxed);
+                        }
+                    }
+                }
+                
+                // Viewers hear of a new size or codec before its first frame arrives
+                let started = SessionMessage::StreamStarted {
+                    epoch,
+                    stream_id,
+                    width: frame.width as u16,
+                    height: frame.height as u16,
+                    codec,
+                };
+                let due = announced.is_none_or(|(sent, at)| {
+                    sent != started || at.elapsed() >= Duration::from_millis(ANNOUNCE_INTERVAL_MS)
+                });
+                if due {
+                    Self::announce(&socket, &delivery, started);
+                    announced = Some((started, Instant::now()));
+                }
+                
+                let send_start = Instant::now();
+                // Chunks go out over most of a frame period, layers get the rest
+                let spf = pacer.lock().unwrap().spf();
+                let spread_left = || send_spread(spf, send_start.elapsed());
+                
+                // Every encoded frame takes a sequence number, sent or not,
+                // so receivers can spot a missing reference frame
+                let frame_type = FrameType::of_payload(&compressed);
+                let frame_sequence = sequence;
+                sequence = sequence.wrapping_add(1);
+                
+                let header = PacketHeader {
+                    frame_id,
+                    chunk_idx: 0,
+                    total_chunks: 0,
+                    frame_type,
+                    sequence: frame_sequence,
+                    capture_ts: clock.timestamp(frame_time),
+                    send_ts: 0,
+                    epoch,
+                    encrypted: cipher.is_some(),
+                    layer: 0,
+                    stream: 0,
+                    stream_id,
+                };
+                
+                // Over the limit for longer than waiting is worth, the frame's
+                // sequence number tells viewers it's missing
+                if !bandwidth.admits(compressed.len(), Instant::now()) {
+                    stats.lock().unwrap().bandwidth_drops += 1;
+                    continue;
+                }
+                if let Err(e) = Self::send_chunked(&socket, &delivery, &compressed, settings.chunk_size, header, &clock, cipher.as_deref(), &bandwidth, spread_left()).await {
+                    eprintln!("❌ Send error: {}", e);
+                    continue;
+                }
+                // Only increment frame ID on successful send
+                frame_id = frame_id.wrapping_add(1);
+                
+                // The same frame for viewers on weaker links, part of this frame's send time
+                for layer in layers.iter_mut() {
+                    let jpeg = match layer.encode(&frame) {
+                        Ok(jpeg) => jpeg,
+                        Err(e) => {
+                            eprintln!("❌ Layer {} encode error: {}", layer.layer(), e);
+                            continue;
                         }
+                    };
+                    let header = layer.next_header(header);
+                    if let Err(e) = Self::send_chunked(&socket, layer.delivery(), &jpeg, settings.chunk_size, header, &clock, cipher.as_deref(), &bandwidth, spread_left()).await {
+                        eprintln!("❌ Layer {} send error: {}", layer.layer(), e);
                     }
                 }
+                if let Some(rtp) = rtp.as_mut() {
+                    if let Err(e) = rtp.send(&socket, &compressed, &frame) {
+                        eprintln!("❌ RTP send error: {}", e);
+                    }
+                }
+                
+                let timings = FrameTimings {
+                    capture: capture_time,
+                    encode: encode_time,
+                    send: send_start.elapsed(),
+                };
+                let total_time = timings.total().as_millis() as u64;
+                
+                // Slow frames cost FPS or quality, depending on where the time went;
+                // the encoder picks up a lower quality with its next frame
+                let loss_rate = stats.lock().unwrap().worst_loss_rate;
+                let (target_fps, actual_fps) = {
+                    let mut pacer = pacer.lock().unwrap();
+                    pacer.adjust_for_frame(timings, loss_rate);
+                    (pacer.target_fps(), pacer.actual_fps())
+                };
                 
-                // Sleep until next frame (handled by pacer)
-                // Small sleep to yield to other tasks
-                tokio::time::sleep(Duration::from_millis(1)).await;
+                {
+                    let mut stats = stats.lock().unwrap();
+                    stats.frames_sent += 1;
+                    stats.bytes_sent += compressed.len() as u64;
+                    stats.target_fps = target_fps;
+                    stats.last_frame_time_ms = total_time;
+                    let frame_ms = timings.total().as_secs_f32() * 1000.0;
+                    stats.avg_frame_time_ms += (frame_ms - stats.avg_frame_time_ms) / 16.0;
+                }
+                
+                // The UI hears about it every 5 seconds, see on_stats
+                if last_stats_log.elapsed().as_secs() >= 5 {
+                    let current = {
+                        let mut stats = stats.lock().unwrap();
+                        stats.actual_fps = actual_fps;
+                        stats.clone()
+                    };
+                    if let Some(notify) = stats_listener.lock().unwrap().as_ref() {
+                        notify(history.lock().unwrap().report(current));
+                    }
+                    last_stats_log = Instant::now();
+                }
             }
             
             eprintln!("🔴 Stream stopped");
-        });
+        };
+        
+        std::thread::Builder::new()
+            .name("stream-capture".to_string())
+            .spawn(capture)
+            .map_err(|e| format!("Failed to spawn capture thread: {}", e))?;
+        std::thread::Builder::new()
+            .name("stream-encoder".to_string())
+            .spawn(encode)
+            .map_err(|e| format!("Failed to spawn encoder thread: {}", e))?;
+        if performance.is_enabled() {
+            // A dedicated thread can be tuned without touching the shared tokio workers
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| format!("Failed to create pipeline runtime: {}", e))?;
+            std::thread::Builder::new()
+                .name("stream-pipeline".to_string())
+                .spawn(move || {
+                    performance::apply_to_current_thread(&performance);
+                    runtime.block_on(stream);
+                })
+                .map_err(|e| format!("Failed to spawn pipeline thread: {}", e))?;
+        } else {
+            tokio::spawn(stream);
+        }
+        
+        Ok(())
+    }
+    
+    /// Send another display next to the main one, as `display.stream`, until the
+    /// server stops. The main stream must be running. Each display gets a thread
+    /// of its own, capturers stay on the thread that opened them.
+    pub fn start_display_stream<S>(
+        &self,
+        display: DisplayStream,
+        mut source: S,
+        mut encoder: Box<dyn VideoEncoder>,
+    ) -> Result<(), String>
+    where
+        S: FrameSource + 'static,
+    {
+        if !self.is_running() {
+            return Err("Stream is not running".to_string());
+        }
+        let keyframe_requested = Arc::new(AtomicBool::new(false));
+        self.display_keyframes.lock().unwrap().push(keyframe_requested.clone());
+        let socket = self.socket.clone();
+        let is_running = self.is_running.clone();
+        let frozen = self.frozen.clone();
+        let epoch = self.epoch;
+        let stream_id = self.stream_id;
+        let cipher = self.cipher.clone();
+        let delivery = self.delivery.clone();
+        let bandwidth = self.bandwidth.clone();
+        let stats = self.stats.clone();
+        let clock = self.clock;
+        let chunk_size = self.settings.chunk_size;
+        let interval = Duration::from_secs_f64(1.0 / self.settings.target_fps.max(1) as f64);
+        let stream = display.stream;
+        eprintln!("🖥️  Display {} as stream {} ({}x{}), encoder: {:?}",
+                 display.display + 1, stream, display.width, display.height, encoder.encoder_type());
+        self.displays.lock().unwrap().push(display);
         
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| format!("Failed to create display runtime: {}", e))?;
+        std::thread::Builder::new()
+            .name(format!("display-stream-{}", stream))
+            .spawn(move || runtime.block_on(async move {
+                let mut frame_id = 0u32;
+                let mut sequence = 0u32;
+                let mut consecutive_errors = 0u32;
+                const MAX_CONSECUTIVE_ERRORS: u32 = 10;
+                
+                while *is_running.lock().unwrap() {
+                    let frame_start = Instant::now();
+                    // Viewers keep the last frame of every display while frozen
+                    let captured = if frozen.load(Ordering::Relaxed) { Ok(None) } else { source.next_frame() };
+                    match captured {
+                        Ok(Some(frame)) => {
+                            consecutive_errors = 0;
+                            if keyframe_requested.swap(false, Ordering::Relaxed) {
+                                encoder.request_keyframe();
+                            }
+                            match encoder.encode(&frame.rgba) {
+                                Ok(data) if data.is_empty() => {}
+                                Ok(data) => {
+                                    let header = PacketHeader {
+                                        frame_id,
+                                        chunk_idx: 0,
+                                        total_chunks: 0,
+                                        frame_type: FrameType::of_payload(&data),
+                                        sequence,
+                                        capture_ts: clock.timestamp(frame.captured_at),
+                                        send_ts: 0,
+                                        epoch,
+                                        encrypted: cipher.is_some(),
+                                        layer: 0,
+                                        stream,
+                                        stream_id,
+                                    };
+                                    sequence = sequence.wrapping_add(1);
+                                    let spread = interval.mul_f32(SEND_SPREAD).saturating_sub(frame_start.elapsed());
+                                    // Over the bandwidth limit it's dropped whole, like the main display's frames
+                                    if !bandwidth.admits(data.len(), Instant::now()) {
+                                        stats.lock().unwrap().bandwidth_drops += 1;
+                                    } else {
+                                        match Self::send_chunked(&socket, &delivery, &data, chunk_size, header, &clock, cipher.as_deref(), &bandwidth, spread).await {
+                                            Ok(()) => frame_id = frame_id.wrapping_add(1),
+                                            Err(e) => eprintln!("❌ Stream {} send error: {}", stream, e),
+                                        }
+                                    }
+                                }
+                                Err(e) => eprintln!("❌ Stream {} encode error: {}", stream, e),
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            consecutive_errors += 1;
+                            eprintln!("❌ Stream {} capture error ({}/{}): {}", stream, consecutive_errors, MAX_CONSECUTIVE_ERRORS, e);
+                            // The main display goes on without this one
+                            if consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
+                                eprintln!("🛑 Stopping stream {}", stream);
+                                break;
+                            }
+                        }
+                    }
+                    tokio::time::sleep(interval.saturating_sub(frame_start.elapsed()).max(Duration::from_millis(1))).await;
+                }
+                eprintln!("🔴 Stream {} stopped", stream);
+            }))
+            .map_err(|e| format!("Failed to spawn display thread: {}", e))?;
         Ok(())
     }
     
@@ -160,25 +952,65 @@ impl UdpServer {
         Ok(buffer.into_inner())
     }
     
-    async fn send_chunked(socket: &UdpSocket, data: &[u8], frame_id: u32) -> Result<(), String> {
-        let total_chunks = (data.len() + CHUNK_SIZE - 1) / CHUNK_SIZE;
-        let chunks: Vec<&[u8]> = data.chunks(CHUNK_SIZE).collect();
+    /// Split `data`, led by its checksum, into `chunk_size` chunks, stamping each
+    /// with `header` plus its own index, chunk count and send time. With a `cipher` every chunk is
+    /// sealed on its own, so receivers can still decrypt around lost chunks.
+    /// Chunks go out evenly over `spread`, a whole frame at once overflows the
+    /// buffers of consumer switches and Wi-Fi, and wait for `bandwidth` to have
+    /// room for them.
+    pub(crate) async fn send_chunked(
+        socket: &UdpSocket,
+        delivery: &Delivery,
+        data: &[u8],
+        chunk_size: usize,
+        header: PacketHeader,
+        clock: &StreamClock,
+        cipher: Option<&StreamCipher>,
+        bandwidth: &BandwidthLimit,
+        spread: Duration,
+    ) -> Result<(), String> {
+        let data = packet::add_frame_checksum(data);
+        let total_chunks = data.len().div_ceil(chunk_size);
+        let chunks: Vec<&[u8]> = data.chunks(chunk_size).collect();
+        
+        let packet_size = |chunk: &[u8]| {
+            packet::HEADER_SIZE + chunk.len() + cipher.map_or(0, |_| stream_crypto::OVERHEAD) + packet::CHECKSUM_SIZE
+        };
+        let build_packet = |chunk_idx: usize, chunk: &[u8]| -> Result<Vec<u8>, String> {
+            let mut packet = Vec::with_capacity(packet_size(chunk));
+            PacketHeader {
+                chunk_idx: chunk_idx as u32,
+                total_chunks: total_chunks as u32,
+                send_ts: clock.now(),
+                ..header
+            }.write(&mut packet);
+            match cipher {
+                Some(cipher) => {
+                    let sealed = cipher.seal(&packet, chunk)?;
+                    packet.extend_from_slice(&sealed);
+                }
+                None => packet.extend_from_slice(chunk),
+            }
+            packet::append_checksum(&mut packet);
+            Ok(packet)
+        };
+        
+        // Everyone gets the same sealed packets; without approved viewers nothing goes out
+        let send = |packet: &[u8]| delivery.send(socket, packet);
         
         // First pass: Send all chunks
+        let start = Instant::now();
         for (i, chunk) in chunks.iter().enumerate() {
-            let mut packet = Vec::with_capacity(12 + chunk.len());
-            packet.extend_from_slice(&frame_id.to_be_bytes());
-            packet.extend_from_slice(&(i as u32).to_be_bytes());
-            packet.extend_from_slice(&(total_chunks as u32).to_be_bytes());
-            packet.extend_from_slice(chunk);
-            
-            socket.send_to(&packet, MULTICAST_ADDR)
-                .map_err(|e| format!("Send failed: {}", e))?;
-            
-            // Small delay between chunks to avoid overwhelming network
-            if i % 10 == 0 {
-                tokio::time::sleep(Duration::from_micros(100)).await;
+            let now = Instant::now();
+            let due = start + spread.mul_f64(i as f64 / total_chunks as f64);
+            let wait = due.saturating_duration_since(now).max(bandwidth.take(packet_size(chunk), now));
+            // Shorter waits are caught up on with the next chunks, timers aren't that precise
+            if wait >= Duration::from_millis(1) {
+                tokio::time::sleep(wait).await;
             }
+            // Built after waiting, so the send time is when it went
+            send(&build_packet(i, chunk)?)
+                .map_err(|e| format!("Send failed: {}", e))?;
         }
         
         // Second pass: Resend first and last chunks for reliability (critical for JPEG)
@@ -187,30 +1019,379 @@ impl UdpServer {
             
             // Resend first chunk (JPEG header)
             if let Some(first_chunk) = chunks.first() {
-                let mut packet = Vec::with_capacity(12 + first_chunk.len());
-                packet.extend_from_slice(&frame_id.to_be_bytes());
-                packet.extend_from_slice(&0u32.to_be_bytes());
-                packet.extend_from_slice(&(total_chunks as u32).to_be_bytes());
-                packet.extend_from_slice(first_chunk);
-                let _ = socket.send_to(&packet, MULTICAST_ADDR);
+                bandwidth.take(packet_size(first_chunk), Instant::now());
+                let _ = send(&build_packet(0, first_chunk)?);
             }
             
             // Resend last chunk (JPEG end marker)
             if let Some(last_chunk) = chunks.last() {
-                let last_idx = chunks.len() - 1;
-                let mut packet = Vec::with_capacity(12 + last_chunk.len());
-                packet.extend_from_slice(&frame_id.to_be_bytes());
-                packet.extend_from_slice(&(last_idx as u32).to_be_bytes());
-                packet.extend_from_slice(&(total_chunks as u32).to_be_bytes());
-                packet.extend_from_slice(last_chunk);
-                let _ = socket.send_to(&packet, MULTICAST_ADDR);
+                bandwidth.take(packet_size(last_chunk), Instant::now());
+                let _ = send(&build_packet(chunks.len() - 1, last_chunk)?);
             }
         }
         
         Ok(())
     }
     
+    // Hands whatever arrives on the control port to the feedback listener
+    fn spawn_control_reader(socket: Arc<UdpSocket>, is_running: Arc<Mutex<bool>>, feedback: Feedback) {
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            while *is_running.lock().unwrap() {
+                if let Ok((size, from)) = socket.recv_from(&mut buf) {
+                    if feedback.send((buf[..size].to_vec(), from)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+    
+    // Session messages are rare and change what viewers do, so they go out twice
+    fn announce(socket: &UdpSocket, delivery: &Delivery, message: SessionMessage) {
+        let packet = message.serialize();
+        for _ in 0..if REDUNDANT_PACKETS { 2 } else { 1 } {
+            let _ = delivery.send(socket, &packet);
+        }
+    }
+    
+    // Tells viewers the server is still there while no frames go out, frozen or idle
+    fn spawn_heartbeat(
+        socket: Arc<UdpSocket>,
+        delivery: Delivery,
+        is_running: Arc<Mutex<bool>>,
+        epoch: u16,
+        stream_id: u32,
+        clock: StreamClock,
+    ) {
+        std::thread::spawn(move || {
+            while *is_running.lock().unwrap() {
+                let heartbeat = packet::Heartbeat { epoch, stream_id, send_ts: clock.now() };
+                let _ = delivery.send(&socket, &heartbeat.serialize());
+                std::thread::sleep(Duration::from_millis(HEARTBEAT_INTERVAL_MS));
+            }
+        });
+    }
+    
+    // Receivers talk back to the control port (or over QUIC): keyframe requests
+    // when they lost sync, periodic reception reports, join requests and remote input
+    fn spawn_feedback_listener(
+        socket: Arc<UdpSocket>,
+        feedback: mpsc::Receiver<(Vec<u8>, SocketAddr)>,
+        is_running: Arc<Mutex<bool>>,
+        keyframe_requested: Arc<AtomicBool>,
+        display_keyframes: Arc<Mutex<Vec<Arc<AtomicBool>>>>,
+        stats: Arc<Mutex<ServerStats>>,
+        history: Arc<Mutex<StatsHistory<ServerStats>>>,
+        remote_control: Arc<AtomicBool>,
+        access: Option<Arc<AccessControl>>,
+        peers: Arc<PeerTable>,
+    ) {
+        std::thread::spawn(move || {
+            let mut reports = ReceiverReports::new();
+            // Created on first use; enigo connections are tied to this thread
+            let mut injector: Option<InputInjector> = None;
+            let mut controller: Option<SocketAddr> = None;
+            while *is_running.lock().unwrap() {
+                if let Ok((message, from)) = feedback.recv_timeout(Duration::from_millis(FEEDBACK_TIMEOUT_MS)) {
+                    let message = message.as_slice();
+                    // Only approved viewers get a say when approval is required
+                    let join = access.as_ref().and_then(|access| Some((access, JoinRequest::parse(message)?)));
+                    let allowed = access.as_ref().is_none_or(|access| access.is_approved(&from));
+                    if let Some((access, request)) = join {
+                        let status = access.handle_join(from, &request);
+                        let _ = socket.send_to(&status.serialize(), from);
+                    } else if !allowed {
+                        // Not approved (yet), nothing they send counts
+                    } else if message == packet::KEYFRAME_REQUEST {
+                        peers.seen(from, None);
+                        if !keyframe_requested.swap(true, Ordering::Relaxed) {
+                            eprintln!("🔑 Keyframe requested by {}", from);
+                        }
+                        // Requests don't say which display lost sync
+                        for requested in display_keyframes.lock().unwrap().iter() {
+                            requested.store(true, Ordering::Relaxed);
+                        }
+                    } else if let Some(report) = ReceiverReport::parse(message) {
+                        peers.seen(from, Some(&report));
+                        reports.insert(from, (report, Instant::now()));
+                    } else if let Some(event) = InputEvent::parse(message) {
+                        peers.seen(from, None);
+                        if remote_control.load(Ordering::Relaxed) {
+                            if controller != Some(from) {
+                                eprintln!("🖱️  Remote input from {}", from);
+                                controller = Some(from);
+                            }
+                            if injector.is_none() {
+                                match InputInjector::new() {
+                                    Ok(created) => injector = Some(created),
+                                    Err(e) => eprintln!("❌ {}", e),
+                                }
+                            }
+                            if let Some(injector) = injector.as_mut() {
+                                if let Err(e) = injector.inject(&event) {
+                                    eprintln!("❌ {}", e);
+                                }
+                            }
+                        }
+                    }
+                }
+                
+                // Runs on every wake-up (at least every read timeout) so departed receivers drop out
+                reports.retain(|_, (_, at)| at.elapsed().as_secs() < REPORT_EXPIRY_SECS);
+                peers.expire();
+                let mut current = stats.lock().unwrap();
+                Self::summarize_reports(&reports, &mut current);
+                history.lock().unwrap().record(&current);
+            }
+        });
+    }
+    
+    fn summarize_reports(reports: &ReceiverReports, stats: &mut ServerStats) {
+        stats.reporting_receivers = reports.len();
+        stats.worst_loss_rate = reports.values()
+            .map(|(report, _)| report.loss_rate())
+            .fold(0.0, f32::max);
+        stats.worst_jitter_ms = reports.values()
+            .map(|(report, _)| report.jitter_us as f32 / 1000.0)
+            .fold(0.0, f32::max);
+    }
+    
+    /// Send the pointer over `display` next to the frames, see cursor_stream.rs
+    pub fn start_cursor_stream(&self, display: Option<usize>) -> Result<(), String> {
+        let sender = CursorSender::start(
+            display,
+            self.socket.clone(),
+            self.delivery.clone(),
+            self.clock,
+            self.cipher.clone(),
+            self.frozen.clone(),
+        )?;
+        *self.cursor.lock().unwrap() = Some(sender);
+        Ok(())
+    }
+    
+    /// Let viewers find this stream. Not fatal if it fails, e.g. with
+    /// another server already answering on this machine.
+    pub fn advertise(&self, width: usize, height: usize) {
+        let info = ServerInfo {
+            name: crate::discovery::host_name(),
+            width,
+            height,
+            group: self.settings.ip_version.group().to_string(),
+            ports: self.settings.ports,
+            requires_approval: self.access.is_some(),
+            encrypted: self.cipher.is_some(),
+            tcp_fallback: matches!(self.delivery, Delivery::Multicast { tcp: Some(_), .. }),
+            quic_fingerprint: match &self.delivery {
+                Delivery::Multicast { quic: Some(quic), .. } => Some(quic.fingerprint().to_string()),
+                _ => None,
+            },
+            simulcast: self.settings.simulcast.clone(),
+            displays: self.displays.lock().unwrap().clone(),
+            stream_id: self.stream_id,
+            address: String::new(),
+        };
+        match DiscoveryResponder::start(info) {
+            Ok(responder) => *self.discovery.lock().unwrap() = Some(responder),
+            Err(e) => eprintln!("⚠️  Stream not discoverable: {}", e),
+        }
+    }
+    
     pub fn stop(&self) {
-        *self.is_running.lock().unwrap() = false;
+        let was_running = std::mem::replace(&mut *self.is_running.lock().unwrap(), false);
+        // Before the unicast transports close, so their viewers hear it too
+        if was_running {
+            Self::announce(&self.socket, &self.delivery, SessionMessage::StreamStopped { epoch: self.epoch, stream_id: self.stream_id });
+        }
+        if self.recording.lock().unwrap().is_some() {
+            if let Err(e) = self.stop_recording() {
+                eprintln!("❌ {}", e);
+            }
+        }
+        if let Some(discovery) = self.discovery.lock().unwrap().take() {
+            discovery.stop();
+        }
+        self.cursor.lock().unwrap().take();
+        if let Some(key_exchange) = self.key_exchange.as_ref() {
+            key_exchange.stop();
+        }
+        if let Delivery::Multicast { tcp, quic, .. } = &self.delivery {
+            if let Some(tcp) = tcp {
+                tcp.stop();
+            }
+            if let Some(quic) = quic {
+                quic.stop();
+            }
+        }
+    }
+    
+    pub fn is_running(&self) -> bool {
+        *self.is_running.lock().unwrap()
+    }
+    
+    /// Tee the encoded stream into a file, `path` gets the container's extension.
+    /// Sealed with `passphrase` if given, or with the stream key when the
+    /// settings ask for it (see recording_crypto.rs).
+    pub fn start_recording(&self, path: &Path, passphrase: Option<&str>) -> Result<PathBuf, String> {
+        let encoder_type = (*self.encoder_type.lock().unwrap()).ok_or("Stream is not running")?;
+        let key = recording_crypto::choose_key(
+            self.settings.encrypt_recordings,
+            passphrase,
+            self.cipher.clone(),
+            self.settings.pairing_pin.is_some(),
+        )?;
+        let mut recording = self.recording.lock().unwrap();
+        if recording.is_some() {
+            return Err("Already recording".to_string());
+        }
+        let started = Recording::start(path, encoder_type, self.settings.jpeg_quality, key)?;
+        let path = started.path().to_path_buf();
+        *recording = Some(started);
+        Ok(path)
+    }
+    
+    pub fn stop_recording(&self) -> Result<RecordingSummary, String> {
+        let recording = self.recording.lock().unwrap().take().ok_or("Not recording")?;
+        recording.finish()
+    }
+    
+    /// Encode the running stream at `quality` (MIN_QUALITY-100, see frame_pacer.rs) from the next frame on
+    pub fn set_quality(&self, quality: u8) -> Result<(), String> {
+        if !(frame_pacer::MIN_QUALITY..=100).contains(&quality) {
+            return Err(format!("Quality ({}) must be within {}-100", quality, frame_pacer::MIN_QUALITY));
+        }
+        self.requested_quality.store(quality, Ordering::Relaxed);
+        Ok(())
+    }
+    
+    /// Pace the running stream at `fps`, within the settings' min_fps-max_fps
+    pub fn set_target_fps(&self, fps: u32) -> Result<(), String> {
+        if fps < self.settings.min_fps || fps > self.settings.max_fps {
+            return Err(format!("FPS ({}) must be within {}-{}", fps, self.settings.min_fps, self.settings.max_fps));
+        }
+        self.requested_fps.store(fps, Ordering::Relaxed);
+        Ok(())
+    }
+    
+    /// Cap what the running stream sends at `mbps` megabits per second, 0 lifts the cap
+    pub fn set_bandwidth_limit(&self, mbps: f32) -> Result<(), String> {
+        if !mbps.is_finite() || mbps < 0.0 {
+            return Err(format!("Bandwidth limit ({}) must be 0 (unlimited) or more", mbps));
+        }
+        self.bandwidth.set_limit(mbps);
+        Ok(())
+    }
+    
+    /// Call `notify` with the stream's stats every 5 seconds while it runs
+    pub fn on_stats<F>(&self, notify: F)
+    where
+        F: Fn(ServerReport) + Send + Sync + 'static,
+    {
+        *self.stats_listener.lock().unwrap() = Some(Box::new(notify));
+    }
+    
+    /// Keep showing viewers the current frame instead of the live screen
+    pub fn set_frozen(&self, frozen: bool) {
+        self.frozen.store(frozen, Ordering::Relaxed);
+    }
+    
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::Relaxed)
+    }
+    
+    /// Let viewers drive this machine's mouse and keyboard
+    pub fn set_remote_control(&self, enabled: bool) {
+        self.remote_control.store(enabled, Ordering::Relaxed);
+    }
+    
+    pub fn is_remote_control(&self) -> bool {
+        self.remote_control.load(Ordering::Relaxed)
+    }
+    
+    pub fn access_control(&self) -> Option<&Arc<AccessControl>> {
+        self.access.as_ref()
+    }
+    
+    /// Viewers currently talking back to this server
+    pub fn peers(&self) -> &PeerTable {
+        &self.peers
+    }
+    
+    pub fn settings(&self) -> &StreamSettings {
+        &self.settings
+    }
+    
+    pub fn stats(&self) -> ServerStats {
+        self.stats.lock().unwrap().clone()
+    }
+    
+    /// Current stats with their 1s/10s/60s aggregates
+    pub fn stats_report(&self) -> ServerReport {
+        let current = self.stats();
+        self.history.lock().unwrap().report(current)
+    }
+}
+
+// How long a frame's chunks may take, counted from when the sender started on
+// it. Capture and encode were busy with this frame while the sender sent the
+// one before, their time isn't the sender's to make up.
+fn send_spread(spf: Duration, sending_for: Duration) -> Duration {
+    spf.mul_f32(SEND_SPREAD).saturating_sub(sending_for)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock;
+
+    #[tokio::test]
+    async fn test_chunks_spread_over_send_time() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let delivery = Delivery::Multicast { group: receiver.local_addr().unwrap(), tcp: None, quic: None };
+        let header = PacketHeader {
+            frame_id: 0,
+            chunk_idx: 0,
+            total_chunks: 0,
+            frame_type: FrameType::Key,
+            sequence: 0,
+            capture_ts: 0,
+            send_ts: 0,
+            epoch: 1,
+            encrypted: false,
+            layer: 0,
+            stream: 0,
+            stream_id: 0,
+        };
+
+        // Nothing sent yet, the chunks have most of a period; layers sent
+        // after the main stream get what's left of it
+        let spf = Duration::from_millis(50);
+        let spread = send_spread(spf, Duration::ZERO);
+        assert!(spread >= spf * 3 / 4);
+        assert!(send_spread(spf, Duration::from_millis(30)) <= spf / 4);
+        assert_eq!(send_spread(spf, spf), Duration::ZERO);
+
+        let data = vec![0u8; 10 * 1000];
+        UdpServer::send_chunked(&socket, &delivery, &data, 1000, header, &StreamClock::new(), None, &BandwidthLimit::new(0.0), spread)
+            .await
+            .unwrap();
+
+        // The frame checksum makes it 11 chunks, the last due 10/11 of the way in
+        let mut buf = [0u8; 2048];
+        let size = receiver.recv(&mut buf).unwrap();
+        let first = PacketHeader::parse(&buf[..size]).unwrap();
+        assert_eq!(first.chunk_idx, 0);
+        let last = loop {
+            let size = receiver.recv(&mut buf).unwrap();
+            let header = PacketHeader::parse(&buf[..size]).unwrap();
+            if header.chunk_idx == 10 {
+                break header;
+            }
+        };
+        let sent_over = Duration::from_micros(clock::elapsed_us(first.send_ts, last.send_ts) as u64);
+        assert!(sent_over >= spread.mul_f32(0.7 * 10.0 / 11.0));
     }
 }
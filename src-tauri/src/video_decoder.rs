@@ -0,0 +1,462 @@
+// Video Decoder - turns H.264 access units and AV1 frames back into frames on the client
+// Decoded frames are re-encoded as JPEG so they reuse the existing display path
+// H.264 goes to the GPU's decoder (DXVA, VideoToolbox, VAAPI) through FFmpeg
+// when built with `hwcodec`, to OpenH264 otherwise or when no GPU decoder opens.
+
+use image::RgbImage;
+
+/// Annex-B streams start every access unit with a start code
+pub fn is_h264_payload(data: &[u8]) -> bool {
+    data.starts_with(&[0, 0, 0, 1]) || data.starts_with(&[0, 0, 1])
+}
+
+/// AV1 frames are "AV01" | flags (bit 0: keyframe) | OBUs, since unlike Annex-B
+/// the OBUs alone neither stand out from other payloads nor say cheaply whether
+/// a frame is a keyframe
+pub const AV1_MAGIC: &[u8; 4] = b"AV01";
+const AV1_HEADER_SIZE: usize = 5;
+
+pub fn is_av1_payload(data: &[u8]) -> bool {
+    data.len() > AV1_HEADER_SIZE && data.starts_with(AV1_MAGIC)
+}
+
+/// Frames that need a decoder with state, see `VideoDecoder`
+pub fn is_video_payload(data: &[u8]) -> bool {
+    is_h264_payload(data) || is_av1_payload(data)
+}
+
+pub fn is_av1_keyframe(data: &[u8]) -> bool {
+    is_av1_payload(data) && data[4] & 1 != 0
+}
+
+#[cfg_attr(not(feature = "av1"), allow(dead_code))]
+pub fn av1_payload(obus: &[u8], keyframe: bool) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(AV1_HEADER_SIZE + obus.len());
+    payload.extend_from_slice(AV1_MAGIC);
+    payload.push(keyframe as u8);
+    payload.extend_from_slice(obus);
+    payload
+}
+
+/// One codec's decoder, the receiving end of `VideoEncoder`
+pub trait FrameDecoder: Send {
+    /// Decode one access unit. Returns `None` while the decoder is still
+    /// waiting for a keyframe or buffering.
+    fn decode(&mut self, data: &[u8]) -> Result<Option<RgbImage>, String>;
+
+    /// What decodes, for the logs
+    fn decoder_name(&self) -> &'static str;
+}
+
+/// The GPU's H.264 decoder when there is one, OpenH264 otherwise
+pub fn create_h264_decoder() -> Result<Box<dyn FrameDecoder>, String> {
+    #[cfg(feature = "hwcodec")]
+    match HardwareH264Decoder::new() {
+        Ok(decoder) => return Ok(Box::new(decoder)),
+        Err(e) => eprintln!("⚠️  No hardware H.264 decoder, trying OpenH264: {}", e),
+    }
+    H264Decoder::new().map(|decoder| Box::new(decoder) as Box<dyn FrameDecoder>)
+}
+
+/// Whichever decoder the stream's payloads need
+pub enum VideoDecoder {
+    H264(Box<dyn FrameDecoder>),
+    Av1(Av1Decoder),
+}
+
+impl VideoDecoder {
+    /// A decoder for the codec `data` is in
+    pub fn for_payload(data: &[u8]) -> Result<Self, String> {
+        let decoder = if is_av1_payload(data) {
+            Av1Decoder::new().map(VideoDecoder::Av1)
+        } else if is_h264_payload(data) {
+            create_h264_decoder().map(VideoDecoder::H264)
+        } else {
+            Err("not an H.264 or AV1 frame".to_string())
+        }?;
+        eprintln!("🎞️  Decoding video with {}", decoder.decoder_name());
+        Ok(decoder)
+    }
+
+    pub fn decoder_name(&self) -> &'static str {
+        match self {
+            VideoDecoder::H264(decoder) => decoder.decoder_name(),
+            VideoDecoder::Av1(decoder) => decoder.decoder_name(),
+        }
+    }
+
+    /// Whether `data` is in this decoder's codec (a restarted server may have switched)
+    pub fn handles(&self, data: &[u8]) -> bool {
+        match self {
+            VideoDecoder::H264(_) => is_h264_payload(data),
+            VideoDecoder::Av1(_) => is_av1_payload(data),
+        }
+    }
+
+    pub fn decode_to_jpeg(&mut self, data: &[u8], quality: u8) -> Result<Option<Vec<u8>>, String> {
+        let picture = match self {
+            VideoDecoder::H264(decoder) => decoder.decode(data)?,
+            VideoDecoder::Av1(decoder) => decoder.decode(&data[AV1_HEADER_SIZE..])?,
+        };
+        picture
+            .map(|rgb| crate::jpeg::encode(rgb.as_raw(), rgb.width() as usize, rgb.height() as usize, crate::jpeg::Layout::Rgb, quality))
+            .transpose()
+    }
+}
+
+/// WebP frames are passed to the webview as they are, it decodes them natively
+pub fn is_webp_payload(data: &[u8]) -> bool {
+    data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP"
+}
+
+#[cfg(feature = "openh264")]
+pub struct H264Decoder {
+    decoder: openh264::decoder::Decoder,
+}
+
+#[cfg(feature = "openh264")]
+impl H264Decoder {
+    pub fn new() -> Result<Self, String> {
+        let decoder = openh264::decoder::Decoder::new()
+            .map_err(|e| format!("Failed to create OpenH264 decoder: {}", e))?;
+        Ok(Self { decoder })
+    }
+}
+
+#[cfg(feature = "openh264")]
+impl FrameDecoder for H264Decoder {
+    fn decode(&mut self, data: &[u8]) -> Result<Option<RgbImage>, String> {
+        use openh264::formats::YUVSource;
+
+        let Some(yuv) = self.decoder.decode(data)
+            .map_err(|e| format!("H264 decoding failed: {}", e))? else {
+            return Ok(None);
+        };
+
+        let (width, height) = yuv.dimensions();
+        let mut rgb = vec![0u8; width * height * 3];
+        yuv.write_rgb8(&mut rgb);
+
+        RgbImage::from_raw(width as u32, height as u32, rgb)
+            .map(Some)
+            .ok_or_else(|| "Failed to create image buffer from decoded frame".to_string())
+    }
+
+    fn decoder_name(&self) -> &'static str {
+        "OpenH264"
+    }
+}
+
+#[cfg(not(feature = "openh264"))]
+pub struct H264Decoder;
+
+#[cfg(not(feature = "openh264"))]
+impl H264Decoder {
+    pub fn new() -> Result<Self, String> {
+        Err("H.264 decoding not compiled in (enable the `openh264` feature)".to_string())
+    }
+}
+
+#[cfg(not(feature = "openh264"))]
+impl FrameDecoder for H264Decoder {
+    fn decode(&mut self, _data: &[u8]) -> Result<Option<RgbImage>, String> {
+        Err("H.264 decoding not compiled in".to_string())
+    }
+
+    fn decoder_name(&self) -> &'static str {
+        "none"
+    }
+}
+
+#[cfg(feature = "av1")]
+pub struct Av1Decoder {
+    decoder: dav1d::Decoder,
+}
+
+#[cfg(feature = "av1")]
+impl Av1Decoder {
+    pub fn new() -> Result<Self, String> {
+        let decoder = dav1d::Decoder::new()
+            .map_err(|e| format!("Failed to create dav1d decoder: {}", e))?;
+        Ok(Self { decoder })
+    }
+}
+
+#[cfg(feature = "av1")]
+impl FrameDecoder for Av1Decoder {
+    /// Decode one frame's OBUs. Returns `None` while dav1d has no picture ready.
+    fn decode(&mut self, obus: &[u8]) -> Result<Option<RgbImage>, String> {
+        use dav1d::{PixelLayout, PlanarImageComponent};
+
+        // Again means a picture has to come out first, the data stays pending
+        let pending = match self.decoder.send_data(obus.to_vec(), None, None, None) {
+            Ok(()) => false,
+            Err(dav1d::Error::Again) => true,
+            Err(e) => return Err(format!("AV1 decoding failed: {}", e)),
+        };
+        let picture = match self.decoder.get_picture() {
+            Ok(picture) => picture,
+            Err(dav1d::Error::Again) => return Ok(None),
+            Err(e) => return Err(format!("AV1 decoding failed: {}", e)),
+        };
+        if pending {
+            let _ = self.decoder.send_pending_data();
+        }
+        if picture.pixel_layout() != PixelLayout::I420 || picture.bit_depth() != 8 {
+            return Err(format!("Unsupported AV1 picture: {:?}, {} bit", picture.pixel_layout(), picture.bit_depth()));
+        }
+
+        let (width, height) = (picture.width() as usize, picture.height() as usize);
+        let (y, u, v) = (
+            picture.plane(PlanarImageComponent::Y),
+            picture.plane(PlanarImageComponent::U),
+            picture.plane(PlanarImageComponent::V),
+        );
+        let luma_stride = picture.stride(PlanarImageComponent::Y) as usize;
+        let chroma_stride = picture.stride(PlanarImageComponent::U) as usize;
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for row in 0..height {
+            for col in 0..width {
+                let chroma = (row / 2) * chroma_stride + col / 2;
+                let pixel = crate::capture_format::yuv_to_rgb(y[row * luma_stride + col], u[chroma], v[chroma]);
+                rgb.extend_from_slice(&pixel[..3]);
+            }
+        }
+        RgbImage::from_raw(width as u32, height as u32, rgb)
+            .map(Some)
+            .ok_or_else(|| "Failed to create image buffer from decoded frame".to_string())
+    }
+
+    fn decoder_name(&self) -> &'static str {
+        "dav1d"
+    }
+}
+
+#[cfg(not(feature = "av1"))]
+pub struct Av1Decoder;
+
+#[cfg(not(feature = "av1"))]
+impl Av1Decoder {
+    pub fn new() -> Result<Self, String> {
+        Err("AV1 decoding not compiled in (enable the `av1` feature)".to_string())
+    }
+}
+
+#[cfg(not(feature = "av1"))]
+impl FrameDecoder for Av1Decoder {
+    fn decode(&mut self, _obus: &[u8]) -> Result<Option<RgbImage>, String> {
+        Err("AV1 decoding not compiled in".to_string())
+    }
+
+    fn decoder_name(&self) -> &'static str {
+        "none"
+    }
+}
+
+// Hardware H264 Decoder - the GPU's video engine driven through FFmpeg
+// The decoder gets a hardware device and picks its surfaces itself; profiles
+// the GPU can't handle are decoded in software by FFmpeg instead. Pictures are
+// copied back from the GPU and converted to RGB on the CPU.
+#[cfg(feature = "hwcodec")]
+use ffmpeg_next as ffmpeg;
+
+#[cfg(feature = "hwcodec")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HardwareDecodeBackend {
+    D3d11va,      // Windows 8+, any DirectX 11 GPU
+    Dxva2,        // Older Windows drivers
+    VideoToolbox, // Apple media engine (macOS)
+    Vaapi,        // Intel/AMD GPUs (Linux)
+}
+
+#[cfg(feature = "hwcodec")]
+impl HardwareDecodeBackend {
+    /// Backends worth trying on this platform, best first
+    fn candidates() -> &'static [HardwareDecodeBackend] {
+        #[cfg(target_os = "windows")]
+        {
+            &[HardwareDecodeBackend::D3d11va, HardwareDecodeBackend::Dxva2]
+        }
+        #[cfg(target_os = "linux")]
+        {
+            &[HardwareDecodeBackend::Vaapi]
+        }
+        #[cfg(target_os = "macos")]
+        {
+            &[HardwareDecodeBackend::VideoToolbox]
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+        {
+            &[]
+        }
+    }
+
+    fn device_type(self) -> ffmpeg::ffi::AVHWDeviceType {
+        use ffmpeg::ffi::AVHWDeviceType::*;
+        match self {
+            HardwareDecodeBackend::D3d11va => AV_HWDEVICE_TYPE_D3D11VA,
+            HardwareDecodeBackend::Dxva2 => AV_HWDEVICE_TYPE_DXVA2,
+            HardwareDecodeBackend::VideoToolbox => AV_HWDEVICE_TYPE_VIDEOTOOLBOX,
+            HardwareDecodeBackend::Vaapi => AV_HWDEVICE_TYPE_VAAPI,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            HardwareDecodeBackend::D3d11va => "D3D11VA",
+            HardwareDecodeBackend::Dxva2 => "DXVA2",
+            HardwareDecodeBackend::VideoToolbox => "VideoToolbox",
+            HardwareDecodeBackend::Vaapi => "VAAPI",
+        }
+    }
+}
+
+#[cfg(feature = "hwcodec")]
+pub struct HardwareH264Decoder {
+    backend: HardwareDecodeBackend,
+    decoder: ffmpeg::decoder::Video,
+    /// RGB converter for the layout and size it was made for
+    scaler: Option<(ffmpeg::software::scaling::Context, (ffmpeg::format::Pixel, u32, u32))>,
+}
+
+// The FFmpeg contexts are only ever touched by the thread that owns the decoder
+#[cfg(feature = "hwcodec")]
+unsafe impl Send for HardwareH264Decoder {}
+
+#[cfg(feature = "hwcodec")]
+impl HardwareH264Decoder {
+    /// H.264 decoder on the first hardware device that opens
+    pub fn new() -> Result<Self, String> {
+        ffmpeg::init().map_err(|e| format!("Failed to initialize FFmpeg: {}", e))?;
+        let mut errors = Vec::new();
+        for &backend in HardwareDecodeBackend::candidates() {
+            match Self::open(backend) {
+                Ok(decoder) => return Ok(decoder),
+                Err(e) => errors.push(format!("{}: {}", backend.name(), e)),
+            }
+        }
+        if errors.is_empty() {
+            return Err("no hardware decoding on this platform".to_string());
+        }
+        Err(errors.join("; "))
+    }
+
+    fn open(backend: HardwareDecodeBackend) -> Result<Self, String> {
+        use ffmpeg::ffi::*;
+
+        let codec = ffmpeg::decoder::find(ffmpeg::codec::Id::H264)
+            .ok_or("H.264 decoder not available in this FFmpeg build")?;
+        let mut context = ffmpeg::codec::context::Context::new_with_codec(codec);
+
+        unsafe {
+            let device_path = (backend == HardwareDecodeBackend::Vaapi)
+                .then(|| std::ffi::CString::new(crate::hw_encoder::VAAPI_RENDER_NODE).unwrap());
+            let mut device: *mut AVBufferRef = std::ptr::null_mut();
+            let ret = av_hwdevice_ctx_create(
+                &mut device,
+                backend.device_type(),
+                device_path.as_ref().map_or(std::ptr::null(), |path| path.as_ptr()),
+                std::ptr::null_mut(),
+                0,
+            );
+            if ret < 0 {
+                return Err(format!("Failed to open the device: {}", ffmpeg::Error::from(ret)));
+            }
+            // Ownership moves to the codec context, which unrefs it when freed.
+            // With a device set, FFmpeg's default format negotiation picks its surfaces.
+            let raw = context.as_mut_ptr();
+            (*raw).hw_device_ctx = device;
+            (*raw).flags |= AV_CODEC_FLAG_LOW_DELAY as i32;
+        }
+
+        let decoder = context.decoder().video()
+            .map_err(|e| format!("Failed to open the decoder: {}", e))?;
+        Ok(Self { backend, decoder, scaler: None })
+    }
+
+    // Download a GPU surface, or take a frame FFmpeg decoded in software as it is
+    fn to_rgb(&mut self, frame: &ffmpeg::frame::Video) -> Result<RgbImage, String> {
+        let downloaded;
+        let frame = if unsafe { (*frame.as_ptr()).hw_frames_ctx.is_null() } {
+            frame
+        } else {
+            let mut software = ffmpeg::frame::Video::empty();
+            let ret = unsafe { ffmpeg::ffi::av_hwframe_transfer_data(software.as_mut_ptr(), frame.as_ptr(), 0) };
+            if ret < 0 {
+                return Err(format!("Failed to download the frame from the GPU: {}", ffmpeg::Error::from(ret)));
+            }
+            downloaded = software;
+            &downloaded
+        };
+
+        let layout = (frame.format(), frame.width(), frame.height());
+        if self.scaler.as_ref().map(|(_, made_for)| *made_for) != Some(layout) {
+            let scaler = ffmpeg::software::scaling::Context::get(
+                layout.0,
+                layout.1,
+                layout.2,
+                ffmpeg::format::Pixel::RGB24,
+                layout.1,
+                layout.2,
+                ffmpeg::software::scaling::Flags::BILINEAR,
+            ).map_err(|e| format!("Failed to create color converter: {}", e))?;
+            self.scaler = Some((scaler, layout));
+        }
+        let (scaler, _) = self.scaler.as_mut().ok_or("Color converter missing")?;
+        let mut rgb = ffmpeg::frame::Video::empty();
+        scaler.run(frame, &mut rgb)
+            .map_err(|e| format!("Color conversion failed: {}", e))?;
+
+        // FFmpeg pads its rows, the image wants them packed
+        let (width, height) = (rgb.width() as usize, rgb.height() as usize);
+        let stride = rgb.stride(0);
+        let mut packed = Vec::with_capacity(width * height * 3);
+        for row in rgb.data(0).chunks(stride).take(height) {
+            packed.extend_from_slice(&row[..width * 3]);
+        }
+        RgbImage::from_raw(width as u32, height as u32, packed)
+            .ok_or_else(|| "Failed to create image buffer from decoded frame".to_string())
+    }
+}
+
+#[cfg(feature = "hwcodec")]
+impl FrameDecoder for HardwareH264Decoder {
+    fn decode(&mut self, data: &[u8]) -> Result<Option<RgbImage>, String> {
+        let packet = ffmpeg::Packet::copy(data);
+        self.decoder.send_packet(&packet)
+            .map_err(|e| format!("{} decoding failed: {}", self.backend.name(), e))?;
+        // One picture per access unit without B-frames; should more come out, the last one wins
+        let mut frame = ffmpeg::frame::Video::empty();
+        let mut picture = None;
+        while self.decoder.receive_frame(&mut frame).is_ok() {
+            picture = Some(self.to_rgb(&frame)?);
+        }
+        Ok(picture)
+    }
+
+    fn decoder_name(&self) -> &'static str {
+        match self.backend {
+            HardwareDecodeBackend::D3d11va => "FFmpeg H.264 (D3D11VA)",
+            HardwareDecodeBackend::Dxva2 => "FFmpeg H.264 (DXVA2)",
+            HardwareDecodeBackend::VideoToolbox => "FFmpeg H.264 (VideoToolbox)",
+            HardwareDecodeBackend::Vaapi => "FFmpeg H.264 (VAAPI)",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::FrameType;
+
+    #[test]
+    fn test_av1_payloads() {
+        let obus = [0x12, 0x00, 0x0A, 0x0B];
+        let key = av1_payload(&obus, true);
+        let delta = av1_payload(&obus, false);
+        assert!(is_video_payload(&key) && !is_h264_payload(&key));
+        assert_eq!((FrameType::of_payload(&key), FrameType::of_payload(&delta)), (FrameType::Key, FrameType::Delta));
+        assert!(!is_av1_payload(AV1_MAGIC));
+        assert!(is_webp_payload(b"RIFF\x10\0\0\0WEBPVP8 "));
+    }
+}
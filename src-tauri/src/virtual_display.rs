@@ -0,0 +1,179 @@
+// Virtual Display - a desktop to stream on machines without a monitor
+// Demo kiosks often run in VMs with no GPU or attached screen, where there is
+// nothing to capture. A virtual display gives capture something to read.
+//   Linux:   starts an Xvfb server and points capture at it, optionally
+//            launching a session (window manager, kiosk browser ...) inside it
+//   Windows: virtual monitors come from an IddCx driver; once installed its
+//            monitor is a normal display, picked with select_display
+//   macOS:   not supported
+
+use serde::Deserialize;
+
+pub const DEFAULT_WIDTH: u32 = 1920;
+pub const DEFAULT_HEIGHT: u32 = 1080;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct VirtualDisplayConfig {
+    pub width: u32,
+    pub height: u32,
+    /// Shell command started inside the display, e.g. "openbox-session"
+    pub session_command: Option<String>,
+}
+
+impl Default for VirtualDisplayConfig {
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            session_command: None,
+        }
+    }
+}
+
+impl VirtualDisplayConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(320..=7680).contains(&self.width) || !(240..=4320).contains(&self.height) {
+            return Err(format!("Virtual display size {}x{} is out of range", self.width, self.height));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::VirtualDisplay;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::VirtualDisplayConfig;
+    use std::ffi::OsString;
+    use std::path::Path;
+    use std::process::{Child, Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    const FIRST_DISPLAY: u32 = 99;
+    const LAST_DISPLAY: u32 = 199;
+    const STARTUP_TIMEOUT_MS: u64 = 5000;
+
+    /// Running Xvfb server, shut down (and capture pointed back) on drop
+    pub struct VirtualDisplay {
+        name: String,
+        xvfb: Child,
+        session: Option<Child>,
+        previous_display: Option<OsString>,
+    }
+
+    impl VirtualDisplay {
+        pub fn start(config: &VirtualDisplayConfig) -> Result<Self, String> {
+            config.validate()?;
+
+            let number = (FIRST_DISPLAY..=LAST_DISPLAY)
+                .find(|n| !socket_path(*n).exists() && !Path::new(&format!("/tmp/.X{}-lock", n)).exists())
+                .ok_or("No free X display number")?;
+            let name = format!(":{}", number);
+
+            let mut xvfb = Command::new("Xvfb")
+                .arg(&name)
+                .args(["-screen", "0", &format!("{}x{}x24", config.width, config.height)])
+                .args(["-nolisten", "tcp"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map_err(|e| format!("Failed to start Xvfb (is it installed?): {}", e))?;
+
+            // The socket shows up once the server accepts connections
+            let started = Instant::now();
+            while !socket_path(number).exists() {
+                if let Ok(Some(status)) = xvfb.try_wait() {
+                    return Err(format!("Xvfb exited during startup ({})", status));
+                }
+                if started.elapsed() > Duration::from_millis(STARTUP_TIMEOUT_MS) {
+                    let _ = xvfb.kill();
+                    let _ = xvfb.wait();
+                    return Err("Xvfb did not start in time".to_string());
+                }
+                std::thread::sleep(Duration::from_millis(50));
+            }
+
+            let session = match config.session_command.as_deref().filter(|c| !c.trim().is_empty()) {
+                Some(command) => match Command::new("sh").args(["-c", command]).env("DISPLAY", &name).spawn() {
+                    Ok(child) => Some(child),
+                    Err(e) => {
+                        let _ = xvfb.kill();
+                        let _ = xvfb.wait();
+                        return Err(format!("Failed to start session command: {}", e));
+                    }
+                },
+                None => None,
+            };
+
+            // Capture opens the display named by DISPLAY
+            let previous_display = std::env::var_os("DISPLAY");
+            std::env::set_var("DISPLAY", &name);
+            crate::screen_capture::reset_capture();
+
+            eprintln!("🖥️  Virtual display {} started ({}x{})", name, config.width, config.height);
+            Ok(Self { name, xvfb, session, previous_display })
+        }
+
+        pub fn name(&self) -> &str {
+            &self.name
+        }
+    }
+
+    impl Drop for VirtualDisplay {
+        fn drop(&mut self) {
+            if let Some(mut session) = self.session.take() {
+                let _ = session.kill();
+                let _ = session.wait();
+            }
+            let _ = self.xvfb.kill();
+            let _ = self.xvfb.wait();
+
+            match self.previous_display.take() {
+                Some(display) => std::env::set_var("DISPLAY", display),
+                None => std::env::remove_var("DISPLAY"),
+            }
+            crate::screen_capture::reset_capture();
+            eprintln!("🔴 Virtual display {} stopped", self.name);
+        }
+    }
+
+    fn socket_path(number: u32) -> std::path::PathBuf {
+        Path::new("/tmp/.X11-unix").join(format!("X{}", number))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub struct VirtualDisplay;
+
+#[cfg(not(target_os = "linux"))]
+impl VirtualDisplay {
+    pub fn start(config: &VirtualDisplayConfig) -> Result<Self, String> {
+        config.validate()?;
+        #[cfg(target_os = "windows")]
+        {
+            Err("Creating virtual displays needs an IddCx virtual display driver; once installed, pick its monitor in the display list".to_string())
+        }
+        #[cfg(not(target_os = "windows"))]
+        {
+            Err("Virtual displays are not supported on this platform".to_string())
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        ""
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_validation() {
+        assert!(VirtualDisplayConfig::default().validate().is_ok());
+        let tiny = VirtualDisplayConfig { width: 100, ..VirtualDisplayConfig::default() };
+        assert!(tiny.validate().is_err());
+    }
+}
@@ -0,0 +1,253 @@
+// Wayland Capture - the screen through xdg-desktop-portal and PipeWire
+// Wayland compositors don't let clients read the screen, and scrap only knows
+// X11. The ScreenCast portal asks the user which monitor to share (GNOME, KDE
+// and wlroots all show a dialog) and hands back a PipeWire stream of it. A
+// thread of its own negotiates with the portal and then runs the PipeWire
+// loop, keeping the newest frame for the capture session to take.
+
+use std::os::fd::OwnedFd;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+use ashpd::desktop::screencast::{CursorMode, Screencast, SourceType};
+use ashpd::desktop::PersistMode;
+use image::RgbaImage;
+use pipewire as pw;
+use pw::spa;
+use spa::param::video::{VideoFormat, VideoInfoRaw};
+use crate::capture_format::CaptureFormat;
+
+// Long enough for the user to pick a monitor in the portal's dialog
+const PORTAL_TIMEOUT: Duration = Duration::from_secs(120);
+const MAX_SIZE: u32 = 8192;
+
+type Ready = mpsc::Sender<Result<pw::channel::Sender<()>, String>>;
+
+/// Whether this is a Wayland session, where only the portal can see the screen
+pub fn is_wayland_session() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+/// A running portal screencast of one monitor, stopped on drop
+pub struct PortalCapture {
+    latest: Arc<Mutex<Option<RgbaImage>>>,
+    /// Set once the stream ended, by the user or the compositor
+    ended: Arc<Mutex<Option<String>>>,
+    stop: pw::channel::Sender<()>,
+}
+
+impl PortalCapture {
+    /// Ask the portal for a monitor and start streaming it. Blocks until the
+    /// user picked one in the dialog.
+    pub fn open() -> Result<Self, String> {
+        let latest = Arc::new(Mutex::new(None));
+        let ended = Arc::new(Mutex::new(None));
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let (thread_latest, thread_ended) = (latest.clone(), ended.clone());
+        std::thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(format!("Failed to start the portal runtime: {}", e)));
+                    return;
+                }
+            };
+            // The portal session stays open for as long as the stream runs
+            runtime.block_on(async move {
+                let proxy = match Screencast::new().await {
+                    Ok(proxy) => proxy,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(format!("No ScreenCast portal: {}", e)));
+                        return;
+                    }
+                };
+                let session = match proxy.create_session().await {
+                    Ok(session) => session,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(format!("Failed to create a portal session: {}", e)));
+                        return;
+                    }
+                };
+                let stream = async {
+                    proxy.select_sources(&session, CursorMode::Embedded, SourceType::Monitor.into(), false, None, PersistMode::DoNot)
+                        .await
+                        .map_err(|e| format!("Failed to select a monitor: {}", e))?;
+                    let response = proxy.start(&session, None).await
+                        .and_then(|request| request.response())
+                        .map_err(|e| format!("Screen sharing was not allowed: {}", e))?;
+                    let node_id = response.streams().first()
+                        .ok_or("The portal shared no stream")?
+                        .pipe_wire_node_id();
+                    let fd = proxy.open_pipe_wire_remote(&session).await
+                        .map_err(|e| format!("Failed to open the PipeWire remote: {}", e))?;
+                    Ok::<_, String>((fd, node_id))
+                }.await;
+
+                match stream {
+                    Ok((fd, node_id)) => {
+                        if let Err(e) = run_stream(fd, node_id, thread_latest, thread_ended.clone(), ready_tx.clone()) {
+                            let _ = ready_tx.send(Err(e.clone()));
+                            *thread_ended.lock().unwrap() = Some(e);
+                        }
+                    }
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                    }
+                }
+                let _ = session.close().await;
+            });
+        });
+
+        let stop = ready_rx.recv_timeout(PORTAL_TIMEOUT)
+            .map_err(|_| "No monitor was picked in the portal's dialog".to_string())??;
+        Ok(Self { latest, ended, stop })
+    }
+
+    /// The newest frame at full resolution, `None` when none arrived since the last call
+    pub fn capture_frame(&self) -> Result<Option<RgbaImage>, String> {
+        if let Some(reason) = self.ended.lock().unwrap().clone() {
+            return Err(reason);
+        }
+        Ok(self.latest.lock().unwrap().take())
+    }
+}
+
+impl Drop for PortalCapture {
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+    }
+}
+
+// Receive the portal's stream until stopped; `ready` gets the stop handle once connected
+fn run_stream(
+    fd: OwnedFd,
+    node_id: u32,
+    latest: Arc<Mutex<Option<RgbaImage>>>,
+    ended: Arc<Mutex<Option<String>>>,
+    ready: Ready,
+) -> Result<(), String> {
+    pw::init();
+    let mainloop = pw::main_loop::MainLoop::new(None)
+        .map_err(|e| format!("Failed to create the PipeWire loop: {}", e))?;
+    let context = pw::context::Context::new(&mainloop)
+        .map_err(|e| format!("Failed to create the PipeWire context: {}", e))?;
+    let core = context.connect_fd(fd, None)
+        .map_err(|e| format!("Failed to connect to PipeWire: {}", e))?;
+
+    let (stop, stopped) = pw::channel::channel();
+    let _stopped = stopped.attach(mainloop.loop_(), {
+        let mainloop = mainloop.clone();
+        move |()| mainloop.quit()
+    });
+
+    let stream = pw::stream::Stream::new(&core, "smartlab-screen", pw::properties::properties! {
+        *pw::keys::MEDIA_TYPE => "Video",
+        *pw::keys::MEDIA_CATEGORY => "Capture",
+        *pw::keys::MEDIA_ROLE => "Screen",
+    }).map_err(|e| format!("Failed to create the PipeWire stream: {}", e))?;
+
+    let _listener = stream.add_local_listener_with_user_data(VideoInfoRaw::default())
+        .state_changed({
+            let mainloop = mainloop.clone();
+            move |_, _, _, state| {
+                let reason = match state {
+                    pw::stream::StreamState::Error(e) => format!("PipeWire stream error: {}", e),
+                    pw::stream::StreamState::Unconnected => "Screen sharing was stopped".to_string(),
+                    _ => return,
+                };
+                *ended.lock().unwrap() = Some(reason);
+                mainloop.quit();
+            }
+        })
+        .param_changed(|_, format, id, param| {
+            let Some(param) = param else {
+                return;
+            };
+            if id == spa::param::ParamType::Format.as_raw() && format.parse(param).is_ok() {
+                eprintln!("🖥️  PipeWire screen {}x{} ({:?})", format.size().width, format.size().height, format.format());
+            }
+        })
+        .process(move |stream, format| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+            let Some(data) = buffer.datas_mut().first_mut() else {
+                return;
+            };
+            let (offset, size, stride) = {
+                let chunk = data.chunk();
+                (chunk.offset() as usize, chunk.size() as usize, chunk.stride() as usize)
+            };
+            let image = data.data()
+                .and_then(|bytes| bytes.get(offset..offset + size))
+                .and_then(|bytes| to_image(format, bytes, stride));
+            if let Some(image) = image {
+                *latest.lock().unwrap() = Some(image);
+            }
+        })
+        .register()
+        .map_err(|e| format!("Failed to listen to the PipeWire stream: {}", e))?;
+
+    let format = format_param()?;
+    let mut params = [spa::pod::Pod::from_bytes(&format).ok_or("Invalid PipeWire format")?];
+    stream.connect(
+        spa::utils::Direction::Input,
+        Some(node_id),
+        pw::stream::StreamFlags::AUTOCONNECT | pw::stream::StreamFlags::MAP_BUFFERS,
+        &mut params,
+    ).map_err(|e| format!("Failed to connect the PipeWire stream: {}", e))?;
+
+    let _ = ready.send(Ok(stop));
+    mainloop.run();
+    Ok(())
+}
+
+// Packed 8-bit RGB layouts, any size and frame rate
+fn format_param() -> Result<Vec<u8>, String> {
+    let object = spa::pod::object!(
+        spa::utils::SpaTypes::ObjectParamFormat,
+        spa::param::ParamType::EnumFormat,
+        spa::pod::property!(spa::param::format::FormatProperties::MediaType, Id, spa::param::format::MediaType::Video),
+        spa::pod::property!(spa::param::format::FormatProperties::MediaSubtype, Id, spa::param::format::MediaSubtype::Raw),
+        spa::pod::property!(
+            spa::param::format::FormatProperties::VideoFormat,
+            Choice, Enum, Id,
+            VideoFormat::BGRx,
+            VideoFormat::BGRx,
+            VideoFormat::BGRA,
+            VideoFormat::RGBx,
+            VideoFormat::RGBA
+        ),
+        spa::pod::property!(
+            spa::param::format::FormatProperties::VideoSize,
+            Choice, Range, Rectangle,
+            spa::utils::Rectangle { width: 1920, height: 1080 },
+            spa::utils::Rectangle { width: 1, height: 1 },
+            spa::utils::Rectangle { width: MAX_SIZE, height: MAX_SIZE }
+        ),
+        spa::pod::property!(
+            spa::param::format::FormatProperties::VideoFramerate,
+            Choice, Range, Fraction,
+            spa::utils::Fraction { num: 30, denom: 1 },
+            spa::utils::Fraction { num: 0, denom: 1 },
+            spa::utils::Fraction { num: 240, denom: 1 }
+        ),
+    );
+    spa::pod::serialize::PodSerializer::serialize(std::io::Cursor::new(Vec::new()), &spa::pod::Value::Object(object))
+        .map(|(cursor, _)| cursor.into_inner())
+        .map_err(|e| format!("Failed to build the PipeWire format: {:?}", e))
+}
+
+// A buffer of the negotiated format as RGBA, `None` for formats we didn't offer
+fn to_image(format: &VideoInfoRaw, data: &[u8], stride: usize) -> Option<RgbaImage> {
+    let layout = match format.format() {
+        VideoFormat::BGRx | VideoFormat::BGRA => CaptureFormat::Bgra8,
+        VideoFormat::RGBx | VideoFormat::RGBA => CaptureFormat::Rgba8,
+        _ => return None,
+    };
+    let (width, height) = (format.size().width as usize, format.size().height as usize);
+    // Some compositors leave the stride out of tightly packed buffers
+    let stride = if stride == 0 { width * 4 } else { stride };
+    let rgba = layout.to_rgba(data, stride, width, height).ok()?;
+    RgbaImage::from_raw(width as u32, height as u32, rgba)
+}
@@ -0,0 +1,318 @@
+// WebRTC Output - lets any browser on the LAN watch without the app
+// Serves a small page on http://<host>:<port>/ that sends its SDP offer to
+// POST /offer and plays the answer's video track. The encoded H.264 access
+// units go out as they are, so browsers decode what the multicast viewers get.
+// JPEG and tile delta streams can't be carried, start with an H.264 encoder.
+//
+// Signalling is one request per viewer without trickle ICE: both sides
+// gather their candidates first, fine on a LAN. Every viewer shares one
+// track, and a new viewer asks the encoder for a keyframe to start from.
+//
+// Browsers can't take part in access control or stream encryption, so
+// servers requiring either don't offer it (see StreamSettings::validate).
+
+use std::sync::atomic::AtomicBool;
+#[cfg(feature = "webrtc")]
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+pub const DEFAULT_PORT: u16 = 8889;
+#[cfg_attr(not(feature = "webrtc"), allow(dead_code))]
+const QUEUE_FRAMES: usize = 8; // A stalled sender drops frames instead of queueing them
+
+/// Running WebRTC endpoint, stopped on `stop()` or drop
+#[cfg_attr(not(feature = "webrtc"), allow(dead_code))]
+pub struct WebRtcOutput {
+    #[cfg(feature = "webrtc")]
+    frames: tokio::sync::mpsc::Sender<Vec<u8>>,
+    #[cfg(feature = "webrtc")]
+    shutdown: std::sync::Mutex<Option<tokio::sync::oneshot::Sender<()>>>,
+    keyframe_requested: Arc<AtomicBool>,
+}
+
+impl WebRtcOutput {
+    pub fn is_h264(encoder_type: crate::hw_encoder::EncoderType) -> bool {
+        use crate::hw_encoder::EncoderType;
+        matches!(encoder_type, EncoderType::SoftwareH264 | EncoderType::HardwareH264)
+    }
+}
+
+impl Drop for WebRtcOutput {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(feature = "webrtc")]
+impl WebRtcOutput {
+    /// Serve browsers on `port`; new viewers set `keyframe_requested`
+    pub fn start(port: u16, fps: u32, keyframe_requested: Arc<AtomicBool>) -> Result<Self, String> {
+        use webrtc::api::media_engine::MIME_TYPE_H264;
+        use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+        use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+
+        // Its own runtime, so frames can be handed over from any thread
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .map_err(|e| format!("Failed to create WebRTC runtime: {}", e))?;
+        let listener = match runtime.block_on(tokio::net::TcpListener::bind(("0.0.0.0", port))) {
+            Ok(listener) => listener,
+            Err(e) => {
+                runtime.shutdown_background();
+                return Err(format!("Failed to bind WebRTC on port {}: {}", port, e));
+            }
+        };
+
+        let track = Arc::new(TrackLocalStaticSample::new(
+            RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_H264.to_owned(),
+                ..Default::default()
+            },
+            "video".to_owned(),
+            "smartlab".to_owned(),
+        ));
+        let (frames, frames_rx) = tokio::sync::mpsc::channel(QUEUE_FRAMES);
+        let (shutdown, shutdown_rx) = tokio::sync::oneshot::channel();
+        let signalling = signalling::Signalling::new(track.clone(), keyframe_requested.clone())?;
+
+        std::thread::Builder::new()
+            .name("webrtc-output".to_string())
+            .spawn(move || {
+                runtime.spawn(signalling::write_frames(track, frames_rx, fps));
+                let result = runtime.block_on(signalling.serve(listener, shutdown_rx));
+                if let Err(e) = result {
+                    eprintln!("❌ WebRTC output: {}", e);
+                }
+                runtime.shutdown_background();
+            })
+            .map_err(|e| format!("Failed to spawn WebRTC thread: {}", e))?;
+
+        eprintln!("🌐 WebRTC viewers on http://0.0.0.0:{}/", port);
+        Ok(Self {
+            frames,
+            shutdown: std::sync::Mutex::new(Some(shutdown)),
+            keyframe_requested,
+        })
+    }
+
+    /// Hand an encoded H.264 access unit to every browser
+    pub fn publish(&self, access_unit: &[u8]) {
+        if !crate::video_decoder::is_h264_payload(access_unit) {
+            return;
+        }
+        // Full queue: the next keyframe catches viewers up
+        if self.frames.try_send(access_unit.to_vec()).is_err() {
+            self.keyframe_requested.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stop(&self) {
+        if let Some(shutdown) = self.shutdown.lock().unwrap().take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+#[cfg(not(feature = "webrtc"))]
+impl WebRtcOutput {
+    pub fn start(_port: u16, _fps: u32, _keyframe_requested: Arc<AtomicBool>) -> Result<Self, String> {
+        Err("WebRTC not compiled in (enable the `webrtc` feature)".to_string())
+    }
+
+    pub fn publish(&self, _access_unit: &[u8]) {}
+
+    pub fn stop(&self) {}
+}
+
+#[cfg(feature = "webrtc")]
+mod signalling {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use axum::{extract::State, http::StatusCode, response::Html, routing::{get, post}, Router};
+    use webrtc::api::interceptor_registry::register_default_interceptors;
+    use webrtc::api::media_engine::MediaEngine;
+    use webrtc::api::{APIBuilder, API};
+    use webrtc::interceptor::registry::Registry;
+    use webrtc::media::Sample;
+    use webrtc::peer_connection::configuration::RTCConfiguration;
+    use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+    use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+    use webrtc::peer_connection::RTCPeerConnection;
+    use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+    use webrtc::track::track_local::TrackLocal;
+
+    #[derive(Clone)]
+    pub struct Signalling {
+        api: Arc<API>,
+        track: Arc<TrackLocalStaticSample>,
+        peers: Arc<tokio::sync::Mutex<Vec<Arc<RTCPeerConnection>>>>,
+        keyframe_requested: Arc<AtomicBool>,
+    }
+
+    impl Signalling {
+        pub fn new(track: Arc<TrackLocalStaticSample>, keyframe_requested: Arc<AtomicBool>) -> Result<Self, String> {
+            let mut media_engine = MediaEngine::default();
+            media_engine.register_default_codecs()
+                .map_err(|e| format!("Failed to register codecs: {}", e))?;
+            let registry = register_default_interceptors(Registry::new(), &mut media_engine)
+                .map_err(|e| format!("Failed to register interceptors: {}", e))?;
+            let api = APIBuilder::new()
+                .with_media_engine(media_engine)
+                .with_interceptor_registry(registry)
+                .build();
+            Ok(Self {
+                api: Arc::new(api),
+                track,
+                peers: Arc::new(tokio::sync::Mutex::new(Vec::new())),
+                keyframe_requested,
+            })
+        }
+
+        pub async fn serve(
+            self,
+            listener: tokio::net::TcpListener,
+            shutdown: tokio::sync::oneshot::Receiver<()>,
+        ) -> Result<(), String> {
+            let peers = self.peers.clone();
+            let router = Router::new()
+                .route("/", get(viewer_page))
+                .route("/offer", post(offer))
+                .with_state(self);
+            let result = axum::serve(listener, router)
+                .with_graceful_shutdown(async {
+                    let _ = shutdown.await;
+                })
+                .await
+                .map_err(|e| format!("WebRTC server error: {}", e));
+            for peer in peers.lock().await.drain(..) {
+                let _ = peer.close().await;
+            }
+            result
+        }
+
+        /// Answer a browser's offer with the stream track, once our candidates are gathered
+        async fn answer(&self, sdp: String) -> Result<String, String> {
+            let peer = Arc::new(self.api.new_peer_connection(RTCConfiguration::default()).await
+                .map_err(|e| format!("Failed to create peer connection: {}", e))?);
+            let sender = peer.add_track(self.track.clone() as Arc<dyn TrackLocal + Send + Sync>).await
+                .map_err(|e| format!("Failed to add track: {}", e))?;
+            // RTCP has to be read for the interceptors (NACK, reports) to work
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 1500];
+                while sender.read(&mut buf).await.is_ok() {}
+            });
+
+            let keyframe_requested = self.keyframe_requested.clone();
+            peer.on_peer_connection_state_change(Box::new(move |state| {
+                match state {
+                    RTCPeerConnectionState::Connected => {
+                        eprintln!("🌐 WebRTC viewer connected");
+                        keyframe_requested.store(true, Ordering::Relaxed);
+                    }
+                    RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed => {
+                        eprintln!("🌐 WebRTC viewer left");
+                    }
+                    _ => {}
+                }
+                Box::pin(async {})
+            }));
+
+            let offer = RTCSessionDescription::offer(sdp)
+                .map_err(|e| format!("Invalid offer: {}", e))?;
+            peer.set_remote_description(offer).await
+                .map_err(|e| format!("Invalid offer: {}", e))?;
+            let answer = peer.create_answer(None).await
+                .map_err(|e| format!("Failed to create answer: {}", e))?;
+            let mut gathered = peer.gathering_complete_promise().await;
+            peer.set_local_description(answer).await
+                .map_err(|e| format!("Failed to set answer: {}", e))?;
+            let _ = gathered.recv().await;
+            let local = peer.local_description().await
+                .ok_or("No local description")?;
+
+            let mut peers = self.peers.lock().await;
+            peers.retain(|peer| !matches!(
+                peer.connection_state(),
+                RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed
+            ));
+            peers.push(peer);
+            Ok(local.sdp)
+        }
+    }
+
+    /// Feed queued access units to the shared track, timed by when they arrived
+    pub async fn write_frames(
+        track: Arc<TrackLocalStaticSample>,
+        mut frames: tokio::sync::mpsc::Receiver<Vec<u8>>,
+        fps: u32,
+    ) {
+        let mut last_frame: Option<Instant> = None;
+        while let Some(frame) = frames.recv().await {
+            let now = Instant::now();
+            let duration = last_frame
+                .map_or(Duration::from_secs(1) / fps.max(1), |last| now.duration_since(last));
+            last_frame = Some(now);
+            let sample = Sample {
+                data: frame.into(),
+                duration,
+                ..Default::default()
+            };
+            // No viewers bound to the track is not an error worth reporting
+            let _ = track.write_sample(&sample).await;
+        }
+    }
+
+    async fn offer(State(signalling): State<Signalling>, sdp: String) -> Result<String, (StatusCode, String)> {
+        signalling.answer(sdp).await
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))
+    }
+
+    async fn viewer_page() -> Html<&'static str> {
+        Html(VIEWER_HTML)
+    }
+
+    // Waits for its own candidates too, the server doesn't trickle
+    const VIEWER_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>SmartLab ScreenShare</title>
+<style>html,body{margin:0;height:100%;background:#000}video{width:100%;height:100%;object-fit:contain}</style>
+</head>
+<body>
+<video id="screen" autoplay muted playsinline></video>
+<script>
+async function connect() {
+  const pc = new RTCPeerConnection();
+  pc.addTransceiver("video", { direction: "recvonly" });
+  pc.ontrack = (event) => {
+    document.getElementById("screen").srcObject = event.streams[0] || new MediaStream([event.track]);
+  };
+  pc.onconnectionstatechange = () => {
+    if (pc.connectionState === "failed" || pc.connectionState === "closed") {
+      pc.close();
+      setTimeout(connect, 1000);
+    }
+  };
+  await pc.setLocalDescription(await pc.createOffer());
+  await new Promise((resolve) => {
+    if (pc.iceGatheringState === "complete") return resolve();
+    pc.onicegatheringstatechange = () => pc.iceGatheringState === "complete" && resolve();
+  });
+  const response = await fetch("/offer", { method: "POST", body: pc.localDescription.sdp });
+  if (!response.ok) {
+    pc.close();
+    setTimeout(connect, 1000);
+    return;
+  }
+  await pc.setRemoteDescription({ type: "answer", sdp: await response.text() });
+}
+connect();
+</script>
+</body>
+</html>
+"#;
+}
@@ -0,0 +1,357 @@
+// Window Capture - share one application window instead of the whole desktop
+//   Windows: windows are listed with EnumWindows and drawn with PrintWindow,
+//            which keeps working while other windows cover them
+//   Linux:   windows are listed with `wmctrl -lG` (X11) and cropped out of the
+//            display capture, so whatever covers them is shared too
+//   macOS:   not supported
+//
+// The stream keeps the size it started with; a window resized mid-stream is
+// scaled to fit and letterboxed.
+
+use std::time::{Duration, Instant};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use crate::frame_source::FrameSource;
+use crate::screen_capture::{self, RawFrame};
+
+// How often the window's position is looked up again (Linux crops by it)
+const LOOKUP_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WindowInfo {
+    /// HWND on Windows, X11 window id on Linux
+    pub id: u64,
+    pub title: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Which window to share: `{"id": 1234}` or `{"title": "Notepad"}`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowSelector {
+    Id(u64),
+    /// First window whose title contains this, ignoring case
+    Title(String),
+}
+
+/// Visible windows with a title, front to back where the platform says
+pub fn list_windows() -> Result<Vec<WindowInfo>, String> {
+    platform::list()
+}
+
+pub fn find_window(selector: &WindowSelector) -> Result<WindowInfo, String> {
+    let windows = list_windows()?;
+    let found = match selector {
+        WindowSelector::Id(id) => windows.into_iter().find(|w| w.id == *id),
+        WindowSelector::Title(title) => {
+            let title = title.to_lowercase();
+            windows.into_iter().find(|w| w.title.to_lowercase().contains(&title))
+        }
+    };
+    found.ok_or_else(|| match selector {
+        WindowSelector::Id(id) => format!("Window {} not found", id),
+        WindowSelector::Title(title) => format!("No window titled \"{}\"", title),
+    })
+}
+
+/// Frame source following one window
+pub struct WindowCapture {
+    window: WindowInfo,
+    looked_up_at: Instant,
+    width: usize,
+    height: usize,
+}
+
+impl WindowCapture {
+    /// Scaled to at most `max_width`, 0 for the window's own size
+    pub fn new(selector: &WindowSelector, max_width: u32) -> Result<Self, String> {
+        let window = find_window(selector)?;
+        let (width, height) = screen_capture::output_size(window.width as usize, window.height as usize, max_width);
+        if width == 0 || height == 0 {
+            return Err(format!("Window \"{}\" is too small to share", window.title));
+        }
+        eprintln!("🪟 Sharing window \"{}\" ({}x{})", window.title, window.width, window.height);
+        Ok(Self { window, looked_up_at: Instant::now(), width, height })
+    }
+
+    /// Stream dimensions, fixed for the session
+    pub fn output_size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+}
+
+impl FrameSource for WindowCapture {
+    fn next_frame(&mut self) -> Result<Option<RawFrame>, String> {
+        if self.looked_up_at.elapsed() >= LOOKUP_INTERVAL {
+            platform::refresh(&mut self.window)?;
+            self.looked_up_at = Instant::now();
+        }
+        let Some(image) = platform::grab(&self.window)? else {
+            return Ok(None);
+        };
+        let captured_at = Instant::now();
+        Ok(Some(RawFrame {
+            rgba: fit(image, self.width as u32, self.height as u32).into_raw(),
+            width: self.width,
+            height: self.height,
+            captured_at,
+            dirty: None,
+            moves: Vec::new(),
+        }))
+    }
+}
+
+// Scale to fit `width` x `height` keeping the aspect ratio, black bars around
+fn fit(image: RgbaImage, width: u32, height: u32) -> RgbaImage {
+    if image.dimensions() == (width, height) {
+        return image;
+    }
+    let scale = (width as f32 / image.width() as f32).min(height as f32 / image.height() as f32);
+    let scaled_width = ((image.width() as f32 * scale) as u32).clamp(1, width);
+    let scaled_height = ((image.height() as f32 * scale) as u32).clamp(1, height);
+    let scaled = image::imageops::resize(&image, scaled_width, scaled_height, image::imageops::FilterType::Triangle);
+
+    let mut canvas = RgbaImage::from_pixel(width, height, image::Rgba([0, 0, 0, 255]));
+    image::imageops::replace(
+        &mut canvas,
+        &scaled,
+        ((width - scaled_width) / 2) as i64,
+        ((height - scaled_height) / 2) as i64,
+    );
+    canvas
+}
+
+// One line of `wmctrl -lG`: id, desktop, x, y, width, height, host, title
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_wmctrl_line(line: &str) -> Option<WindowInfo> {
+    let mut rest = line;
+    let mut fields = Vec::with_capacity(7);
+    for _ in 0..7 {
+        let (field, tail) = rest.trim_start().split_once(char::is_whitespace)?;
+        fields.push(field);
+        rest = tail;
+    }
+    let title = rest.trim().to_string();
+    // Desktop -1 marks sticky windows, which are normal windows too
+    fields[1].parse::<i32>().ok()?;
+    Some(WindowInfo {
+        id: u64::from_str_radix(fields[0].trim_start_matches("0x"), 16).ok()?,
+        title,
+        x: fields[2].parse().ok()?,
+        y: fields[3].parse().ok()?,
+        width: fields[4].parse().ok()?,
+        height: fields[5].parse().ok()?,
+    })
+    .filter(|w| !w.title.is_empty() && w.width > 0 && w.height > 0)
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::WindowInfo;
+    use image::RgbaImage;
+    use windows::Win32::{
+        Foundation::*,
+        Graphics::Gdi::*,
+        Storage::Xps::{PrintWindow, PRINT_WINDOW_FLAGS},
+        UI::WindowsAndMessaging::*,
+    };
+
+    // Also captures windows drawn with DirectX (Windows 8.1+)
+    const PW_RENDERFULLCONTENT: u32 = 2;
+
+    unsafe extern "system" fn collect(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let windows = &mut *(lparam.0 as *mut Vec<WindowInfo>);
+        if IsWindowVisible(hwnd).as_bool() && !IsIconic(hwnd).as_bool() {
+            let mut title = [0u16; 512];
+            let len = GetWindowTextW(hwnd, &mut title);
+            let mut rect = RECT::default();
+            if len > 0 && GetWindowRect(hwnd, &mut rect).is_ok() && rect.right > rect.left && rect.bottom > rect.top {
+                windows.push(WindowInfo {
+                    id: hwnd.0 as u64,
+                    title: String::from_utf16_lossy(&title[..len as usize]),
+                    x: rect.left,
+                    y: rect.top,
+                    width: (rect.right - rect.left) as u32,
+                    height: (rect.bottom - rect.top) as u32,
+                });
+            }
+        }
+        TRUE
+    }
+
+    pub fn list() -> Result<Vec<WindowInfo>, String> {
+        let mut windows: Vec<WindowInfo> = Vec::new();
+        unsafe { EnumWindows(Some(collect), LPARAM(&mut windows as *mut _ as isize)) }
+            .map_err(|e| format!("Failed to list windows: {}", e))?;
+        Ok(windows)
+    }
+
+    // Minimized windows stay in the stream, unlike in the list
+    pub fn refresh(window: &mut WindowInfo) -> Result<(), String> {
+        let hwnd = HWND(window.id as *mut _);
+        unsafe {
+            if !IsWindow(hwnd).as_bool() {
+                return Err(format!("Window \"{}\" was closed", window.title));
+            }
+            let mut rect = RECT::default();
+            if GetWindowRect(hwnd, &mut rect).is_ok() && rect.right > rect.left && rect.bottom > rect.top {
+                window.x = rect.left;
+                window.y = rect.top;
+                window.width = (rect.right - rect.left) as u32;
+                window.height = (rect.bottom - rect.top) as u32;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn grab(window: &WindowInfo) -> Result<Option<RgbaImage>, String> {
+        let hwnd = HWND(window.id as *mut _);
+        unsafe {
+            if !IsWindow(hwnd).as_bool() {
+                return Err(format!("Window \"{}\" was closed", window.title));
+            }
+            // Minimized windows have nothing to draw, keep the last frame
+            if IsIconic(hwnd).as_bool() {
+                return Ok(None);
+            }
+            let mut rect = RECT::default();
+            GetWindowRect(hwnd, &mut rect).map_err(|e| format!("Failed to get window size: {}", e))?;
+            let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+            if width <= 0 || height <= 0 {
+                return Ok(None);
+            }
+
+            let screen = GetDC(HWND::default());
+            let dc = CreateCompatibleDC(screen);
+            let bitmap = CreateCompatibleBitmap(screen, width, height);
+            let previous = SelectObject(dc, bitmap);
+            let printed = PrintWindow(hwnd, dc, PRINT_WINDOW_FLAGS(PW_RENDERFULLCONTENT)).as_bool();
+
+            // Top-down 32-bit rows, no padding
+            let mut info = BITMAPINFO {
+                bmiHeader: BITMAPINFOHEADER {
+                    biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                    biWidth: width,
+                    biHeight: -height,
+                    biPlanes: 1,
+                    biBitCount: 32,
+                    biCompression: BI_RGB.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut bgra = vec![0u8; width as usize * height as usize * 4];
+            let lines = GetDIBits(dc, bitmap, 0, height as u32, Some(bgra.as_mut_ptr() as *mut _), &mut info, DIB_RGB_COLORS);
+
+            SelectObject(dc, previous);
+            DeleteObject(bitmap).ok();
+            DeleteDC(dc).ok();
+            ReleaseDC(HWND::default(), screen);
+
+            if !printed || lines != height {
+                return Err(format!("Failed to draw window \"{}\"", window.title));
+            }
+            for px in bgra.chunks_exact_mut(4) {
+                px.swap(0, 2);
+                px[3] = 255;
+            }
+            RgbaImage::from_raw(width as u32, height as u32, bgra)
+                .map(Some)
+                .ok_or_else(|| "Failed to create image from window".to_string())
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::WindowInfo;
+    use image::RgbaImage;
+    use std::process::Command;
+
+    pub fn list() -> Result<Vec<WindowInfo>, String> {
+        let output = Command::new("wmctrl")
+            .arg("-lG")
+            .output()
+            .map_err(|e| format!("Failed to list windows (is wmctrl installed?): {}", e))?;
+        if !output.status.success() {
+            return Err(format!("wmctrl failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(super::parse_wmctrl_line)
+            .collect())
+    }
+
+    pub fn refresh(window: &mut WindowInfo) -> Result<(), String> {
+        *window = list()?
+            .into_iter()
+            .find(|w| w.id == window.id)
+            .ok_or_else(|| format!("Window \"{}\" was closed", window.title))?;
+        Ok(())
+    }
+
+    // Cropped from the X screen, which spans every monitor
+    pub fn grab(window: &WindowInfo) -> Result<Option<RgbaImage>, String> {
+        let Some(screen) = crate::screen_capture::capture_full_resolution(None)? else {
+            return Ok(None);
+        };
+        let left = window.x.clamp(0, screen.width() as i32) as u32;
+        let top = window.y.clamp(0, screen.height() as i32) as u32;
+        let right = (window.x + window.width as i32).clamp(0, screen.width() as i32) as u32;
+        let bottom = (window.y + window.height as i32).clamp(0, screen.height() as i32) as u32;
+        if right <= left || bottom <= top {
+            return Err(format!("Window \"{}\" is off screen", window.title));
+        }
+        Ok(Some(image::imageops::crop_imm(&screen, left, top, right - left, bottom - top).to_image()))
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    use super::WindowInfo;
+    use image::RgbaImage;
+
+    pub fn list() -> Result<Vec<WindowInfo>, String> {
+        Err("Window capture is not supported on this platform".to_string())
+    }
+
+    pub fn refresh(_window: &mut WindowInfo) -> Result<(), String> {
+        Err("Window capture is not supported on this platform".to_string())
+    }
+
+    pub fn grab(_window: &WindowInfo) -> Result<Option<RgbaImage>, String> {
+        Err("Window capture is not supported on this platform".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_wmctrl() {
+        let window = parse_wmctrl_line("0x03a00003  0 120  80   800  600  host Untitled - Text  Editor").unwrap();
+        assert_eq!(window.id, 0x03a00003);
+        assert_eq!((window.x, window.y, window.width, window.height), (120, 80, 800, 600));
+        assert_eq!(window.title, "Untitled - Text  Editor");
+
+        assert_eq!(parse_wmctrl_line("0x01 -1 0 0 1920 24 host panel").unwrap().title, "panel");
+        // Untitled windows can't be picked
+        assert!(parse_wmctrl_line("0x02 0 0 0 100 100 host").is_none());
+    }
+
+    #[test]
+    fn test_fit_letterboxes() {
+        let wide = RgbaImage::from_pixel(100, 50, image::Rgba([255, 255, 255, 255]));
+        let fitted = fit(wide, 50, 50);
+        assert_eq!(fitted.dimensions(), (50, 50));
+        assert_eq!(fitted.get_pixel(25, 5)[0], 0);
+        assert_eq!(fitted.get_pixel(25, 25)[0], 255);
+
+        let selector: WindowSelector = serde_json::from_str(r#"{"title": "notepad"}"#).unwrap();
+        assert_eq!(selector, WindowSelector::Title("notepad".to_string()));
+    }
+}
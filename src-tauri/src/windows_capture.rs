@@ -1,155 +1,237 @@
 // Windows.Graphics.Capture implementation for better performance on Windows 10+
 // Note: This requires Windows 10 version 1803 (April 2018 Update) or later
+// The primary monitor is captured into a free-threaded frame pool that is
+// polled for its newest frame, so no dispatcher queue or event handler is
+// needed. Windows draws its yellow privacy border around the display while the
+// session runs. Frames are copied to a CPU-readable texture and converted like
+// DXGI's, see capture_format.rs.
+
 #[cfg(target_os = "windows")]
-use windows::Graphics::Capture::{GraphicsCaptureItem, Direct3D11CaptureFramePool, GraphicsCaptureSession};
-#[cfg(target_os = "windows")]
-use windows::Graphics::DirectX::{Direct3D11::IDirect3DDevice, DirectXPixelFormat};
+use windows::{
+    core::{factory, Interface},
+    Graphics::Capture::{Direct3D11CaptureFrame, Direct3D11CaptureFramePool, GraphicsCaptureItem, GraphicsCaptureSession},
+    Graphics::DirectX::{Direct3D11::IDirect3DDevice, DirectXPixelFormat},
+    Graphics::SizeInt32,
+    Win32::Foundation::{HMODULE, POINT},
+    Win32::Graphics::{
+        Direct3D::D3D_DRIVER_TYPE_HARDWARE,
+        Direct3D11::*,
+        Dxgi::IDXGIDevice,
+        Gdi::{MonitorFromPoint, MONITOR_DEFAULTTOPRIMARY},
+    },
+    Win32::System::WinRT::{
+        Direct3D11::{CreateDirect3D11DeviceFromDXGIDevice, IDirect3DDxgiInterfaceAccess},
+        Graphics::Capture::IGraphicsCaptureItemInterop,
+        RoInitialize, RO_INIT_MULTITHREADED,
+    },
+};
 #[cfg(target_os = "windows")]
-use windows::Foundation::TypedEventHandler;
+use image::RgbaImage;
 #[cfg(target_os = "windows")]
-use std::sync::{Arc, Mutex};
+use crate::capture_format::CaptureFormat;
+
+// Two buffers: one being read while Windows fills the other
 #[cfg(target_os = "windows")]
-use image::{ImageBuffer, RgbaImage, DynamicImage};
+const FRAME_BUFFERS: i32 = 2;
 #[cfg(target_os = "windows")]
-use std::io::Cursor;
+const PIXEL_FORMAT: DirectXPixelFormat = DirectXPixelFormat::B8G8R8A8UIntNormalized;
 
 #[cfg(target_os = "windows")]
 pub struct WindowsScreenCapture {
+    device: ID3D11Device,
+    context: ID3D11DeviceContext,
+    /// `device` as WinRT wants it, for recreating the pool
+    direct3d: IDirect3DDevice,
+    item: GraphicsCaptureItem,
+    frame_pool: Direct3D11CaptureFramePool,
     session: Option<GraphicsCaptureSession>,
-    frame_pool: Option<Direct3D11CaptureFramePool>,
-    last_frame: Arc<Mutex<Option<Vec<u8>>>>,
+    /// Size the pool's buffers were made for
+    size: SizeInt32,
+    /// CPU-readable copy of the frame, kept while its size stays the same
+    staging: Option<(ID3D11Texture2D, D3D11_TEXTURE2D_DESC)>,
 }
 
 #[cfg(target_os = "windows")]
 impl WindowsScreenCapture {
+    /// Device, capture item and frame pool for the primary monitor
     pub fn new() -> Result<Self, String> {
-        Ok(Self {
-            session: None,
-            frame_pool: None,
-            last_frame: Arc::new(Mutex::new(None)),
-        })
+        unsafe {
+            // 1. WinRT on this thread; already initialized, even as STA, is fine too
+            let _ = RoInitialize(RO_INIT_MULTITHREADED);
+
+            // 2. Capture item for the primary monitor
+            let monitor = MonitorFromPoint(POINT { x: 0, y: 0 }, MONITOR_DEFAULTTOPRIMARY);
+            let interop = factory::<GraphicsCaptureItem, IGraphicsCaptureItemInterop>()
+                .map_err(|e| format!("Failed to get capture item interop: {:?}", e))?;
+            let item: GraphicsCaptureItem = interop.CreateForMonitor(monitor)
+                .map_err(|e| format!("Failed to create capture item for the primary monitor: {:?}", e))?;
+
+            // 3. Direct3D11 device, BGRA support is required by the capture API
+            let mut device: Option<ID3D11Device> = None;
+            let mut context: Option<ID3D11DeviceContext> = None;
+            D3D11CreateDevice(
+                None,
+                D3D_DRIVER_TYPE_HARDWARE,
+                HMODULE::default(),
+                D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+                None,
+                D3D11_SDK_VERSION,
+                Some(&mut device),
+                None,
+                Some(&mut context),
+            ).map_err(|e| format!("Failed to create D3D11 device: {:?}", e))?;
+            let device = device.ok_or("Device is None")?;
+            let context = context.ok_or("Context is None")?;
+
+            let dxgi_device: IDXGIDevice = device.cast()
+                .map_err(|e| format!("Failed to cast to IDXGIDevice: {:?}", e))?;
+            let direct3d: IDirect3DDevice = CreateDirect3D11DeviceFromDXGIDevice(&dxgi_device)
+                .and_then(|inspectable| inspectable.cast())
+                .map_err(|e| format!("Failed to wrap the D3D11 device for WinRT: {:?}", e))?;
+
+            // 4. Frame pool, polled from whichever thread captures
+            let size = item.Size()
+                .map_err(|e| format!("Failed to get capture item size: {:?}", e))?;
+            let frame_pool = Direct3D11CaptureFramePool::CreateFreeThreaded(&direct3d, PIXEL_FORMAT, FRAME_BUFFERS, size)
+                .map_err(|e| format!("Failed to create frame pool: {:?}", e))?;
+
+            eprintln!("🖥️  Windows.Graphics.Capture primary display: {}x{}", size.Width, size.Height);
+
+            Ok(Self {
+                device,
+                context,
+                direct3d,
+                item,
+                frame_pool,
+                session: None,
+                size,
+                staging: None,
+            })
+        }
     }
 
-    /// Initialize Windows.Graphics.Capture
-    /// This is more efficient than scrap but requires Windows 10 1803+
+    /// Start the capture session, Windows shows its capture border from now on
     pub fn start_capture(&mut self) -> Result<(), String> {
-        eprintln!("⚠️  Windows.Graphics.Capture requires complex COM initialization");
-        eprintln!("    Current implementation: Using scrap as stable fallback");
-        eprintln!("    For full Windows.Graphics.Capture support:");
-        eprintln!("    1. Initialize COM apartment");
-        eprintln!("    2. Create Direct3D11 device");
-        eprintln!("    3. Create GraphicsCaptureItem for primary monitor");
-        eprintln!("    4. Setup frame pool and capture session");
-        eprintln!("    See: https://docs.microsoft.com/en-us/windows/uwp/audio-video-camera/screen-capture");
-        
-        // For now, return error to fallback to scrap
-        // Full implementation would require:
-        // - windows-rs bindings for COM initialization
-        // - Direct3D11 device creation
-        // - Monitor enumeration via DXGI
-        // - GraphicsCaptureItem creation
-        Err("Windows.Graphics.Capture initialization deferred - using scrap".to_string())
+        let session = self.frame_pool.CreateCaptureSession(&self.item)
+            .map_err(|e| format!("Failed to create capture session: {:?}", e))?;
+        session.StartCapture()
+            .map_err(|e| format!("Failed to start capture session: {:?}", e))?;
+        self.session = Some(session);
+        Ok(())
     }
 
-    pub fn get_frame(&self) -> Result<Vec<u8>, String> {
-        let frame = self.last_frame.lock().unwrap();
-        frame.clone().ok_or_else(|| "No frame available".to_string())
+    /// Leave the pointer out of the frames (Windows 10 2004 or later, drawn otherwise)
+    pub fn hide_cursor(&self) {
+        if let Some(session) = &self.session {
+            if session.SetIsCursorCaptureEnabled(false).is_err() {
+                eprintln!("⚠️  Windows.Graphics.Capture can't leave the pointer out on this Windows version");
+            }
+        }
+    }
+
+    /// The newest frame at full resolution, `None` when none arrived since the last call
+    pub fn capture_frame(&mut self) -> Result<Option<RgbaImage>, String> {
+        // Frames that queued up meanwhile are already stale
+        let mut newest: Option<Direct3D11CaptureFrame> = None;
+        while let Ok(frame) = self.frame_pool.TryGetNextFrame() {
+            if let Some(stale) = newest.replace(frame) {
+                let _ = stale.Close();
+            }
+        }
+        let Some(frame) = newest else {
+            return Ok(None);
+        };
+
+        let content_size = frame.ContentSize()
+            .map_err(|e| format!("Failed to get frame size: {:?}", e));
+        let image = match content_size {
+            // The display changed size: new buffers, and this frame only partly fills the old ones
+            Ok(size) if (size.Width, size.Height) != (self.size.Width, self.size.Height) => {
+                let _ = frame.Close();
+                self.frame_pool.Recreate(&self.direct3d, PIXEL_FORMAT, FRAME_BUFFERS, size)
+                    .map_err(|e| format!("Failed to recreate frame pool: {:?}", e))?;
+                eprintln!("🖥️  Windows.Graphics.Capture display resized to {}x{}", size.Width, size.Height);
+                self.size = size;
+                return Ok(None);
+            }
+            Ok(_) => unsafe { self.read_frame(&frame) },
+            Err(e) => Err(e),
+        };
+        let _ = frame.Close();
+        image.map(Some)
+    }
+
+    // Copy the frame's texture to the CPU and convert it to RGBA
+    unsafe fn read_frame(&mut self, frame: &Direct3D11CaptureFrame) -> Result<RgbaImage, String> {
+        let access: IDirect3DDxgiInterfaceAccess = frame.Surface()
+            .and_then(|surface| surface.cast())
+            .map_err(|e| format!("Failed to get frame surface: {:?}", e))?;
+        let texture: ID3D11Texture2D = access.GetInterface()
+            .map_err(|e| format!("Failed to get frame texture: {:?}", e))?;
+
+        let mut texture_desc = D3D11_TEXTURE2D_DESC::default();
+        texture.GetDesc(&mut texture_desc);
+        texture_desc.Usage = D3D11_USAGE_STAGING;
+        texture_desc.BindFlags = D3D11_BIND_FLAG(0);
+        texture_desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ;
+        texture_desc.MiscFlags = D3D11_RESOURCE_MISC_FLAG(0);
+
+        let reusable = self.staging.as_ref().is_some_and(|(_, desc)| {
+            (desc.Width, desc.Height, desc.Format) == (texture_desc.Width, texture_desc.Height, texture_desc.Format)
+        });
+        if !reusable {
+            // The old one goes first, so there are never two
+            self.staging = None;
+            let staging = self.device.CreateTexture2D(&texture_desc, None)
+                .map_err(|e| format!("Failed to create staging texture: {:?}", e))?;
+            self.staging = Some((staging, texture_desc));
+        }
+        let (staging, _) = self.staging.as_ref().ok_or("Staging texture missing")?;
+
+        self.context.CopyResource(staging, &texture);
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        self.context.Map(staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))
+            .map_err(|e| format!("Failed to map texture: {:?}", e))?;
+
+        let (width, height) = (texture_desc.Width as usize, texture_desc.Height as usize);
+        let row_pitch = mapped.RowPitch as usize;
+        let data = std::slice::from_raw_parts(mapped.pData as *const u8, row_pitch * height);
+        let rgba = CaptureFormat::Bgra8.to_rgba(data, row_pitch, width, height);
+        self.context.Unmap(staging, 0);
+
+        RgbaImage::from_raw(width as u32, height as u32, rgba.map_err(|e| e.to_string())?)
+            .ok_or_else(|| "Failed to create image buffer from captured frame".to_string())
     }
 
     pub fn stop_capture(&mut self) {
         if let Some(session) = self.session.take() {
             let _ = session.Close();
         }
-        self.frame_pool = None;
+        let _ = self.frame_pool.Close();
     }
 }
 
-/// Simple function to check if Windows.Graphics.Capture is available
 #[cfg(target_os = "windows")]
-pub fn is_windows_graphics_capture_available() -> bool {
-    // Check Windows version (requires Windows 10 1803+)
-    // For simplicity, always return false for now
-    // Real implementation would check: ntdll.RtlGetVersion() >= 10.0.17134
-    false
+impl Drop for WindowsScreenCapture {
+    fn drop(&mut self) {
+        self.stop_capture();
+    }
 }
 
-/// Platform-specific screen capture with automatic fallback
-/// Windows: Tries Windows.Graphics.Capture, falls back to scrap
-/// macOS/Linux: Uses scrap directly
-pub fn capture_screen_platform_specific() -> Result<Vec<u8>, String> {
-    #[cfg(target_os = "windows")]
-    {
-        // Check if Windows.Graphics.Capture is available
-        if is_windows_graphics_capture_available() {
-            // Try Windows.Graphics.Capture (better performance)
-            static mut WINDOWS_CAPTURE: Option<WindowsScreenCapture> = None;
-            static mut TRIED_INIT: bool = false;
-            
-            unsafe {
-                if !TRIED_INIT {
-                    match WindowsScreenCapture::new() {
-                        Ok(mut capture) => {
-                            match capture.start_capture() {
-                                Ok(_) => {
-                                    eprintln!("✅ Using Windows.Graphics.Capture (high performance)");
-                                    WINDOWS_CAPTURE = Some(capture);
-                                }
-                                Err(e) => {
-                                    eprintln!("⚠️  Windows.Graphics.Capture init failed: {}", e);
-                                    eprintln!("    Falling back to scrap library");
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("⚠️  Failed to create WindowsScreenCapture: {}", e);
-                        }
-                    }
-                    TRIED_INIT = true;
-                }
-                
-                // Try to use Windows.Graphics.Capture if initialized
-                if let Some(ref capture) = WINDOWS_CAPTURE {
-                    if let Ok(frame) = capture.get_frame() {
-                        return Ok(frame);
-                    }
-                }
-            }
-        }
-        
-        // Fallback to scrap (stable, cross-platform)
-        crate::screen_capture::capture_screen()
-    }
-    
-    #[cfg(not(target_os = "windows"))]
-    {
-        crate::screen_capture::capture_screen()
-    }
+/// Whether Windows.Graphics.Capture can be used, Windows 10 1803 or later
+#[cfg(target_os = "windows")]
+pub fn is_windows_graphics_capture_available() -> bool {
+    // Older Windows doesn't have the class at all, which errors too
+    GraphicsCaptureSession::IsSupported().unwrap_or(false)
 }
 
-/// Get a human-readable name of the capture method being used
-#[allow(dead_code)]
-pub fn get_capture_method_name() -> &'static str {
-    #[cfg(target_os = "windows")]
-    {
-        if is_windows_graphics_capture_available() {
-            "Windows.Graphics.Capture (Windows 10+)"
-        } else {
-            "scrap (fallback)"
-        }
-    }
-    
-    #[cfg(target_os = "macos")]
-    {
-        "scrap (macOS CoreGraphics)"
-    }
-    
-    #[cfg(target_os = "linux")]
-    {
-        "scrap (X11/Wayland)"
-    }
-    
-    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
-    {
-        "scrap (generic)"
+/// Windows.Graphics.Capture of the primary monitor, started and ready to poll
+#[cfg(target_os = "windows")]
+pub fn open_graphics_capture() -> Result<WindowsScreenCapture, String> {
+    if !is_windows_graphics_capture_available() {
+        return Err("Windows.Graphics.Capture needs Windows 10 1803 or later".to_string());
     }
+    let mut capture = WindowsScreenCapture::new()?;
+    capture.start_capture()?;
+    Ok(capture)
 }
@@ -0,0 +1,191 @@
+// Headless Receiver - re-serves the multicast stream over WebSocket
+// Thin web dashboards and signage browsers connect to ws://<host>:<port>/stream
+// and get one binary message per frame (plain JPEG, or a "TILE" delta payload)
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::{Html, Response},
+    routing::get,
+    Router,
+};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::net_interfaces::IpVersion;
+use crate::settings::PortMapping;
+use crate::udp_client::{FrameOutput, UdpClient};
+
+pub const DEFAULT_PORT: u16 = 9998;
+const CHANNEL_CAPACITY: usize = 8; // Slow viewers skip ahead instead of queueing
+
+/// Fan-out of reassembled frames to every connected WebSocket viewer
+#[derive(Clone)]
+pub struct FrameBroadcast {
+    tx: broadcast::Sender<Arc<Vec<u8>>>,
+    // Tile deltas are useless without a base frame, so new viewers get this first
+    last_keyframe: Arc<Mutex<Option<Arc<Vec<u8>>>>>,
+}
+
+impl FrameBroadcast {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            tx,
+            last_keyframe: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn publish(&self, payload: Vec<u8>, keyframe: bool) {
+        let payload = Arc::new(payload);
+        if keyframe {
+            *self.last_keyframe.lock().unwrap() = Some(payload.clone());
+        }
+        // No receivers is fine - nobody is watching yet
+        let _ = self.tx.send(payload);
+    }
+
+    /// Whether anyone is watching, so callers can skip preparing frames
+    pub fn has_viewers(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+
+    pub fn subscribe(&self) -> (Option<Arc<Vec<u8>>>, broadcast::Receiver<Arc<Vec<u8>>>) {
+        let keyframe = self.last_keyframe.lock().unwrap().clone();
+        (keyframe, self.tx.subscribe())
+    }
+}
+
+impl Default for FrameBroadcast {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Run the headless receiver until the process is killed
+pub fn run(port: u16, ip_version: IpVersion) -> Result<(), String> {
+    let runtime = tokio::runtime::Runtime::new()
+        .map_err(|e| format!("Failed to create runtime: {}", e))?;
+    runtime.block_on(serve(port, ip_version))
+}
+
+async fn serve(port: u16, ip_version: IpVersion) -> Result<(), String> {
+    let broadcast = FrameBroadcast::new();
+
+    let client = UdpClient::new(ip_version, None, None, PortMapping::default())?;
+    client.set_receive_buffer(crate::settings::DEFAULT_RECEIVE_BUFFER_KB);
+    client.start_receiving(FrameOutput::Broadcast(broadcast.clone()))?;
+
+    let router = Router::new()
+        .route("/", get(viewer_page))
+        .route("/stream", get(stream_socket))
+        .with_state(broadcast);
+
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port))
+        .await
+        .map_err(|e| format!("Failed to bind WebSocket server on port {}: {}", port, e))?;
+
+    eprintln!("📺 Headless receiver serving ws://0.0.0.0:{}/stream", port);
+
+    let result = axum::serve(listener, router)
+        .await
+        .map_err(|e| format!("WebSocket server error: {}", e));
+
+    client.stop();
+    result
+}
+
+async fn stream_socket(State(broadcast): State<FrameBroadcast>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| forward_frames(socket, broadcast))
+}
+
+async fn forward_frames(mut socket: WebSocket, broadcast: FrameBroadcast) {
+    let (keyframe, mut rx) = broadcast.subscribe();
+
+    if let Some(frame) = keyframe {
+        if socket.send(Message::Binary(frame.to_vec())).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        match rx.recv().await {
+            Ok(frame) => {
+                if socket.send(Message::Binary(frame.to_vec())).await.is_err() {
+                    break;
+                }
+            }
+            // Viewer fell behind - drop the backlog and carry on with live frames
+            Err(RecvError::Lagged(skipped)) => {
+                eprintln!("⚠️  WebSocket viewer lagging, skipped {} frames", skipped);
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+async fn viewer_page() -> Html<&'static str> {
+    Html(VIEWER_HTML)
+}
+
+// Minimal zero-install viewer: draws JPEG frames and composites tile deltas
+const VIEWER_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>SmartLab ScreenShare</title>
+<style>html,body{margin:0;height:100%;background:#000}canvas{width:100%;height:100%;object-fit:contain}</style>
+</head>
+<body>
+<canvas id="screen"></canvas>
+<script>
+const canvas = document.getElementById("screen");
+const ctx = canvas.getContext("2d");
+const jpeg = (bytes) => createImageBitmap(new Blob([bytes], { type: "image/jpeg" }));
+
+async function drawTiles(buf) {
+  const view = new DataView(buf);
+  if (canvas.width !== view.getUint16(4) || canvas.height !== view.getUint16(6)) return;
+  const tiles = [];
+  let offset = 10;
+  for (let i = 0, n = view.getUint16(8); i < n; i++) {
+    const len = view.getUint32(offset + 8);
+    tiles.push({ x: view.getUint16(offset), y: view.getUint16(offset + 2), bitmap: jpeg(new Uint8Array(buf, offset + 12, len)) });
+    offset += 12 + len;
+  }
+  // Scrolled regions move first, then the tiles land on top
+  for (let i = 0, n = offset + 2 <= buf.byteLength ? view.getUint16(offset) : 0; i < n; i++) {
+    const at = offset + 2 + i * 12, v = (k) => view.getUint16(at + k * 2);
+    ctx.drawImage(canvas, v(0), v(1), v(4), v(5), v(2), v(3), v(4), v(5));
+  }
+  for (const tile of tiles) {
+    const bitmap = await tile.bitmap;
+    ctx.drawImage(bitmap, tile.x, tile.y);
+    bitmap.close();
+  }
+}
+
+function connect() {
+  const ws = new WebSocket(`ws://${location.host}/stream`);
+  ws.binaryType = "arraybuffer";
+  ws.onmessage = async (event) => {
+    const bytes = new Uint8Array(event.data);
+    if (bytes[0] === 0x54 && bytes[1] === 0x49 && bytes[2] === 0x4c && bytes[3] === 0x45) {
+      await drawTiles(event.data);
+      return;
+    }
+    const bitmap = await jpeg(bytes);
+    canvas.width = bitmap.width;
+    canvas.height = bitmap.height;
+    ctx.drawImage(bitmap, 0, 0);
+    bitmap.close();
+  };
+  ws.onclose = () => setTimeout(connect, 1000);
+}
+connect();
+</script>
+</body>
+</html>
+"#;
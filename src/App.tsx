@@ -1,5 +1,6 @@
 import { useState, useEffect, useRef } from "react";
-import { invoke } from "@tauri-apps/api/core";
+import type { MouseEvent as ReactMouseEvent, WheelEvent as ReactWheelEvent } from "react";
+import { Channel, invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import "./App.css";
 
@@ -11,6 +12,110 @@ interface DisplayInfo {
   height: number;
 }
 
+interface ViewerSettings {
+  smoothing: boolean;
+  stale_threshold_ms: number;
+  stale_overlay: boolean;
+  memory_limit_mb: number;
+  passphrase: string | null;
+  pin: string | null;
+  server_address: string | null;
+  stream_id: number | null;
+}
+
+interface ViewerRequest {
+  id: number;
+  address: string;
+  name: string;
+}
+
+// A viewer talking back to the running server
+interface ConnectedClient {
+  address: string;
+  joined_at_ms: number;
+  last_report_ms: number | null;
+  loss_rate: number;
+  jitter_ms: number;
+}
+
+interface PortMapping {
+  data: number;
+  control: number;
+  discovery: number;
+}
+
+interface ServerInfo {
+  name: string;
+  width: number;
+  height: number;
+  group: string;
+  ports: PortMapping;
+  requires_approval: boolean;
+  encrypted: boolean;
+  address: string;
+  stream_id: number;
+}
+
+type JoinStatus = "pending" | "approved" | "denied" | "wrong_pin";
+
+const JOIN_STATUS_TEXT: Record<JoinStatus, string> = {
+  pending: "⏳ Đang chờ giảng viên duyệt...",
+  approved: "✅ Giảng viên đã cho phép xem",
+  denied: "⛔ Giảng viên đã từ chối",
+  wrong_pin: "❌ Sai mã PIN",
+};
+
+// What the server's encoder just produced, a few times a second
+interface PreviewFrame {
+  image: string | null;
+  bytes: number;
+  keyframe: boolean;
+}
+
+interface TileFrame {
+  width: number;
+  height: number;
+  tiles: { x: number; y: number; data: Uint8Array }[];
+  // Scrolled regions, moved on the canvas before the tiles are drawn
+  copies: { from_x: number; from_y: number; x: number; y: number; width: number; height: number }[];
+}
+
+type InputEvent =
+  | { type: "mouse_move"; x: number; y: number }
+  | { type: "mouse_button"; button: "left" | "middle" | "right"; pressed: boolean }
+  | { type: "scroll"; dx: number; dy: number }
+  | { type: "key"; key: string; pressed: boolean };
+
+// Mouse moves are sent at most this often
+const REMOTE_MOVE_INTERVAL_MS = 16;
+const MOUSE_BUTTONS = ["left", "middle", "right"] as const;
+
+// Cross-fade only kicks in below ~20 FPS, where hard cuts read as stutter
+const SMOOTHING_MIN_INTERVAL_MS = 50;
+const SMOOTHING_MAX_FADE_MS = 150;
+
+const isTilePayload = (bytes: Uint8Array) =>
+  bytes.length >= 4 && bytes[0] === 0x54 && bytes[1] === 0x49 && bytes[2] === 0x4c && bytes[3] === 0x45;
+
+// "TILE" | width u16 | height u16 | count u16 | tiles, then optional scroll copies (see tile_delta.rs)
+const parseTileFrame = (bytes: Uint8Array): TileFrame => {
+  const view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+  const tiles: TileFrame["tiles"] = [];
+  let offset = 10;
+  for (let i = 0, n = view.getUint16(8); i < n; i++) {
+    const len = view.getUint32(offset + 8);
+    tiles.push({ x: view.getUint16(offset), y: view.getUint16(offset + 2), data: bytes.subarray(offset + 12, offset + 12 + len) });
+    offset += 12 + len;
+  }
+  const copies: TileFrame["copies"] = [];
+  for (let i = 0, n = offset + 2 <= bytes.length ? view.getUint16(offset) : 0; i < n; i++) {
+    const at = offset + 2 + i * 12;
+    const v = (k: number) => view.getUint16(at + k * 2);
+    copies.push({ from_x: v(0), from_y: v(1), x: v(2), y: v(3), width: v(4), height: v(5) });
+  }
+  return { width: view.getUint16(4), height: view.getUint16(6), tiles, copies };
+};
+
 function App() {
   const [mode, setMode] = useState<Mode>("none");
   const [isActive, setIsActive] = useState(false);
@@ -21,6 +126,39 @@ function App() {
   const ctxRef = useRef<CanvasRenderingContext2D | null>(null);
   const lastFrameRef = useRef<ImageBitmap | null>(null);
   const animationFrameRef = useRef<number | null>(null);
+  const fadeFromRef = useRef<ImageBitmap | null>(null);
+  const smoothingRef = useRef(false);
+  const [viewerSettings, setViewerSettings] = useState<ViewerSettings>({
+    smoothing: false,
+    stale_threshold_ms: 2000,
+    stale_overlay: true,
+    memory_limit_mb: 256,
+    passphrase: null,
+    pin: null,
+    server_address: null,
+  });
+  const [staleSince, setStaleSince] = useState<Date | null>(null);
+  const [streamLost, setStreamLost] = useState(false);
+  const [streamStopped, setStreamStopped] = useState(false);
+  const [streamInfo, setStreamInfo] = useState<{ width: number; height: number; codec: string } | null>(null);
+  const [previewSrc, setPreviewSrc] = useState<string | null>(null);
+  const [onAir, setOnAir] = useState<PreviewFrame | null>(null);
+  const [remoteControlAllowed, setRemoteControlAllowed] = useState(false);
+  const [remoteControlActive, setRemoteControlActive] = useState(false);
+  const lastRemoteMoveRef = useRef(0);
+  const [clipboardSync, setClipboardSync] = useState(false);
+  const [passphrase, setPassphrase] = useState("");
+  const [pin, setPin] = useState("");
+  const [requireApproval, setRequireApproval] = useState(false);
+  const [sessionPin, setSessionPin] = useState<string | null>(null);
+  const [viewerRequests, setViewerRequests] = useState<ViewerRequest[]>([]);
+  const [connectedClients, setConnectedClients] = useState<ConnectedClient[]>([]);
+  const [serverAddress, setServerAddress] = useState("");
+  // The discovered server picked, among several on the same group
+  const [streamId, setStreamId] = useState<number | null>(null);
+  const [joinStatus, setJoinStatus] = useState<JoinStatus | null>(null);
+  const [servers, setServers] = useState<ServerInfo[] | null>(null);
+  const [discovering, setDiscovering] = useState(false);
   const isVisibleRef = useRef(true);
   
   // Diagnostic refs
@@ -86,7 +224,7 @@ function App() {
       canvas.addEventListener('mouseleave', handleMouseLeave);
     }
 
-    const unlisten = listen<string>("screen-frame", async (event) => {
+    const drawFrame = async (bytes: Uint8Array) => {
       const canvas = canvasRef.current;
       if (!canvas) {
         console.warn("⚠️ Canvas not available");
@@ -98,32 +236,25 @@ function App() {
       lastFrameTimeRef.current = now;
 
       try {
-        // Decode base64 to blob
-        const base64Data = event.payload;
-        
-        // Validate base64 data is not empty
-        if (!base64Data || base64Data.length < 100) {
+        // Validate frame data is not empty
+        if (bytes.length < 100) {
           errorCountRef.current++;
           console.warn("❌ Received empty or too small frame data, keeping last frame");
-          console.warn(`   Data length: ${base64Data?.length || 0} bytes`);
+          console.warn(`   Data length: ${bytes.length} bytes`);
           return;
         }
         
-        const binaryString = atob(base64Data);
-        const bytes = new Uint8Array(binaryString.length);
-        for (let i = 0; i < binaryString.length; i++) {
-          bytes[i] = binaryString.charCodeAt(i);
-        }
-        
-        // Validate JPEG signature before creating blob
-        if (bytes.length < 2 || bytes[0] !== 0xFF || bytes[1] !== 0xD8) {
+        // Validate the signature before creating blob (lossless streams arrive as PNG, WebP streams as RIFF)
+        const isPng = bytes.length >= 2 && bytes[0] === 0x89 && bytes[1] === 0x50;
+        const isWebp = bytes.length >= 12 && bytes[0] === 0x52 && bytes[1] === 0x49 && bytes[8] === 0x57 && bytes[9] === 0x45;
+        if (!isPng && !isWebp && (bytes.length < 2 || bytes[0] !== 0xFF || bytes[1] !== 0xD8)) {
           errorCountRef.current++;
           console.warn("❌ Received invalid JPEG data (missing magic bytes), keeping last frame");
           console.warn(`   Bytes: [${bytes[0]?.toString(16)}, ${bytes[1]?.toString(16)}], Expected: [FF, D8]`);
           return;
         }
         
-        const blob = new Blob([bytes], { type: "image/jpeg" });
+        const blob = new Blob([bytes], { type: isPng ? "image/png" : isWebp ? "image/webp" : "image/jpeg" });
 
         // Create ImageBitmap for better performance (with error handling)
         let imageBitmap: ImageBitmap;
@@ -166,25 +297,66 @@ function App() {
         // Use requestAnimationFrame for smoother rendering
         if (animationFrameRef.current) {
           cancelAnimationFrame(animationFrameRef.current);
+          animationFrameRef.current = null;
         }
 
-        animationFrameRef.current = requestAnimationFrame(() => {
-          const renderStart = performance.now();
-          
-          // Draw the ImageBitmap
-          ctx.drawImage(imageBitmap, 0, 0);
-          
-          const renderTime = performance.now() - renderStart;
-          if (renderTime > 16) {
-            console.warn(`⚠️ Slow render: ${renderTime.toFixed(1)}ms (should be <16ms for 60fps)`);
-          }
-          
-          animationFrameRef.current = null;
-        });
+        // A new frame interrupts any cross-fade still in progress
+        if (fadeFromRef.current) {
+          fadeFromRef.current.close();
+          fadeFromRef.current = null;
+        }
+
+        const previous = lastFrameRef.current;
+        const shouldFade = smoothingRef.current
+          && previous !== null
+          && previous.width === imageBitmap.width
+          && previous.height === imageBitmap.height
+          && timeSinceLastFrame >= SMOOTHING_MIN_INTERVAL_MS;
+
+        if (shouldFade && previous) {
+          // Low frame rate: blend from the previous frame instead of hard-cutting
+          const duration = Math.min(timeSinceLastFrame / 2, SMOOTHING_MAX_FADE_MS);
+          const fadeStart = performance.now();
+          fadeFromRef.current = previous;
 
-        // Clean up previous frame
-        if (lastFrameRef.current && lastFrameRef.current !== imageBitmap) {
-          lastFrameRef.current.close();
+          const step = (now: number) => {
+            const t = Math.min((now - fadeStart) / duration, 1);
+            ctx.globalAlpha = 1;
+            ctx.drawImage(previous, 0, 0);
+            ctx.globalAlpha = t;
+            ctx.drawImage(imageBitmap, 0, 0);
+            ctx.globalAlpha = 1;
+
+            if (t < 1) {
+              animationFrameRef.current = requestAnimationFrame(step);
+            } else {
+              animationFrameRef.current = null;
+              if (fadeFromRef.current === previous) {
+                previous.close();
+                fadeFromRef.current = null;
+              }
+            }
+          };
+          animationFrameRef.current = requestAnimationFrame(step);
+        } else {
+          animationFrameRef.current = requestAnimationFrame(() => {
+            const renderStart = performance.now();
+
+            // Draw the ImageBitmap
+            ctx.drawImage(imageBitmap, 0, 0);
+
+            const renderTime = performance.now() - renderStart;
+            if (renderTime > 16) {
+              console.warn(`⚠️ Slow render: ${renderTime.toFixed(1)}ms (should be <16ms for 60fps)`);
+            }
+
+            animationFrameRef.current = null;
+          });
+
+          // Clean up previous frame
+          if (previous && previous !== imageBitmap) {
+            previous.close();
+          }
         }
         lastFrameRef.current = imageBitmap;
         
@@ -214,13 +386,157 @@ function App() {
         errorCountRef.current++;
         console.error("❌ Failed to render frame (outer catch):", error);
       }
+    };
+
+    // Tile deltas: composite changed regions onto the last full frame
+    const drawTiles = async ({ width, height, tiles, copies }: TileFrame) => {
+      const canvas = canvasRef.current;
+      const ctx = ctxRef.current;
+
+      // Tiles only make sense on top of a full frame of the same size
+      if (!canvas || !ctx || canvas.width !== width || canvas.height !== height) {
+        return;
+      }
+
+      try {
+        const bitmaps = await Promise.all(
+          tiles.map((tile) => createImageBitmap(new Blob([tile.data], { type: "image/jpeg" })))
+        );
+
+        // Queue behind any pending full-frame draw so tiles land on top of it
+        requestAnimationFrame(async () => {
+          for (const copy of copies) {
+            ctx.drawImage(canvas, copy.from_x, copy.from_y, copy.width, copy.height, copy.x, copy.y, copy.width, copy.height);
+          }
+          bitmaps.forEach((bitmap, i) => {
+            ctx.drawImage(bitmap, tiles[i].x, tiles[i].y);
+            bitmap.close();
+          });
+
+          // Keep the redraw-on-focus snapshot in sync with the composited canvas
+          const base = lastFrameRef.current;
+          const snapshot = await createImageBitmap(canvas);
+          if (lastFrameRef.current === base) {
+            base?.close();
+            lastFrameRef.current = snapshot;
+          } else {
+            snapshot.close();
+          }
+        });
+
+        frameCountRef.current++;
+      } catch (error) {
+        errorCountRef.current++;
+        console.error("❌ Failed to composite tile update:", error);
+      }
+    };
+
+    // Frames arrive as raw bytes, no base64 round-trip: image files, or "TILE" deltas
+    const frames = new Channel<ArrayBuffer>();
+    frames.onmessage = (buffer) => {
+      const bytes = new Uint8Array(buffer);
+      const drawn = isTilePayload(bytes) ? drawTiles(parseTileFrame(bytes)) : drawFrame(bytes);
+      // The backend holds newer frames back while we're still drawing
+      drawn.finally(() => invoke("ack_frame").catch(() => {}));
+    };
+    invoke("subscribe_frames", { channel: frames })
+      .catch((error) => console.error("Failed to subscribe to frames:", error));
+
+    const applyViewerSettings = (settings: ViewerSettings) => {
+      smoothingRef.current = settings.smoothing;
+      setViewerSettings(settings);
+    };
+
+    const unlistenViewerSettings = listen<ViewerSettings>("viewer-settings", (event) => {
+      applyViewerSettings(event.payload);
+    });
+
+    invoke<ViewerSettings>("get_viewer_settings")
+      .then(applyViewerSettings)
+      .catch((error) => console.error("Failed to load viewer settings:", error));
+
+    // Stale stream: remember when the last frame arrived so the overlay can show it
+    const unlistenStale = listen<{ stale: boolean; last_frame_age_ms: number }>("stream-stale", (event) => {
+      setStaleSince(event.payload.stale ? new Date(Date.now() - event.payload.last_frame_age_ms) : null);
+    });
+
+    // Nothing arrives at all any more; the client keeps listening and says when frames are back
+    const unlistenLost = listen<{ last_packet_age_ms: number }>("stream-lost", () => setStreamLost(true));
+    const unlistenResumed = listen<{ lost_for_ms: number }>("stream-resumed", () => {
+      setStreamLost(false);
+      setStreamStopped(false);
+    });
+
+    // The server announces its sessions, and says when the host stopped instead of going quiet
+    const unlistenStarted = listen<{ width: number; height: number; codec: string }>("stream-started", (event) => {
+      setStreamInfo(event.payload);
+      setStreamStopped(false);
+    });
+    const unlistenStopped = listen("stream-stopped", () => {
+      setStreamStopped(true);
+      setStreamLost(false);
+      setStaleSince(null);
+      if (lastFrameRef.current) {
+        lastFrameRef.current.close();
+        lastFrameRef.current = null;
+      }
+      if (canvasRef.current && ctxRef.current) {
+        ctxRef.current.clearRect(0, 0, canvasRef.current.width, canvasRef.current.height);
+      }
+    });
+
+    // The client dropped buffered frames to stay under its memory limit
+    const unlistenMemory = listen<{ buffered_bytes: number; limit_bytes: number; evicted_frames: number }>("memory-pressure", (event) => {
+      const { buffered_bytes, evicted_frames } = event.payload;
+      console.warn(`Memory pressure: ${Math.round(buffered_bytes / 1024 / 1024)} MB buffered, evicted ${evicted_frames} frames`);
+      setStatus(`⚠️ Bộ nhớ đệm vượt giới hạn, đã bỏ ${evicted_frames} khung hình chưa hoàn chỉnh`);
+    });
+
+    // Server-side preview of what is being broadcast (small, low-rate JPEG)
+    const unlistenPreview = listen<string>("server-preview", (event) => {
+      setPreviewSrc(`data:image/jpeg;base64,${event.payload}`);
+    });
+
+    // Straight from the encoder: proof the stream is going out, whatever the transport
+    const unlistenOnAir = listen<PreviewFrame>("preview-frame", (event) => {
+      setOnAir((previous) => ({ ...event.payload, image: event.payload.image ?? previous?.image ?? null }));
+    });
+
+    // A viewer entered the session PIN and waits for the host's decision
+    const unlistenJoinRequest = listen<ViewerRequest>("viewer-join-request", (event) => {
+      setViewerRequests((requests) => [...requests, event.payload]);
+    });
+
+    const unlistenClientJoined = listen<ConnectedClient>("client-joined", (event) => {
+      setConnectedClients((clients) => [...clients.filter((c) => c.address !== event.payload.address), event.payload]);
+    });
+
+    const unlistenClientLeft = listen<ConnectedClient>("client-left", (event) => {
+      setConnectedClients((clients) => clients.filter((c) => c.address !== event.payload.address));
+    });
+
+    const unlistenJoinStatus = listen<JoinStatus>("join-status", (event) => {
+      setJoinStatus(event.payload);
     });
 
     // Load available displays
     loadDisplays();
 
     return () => {
-      unlisten.then((fn) => fn());
+      frames.onmessage = () => {};
+      unlistenViewerSettings.then((fn) => fn());
+      unlistenStale.then((fn) => fn());
+      unlistenLost.then((fn) => fn());
+      unlistenResumed.then((fn) => fn());
+      unlistenStarted.then((fn) => fn());
+      unlistenStopped.then((fn) => fn());
+      unlistenPreview.then((fn) => fn());
+      unlistenOnAir.then((fn) => fn());
+      unlistenMemory.then((fn) => fn());
+      unlistenJoinRequest.then((fn) => fn());
+      unlistenClientJoined.then((fn) => fn());
+      unlistenClientLeft.then((fn) => fn());
+      unlistenJoinStatus.then((fn) => fn());
       
       // Remove event listeners
       document.removeEventListener('visibilitychange', handleVisibilityChange);
@@ -239,6 +555,10 @@ function App() {
       }
 
       // Clean up ImageBitmap on unmount
+      if (fadeFromRef.current) {
+        fadeFromRef.current.close();
+        fadeFromRef.current = null;
+      }
       if (lastFrameRef.current) {
         lastFrameRef.current.close();
         lastFrameRef.current = null;
@@ -247,6 +567,112 @@ function App() {
     };
   }, []);
 
+  const sendRemoteInput = (event: InputEvent) => {
+    invoke("send_remote_input", { event }).catch((error) => {
+      console.error("Failed to send remote input:", error);
+    });
+  };
+
+  // Joins and leaves arrive as events, loss rates only get fresher by asking
+  useEffect(() => {
+    if (mode !== "server" || !isActive) return;
+
+    const refresh = () => {
+      invoke<ConnectedClient[]>("get_connected_clients")
+        .then(setConnectedClients)
+        .catch((error) => console.error("Failed to load connected clients:", error));
+    };
+    refresh();
+    const timer = setInterval(refresh, 2000);
+    return () => clearInterval(timer);
+  }, [mode, isActive]);
+
+  // Keyboard goes to the server while remote control is on, not to this window
+  useEffect(() => {
+    if (mode !== "client" || !isActive || !remoteControlActive) return;
+
+    const handleKey = (pressed: boolean) => (e: KeyboardEvent) => {
+      e.preventDefault();
+      sendRemoteInput({ type: "key", key: e.key, pressed });
+    };
+    const handleKeyDown = handleKey(true);
+    const handleKeyUp = handleKey(false);
+    window.addEventListener("keydown", handleKeyDown);
+    window.addEventListener("keyup", handleKeyUp);
+    return () => {
+      window.removeEventListener("keydown", handleKeyDown);
+      window.removeEventListener("keyup", handleKeyUp);
+    };
+  }, [mode, isActive, remoteControlActive]);
+
+  const remotePointer = (e: ReactMouseEvent<HTMLCanvasElement>) => {
+    const rect = e.currentTarget.getBoundingClientRect();
+    return {
+      x: (e.clientX - rect.left) / rect.width,
+      y: (e.clientY - rect.top) / rect.height,
+    };
+  };
+
+  const handleRemoteMouseMove = (e: ReactMouseEvent<HTMLCanvasElement>) => {
+    if (!remoteControlActive) return;
+    const now = Date.now();
+    if (now - lastRemoteMoveRef.current < REMOTE_MOVE_INTERVAL_MS) return;
+    lastRemoteMoveRef.current = now;
+    sendRemoteInput({ type: "mouse_move", ...remotePointer(e) });
+  };
+
+  const handleRemoteMouseButton = (pressed: boolean) => (e: ReactMouseEvent<HTMLCanvasElement>) => {
+    if (!remoteControlActive) return;
+    const button = MOUSE_BUTTONS[e.button];
+    if (!button) return;
+    e.preventDefault();
+    sendRemoteInput({ type: "mouse_move", ...remotePointer(e) });
+    sendRemoteInput({ type: "mouse_button", button, pressed });
+  };
+
+  const handleRemoteWheel = (e: ReactWheelEvent<HTMLCanvasElement>) => {
+    if (!remoteControlActive) return;
+    sendRemoteInput({ type: "scroll", dx: Math.sign(e.deltaX), dy: Math.sign(e.deltaY) });
+  };
+
+  const toggleRemoteControl = async (enabled: boolean) => {
+    try {
+      const result = await invoke<string>(enabled ? "enable_remote_control" : "disable_remote_control");
+      setRemoteControlAllowed(enabled);
+      setStatus(result);
+    } catch (error) {
+      setStatus(`Error: ${error}`);
+    }
+  };
+
+  const toggleClipboardSync = async (enabled: boolean) => {
+    try {
+      const result = await invoke<string>(enabled ? "enable_clipboard_sync" : "disable_clipboard_sync");
+      setClipboardSync(enabled);
+      setStatus(result);
+    } catch (error) {
+      setStatus(`Error: ${error}`);
+    }
+  };
+
+  const decideViewer = async (request: ViewerRequest, approve: boolean) => {
+    try {
+      const result = await invoke<string>(approve ? "approve_viewer" : "deny_viewer", { id: request.id });
+      setStatus(`${result}: ${request.name}`);
+    } catch (error) {
+      setStatus(`Error: ${error}`);
+    }
+    setViewerRequests((requests) => requests.filter((r) => r.id !== request.id));
+  };
+
+  const toggleSmoothing = async (enabled: boolean) => {
+    try {
+      await invoke<string>("set_smoothing", { enabled });
+    } catch (error) {
+      setStatus(`Error: ${error}`);
+    }
+  };
+
   const loadDisplays = async () => {
     try {
       const result = await invoke<DisplayInfo[]>("get_displays");
@@ -258,9 +684,14 @@ function App() {
 
   const startServer = async () => {
     try {
+      await invoke<string>("set_stream_passphrase", { passphrase: passphrase || null });
+      await invoke<string>("set_stream_pin", { pin: pin || null });
+      await invoke<string>("set_require_approval", { enabled: requireApproval });
       const result = await invoke<string>("start_server");
       setStatus(result);
       setIsActive(true);
+      const status = await invoke<{ session_pin: string | null }>("get_status");
+      setSessionPin(status.session_pin);
     } catch (error) {
       setStatus(`Error: ${error}`);
     }
@@ -271,6 +702,13 @@ function App() {
       const result = await invoke<string>("stop_server");
       setStatus(result);
       setIsActive(false);
+      setPreviewSrc(null);
+      setOnAir(null);
+      setRemoteControlAllowed(false);
+      setSessionPin(null);
+      setViewerRequests([]);
+      setConnectedClients([]);
+      if (clipboardSync) toggleClipboardSync(false);
     } catch (error) {
       setStatus(`Error: ${error}`);
     }
@@ -278,7 +716,11 @@ function App() {
 
   const startClient = async () => {
     try {
-      const result = await invoke<string>("start_client");
+      await invoke<string>("set_viewer_passphrase", { passphrase: passphrase || null });
+      await invoke<string>("set_viewer_pin", { pin: pin || null });
+      await invoke<string>("set_viewer_server_address", { address: serverAddress || null });
+      setJoinStatus(null);
+      const result = await invoke<string>("start_client", { streamId });
       setStatus(result);
       setIsActive(true);
     } catch (error) {
@@ -286,11 +728,41 @@ function App() {
     }
   };
 
+  const discoverServers = async () => {
+    setDiscovering(true);
+    try {
+      setServers(await invoke<ServerInfo[]>("discover_servers", { timeoutMs: null }));
+    } catch (error) {
+      setStatus(`Error: ${error}`);
+    } finally {
+      setDiscovering(false);
+    }
+  };
+
+  // Listen on the ports this server uses, ask it to let us in if it needs approval
+  const chooseServer = async (server: ServerInfo) => {
+    setServerAddress(server.requires_approval ? server.address : "");
+    // Older servers send no stream ID
+    setStreamId(server.stream_id || null);
+    try {
+      await invoke<string>("set_viewer_ports", { ports: server.ports });
+    } catch (error) {
+      setStatus(`Error: ${error}`);
+    }
+  };
+
   const stopClient = async () => {
     try {
       const result = await invoke<string>("stop_client");
       setStatus(result);
       setIsActive(false);
+      setStaleSince(null);
+      setStreamLost(false);
+      setStreamStopped(false);
+      setStreamInfo(null);
+      setRemoteControlActive(false);
+      setJoinStatus(null);
+      if (clipboardSync) toggleClipboardSync(false);
       
       // Clean up ImageBitmap
       if (lastFrameRef.current) {
@@ -338,6 +810,38 @@ function App() {
             </div>
           )}
           
+          {!isActive && (
+            <div className="status">
+              <label>
+                🔒 Mật khẩu mã hoá (để trống nếu không mã hoá):{" "}
+                <input
+                  type="password"
+                  value={passphrase}
+                  onChange={(e) => setPassphrase(e.target.value)}
+                />
+              </label>
+              <br />
+              <label>
+                🔑 Mã PIN ghép nối (4-10 chữ số, thay cho mật khẩu):{" "}
+                <input
+                  inputMode="numeric"
+                  maxLength={10}
+                  value={pin}
+                  onChange={(e) => setPin(e.target.value.replace(/\D/g, ""))}
+                />
+              </label>
+              <br />
+              <label>
+                <input
+                  type="checkbox"
+                  checked={requireApproval}
+                  onChange={(e) => setRequireApproval(e.target.checked)}
+                />
+                🙋 Chỉ gửi cho học viên được duyệt (học viên nhập mã PIN để xin vào)
+              </label>
+            </div>
+          )}
+          
           <div className="controls">
             {!isActive ? (
               <button onClick={startServer} className="start-btn">
@@ -359,12 +863,117 @@ function App() {
               📡 Sử dụng scrap library cho hiệu suất tối ưu
             </div>
           )}
+          {isActive && (
+            <label className="status">
+              <input
+                type="checkbox"
+                checked={remoteControlAllowed}
+                onChange={(e) => toggleRemoteControl(e.target.checked)}
+              />
+              🖱️ Cho phép học viên điều khiển chuột và bàn phím
+            </label>
+          )}
+          {isActive && (
+            <label className="status">
+              <input
+                type="checkbox"
+                checked={clipboardSync}
+                onChange={(e) => toggleClipboardSync(e.target.checked)}
+              />
+              📋 Đồng bộ clipboard với học viên
+            </label>
+          )}
+          {isActive && sessionPin && (
+            <div className="status">
+              🔑 Mã PIN buổi học: <strong>{sessionPin}</strong>
+            </div>
+          )}
+          {isActive && viewerRequests.map((request) => (
+            <div key={request.id} className="status">
+              🙋 {request.name} ({request.address}) xin xem màn hình{" "}
+              <button onClick={() => decideViewer(request, true)}>Cho phép</button>{" "}
+              <button onClick={() => decideViewer(request, false)}>Từ chối</button>
+            </div>
+          ))}
+          {isActive && (
+            <div className="status">
+              👥 Học viên đang xem: {connectedClients.length}
+              {connectedClients.map((client) => (
+                <div key={client.address}>
+                  {client.address} · mất {(client.loss_rate * 100).toFixed(1)}% · từ{" "}
+                  {new Date(client.joined_at_ms).toLocaleTimeString()}
+                </div>
+              ))}
+            </div>
+          )}
+          {isActive && onAir && (
+            <div className="status">
+              🔴 Đang phát · {Math.round(onAir.bytes / 1024)} KB/khung hình
+            </div>
+          )}
+          {isActive && !previewSrc && onAir?.image && (
+            <div className="server-preview">
+              <strong>👀 Đang phát:</strong>
+              <img src={`data:image/jpeg;base64,${onAir.image}`} alt="Khung hình vừa mã hóa" />
+            </div>
+          )}
+          {isActive && previewSrc && (
+            <div className="server-preview">
+              <strong>👀 Xem trước (học viên đang thấy):</strong>
+              <img src={previewSrc} alt="Xem trước màn hình đang chia sẻ" />
+            </div>
+          )}
         </div>
       )}
 
       {mode === "client" && (
         <div className="client-mode">
           <h2>👁️ Client Mode - Xem màn hình</h2>
+          {!isActive && (
+            <div className="status">
+              <label>
+                🔒 Mật khẩu buổi học (nếu giảng viên có đặt):{" "}
+                <input
+                  type="password"
+                  value={passphrase}
+                  onChange={(e) => setPassphrase(e.target.value)}
+                />
+              </label>
+              <br />
+              <label>
+                🔑 Mã PIN (nếu giảng viên dùng mã PIN):{" "}
+                <input
+                  inputMode="numeric"
+                  maxLength={10}
+                  value={pin}
+                  onChange={(e) => setPin(e.target.value.replace(/\D/g, ""))}
+                />
+              </label>
+              <br />
+              <label>
+                🖥️ Địa chỉ máy giảng viên (nếu lớp cần duyệt):{" "}
+                <input
+                  placeholder="192.168.1.10"
+                  value={serverAddress}
+                  onChange={(e) => setServerAddress(e.target.value)}
+                />
+              </label>
+              <br />
+              <button onClick={discoverServers} disabled={discovering}>
+                {discovering ? "Đang tìm..." : "🔍 Tìm máy chia sẻ"}
+              </button>
+              {servers && servers.length === 0 && <div>Không tìm thấy máy nào đang chia sẻ</div>}
+              {servers && servers.map((server) => (
+                <div key={server.address}>
+                  <button onClick={() => chooseServer(server)}>
+                    {server.name} ({server.address}) - {server.width}x{server.height}
+                    {server.encrypted ? " 🔒" : ""}
+                    {server.requires_approval ? " 🙋" : ""}
+                  </button>
+                </div>
+              ))}
+            </div>
+          )}
           <div className="controls">
             {!isActive ? (
               <button onClick={startClient} className="start-btn">
@@ -379,10 +988,34 @@ function App() {
               Quay lại
             </button>
           </div>
+          {isActive && joinStatus && (
+            <div className="status">{JOIN_STATUS_TEXT[joinStatus]}</div>
+          )}
           {isActive && (
             <>
-              <div className="screen-display">
-                <canvas ref={canvasRef} />
+              <div className="screen-display" style={{ position: 'relative' }}>
+                <canvas
+                  ref={canvasRef}
+                  onMouseMove={handleRemoteMouseMove}
+                  onMouseDown={handleRemoteMouseButton(true)}
+                  onMouseUp={handleRemoteMouseButton(false)}
+                  onWheel={handleRemoteWheel}
+                  onContextMenu={(e) => remoteControlActive && e.preventDefault()}
+                  style={streamLost || (staleSince && viewerSettings.stale_overlay) ? { filter: 'brightness(0.4)' } : undefined}
+                />
+                {streamStopped ? (
+                  <div className="stale-overlay">
+                    ⏹️ Máy chủ đã dừng chia sẻ màn hình
+                  </div>
+                ) : streamLost ? (
+                  <div className="stale-overlay">
+                    📡 Mất kết nối với máy chủ - đang chờ tín hiệu trở lại...
+                  </div>
+                ) : staleSince && viewerSettings.stale_overlay && (
+                  <div className="stale-overlay">
+                    ⏸️ Không có hình mới - cập nhật lần cuối lúc {staleSince.toLocaleTimeString()}
+                  </div>
+                )}
               </div>
               <div style={{ 
                 marginTop: '1rem', 
@@ -392,6 +1025,7 @@ function App() {
                 fontSize: '0.9em',
                 color: '#555'
               }}>
+                {streamInfo && <>🎬 {streamInfo.width}x{streamInfo.height} {streamInfo.codec} | </>}
                 📊 FPS: <strong>{debugInfo.fps}</strong> | 
                 ❌ Errors (last 5s): <strong style={{ color: debugInfo.errors > 5 ? 'red' : 'inherit' }}>
                   {debugInfo.errors}
@@ -399,6 +1033,30 @@ function App() {
                 {debugInfo.errors > 10 && <span style={{ color: 'red', marginLeft: '1rem' }}>
                   ⚠️ Nhiều lỗi - kiểm tra mạng!
                 </span>}
+                <label style={{ marginLeft: '1rem' }}>
+                  <input
+                    type="checkbox"
+                    checked={viewerSettings.smoothing}
+                    onChange={(e) => toggleSmoothing(e.target.checked)}
+                  />
+                  Làm mượt khung hình
+                </label>
+                <label style={{ marginLeft: '1rem' }}>
+                  <input
+                    type="checkbox"
+                    checked={remoteControlActive}
+                    onChange={(e) => setRemoteControlActive(e.target.checked)}
+                  />
+                  Điều khiển từ xa
+                </label>
+                <label style={{ marginLeft: '1rem' }}>
+                  <input
+                    type="checkbox"
+                    checked={clipboardSync}
+                    onChange={(e) => toggleClipboardSync(e.target.checked)}
+                  />
+                  Đồng bộ clipboard
+                </label>
               </div>
             </>
           )}